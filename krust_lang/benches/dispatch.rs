@@ -0,0 +1,31 @@
+//! Benchmarks for the VM's main loop on a tight loop, where the time to dispatch each instruction dominates. The loop is
+//! a function that calls itself in tail position forever, stopped after a fixed number of instructions.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use krust::compiler::{compile, CompilerOptions};
+use krust::lexer::lex;
+use krust::parser::parse;
+use krust::vm::Vm;
+
+const PROGRAM: &str =
+    "fn spin(n: int, acc: int) -> int { spin(n + 1, acc ^ (n * 3 + 7)) }\nspin(0, 0)";
+
+// The number of instructions run by each iteration of the benchmark.
+const FUEL: u64 = 1_000_000;
+
+fn dispatch(c: &mut Criterion) {
+    let bytecode: Vec<u8> = compile(parse(lex(PROGRAM)), CompilerOptions::default())
+        .bytecode
+        .expect("the program should compile");
+    let mut vm: Vm = Vm::new(bytecode);
+    c.bench_function("tight loop", |b| {
+        b.iter(|| vm.run_with_fuel(FUEL));
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = dispatch
+}
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+//! Benchmarks for running an arithmetic-heavy program with the stack and register encodings, which shows how much the
+//! register instructions save over pushing every variable and literal before it is used.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use krust::compiler::{compile_with_passes, CompilerOptions};
+use krust::lexer::lex;
+use krust::parser::parse;
+use krust::pass_manager::{CompilerPass, PassManager};
+use krust::vm::Vm;
+
+// The statement that is repeated to make the program. Nothing is printed, as the last statement has no value.
+const STATEMENT: &str = "d = a + b * c - d / b + (a << 2) % c;\n";
+
+fn encoding(c: &mut Criterion) {
+    let program: String = format!(
+        "int a = 7; int b = 3; int c = 5; int d = 1;\n{}",
+        STATEMENT.repeat(1000)
+    );
+    let mut group = c.benchmark_group("encoding");
+    for (name, registers) in [("stack", false), ("register", true)] {
+        let mut passes: PassManager = PassManager::default();
+        if registers {
+            passes.enable(CompilerPass::Registers);
+        }
+        let bytecode: Vec<u8> = compile_with_passes(
+            parse(lex(&program)),
+            CompilerOptions::default(),
+            false,
+            &passes,
+        )
+        .bytecode
+        .expect("the program should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        group.bench_function(name, |b| {
+            b.iter(|| vm.run());
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = encoding
+}
+criterion_main!(benches);
@@ -0,0 +1,40 @@
+//! Benchmarks for the lexer on typical and adversarial inputs. Each input is lexed at two sizes, so that a lexer that
+//! takes more than linear time shows up as a time that grows faster than the input.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use krust::lexer::{keyword_trie, lex_with_keywords, TokenType};
+use krust::util::trie::Node;
+
+// Builds the inputs to lex, each given by a name and the text that is repeated to reach the size of the input.
+const INPUTS: [(&str, &str); 5] = [
+    (
+        "program",
+        "fn f(n: int) -> int { n * 2 } // Doubles n.\nint x = f(5) << 2;\n",
+    ),
+    ("digits", "9"),
+    ("operators", "<<=!"),
+    ("identifiers", "long_identifier_name "),
+    ("strings", "\"a \\\"quoted\\\" word\" "),
+];
+
+fn lexer(c: &mut Criterion) {
+    let trie: Node<char, TokenType> = keyword_trie();
+    for (name, unit) in INPUTS {
+        let mut group = c.benchmark_group(name);
+        for size in [100_000, 1_000_000] {
+            let text: String = unit.repeat(size / unit.len());
+            group.throughput(Throughput::Bytes(text.len() as u64));
+            group.bench_with_input(BenchmarkId::from_parameter(size), &text, |b, text| {
+                b.iter(|| lex_with_keywords(text, &trie));
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = lexer
+}
+criterion_main!(benches);
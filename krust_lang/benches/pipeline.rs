@@ -0,0 +1,45 @@
+//! Benchmarks for each stage of running a program, from lexing to the VM, on synthetic programs of each size. A stage
+//! that takes more than linear time in the size of the program shows up as a time that grows faster than the program.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use krust::bench::{synthetic_program, ProgramSize};
+use krust::compiler::{compile, CompilerOptions};
+use krust::lexer::lex;
+use krust::parser::parse;
+use krust::vm::Vm;
+
+use std::io;
+
+fn pipeline(c: &mut Criterion) {
+    let options: CompilerOptions = CompilerOptions::default();
+    for size in ProgramSize::ALL {
+        let code: String = synthetic_program(size);
+        let mut group = c.benchmark_group(size.name());
+        group.throughput(Throughput::Bytes(code.len() as u64));
+        group.bench_with_input(BenchmarkId::new("lex", code.len()), &code, |b, code| {
+            b.iter(|| lex(code));
+        });
+        group.bench_with_input(BenchmarkId::new("parse", code.len()), &code, |b, code| {
+            b.iter_batched(|| lex(code), parse, BatchSize::LargeInput);
+        });
+        group.bench_with_input(BenchmarkId::new("codegen", code.len()), &code, |b, code| {
+            b.iter_batched(|| parse(lex(code)), |parser_output| compile(parser_output, options), BatchSize::LargeInput);
+        });
+        let bytecode: Vec<u8> = compile(parse(lex(&code)), options)
+            .bytecode
+            .expect("the program should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.set_output(io::sink());
+        group.bench_function(BenchmarkId::new("vm", code.len()), |b| {
+            b.iter(|| vm.run());
+        });
+        group.finish();
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = pipeline
+}
+criterion_main!(benches);
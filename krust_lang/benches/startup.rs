@@ -0,0 +1,39 @@
+//! Benchmarks for the time it takes to compile and run a tiny program, which is dominated by the fixed costs of each
+//! stage rather than by the size of the code.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use krust::compiler::{compile, CompilerOptions, CompilerOutput};
+use krust::lexer::{lex, LexerOutput};
+use krust::parser::{parse, ParserOutput};
+use krust::vm::Vm;
+
+const PROGRAM: &str = "int x = 2; x * 3 + 1";
+
+fn startup(c: &mut Criterion) {
+    let options: CompilerOptions = CompilerOptions::default();
+    c.bench_function("lex", |b| b.iter(|| lex(PROGRAM)));
+    c.bench_function("parse", |b| b.iter(|| parse(lex(PROGRAM))));
+    c.bench_function("compile", |b| {
+        b.iter(|| compile(parse(lex(PROGRAM)), options))
+    });
+    let bytecode: Vec<u8> = compile(parse(lex(PROGRAM)), options)
+        .bytecode
+        .expect("the program should compile");
+    c.bench_function("run", |b| b.iter(|| Vm::new(bytecode.clone()).run()));
+    c.bench_function("pipeline", |b| {
+        b.iter(|| {
+            let lex_output: LexerOutput = lex(PROGRAM);
+            let parser_output: ParserOutput = parse(lex_output);
+            let compiler_output: CompilerOutput = compile(parser_output, options);
+            Vm::new(
+                compiler_output
+                    .bytecode
+                    .expect("the program should compile"),
+            )
+            .run()
+        });
+    });
+}
+
+criterion_group!(benches, startup);
+criterion_main!(benches);
@@ -0,0 +1,122 @@
+//! The module for embedding krust, which compiles and runs source code in one call instead of chaining the lexer,
+//! parser, compiler, and VM by hand. The functions are exported at the root of the crate.
+//!
+//! ```
+//! use krust::compiler::CompilerOptions;
+//! use krust::vm::Value;
+//!
+//! let value: Value = krust::run_source("int x = 2;\nx * 21", CompilerOptions::default()).expect("it should run");
+//! assert_eq!(value, Value::Int(42));
+//! ```
+
+use crate::{compiler, lexer, parser, util::log, vm};
+use compiler::{compile, CompilerOptions, CompilerOutput};
+use lexer::lex;
+use log::{is_error, Log, RenderOptions, Severity};
+use parser::parse;
+use vm::{Value, Vm};
+
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+
+/// A program that compiled without errors.
+#[derive(Clone)]
+pub struct Program {
+    /// The bytecode of the program, which can be written to a compiled program file.
+    pub bytecode: Vec<u8>,
+    /// The warnings and other logs given while compiling the program.
+    pub warnings: Diagnostics,
+}
+
+impl Program {
+    /// Creates a VM for the program, so that its input, output, and limits can be set before it runs.
+    #[must_use]
+    pub fn vm(&self) -> Vm {
+        Vm::new(self.bytecode.clone())
+    }
+
+    /// Runs the program, printing its output to stdout.
+    ///
+    /// # Errors
+    /// Returns the runtime error that stopped the program, along with its stack trace.
+    pub fn run(&self) -> Result<Value, Diagnostics> {
+        self.vm().run().map_err(|logs| Diagnostics {
+            logs,
+            source: self.warnings.source.clone(),
+        })
+    }
+}
+
+/// The logs given for some source code, which are shown with the lines of the source they are about when displayed.
+#[derive(Clone)]
+pub struct Diagnostics {
+    pub logs: Vec<Log>,
+    source: String,
+}
+
+impl Diagnostics {
+    /// Gets the source code the logs are about.
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns whether or not any of the logs are errors.
+    #[must_use]
+    pub fn has_errors(&self) -> bool {
+        is_error(&self.logs)
+    }
+
+    /// Gets the logs that are errors.
+    pub fn errors(&self) -> impl Iterator<Item = &Log> {
+        self.logs
+            .iter()
+            .filter(|log| log.log_type.severity() == Severity::Error)
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let rendered: Vec<String> = self.logs.iter().map(|log| log.render(Some(&self.source), RenderOptions::default())).collect();
+        write!(f, "{}", rendered.join("\n"))
+    }
+}
+
+// The diagnostics are shown as their messages, which are easier to read than the fields of each log.
+impl Debug for Diagnostics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_list()
+            .entries(self.logs.iter().map(ToString::to_string))
+            .finish()
+    }
+}
+
+impl Error for Diagnostics {}
+
+/// Compiles the source code with the options, running the passes of the optimization level in the options.
+///
+/// # Errors
+/// Returns every log given while compiling if any of them is an error, as the program can't be run.
+pub fn compile_source(source: &str, options: CompilerOptions) -> Result<Program, Diagnostics> {
+    let output: CompilerOutput = compile(parse(lex(source)), options);
+    let diagnostics: Diagnostics = Diagnostics {
+        logs: output.logs,
+        source: source.to_string(),
+    };
+    match output.bytecode {
+        Some(bytecode) if !diagnostics.has_errors() => Ok(Program {
+            bytecode,
+            warnings: diagnostics,
+        }),
+        _ => Err(diagnostics),
+    }
+}
+
+/// Compiles the source code with the options and runs it, printing its output to stdout. The warnings given while
+/// compiling are left out, so `compile_source` should be used to see them.
+///
+/// # Errors
+/// Returns the logs from compiling if the source has errors, or the runtime error that stopped the program.
+pub fn run_source(source: &str, options: CompilerOptions) -> Result<Value, Diagnostics> {
+    compile_source(source, options)?.run()
+}
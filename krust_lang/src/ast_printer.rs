@@ -0,0 +1,252 @@
+//! The module for printing the AST as an indented tree, so that changes to the parser can be checked by looking at the
+//! expressions it builds.
+
+use crate::{lexer, parser};
+use lexer::Token;
+use parser::{Expression, Type};
+
+use std::fmt::{Result, Write};
+use std::ops::Range;
+
+impl Expression {
+    /// Writes the expression as a tree with one node per line, where the children of each node are indented below it.
+    /// Each line gives the kind of the node, its token with the token's line, column, and span in bytes, and the type
+    /// of the node. The source must be the code the expression was parsed from, as it is used to get the token text.
+    ///
+    /// # Errors
+    /// Returns an error if writing to the output fails.
+    pub fn fmt_tree(&self, f: &mut dyn Write, source: &str) -> Result {
+        write_node(self, f, source, 0)
+    }
+
+    /// Converts the expression to a tree in the format used by `fmt_tree`.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)] // Writing to a string never fails.
+    pub fn to_tree_string(&self, source: &str) -> String {
+        let mut tree: String = String::new();
+        self.fmt_tree(&mut tree, source)
+            .expect("writing to a string never fails");
+        tree
+    }
+}
+
+// The parts of a node that are printed.
+struct Node<'a> {
+    kind: &'static str,
+    token: Option<Token>,
+    details: String,
+    children: Vec<&'a Expression>,
+    flags: Vec<(Token, &'a Expression)>,
+}
+
+impl<'a> Node<'a> {
+    // Creates a node with no token, details, or children.
+    fn new(kind: &'static str) -> Self {
+        Self {
+            kind,
+            token: None,
+            details: String::new(),
+            children: Vec::new(),
+            flags: Vec::new(),
+        }
+    }
+
+    // Sets the token of the node.
+    fn token(mut self, token: Token) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    // Sets the details printed after the token.
+    fn details(mut self, details: String) -> Self {
+        self.details = details;
+        self
+    }
+
+    // Adds children to the node.
+    fn children(mut self, children: impl IntoIterator<Item = &'a Expression>) -> Self {
+        self.children.extend(children);
+        self
+    }
+}
+
+// Writes a node and its children at the given depth.
+fn write_node(expr: &Expression, f: &mut dyn Write, source: &str, depth: usize) -> Result {
+    let node: Node = describe(expr);
+    write!(f, "{}{}", "  ".repeat(depth), node.kind)?;
+    if let Some(token) = node.token {
+        write!(f, " {}", format_token(token, source))?;
+    }
+    if !node.details.is_empty() {
+        write!(f, " {}", node.details)?;
+    }
+    match expr.get_type() {
+        Some(expr_type) => writeln!(f, ": {}", expr_type.name())?,
+        None => writeln!(f, ": ?")?,
+    }
+    for child in node.children {
+        write_node(child, f, source, depth + 1)?;
+    }
+    for (token, value) in node.flags {
+        writeln!(
+            f,
+            "{}Flag {}",
+            "  ".repeat(depth + 1),
+            format_token(token, source)
+        )?;
+        write_node(value, f, source, depth + 2)?;
+    }
+    Ok(())
+}
+
+// Formats a token as its text, followed by its line, column, and span in bytes.
+fn format_token(token: Token, source: &str) -> String {
+    let span: Range<usize> = token.span().byte_range;
+    format!(
+        "{:?} ({}:{}, bytes {}..{})",
+        token.to_string(source),
+        token.line,
+        token.col,
+        span.start,
+        span.end
+    )
+}
+
+// Formats a list of types as type arguments, or nothing if the list is empty.
+fn format_type_args(types: &[Type]) -> String {
+    if types.is_empty() {
+        String::new()
+    } else {
+        let names: Vec<String> = types.iter().map(Type::name).collect();
+        format!("<{}>", names.join(", "))
+    }
+}
+
+// Gets the parts of the expression that are printed.
+#[allow(clippy::too_many_lines)] // Necessary for all the different expression types.
+fn describe(expr: &Expression) -> Node<'_> {
+    match expr {
+        Expression::Binary {
+            left, op, right, ..
+        } => Node::new("Binary").token(*op).children([&**left, &**right]),
+        Expression::Call {
+            token,
+            function,
+            args,
+            ..
+        } => Node::new("Call")
+            .token(*token)
+            .details(format!("builtin {}", function.name()))
+            .children(args.iter().map(|arg| &**arg)),
+        Expression::Cast { expr, .. } => Node::new("Cast").children([&**expr]),
+        Expression::FunctionCall {
+            token,
+            args,
+            type_args,
+            ..
+        } => Node::new("FunctionCall")
+            .token(*token)
+            .details(format_type_args(type_args))
+            .children(args.iter().map(|arg| &**arg)),
+        Expression::HostCall {
+            token, name, args, ..
+        } => Node::new("HostCall")
+            .token(*token)
+            .details(format!("host {name}"))
+            .children(args.iter().map(|arg| &**arg)),
+        Expression::FunctionDeclaration {
+            token,
+            type_params,
+            type_bounds,
+            params,
+            defaults,
+            return_type,
+            body,
+        } => {
+            let params_text: Vec<String> = type_params
+                .iter()
+                .zip(type_bounds)
+                .map(|(param, bound)| match bound {
+                    Some(bound) => format!("{param}: {bound}"),
+                    None => param.clone(),
+                })
+                .collect();
+            let type_params_text: String = if params_text.is_empty() {
+                String::new()
+            } else {
+                format!("<{}> ", params_text.join(", "))
+            };
+            Node::new("FunctionDeclaration")
+                .token(*token)
+                .details(format!("{type_params_text}-> {}", return_type.name()))
+                .children(params.iter().chain(defaults).map(|expr| &**expr))
+                .children([&**body])
+        }
+        Expression::ExternDeclaration { signature } => {
+            Node::new("ExternDeclaration").children([&**signature])
+        }
+        Expression::TraitDeclaration { token, methods } => Node::new("TraitDeclaration")
+            .token(*token)
+            .children(methods.iter().map(|method| &**method)),
+        Expression::FlagsDeclaration { token, members } => Node {
+            flags: members
+                .iter()
+                .map(|(token, value)| (*token, &**value))
+                .collect(),
+            ..Node::new("FlagsDeclaration").token(*token)
+        },
+        Expression::ImplDeclaration {
+            trait_token,
+            impl_type,
+            methods,
+        } => Node::new("ImplDeclaration")
+            .token(*trait_token)
+            .details(format!("for {}", impl_type.name()))
+            .children(methods.iter().map(|method| &**method)),
+        Expression::MethodCall { token, args, .. } => Node::new("MethodCall")
+            .token(*token)
+            .children(args.iter().map(|arg| &**arg)),
+        Expression::CastOp { .. } => Node::new("CastOp"),
+        Expression::ExpressionList { list } => {
+            Node::new("ExpressionList").children(list.iter().map(|expr| &**expr))
+        }
+        Expression::Grouping { expr, .. } => Node::new("Grouping").children([&**expr]),
+        Expression::Literal { token, .. } => Node::new("Literal").token(*token),
+        Expression::MapLiteral { token, entries, .. } => Node::new("MapLiteral")
+            .token(*token)
+            .children(entries.iter().flat_map(|(key, value)| [&**key, &**value])),
+        Expression::ListLiteral {
+            token, elements, ..
+        } => Node::new("ListLiteral")
+            .token(*token)
+            .children(elements.iter().map(|element| &**element)),
+        Expression::Index {
+            token, expr, index, ..
+        } => Node::new("Index")
+            .token(*token)
+            .children([&**expr, &**index]),
+        Expression::Postfix { op, expr, .. } => Node::new("Postfix").token(*op).children([&**expr]),
+        Expression::Statement { expr } => Node::new("Statement").children([&**expr]),
+        Expression::StringLiteral { token, value } => Node::new("StringLiteral")
+            .token(*token)
+            .details(format!("value {value:?}")),
+        Expression::Type { value } => Node::new("Type").details(value.name()),
+        Expression::Unary { op, expr, .. } => Node::new("Unary").token(*op).children([&**expr]),
+        Expression::Void => Node::new("Void"),
+        Expression::Variable {
+            initialized, token, ..
+        } => Node::new("Variable")
+            .token(*token)
+            .details(if *initialized {
+                String::new()
+            } else {
+                "uninitialized".to_string()
+            }),
+        Expression::VariableDeclaration { initialized_var } => {
+            Node::new("VariableDeclaration").children([&**initialized_var])
+        }
+        Expression::Error { token } => Node::new("Error").token(*token),
+        Expression::EOF => Node::new("EOF"),
+        Expression::Null => Node::new("Null"),
+    }
+}
@@ -0,0 +1,301 @@
+//! The module for transpiling the typed AST to a standalone C file, so that krust programs can be compiled to native
+//! code with any C99 compiler. Like the wasm backend, only ints, bools, variables, and the operators on them are
+//! supported so far, and any other code is reported as an error.
+//!
+//! The generated program gives the same results as the VM: ints wrap on overflow, shifts saturate, and a division or
+//! modulo by zero prints the same error as the VM and exits with status 1. Each value is stored in its own temporary
+//! before it is used, so operands are evaluated from left to right as they are in krust, even though C leaves the
+//! order unspecified.
+
+use super::{unsupported_feature, unsupported_log};
+use crate::{compiler, lexer, optimizer, parser, pass_manager, util::log};
+use compiler::CompilerOptions;
+use lexer::{Token, TokenType};
+use log::{is_error, ErrorType, Log, LogType};
+use optimizer::fold_constants;
+use parser::{Expression, ParserOutput, Type};
+use pass_manager::CompilerPass;
+
+/// The output given by the C backend.
+pub struct COutput {
+    pub file_text: String,
+    /// The C source code, or None if the code has errors or uses features the backend doesn't support.
+    pub source: Option<String>,
+    pub logs: Vec<Log>,
+}
+
+// The start of every generated file, with the functions that give the operators the same semantics as in the VM.
+const PRELUDE: &str = r#"// Generated by the krust C backend.
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+static inline int32_t krust_add(int32_t a, int32_t b) { return (int32_t)((uint32_t)a + (uint32_t)b); }
+static inline int32_t krust_sub(int32_t a, int32_t b) { return (int32_t)((uint32_t)a - (uint32_t)b); }
+static inline int32_t krust_mul(int32_t a, int32_t b) { return (int32_t)((uint32_t)a * (uint32_t)b); }
+
+static void krust_divide_by_zero(int line, int col) {
+    if (line > 0) {
+        fprintf(stderr, "error (line %d:%d): division by zero.\n", line, col);
+    } else {
+        fprintf(stderr, "error: division by zero.\n");
+    }
+    exit(1);
+}
+
+static inline int32_t krust_div(int32_t a, int32_t b, int line, int col) {
+    if (b == 0) {
+        krust_divide_by_zero(line, col);
+    }
+    return b == -1 ? krust_sub(0, a) : a / b;
+}
+
+static inline int32_t krust_mod(int32_t a, int32_t b, int line, int col) {
+    int32_t r;
+    if (b == 0) {
+        krust_divide_by_zero(line, col);
+    }
+    if (b == -1) {
+        return 0;
+    }
+    r = a % b;
+    return r >= 0 ? r : b < 0 ? r - b : r + b;
+}
+
+static inline int32_t krust_shift(int32_t a, int32_t b) {
+    if (b >= 32) {
+        return 0;
+    }
+    if (b <= -32) {
+        return a < 0 ? -1 : 0;
+    }
+    if (b >= 0) {
+        return (int32_t)((uint32_t)a << b);
+    }
+    return a < 0 ? ~(~a >> -b) : a >> -b;
+}
+"#;
+
+/// Transpiles the parsed code to C. Constants are folded first if the optimization level in the options folds them for
+/// bytecode, and division errors only give their line and column with detailed errors.
+#[must_use]
+pub fn transpile(parser_output: ParserOutput, options: CompilerOptions) -> COutput {
+    let mut logs: Vec<Log> = parser_output.logs;
+    let mut source: Option<String> = None;
+    if !is_error(&logs) {
+        let mut expr: Expression = parser_output.expr;
+        if CompilerPass::ConstantFolding.is_default(options.opt_level) {
+            expr = fold_constants(&expr, &mut logs);
+        }
+        logs = options.diagnostics.apply(logs);
+        let mut transpiler: Transpiler = Transpiler {
+            body: Vec::new(),
+            vars: Vec::new(),
+            temps: 0,
+            logs: Vec::new(),
+            detailed_err: options.detailed_err,
+        };
+        let value: Option<String> = transpiler.lower(&expr);
+        match (expr.get_type(), value) {
+            (Some(Type::Int), Some(value)) => transpiler
+                .body
+                .push(format!("printf(\"%\" PRId32 \"\\n\", {value});")),
+            (Some(Type::Bool), Some(value)) => transpiler
+                .body
+                .push(format!("puts({value} ? \"true\" : \"false\");")),
+            _ => {}
+        }
+        logs.append(&mut transpiler.logs);
+        if !is_error(&logs) {
+            source = Some(transpiler.finish());
+        }
+    }
+    COutput {
+        file_text: parser_output.file_text,
+        source,
+        logs,
+    }
+}
+
+// The state of transpiling the AST to the body of the main function.
+struct Transpiler {
+    // The statements of the main function, not counting the variable declarations.
+    body: Vec<String>,
+    // The declaration tokens of the variables, in the order of their names.
+    vars: Vec<Token>,
+    // The number of temporaries declared so far.
+    temps: usize,
+    logs: Vec<Log>,
+    detailed_err: bool,
+}
+
+impl Transpiler {
+    // Adds the statements that compute the value of the expression, returning the C expression that holds it, or None
+    // if it has no value. The returned expression is a literal or a temporary, so it never changes after this returns.
+    fn lower(&mut self, expr: &Expression) -> Option<String> {
+        if let Some(feature) = unsupported_feature(expr) {
+            self.logs.push(unsupported_log("C", &feature, expr));
+            return None;
+        }
+        match expr {
+            Expression::Binary {
+                left, op, right, ..
+            } => self.lower_binary(left, *op, right),
+            Expression::Unary { op, expr, .. } => {
+                let value: String = self.lower(expr)?;
+                match op.token_type {
+                    TokenType::Minus => Some(self.temp(&format!("krust_sub(0, {value})"))),
+                    TokenType::Tilde => Some(self.temp(&format!("~{value}"))),
+                    TokenType::ExclamationMark => Some(self.temp(&format!("!{value}"))),
+                    _ => {
+                        self.logs.push(unsupported_log("C", "this operator", expr));
+                        None
+                    }
+                }
+            }
+            Expression::Cast {
+                expr_type,
+                expr: inner,
+            } => {
+                let value: String = self.lower(inner)?;
+                if expr_type == &Some(Type::Bool) && inner.get_type() == Some(Type::Int) {
+                    Some(self.temp(&format!("{value} != 0")))
+                } else {
+                    Some(value)
+                }
+            }
+            Expression::Grouping { expr, .. } => self.lower(expr),
+            Expression::Literal { token, .. } => match token.token_type {
+                // The smallest int can't be written as a literal in C, as its absolute value is too large.
+                TokenType::IntLiteral(value) if value.cast_signed() == i32::MIN => {
+                    Some("INT32_MIN".to_string())
+                }
+                TokenType::IntLiteral(value) => Some(value.cast_signed().to_string()),
+                TokenType::True => Some("1".to_string()),
+                TokenType::False => Some("0".to_string()),
+                _ => {
+                    self.logs.push(unsupported_log("C", "this literal", expr));
+                    None
+                }
+            },
+            Expression::Statement { expr } => {
+                // Temporaries that are never used would make C compilers warn.
+                if let Some(value) = self.lower(expr).filter(|value| value.starts_with('t')) {
+                    self.body.push(format!("(void){value};"));
+                }
+                None
+            }
+            Expression::ExpressionList { list } => {
+                let mut value: Option<String> = None;
+                for expr in list {
+                    value = self.lower(expr);
+                }
+                value
+            }
+            // Variables start as zero, which is the default value of ints and bools.
+            Expression::VariableDeclaration { initialized_var } => self.lower(initialized_var),
+            Expression::Variable { token, .. } => {
+                let var: String = self.var(*token);
+                Some(self.temp(&var))
+            }
+            // Declarations only matter where they are used, and flags are replaced by their values. Everything else
+            // was reported as unsupported above.
+            _ => None,
+        }
+    }
+
+    // Adds the statements for a binary operation or an assignment.
+    fn lower_binary(&mut self, left: &Expression, op: Token, right: &Expression) -> Option<String> {
+        if op.token_type == TokenType::Equals {
+            let var: &Expression = match left {
+                Expression::VariableDeclaration { initialized_var } => initialized_var,
+                _ => left,
+            };
+            if let Expression::Variable { token, .. } = var {
+                let var: String = self.var(*token);
+                let value: String = self.lower(right)?;
+                self.body.push(format!("{var} = {value};"));
+                return Some(value);
+            }
+            return None;
+        }
+        let left: String = self.lower(left)?;
+        let right: String = self.lower(right)?;
+        let value: String = match op.token_type {
+            TokenType::Plus => format!("krust_add({left}, {right})"),
+            TokenType::Minus => format!("krust_sub({left}, {right})"),
+            TokenType::Star => format!("krust_mul({left}, {right})"),
+            TokenType::Slash | TokenType::Percent => {
+                let (line, col): (usize, usize) = if self.detailed_err {
+                    (op.line, op.col)
+                } else {
+                    (0, 0)
+                };
+                let function: &str = if op.token_type == TokenType::Slash {
+                    "krust_div"
+                } else {
+                    "krust_mod"
+                };
+                format!("{function}({left}, {right}, {line}, {col})")
+            }
+            TokenType::LeftShift => format!("krust_shift({left}, {right})"),
+            // A right shift is a left shift by the negated amount.
+            TokenType::RightShift => format!("krust_shift({left}, krust_sub(0, {right}))"),
+            TokenType::Ampersand => format!("{left} & {right}"),
+            TokenType::Bar => format!("{left} | {right}"),
+            TokenType::Caret => format!("{left} ^ {right}"),
+            TokenType::Less => format!("{left} < {right}"),
+            TokenType::LessEqual => format!("{left} <= {right}"),
+            TokenType::Greater => format!("{left} > {right}"),
+            TokenType::GreaterEqual => format!("{left} >= {right}"),
+            TokenType::Equality => format!("{left} == {right}"),
+            TokenType::Inequality => format!("{left} != {right}"),
+            _ => {
+                self.logs.push(Log {
+                    log_type: LogType::Error(ErrorType::UnsupportedByBackend(
+                        "C".to_string(),
+                        "this operator".to_string(),
+                    )),
+                    line_and_col: Some((op.line, op.col)),
+                    span: Some(op.span()),
+                    notes: Vec::new(),
+                });
+                return None;
+            }
+        };
+        Some(self.temp(&value))
+    }
+
+    // Gets the name of the variable with the declaration token, declaring it if it hasn't been used yet.
+    fn var(&mut self, token: Token) -> String {
+        let index: usize = if let Some(index) = self.vars.iter().position(|other| *other == token) {
+            index
+        } else {
+            self.vars.push(token);
+            self.vars.len() - 1
+        };
+        format!("v{index}")
+    }
+
+    // Stores the value in a new temporary and returns its name.
+    fn temp(&mut self, value: &str) -> String {
+        let name: String = format!("t{}", self.temps);
+        self.temps += 1;
+        self.body.push(format!("int32_t {name} = {value};"));
+        name
+    }
+
+    // Gets the C file, with the variables declared at the start of the main function.
+    fn finish(self) -> String {
+        let declarations = (0..self.vars.len()).map(|index| format!("int32_t v{index} = 0;"));
+        let statements: Vec<String> = declarations
+            .chain(self.body)
+            .map(|statement| format!("    {statement}\n"))
+            .collect();
+        format!(
+            "{PRELUDE}\nint main(void) {{\n{}    return 0;\n}}\n",
+            statements.concat()
+        )
+    }
+}
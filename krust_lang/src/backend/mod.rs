@@ -0,0 +1,100 @@
+//! The module for the backends that compile the typed AST to something other than krust bytecode.
+
+pub mod c;
+pub mod wasm;
+
+use crate::{parser, util::log};
+use log::{ErrorType, Log, LogType};
+use parser::{Expression, Type};
+
+/// What the compiler produces, chosen on the command line with `--target`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Backend {
+    /// Bytecode for the krust VM.
+    #[default]
+    Bytecode,
+    /// A WebAssembly module, which can run in browsers.
+    Wasm,
+}
+
+impl Backend {
+    /// Every backend.
+    pub const ALL: [Self; 2] = [Self::Bytecode, Self::Wasm];
+
+    /// Gets the name of the backend, as used on the command line.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bytecode => "bytecode",
+            Self::Wasm => "wasm",
+        }
+    }
+
+    /// Gets the backend with the given name.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|backend| backend.name() == name)
+    }
+
+    /// Gets the extension of the files that `krust build` writes for the backend.
+    #[must_use]
+    pub fn file_extension(self) -> &'static str {
+        match self {
+            Self::Bytecode => "krustc",
+            Self::Wasm => "wasm",
+        }
+    }
+}
+
+/// Gets the feature used by the expression, not counting its children, that the backends don't support yet, if any.
+/// They only support ints, bools, variables, and the operators on them so far.
+#[must_use]
+pub fn unsupported_feature(expr: &Expression) -> Option<String> {
+    if let Some(feature) = expr.get_type().as_ref().and_then(unsupported_type) {
+        return Some(feature.to_string());
+    }
+    let feature: &str = match expr {
+        Expression::Call { function, .. } => {
+            return Some(format!("the built-in function \"{}\"", function.name()))
+        }
+        Expression::FunctionCall { .. } => "function calls",
+        Expression::HostCall { .. } => "host functions",
+        Expression::MethodCall { .. } => "method calls",
+        Expression::Postfix { .. } => "unwrapping",
+        Expression::CastOp { .. } => "this cast",
+        Expression::StringLiteral { .. } => "strings",
+        Expression::MapLiteral { .. } => "maps",
+        Expression::ListLiteral { .. } | Expression::Index { .. } => "lists",
+        Expression::Type { .. } => "types as values",
+        _ => return None,
+    };
+    Some(feature.to_string())
+}
+
+/// Creates the error for a feature that a backend doesn't support, at the first token of the expression that uses it.
+#[must_use]
+pub fn unsupported_log(backend: &str, feature: &str, expr: &Expression) -> Log {
+    Log {
+        log_type: LogType::Error(ErrorType::UnsupportedByBackend(
+            backend.to_string(),
+            feature.to_string(),
+        )),
+        line_and_col: expr.first_token().map(|token| (token.line, token.col)),
+        span: None,
+        notes: Vec::new(),
+    }
+}
+
+// Gets the feature that values of the type need which the backends don't support yet, if any.
+fn unsupported_type(value_type: &Type) -> Option<&'static str> {
+    match value_type {
+        Type::Int | Type::Bool | Type::Void => None,
+        Type::String => Some("strings"),
+        Type::Map(..) => Some("maps"),
+        Type::List(_) => Some("lists"),
+        Type::Result(_) | Type::Err => Some("results"),
+        Type::Nullable(_) | Type::Null => Some("nullable values"),
+        Type::Type => Some("types as values"),
+        Type::Generic(_) => Some("generic functions"),
+    }
+}
@@ -0,0 +1,432 @@
+//! The module for compiling the typed AST to a WebAssembly module, so that krust programs can run in browsers. Only
+//! ints, bools, variables, and the operators on them are supported so far, and any other code is reported as an error.
+//!
+//! The module imports these functions from `env`, which whatever runs the module has to provide:
+//! - `print_int(value: i32)` and `print_bool(value: i32)` print the value of the program, with bools given as 0 or 1.
+//! - `divide_by_zero(line: i32, col: i32)` reports a division or modulo by zero, after which the module traps. The
+//!   line and column are 0 if the program was compiled with `-detailed_errors=false`.
+//!
+//! The program runs when the exported `main` function is called. Ints wrap on overflow and shifts saturate, so the
+//! results are the same as in the VM.
+
+use super::{unsupported_feature, unsupported_log};
+use crate::{compiler, lexer, optimizer, parser, pass_manager, util::log};
+use compiler::CompilerOptions;
+use lexer::{Token, TokenType};
+use log::{is_error, ErrorType, Log, LogType};
+use optimizer::fold_constants;
+use parser::{Expression, ParserOutput, Type};
+use pass_manager::CompilerPass;
+
+/// The output given by the WebAssembly backend.
+pub struct WasmOutput {
+    pub file_text: String,
+    /// The encoded module, or None if the code has errors or uses features the backend doesn't support.
+    pub module: Option<Vec<u8>>,
+    pub logs: Vec<Log>,
+}
+
+// The indices of the functions in the module. The imports come first, followed by the functions defined in it.
+const PRINT_INT: u8 = 0;
+const PRINT_BOOL: u8 = 1;
+const DIVIDE_BY_ZERO: u8 = 2;
+const MAIN: u8 = 3;
+const DIVIDE: u8 = 4;
+const MODULO: u8 = 5;
+const SHIFT: u8 = 6;
+
+// The value types and block types used in the module.
+const I32: u8 = 0x7f;
+const EMPTY: u8 = 0x40;
+
+// The instructions used in the module.
+const UNREACHABLE: u8 = 0x00;
+const IF: u8 = 0x04;
+const ELSE: u8 = 0x05;
+const END: u8 = 0x0b;
+const CALL: u8 = 0x10;
+const DROP: u8 = 0x1a;
+const LOCAL_GET: u8 = 0x20;
+const LOCAL_TEE: u8 = 0x22;
+const I32_CONST: u8 = 0x41;
+const I32_EQZ: u8 = 0x45;
+const I32_EQ: u8 = 0x46;
+const I32_NE: u8 = 0x47;
+const I32_LT_S: u8 = 0x48;
+const I32_GT_S: u8 = 0x4a;
+const I32_LE_S: u8 = 0x4c;
+const I32_GE_S: u8 = 0x4e;
+const I32_ADD: u8 = 0x6a;
+const I32_SUB: u8 = 0x6b;
+const I32_MUL: u8 = 0x6c;
+const I32_DIV_S: u8 = 0x6d;
+const I32_REM_S: u8 = 0x6f;
+const I32_AND: u8 = 0x71;
+const I32_OR: u8 = 0x72;
+const I32_XOR: u8 = 0x73;
+const I32_SHL: u8 = 0x74;
+const I32_SHR_S: u8 = 0x75;
+
+/// Compiles the parsed code to a WebAssembly module. Constants are folded first if the optimization level in the
+/// options folds them for bytecode, and the line and column of each division are only kept with detailed errors.
+#[must_use]
+pub fn compile(parser_output: ParserOutput, options: CompilerOptions) -> WasmOutput {
+    let mut logs: Vec<Log> = parser_output.logs;
+    let mut module: Option<Vec<u8>> = None;
+    if !is_error(&logs) {
+        let mut expr: Expression = parser_output.expr;
+        if CompilerPass::ConstantFolding.is_default(options.opt_level) {
+            expr = fold_constants(&expr, &mut logs);
+        }
+        logs = options.diagnostics.apply(logs);
+        let mut lowering: Lowering = Lowering {
+            code: Vec::new(),
+            locals: Vec::new(),
+            logs: Vec::new(),
+            detailed_err: options.detailed_err,
+        };
+        lowering.lower(&expr);
+        match expr.get_type() {
+            Some(Type::Int) => lowering.code.extend_from_slice(&[CALL, PRINT_INT]),
+            Some(Type::Bool) => lowering.code.extend_from_slice(&[CALL, PRINT_BOOL]),
+            _ => {}
+        }
+        logs.append(&mut lowering.logs);
+        if !is_error(&logs) {
+            module = Some(encode_module(&lowering.code, lowering.locals.len()));
+        }
+    }
+    WasmOutput {
+        file_text: parser_output.file_text,
+        module,
+        logs,
+    }
+}
+
+// The state of lowering the AST to the body of the main function.
+struct Lowering {
+    code: Vec<u8>,
+    // The declaration tokens of the variables, in the order of their locals.
+    locals: Vec<Token>,
+    logs: Vec<Log>,
+    detailed_err: bool,
+}
+
+impl Lowering {
+    // Adds the instructions that leave the value of the expression on the stack, or nothing if it has no value.
+    fn lower(&mut self, expr: &Expression) {
+        if let Some(feature) = unsupported_feature(expr) {
+            self.unsupported(&feature, expr);
+            return;
+        }
+        match expr {
+            Expression::Binary {
+                left, op, right, ..
+            } => self.lower_binary(left, *op, right),
+            Expression::Unary { op, expr, .. } => match op.token_type {
+                TokenType::Minus => {
+                    self.push_int(0);
+                    self.lower(expr);
+                    self.code.push(I32_SUB);
+                }
+                TokenType::Tilde => {
+                    self.lower(expr);
+                    self.push_int(-1);
+                    self.code.push(I32_XOR);
+                }
+                TokenType::ExclamationMark => {
+                    self.lower(expr);
+                    self.code.push(I32_EQZ);
+                }
+                _ => self.unsupported("this operator", expr),
+            },
+            Expression::Cast {
+                expr_type,
+                expr: inner,
+            } => {
+                self.lower(inner);
+                if expr_type == &Some(Type::Bool) && inner.get_type() == Some(Type::Int) {
+                    self.push_int(0);
+                    self.code.push(I32_NE);
+                }
+            }
+            Expression::Grouping { expr, .. } => self.lower(expr),
+            Expression::Literal { token, .. } => match token.token_type {
+                TokenType::IntLiteral(value) => self.push_int(value.cast_signed()),
+                TokenType::True => self.push_int(1),
+                TokenType::False => self.push_int(0),
+                _ => self.unsupported("this literal", expr),
+            },
+            Expression::Statement { expr } => {
+                self.lower(expr);
+                if expr
+                    .get_type()
+                    .is_some_and(|expr_type| expr_type != Type::Void)
+                {
+                    self.code.push(DROP);
+                }
+            }
+            Expression::ExpressionList { list } => {
+                for expr in list {
+                    self.lower(expr);
+                }
+            }
+            Expression::VariableDeclaration { initialized_var } => {
+                // Locals start as zero, which is the default value of ints and bools.
+                if let Expression::Variable { token, .. } = **initialized_var {
+                    let local: usize = self.local(token);
+                    self.code.push(LOCAL_GET);
+                    push_u32(&mut self.code, local);
+                }
+            }
+            Expression::Variable { token, .. } => {
+                let local: usize = self.local(*token);
+                self.code.push(LOCAL_GET);
+                push_u32(&mut self.code, local);
+            }
+            // Declarations only matter where they are used, and flags are replaced by their values. Everything else
+            // was reported as unsupported above.
+            _ => {}
+        }
+    }
+
+    // Adds the instructions for a binary operation or an assignment.
+    fn lower_binary(&mut self, left: &Expression, op: Token, right: &Expression) {
+        if op.token_type == TokenType::Equals {
+            let var: &Expression = match left {
+                Expression::VariableDeclaration { initialized_var } => initialized_var,
+                _ => left,
+            };
+            if let Expression::Variable { token, .. } = var {
+                let local: usize = self.local(*token);
+                self.lower(right);
+                self.code.push(LOCAL_TEE);
+                push_u32(&mut self.code, local);
+            }
+            return;
+        }
+        self.lower(left);
+        if op.token_type == TokenType::RightShift {
+            // A right shift is a left shift by the negated amount.
+            self.push_int(0);
+            self.lower(right);
+            self.code.extend_from_slice(&[I32_SUB, CALL, SHIFT]);
+            return;
+        }
+        self.lower(right);
+        match op.token_type {
+            TokenType::Plus => self.code.push(I32_ADD),
+            TokenType::Minus => self.code.push(I32_SUB),
+            TokenType::Star => self.code.push(I32_MUL),
+            TokenType::Slash | TokenType::Percent => {
+                let (line, col): (usize, usize) = if self.detailed_err {
+                    (op.line, op.col)
+                } else {
+                    (0, 0)
+                };
+                self.push_int(i32::try_from(line).unwrap_or(i32::MAX));
+                self.push_int(i32::try_from(col).unwrap_or(i32::MAX));
+                let function: u8 = if op.token_type == TokenType::Slash {
+                    DIVIDE
+                } else {
+                    MODULO
+                };
+                self.code.extend_from_slice(&[CALL, function]);
+            }
+            TokenType::LeftShift => self.code.extend_from_slice(&[CALL, SHIFT]),
+            TokenType::Ampersand => self.code.push(I32_AND),
+            TokenType::Bar => self.code.push(I32_OR),
+            TokenType::Caret => self.code.push(I32_XOR),
+            TokenType::Less => self.code.push(I32_LT_S),
+            TokenType::LessEqual => self.code.push(I32_LE_S),
+            TokenType::Greater => self.code.push(I32_GT_S),
+            TokenType::GreaterEqual => self.code.push(I32_GE_S),
+            TokenType::Equality => self.code.push(I32_EQ),
+            TokenType::Inequality => self.code.push(I32_NE),
+            _ => self.logs.push(Log {
+                log_type: LogType::Error(ErrorType::UnsupportedByBackend(
+                    "wasm".to_string(),
+                    "this operator".to_string(),
+                )),
+                line_and_col: Some((op.line, op.col)),
+                span: Some(op.span()),
+                notes: Vec::new(),
+            }),
+        }
+    }
+
+    // Gets the local of the variable with the declaration token, adding a local if it doesn't have one yet.
+    fn local(&mut self, token: Token) -> usize {
+        if let Some(local) = self.locals.iter().position(|other| *other == token) {
+            local
+        } else {
+            self.locals.push(token);
+            self.locals.len() - 1
+        }
+    }
+
+    // Adds an instruction that pushes the int.
+    fn push_int(&mut self, value: i32) {
+        self.code.push(I32_CONST);
+        push_i32(&mut self.code, value);
+    }
+
+    // Reports that the backend can't compile a feature used by the expression.
+    fn unsupported(&mut self, feature: &str, expr: &Expression) {
+        self.logs.push(unsupported_log("wasm", feature, expr));
+    }
+}
+
+// Encodes the module, given the body of the main function and the number of locals it uses.
+fn encode_module(main: &[u8], locals: usize) -> Vec<u8> {
+    let mut module: Vec<u8> = b"\0asm".to_vec();
+    module.extend_from_slice(&1u32.to_le_bytes());
+
+    // The types of the functions: (i32), (i32, i32), (), (i32, i32, i32, i32) -> i32, and (i32, i32) -> i32.
+    let types: [(&[u8], &[u8]); 5] = [
+        (&[I32], &[]),
+        (&[I32, I32], &[]),
+        (&[], &[]),
+        (&[I32, I32, I32, I32], &[I32]),
+        (&[I32, I32], &[I32]),
+    ];
+    let mut contents: Vec<u8> = Vec::new();
+    push_u32(&mut contents, types.len());
+    for (params, results) in types {
+        contents.push(0x60);
+        push_bytes(&mut contents, params);
+        push_bytes(&mut contents, results);
+    }
+    push_section(&mut module, 1, &contents);
+
+    let imports: [(&str, u8); 3] = [("print_int", 0), ("print_bool", 0), ("divide_by_zero", 1)];
+    let mut contents: Vec<u8> = Vec::new();
+    push_u32(&mut contents, imports.len());
+    for (name, function_type) in imports {
+        push_bytes(&mut contents, b"env");
+        push_bytes(&mut contents, name.as_bytes());
+        contents.extend_from_slice(&[0x00, function_type]);
+    }
+    push_section(&mut module, 2, &contents);
+
+    // The types of main, divide, modulo, and shift.
+    push_section(&mut module, 3, &[4, 2, 3, 3, 4]);
+
+    let mut contents: Vec<u8> = Vec::new();
+    push_u32(&mut contents, 1);
+    push_bytes(&mut contents, b"main");
+    contents.extend_from_slice(&[0x00, MAIN]);
+    push_section(&mut module, 7, &contents);
+
+    let mut body: Vec<u8> = Vec::new();
+    if locals == 0 {
+        body.push(0);
+    } else {
+        body.push(1);
+        push_u32(&mut body, locals);
+        body.push(I32);
+    }
+    body.extend_from_slice(main);
+    body.push(END);
+    let mut contents: Vec<u8> = Vec::new();
+    push_u32(&mut contents, 4);
+    for function in [body, divide_body(), modulo_body(), shift_body()] {
+        push_bytes(&mut contents, &function);
+    }
+    push_section(&mut module, 10, &contents);
+    module
+}
+
+// Gets the instructions that call divide_by_zero and trap if the second parameter is zero. The line and column are
+// the third and fourth parameters.
+fn zero_check() -> Vec<u8> {
+    vec![
+        LOCAL_GET,
+        1,
+        I32_EQZ,
+        IF,
+        EMPTY,
+        LOCAL_GET,
+        2,
+        LOCAL_GET,
+        3,
+        CALL,
+        DIVIDE_BY_ZERO,
+        UNREACHABLE,
+        END,
+    ]
+}
+
+// Gets the body of divide(a, b, line, col), which divides a by b, wrapping if the result doesn't fit.
+fn divide_body() -> Vec<u8> {
+    let mut body: Vec<u8> = vec![0];
+    body.append(&mut zero_check());
+    body.extend_from_slice(&[
+        LOCAL_GET, 1, I32_CONST, 0x7f, I32_EQ, IF,
+        I32, // b == -1, which is the only way to overflow.
+        I32_CONST, 0, LOCAL_GET, 0, I32_SUB, ELSE, LOCAL_GET, 0, LOCAL_GET, 1, I32_DIV_S, END, END,
+    ]);
+    body
+}
+
+// Gets the body of modulo(a, b, line, col), which gives the remainder of a divided by b that is never negative.
+fn modulo_body() -> Vec<u8> {
+    let mut body: Vec<u8> = vec![0];
+    body.append(&mut zero_check());
+    body.extend_from_slice(&[
+        LOCAL_GET, 0, LOCAL_GET, 1, I32_REM_S, LOCAL_TEE, 2, I32_CONST, 0, I32_LT_S, IF, I32,
+        // A negative remainder is made positive by adding the absolute value of b.
+        LOCAL_GET, 2, LOCAL_GET, 1, I32_CONST, 0, I32_LT_S, IF, I32, I32_CONST, 0, LOCAL_GET, 1,
+        I32_SUB, ELSE, LOCAL_GET, 1, END, I32_ADD, ELSE, LOCAL_GET, 2, END, END,
+    ]);
+    body
+}
+
+// Gets the body of shift(a, b), which shifts a left by b bits, or right if b is negative. Shifting by 32 or more bits
+// shifts every bit out.
+fn shift_body() -> Vec<u8> {
+    vec![
+        0, LOCAL_GET, 1, I32_CONST, 32, I32_GE_S, IF, I32, I32_CONST, 0, ELSE, LOCAL_GET, 1,
+        I32_CONST, 0x60, I32_LE_S, IF, I32, LOCAL_GET, 0, I32_CONST, 31, I32_SHR_S, ELSE,
+        LOCAL_GET, 1, I32_CONST, 0, I32_GE_S, IF, I32, LOCAL_GET, 0, LOCAL_GET, 1, I32_SHL, ELSE,
+        LOCAL_GET, 0, I32_CONST, 0, LOCAL_GET, 1, I32_SUB, I32_SHR_S, END, END, END, END,
+    ]
+}
+
+// Adds a section with its id and the length of its contents.
+fn push_section(module: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    module.push(id);
+    push_bytes(module, contents);
+}
+
+// Adds bytes preceded by their length, which is how names and vectors of value types are encoded.
+fn push_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    push_u32(bytes, value.len());
+    bytes.extend_from_slice(value);
+}
+
+// Adds an unsigned LEB128 number.
+fn push_u32(bytes: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte: u8 = u8::try_from(value & 0x7f).expect("masked to 7 bits");
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+// Adds a signed LEB128 number.
+fn push_i32(bytes: &mut Vec<u8>, mut value: i32) {
+    loop {
+        let byte: u8 = u8::try_from(value & 0x7f).expect("masked to 7 bits");
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
@@ -0,0 +1,291 @@
+//! The module for compiling many source files at once.
+
+use crate::{compiler, events, lexer, parser, util::log, util::trie};
+use compiler::{compile, CompilerOptions, CompilerOutput};
+use events::{Event, EventLog, Stage};
+use lexer::{keyword_trie, lex, lex_with_keywords, LexerOutput, Span, Token, TokenType};
+use log::{ErrorFormat, ErrorType, Log, LogType, Note, RenderOptions};
+use parser::{parse, ParserOutput};
+use std::fs::read_to_string;
+use std::path::PathBuf;
+
+use trie::Node;
+
+/// A path to a source file.
+pub type SourcePath = PathBuf;
+
+/// Stores the text of every source file that has been compiled, so diagnostics can refer back to them.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<(SourcePath, String)>,
+}
+
+impl SourceMap {
+    /// Adds a file to the source map and returns its id.
+    pub fn add(&mut self, path: SourcePath, file_text: String) -> usize {
+        self.files.push((path, file_text));
+        self.files.len() - 1
+    }
+
+    /// Gets the path of the file with the given id.
+    #[must_use]
+    pub fn path(&self, file_id: usize) -> Option<&SourcePath> {
+        self.files.get(file_id).map(|file| &file.0)
+    }
+
+    /// Gets the text of the file with the given id.
+    #[must_use]
+    pub fn file_text(&self, file_id: usize) -> Option<&str> {
+        self.files.get(file_id).map(|file| file.1.as_str())
+    }
+
+    /// The number of files in the source map.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns whether or not the source map has no files.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// Source files joined into a single program, so that the functions declared in each file can be called by the files
+/// after it. Each file starts on a new line of the program, so every diagnostic can be traced back to its file, and is
+/// printed with the name of the file and the line in it (`main.txt: error[E0105] (line 2:3): division by zero.`).
+#[derive(Clone)]
+pub struct LinkedSource {
+    /// The text of the program.
+    pub file_text: String,
+    /// The paths of the files, separated by commas.
+    pub name: String,
+    // The path of each file and the line and byte of the program that it starts on.
+    files: Vec<(SourcePath, usize, usize)>,
+}
+
+impl LinkedSource {
+    /// Joins the files in the given order. Only the value of the last file is printed, so every other file that
+    /// doesn't end with a semicolon is given one on the line after its code.
+    #[must_use]
+    pub fn link(files: Vec<(SourcePath, String)>) -> Self {
+        let file_count: usize = files.len();
+        let mut file_text: String = String::new();
+        let mut starts: Vec<(SourcePath, usize, usize)> = Vec::new();
+        let mut line: usize = 1;
+        for (index, (path, text)) in files.into_iter().enumerate() {
+            starts.push((path, line, file_text.len()));
+            file_text.push_str(&text);
+            line += text.matches('\n').count();
+            if index + 1 < file_count {
+                if needs_semicolon(&text) {
+                    file_text.push_str("\n;");
+                    line += 1;
+                }
+                file_text.push('\n');
+                line += 1;
+            }
+        }
+        let name: String = starts
+            .iter()
+            .map(|(path, _, _)| path.display().to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        Self {
+            file_text,
+            name,
+            files: starts,
+        }
+    }
+
+    /// Gets the file that a line of the program is in, along with the line in that file.
+    #[must_use]
+    pub fn locate(&self, line: usize) -> Option<(&SourcePath, usize)> {
+        self.file_at(line)
+            .map(|(path, start, _)| (path, line - start + 1))
+    }
+
+    // Gets the file that a line of the program is in, along with the line and byte of the program it starts on.
+    fn file_at(&self, line: usize) -> Option<(&SourcePath, usize, usize)> {
+        self.files
+            .iter()
+            .rev()
+            .find(|(_, start, _)| *start <= line)
+            .map(|(path, start, byte)| (path, *start, *byte))
+    }
+
+    /// Gets the file that a log comes from, along with the log with its line and span in that file. Logs without a
+    /// location don't come from a file and are returned unchanged.
+    #[must_use]
+    pub fn locate_log(&self, log: &Log) -> (Option<&SourcePath>, Log) {
+        if let Some((line, col)) = log.line_and_col {
+            if let Some((path, start, byte)) = self.file_at(line) {
+                // A span that starts in an earlier file is cut off at the start of this one.
+                let locate = |span: &Span| Span {
+                    start: ((span.start.0 + 1).saturating_sub(start), span.start.1),
+                    end: (span.end.0 + 1 - start, span.end.1),
+                    byte_range: span.byte_range.start.saturating_sub(byte)
+                        ..span.byte_range.end - byte,
+                };
+                return (
+                    Some(path),
+                    Log {
+                        log_type: log.log_type.clone(),
+                        line_and_col: Some((line - start + 1, col)),
+                        span: log.span.as_ref().map(locate),
+                        notes: log
+                            .notes
+                            .iter()
+                            .map(|note| Note {
+                                log_type: note.log_type.clone(),
+                                span: note.span.as_ref().map(locate),
+                            })
+                            .collect(),
+                    },
+                );
+            }
+        }
+        (None, log.clone())
+    }
+
+    /// Writes a log the way it is printed, starting with the path of its file if it has a location. JSON logs name the
+    /// file instead.
+    #[must_use]
+    pub fn describe(&self, log: &Log, options: RenderOptions) -> String {
+        match self.locate_log(log) {
+            (path, log) if options.format == ErrorFormat::Json => log.to_json(path.map(|path| path.display().to_string()).as_deref(), options.numbers),
+            (Some(path), log) => format!("{}: {}", path.display(), log.render(None, options)),
+            (None, log) => log.render(None, options),
+        }
+    }
+
+    /// Writes a log the way it is printed, followed by the line of its file that it is about with its span underlined.
+    /// JSON logs name the file instead, without the line.
+    #[must_use]
+    pub fn render(&self, log: &Log, options: RenderOptions) -> String {
+        let byte: usize = log
+            .line_and_col
+            .and_then(|(line, _)| self.file_at(line))
+            .map_or(0, |(_, _, byte)| byte);
+        match self.locate_log(log) {
+            (path, log) if options.format == ErrorFormat::Json => log.to_json(path.map(|path| path.display().to_string()).as_deref(), options.numbers),
+            (Some(path), log) => format!(
+                "{}: {}",
+                path.display(),
+                log.render(Some(&self.file_text[byte..]), options)
+            ),
+            (None, log) => log.render(Some(&self.file_text), options),
+        }
+    }
+}
+
+// Returns whether or not the code has to be given a semicolon to end its last expression.
+fn needs_semicolon(file_text: &str) -> bool {
+    let tokens: Vec<Token> = lex(file_text).tokens;
+    tokens
+        .iter()
+        .rev()
+        .find(|token| token.token_type != TokenType::EOF)
+        .is_some_and(|token| token.token_type != TokenType::Semicolon)
+}
+
+/// The result of compiling a single file in a batch.
+pub struct FileResult {
+    pub path: SourcePath,
+    /// The id of the file in the source map. This is None if the file could not be read.
+    pub file_id: Option<usize>,
+    pub output: CompilerOutput,
+}
+
+/// Data that is shared between every file compiled with it. Build tools can keep one of these
+/// around to avoid paying startup costs for every file they compile.
+pub struct CompilationCache {
+    pub source_map: SourceMap,
+    /// Where the progress of each file is reported. Nothing is reported by default.
+    pub events: EventLog,
+    keywords: Node<char, TokenType>,
+}
+
+impl Default for CompilationCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CompilationCache {
+    /// Creates an empty cache.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            source_map: SourceMap::default(),
+            events: EventLog::default(),
+            keywords: keyword_trie(),
+        }
+    }
+
+    /// Compiles a single file, adding it to the source map.
+    pub fn compile_file(&mut self, path: &SourcePath, options: CompilerOptions) -> FileResult {
+        let name: String = path.display().to_string();
+        let Ok(file_text) = read_to_string(path) else {
+            let result: FileResult = FileResult {
+                path: path.clone(),
+                file_id: None,
+                output: CompilerOutput {
+                    file_text: String::new(),
+                    bytecode: None,
+                    logs: vec![Log {
+                        log_type: LogType::Error(ErrorType::CLICantOpenFile(name.clone())),
+                        line_and_col: None,
+                        span: None,
+                        notes: Vec::new(),
+                    }],
+                    dump: None,
+                    sizes: Vec::new(),
+                    cancelled_at: None,
+                },
+            };
+            self.events
+                .emit(&Event::diagnostics(&name, &result.output.logs));
+            return result;
+        };
+        let lexer_output: LexerOutput = self.events.stage(&name, Stage::Lex, || {
+            lex_with_keywords(&file_text, &self.keywords)
+        });
+        let parser_output: ParserOutput = self
+            .events
+            .stage(&name, Stage::Parse, || parse(lexer_output));
+        let output: CompilerOutput = self
+            .events
+            .stage(&name, Stage::Compile, || compile(parser_output, options));
+        self.events.emit(&Event::diagnostics(&name, &output.logs));
+        FileResult {
+            path: path.clone(),
+            file_id: Some(self.source_map.add(path.clone(), file_text)),
+            output,
+        }
+    }
+
+    /// Compiles every file, returning the results in the same order as the paths.
+    pub fn compile_many(
+        &mut self,
+        paths: &[SourcePath],
+        options: CompilerOptions,
+    ) -> Vec<FileResult> {
+        paths
+            .iter()
+            .map(|path| self.compile_file(path, options))
+            .collect()
+    }
+}
+
+/// Compiles every file with a fresh cache. Returns the results for each file and the source map of the files.
+#[must_use]
+pub fn compile_many(
+    paths: &[SourcePath],
+    options: CompilerOptions,
+) -> (Vec<FileResult>, SourceMap) {
+    let mut cache: CompilationCache = CompilationCache::new();
+    let results: Vec<FileResult> = cache.compile_many(paths, options);
+    (results, cache.source_map)
+}
@@ -0,0 +1,165 @@
+//! The module for benchmarking, with the synthetic programs that the benches in `benches/` compile and run, and the
+//! timing of runs used by the `bench` subcommand. Each run of a program is timed separately after a few untimed runs,
+//! so that the first runs, which fill caches and precompile the bytecode, don't skew the times.
+
+use crate::{util::log, vm};
+use log::Log;
+use vm::Vm;
+
+use std::fmt::Write as _;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// The sizes of the synthetic programs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProgramSize {
+    Small,
+    Medium,
+    Large,
+}
+
+impl ProgramSize {
+    /// Every size, from smallest to largest.
+    pub const ALL: [Self; 3] = [Self::Small, Self::Medium, Self::Large];
+
+    /// Gets the name of the size, as used in the names of benchmarks.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Small => "small",
+            Self::Medium => "medium",
+            Self::Large => "large",
+        }
+    }
+
+    // Gets the number of functions in a program of this size.
+    fn function_count(self) -> usize {
+        match self {
+            Self::Small => 8,
+            Self::Medium => 128,
+            Self::Large => 1024,
+        }
+    }
+}
+
+/// Generates a program of the given size, which uses most of the language: functions that call each other, ints,
+/// bools, strings, and lists. Each function is declared and then called once at the top level, so the time the
+/// program takes to compile and to run both grow with its size.
+#[must_use]
+pub fn synthetic_program(size: ProgramSize) -> String {
+    let mut code: String = String::new();
+    // Writing to a string can't fail.
+    for index in 0..size.function_count() {
+        // Each function calls the one at half its index, so calls never go more than a few levels deep.
+        let call: String = if index == 0 { "0".to_string() } else { format!("f{}(b, a)", index / 2) };
+        let _ = writeln!(
+            code,
+            "fn f{index}(a: int, b: int) -> int {{ int c = a * {index} + (b ^ {call}); bool odd = c % 2 == 1; c + (int) odd }}"
+        );
+        let previous: String = if index == 0 { "1".to_string() } else { format!("v{}", index - 1) };
+        let _ = writeln!(code, "int v{index} = f{index}({previous}, {index}) % 1000;");
+        if index % 16 == 0 {
+            let _ = writeln!(
+                code,
+                "list<int> l{index} = list<int> {{v{index}, {index}, 3}}; string s{index} = to_string(v{index} + len(l{index}));"
+            );
+        }
+    }
+    let _ = write!(code, "v{}", size.function_count() - 1);
+    code
+}
+
+/// How many times a program is run when it is benchmarked.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BenchConfig {
+    /// The number of runs before the timed runs, whose times are thrown away.
+    pub warmups: usize,
+    /// The number of timed runs.
+    pub iterations: usize,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            warmups: 3,
+            iterations: 10,
+        }
+    }
+}
+
+/// The times of the timed runs of a program.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BenchResult {
+    /// The time of each run, in the order they ran.
+    pub times: Vec<Duration>,
+}
+
+impl BenchResult {
+    /// Gets the time of the fastest run.
+    #[must_use]
+    pub fn min(&self) -> Duration {
+        self.times.iter().min().copied().unwrap_or_default()
+    }
+
+    /// Gets the time of the slowest run.
+    #[must_use]
+    pub fn max(&self) -> Duration {
+        self.times.iter().max().copied().unwrap_or_default()
+    }
+
+    /// Gets the average time of the runs.
+    #[must_use]
+    pub fn mean(&self) -> Duration {
+        let count: u32 = self.times.len().try_into().unwrap_or(u32::MAX);
+        if count == 0 {
+            return Duration::ZERO;
+        }
+        self.times.iter().sum::<Duration>() / count
+    }
+
+    /// Gets the middle time of the runs, which is the average of the two middle times if there is an even number.
+    #[must_use]
+    pub fn median(&self) -> Duration {
+        let mut times: Vec<Duration> = self.times.clone();
+        times.sort();
+        match times.len() {
+            0 => Duration::ZERO,
+            len if len % 2 == 1 => times[len / 2],
+            len => (times[len / 2 - 1] + times[len / 2]) / 2,
+        }
+    }
+
+    /// Writes the times as a table for people to read.
+    #[must_use]
+    pub fn to_table(&self) -> String {
+        [("min", self.min()), ("median", self.median()), ("mean", self.mean()), ("max", self.max())]
+            .iter()
+            .map(|(name, time)| format!("{name:<8}{:>14}", format!("{time:.3?}")))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// Runs the bytecode the number of times in the config, timing each run after the warmups. The bytecode is verified
+/// before the first run, so bytecode read from a file can't crash the VM. The program can't access files, and what it
+/// prints is discarded.
+///
+/// # Errors
+/// Returns the errors of the verifier, or of the first run that fails, as a program that fails isn't worth timing.
+pub fn time_runs(bytecode: Vec<u8>, config: BenchConfig) -> Result<BenchResult, Vec<Log>> {
+    let mut vm: Vm = Vm::new(bytecode);
+    vm.set_verify(true);
+    vm.set_output(io::sink());
+    vm.set_error_output(io::sink());
+    vm.precompile()?;
+    for _ in 0..config.warmups {
+        vm.run()?;
+    }
+    let mut times: Vec<Duration> = Vec::with_capacity(config.iterations);
+    for _ in 0..config.iterations {
+        let start: Instant = Instant::now();
+        vm.run()?;
+        times.push(start.elapsed());
+    }
+    Ok(BenchResult { times })
+}
@@ -1,28 +1,43 @@
 #![deny(clippy::all)]
 #![deny(clippy::pedantic)]
 
-use krust::cli_reader::{read_command_line, CLIInfo};
-use krust::compiler::{compile, CompilerOutput};
+use krust::cli_reader::{read_command_line, CliOptions};
+use krust::compiler::{compile_with_passes, CompilerOptions, CompilerOutput};
+use krust::events::{Event, EventLog, Stage};
 use krust::lexer::{lex, LexerOutput};
 use krust::parser::{parse, ParserOutput};
-use krust::util::log::{ErrorType, Log, LogType};
+use krust::pass_manager::PassManager;
+use krust::util::log::{
+    limit_errors, ColorChoice, DiagnosticSink, ErrorFormat, ErrorType, JsonSink, Log,
+    LogType, RenderOptions, TerminalSink,
+};
+use krust::util::number_format::NumberFormat;
 use std::env::{current_dir, set_current_dir};
 use std::fs::{rename, File, read_to_string};
-use std::io::{prelude::*, Error};
+use std::io::{prelude::*, stderr, Error};
 use std::path::PathBuf;
 use std::process::Command;
 
 fn main() {
-    let cli_output: (Option<CLIInfo>, Vec<Log>) = read_command_line();
+    let cli_output: (Option<CliOptions>, Vec<Log>) = read_command_line();
+    let color: ColorChoice = cli_output.0.as_ref().map_or(ColorChoice::Auto, |cli_info| cli_info.color);
+    let render: RenderOptions = RenderOptions {
+        format: cli_output.0.as_ref().map_or(ErrorFormat::Human, |cli_info| cli_info.error_format),
+        colored: color.enabled(),
+        numbers: cli_output.0.as_ref().map_or(NumberFormat::default(), |cli_info| cli_info.number_format),
+    };
     for log in cli_output.1 {
-        eprintln!("{log}");
+        eprintln!("{}", log.render(None, render));
     }
 
-    if cli_output.0.is_some() {
-        let cli_output: CLIInfo = cli_output.0.expect("checked by if statement");
+    if let Some(cli_output) = cli_output.0 {
+        let events: EventLog = EventLog::new(cli_output.log_json);
         let compiler_output: CompilerOutput =
-            generate_bytecode(&cli_output.file_path, cli_output.cli_args);
-        let mut logs: Vec<Log> = compiler_output.logs.clone();
+            generate_bytecode(&cli_output.file_path, cli_output.options, &cli_output.passes, events);
+        if let Some(dump) = &compiler_output.dump {
+            eprintln!("{dump}");
+        }
+        let mut logs: Vec<Log> = limit_errors(&compiler_output.logs, cli_output.error_limit);
         if let Some(bytecode) = compiler_output.bytecode {
             let file_path: String = cli_output
                 .file_path
@@ -35,25 +50,38 @@ fn main() {
                 logs.push(Log {
                     log_type: LogType::Error(ErrorType::FatalError),
                     line_and_col: None,
+                    span: None,
+                    notes: Vec::new(),
                 });
+            } else {
+                events.emit(&Event::ArtifactWritten { file: cli_output.file_path.clone(), path: file_path });
             }
         } else {
             logs.push(Log {
                 log_type: LogType::Error(ErrorType::CantCompile),
                 line_and_col: None,
+                span: None,
+                notes: Vec::new(),
             });
         }
-        for log in logs {
-            eprintln!("{log}");
+        events.emit(&Event::diagnostics(&cli_output.file_path, &logs));
+        // JSON logs name the file they are about instead of showing its source.
+        if render.format == ErrorFormat::Json {
+            JsonSink::new(stderr(), Some(&cli_output.file_path)).with_number_format(render.numbers).report_all(&logs);
+        } else {
+            TerminalSink::stderr(Some(&compiler_output.file_text))
+                .with_colors(render.colored)
+                .with_number_format(render.numbers)
+                .report_all(&logs);
         }
     }
 }
 
-// Produces bytecode from the file.
-fn generate_bytecode(file_path: &str, cli_args: [u8; 2]) -> CompilerOutput {
-    let lex_output: LexerOutput = lex(read_to_string(file_path).expect("should be valid as error handled in command line reader"));
-    let parse_output: ParserOutput = parse(lex_output);
-    compile(parse_output, cli_args)
+// Produces bytecode from the file, sending the progress of each stage to events.
+fn generate_bytecode(file_path: &str, options: CompilerOptions, passes: &PassManager, events: EventLog) -> CompilerOutput {
+    let lex_output: LexerOutput = events.stage(file_path, Stage::Lex, || lex(&read_to_string(file_path).expect("should be valid as error handled in command line reader")));
+    let parse_output: ParserOutput = events.stage(file_path, Stage::Parse, || parse(lex_output));
+    events.stage(file_path, Stage::Compile, || compile_with_passes(parse_output, options, false, passes))
 }
 
 // Create the exe.
@@ -89,11 +117,14 @@ fn create_compiled_exe(bytecode: &Vec<u8>, file_path: &str) -> Result<(), Error>
 // Creates the rust code that can be compiled into an executable.
 fn create_code(bytecode: &Vec<u8>) -> String {
     format!(
-        "use krust::vm;
+        "use krust::vm::Vm;
     
 fn main(){{
     let bytecode: Vec<u8> = vec!{bytecode:?};
-    vm::run(&bytecode);
+    let mut vm: Vm = Vm::new(bytecode);
+    vm.set_args(std::env::args().skip(1).collect());
+    vm.set_file_access(true);
+    let _ = vm.run();
 }}"
     )
 }
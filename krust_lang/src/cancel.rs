@@ -0,0 +1,147 @@
+//! The module for compiling source code in a way that can be cancelled, such as by an editor when the file changes.
+
+use crate::{compiler, lexer, parser, pass_manager, util::log};
+use compiler::{compile_with_passes, CompilerOptions, CompilerOutput};
+use lexer::{lex, LexerOutput};
+use log::Log;
+use parser::{parse, ParserOutput};
+use pass_manager::{CompilerPass, PassManager};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A flag that can be used to cancel a compilation from another thread. Clones share the same flag.
+#[derive(Clone, Debug)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    // The number of times the token can still be checked before it cancels itself.
+    checks_left: Arc<AtomicUsize>,
+}
+
+impl CancellationToken {
+    /// Creates a token that has not been cancelled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests that any compilation using this token stops at the next pass.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Cancels the token once it has been checked the given number of times, so that a compilation using it stops
+    /// after that many passes. The lexer, the parser, the start of the compiler, and each pass the pass manager runs
+    /// all check the token once.
+    pub fn cancel_after(&self, checks: usize) {
+        self.checks_left.store(checks, Ordering::Relaxed);
+    }
+
+    /// Returns whether or not the token has been cancelled.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    // Checks the token before a pass runs, counting the check towards the limit set with `cancel_after`.
+    pub(crate) fn check(&self) -> bool {
+        let out_of_checks: bool = self
+            .checks_left
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| left.checked_sub(1))
+            .is_err();
+        if out_of_checks {
+            self.cancel();
+        }
+        self.is_cancelled()
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self {
+            cancelled: Arc::default(),
+            checks_left: Arc::new(AtomicUsize::new(usize::MAX)),
+        }
+    }
+}
+
+// Tokens are equal if they share the same flag.
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.cancelled, &other.cancelled)
+    }
+}
+
+impl Eq for CancellationToken {}
+
+/// The passes of the compiler, in the order they are run.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pass {
+    Lex,
+    Parse,
+    Compile,
+    /// A pass run by the pass manager, which checks the token before each pass it runs.
+    Compiler(CompilerPass),
+}
+
+/// The output given by a compilation that may have been cancelled.
+pub struct PartialCompilerOutput {
+    pub file_text: String,
+    /// The bytecode, if every pass was run without errors.
+    pub bytecode: Option<Vec<u8>>,
+    /// The logs produced by the passes that were run.
+    pub logs: Vec<Log>,
+    /// The pass that would have been run next if the compilation was cancelled.
+    pub cancelled_at: Option<Pass>,
+}
+
+impl PartialCompilerOutput {
+    // Creates the output for a compilation that was cancelled before the given pass.
+    fn cancelled(file_text: String, logs: Vec<Log>, pass: Pass) -> Self {
+        Self {
+            file_text,
+            bytecode: None,
+            logs,
+            cancelled_at: Some(pass),
+        }
+    }
+}
+
+/// Compiles the source code, checking the token between passes, including those of the pass manager. If the token is cancelled, the compilation stops
+/// and the logs from the passes that already ran are returned.
+#[must_use]
+pub fn compile_with_cancellation(
+    file_text: &str,
+    options: CompilerOptions,
+    token: &CancellationToken,
+) -> PartialCompilerOutput {
+    if token.check() {
+        return PartialCompilerOutput::cancelled(file_text.to_string(), Vec::new(), Pass::Lex);
+    }
+    let lexer_output: LexerOutput = lex(file_text);
+
+    if token.check() {
+        return PartialCompilerOutput::cancelled(
+            lexer_output.file_text,
+            lexer_output.logs,
+            Pass::Parse,
+        );
+    }
+    let parser_output: ParserOutput = parse(lexer_output);
+
+    if token.check() {
+        return PartialCompilerOutput::cancelled(
+            parser_output.file_text,
+            parser_output.logs,
+            Pass::Compile,
+        );
+    }
+    let mut passes: PassManager = PassManager::with_opt_level(options.opt_level);
+    passes.set_cancellation(token.clone());
+    let compiler_output: CompilerOutput = compile_with_passes(parser_output, options, false, &passes);
+    PartialCompilerOutput {
+        file_text: compiler_output.file_text,
+        bytecode: compiler_output.bytecode,
+        logs: compiler_output.logs,
+        cancelled_at: compiler_output.cancelled_at.map(Pass::Compiler),
+    }
+}
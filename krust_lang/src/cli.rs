@@ -0,0 +1,549 @@
+//! The module for the structure of the command line: the subcommands and compiler flags krust takes, and the help,
+//! version, and shell completions generated from them. The arguments are parsed into what krust is asked to do, and
+//! the flags of a subcommand are read into `CliOptions` by the command line reader.
+//!
+//! Every compiler flag is listed once in `FLAGS` with its value and help text. `krust --help` (or `-h`) lists the
+//! subcommands and flags, `krust build --help` also shows the files the subcommand takes, and `krust --version` (or
+//! `-V`) prints the version. `krust completions bash|zsh|fish` prints a script that completes subcommands, flags, and
+//! the values of flags such as `--emit=disasm`, and any other shell is `error[E0132]`. Library users call `parse_args`
+//! for what the command line asks for, or `cli_reader::read_args` for the flags alone.
+
+use crate::{
+    backend::Backend, cli_reader, compiler::Endianness, pass_manager::CompilerPass, profiler::ProfileFormat, util::log,
+};
+use cli_reader::{get_args, read_args, CliOptions, Command, Emit, Encoding};
+use log::{ErrorType, Log, LogType, WARNING_NAMES};
+
+use std::fmt::Write as _;
+
+/// The name of the subcommand that prints a completion script, which is taken instead of a file.
+pub const COMPLETIONS_COMMAND: &str = "completions";
+
+/// A compiler flag, with what is shown for it in the help and suggested for it by shell completions.
+#[derive(Clone, Copy, Debug)]
+pub struct Flag {
+    /// The name of the flag, including its dashes.
+    pub name: &'static str,
+    /// What follows the name in the help, such as "=BITS", or nothing if the flag takes no value. A value in brackets
+    /// can be left out.
+    pub value: &'static str,
+    /// What the flag does, as shown in the help.
+    pub help: &'static str,
+    /// Gets the values that shell completions suggest for the flag, which are none if it takes any number or path.
+    pub values: fn() -> Vec<&'static str>,
+}
+
+impl Flag {
+    // Gets the ways of writing the flag that shell completions suggest, with each of its values filled in. A flag whose
+    // value can be anything is suggested ending with "=", so the value can be typed after it.
+    fn completions(&self) -> Vec<String> {
+        let values: Vec<&str> = (self.values)();
+        let separator: &str = if self.value.contains('=') { "=" } else { "" };
+        let mut completions: Vec<String> = Vec::new();
+        if self.value.is_empty() || self.value.starts_with('[') {
+            completions.push(self.name.to_string());
+        }
+        if values.is_empty() && self.value.starts_with('=') {
+            completions.push(format!("{}=", self.name));
+        }
+        completions.extend(values.iter().map(|value| format!("{}{separator}{value}", self.name)));
+        completions
+    }
+}
+
+/// Every compiler flag. The command line reader handles each flag by its index in this list.
+pub const FLAGS: [Flag; 35] = [
+    Flag {
+        name: "-pointer_size",
+        value: "=BITS",
+        help: "Sets the size of pointers on the machine the program is compiled for",
+        values: Vec::new,
+    },
+    Flag {
+        name: "-detailed_errors",
+        value: "=BOOL",
+        help: "Sets whether or not errors are explained in detail",
+        values: || vec!["true", "false"],
+    },
+    Flag {
+        name: "-min_severity",
+        value: "=SEVERITY",
+        help: "Hides the logs that are less severe than this",
+        values: || vec!["info", "note", "help", "warning", "error"],
+    },
+    Flag {
+        name: "-isa_level",
+        value: "=LEVEL",
+        help: "Sets the newest instruction set level the program can use",
+        values: Vec::new,
+    },
+    Flag {
+        name: "-internal_checks",
+        value: "=BOOL",
+        help: "Sets whether or not the invariants between compiler passes are checked",
+        values: || vec!["true", "false"],
+    },
+    Flag {
+        name: "--log-json",
+        value: "[=STREAM]",
+        help: "Writes progress events as JSON lines, to stderr unless stdout is given",
+        values: || vec!["stdout", "stderr"],
+    },
+    Flag {
+        name: "--no-addresses",
+        value: "",
+        help: "Writes labels instead of offsets in disassembly",
+        values: Vec::new,
+    },
+    Flag {
+        name: "--number-format",
+        value: "=FORMAT",
+        help: "Sets how numbers are written in messages",
+        values: || vec!["grouped", "plain"],
+    },
+    Flag {
+        name: "--sandbox",
+        value: "",
+        help: "Runs the program without access to files",
+        values: Vec::new,
+    },
+    Flag {
+        name: "--passes",
+        value: "=PASS,...",
+        help: "Runs only the given compiler passes, along with the required ones",
+        values: || CompilerPass::ALL.map(CompilerPass::name).to_vec(),
+    },
+    Flag {
+        name: "--disable-pass",
+        value: "=PASS,...",
+        help: "Stops the given compiler passes from running",
+        values: || CompilerPass::ALL.map(CompilerPass::name).to_vec(),
+    },
+    Flag {
+        name: "--print-after",
+        value: "=PASS",
+        help: "Prints the code after the given compiler pass runs",
+        values: || CompilerPass::ALL.map(CompilerPass::name).to_vec(),
+    },
+    Flag {
+        name: "--timeout",
+        value: "=MILLISECONDS",
+        help: "Sets how long each file checked by crashcheck can take",
+        values: Vec::new,
+    },
+    Flag {
+        name: "-O",
+        value: "<LEVEL>",
+        help: "Sets the optimization level, such as -O1",
+        values: || vec!["0", "1", "2"],
+    },
+    Flag {
+        name: "--emit",
+        value: "=OUTPUT",
+        help: "Prints an output of the compiler instead of running the program (c: ints and bools only)",
+        values: || Emit::ALL.map(Emit::name).to_vec(),
+    },
+    Flag {
+        name: "--target",
+        value: "=TARGET",
+        help: "Sets what the program is compiled to (wasm: ints and bools only)",
+        values: || Backend::ALL.map(Backend::name).to_vec(),
+    },
+    Flag {
+        name: "--encoding",
+        value: "=ENCODING",
+        help: "Sets how operations are encoded in the bytecode",
+        values: || Encoding::ALL.map(Encoding::name).to_vec(),
+    },
+    Flag {
+        name: "--endianness",
+        value: "=ORDER",
+        help: "Sets the byte order of the bytecode",
+        values: || Endianness::ALL.map(Endianness::name).to_vec(),
+    },
+    Flag {
+        name: "--max-bytecode-size",
+        value: "=BYTES",
+        help: "Fails to compile programs whose bytecode is larger than this",
+        values: Vec::new,
+    },
+    Flag {
+        name: "--profile",
+        value: "[=FORMAT]",
+        help: "Prints a profile once the program has run, as a table unless json is given",
+        values: || ProfileFormat::ALL.map(ProfileFormat::name).to_vec(),
+    },
+    Flag {
+        name: "--interpret",
+        value: "",
+        help: "Runs the program with the interpreter instead of the VM",
+        values: Vec::new,
+    },
+    Flag {
+        name: "--error-format",
+        value: "=FORMAT",
+        help: "Sets how logs are printed",
+        values: || vec!["human", "json"],
+    },
+    Flag {
+        name: "-W",
+        value: "=WARNING",
+        help: "Reports the warning, which can be warnings or lints for all of them",
+        values: warning_values,
+    },
+    Flag {
+        name: "-A",
+        value: "=WARNING",
+        help: "Hides the warning",
+        values: warning_values,
+    },
+    Flag {
+        name: "--deny",
+        value: "=WARNING",
+        help: "Makes the warning an error",
+        values: warning_values,
+    },
+    Flag {
+        name: "--color",
+        value: "=WHEN",
+        help: "Sets when logs are printed in color",
+        values: || vec!["auto", "always", "never"],
+    },
+    Flag {
+        name: "--error-limit",
+        value: "=COUNT",
+        help: "Sets the most errors printed for a file, or 0 for no limit",
+        values: Vec::new,
+    },
+    Flag {
+        name: "--output",
+        value: "=PATH",
+        help: "Sets where build writes the program",
+        values: Vec::new,
+    },
+    Flag {
+        name: "-o",
+        value: "=PATH",
+        help: "The same as --output",
+        values: Vec::new,
+    },
+    Flag {
+        name: "--check",
+        value: "",
+        help: "Makes fmt check that the file is formatted instead of formatting it",
+        values: Vec::new,
+    },
+    Flag {
+        name: "--filter",
+        value: "=TEXT",
+        help: "Makes test run only the tests whose names contain the text",
+        values: Vec::new,
+    },
+    Flag {
+        name: "--warmups",
+        value: "=COUNT",
+        help: "Sets how many untimed runs bench does first",
+        values: Vec::new,
+    },
+    Flag {
+        name: "--iterations",
+        value: "=COUNT",
+        help: "Sets how many runs bench times",
+        values: Vec::new,
+    },
+    Flag {
+        name: "--quiet",
+        value: "",
+        help: "Only prints errors",
+        values: Vec::new,
+    },
+    Flag {
+        name: "--verbose",
+        value: "",
+        help: "Prints how long each stage takes",
+        values: Vec::new,
+    },
+];
+
+// The flags that print something about krust instead of running a subcommand, with their short forms.
+const INFO_FLAGS: [(&str, &str, &str); 2] = [
+    ("--help", "-h", "Prints this help"),
+    ("--version", "-V", "Prints the version"),
+];
+
+// Gets the values that the flags for warnings take.
+fn warning_values() -> Vec<&'static str> {
+    let mut values: Vec<&str> = vec!["warnings", "lints"];
+    values.extend(WARNING_NAMES);
+    values
+}
+
+/// The shells that completion scripts can be generated for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    /// Every shell.
+    pub const ALL: [Self; 3] = [Self::Bash, Self::Zsh, Self::Fish];
+
+    /// Gets the name of the shell, as used on the command line.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bash => "bash",
+            Self::Zsh => "zsh",
+            Self::Fish => "fish",
+        }
+    }
+
+    /// Gets the shell with the given name.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|shell| shell.name() == name)
+    }
+}
+
+/// What the command line asks krust to do.
+pub enum Invocation {
+    /// Runs a subcommand with the options read from the command line, which are boxed as they are much larger than
+    /// the other variants.
+    Command(Box<CliOptions>),
+    /// Prints the help, for the subcommand if one was given.
+    Help(Option<Command>),
+    /// Prints the version of krust.
+    Version,
+    /// Prints the completion script for the shell.
+    Completions(Shell),
+}
+
+/// Parses the arguments from the command line.
+#[must_use]
+pub fn from_env() -> (Option<Invocation>, Vec<Log>) {
+    match get_args() {
+        Ok(args) => parse_args(args),
+        Err(logs) => (None, logs),
+    }
+}
+
+/// Parses the arguments that follow the name of krust. The help and version flags are looked for before anything
+/// else, so they work even when the rest of the command line isn't valid, but not after `--`, where the arguments
+/// belong to the program. Anything else is read as a subcommand with its files and compiler flags.
+#[must_use]
+pub fn parse_args(args: Vec<String>) -> (Option<Invocation>, Vec<Log>) {
+    if args.first().is_some_and(|arg| arg == COMPLETIONS_COMMAND) {
+        return match args.get(1).and_then(|name| Shell::from_name(name)) {
+            Some(shell) if args.len() == 2 => (Some(Invocation::Completions(shell)), Vec::new()),
+            _ => (
+                None,
+                vec![Log {
+                    log_type: LogType::Error(ErrorType::CLIRequiresShellArg(COMPLETIONS_COMMAND.to_string())),
+                    line_and_col: None,
+                    span: None,
+                    notes: Vec::new(),
+                }],
+            ),
+        };
+    }
+    let flags = || args.iter().take_while(|arg| *arg != "--");
+    if flags().any(|arg| arg == INFO_FLAGS[0].0 || arg == INFO_FLAGS[0].1) {
+        let command: Option<Command> = args.first().and_then(|arg| Command::from_name(arg));
+        return (Some(Invocation::Help(command)), Vec::new());
+    }
+    if flags().any(|arg| arg == INFO_FLAGS[1].0 || arg == INFO_FLAGS[1].1) {
+        return (Some(Invocation::Version), Vec::new());
+    }
+    let (options, logs): (Option<CliOptions>, Vec<Log>) = read_args(args);
+    (options.map(|options| Invocation::Command(Box::new(options))), logs)
+}
+
+/// Gets the version of krust, as printed by `--version`.
+#[must_use]
+pub fn version() -> String {
+    format!("krust {}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Writes the help, which lists the subcommands and the flags. The help for a subcommand describes the files it takes
+/// instead of listing the other subcommands.
+#[must_use]
+pub fn help(command: Option<Command>) -> String {
+    let mut output: String = String::new();
+    // Writing to a string can't fail.
+    if let Some(command) = command {
+        let _ = writeln!(output, "krust {}: {}\n", command.name(), command.summary());
+        let _ = writeln!(output, "Usage: krust {} {} [FLAGS]", command.name(), usage_input(command));
+    } else {
+        let _ = writeln!(output, "{}\n", version());
+        let _ = writeln!(output, "Usage: krust [COMMAND] <FILE>... [FLAGS] [-- ARGS...]\n");
+        let _ = writeln!(output, "Commands:");
+        let commands: Vec<(String, &str)> = Command::ALL
+            .iter()
+            .map(|command| (command.name().to_string(), command.summary()))
+            .chain([(format!("{COMPLETIONS_COMMAND} <SHELL>"), "Prints a completion script for bash, zsh, or fish")])
+            .collect();
+        write_table(&mut output, &commands);
+    }
+    let _ = writeln!(output, "\nFlags:");
+    let flags: Vec<(String, &str)> = FLAGS
+        .iter()
+        .map(|flag| (format!("{}{}", flag.name, flag.value), flag.help))
+        .chain(INFO_FLAGS.iter().map(|(long, short, help)| (format!("{short}, {long}"), *help)))
+        .collect();
+    write_table(&mut output, &flags);
+    output
+}
+
+// Describes the files that the subcommand takes, such as "<FILE.txt>...".
+fn usage_input(command: Command) -> String {
+    let input: String = match command.file_extensions() {
+        [] => "<DIR>".to_string(),
+        extensions => format!(
+            "<{}>",
+            extensions
+                .iter()
+                .map(|extension| format!("FILE.{extension}"))
+                .collect::<Vec<String>>()
+                .join("|")
+        ),
+    };
+    if command.links_files() {
+        input + "..."
+    } else {
+        input
+    }
+}
+
+// Writes rows of two columns, with the second column lined up.
+fn write_table(output: &mut String, rows: &[(String, &str)]) {
+    let width: usize = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, description) in rows {
+        let _ = writeln!(output, "  {name:<width$}  {description}"); // Writing to a string can't fail.
+    }
+}
+
+/// Writes the completion script for the shell, which completes subcommands, flags, and the values of flags, and leaves
+/// files to the shell. It is meant to be saved where the shell loads completions from, or sourced when it starts.
+#[must_use]
+pub fn completions(shell: Shell) -> String {
+    let commands: Vec<&str> = Command::ALL
+        .iter()
+        .map(|command| command.name())
+        .chain([COMPLETIONS_COMMAND])
+        .collect();
+    let flags: Vec<String> = FLAGS
+        .iter()
+        .flat_map(Flag::completions)
+        .chain(INFO_FLAGS.iter().flat_map(|(long, short, _)| [long.to_string(), short.to_string()]))
+        .collect();
+    match shell {
+        Shell::Bash => bash_completions(&commands, &flags),
+        Shell::Zsh => zsh_completions(&commands, &flags),
+        Shell::Fish => fish_completions(),
+    }
+}
+
+// Writes the completion script for bash. The word being completed is taken from the whole line, since bash splits
+// words at "=", and the flag before the "=" is removed from the matches.
+fn bash_completions(commands: &[&str], flags: &[String]) -> String {
+    format!(
+        r#"# bash completion for krust
+_krust() {{
+    local cur="${{COMP_LINE:0:COMP_POINT}}"
+    cur="${{cur##* }}"
+    if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "{}" -- "$cur"))
+        if [[ $cur == *=* ]]; then
+            COMPREPLY=("${{COMPREPLY[@]#*=}}")
+        fi
+        if [[ ${{#COMPREPLY[@]}} -eq 1 && ${{COMPREPLY[0]}} == *= ]]; then
+            compopt -o nospace
+        fi
+    elif [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "{}" -- "$cur"))
+    elif [[ ${{COMP_WORDS[1]}} == {COMPLETIONS_COMMAND} && $COMP_CWORD -eq 2 ]]; then
+        COMPREPLY=($(compgen -W "{}" -- "$cur"))
+    fi
+}}
+complete -o default -F _krust krust
+"#,
+        flags.join(" "),
+        commands.join(" "),
+        Shell::ALL.map(Shell::name).join(" "),
+    )
+}
+
+// Writes the completion script for zsh. Flags that end with "=" are completed without a space after them.
+fn zsh_completions(commands: &[&str], flags: &[String]) -> String {
+    let (open, closed): (Vec<&String>, Vec<&String>) = flags.iter().partition(|flag| flag.ends_with('='));
+    let join = |flags: Vec<&String>| flags.iter().map(|flag| flag.as_str()).collect::<Vec<&str>>().join(" ");
+    format!(
+        r#"#compdef krust
+
+_krust() {{
+    if [[ $PREFIX == -* ]]; then
+        compadd -S '' -- {}
+        compadd -- {}
+    elif (( CURRENT == 3 )) && [[ $words[2] == {COMPLETIONS_COMMAND} ]]; then
+        compadd -- {}
+    else
+        (( CURRENT == 2 )) && compadd -- {}
+        _files
+    fi
+}}
+
+_krust "$@"
+"#,
+        join(open),
+        join(closed),
+        Shell::ALL.map(Shell::name).join(" "),
+        commands.join(" "),
+    )
+}
+
+// Writes the completion script for fish, which describes each subcommand and flag. Flags with one dash and more than
+// one letter are old-style options in fish.
+fn fish_completions() -> String {
+    let mut output: String = String::from("# fish completion for krust\ncomplete -c krust -f\n");
+    // Writing to a string can't fail.
+    for command in Command::ALL {
+        let _ = writeln!(
+            output,
+            "complete -c krust -n __fish_use_subcommand -a {} -d '{}'",
+            command.name(),
+            command.summary()
+        );
+    }
+    let _ = writeln!(
+        output,
+        "complete -c krust -n __fish_use_subcommand -a {COMPLETIONS_COMMAND} -d 'Prints a completion script'"
+    );
+    let _ = writeln!(
+        output,
+        "complete -c krust -n '__fish_seen_subcommand_from {COMPLETIONS_COMMAND}' -a '{}'",
+        Shell::ALL.map(Shell::name).join(" ")
+    );
+    let _ = writeln!(output, "complete -c krust -n 'not __fish_seen_subcommand_from {COMPLETIONS_COMMAND}' -F");
+    for flag in FLAGS {
+        let option: String = match flag.name.strip_prefix("--") {
+            Some(long) => format!("-l {long}"),
+            None if flag.name.len() == 2 => format!("-s {}", &flag.name[1..]),
+            None => format!("-o {}", &flag.name[1..]),
+        };
+        let _ = write!(output, "complete -c krust {option}");
+        if !flag.value.is_empty() && !flag.value.starts_with('[') {
+            output.push_str(" -r");
+        }
+        let values: Vec<&str> = (flag.values)();
+        if !values.is_empty() {
+            let _ = write!(output, " -a '{}'", values.join(" "));
+        }
+        let _ = writeln!(output, " -d '{}'", flag.help);
+    }
+    for (long, short, help) in INFO_FLAGS {
+        let _ = writeln!(output, "complete -c krust -l {} -s {} -d '{help}'", &long[2..], &short[1..]);
+    }
+    output
+}
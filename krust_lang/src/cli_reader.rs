@@ -1,90 +1,621 @@
 //! The module for reading from the command line.
 
-use crate::util::log;
-use log::{ErrorType, Log, LogType, WarningType};
+use crate::{
+    backend::Backend, bench::BenchConfig, cli::FLAGS, compiler, crashcheck::DEFAULT_TIMEOUT, events::EventStream,
+    pass_manager, profiler::ProfileFormat, project, util::log, util::number_format::NumberFormat,
+    util::suggestion::closest,
+};
+use compiler::{CompilerOptions, Endianness, IsaLevel, Target};
+use log::{
+    is_error, ColorChoice, DiagnosticConfig, ErrorFormat, ErrorType, HelpType, Log, LogType, Severity, WarningLevel,
+    WarningType, DEFAULT_ERROR_LIMIT,
+};
+use pass_manager::{CompilerPass, OptLevel, PassManager};
+use project::{find, ProjectConfig};
 use std::cmp::min;
 use std::env::args;
-use std::fs::read_to_string;
+use std::fs::{canonicalize, read};
+use std::iter::once;
 use std::io;
+use std::mem::take;
 use std::num::ParseIntError;
 use std::panic::catch_unwind;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::ParseBoolError;
 use std::thread;
+use std::time::Duration;
+
+use num_traits::FromPrimitive;
+
+/// The subcommands that can be given on the command line.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Command {
+    /// Compiles and runs a source file, or runs a compiled program file. Used when no subcommand is given.
+    Run,
+    /// Compiles a source file to a compiled program file with the same name and the extension "krustc".
+    Build,
+    /// Checks a compiled bytecode file without running it.
+    Verify,
+    /// Prints the instructions in a compiled bytecode file.
+    Disassemble,
+    /// Compiles and runs a script, printing the value of each statement at the top level.
+    Script,
+    /// Tests the krust code blocks in a Markdown file.
+    Doctest,
+    /// Prints the metadata in the header of a compiled bytecode file.
+    Inspect,
+    /// Compiles and runs every file in a directory, reporting the files that crash krust.
+    Crashcheck,
+    /// Checks a source file for errors without generating bytecode or running it.
+    Check,
+    /// Runs a source file again whenever it or the files linked with it change.
+    Watch,
+    /// Formats a source file in place.
+    Fmt,
+    /// Runs the test functions in a source file.
+    Test,
+    /// Runs a source file or a compiled program file many times, printing how long the runs took.
+    Bench,
+}
+
+impl Command {
+    /// Every subcommand, in the order they are listed in the help.
+    pub const ALL: [Self; 13] = [
+        Self::Run,
+        Self::Build,
+        Self::Check,
+        Self::Test,
+        Self::Bench,
+        Self::Watch,
+        Self::Script,
+        Self::Fmt,
+        Self::Verify,
+        Self::Disassemble,
+        Self::Inspect,
+        Self::Doctest,
+        Self::Crashcheck,
+    ];
+
+    /// Gets the name of the subcommand, as used on the command line.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Run => "run",
+            Self::Build => "build",
+            Self::Verify => "verify",
+            Self::Disassemble => "disasm",
+            Self::Script => "script",
+            Self::Doctest => "doctest",
+            Self::Inspect => "inspect",
+            Self::Crashcheck => "crashcheck",
+            Self::Check => "check",
+            Self::Watch => "watch",
+            Self::Fmt => "fmt",
+            Self::Test => "test",
+            Self::Bench => "bench",
+        }
+    }
+
+    /// Gets the subcommand with the given name.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|command| command.name() == name)
+    }
+
+    /// Gets a short description of the subcommand, as shown in the help.
+    #[must_use]
+    pub fn summary(self) -> &'static str {
+        match self {
+            Self::Run => "Compiles and runs a source file, or runs a compiled program file",
+            Self::Build => "Compiles a source file to a compiled program file",
+            Self::Verify => "Checks a compiled program file without running it",
+            Self::Disassemble => "Prints the instructions in a compiled program file",
+            Self::Script => "Runs a script, printing the value of each statement",
+            Self::Doctest => "Tests the krust code blocks in a Markdown file",
+            Self::Inspect => "Prints the metadata of a compiled program file",
+            Self::Crashcheck => "Reports the files in a directory that crash krust",
+            Self::Check => "Checks a source file for errors without running it",
+            Self::Watch => "Runs a source file again whenever it changes",
+            Self::Fmt => "Formats a source file in place",
+            Self::Test => "Runs the test functions in a source file",
+            Self::Bench => "Times many runs of a source file or compiled program file",
+        }
+    }
+
+    // Gets the extensions of the files the command takes as input, which are none if it takes a directory.
+    pub(crate) fn file_extensions(self) -> &'static [&'static str] {
+        match self {
+            Self::Run | Self::Bench => &["txt", "krustc"],
+            Self::Build | Self::Check | Self::Watch | Self::Test => &["txt"],
+            Self::Verify | Self::Disassemble | Self::Inspect => &["krustc"],
+            Self::Script => &["krs"],
+            Self::Fmt => &["txt", "krs"],
+            Self::Doctest => &["md"],
+            Self::Crashcheck => &[],
+        }
+    }
+
+    // Returns whether or not the argument is the path the command takes as input.
+    fn is_input_path(self, arg: &str) -> bool {
+        let path: &Path = Path::new(arg);
+        match self.file_extensions() {
+            [] => !arg.starts_with('-') && path.is_dir(),
+            extensions => path.extension().is_some_and(|ext| {
+                extensions
+                    .iter()
+                    .any(|extension| ext.eq_ignore_ascii_case(extension))
+            }),
+        }
+    }
+
+    // Returns whether or not the command can link many source files into one program.
+    pub(crate) fn links_files(self) -> bool {
+        matches!(self, Self::Run | Self::Build | Self::Check | Self::Watch)
+    }
+}
+
+/// The status that krust exits with, which tells scripts why a command failed without them having to read its output.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExitStatus {
+    /// The command did what it was asked to, exiting with 0.
+    Success,
+    /// The code couldn't be compiled, checked, or loaded, or a check such as `fmt --check` failed, exiting with 1.
+    CompileError,
+    /// The program stopped with an error while it was running, or a test failed, exiting with 2.
+    RuntimeError,
+    /// The command line or the config file of the project wasn't valid, exiting with 3.
+    UsageError,
+}
+
+impl ExitStatus {
+    /// Gets the code that the process exits with.
+    #[must_use]
+    pub fn code(self) -> i32 {
+        match self {
+            Self::Success => 0,
+            Self::CompileError => 1,
+            Self::RuntimeError => 2,
+            Self::UsageError => 3,
+        }
+    }
+
+    /// Gets the status of a command from the logs it gave. A command stops at the first stage that has errors, so the
+    /// errors that are about how krust was used are looked for first, then those from running the program.
+    #[must_use]
+    pub fn from_logs(logs: &[Log]) -> Self {
+        let errors = || logs.iter().filter_map(|log| match &log.log_type {
+            LogType::Error(error) => Some(error),
+            _ => None,
+        });
+        if errors().any(ErrorType::is_usage_error) {
+            Self::UsageError
+        } else if errors().any(ErrorType::is_runtime_error) {
+            Self::RuntimeError
+        } else if errors().next().is_some() {
+            Self::CompileError
+        } else {
+            Self::Success
+        }
+    }
+}
+
+// Returns whether or not the path is a source file, rather than a compiled program file.
+fn is_source_path(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("txt"))
+}
+
+/// The output of the compiler that can be printed instead of running or building the program.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Emit {
+    /// The instructions in the bytecode, with the source location of each runtime error.
+    Disasm,
+    /// The expression tree built by the parser, with the token and type of each node.
+    Ast,
+    /// The tokens found by the lexer, with the type, text, and position of each one.
+    Tokens,
+    /// A standalone C file that can be compiled to native code. Only programs made of int and bool literals,
+    /// variables, and their operators can be transpiled.
+    C,
+    /// A hash of the compiled program file, which is the same on every machine for the same code and options.
+    Hash,
+    /// The bytes taken up by each statement at the top level, each function, and the other parts of the bytecode.
+    Size,
+}
+
+impl Emit {
+    /// Every kind of output.
+    pub const ALL: [Self; 6] = [
+        Self::Disasm,
+        Self::Ast,
+        Self::Tokens,
+        Self::C,
+        Self::Hash,
+        Self::Size,
+    ];
+
+    /// Gets the name of the output, as used on the command line.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Disasm => "disasm",
+            Self::Ast => "ast",
+            Self::Tokens => "tokens",
+            Self::C => "c",
+            Self::Hash => "hash",
+            Self::Size => "size",
+        }
+    }
+
+    /// Gets the output with the given name.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|emit| emit.name() == name)
+    }
+}
+
+/// How operations are encoded in the bytecode.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Encoding {
+    /// Every operand is pushed to the stack before the operation pops it.
+    #[default]
+    Stack,
+    /// Operations on ints read operands that are variables or literals directly, which runs the registers pass.
+    Register,
+}
+
+impl Encoding {
+    /// Every encoding.
+    pub const ALL: [Self; 2] = [Self::Stack, Self::Register];
+
+    /// Gets the name of the encoding, as used on the command line.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Stack => "stack",
+            Self::Register => "register",
+        }
+    }
+
+    /// Gets the encoding with the given name.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|encoding| encoding.name() == name)
+    }
+}
 
 /// The result from reading the command line without errors.
-pub struct CLIInfo {
+#[allow(clippy::struct_excessive_bools)] // Each is a separate flag from the command line.
+pub struct CliOptions {
+    pub command: Command,
     pub file_path: String,
-    pub cli_args: [u8; 2],
+    /// The source files given after the first, which are linked with it into one program in the order given.
+    pub linked_paths: Vec<String>,
+    pub options: CompilerOptions,
+    /// Logs less severe than this are not shown.
+    pub min_severity: Severity,
+    /// Whether or not the invariants between compiler passes are checked.
+    pub internal_checks: bool,
+    /// The stream that progress events are written to as JSON lines, if any.
+    pub log_json: Option<EventStream>,
+    /// Whether or not the disassembler uses labels instead of offsets.
+    pub no_addresses: bool,
+    /// The arguments given to the program, which are everything after `--`.
+    pub program_args: Vec<String>,
+    /// How numbers are written in diagnostic messages.
+    pub number_format: NumberFormat,
+    /// Whether or not the program is run without access to files.
+    pub sandbox: bool,
+    /// The passes of the compiler that run, and the pass whose output is dumped.
+    pub passes: PassManager,
+    /// How long each file checked by crashcheck can take before it is reported as a hang.
+    pub timeout: Duration,
+    /// The output printed instead of running the program or writing a compiled program file, if any.
+    pub emit: Option<Emit>,
+    /// What the program is compiled to.
+    pub backend: Backend,
+    /// The format of the profile printed once the program has run, if it is profiled.
+    pub profile: Option<ProfileFormat>,
+    /// Whether or not the program is run by the interpreter instead of being compiled to bytecode for the VM.
+    pub interpret: bool,
+    /// How logs are printed.
+    pub error_format: ErrorFormat,
+    /// When logs are printed in color.
+    pub color: ColorChoice,
+    /// The most errors printed for a file before the rest are left out, or 0 for no limit.
+    pub error_limit: usize,
+    /// Where the built program is written, if not next to the source file with the extension of the target.
+    pub output: Option<String>,
+    /// Whether or not fmt only checks that the file is formatted instead of formatting it.
+    pub check_formatting: bool,
+    /// The text that the names of the tests run by test must contain, if any.
+    pub filter: Option<String>,
+    /// How many times bench runs the program.
+    pub bench: BenchConfig,
+    /// Whether or not the time each stage takes is printed.
+    pub verbose: bool,
 }
 
-const COMPILER_FLAGS: [&str; 2] = ["-pointer_size", "-detailed_errors"];
+// The names of the compiler flags, in the order of the flags in the cli module. Each flag is handled by its index.
+const COMPILER_FLAGS: [&str; FLAGS.len()] = {
+    let mut names: [&str; FLAGS.len()] = [""; FLAGS.len()];
+    let mut index: usize = 0;
+    while index < FLAGS.len() {
+        names[index] = FLAGS[index].name;
+        index += 1;
+    }
+    names
+};
 
 /// Get file name and compiler flags from the command line.
 #[must_use]
-pub fn read_command_line() -> (Option<CLIInfo>, Vec<Log>) {
-    let input: Result<Vec<String>, Vec<Log>> = get_args();
-    if input.is_err() {
-        return (None, input.expect_err("checked by if statement"));
-    };
-    let input: Vec<String> = input.ok().expect("checked by if statement");
+pub fn read_command_line() -> (Option<CliOptions>, Vec<Log>) {
+    match get_args() {
+        Ok(input) => read_args(input),
+        Err(logs) => (None, logs),
+    }
+}
+
+/// Reads the subcommand, files, and compiler flags from the arguments that follow the name of krust. The config file
+/// of the project that the current directory is in is read as well, and the files given must exist. With no arguments,
+/// the entry file of the project is run, if it has one.
+#[must_use]
+#[allow(clippy::missing_panics_doc)] // Should never actually panic.
+#[allow(clippy::too_many_lines)] // Each compiler flag is handled separately.
+pub fn read_args(mut input: Vec<String>) -> (Option<CliOptions>, Vec<Log>) {
+    let no_args: bool = input.is_empty();
+    let command: Option<Command> = input.first().and_then(|arg| Command::from_name(arg));
+    if command.is_some() {
+        input.remove(0);
+    }
+    let command: Command = command.unwrap_or(Command::Run);
+    let program_args: Vec<String> = split_program_args(&mut input);
+    let mut logs: Vec<Log> = Vec::new();
+    let entry: Option<String> = read_project_config(&mut input, &mut logs);
+    if no_args && entry.is_none() {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLINoArgs),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return (None, logs);
+    }
     let mut file_path: Option<String> = None;
+    let mut linked_paths: Vec<String> = Vec::new();
     let mut ptr_size: u16 = min(usize::BITS, 2047)
         .try_into()
         .expect("should be valid as max value is less than u16::MAX");
     let mut detailed_err: bool = true;
-    let mut logs: Vec<Log> = Vec::new();
+    let mut min_severity: Severity = Severity::Info;
+    let mut isa_level: IsaLevel = IsaLevel::LATEST;
+    let mut internal_checks: bool = false;
+    let mut log_json: Option<EventStream> = None;
+    let mut no_addresses: bool = false;
+    let mut number_format: NumberFormat = NumberFormat::default();
+    let mut sandbox: bool = false;
+    let mut passes: PassManager = PassManager::default();
+    let mut timeout: Duration = DEFAULT_TIMEOUT;
+    let mut opt_level: OptLevel = OptLevel::default();
+    let mut emit: Option<Emit> = None;
+    let mut backend: Backend = Backend::default();
+    let mut encoding: Encoding = Encoding::default();
+    let mut endianness: Endianness = Endianness::default();
+    let mut max_bytecode_size: Option<usize> = None;
+    let mut profile: Option<ProfileFormat> = None;
+    let mut interpret: bool = false;
+    let mut error_format: ErrorFormat = ErrorFormat::default();
+    let mut color: ColorChoice = ColorChoice::default();
+    let mut error_limit: usize = DEFAULT_ERROR_LIMIT;
+    let mut output: Option<String> = None;
+    let mut check_formatting: bool = false;
+    let mut filter: Option<String> = None;
+    let mut bench: BenchConfig = BenchConfig::default();
+    let mut verbose: bool = false;
+    let mut diagnostics: DiagnosticConfig = DiagnosticConfig::default();
     let mut multiple_file_error: bool = false;
-    for arg in input {
-        if Path::new(&arg)
-            .extension()
-            .map_or(false, |ext| ext.eq_ignore_ascii_case("txt"))
-            && !multiple_file_error
-        {
+    let mut input = input.into_iter().peekable();
+    while let Some(arg) = input.next() {
+        if command.is_input_path(&arg) && !multiple_file_error {
             if file_path.is_none() {
-                file_path = Some(arg.to_string());
+                file_path = Some(arg.clone());
+            } else if command.links_files()
+                && file_path.as_deref().is_some_and(is_source_path)
+                && is_source_path(&arg)
+            {
+                linked_paths.push(arg.clone());
             } else {
                 logs.push(Log {
                     log_type: LogType::Error(ErrorType::CLIMultipleFiles),
                     line_and_col: None,
+                    span: None,
+                    notes: Vec::new(),
                 });
                 file_path = None;
+                linked_paths.clear();
                 multiple_file_error = true;
             }
         } else if arg.starts_with(COMPILER_FLAGS[0]) {
             ptr_size = handle_ptr_size(&arg, &mut logs, ptr_size);
         } else if arg.starts_with(COMPILER_FLAGS[1]) {
             detailed_err = handle_detailed_err(&arg, &mut logs);
+        } else if arg.starts_with(COMPILER_FLAGS[2]) {
+            min_severity = handle_min_severity(&arg, &mut logs, min_severity);
+        } else if arg.starts_with(COMPILER_FLAGS[3]) {
+            isa_level = handle_isa_level(&arg, &mut logs, isa_level);
+        } else if arg.starts_with(COMPILER_FLAGS[4]) {
+            internal_checks = handle_internal_checks(&arg, &mut logs);
+        } else if arg.starts_with(COMPILER_FLAGS[5]) {
+            log_json = handle_log_json(&arg, &mut logs);
+        } else if arg == COMPILER_FLAGS[6] {
+            no_addresses = true;
+        } else if arg.starts_with(COMPILER_FLAGS[7]) {
+            number_format = handle_number_format(&arg, &mut logs, number_format);
+        } else if arg == COMPILER_FLAGS[8] {
+            sandbox = true;
+        } else if arg.starts_with(COMPILER_FLAGS[9]) {
+            handle_passes(&arg, &mut logs, &mut passes);
+        } else if arg.starts_with(COMPILER_FLAGS[10]) {
+            handle_disable_pass(&arg, &mut logs, &mut passes);
+        } else if arg.starts_with(COMPILER_FLAGS[11]) {
+            handle_print_after(&arg, &mut logs, &mut passes);
+        } else if arg.starts_with(COMPILER_FLAGS[12]) {
+            timeout = handle_timeout(&arg, &mut logs, timeout);
+        } else if arg.starts_with(COMPILER_FLAGS[13]) {
+            opt_level = handle_opt_level(&arg, &mut logs, opt_level, &mut passes);
+        } else if arg.starts_with(COMPILER_FLAGS[14]) {
+            emit = handle_emit(&arg, &mut logs, emit);
+        } else if arg.starts_with(COMPILER_FLAGS[15]) {
+            backend = handle_target(&arg, &mut logs, backend);
+        } else if arg.starts_with(COMPILER_FLAGS[16]) {
+            encoding = handle_encoding(&arg, &mut logs, encoding);
+        } else if arg.starts_with(COMPILER_FLAGS[17]) {
+            endianness = handle_endianness(&arg, &mut logs, endianness);
+        } else if arg.starts_with(COMPILER_FLAGS[18]) {
+            max_bytecode_size = handle_max_bytecode_size(&arg, &mut logs, max_bytecode_size);
+        } else if arg.starts_with(COMPILER_FLAGS[19]) {
+            profile = handle_profile(&arg, &mut logs, profile);
+        } else if arg == COMPILER_FLAGS[20] {
+            interpret = true;
+        } else if arg.starts_with(COMPILER_FLAGS[21]) {
+            error_format = handle_error_format(&arg, &mut logs, error_format);
+        } else if let Some(flag) = (22..25).find(|flag| arg.starts_with(COMPILER_FLAGS[*flag])) {
+            // The warning can also be given as the next argument, as in "--deny warnings".
+            let value: Option<String> = if arg == COMPILER_FLAGS[flag] { input.next() } else { None };
+            handle_warning_level(&arg, value.as_deref(), flag, &mut logs, &mut diagnostics);
+        } else if arg.starts_with(COMPILER_FLAGS[25]) {
+            color = handle_color(&arg, &mut logs, color);
+        } else if arg.starts_with(COMPILER_FLAGS[26]) {
+            error_limit = handle_error_limit(&arg, &mut logs, error_limit);
+        } else if let Some(flag) = (27..29).find(|flag| arg.starts_with(COMPILER_FLAGS[*flag])) {
+            // The path can also be the next argument, as in "-o main.krustc", but not a flag after it.
+            let value: Option<String> =
+                if arg == COMPILER_FLAGS[flag] { input.next_if(|next| !next.starts_with('-')) } else { None };
+            output = handle_string_arg(&arg, value, flag, &mut logs, output);
+        } else if arg == COMPILER_FLAGS[29] {
+            check_formatting = true;
+        } else if arg.starts_with(COMPILER_FLAGS[30]) {
+            let value: Option<String> =
+                if arg == COMPILER_FLAGS[30] { input.next_if(|next| !next.starts_with('-')) } else { None };
+            filter = handle_string_arg(&arg, value, 30, &mut logs, filter);
+        } else if arg.starts_with(COMPILER_FLAGS[31]) {
+            bench.warmups = handle_count(&arg, 31, &mut logs, bench.warmups);
+        } else if arg.starts_with(COMPILER_FLAGS[32]) {
+            bench.iterations = handle_count(&arg, 32, &mut logs, bench.iterations);
+        } else if arg == COMPILER_FLAGS[33] {
+            // Only errors are shown, so that warnings don't clutter the output of scripts.
+            min_severity = Severity::Error;
+        } else if arg == COMPILER_FLAGS[34] {
+            verbose = true;
         } else {
             handle_unrecognized_flag(&arg, &mut logs);
         }
     }
+    if file_path.is_none() && !multiple_file_error {
+        file_path = entry.filter(|entry| command.is_input_path(entry));
+    }
+    if let (Command::Build, None, Some(output), Some(file_path)) = (command, emit, &output, &file_path) {
+        check_output(output, backend, once(file_path).chain(&linked_paths), &mut logs);
+    }
+    // The emitted output is only printed for a single file.
+    if emit.is_some() && !linked_paths.is_empty() {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIMultipleFiles),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        file_path = None;
+        linked_paths.clear();
+        multiple_file_error = true;
+    }
+    // The encoding is chosen after the other flags, so that an optimization level given later doesn't replace it.
+    if encoding == Encoding::Register {
+        passes.enable(CompilerPass::Registers);
+    }
 
     get_result(
-        &file_path,
+        command,
+        file_path.as_ref(),
+        linked_paths,
         &mut logs,
         ptr_size,
         detailed_err,
+        isa_level,
+        endianness,
+        max_bytecode_size,
+        opt_level,
+        diagnostics,
+        min_severity,
+        internal_checks,
+        log_json,
+        no_addresses,
+        program_args,
+        number_format,
+        sandbox,
+        passes,
+        timeout,
+        emit,
+        backend,
+        profile,
+        interpret,
+        error_format,
+        color,
+        error_limit,
+        output,
+        check_formatting,
+        filter,
+        bench,
+        verbose,
         multiple_file_error,
     )
 }
 
+// Removes the arguments for the program from the input and returns them. These are everything after the first
+// `--`, so that they are never read as files or compiler flags.
+fn split_program_args(input: &mut Vec<String>) -> Vec<String> {
+    let Some(separator) = input.iter().position(|arg| arg == "--") else {
+        return Vec::new();
+    };
+    let program_args: Vec<String> = input.split_off(separator + 1);
+    input.pop();
+    program_args
+}
+
+// Reads the config file of the project that the current directory is in, if there is one, adding its settings to the
+// start of the input as flags so that the flags given on the command line win over them. Returns the path of the
+// entry file of the project, if it has one.
+fn read_project_config(input: &mut Vec<String>, logs: &mut Vec<Log>) -> Option<String> {
+    let path: PathBuf = find()?;
+    match ProjectConfig::load(&path) {
+        Ok(config) => {
+            input.splice(0..0, config.flags());
+            config
+                .entry
+                .map(|entry| path.with_file_name(entry).display().to_string())
+        }
+        Err(mut errors) => {
+            logs.append(&mut errors);
+            None
+        }
+    }
+}
+
 // Get the arguments from the command line.
-fn get_args() -> Result<Vec<String>, Vec<Log>> {
+pub(crate) fn get_args() -> Result<Vec<String>, Vec<Log>> {
     let input: thread::Result<Vec<String>> = catch_unwind(|| args().collect());
     if input.is_err() {
         return Err(vec![Log {
             log_type: LogType::Error(ErrorType::CLICantReadArgs),
             line_and_col: None,
+            span: None,
+            notes: Vec::new(),
         }]);
     }
     let mut input: Vec<String> = input.expect("should be valid as error handled earlier");
-    if input.len() == 1 {
-        return Err(vec![Log {
-            log_type: LogType::Error(ErrorType::CLINoArgs),
-            line_and_col: None,
-        }]);
-    }
     input.remove(0);
     Ok(input)
 }
@@ -100,6 +631,8 @@ fn handle_ptr_size(arg: &str, logs: &mut Vec<Log>, mut ptr_size: u16) -> u16 {
                     COMPILER_FLAGS[0].to_string(),
                 )),
                 line_and_col: None,
+                span: None,
+                notes: Vec::new(),
             });
         } else {
             ptr_size = parsed_arg.expect("should be valid as error handled earlier.");
@@ -111,6 +644,8 @@ fn handle_ptr_size(arg: &str, logs: &mut Vec<Log>, mut ptr_size: u16) -> u16 {
                     2048,
                 )),
                 line_and_col: None,
+                span: None,
+                notes: Vec::new(),
             });
         }
         if ptr_size < 8 {
@@ -120,12 +655,16 @@ fn handle_ptr_size(arg: &str, logs: &mut Vec<Log>, mut ptr_size: u16) -> u16 {
                     8,
                 )),
                 line_and_col: None,
+                span: None,
+                notes: Vec::new(),
             });
         }
     } else {
         logs.push(Log {
             log_type: LogType::Error(ErrorType::CLIRequiresArg(COMPILER_FLAGS[0].to_string())),
             line_and_col: None,
+            span: None,
+            notes: Vec::new(),
         });
     }
     ptr_size
@@ -142,6 +681,8 @@ fn handle_detailed_err(arg: &str, logs: &mut Vec<Log>) -> bool {
                     COMPILER_FLAGS[1].to_string(),
                 )),
                 line_and_col: None,
+                span: None,
+                notes: Vec::new(),
             });
         } else {
             return parsed_arg.expect("should be valid as error handled earlier.");
@@ -150,11 +691,511 @@ fn handle_detailed_err(arg: &str, logs: &mut Vec<Log>) -> bool {
         logs.push(Log {
             log_type: LogType::Error(ErrorType::CLIRequiresArg(COMPILER_FLAGS[1].to_string())),
             line_and_col: None,
+            span: None,
+            notes: Vec::new(),
         });
     }
     true
 }
 
+// Handle the minimum severity compiler flag.
+fn handle_min_severity(arg: &str, logs: &mut Vec<Log>, min_severity: Severity) -> Severity {
+    let arg: &str = &arg[COMPILER_FLAGS[2].len()..];
+    if let Some(value) = arg.strip_prefix('=') {
+        if let Some(severity) = Severity::from_name(value) {
+            return severity;
+        }
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresSeverityArg(
+                COMPILER_FLAGS[2].to_string(),
+            )),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresArg(COMPILER_FLAGS[2].to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+    min_severity
+}
+
+// Handle the instruction set level compiler flag.
+fn handle_isa_level(arg: &str, logs: &mut Vec<Log>, isa_level: IsaLevel) -> IsaLevel {
+    let arg: &str = &arg[COMPILER_FLAGS[3].len()..];
+    if let Some(value) = arg.strip_prefix('=') {
+        let level: Option<IsaLevel> = value.parse::<u8>().ok().and_then(FromPrimitive::from_u8);
+        if let Some(level) = level {
+            return level;
+        }
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresIsaLevelArg(
+                COMPILER_FLAGS[3].to_string(),
+            )),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresArg(COMPILER_FLAGS[3].to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+    isa_level
+}
+
+// Handle the optimization level compiler flag, which is given as "-O0", "-O1", or "-O2". The passes of the level replace
+// any passes chosen by earlier flags, and later flags can change them.
+fn handle_opt_level(
+    arg: &str,
+    logs: &mut Vec<Log>,
+    opt_level: OptLevel,
+    passes: &mut PassManager,
+) -> OptLevel {
+    let arg: &str = &arg[COMPILER_FLAGS[13].len()..];
+    if let Some(level) = arg.parse::<u8>().ok().and_then(OptLevel::from_u8) {
+        passes.set_opt_level(level);
+        return level;
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::CLIRequiresOptLevelArg(
+            COMPILER_FLAGS[13].to_string(),
+        )),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+    opt_level
+}
+
+// Handle the internal checks compiler flag.
+fn handle_internal_checks(arg: &str, logs: &mut Vec<Log>) -> bool {
+    let arg: &str = &arg[COMPILER_FLAGS[4].len()..];
+    if let Some(value) = arg.strip_prefix('=') {
+        if let Ok(value) = value.parse::<bool>() {
+            return value;
+        }
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresBoolArg(COMPILER_FLAGS[4].to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresArg(COMPILER_FLAGS[4].to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+    false
+}
+
+// Handle the JSON event log flag. The events are written to stderr unless another stream is given.
+fn handle_log_json(arg: &str, logs: &mut Vec<Log>) -> Option<EventStream> {
+    let arg: &str = &arg[COMPILER_FLAGS[5].len()..];
+    if arg.is_empty() {
+        return Some(EventStream::Stderr);
+    }
+    if let Some(stream) = arg.strip_prefix('=').and_then(EventStream::from_name) {
+        return Some(stream);
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::CLIRequiresStreamArg(
+            COMPILER_FLAGS[5].to_string(),
+        )),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+    None
+}
+
+// Handle the number format compiler flag.
+fn handle_number_format(
+    arg: &str,
+    logs: &mut Vec<Log>,
+    number_format: NumberFormat,
+) -> NumberFormat {
+    let arg: &str = &arg[COMPILER_FLAGS[7].len()..];
+    if let Some(number_format) = arg.strip_prefix('=').and_then(NumberFormat::from_name) {
+        return number_format;
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::CLIRequiresNumberFormatArg(
+            COMPILER_FLAGS[7].to_string(),
+        )),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+    number_format
+}
+
+// Handle the flag for the passes to run, which are given as a comma-separated list. Required passes always run.
+fn handle_passes(arg: &str, logs: &mut Vec<Log>, passes: &mut PassManager) {
+    let arg: &str = &arg[COMPILER_FLAGS[9].len()..];
+    let chosen: Option<Vec<CompilerPass>> = arg.strip_prefix('=').and_then(|names| {
+        names
+            .split(',')
+            .filter(|name| !name.is_empty())
+            .map(CompilerPass::from_name)
+            .collect()
+    });
+    if let Some(chosen) = chosen {
+        passes.set_passes(&chosen);
+    } else {
+        push_pass_arg_error(logs, 9);
+    }
+}
+
+// Handle the flag for a pass that shouldn't run.
+fn handle_disable_pass(arg: &str, logs: &mut Vec<Log>, passes: &mut PassManager) {
+    let arg: &str = &arg[COMPILER_FLAGS[10].len()..];
+    let Some(pass) = arg.strip_prefix('=').and_then(CompilerPass::from_name) else {
+        push_pass_arg_error(logs, 10);
+        return;
+    };
+    if !passes.disable(pass) {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiredPass(pass.name().to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Handle the flag for the pass whose output is dumped.
+fn handle_print_after(arg: &str, logs: &mut Vec<Log>, passes: &mut PassManager) {
+    let arg: &str = &arg[COMPILER_FLAGS[11].len()..];
+    if let Some(pass) = arg.strip_prefix('=').and_then(CompilerPass::from_name) {
+        passes.set_print_after(pass);
+    } else {
+        push_pass_arg_error(logs, 11);
+    }
+}
+
+// Handle the flag for the time limit of each file checked by crashcheck, which is given in milliseconds.
+fn handle_timeout(arg: &str, logs: &mut Vec<Log>, timeout: Duration) -> Duration {
+    let arg: &str = &arg[COMPILER_FLAGS[12].len()..];
+    if let Some(value) = arg.strip_prefix('=') {
+        if let Ok(millis) = value.parse::<u64>() {
+            return Duration::from_millis(millis);
+        }
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresNumArg(COMPILER_FLAGS[12].to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresArg(COMPILER_FLAGS[12].to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+    timeout
+}
+
+// Handle the flag for the output printed instead of running or building the program.
+fn handle_emit(arg: &str, logs: &mut Vec<Log>, emit: Option<Emit>) -> Option<Emit> {
+    let arg: &str = &arg[COMPILER_FLAGS[14].len()..];
+    if let Some(emit) = arg.strip_prefix('=').and_then(Emit::from_name) {
+        return Some(emit);
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::CLIRequiresEmitArg(
+            COMPILER_FLAGS[14].to_string(),
+        )),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+    emit
+}
+
+// Handle the flag for what the program is compiled to.
+fn handle_target(arg: &str, logs: &mut Vec<Log>, backend: Backend) -> Backend {
+    let arg: &str = &arg[COMPILER_FLAGS[15].len()..];
+    if let Some(backend) = arg.strip_prefix('=').and_then(Backend::from_name) {
+        return backend;
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::CLIRequiresTargetArg(
+            COMPILER_FLAGS[15].to_string(),
+        )),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+    backend
+}
+
+// Handle the flag for how operations are encoded in the bytecode.
+fn handle_encoding(arg: &str, logs: &mut Vec<Log>, encoding: Encoding) -> Encoding {
+    let arg: &str = &arg[COMPILER_FLAGS[16].len()..];
+    if let Some(encoding) = arg.strip_prefix('=').and_then(Encoding::from_name) {
+        return encoding;
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::CLIRequiresEncodingArg(
+            COMPILER_FLAGS[16].to_string(),
+        )),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+    encoding
+}
+
+// Handle the flag for the byte order of the target.
+fn handle_endianness(arg: &str, logs: &mut Vec<Log>, endianness: Endianness) -> Endianness {
+    let arg: &str = &arg[COMPILER_FLAGS[17].len()..];
+    if let Some(endianness) = arg.strip_prefix('=').and_then(Endianness::from_name) {
+        return endianness;
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::CLIRequiresEndiannessArg(
+            COMPILER_FLAGS[17].to_string(),
+        )),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+    endianness
+}
+
+// Handle the flag for the most bytes the bytecode can take up, past which it fails to compile.
+fn handle_max_bytecode_size(
+    arg: &str,
+    logs: &mut Vec<Log>,
+    max_bytecode_size: Option<usize>,
+) -> Option<usize> {
+    let arg: &str = &arg[COMPILER_FLAGS[18].len()..];
+    if let Some(value) = arg.strip_prefix('=') {
+        if let Ok(bytes) = value.parse::<usize>() {
+            return Some(bytes);
+        }
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresNumArg(COMPILER_FLAGS[18].to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresArg(COMPILER_FLAGS[18].to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+    max_bytecode_size
+}
+
+// Handle the flag for profiling the program, which prints a table unless another format is given.
+fn handle_profile(
+    arg: &str,
+    logs: &mut Vec<Log>,
+    profile: Option<ProfileFormat>,
+) -> Option<ProfileFormat> {
+    let arg: &str = &arg[COMPILER_FLAGS[19].len()..];
+    if arg.is_empty() {
+        return Some(ProfileFormat::Table);
+    }
+    if let Some(format) = arg.strip_prefix('=').and_then(ProfileFormat::from_name) {
+        return Some(format);
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::CLIRequiresProfileArg(
+            COMPILER_FLAGS[19].to_string(),
+        )),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+    profile
+}
+
+// Handle the flag for how logs are printed.
+fn handle_error_format(arg: &str, logs: &mut Vec<Log>, error_format: ErrorFormat) -> ErrorFormat {
+    let arg: &str = &arg[COMPILER_FLAGS[21].len()..];
+    if let Some(error_format) = arg.strip_prefix('=').and_then(ErrorFormat::from_name) {
+        return error_format;
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::CLIRequiresErrorFormatArg(
+            COMPILER_FLAGS[21].to_string(),
+        )),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+    error_format
+}
+
+// Handle the color flag, which chooses when logs are printed in color.
+fn handle_color(arg: &str, logs: &mut Vec<Log>, color: ColorChoice) -> ColorChoice {
+    let arg: &str = &arg[COMPILER_FLAGS[25].len()..];
+    if let Some(color) = arg.strip_prefix('=').and_then(ColorChoice::from_name) {
+        return color;
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::CLIRequiresColorArg(COMPILER_FLAGS[25].to_string())),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+    color
+}
+
+// Handle the flag for the most errors printed for a file, where 0 means every error is printed.
+fn handle_error_limit(arg: &str, logs: &mut Vec<Log>, error_limit: usize) -> usize {
+    let arg: &str = &arg[COMPILER_FLAGS[26].len()..];
+    if let Some(value) = arg.strip_prefix('=') {
+        if let Ok(limit) = value.parse::<usize>() {
+            return limit;
+        }
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresNumArg(COMPILER_FLAGS[26].to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresArg(COMPILER_FLAGS[26].to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+    error_limit
+}
+
+// Handle the flags that take a number of times, such as how many times bench runs the program before timing it.
+fn handle_count(arg: &str, flag: usize, logs: &mut Vec<Log>, count: usize) -> usize {
+    let arg: &str = &arg[COMPILER_FLAGS[flag].len()..];
+    if let Some(value) = arg.strip_prefix('=') {
+        if let Ok(count) = value.parse::<usize>() {
+            return count;
+        }
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresNumArg(COMPILER_FLAGS[flag].to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresArg(COMPILER_FLAGS[flag].to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+    count
+}
+
+// Handle the flags that take a string, such as where the built program is written, which are given as "--output=path"
+// or "-o path".
+fn handle_string_arg(
+    arg: &str,
+    value: Option<String>,
+    flag: usize,
+    logs: &mut Vec<Log>,
+    old_value: Option<String>,
+) -> Option<String> {
+    let arg: &str = &arg[COMPILER_FLAGS[flag].len()..];
+    let value: Option<String> = value.or_else(|| arg.strip_prefix('=').map(str::to_string));
+    match value {
+        Some(value) if !value.is_empty() && !value.starts_with('-') => Some(value),
+        _ => {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::CLIRequiresArg(COMPILER_FLAGS[flag].to_string())),
+                line_and_col: None,
+                span: None,
+                notes: Vec::new(),
+            });
+            old_value
+        }
+    }
+}
+
+// Checks that the path a program is built to won't overwrite one of the input files, and that it has the extension of
+// the target's programs, as krust only recognizes a compiled program by its extension.
+fn check_output<'a>(output: &str, backend: Backend, mut inputs: impl Iterator<Item = &'a String>, logs: &mut Vec<Log>) {
+    // The paths are compared once resolved, so that "./main.txt" is found to be the same file as "main.txt".
+    let overwrites_input: bool = canonicalize(output).is_ok_and(|output| {
+        inputs.any(|input| canonicalize(input).is_ok_and(|input| input == output))
+    });
+    let extension: &str = backend.file_extension();
+    let log_type: ErrorType = if overwrites_input {
+        ErrorType::CLIOutputIsInput(output.to_string())
+    } else if Path::new(output).extension().is_some_and(|ext| ext.eq_ignore_ascii_case(extension)) {
+        return;
+    } else {
+        ErrorType::CLIOutputExtension(output.to_string(), extension.to_string())
+    };
+    logs.push(Log {
+        log_type: LogType::Error(log_type),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+}
+
+// Handle the flags that allow, warn about, or deny a warning, which are given as "-W=name" or "-W name". The name
+// can also be the code of the warning, "lints" for every lint, or "warnings" for every warning.
+fn handle_warning_level(
+    arg: &str,
+    value: Option<&str>,
+    flag: usize,
+    logs: &mut Vec<Log>,
+    diagnostics: &mut DiagnosticConfig,
+) {
+    let level: WarningLevel = match flag {
+        22 => WarningLevel::Warn,
+        23 => WarningLevel::Allow,
+        _ => WarningLevel::Deny,
+    };
+    let arg: &str = &arg[COMPILER_FLAGS[flag].len()..];
+    let name: Option<&str> = value.or_else(|| arg.strip_prefix('='));
+    if !name.is_some_and(|name| diagnostics.set(name, level)) {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CLIRequiresWarningArg(
+                COMPILER_FLAGS[flag].to_string(),
+            )),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Reports that the compiler flag at the index wasn't given valid pass names.
+fn push_pass_arg_error(logs: &mut Vec<Log>, flag: usize) {
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::CLIRequiresPassArg(
+            COMPILER_FLAGS[flag].to_string(),
+        )),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+}
+
 // Handle unrecognized flags in the command line.
 fn handle_unrecognized_flag(arg: &String, logs: &mut Vec<Log>) {
     let index: Option<usize> = arg.find('=');
@@ -165,72 +1206,189 @@ fn handle_unrecognized_flag(arg: &String, logs: &mut Vec<Log>) {
     logs.push(Log {
         log_type: LogType::Error(ErrorType::CLIUnrecognizedArg(arg_substr.to_string())),
         line_and_col: None,
+        span: None,
+        notes: Vec::new(),
     });
+    if let Some(flag) = closest(arg_substr, COMPILER_FLAGS) {
+        logs.push(Log {
+            log_type: LogType::Help(HelpType::DidYouMean(flag.to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
 }
 
 // Gets the CLI info.
+#[allow(clippy::too_many_arguments, clippy::fn_params_excessive_bools)] // Each compiler flag is passed separately.
 fn get_result(
-    file_path: &Option<String>,
+    command: Command,
+    file_path: Option<&String>,
+    linked_paths: Vec<String>,
     logs: &mut Vec<Log>,
     ptr_size: u16,
     detailed_err: bool,
+    isa_level: IsaLevel,
+    endianness: Endianness,
+    max_bytecode_size: Option<usize>,
+    opt_level: OptLevel,
+    diagnostics: DiagnosticConfig,
+    min_severity: Severity,
+    internal_checks: bool,
+    log_json: Option<EventStream>,
+    no_addresses: bool,
+    program_args: Vec<String>,
+    number_format: NumberFormat,
+    sandbox: bool,
+    passes: PassManager,
+    timeout: Duration,
+    emit: Option<Emit>,
+    backend: Backend,
+    profile: Option<ProfileFormat>,
+    interpret: bool,
+    error_format: ErrorFormat,
+    color: ColorChoice,
+    error_limit: usize,
+    output: Option<String>,
+    check_formatting: bool,
+    filter: Option<String>,
+    bench: BenchConfig,
+    verbose: bool,
     multiple_file_error: bool,
-) -> (Option<CLIInfo>, Vec<Log>) {
-    let file_size: usize = get_file_size(file_path, logs, multiple_file_error);
+) -> (Option<CliOptions>, Vec<Log>) {
+    let mut file_size: usize = get_file_size(file_path, logs, multiple_file_error);
+    for path in &linked_paths {
+        file_size += get_file_size(Some(path), logs, multiple_file_error);
+    }
 
     if logs.is_empty() {
-        handle_compiler_flag_issues(file_path, logs, ptr_size, detailed_err, file_size)
+        handle_compiler_flag_issues(
+            command,
+            file_path,
+            linked_paths,
+            logs,
+            ptr_size,
+            detailed_err,
+            isa_level,
+            endianness,
+            max_bytecode_size,
+            opt_level,
+            diagnostics,
+            min_severity,
+            internal_checks,
+            log_json,
+            no_addresses,
+            program_args,
+            number_format,
+            sandbox,
+            passes,
+            timeout,
+            emit,
+            backend,
+            profile,
+            interpret,
+            error_format,
+            color,
+            error_limit,
+            output,
+            check_formatting,
+            filter,
+            bench,
+            verbose,
+            file_size,
+        )
     } else {
         (None, logs.clone())
     }
 }
 
-// Get the size of the file, if exactly one is given.
+// Get the size of the file, if one is given.
 fn get_file_size(
-    file_path: &Option<String>,
+    file_path: Option<&String>,
     logs: &mut Vec<Log>,
     multiple_file_error: bool,
 ) -> usize {
     let mut file_size: usize = 0;
-    if let Some(ref path) = file_path {
-        let result: io::Result<String> = read_to_string(path);
-        if let Ok(file_text) = result {
-            file_size = file_text.len();
+    if let Some(path) = file_path {
+        if Path::new(path).is_dir() {
+            return file_size;
+        }
+        let result: io::Result<Vec<u8>> = read(path);
+        if let Ok(file_bytes) = result {
+            file_size = file_bytes.len();
         } else {
             logs.push(Log {
                 log_type: LogType::Error(ErrorType::CLICantOpenFile(path.clone())),
                 line_and_col: None,
+                span: None,
+                notes: Vec::new(),
             });
         }
     } else if !multiple_file_error {
         logs.push(Log {
             log_type: LogType::Error(ErrorType::CLINoFile),
             line_and_col: None,
+            span: None,
+            notes: Vec::new(),
         });
     }
     file_size
 }
 
 // Deal with issues relating to compiler flag values.
+#[allow(
+    clippy::too_many_arguments,
+    clippy::fn_params_excessive_bools,
+    clippy::too_many_lines
+)] // Each compiler flag is passed separately.
 fn handle_compiler_flag_issues(
-    file_path: &Option<String>,
+    command: Command,
+    file_path: Option<&String>,
+    linked_paths: Vec<String>,
     logs: &mut Vec<Log>,
     ptr_size: u16,
     detailed_err: bool,
+    isa_level: IsaLevel,
+    endianness: Endianness,
+    max_bytecode_size: Option<usize>,
+    opt_level: OptLevel,
+    diagnostics: DiagnosticConfig,
+    min_severity: Severity,
+    internal_checks: bool,
+    log_json: Option<EventStream>,
+    no_addresses: bool,
+    program_args: Vec<String>,
+    number_format: NumberFormat,
+    sandbox: bool,
+    passes: PassManager,
+    timeout: Duration,
+    emit: Option<Emit>,
+    backend: Backend,
+    profile: Option<ProfileFormat>,
+    interpret: bool,
+    error_format: ErrorFormat,
+    color: ColorChoice,
+    error_limit: usize,
+    output: Option<String>,
+    check_formatting: bool,
+    filter: Option<String>,
+    bench: BenchConfig,
+    verbose: bool,
     file_size: usize,
-) -> (Option<CLIInfo>, Vec<Log>) {
+) -> (Option<CliOptions>, Vec<Log>) {
     if let Some(file_path) = file_path {
-        let detailed_err: u8 = u8::from(detailed_err);
         let ptr_size_bytes: u8 = (ptr_size / 8)
             .try_into()
             .expect("ptr_size maximum is less than 2048");
-        if ptr_size % 8 != 0 {
+        if !ptr_size.is_multiple_of(8) {
             logs.push(Log {
                 log_type: LogType::Warning(WarningType::CLIArgRoundedDownU16(
                     COMPILER_FLAGS[0].to_string(),
                     8,
                 )),
                 line_and_col: None,
+                span: None,
+                notes: Vec::new(),
             });
         }
         let ptr_size: usize = <u8 as Into<usize>>::into(ptr_size_bytes) * 8;
@@ -242,6 +1400,8 @@ fn handle_compiler_flag_issues(
             logs.push(Log {
                 log_type: LogType::Warning(WarningType::CLITargetLargerThanMachine(ptr_size)),
                 line_and_col: None,
+                span: None,
+                notes: Vec::new(),
             });
         } else if ptr_size
             < usize::BITS
@@ -252,19 +1412,92 @@ fn handle_compiler_flag_issues(
             logs.push(Log {
                 log_type: LogType::Error(ErrorType::CLIFileToBig(ptr_size)),
                 line_and_col: None,
+                span: None,
+                notes: Vec::new(),
             });
             return (
-                Some(CLIInfo {
-                    file_path: file_path.to_string(),
-                    cli_args: [ptr_size_bytes, detailed_err],
+                Some(CliOptions {
+                    command,
+                    file_path: file_path.clone(),
+                    linked_paths,
+                    options: CompilerOptions {
+                        target: Target {
+                            ptr_size: ptr_size_bytes,
+                            endianness,
+                            features: isa_level,
+                        },
+                        detailed_err,
+                        opt_level,
+                        max_bytecode_size,
+                        diagnostics,
+                    },
+                    min_severity,
+                    internal_checks,
+                    log_json,
+                    no_addresses,
+                    program_args,
+                    number_format,
+                    sandbox,
+                    passes,
+                    timeout,
+                    emit,
+                    backend,
+                    profile,
+                    interpret,
+                    error_format,
+                    color,
+                    error_limit,
+                    output,
+                    check_formatting,
+                    filter,
+                    bench,
+                    verbose,
                 }),
-                logs.clone(),
+                diagnostics.apply(logs.clone()),
             );
         }
+        // A denied warning stops the command line from being read, like any other error.
+        *logs = diagnostics.apply(take(logs));
+        if is_error(logs) {
+            return (None, logs.clone());
+        }
         (
-            Some(CLIInfo {
-                file_path: file_path.to_string(),
-                cli_args: [ptr_size_bytes, detailed_err],
+            Some(CliOptions {
+                command,
+                file_path: file_path.clone(),
+                linked_paths,
+                options: CompilerOptions {
+                    target: Target {
+                        ptr_size: ptr_size_bytes,
+                        endianness,
+                        features: isa_level,
+                    },
+                    detailed_err,
+                    opt_level,
+                    max_bytecode_size,
+                    diagnostics,
+                },
+                min_severity,
+                internal_checks,
+                log_json,
+                no_addresses,
+                program_args,
+                number_format,
+                sandbox,
+                passes,
+                timeout,
+                emit,
+                backend,
+                profile,
+                interpret,
+                error_format,
+                color,
+                error_limit,
+                output,
+                check_formatting,
+                filter,
+                bench,
+                verbose,
             }),
             logs.clone(),
         )
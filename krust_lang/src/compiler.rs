@@ -1,33 +1,345 @@
 //! The module for compiling source code into byte code.
+//!
+//! Compiling the same code with the same options always gives the same bytes, whatever machine the compiler runs on.
+//! Numbers are written with the pointer size in the options rather than that of the host, and maps are only used to
+//! look things up, never iterated over to decide the order of the output.
 
-use crate::{lexer, parser, util::log};
+use crate::{disassembler, lexer, lint, metadata, optimizer, pass_manager, parser, util::log, verifier, visit};
+use disassembler::{disassemble, AddressMode};
 use lexer::{Token, TokenType};
-use log::{is_error, ErrorType, Log, LogType};
-use parser::{Expression, ParserOutput, Type};
+use lint::lint;
+use log::{is_error, DiagnosticConfig, ErrorType, Log, LogType, WarningType};
+use metadata::Metadata;
+use optimizer::fold_constants;
+use parser::{Builtin, Expression, ParserOutput, Type};
+use pass_manager::{CompilerPass, OptLevel, PassManager};
+use verifier::{verify, VerifierOutput};
+use visit::children;
 
 use num_derive::FromPrimitive;
+use std::collections::HashMap;
+use std::iter::once;
 
 /// The number of bytes used to keep track of variables.
 pub const BYTES_PER_VAR: usize = 2;
 
+/// The number of bytes in the fixed part of the bytecode header, which holds the pointer size, the flags of the target,
+/// the instruction set level needed to run the bytecode, and the length of the metadata that follows as a 2 byte
+/// little-endian number.
+pub const HEADER_SIZE: usize = 5;
+
+/// The flag in the bytecode header that is set if runtime errors give the line and column they happened at.
+pub const DETAILED_ERRORS_FLAG: u8 = 1;
+
+/// The flag in the bytecode header that is set if the target is big-endian. Bytecode is always little-endian in
+/// memory, and only the numbers in the instructions of a compiled program file are written in the order of the target.
+pub const BIG_ENDIAN_FLAG: u8 = 2;
+
+/// The bytes that start every compiled program file.
+pub const MAGIC: [u8; 4] = *b"KRBC";
+
+/// The version of the file format written by `serialize_bytecode`.
+pub const FORMAT_VERSION: u16 = 2;
+
+/// The number of bytes before the first section of a compiled program file, which hold the magic bytes, the format
+/// version as a 2 byte little-endian number, the pointer size, and the number of sections.
+pub const FILE_HEADER_SIZE: usize = 8;
+
+/// The sections of a compiled program file. Each section starts with its kind as a byte and its length as a 4 byte
+/// little-endian number.
+#[derive(FromPrimitive, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Section {
+    /// The constants that `LoadConst` instructions push, which are stored as `Constant` instructions.
+    ConstantPool = 1,
+    /// The bytecode header, its metadata, and the instructions.
+    Code = 2,
+    /// The names of the functions and the line table, which are only used to report runtime errors.
+    DebugInfo = 3,
+}
+
+impl Section {
+    /// Every section, in the order they are written.
+    pub const ALL: [Self; 3] = [Self::ConstantPool, Self::Code, Self::DebugInfo];
+
+    /// Gets the name of the section used in error messages.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ConstantPool => "constant pool",
+            Self::Code => "code",
+            Self::DebugInfo => "debug info",
+        }
+    }
+}
+
+/// Gets the offset of the first instruction, which follows the header and its metadata. The fixed part of the header
+/// must be complete.
+#[must_use]
+pub fn code_start(bytecode: &[u8]) -> usize {
+    HEADER_SIZE + usize::from(u16::from_le_bytes([bytecode[3], bytecode[4]]))
+}
+
+/// The versions of the instruction set. Each level supports every instruction of the levels below it.
+#[derive(FromPrimitive, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum IsaLevel {
+    V1 = 1,
+    V2 = 2,
+    V3 = 3,
+    V4 = 4,
+    V5 = 5,
+    V6 = 6,
+    V7 = 7,
+    V8 = 8,
+    V9 = 9,
+    V10 = 10,
+    V11 = 11,
+    V12 = 12,
+    V13 = 13,
+}
+
+impl IsaLevel {
+    /// The newest instruction set level.
+    pub const LATEST: Self = Self::V13;
+
+    /// Gets a description of the features that this level adds.
+    #[must_use]
+    pub fn features(self) -> &'static str {
+        match self {
+            Self::V1 => "stack, variable, arithmetic, string, map, and list instructions",
+            Self::V2 => "function calls",
+            Self::V3 => "results",
+            Self::V4 => "panics",
+            Self::V5 => "string conversions",
+            Self::V6 => "program arguments and lists of strings",
+            Self::V7 => "host functions",
+            Self::V8 => "file access",
+            Self::V9 => "constant pools",
+            Self::V10 => "line tables",
+            Self::V11 => "duplicating values",
+            Self::V12 => "register instructions",
+            Self::V13 => "assertions",
+        }
+    }
+}
+
+/// The order of the bytes in the numbers of a compiled program file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Endianness {
+    /// The least significant byte comes first.
+    #[default]
+    Little,
+    /// The most significant byte comes first.
+    Big,
+}
+
+impl Endianness {
+    /// Every byte order.
+    pub const ALL: [Self; 2] = [Self::Little, Self::Big];
+
+    /// Gets the name of the byte order, as used on the command line.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Little => "little",
+            Self::Big => "big",
+        }
+    }
+
+    /// Gets the byte order with the given name.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|endianness| endianness.name() == name)
+    }
+}
+
+/// The machine that a program is compiled for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Target {
+    /// The size of a pointer in bytes.
+    pub ptr_size: u8,
+    /// The byte order that the numbers in the instructions of a compiled program file are written in.
+    pub endianness: Endianness,
+    /// The highest instruction set level that the target supports, which decides the instructions the bytecode can
+    /// use.
+    pub features: IsaLevel,
+}
+
+impl Target {
+    /// Creates a little-endian target.
+    #[must_use]
+    pub fn new(ptr_size: u8, features: IsaLevel) -> Self {
+        Self {
+            ptr_size,
+            endianness: Endianness::Little,
+            features,
+        }
+    }
+}
+
+impl Default for Target {
+    /// The machine the compiler runs on, with the latest instruction set level. Compiled program files are
+    /// little-endian by default, whatever the byte order of the machine, so that they run anywhere.
+    fn default() -> Self {
+        Self::new(
+            u8::try_from(usize::BITS / 8).expect("pointers are less than 256 bytes"),
+            IsaLevel::LATEST,
+        )
+    }
+}
+
+/// The options that decide the bytecode the compiler generates.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CompilerOptions {
+    pub target: Target,
+    /// Whether or not runtime errors give the line and column they happened at.
+    pub detailed_err: bool,
+    /// Decides which optimization passes run when they aren't chosen with a pass manager.
+    pub opt_level: OptLevel,
+    /// The most bytes the bytecode can take up, or None if it can fill the address space of the pointer size.
+    pub max_bytecode_size: Option<usize>,
+    /// Decides which warnings are reported, and which are reported as errors.
+    pub diagnostics: DiagnosticConfig,
+}
+
+impl CompilerOptions {
+    /// Creates the options for a little-endian target, optimizing at the default level.
+    #[must_use]
+    pub fn new(ptr_size: u8, detailed_err: bool, isa_level: IsaLevel) -> Self {
+        Self {
+            target: Target::new(ptr_size, isa_level),
+            detailed_err,
+            opt_level: OptLevel::default(),
+            max_bytecode_size: None,
+            diagnostics: DiagnosticConfig::default(),
+        }
+    }
+
+    /// Sets the size of a pointer on the target, in bytes.
+    #[must_use]
+    pub fn with_ptr_size(mut self, ptr_size: u8) -> Self {
+        self.target.ptr_size = ptr_size;
+        self
+    }
+
+    /// Sets the byte order of the compiled program file.
+    #[must_use]
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.target.endianness = endianness;
+        self
+    }
+
+    /// Sets the highest instruction set level the target supports.
+    #[must_use]
+    pub fn with_isa_level(mut self, isa_level: IsaLevel) -> Self {
+        self.target.features = isa_level;
+        self
+    }
+
+    /// Sets whether or not runtime errors give the line and column they happened at.
+    #[must_use]
+    pub fn with_detailed_errors(mut self, detailed_err: bool) -> Self {
+        self.detailed_err = detailed_err;
+        self
+    }
+
+    /// Sets the optimization level, which decides the passes that run when they aren't chosen with a pass manager.
+    #[must_use]
+    pub fn with_opt_level(mut self, opt_level: OptLevel) -> Self {
+        self.opt_level = opt_level;
+        self
+    }
+
+    /// Limits the bytes the bytecode can take up.
+    #[must_use]
+    pub fn with_max_bytecode_size(mut self, max_bytecode_size: usize) -> Self {
+        self.max_bytecode_size = Some(max_bytecode_size);
+        self
+    }
+
+    /// Sets which warnings are reported, and which are reported as errors.
+    #[must_use]
+    pub fn with_diagnostics(mut self, diagnostics: DiagnosticConfig) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+
+    /// Checks that code can be compiled with the options, which it can't if pointers have no bytes.
+    ///
+    /// # Errors
+    /// Returns the errors that make the options invalid.
+    pub fn validate(self) -> Result<Self, Vec<Log>> {
+        if self.target.ptr_size == 0 {
+            return Err(vec![Log {
+                log_type: LogType::Error(ErrorType::ZeroPointerSize),
+                line_and_col: None,
+                span: None,
+                notes: Vec::new(),
+            }]);
+        }
+        Ok(self)
+    }
+
+    /// Gets the flags in the bytecode header for the options.
+    #[must_use]
+    pub fn header_flags(self) -> u8 {
+        let mut flags: u8 = 0;
+        if self.detailed_err {
+            flags |= DETAILED_ERRORS_FLAG;
+        }
+        if self.target.endianness == Endianness::Big {
+            flags |= BIG_ENDIAN_FLAG;
+        }
+        flags
+    }
+}
+
+impl Default for CompilerOptions {
+    /// Targets the machine the compiler runs on, with detailed errors and the latest instruction set level.
+    fn default() -> Self {
+        Self {
+            target: Target::default(),
+            detailed_err: true,
+            opt_level: OptLevel::default(),
+            max_bytecode_size: None,
+            diagnostics: DiagnosticConfig::default(),
+        }
+    }
+}
+
 /// The `OpCode` used in the bytecode.
-#[derive(FromPrimitive, Clone, Copy)]
+#[derive(FromPrimitive, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum OpCode {
     // Stack operators
     PushInt,
     PushByte,
+    PushString,
+    LoadConst,
     PopInt,
     PopByte,
+    Dup,
     PrintInt,
     PrintBool,
+    PrintString,
+    PrintNull,
+    PrintNullableInt,
+    PrintNullableBool,
 
     // Variable operators
     AllocInt,
     AllocBool,
+    AllocNullableInt,
+    AllocNullableBool,
     GetInt,
     GetBool,
+    GetNullableInt,
+    GetNullableBool,
     SetInt,
     SetBool,
+    SetNullableInt,
+    SetNullableBool,
+
+    // Nullable operators
+    Unwrap,
 
     // Casting/conversion operators
     IntToBool,
@@ -40,6 +352,64 @@ pub enum OpCode {
     MultiplyInt,
     DivideInt,
     ModuloInt,
+    RegisterOp, // Only generated by the registers pass, which fuses an operation on ints with its operands.
+
+    // Built-in math functions
+    AbsInt,
+    MinInt,
+    MaxInt,
+    PowInt,
+    SqrtInt,
+    ClampInt,
+
+    // String operators
+    LenString,
+    SubstringString,
+    ContainsString,
+    IntToString,
+    ParseInt,
+    ReadFile,
+    WriteFile,
+
+    // Map operators
+    NewMap,
+    PrintMap,
+    LenMap,
+    MapInsert,
+    MapGet,
+    MapRemove,
+
+    // List operators
+    NewList,
+    PrintList,
+    PrintStringList,
+    LenList,
+    ListPush,
+    ListPop,
+    ListIndex,
+    Args,
+
+    // Result operators
+    OkResult,
+    ErrResult,
+    IsOkResult,
+    UnwrapResult,
+    PrintResult,
+    JumpIfOk,
+
+    // Function operators
+    Call,
+    TailCall,
+    BindParam,
+    Return,
+    Halt,
+    Panic,
+    Assert,
+    FunctionName,
+    Constant,
+    LineTable,
+    Location, // Only generated by the compiler, which moves it into the line table.
+    CallHost,
 
     // Comparison operators.
     LessInt,
@@ -66,65 +436,763 @@ pub enum OpCode {
     // Equality operators
     EqualityInt,
     EqualityByte,
+    EqualityNullableInt,
+    EqualityNullableBool,
     InequalityInt,
     InequalityByte,
+    InequalityNullableInt,
+    InequalityNullableBool,
 }
 
+impl OpCode {
+    /// Gets the instruction set level that introduced the opcode.
+    #[must_use]
+    pub fn isa_level(self) -> IsaLevel {
+        match self {
+            Self::Call | Self::TailCall | Self::BindParam | Self::Return | Self::Halt => IsaLevel::V2,
+            Self::OkResult
+            | Self::ErrResult
+            | Self::IsOkResult
+            | Self::UnwrapResult
+            | Self::PrintResult
+            | Self::JumpIfOk => IsaLevel::V3,
+            Self::Panic | Self::FunctionName => IsaLevel::V4,
+            Self::IntToString | Self::ParseInt => IsaLevel::V5,
+            Self::PrintStringList | Self::Args => IsaLevel::V6,
+            Self::CallHost => IsaLevel::V7,
+            Self::ReadFile | Self::WriteFile => IsaLevel::V8,
+            Self::LoadConst | Self::Constant => IsaLevel::V9,
+            Self::LineTable | Self::Location => IsaLevel::V10,
+            Self::Dup => IsaLevel::V11,
+            Self::RegisterOp => IsaLevel::V12,
+            Self::Assert => IsaLevel::V13,
+            _ => IsaLevel::V1,
+        }
+    }
+
+    /// Gets the number of operand bytes that follow the opcode in the bytecode. The operand is the bytecode after
+    /// the opcode, which is needed for instructions with variable length operands. Returns None if the length of
+    /// the operand can not be read.
+    #[must_use]
+    #[allow(clippy::match_same_arms)] // Opcodes are grouped by what their operands contain.
+    pub fn operand_length(self, ptr_size: usize, operand: &[u8]) -> Option<usize> {
+        Some(match self {
+            Self::PushInt => 4,
+            Self::PushByte => 1,
+            // The length of the string followed by its bytes.
+            Self::PushString => {
+                let length: [u8; 4] = operand.get(0..4)?.try_into().ok()?;
+                4 + usize::try_from(u32::from_le_bytes(length)).ok()?
+            }
+            // The index of the constant in the constant pool.
+            Self::LoadConst => 2,
+            // The size of the value to copy.
+            Self::Dup => 1,
+            // The operation, the sources of its operands, and the values of its left and right operands.
+            Self::RegisterOp => {
+                read_register_operand(operand)?;
+                REGISTER_OPERAND_LENGTH
+            }
+            // The instruction that pushes the constant, which is either `PushInt` or `PushString`, with its operand.
+            Self::Constant => {
+                let op: Option<Self> = num_traits::FromPrimitive::from_u8(*operand.first()?);
+                let op: Self = op.filter(|op| matches!(op, Self::PushInt | Self::PushString))?;
+                1 + op.operand_length(ptr_size, &operand[1..])?
+            }
+            // The sizes of the keys and values of the map.
+            Self::PrintMap | Self::MapInsert | Self::MapGet | Self::MapRemove => 2,
+            // The size of the elements of the list.
+            Self::PrintList | Self::LenList | Self::ListPush | Self::ListPop | Self::ListIndex => 1,
+            // The size of the value of the result.
+            Self::OkResult | Self::PrintResult | Self::UnwrapResult => 1,
+            // The distance to jump forward, counted from the end of the instruction.
+            Self::JumpIfOk => ptr_size,
+            // The address of the function, the size of the arguments, and the size of the return value.
+            Self::Call | Self::TailCall => 2 * ptr_size + 1,
+            // The offset of the argument from the start of the arguments, followed by its size.
+            Self::BindParam => ptr_size + 1,
+            // The size of the return value.
+            Self::Return => 1,
+            // The address of a function, followed by the length of its name and the bytes of its name.
+            Self::FunctionName => {
+                let length: [u8; 4] = operand.get(ptr_size..ptr_size + 4)?.try_into().ok()?;
+                ptr_size + 4 + usize::try_from(u32::from_le_bytes(length)).ok()?
+            }
+            // The number of entries, followed by the offset of an instruction, its line, and its column for each entry.
+            Self::LineTable => {
+                let count: [u8; 4] = operand.get(0..4)?.try_into().ok()?;
+                4 + usize::try_from(u32::from_le_bytes(count)).ok()?.checked_mul(3 * ptr_size)?
+            }
+            // The line and column of the instruction that follows.
+            Self::Location => 2 * ptr_size,
+            // The number of arguments, followed by the length of the name of the host function and the bytes of its
+            // name.
+            Self::CallHost => {
+                let length: [u8; 4] = operand.get(1..5)?.try_into().ok()?;
+                5 + usize::try_from(u32::from_le_bytes(length)).ok()?
+            }
+            Self::GetInt
+            | Self::GetBool
+            | Self::GetNullableInt
+            | Self::GetNullableBool
+            | Self::SetInt
+            | Self::SetBool
+            | Self::SetNullableInt
+            | Self::SetNullableBool => BYTES_PER_VAR,
+            _ => 0,
+        })
+    }
+
+    /// Gets the size of the value left by an operation that a `RegisterOp` can run, or None if it can't run the
+    /// operation. These are the operations on two ints that give an int or a bool.
+    #[must_use]
+    pub fn register_result_size(self) -> Option<usize> {
+        match self {
+            Self::AddInt
+            | Self::SubtractInt
+            | Self::MultiplyInt
+            | Self::DivideInt
+            | Self::ModuloInt
+            | Self::MinInt
+            | Self::MaxInt
+            | Self::PowInt
+            | Self::AndInt
+            | Self::XorInt
+            | Self::OrInt
+            | Self::LeftShiftInt
+            | Self::RightShiftInt => Some(4),
+            Self::LessInt
+            | Self::LessEqualInt
+            | Self::GreaterInt
+            | Self::GreaterEqualInt
+            | Self::EqualityInt
+            | Self::InequalityInt => Some(1),
+            _ => None,
+        }
+    }
+}
+
+/// Where a `RegisterOp` gets one of its operands from.
+#[derive(FromPrimitive, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OperandSource {
+    /// The operand is popped from the stack. The right operand is popped before the left one.
+    Stack = 0,
+    /// The operand is the value of the variable whose index is given.
+    Register = 1,
+    /// The operand is the int given in the bytecode.
+    Immediate = 2,
+}
+
+/// The number of operand bytes of a `RegisterOp`. The first byte is the operation, the second holds the source of the
+/// left operand in its low 4 bits and the source of the right operand in its high 4 bits, and each operand is followed
+/// by 4 little-endian bytes that hold a variable index or an int, which are ignored for operands on the stack.
+pub const REGISTER_OPERAND_LENGTH: usize = 10;
+
+/// Reads the operand of a `RegisterOp`, giving the operation followed by the source and the value of its left and
+/// right operands. Returns None if the operand is cut off, or it has an operation or a source that isn't valid.
+#[must_use]
+pub fn read_register_operand(operand: &[u8]) -> Option<(OpCode, [(OperandSource, u32); 2])> {
+    let operand: &[u8] = operand.get(..REGISTER_OPERAND_LENGTH)?;
+    let op: OpCode = num_traits::FromPrimitive::from_u8(operand[0])?;
+    op.register_result_size()?;
+    let left: OperandSource = num_traits::FromPrimitive::from_u8(operand[1] & 0xf)?;
+    let right: OperandSource = num_traits::FromPrimitive::from_u8(operand[1] >> 4)?;
+    let value = |start: usize| {
+        u32::from_le_bytes([operand[start], operand[start + 1], operand[start + 2], operand[start + 3]])
+    };
+    Some((op, [(left, value(2)), (right, value(6))]))
+}
+
+/// An instance of a generic function to generate, given by the name token of its declaration and the types bound to
+/// its type parameters. Calls refer to instances by their index until the addresses of the instances are known.
+pub type Instance = (Token, Vec<Type>);
+
+// An instruction generated by the compiler, given by its opcode, its offset, and its bytes, including the opcode.
+type Instruction<'b> = (OpCode, usize, &'b [u8]);
+
+// A part of the code in the size report, given by its name and where it starts in the source code.
+type Part = (String, Option<(usize, usize)>);
+
+/// The statements of a function body before a call in tail position, followed by the arguments of the call.
+pub type TailCall<'e> = (&'e [Box<Expression>], &'e [Box<Expression>]);
+
 /// The output given by the compiler.
 pub struct CompilerOutput {
     pub file_text: String,
     pub bytecode: Option<Vec<u8>>,
     pub logs: Vec<Log>,
+    /// The output of the pass chosen with `--print-after`, if it ran.
+    pub dump: Option<String>,
+    /// The parts of the bytecode and the bytes each one takes up, in the order they appear, or an empty list if no
+    /// bytecode was generated. The parts are listed even if the bytecode is too large, so that `--emit=size` can show
+    /// what takes up the space.
+    pub sizes: Vec<SizeEntry>,
+    /// The pass that would have run next if the compilation was cancelled through the pass manager, in which case
+    /// there is no bytecode.
+    pub cancelled_at: Option<CompilerPass>,
+}
+
+/// A part of the bytecode in the size report: the header, a statement at the top level, an instance of a function,
+/// the constant pool, or the debug info.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SizeEntry {
+    /// What the part is, such as the name of a function or the source code of a statement.
+    pub name: String,
+    /// Where the part starts in the source code, if it comes from the source code.
+    pub line_and_col: Option<(usize, usize)>,
+    /// The number of bytes the part takes up after every pass has run.
+    pub size: usize,
+}
+
+/// Compiles to bytecode, running the passes of the optimization level in the options.
+#[must_use]
+pub fn compile(parser_output: ParserOutput, options: CompilerOptions) -> CompilerOutput {
+    compile_program(parser_output, options, false, &PassManager::with_opt_level(options.opt_level))
+}
+
+/// Compiles to bytecode for a script, where each statement at the top level is treated like an entry in a REPL. The
+/// value of every statement is printed as it runs, unless it has no value or it declares or assigns a variable.
+#[must_use]
+pub fn compile_script(parser_output: ParserOutput, options: CompilerOptions) -> CompilerOutput {
+    compile_program(parser_output, options, true, &PassManager::with_opt_level(options.opt_level))
 }
 
-/// Compiles to bytecode.
+/// Compiles to bytecode, running only the passes enabled in the pass manager instead of those of the optimization
+/// level. The code is compiled as a script (see `compile_script`) if script is true.
 #[must_use]
+pub fn compile_with_passes(
+    parser_output: ParserOutput,
+    options: CompilerOptions,
+    script: bool,
+    passes: &PassManager,
+) -> CompilerOutput {
+    compile_program(parser_output, options, script, passes)
+}
+
+/// Checks the code without generating bytecode, returning the logs that compiling it with the passes would give up to
+/// codegen. Errors that only codegen finds, such as the bytecode being too large, aren't reported.
+#[must_use]
+pub fn check(parser_output: ParserOutput, options: CompilerOptions, script: bool, passes: &PassManager) -> Vec<Log> {
+    let mut logs: Vec<Log> = parser_output.logs;
+    logs.append(&mut check_unused_values(&parser_output.expr, &parser_output.file_text, script));
+    logs.append(&mut lint(&parser_output.expr, &parser_output.file_text, &options.diagnostics));
+    // A cancelled check gives the logs found before it stopped.
+    let _ = optimize(parser_output.expr, &mut logs, passes, &mut None);
+    options.diagnostics.apply(logs)
+}
+
+// Compiles to bytecode, printing the values of the statements at the top level if the code is a script.
+fn compile_program(parser_output: ParserOutput, options: CompilerOptions, script: bool, passes: &PassManager) -> CompilerOutput {
+    let mut logs: Vec<Log> = parser_output.logs;
+    logs.append(&mut check_unused_values(&parser_output.expr, &parser_output.file_text, script));
+    logs.append(&mut lint(&parser_output.expr, &parser_output.file_text, &options.diagnostics));
+    if let Err(mut errors) = options.validate() {
+        logs.append(&mut errors);
+    }
+    let mut dump: Option<String> = None;
+    let optimized: Result<Expression, CompilerPass> = optimize(parser_output.expr, &mut logs, passes, &mut dump);
+    logs = options.diagnostics.apply(logs);
+    let generated: Result<(Option<Vec<u8>>, Vec<SizeEntry>), CompilerPass> = optimized.and_then(|expr| {
+        if is_error(&logs) {
+            Ok((None, Vec::new()))
+        } else {
+            generate(&expr, &parser_output.file_text, options, script, passes, &mut logs, &mut dump)
+        }
+    });
+    let (bytecode, sizes, cancelled_at) = match generated {
+        Ok((bytecode, sizes)) => (bytecode, sizes, None),
+        Err(pass) => (None, Vec::new(), Some(pass)),
+    };
+
+    CompilerOutput {
+        file_text: parser_output.file_text,
+        bytecode,
+        logs,
+        dump,
+        sizes,
+        cancelled_at,
+    }
+}
+
+// Generates the bytecode for code without errors and runs the passes over it, giving the bytecode if no errors were
+// found along with the size report, or the pass the compilation was cancelled before.
 #[allow(clippy::missing_panics_doc)] // Should never actually panic.
-pub fn compile(parser_output: ParserOutput, cli_args: [u8; 2]) -> CompilerOutput {
+#[allow(clippy::too_many_lines)] // Each pass is only a few lines, and splitting them up would hide their order.
+fn generate(
+    expr: &Expression,
+    file_text: &str,
+    options: CompilerOptions,
+    script: bool,
+    passes: &PassManager,
+    logs: &mut Vec<Log>,
+    dump: &mut Option<String>,
+) -> Result<(Option<Vec<u8>>, Vec<SizeEntry>), CompilerPass> {
     let mut bytecode: Option<Vec<u8>> = None;
-    let mut logs: Vec<Log> = parser_output.logs.clone();
-
-    if !is_error(&logs) {
-        let mut byte_list: Vec<u8> = cli_args.to_vec();
-        let expr_type: Type = parser_output
-            .expr
-            .get_type()
-            .expect("any \"None\" should have a parsing error");
-        byte_list.append(&mut generate_bytecode(
-            &parser_output.expr,
-            cli_args[0],
-            &mut logs,
-            &mut Vec::new(),
-        ));
-        if ![Type::Void, Type::Type].contains(&expr_type) {
-            byte_list.push(match expr_type {
-                Type::Int => OpCode::PrintInt,
-                Type::Bool => OpCode::PrintBool,
-                Type::Void | Type::Type => panic!("Should have been caught by above if statement."),
-            } as u8);
+    passes.check_cancelled(CompilerPass::Codegen)?;
+    let mut byte_list: Vec<u8> = header(file_text, options, passes);
+    let expr_type: Type = expr
+        .get_type()
+        .expect("any \"None\" should have a parsing error");
+    let mut instances: Vec<Instance> = Vec::new();
+    let (mut main_code, statements): (Vec<u8>, Vec<(usize, &Expression)>) =
+        generate_top_level(expr, options.target.ptr_size, script, logs, &mut instances);
+    // The parts of the size report after the header, and the offsets they start at, which are moved by the passes.
+    let mut parts: Vec<Part> = statement_parts(file_text, &statements);
+    let mut starts: Vec<usize> = statements.iter().map(|(offset, _)| byte_list.len() + offset).collect();
+    byte_list.append(&mut main_code);
+    byte_list.append(&mut print_ops(&expr_type));
+    if !instances.is_empty() {
+        let addresses: Vec<usize> = generate_functions(
+            &mut byte_list,
+            expr,
+            options.target.ptr_size,
+            logs,
+            &mut instances,
+            passes.is_enabled(CompilerPass::TailCalls),
+        );
+        parts.append(&mut instance_parts(expr, file_text, &instances));
+        starts.extend_from_slice(&addresses);
+        if passes.should_print_after(CompilerPass::Codegen) {
+            *dump = Some(dump_bytecode(&byte_list));
+        }
+        // The names are used by the stack traces of runtime errors, and left out if the targeted level doesn't have them.
+        if passes.is_enabled(CompilerPass::FunctionNames) && options.target.features >= IsaLevel::V4 {
+            passes.check_cancelled(CompilerPass::FunctionNames)?;
+            append_function_names(
+                &mut byte_list,
+                expr,
+                file_text,
+                options.target.ptr_size,
+                (&instances, &addresses),
+            );
+        }
+        if passes.should_print_after(CompilerPass::FunctionNames) {
+            *dump = Some(dump_bytecode(&byte_list));
+        }
+    } else if passes.should_print_after(CompilerPass::Codegen) {
+        *dump = Some(dump_bytecode(&byte_list));
+    }
+    byte_list = optimize_bytecode(byte_list, options, passes, &mut starts, dump)?;
+    byte_list = build_line_table(&byte_list, options, &mut starts);
+    let sizes: Vec<SizeEntry> = size_report(&byte_list, parts, &starts);
+    let isa_level: IsaLevel = required_isa_level(&byte_list, usize::from(options.target.ptr_size));
+    byte_list[2] = isa_level as u8;
+    let max_size: usize = max_bytecode_size(options);
+    if isa_level > options.target.features {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::IsaLevelNotTargeted(
+                isa_level as u8,
+                isa_level.features().to_string(),
+                options.target.features as u8,
+            )),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    } else if byte_list.len() > max_size {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::ExcessiveBytecode(byte_list.len(), max_size)),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    } else if passes.is_enabled(CompilerPass::Verify) {
+        passes.check_cancelled(CompilerPass::Verify)?;
+        let output: VerifierOutput = verify(&byte_list);
+        if passes.should_print_after(CompilerPass::Verify) {
+            *dump = Some(dump_bytecode(&byte_list));
         }
-        if u32::from(cli_args[0]) * 8 < usize::BITS && byte_list.len() >= 1 << (cli_args[0] * 8) {
+        if output.logs.is_empty() {
+            bytecode = Some(byte_list);
+        } else {
             logs.push(Log {
-                log_type: LogType::Error(ErrorType::ExcessiveBytecode),
+                log_type: LogType::Error(ErrorType::InternalCompilerError(
+                    "the generated bytecode failed verification.".to_string(),
+                )),
                 line_and_col: None,
+                span: None,
+                notes: Vec::new(),
             });
+            logs.extend(output.logs);
+        }
+    } else {
+        bytecode = Some(byte_list);
+    }
+    Ok((bytecode, sizes))
+}
+
+/// Warns about statements that compute a value with an operator and then throw it away, such as `x == 1;`, as the
+/// value was probably meant to be used. The statements at the top level of a script aren't checked, as their values
+/// are printed.
+#[must_use]
+pub fn check_unused_values(expr: &Expression, source: &str, script: bool) -> Vec<Log> {
+    let mut logs: Vec<Log> = Vec::new();
+    match expr {
+        Expression::ExpressionList { list } if script => {
+            for entry in list {
+                match &**entry {
+                    Expression::Statement { expr } => find_unused_values(expr, source, &mut logs),
+                    entry => find_unused_values(entry, source, &mut logs),
+                }
+            }
+        }
+        _ => find_unused_values(expr, source, &mut logs),
+    }
+    logs
+}
+
+// Adds a warning for each statement in the expression whose value is computed by an operator and never used.
+fn find_unused_values(expr: &Expression, source: &str, logs: &mut Vec<Log>) {
+    if let Expression::Statement { expr: value } = expr {
+        let mut inner: &Expression = value;
+        while let Expression::Grouping { expr, .. } = inner {
+            inner = expr;
+        }
+        let op: Option<Token> = match inner {
+            Expression::Binary { op, .. } if op.token_type != TokenType::Equals => Some(*op),
+            Expression::Unary { op, .. } => Some(*op),
+            _ => None,
+        };
+        // Values without a size, like null, aren't popped from the stack, so nothing is thrown away.
+        let has_value: bool = value.get_type().is_some_and(|value_type| !matches!(value_type, Type::Null | Type::Void | Type::Type));
+        if let (Some(op), true) = (op, has_value) {
+            logs.push(Log {
+                log_type: LogType::Warning(WarningType::UnusedValue(op.to_string(source))),
+                line_and_col: Some((op.line, op.col)),
+                span: value.span(),
+                notes: Vec::new(),
+            });
+        }
+    }
+    for child in children(expr) {
+        find_unused_values(child, source, logs);
+    }
+}
+
+/// Converts bytecode generated by the compiler to the format of a compiled program file, which `vm::load_bytecode`
+/// reads. The constants, function names, and line table at the end of the bytecode are moved to the constant pool and
+/// debug info sections. If the bytecode is for a big-endian target, the numbers in its instructions are written with
+/// their most significant byte first.
+///
+/// # Panics
+/// Panics if the bytecode is too short to have a header or longer than 4 GiB.
+#[must_use]
+pub fn serialize_bytecode(bytecode: &[u8]) -> Vec<u8> {
+    let code_start: usize = code_start(bytecode);
+    let pool_start: usize = first_offset(bytecode, &[OpCode::Constant, OpCode::FunctionName, OpCode::LineTable]);
+    let debug_info_start: usize = first_offset(bytecode, &[OpCode::FunctionName, OpCode::LineTable]);
+    let in_target_order = |instructions: &[u8]| {
+        if bytecode[1] & BIG_ENDIAN_FLAG == 0 {
+            instructions.to_vec()
         } else {
-            bytecode = Some(byte_list);
+            swap_byte_order(instructions, bytecode[0].into(), false)
         }
+    };
+    let mut file: Vec<u8> = MAGIC.to_vec();
+    file.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    file.push(bytecode[0]);
+    file.push(u8::try_from(Section::ALL.len()).expect("there are only a few sections"));
+    for section in Section::ALL {
+        let contents: Vec<u8> = match section {
+            Section::ConstantPool => in_target_order(&bytecode[pool_start..debug_info_start]),
+            Section::Code => {
+                [&bytecode[..code_start], &in_target_order(&bytecode[code_start..pool_start])[..]].concat()
+            }
+            Section::DebugInfo => in_target_order(&bytecode[debug_info_start..]),
+        };
+        file.push(section as u8);
+        file.extend_from_slice(&u32::try_from(contents.len()).expect("bytecode is less than 4 GiB").to_le_bytes());
+        file.extend_from_slice(&contents);
     }
+    file
+}
 
-    CompilerOutput {
-        file_text: parser_output.file_text,
-        bytecode,
-        logs,
+/// Reverses the bytes of every number in the operands of the instructions, which converts them between little-endian
+/// and big-endian. The instructions are read as big-endian if `big_endian` is true, and as little-endian otherwise. Any
+/// bytes from the first instruction that can't be read onwards are left as they are.
+#[must_use]
+pub fn swap_byte_order(instructions: &[u8], ptr_size: usize, big_endian: bool) -> Vec<u8> {
+    let mut swapped: Vec<u8> = instructions.to_vec();
+    let mut index: usize = 0;
+    while index < instructions.len() {
+        let op: Option<OpCode> = num_traits::FromPrimitive::from_u8(instructions[index]);
+        let operand: &[u8] = &instructions[index + 1..];
+        let Some((length, numbers)) = op.and_then(|op| operand_numbers(op, ptr_size, operand, big_endian)) else {
+            break;
+        };
+        for (start, size) in numbers {
+            swapped[index + 1 + start..index + 1 + start + size].reverse();
+        }
+        index += 1 + length;
     }
+    swapped
+}
+
+// Gets the length of the operand of an instruction, along with the start and size of each number in it. The operand
+// is read as big-endian if big_endian is true. Returns None if the operand can't be read or is cut off.
+fn operand_numbers(
+    op: OpCode,
+    ptr_size: usize,
+    operand: &[u8],
+    big_endian: bool,
+) -> Option<(usize, Vec<(usize, usize)>)> {
+    let read_length = |start: usize| -> Option<usize> {
+        let mut bytes: [u8; 4] = operand.get(start..start + 4)?.try_into().ok()?;
+        if big_endian {
+            bytes.reverse();
+        }
+        usize::try_from(u32::from_le_bytes(bytes)).ok()
+    };
+    let (length, numbers): (usize, Vec<(usize, usize)>) = match op {
+        OpCode::PushInt => (4, vec![(0, 4)]),
+        OpCode::PushString => (4 + read_length(0)?, vec![(0, 4)]),
+        OpCode::LoadConst => (2, vec![(0, 2)]),
+        OpCode::RegisterOp => (op.operand_length(ptr_size, operand)?, vec![(2, 4), (6, 4)]),
+        OpCode::Constant => {
+            let inner: Option<OpCode> = num_traits::FromPrimitive::from_u8(*operand.first()?);
+            let inner: OpCode = inner.filter(|op| matches!(op, OpCode::PushInt | OpCode::PushString))?;
+            let (length, numbers) = operand_numbers(inner, ptr_size, &operand[1..], big_endian)?;
+            (1 + length, numbers.into_iter().map(|(start, size)| (start + 1, size)).collect())
+        }
+        OpCode::JumpIfOk => (ptr_size, vec![(0, ptr_size)]),
+        OpCode::Call | OpCode::TailCall => (2 * ptr_size + 1, vec![(0, ptr_size), (ptr_size, ptr_size)]),
+        OpCode::BindParam => (ptr_size + 1, vec![(0, ptr_size)]),
+        OpCode::FunctionName => (ptr_size + 4 + read_length(ptr_size)?, vec![(0, ptr_size), (ptr_size, 4)]),
+        OpCode::LineTable => {
+            let count: usize = read_length(0)?.checked_mul(3)?;
+            let length: usize = count.checked_mul(ptr_size)?.checked_add(4)?;
+            if length > operand.len() {
+                return None;
+            }
+            (length, once((0, 4)).chain((0..count).map(|entry| (4 + entry * ptr_size, ptr_size))).collect())
+        }
+        OpCode::Location => (2 * ptr_size, vec![(0, ptr_size), (ptr_size, ptr_size)]),
+        OpCode::CallHost => (5 + read_length(1)?, vec![(1, 4)]),
+        OpCode::GetInt
+        | OpCode::GetBool
+        | OpCode::GetNullableInt
+        | OpCode::GetNullableBool
+        | OpCode::SetInt
+        | OpCode::SetBool
+        | OpCode::SetNullableInt
+        | OpCode::SetNullableBool => (BYTES_PER_VAR, vec![(0, BYTES_PER_VAR)]),
+        _ => (op.operand_length(ptr_size, operand)?, Vec::new()),
+    };
+    (length <= operand.len()).then_some((length, numbers))
 }
 
+// Gets the offset of the first instruction with one of the opcodes, which is where the constant pool or the debug info
+// starts. This is the end of the bytecode if there is no such instruction, or if an instruction before it can't be read.
+fn first_offset(bytecode: &[u8], ops: &[OpCode]) -> usize {
+    let ptr_size: usize = usize::from(bytecode[0]);
+    let mut index: usize = code_start(bytecode);
+    while index < bytecode.len() {
+        let op: Option<OpCode> = num_traits::FromPrimitive::from_u8(bytecode[index]);
+        let Some(op) = op else {
+            break;
+        };
+        if ops.contains(&op) {
+            return index;
+        }
+        let Some(length) = op.operand_length(ptr_size, &bytecode[index + 1..]) else {
+            break;
+        };
+        index += 1 + length;
+    }
+    bytecode.len()
+}
+
+// Runs the passes that change the AST before codegen, dumping the AST if any of them is chosen with --print-after.
+// The AST is only optimized if it parsed without errors. Gives the pass the compilation was cancelled before, if any.
+fn optimize(
+    mut expr: Expression,
+    logs: &mut Vec<Log>,
+    passes: &PassManager,
+    dump: &mut Option<String>,
+) -> Result<Expression, CompilerPass> {
+    if passes.should_print_after(CompilerPass::Parse) {
+        *dump = Some(format!("{expr:#?}"));
+    }
+    if !is_error(logs) && passes.is_enabled(CompilerPass::ConstantFolding) {
+        passes.check_cancelled(CompilerPass::ConstantFolding)?;
+        expr = fold_constants(&expr, logs);
+    }
+    // Tail calls are found during codegen, so the AST given to codegen is their output.
+    if passes.should_print_after(CompilerPass::ConstantFolding) || passes.should_print_after(CompilerPass::TailCalls) {
+        *dump = Some(format!("{expr:#?}"));
+    }
+    Ok(expr)
+}
+
+// Gets the most bytes the bytecode can take up, which is the most that pointers can address unless a smaller limit is
+// chosen in the options.
+fn max_bytecode_size(options: CompilerOptions) -> usize {
+    let addressable: usize = if u32::from(options.target.ptr_size) * 8 < usize::BITS {
+        (1 << (options.target.ptr_size * 8)) - 1
+    } else {
+        usize::MAX
+    };
+    options.max_bytecode_size.map_or(addressable, |limit| limit.min(addressable))
+}
+
+// Runs the passes that change the generated bytecode, dumping it after any of them chosen with --print-after. The
+// offsets are moved along with the instructions at them. Gives the pass the compilation was cancelled before, if any.
+fn optimize_bytecode(
+    mut bytecode: Vec<u8>,
+    options: CompilerOptions,
+    passes: &PassManager,
+    offsets: &mut [usize],
+    dump: &mut Option<String>,
+) -> Result<Vec<u8>, CompilerPass> {
+    // Older instruction set levels can't duplicate values.
+    if passes.is_enabled(CompilerPass::CommonSubexpressions) && options.target.features >= IsaLevel::V11 {
+        passes.check_cancelled(CompilerPass::CommonSubexpressions)?;
+        bytecode = eliminate_common_subexpressions(&bytecode, options.target.ptr_size, offsets);
+    }
+    if passes.should_print_after(CompilerPass::CommonSubexpressions) {
+        *dump = Some(dump_bytecode(&bytecode));
+    }
+    // Older instruction set levels have no register instructions.
+    if passes.is_enabled(CompilerPass::Registers) && options.target.features >= IsaLevel::V12 {
+        passes.check_cancelled(CompilerPass::Registers)?;
+        bytecode = use_registers(&bytecode, options.target.ptr_size, offsets);
+    }
+    if passes.should_print_after(CompilerPass::Registers) {
+        *dump = Some(dump_bytecode(&bytecode));
+    }
+    // Older instruction set levels have no constant pool.
+    if passes.is_enabled(CompilerPass::ConstantPool) && options.target.features >= IsaLevel::V9 {
+        passes.check_cancelled(CompilerPass::ConstantPool)?;
+        bytecode = pool_constants(&bytecode, options.target.ptr_size, offsets);
+    }
+    if passes.should_print_after(CompilerPass::ConstantPool) {
+        *dump = Some(dump_bytecode(&bytecode));
+    }
+    Ok(bytecode)
+}
+
+// Creates the bytecode header. The level in the header is filled in once the instructions are known.
+fn header(source: &str, options: CompilerOptions, passes: &PassManager) -> Vec<u8> {
+    let mut header: Vec<u8> = vec![options.target.ptr_size, options.header_flags(), 0];
+    let mut metadata: Vec<u8> = Metadata::new(source, options, passes).encode();
+    header.extend_from_slice(&u16::try_from(metadata.len()).expect("metadata is short").to_le_bytes());
+    header.append(&mut metadata);
+    header
+}
+
+// Lists the bytes taken up by the header, the parts of the code that start at the offsets, the constant pool, and the
+// debug info. Each part of the code ends where the next one starts, and parts that take up no bytes, such as
+// declarations, are left out.
+fn size_report(bytecode: &[u8], parts: Vec<Part>, starts: &[usize]) -> Vec<SizeEntry> {
+    let pool_start: usize = first_offset(bytecode, &[OpCode::Constant, OpCode::FunctionName, OpCode::LineTable]);
+    let debug_info_start: usize = first_offset(bytecode, &[OpCode::FunctionName, OpCode::LineTable]);
+    let entry = |name: &str, size: usize| SizeEntry {
+        name: name.to_string(),
+        line_and_col: None,
+        size,
+    };
+    let mut sizes: Vec<SizeEntry> = vec![entry("header", code_start(bytecode))];
+    let ends = starts.iter().skip(1).copied().chain([pool_start]);
+    for ((name, line_and_col), (start, end)) in parts.into_iter().zip(starts.iter().zip(ends)) {
+        sizes.push(SizeEntry {
+            name,
+            line_and_col,
+            size: end - start,
+        });
+    }
+    sizes.push(entry("constant pool", debug_info_start - pool_start));
+    sizes.push(entry("debug info", bytecode.len() - debug_info_start));
+    sizes.retain(|entry| entry.size > 0);
+    sizes
+}
+
+// Gets the line of source code the token is on, shortened to fit on a line of the size report.
+fn source_snippet(source: &str, token: Token) -> String {
+    let line: &str = source.lines().nth(token.line.saturating_sub(1)).unwrap_or_default().trim();
+    if line.chars().count() > 40 {
+        format!("{}...", line.chars().take(37).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+// Disassembles the bytecode generated so far for a dump, filling in the instruction set level the header will have.
+fn dump_bytecode(bytecode: &[u8]) -> String {
+    let mut bytecode: Vec<u8> = bytecode.to_vec();
+    bytecode[2] = required_isa_level(&bytecode, bytecode[0] as usize) as u8;
+    disassemble(&bytecode, AddressMode::Offsets).lines.join("\n")
+}
+
+// Gets the bytecode that prints the value on top of the stack, which is nothing for types without values.
+fn print_ops(expr_type: &Type) -> Vec<u8> {
+    let op: OpCode = match expr_type {
+        Type::Void | Type::Type => return Vec::new(),
+        Type::Int => OpCode::PrintInt,
+        Type::Bool => OpCode::PrintBool,
+        Type::String => OpCode::PrintString,
+        Type::Null => OpCode::PrintNull,
+        Type::Nullable(inner) => match **inner {
+            Type::Int => OpCode::PrintNullableInt,
+            Type::Bool => OpCode::PrintNullableBool,
+            _ => panic!("no other nullable types should be possible."),
+        },
+        Type::Map(..) => OpCode::PrintMap,
+        Type::List(element) if **element == Type::String => OpCode::PrintStringList,
+        Type::List(_) => OpCode::PrintList,
+        Type::Result(_) | Type::Err => OpCode::PrintResult,
+        Type::Generic(_) => panic!("type parameters should only be used in function bodies"),
+    };
+    let mut ops: Vec<u8> = vec![op as u8];
+    match expr_type {
+        Type::Map(..) => ops.append(&mut map_sizes(expr_type)),
+        Type::List(element) if **element == Type::String => {}
+        Type::List(_) => ops.push(list_size(expr_type)),
+        Type::Result(_) | Type::Err => ops.push(result_size(expr_type)),
+        _ => {}
+    }
+    ops
+}
+
+// Generates the bytecode for the top level of the code, along with the offset in it that each statement starts at. If
+// the code is a script, statements that would be popped are printed instead, unless they declare or assign a variable.
+fn generate_top_level<'e>(
+    expr: &'e Expression,
+    ptr_size: u8,
+    script: bool,
+    logs: &mut Vec<Log>,
+    instances: &mut Vec<Instance>,
+) -> (Vec<u8>, Vec<(usize, &'e Expression)>) {
+    let Expression::ExpressionList { list } = expr else {
+        return (generate_bytecode(expr, ptr_size, logs, &mut Vec::new(), instances), vec![(0, expr)]);
+    };
+    let mut var_list: Vec<Token> = Vec::new();
+    let mut bytecode: Vec<u8> = Vec::new();
+    let mut statements: Vec<(usize, &Expression)> = Vec::new();
+    for entry in list {
+        statements.push((bytecode.len(), entry));
+        bytecode.append(&mut statement_location(entry, ptr_size));
+        match &**entry {
+            Expression::Statement { expr }
+                if script
+                    && !matches!(**expr, Expression::VariableDeclaration { .. })
+                    && !matches!(&**expr, Expression::Binary { op, .. } if op.token_type == TokenType::Equals) =>
+            {
+                bytecode.append(&mut generate_bytecode(expr, ptr_size, logs, &mut var_list, instances));
+                bytecode.append(&mut print_ops(&expr.get_type().expect("any \"None\" should have a parsing error")));
+            }
+            _ => bytecode.append(&mut generate_bytecode(entry, ptr_size, logs, &mut var_list, instances)),
+        }
+    }
+    (bytecode, statements)
+}
+
+#[allow(clippy::too_many_lines)] // Necessary for all the different expression types.
 fn generate_bytecode(
     expr: &Expression,
     ptr_size: u8,
     logs: &mut Vec<Log>,
     var_list: &mut Vec<Token>,
+    instances: &mut Vec<Instance>,
 ) -> Vec<u8> {
     let mut bytecode: Vec<u8> = Vec::new();
     match expr {
@@ -140,63 +1208,273 @@ fn generate_bytecode(
                 left,
                 *op,
                 right,
-                expr_type.expect("any \"None\" should have a parsing error"),
+                expr_type
+                    .as_ref()
+                    .expect("any \"None\" should have a parsing error"),
                 logs,
                 var_list,
+                instances,
             );
         }
+        Expression::Call {
+            token,
+            function,
+            args,
+            ..
+        } => {
+            for arg in args {
+                bytecode.append(&mut generate_bytecode(arg, ptr_size, logs, var_list, instances));
+            }
+            let arg_type: Option<Type> = args.first().and_then(|arg| arg.get_type());
+            if let Some(map_type @ Type::Map(..)) = &arg_type {
+                bytecode.push(match function {
+                    Builtin::Len => OpCode::LenMap,
+                    Builtin::Insert => OpCode::MapInsert,
+                    Builtin::Get => OpCode::MapGet,
+                    Builtin::Remove => OpCode::MapRemove,
+                    _ => panic!("no other functions take maps"),
+                } as u8);
+                if *function != Builtin::Len {
+                    bytecode.append(&mut map_sizes(map_type));
+                }
+                return bytecode;
+            }
+            if let Some(list_type @ Type::List(_)) = &arg_type {
+                bytecode.push(match function {
+                    Builtin::Len => OpCode::LenList,
+                    Builtin::Push => OpCode::ListPush,
+                    Builtin::Pop => OpCode::ListPop,
+                    _ => panic!("no other functions take lists"),
+                } as u8);
+                bytecode.push(list_size(list_type));
+                return bytecode;
+            }
+            if [
+                Builtin::Sqrt,
+                Builtin::Substring,
+                Builtin::Panic,
+                Builtin::Assert,
+                Builtin::RandomInt,
+                Builtin::NowMillis,
+                Builtin::ReadFile,
+                Builtin::WriteFile,
+            ]
+            .contains(function)
+            {
+                bytecode.append(&mut location(*token, ptr_size));
+            }
+            bytecode.push(match function {
+                Builtin::Abs => OpCode::AbsInt,
+                Builtin::Min => OpCode::MinInt,
+                Builtin::Max => OpCode::MaxInt,
+                Builtin::Pow => OpCode::PowInt,
+                Builtin::Sqrt => OpCode::SqrtInt,
+                Builtin::Clamp => OpCode::ClampInt,
+                Builtin::Len => OpCode::LenString,
+                Builtin::Substring => OpCode::SubstringString,
+                Builtin::Contains => OpCode::ContainsString,
+                Builtin::Ok => OpCode::OkResult,
+                Builtin::Err => OpCode::ErrResult,
+                Builtin::IsOk => OpCode::IsOkResult,
+                Builtin::Panic => OpCode::Panic,
+                Builtin::Assert => OpCode::Assert,
+                Builtin::ToString => OpCode::IntToString,
+                Builtin::ParseInt => OpCode::ParseInt,
+                Builtin::Args => OpCode::Args,
+                Builtin::RandomInt | Builtin::NowMillis => OpCode::CallHost,
+                Builtin::ReadFile => OpCode::ReadFile,
+                Builtin::WriteFile => OpCode::WriteFile,
+                Builtin::Insert | Builtin::Get | Builtin::Remove | Builtin::Push | Builtin::Pop => {
+                    panic!("functions over maps and lists should have been handled above")
+                }
+            } as u8);
+            if *function == Builtin::Ok {
+                bytecode.push(element_size(&arg_type.expect("any \"None\" should have a parsing error")));
+            }
+            if [Builtin::RandomInt, Builtin::NowMillis].contains(function) {
+                // These are provided by the host functions of the same name that the VM registers by default.
+                bytecode.append(&mut host_call_operand(args.len(), function.name()));
+            }
+        }
+        Expression::HostCall { token, name, args, .. } => {
+            for arg in args {
+                bytecode.append(&mut generate_bytecode(arg, ptr_size, logs, var_list, instances));
+            }
+            bytecode.append(&mut location(*token, ptr_size));
+            bytecode.push(OpCode::CallHost as u8);
+            bytecode.append(&mut host_call_operand(args.len(), name));
+        }
         Expression::Cast { expr_type, expr } => {
-            bytecode.append(&mut generate_bytecode(expr, ptr_size, logs, var_list));
-            let cast_op: Option<OpCode> = match expr.get_type() {
-                Some(Type::Int) => match expr_type {
-                    Some(Type::Int) => None,
-                    Some(Type::Bool) => Some(OpCode::IntToBool),
-                    _ => panic!("no other types should be possible."),
-                },
-                Some(Type::Bool) => match expr_type {
-                    Some(Type::Int) => Some(OpCode::BoolToInt),
-                    Some(Type::Bool) => None,
-                    _ => panic!("no other types should be possible."),
-                },
-                Some(Type::Void) => match expr_type {
-                    Some(Type::Void) => None,
-                    _ => panic!("no other types should be possible."),
-                },
-                Some(Type::Type) => match expr_type {
-                    Some(Type::Type) => None,
-                    _ => panic!("no other types should be possible."),
-                },
-                None => panic!("should not be able to cast from a None type"),
-            };
-            if let Some(cast_op) = cast_op {
-                bytecode.push(cast_op as u8);
+            bytecode.append(&mut generate_bytecode(expr, ptr_size, logs, var_list, instances));
+            handle_cast(
+                &mut bytecode,
+                &expr.get_type().expect("should not be able to cast from a None type"),
+                &expr_type
+                    .clone()
+                    .expect("any \"None\" should have a parsing error"),
+            );
+        }
+        Expression::FunctionCall {
+            token,
+            declaration,
+            args,
+            type_args,
+            expr_type,
+        } => {
+            let mut args_size: usize = 0;
+            for arg in args {
+                bytecode.append(&mut generate_bytecode(arg, ptr_size, logs, var_list, instances));
+                args_size += type_size(&arg.get_type().expect("any \"None\" should have a parsing error"));
             }
+            let instance: Instance = (*declaration, type_args.clone());
+            let id: usize = instances
+                .iter()
+                .position(|other| *other == instance)
+                .unwrap_or_else(|| {
+                    instances.push(instance);
+                    instances.len() - 1
+                });
+            bytecode.append(&mut location(*token, ptr_size));
+            bytecode.push(OpCode::Call as u8);
+            bytecode.append(&mut usize_to_ptr_size(id, ptr_size)); // Replaced by the address when linking.
+            bytecode.append(&mut usize_to_ptr_size(args_size, ptr_size));
+            bytecode.push(
+                u8::try_from(type_size(
+                    expr_type
+                        .as_ref()
+                        .expect("any \"None\" should have a parsing error"),
+                ))
+                .expect("values are at most 5 bytes"),
+            );
+        }
+        // Method calls are instanced using the trait method and the type of the value it is called on, and resolved to
+        // the method of the matching impl block when the instance is generated.
+        Expression::MethodCall {
+            token,
+            method,
+            args,
+            expr_type,
+        } => {
+            let call: Expression = Expression::FunctionCall {
+                token: *token,
+                declaration: *method,
+                args: args.clone(),
+                type_args: vec![args[0].get_type().expect("any \"None\" should have a parsing error")],
+                expr_type: expr_type.clone(),
+            };
+            bytecode.append(&mut generate_bytecode(&call, ptr_size, logs, var_list, instances));
         }
         Expression::ExpressionList { list } => {
             for expr in list {
-                bytecode.append(&mut generate_bytecode(expr, ptr_size, logs, var_list));
+                bytecode.append(&mut statement_location(expr, ptr_size));
+                bytecode.append(&mut generate_bytecode(expr, ptr_size, logs, var_list, instances));
             }
         }
         Expression::Grouping { expr: child, .. } => {
-            bytecode.append(&mut generate_bytecode(child, ptr_size, logs, var_list));
+            bytecode.append(&mut generate_bytecode(child, ptr_size, logs, var_list, instances));
         }
         Expression::Literal { token, .. } => {
             handle_literal(&mut bytecode, *token);
         }
+        Expression::MapLiteral {
+            entries, expr_type, ..
+        } => {
+            let map_type: &Type = expr_type
+                .as_ref()
+                .expect("any \"None\" should have a parsing error");
+            bytecode.push(OpCode::NewMap as u8);
+            for (key, value) in entries {
+                bytecode.append(&mut generate_bytecode(key, ptr_size, logs, var_list, instances));
+                bytecode.append(&mut generate_bytecode(value, ptr_size, logs, var_list, instances));
+                bytecode.push(OpCode::MapInsert as u8);
+                bytecode.append(&mut map_sizes(map_type));
+            }
+        }
+        Expression::Index {
+            token,
+            expr: list,
+            index,
+            ..
+        } => {
+            bytecode.append(&mut generate_bytecode(list, ptr_size, logs, var_list, instances));
+            bytecode.append(&mut generate_bytecode(index, ptr_size, logs, var_list, instances));
+            bytecode.append(&mut location(*token, ptr_size));
+            bytecode.push(OpCode::ListIndex as u8);
+            bytecode.push(list_size(
+                &list.get_type().expect("any \"None\" should have a parsing error"),
+            ));
+        }
+        Expression::ListLiteral {
+            elements,
+            expr_type,
+            ..
+        } => {
+            let size: u8 = list_size(
+                expr_type
+                    .as_ref()
+                    .expect("any \"None\" should have a parsing error"),
+            );
+            bytecode.push(OpCode::NewList as u8);
+            for element in elements {
+                bytecode.append(&mut generate_bytecode(element, ptr_size, logs, var_list, instances));
+                bytecode.push(OpCode::ListPush as u8);
+                bytecode.push(size);
+            }
+        }
+        Expression::Postfix {
+            op, expr: child, ..
+        } => {
+            bytecode.append(&mut generate_bytecode(child, ptr_size, logs, var_list, instances));
+            let child_type: Type = child.get_type().expect("any \"None\" should have a parsing error");
+            match (op.token_type, &child_type) {
+                (TokenType::ExclamationMark, Type::Nullable(_)) => {
+                    bytecode.append(&mut location(*op, ptr_size));
+                    bytecode.push(OpCode::Unwrap as u8);
+                }
+                (TokenType::ExclamationMark, Type::Result(_)) => {
+                    bytecode.append(&mut location(*op, ptr_size));
+                    bytecode.push(OpCode::UnwrapResult as u8);
+                    bytecode.push(result_size(&child_type));
+                }
+                // An error is returned as it is, since results have the same representation for every value type.
+                (TokenType::QuestionMark, Type::Result(_)) => {
+                    bytecode.push(OpCode::JumpIfOk as u8);
+                    bytecode.append(&mut usize_to_ptr_size(2, ptr_size)); // Skips the return instruction.
+                    bytecode.push(OpCode::Return as u8);
+                    bytecode.push(4);
+                    bytecode.append(&mut location(*op, ptr_size));
+                    bytecode.push(OpCode::UnwrapResult as u8);
+                    bytecode.push(result_size(&child_type));
+                }
+                _ => panic!("all postfix operators should have been accounted for"),
+            }
+        }
         Expression::Statement { expr } => {
-            bytecode.append(&mut generate_bytecode(expr, ptr_size, logs, var_list));
-            if expr.get_type() != Some(Type::Void) {
-                bytecode.push(match expr.get_type() {
-                    Some(Type::Int) => OpCode::PopInt,
-                    Some(Type::Bool) => OpCode::PopByte,
-                    _ => panic!("This type is invalid."),
-                } as u8);
+            bytecode.append(&mut generate_bytecode(expr, ptr_size, logs, var_list, instances));
+            if let Some(expr_type) = expr.get_type() {
+                bytecode.append(&mut pop_ops(&expr_type));
+            } else {
+                panic!("This type is invalid.");
             }
         }
+        Expression::StringLiteral { token, value } => {
+            let Ok(length) = u32::try_from(value.len()) else {
+                logs.push(Log {
+                    log_type: LogType::Error(ErrorType::ExcessiveBytecode(value.len(), u32::MAX as usize)),
+                    line_and_col: Some((token.line, token.col)),
+                    span: Some(token.span()),
+                    notes: Vec::new(),
+                });
+                return bytecode;
+            };
+            bytecode.push(OpCode::PushString as u8);
+            bytecode.append(&mut length.to_le_bytes().to_vec());
+            bytecode.extend_from_slice(value.as_bytes());
+        }
         Expression::Unary {
             op, expr: child, ..
         } => {
-            bytecode.append(&mut generate_bytecode(child, ptr_size, logs, var_list));
+            bytecode.append(&mut generate_bytecode(child, ptr_size, logs, var_list, instances));
             bytecode.push(match op.token_type {
                 TokenType::Minus => OpCode::MinusInt,
                 TokenType::Tilde => OpCode::ComplementInt,
@@ -208,45 +1486,36 @@ fn generate_bytecode(
             initialized,
             token,
             expr_type,
+            ..
         } => {
             // This handles get expressions; set expressions handled with other binary expressions.
             if *initialized {
                 let index: Option<usize> = var_list.iter().position(|t| t == token);
                 assert!(index.is_some(), "variable should be in var_list");
                 let index: usize = index.expect("checked by if");
-                bytecode.push(match expr_type {
-                    Some(Type::Int) => OpCode::GetInt,
-                    Some(Type::Bool) => OpCode::GetBool,
-                    _ => panic!("all variable types should have been accounted for",),
-                } as u8);
+                bytecode.push(
+                    variable_op(expr_type.as_ref(), [OpCode::GetInt, OpCode::GetBool, OpCode::GetNullableInt, OpCode::GetNullableBool])
+                        as u8,
+                );
                 bytecode.append(&mut index.to_le_bytes()[0..BYTES_PER_VAR].to_vec());
             }
         }
         Expression::VariableDeclaration { initialized_var } => {
-            if let Expression::Variable {
-                token, expr_type, ..
-            } = **initialized_var
-            {
-                if var_list.len() == 1 << (8 * BYTES_PER_VAR) {
-                    // Equals rather than greater or equals so that this only happens once.
-                    logs.push(Log {
-                        log_type: LogType::Error(ErrorType::TooManyVariables(BYTES_PER_VAR)),
-                        line_and_col: None, // TODO: Should this contain line and col of declaration of variable that pushes compiler past the limit?
-                    });
-                }
-                var_list.push(token);
-                bytecode.push(match expr_type {
-                    Some(Type::Int) => OpCode::AllocInt,
-                    Some(Type::Bool) => OpCode::AllocBool,
-                    _ => panic!("all variable types should have been accounted for",),
-                } as u8);
-            } else {
-                panic!("variable declarations should always contain variables.")
-            }
+            handle_declaration(&mut bytecode, initialized_var, logs, var_list, true);
         }
         // Void expressions are empty; cast and type expressions shouldn't occur in isolation.
-        Expression::CastOp { .. } | Expression::Type { .. } | Expression::Void => {}
-        Expression::EOF | Expression::Null => {
+        // Function declarations and impl blocks are generated after the rest of the code, once for each instance.
+        // Flags declarations are empty, as each use of a flag is replaced by its value, and host functions are provided
+        // by the VM.
+        Expression::CastOp { .. }
+        | Expression::FunctionDeclaration { .. }
+        | Expression::TraitDeclaration { .. }
+        | Expression::FlagsDeclaration { .. }
+        | Expression::ExternDeclaration { .. }
+        | Expression::ImplDeclaration { .. }
+        | Expression::Type { .. }
+        | Expression::Void => {}
+        Expression::Error { .. } | Expression::EOF | Expression::Null => {
             panic!("all expression types should have been accounted for")
         }
     }
@@ -254,50 +1523,48 @@ fn generate_bytecode(
 }
 
 // Handles binary expressions.
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)] // Necessary for all the different binary operators.
 fn handle_binary(
     bytecode: &mut Vec<u8>,
     ptr_size: u8,
     left: &Expression,
     op: Token,
     right: &Expression,
-    expr_type: Type,
+    expr_type: &Type,
     logs: &mut Vec<Log>,
     var_list: &mut Vec<Token>,
+    instances: &mut Vec<Instance>,
 ) {
     // Variable expressions are weird and need to be handled separately.
     if op.token_type != TokenType::Equals {
-        bytecode.append(&mut generate_bytecode(left, ptr_size, logs, var_list));
-        bytecode.append(&mut generate_bytecode(right, ptr_size, logs, var_list));
+        bytecode.append(&mut generate_bytecode(left, ptr_size, logs, var_list, instances));
+        bytecode.append(&mut generate_bytecode(right, ptr_size, logs, var_list, instances));
     }
     match op.token_type {
         TokenType::Equals => {
             let mut var: Expression = left.clone();
             if let Expression::VariableDeclaration { initialized_var } = var {
                 // This should only run if this is a declaration, not a lone variable; otherwise this could be interpreted as a get.
-                bytecode.append(&mut generate_bytecode(left, ptr_size, logs, var_list));
+                handle_declaration(bytecode, &initialized_var, logs, var_list, false);
+                bytecode.append(&mut pop_ops(expr_type)); // The declaration's value is replaced by the assignment's.
                 var = *initialized_var;
             }
-            bytecode.push(match expr_type {
-                Type::Int => OpCode::PopInt,
-                Type::Bool => OpCode::PopByte,
-                Type::Void | Type::Type => panic!("all variable types should have been accounted for",),
-            } as u8);
-            bytecode.append(&mut generate_bytecode(right, ptr_size, logs, var_list));
+            bytecode.append(&mut generate_bytecode(right, ptr_size, logs, var_list, instances));
             if let Expression::Variable {
                 initialized,
                 token,
                 expr_type,
+                ..
             } = var
             {
                 if initialized {
                     let index: Option<usize> = var_list.iter().position(|t| *t == token);
                     assert!(index.is_some(), "variable should be in var_list");
                     let index: usize = index.expect("checked by if");
-                    bytecode.push(match expr_type {
-                        Some(Type::Int) => OpCode::SetInt,
-                        Some(Type::Bool) => OpCode::SetBool,
-                        _ => panic!("all variable types should have been accounted for",),
-                    } as u8);
+                    bytecode.push(
+                        variable_op(expr_type.as_ref(), [OpCode::SetInt, OpCode::SetBool, OpCode::SetNullableInt, OpCode::SetNullableBool])
+                            as u8,
+                    );
                     bytecode.append(&mut index.to_le_bytes()[0..BYTES_PER_VAR].to_vec());
                 }
             }
@@ -313,14 +1580,12 @@ fn handle_binary(
             bytecode.push(OpCode::MultiplyInt as u8);
         }
         TokenType::Slash => {
+            bytecode.append(&mut location(op, ptr_size));
             bytecode.push(OpCode::DivideInt as u8);
-            bytecode.append(&mut usize_to_ptr_size(op.line, ptr_size));
-            bytecode.append(&mut usize_to_ptr_size(op.col, ptr_size));
         }
         TokenType::Percent => {
+            bytecode.append(&mut location(op, ptr_size));
             bytecode.push(OpCode::ModuloInt as u8);
-            bytecode.append(&mut usize_to_ptr_size(op.line, ptr_size));
-            bytecode.append(&mut usize_to_ptr_size(op.col, ptr_size));
         }
 
         TokenType::Less => {
@@ -340,21 +1605,21 @@ fn handle_binary(
             bytecode.push(match expr_type {
                 Type::Int => OpCode::AndInt,
                 Type::Bool => OpCode::AndByte,
-                Type::Void | Type::Type => panic!("Invalid type for this operation"),
+                _ => panic!("Invalid type for this operation"),
             } as u8);
         }
         TokenType::Caret => {
             bytecode.push(match expr_type {
                 Type::Int => OpCode::XorInt,
                 Type::Bool => OpCode::XorByte,
-                Type::Void | Type::Type => panic!("Invalid type for this operation"),
+                _ => panic!("Invalid type for this operation"),
             } as u8);
         }
         TokenType::Bar => {
             bytecode.push(match expr_type {
                 Type::Int => OpCode::OrInt,
                 Type::Bool => OpCode::OrByte,
-                Type::Void | Type::Type => panic!("Invalid type for this operation"),
+                _ => panic!("Invalid type for this operation"),
             } as u8);
         }
 
@@ -369,6 +1634,11 @@ fn handle_binary(
             bytecode.push(match &left.get_type() {
                 Some(Type::Int) => OpCode::EqualityInt,
                 Some(Type::Bool) => OpCode::EqualityByte,
+                Some(Type::Nullable(inner)) => match **inner {
+                    Type::Int => OpCode::EqualityNullableInt,
+                    Type::Bool => OpCode::EqualityNullableBool,
+                    _ => panic!("No other type should be possible."),
+                },
                 _ => panic!("No other type should be possible."),
             } as u8);
         }
@@ -376,6 +1646,11 @@ fn handle_binary(
             bytecode.push(match &left.get_type() {
                 Some(Type::Int) => OpCode::InequalityInt,
                 Some(Type::Bool) => OpCode::InequalityByte,
+                Some(Type::Nullable(inner)) => match **inner {
+                    Type::Int => OpCode::InequalityNullableInt,
+                    Type::Bool => OpCode::InequalityNullableBool,
+                    _ => panic!("No other type should be possible."),
+                },
                 _ => panic!("No other type should be possible."),
             } as u8);
         }
@@ -386,6 +1661,58 @@ fn handle_binary(
     }
 }
 
+// Handles variable declarations. If set_default is true, variables stored on the heap are set to a new empty
+// object, as the default handle does not refer to an object of the right type.
+fn handle_declaration(
+    bytecode: &mut Vec<u8>,
+    initialized_var: &Expression,
+    logs: &mut Vec<Log>,
+    var_list: &mut Vec<Token>,
+    set_default: bool,
+) {
+    if let Expression::Variable {
+        token,
+        ref expr_type,
+        ..
+    } = *initialized_var
+    {
+        if var_list.len() == 1 << (8 * BYTES_PER_VAR) {
+            // Equals rather than greater or equals so that this only happens once.
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::TooManyVariables(BYTES_PER_VAR)),
+                line_and_col: None, // TODO: Should this contain line and col of declaration of variable that pushes compiler past the limit?,
+                span: None,
+                notes: Vec::new(),
+            });
+        }
+        let index: usize = var_list.len();
+        var_list.push(token);
+        bytecode.push(variable_op(
+            expr_type.as_ref(),
+            [OpCode::AllocInt, OpCode::AllocBool, OpCode::AllocNullableInt, OpCode::AllocNullableBool],
+        ) as u8);
+
+        if set_default {
+            let default: Option<Vec<u8>> = match expr_type {
+                Some(Type::String) => Some(vec![OpCode::PushString as u8, 0, 0, 0, 0]),
+                Some(Type::Map(..)) => Some(vec![OpCode::NewMap as u8]),
+                Some(Type::List(_)) => Some(vec![OpCode::NewList as u8]),
+                // Results start as an error with an empty message.
+                Some(Type::Result(_)) => Some(vec![OpCode::PushString as u8, 0, 0, 0, 0, OpCode::ErrResult as u8]),
+                _ => None,
+            };
+            if let Some(mut default) = default {
+                bytecode.push(OpCode::PopInt as u8);
+                bytecode.append(&mut default);
+                bytecode.push(OpCode::SetInt as u8);
+                bytecode.append(&mut index.to_le_bytes()[0..BYTES_PER_VAR].to_vec());
+            }
+        }
+    } else {
+        panic!("variable declarations should always contain variables.")
+    }
+}
+
 // Handles literal expressions/tokens.
 fn handle_literal(bytecode: &mut Vec<u8>, token: Token) {
     match token.token_type {
@@ -401,18 +1728,817 @@ fn handle_literal(bytecode: &mut Vec<u8>, token: Token) {
             bytecode.push(OpCode::PushByte as u8);
             bytecode.push(0u8);
         }
+        TokenType::Null => {} // Null values have no size until they are cast to a nullable type.
         _ => panic!("all literals should have been accounted for"),
     }
 }
 
-// Converts a usize value to a list of bytes with a length of ptr_size.
-fn usize_to_ptr_size(value: usize, ptr_size: u8) -> Vec<u8> {
-    let usize_size_bytes: u32 = usize::BITS / 8;
-    let bytes: [u8; 8] = value.to_le_bytes();
-    let bytes: &[u8] = &bytes[..u32::min(u32::from(ptr_size), usize_size_bytes) as usize];
-    let mut bytes: Vec<u8> = bytes.to_vec();
-    while bytes.len() < ptr_size as usize {
-        bytes.push(0);
+// Handles casts between two types.
+fn handle_cast(bytecode: &mut Vec<u8>, type_in: &Type, type_out: &Type) {
+    if type_in == type_out {
+        return;
+    }
+    match (type_in, type_out) {
+        (Type::Int, Type::Bool) => bytecode.push(OpCode::IntToBool as u8),
+        (Type::Bool, Type::Int) => bytecode.push(OpCode::BoolToInt as u8),
+        (Type::Null, Type::Nullable(inner)) => {
+            // A null value is the default value of the inner type followed by a false flag.
+            match **inner {
+                Type::Int => {
+                    bytecode.push(OpCode::PushInt as u8);
+                    bytecode.append(&mut 0u32.to_le_bytes().to_vec());
+                }
+                Type::Bool => {
+                    bytecode.push(OpCode::PushByte as u8);
+                    bytecode.push(0u8);
+                }
+                _ => panic!("no other nullable types should be possible."),
+            }
+            bytecode.push(OpCode::PushByte as u8);
+            bytecode.push(0u8);
+        }
+        (_, Type::Nullable(inner)) if **inner == *type_in => {
+            bytecode.push(OpCode::PushByte as u8);
+            bytecode.push(1u8);
+        }
+        (Type::Err, Type::Result(_)) => {} // Errors are stored the same way for every value type.
+        _ => panic!("no other casts should be possible."),
+    }
+}
+
+// Gets the opcodes that pop a value of the given type from the stack.
+fn pop_ops(expr_type: &Type) -> Vec<u8> {
+    match expr_type {
+        // Strings, maps, lists, and results are stored on the stack as handles the size of an int.
+        Type::Int | Type::String | Type::Map(..) | Type::List(_) | Type::Result(_) | Type::Err => {
+            vec![OpCode::PopInt as u8]
+        }
+        Type::Bool => vec![OpCode::PopByte as u8],
+        Type::Nullable(inner) => {
+            let mut ops: Vec<u8> = vec![OpCode::PopByte as u8];
+            ops.append(&mut pop_ops(inner));
+            ops
+        }
+        Type::Null | Type::Void | Type::Type => Vec::new(),
+        Type::Generic(_) => panic!("type parameters should have been substituted"),
     }
+}
+
+// Chooses the variable opcode for the given type. The options are in the order int, bool, int?, bool?.
+// String, map, list, and result variables use the int opcodes as they only store a handle.
+fn variable_op(expr_type: Option<&Type>, options: [OpCode; 4]) -> OpCode {
+    match expr_type {
+        Some(Type::Int | Type::String | Type::Map(..) | Type::List(_) | Type::Result(_) | Type::Err) => options[0],
+        Some(Type::Bool) => options[1],
+        Some(Type::Nullable(inner)) => match **inner {
+            Type::Int => options[2],
+            Type::Bool => options[3],
+            _ => panic!("all variable types should have been accounted for"),
+        },
+        _ => panic!("all variable types should have been accounted for"),
+    }
+}
+
+// Gets the size (in bytes) of an element of a map or list.
+fn element_size(element_type: &Type) -> u8 {
+    match element_type {
+        Type::Int | Type::String => 4, // Lists of strings hold their handles.
+        Type::Bool => 1,
+        _ => panic!("map and list elements should only be ints, bools, and strings"),
+    }
+}
+
+// Gets the operand of a map instruction, which is the sizes (in bytes) of the keys and values of the map.
+fn map_sizes(map_type: &Type) -> Vec<u8> {
+    if let Type::Map(key, value) = map_type {
+        vec![element_size(key), element_size(value)]
+    } else {
+        panic!("only maps have key and value sizes")
+    }
+}
+
+// Gets the operand of a list instruction, which is the size (in bytes) of the elements of the list.
+fn list_size(list_type: &Type) -> u8 {
+    if let Type::List(element) = list_type {
+        element_size(element)
+    } else {
+        panic!("only lists have element sizes")
+    }
+}
+
+// Gets the operand of a result instruction, which is the size (in bytes) of the value of the result. Errors that
+// haven't been given a value type have no value.
+fn result_size(result_type: &Type) -> u8 {
+    match result_type {
+        Type::Result(value) => element_size(value),
+        Type::Err => 0,
+        _ => panic!("only results have value sizes"),
+    }
+}
+
+// Gets the size (in bytes) of a value of the given type on the stack.
+fn type_size(value_type: &Type) -> usize {
+    match value_type {
+        Type::Int | Type::String | Type::Map(..) | Type::List(_) | Type::Result(_) | Type::Err => 4,
+        Type::Bool => 1,
+        Type::Nullable(inner) => type_size(inner) + 1,
+        Type::Null | Type::Void | Type::Type => 0,
+        Type::Generic(_) => panic!("type parameters should have been substituted"),
+    }
+}
+
+// Generates the instances of the functions that were called, which are placed after a halt instruction at the end of
+// the rest of the code. Instances may call other instances, so this continues until every instance is generated.
+// The calls in all of the bytecode are then linked to the addresses of the instances, which are returned.
+fn generate_functions(
+    bytecode: &mut Vec<u8>,
+    expr: &Expression,
+    ptr_size: u8,
+    logs: &mut Vec<Log>,
+    instances: &mut Vec<Instance>,
+    tail_calls: bool,
+) -> Vec<usize> {
+    let mut declarations: Vec<&Expression> = Vec::new();
+    collect_functions(expr, &mut declarations);
+
+    bytecode.push(OpCode::Halt as u8);
+    let mut addresses: Vec<usize> = Vec::new();
+    let mut index: usize = 0;
+    while index < instances.len() {
+        addresses.push(bytecode.len());
+        let (token, type_args): Instance = instances[index].clone();
+        let declaration: Option<&Expression> = resolve_method(&declarations, token, &type_args).or_else(|| {
+            declarations.iter().copied().find(|declaration| {
+                matches!(declaration, Expression::FunctionDeclaration { token: other, .. } if *other == token)
+            })
+        });
+        let Some(Expression::FunctionDeclaration {
+            type_params,
+            params,
+            return_type,
+            body,
+            ..
+        }) = declaration
+        else {
+            panic!("called functions should have been declared")
+        };
+        let substitutions: HashMap<String, Type> =
+            type_params.iter().cloned().zip(type_args).collect();
+
+        let mut var_list: Vec<Token> = Vec::new();
+        let mut offset: usize = 0;
+        for param in params {
+            if let Expression::Variable {
+                token,
+                expr_type: Some(expr_type),
+                ..
+            } = param.substitute_types(&substitutions)
+            {
+                let size: usize = type_size(&expr_type);
+                bytecode.push(OpCode::BindParam as u8);
+                bytecode.append(&mut usize_to_ptr_size(offset, ptr_size));
+                bytecode.push(u8::try_from(size).expect("values are at most 5 bytes"));
+                var_list.push(token);
+                offset += size;
+            }
+        }
+        let body: Expression = body.substitute_types(&substitutions);
+        let return_size: u8 =
+            u8::try_from(type_size(&return_type.substitute(&substitutions))).expect("values are at most 5 bytes");
+        if let Some((statements, args)) = split_tail_call(&body, &instances[index]).filter(|_| tail_calls) {
+            let mut args_size: usize = 0;
+            for statement in statements {
+                bytecode.append(&mut statement_location(statement, ptr_size));
+                bytecode.append(&mut generate_bytecode(statement, ptr_size, logs, &mut var_list, instances));
+            }
+            for expr in args {
+                bytecode.append(&mut generate_bytecode(expr, ptr_size, logs, &mut var_list, instances));
+            }
+            for arg in args {
+                args_size += type_size(&arg.get_type().expect("any \"None\" should have a parsing error"));
+            }
+            bytecode.push(OpCode::TailCall as u8);
+            bytecode.append(&mut usize_to_ptr_size(index, ptr_size)); // Replaced by the address when linking.
+            bytecode.append(&mut usize_to_ptr_size(args_size, ptr_size));
+            bytecode.push(return_size);
+        } else {
+            bytecode.append(&mut generate_bytecode(&body, ptr_size, logs, &mut var_list, instances));
+        }
+        bytecode.push(OpCode::Return as u8);
+        bytecode.push(return_size);
+        index += 1;
+    }
+    link_calls(bytecode, ptr_size, &addresses);
+    addresses
+}
+
+// Adds the name of each instance after the functions, so that the VM can name the functions in a stack trace. Generic
+// functions are named with their type arguments, and methods with the type they are called on.
+fn append_function_names(
+    bytecode: &mut Vec<u8>,
+    expr: &Expression,
+    source: &str,
+    ptr_size: u8,
+    (instances, addresses): (&[Instance], &[usize]),
+) {
+    let mut declarations: Vec<&Expression> = Vec::new();
+    collect_functions(expr, &mut declarations);
+    for (instance, address) in instances.iter().zip(addresses) {
+        let name: String = instance_name(&declarations, source, instance);
+        bytecode.push(OpCode::FunctionName as u8);
+        bytecode.append(&mut usize_to_ptr_size(*address, ptr_size));
+        bytecode.extend_from_slice(&u32::try_from(name.len()).expect("names are shorter than the source").to_le_bytes());
+        bytecode.extend_from_slice(name.as_bytes());
+    }
+}
+
+// Gets the parts of the size report for the statements at the top level, which are named after their lines of source
+// code.
+fn statement_parts(source: &str, statements: &[(usize, &Expression)]) -> Vec<Part> {
+    statements
+        .iter()
+        .map(|(_, statement)| {
+            let token: Option<Token> = statement.first_token();
+            (
+                token.map_or_else(String::new, |token| source_snippet(source, token)),
+                token.map(|token| (token.line, token.col)),
+            )
+        })
+        .collect()
+}
+
+// Gets the parts of the size report for the instances, which are named after them and start where their
+// declarations do.
+fn instance_parts(expr: &Expression, source: &str, instances: &[Instance]) -> Vec<Part> {
+    let mut declarations: Vec<&Expression> = Vec::new();
+    collect_functions(expr, &mut declarations);
+    instances
+        .iter()
+        .map(|instance| {
+            let token: Token = resolve_method(&declarations, instance.0, &instance.1)
+                .and_then(Expression::first_token)
+                .unwrap_or(instance.0);
+            (format!("fn {}", instance_name(&declarations, source, instance)), Some((token.line, token.col)))
+        })
+        .collect()
+}
+
+/// Gets the name of an instance, which has its type arguments if it's generic, or the type it's called on if it's a
+/// method.
+#[must_use]
+pub fn instance_name(declarations: &[&Expression], source: &str, (token, type_args): &Instance) -> String {
+    let type_names: Vec<String> = type_args.iter().map(Type::name).collect();
+    if resolve_method(declarations, *token, type_args).is_some() {
+        format!("{}.{}", type_names[0], token.to_string(source))
+    } else if type_names.is_empty() {
+        token.to_string(source)
+    } else {
+        format!("{}<{}>", token.to_string(source), type_names.join(", "))
+    }
+}
+
+/// Splits a function body that ends with a call to its own instance into the statements before the call and the
+/// arguments of the call, so the call can reuse the frame of the function. Groupings around the call are looked
+/// through, and method calls are matched the same way they are instanced. Returns None if the body doesn't end with
+/// such a call.
+#[must_use]
+#[allow(clippy::missing_panics_doc)] // Should never actually panic.
+pub fn split_tail_call<'e>(
+    body: &'e Expression,
+    instance: &Instance,
+) -> Option<TailCall<'e>> {
+    let Expression::ExpressionList { list } = body else {
+        return None;
+    };
+    let (mut last, statements): (&Expression, &[Box<Expression>]) =
+        list.split_last().map(|(last, statements)| (last.as_ref(), statements))?;
+    while let Expression::Grouping { expr, .. } = last {
+        last = expr;
+    }
+    let (call, args): (Instance, &[Box<Expression>]) = match last {
+        Expression::FunctionCall {
+            declaration,
+            args,
+            type_args,
+            ..
+        } => ((*declaration, type_args.clone()), args),
+        Expression::MethodCall { method, args, .. } => (
+            (*method, vec![args[0].get_type().expect("any \"None\" should have a parsing error")]),
+            args,
+        ),
+        _ => return None,
+    };
+    (call == *instance).then_some((statements, args))
+}
+
+/// Finds the method of an impl block that implements a trait method for the type it is called on, which is the only
+/// type argument of a method call's instance. Returns None if the token is not a trait method.
+#[must_use]
+pub fn resolve_method<'e>(declarations: &[&'e Expression], method: Token, type_args: &[Type]) -> Option<&'e Expression> {
+    let (trait_token, position): (Token, usize) = declarations.iter().find_map(|declaration| match declaration {
+        Expression::TraitDeclaration { token, methods } => methods
+            .iter()
+            .position(|other| matches!(**other, Expression::FunctionDeclaration { token: other, .. } if other == method))
+            .map(|position| (*token, position)),
+        _ => None,
+    })?;
+    declarations.iter().find_map(|declaration| match declaration {
+        Expression::ImplDeclaration {
+            trait_token: other,
+            impl_type,
+            methods,
+        } if *other == trait_token && type_args.first() == Some(impl_type) => Some(&*methods[position]),
+        _ => None,
+    })
+}
+
+/// Finds the function, trait, and impl declarations in the code, including those declared in the bodies of other
+/// functions.
+pub fn collect_functions<'e>(expr: &'e Expression, declarations: &mut Vec<&'e Expression>) {
+    match expr {
+        Expression::ExpressionList { list } => {
+            for expr in list {
+                collect_functions(expr, declarations);
+            }
+        }
+        Expression::TraitDeclaration { .. } => declarations.push(expr),
+        Expression::ImplDeclaration { methods, .. } => {
+            declarations.push(expr);
+            for method in methods {
+                collect_functions(method, declarations);
+            }
+        }
+        Expression::Statement { expr } => collect_functions(expr, declarations),
+        Expression::FunctionDeclaration { body, .. } => {
+            declarations.push(expr);
+            collect_functions(body, declarations);
+        }
+        _ => {}
+    }
+}
+
+// Replaces the instance indices in call instructions with the addresses of the instances.
+fn link_calls(bytecode: &mut [u8], ptr_size: u8, addresses: &[usize]) {
+    let ptr_bytes: usize = ptr_size as usize;
+    for (op, index) in instructions(bytecode, ptr_bytes) {
+        if matches!(op, OpCode::Call | OpCode::TailCall) {
+            let index: usize = index + 1;
+            let mut bytes: [u8; (usize::BITS / 8) as usize] = [0; (usize::BITS / 8) as usize];
+            let length: usize = usize::min(ptr_bytes, bytes.len());
+            bytes[..length].copy_from_slice(&bytecode[index..index + length]);
+            let address: Vec<u8> = usize_to_ptr_size(addresses[usize::from_le_bytes(bytes)], ptr_size);
+            bytecode[index..index + ptr_bytes].copy_from_slice(&address);
+        }
+    }
+}
+
+// Moves the literals that are pushed more than once into a constant pool, replacing their pushes with `LoadConst`
+// instructions. The constants go between the code and the debug info.
+fn pool_constants(bytecode: &[u8], ptr_size: u8, offsets: &mut [usize]) -> Vec<u8> {
+    let instructions: Vec<Instruction> = instruction_bytes(bytecode, ptr_size.into());
+    let pool: Vec<&[u8]> = choose_constants(&instructions);
+    if pool.is_empty() {
+        return bytecode.to_vec();
+    }
+    // The literals include their push opcodes, so only pushes can match them.
+    let (mut pooled, new_offsets): (Vec<u8>, HashMap<usize, usize>) =
+        rewrite(bytecode, ptr_size, &instructions, |(_, _, bytes)| {
+            let index: usize = pool.iter().position(|literal| literal == bytes)?;
+            let index: u16 = u16::try_from(index).expect("the pool has at most 65536 constants");
+            Some([&[OpCode::LoadConst as u8], &index.to_le_bytes()[..]].concat())
+        });
+    move_offsets(offsets, &new_offsets);
+    let pool_bytes: Vec<u8> = pool.iter().flat_map(|literal| [&[OpCode::Constant as u8], *literal].concat()).collect();
+    let pool_start: usize = first_offset(&pooled, &[OpCode::FunctionName, OpCode::LineTable]);
+    pooled.splice(pool_start..pool_start, pool_bytes);
+    pooled
+}
+
+// Replaces the location markers generated before statements and instructions that can fail with a line table at the
+// end of the bytecode, keyed by the offsets of the instructions. The markers are only removed if the errors aren't
+// detailed or the instruction set level has no line tables, so runtime errors are reported without their locations.
+// The offsets are moved along with the instructions at them.
+fn build_line_table(bytecode: &[u8], options: CompilerOptions, offsets: &mut [usize]) -> Vec<u8> {
+    let ptr_size: usize = options.target.ptr_size.into();
+    let instructions: Vec<Instruction> = instruction_bytes(bytecode, ptr_size);
+    let (mut stripped, new_offsets): (Vec<u8>, HashMap<usize, usize>) =
+        rewrite(bytecode, options.target.ptr_size, &instructions, |(op, ..)| (*op == OpCode::Location).then(Vec::new));
+    move_offsets(offsets, &new_offsets);
+    let mut entries: Vec<&Instruction> = instructions.iter().filter(|(op, ..)| *op == OpCode::Location).collect();
+    // A statement that starts with an instruction that can fail has two markers before it, and the later one, which is
+    // that of the instruction, is kept.
+    entries.reverse();
+    entries.dedup_by_key(|(_, offset, _)| new_offsets[offset]);
+    entries.reverse();
+    if entries.is_empty() || !options.detailed_err || options.target.features < IsaLevel::V10 {
+        return stripped;
+    }
+    stripped.push(OpCode::LineTable as u8);
+    stripped.extend_from_slice(&u32::try_from(entries.len()).expect("there are fewer entries than bytes").to_le_bytes());
+    for (_, offset, bytes) in entries {
+        // A marker is removed, so its new offset is that of the instruction after it.
+        stripped.append(&mut usize_to_ptr_size(new_offsets[offset], options.target.ptr_size));
+        stripped.extend_from_slice(&bytes[1..]);
+    }
+    stripped
+}
+
+// Rebuilds the bytecode with some of its instructions replaced, which is used by the passes that run on the generated
+// bytecode. The replacement of each instruction is given by replace, which returns None to keep the instruction. The
+// addresses in calls, jumps, and function names are moved to match the new offsets, which are returned by the offsets
+// they replace. A removed instruction is given the offset of the instruction after it, and the end of the bytecode is
+// also given its new offset.
+fn rewrite(
+    bytecode: &[u8],
+    ptr_size: u8,
+    instructions: &[Instruction],
+    replace: impl Fn(&Instruction) -> Option<Vec<u8>>,
+) -> (Vec<u8>, HashMap<usize, usize>) {
+    let ptr_bytes: usize = ptr_size as usize;
+    let replacements: Vec<Option<Vec<u8>>> = instructions.iter().map(replace).collect();
+    let mut new_offsets: HashMap<usize, usize> = HashMap::new();
+    let mut new_offset: usize = code_start(bytecode);
+    for ((_, offset, bytes), replacement) in instructions.iter().zip(&replacements) {
+        new_offsets.insert(*offset, new_offset);
+        new_offset += replacement.as_ref().map_or(bytes.len(), Vec::len);
+    }
+    new_offsets.insert(bytecode.len(), new_offset);
+
+    let mut rewritten: Vec<u8> = bytecode[..code_start(bytecode)].to_vec();
+    for ((op, offset, bytes), replacement) in instructions.iter().zip(replacements) {
+        if let Some(mut replacement) = replacement {
+            rewritten.append(&mut replacement);
+            continue;
+        }
+        let operand: &[u8] = &bytes[1..];
+        rewritten.push(*op as u8);
+        match op {
+            OpCode::Call | OpCode::TailCall | OpCode::FunctionName => {
+                rewritten.append(&mut usize_to_ptr_size(new_offsets[&read_ptr(operand, ptr_bytes)], ptr_size));
+                rewritten.extend_from_slice(&operand[ptr_bytes..]);
+            }
+            // Jumps are counted from the end of the jump, which keeps its length.
+            OpCode::JumpIfOk => {
+                let target: usize = new_offsets[&(offset + bytes.len() + read_ptr(operand, ptr_bytes))];
+                rewritten.append(&mut usize_to_ptr_size(target - new_offsets[offset] - bytes.len(), ptr_size));
+            }
+            _ => rewritten.extend_from_slice(operand),
+        }
+    }
+    (rewritten, new_offsets)
+}
+
+// Moves offsets in the bytecode to where the instructions at them are after it was rewritten.
+fn move_offsets(offsets: &mut [usize], new_offsets: &HashMap<usize, usize>) {
+    for offset in offsets {
+        *offset = new_offsets[offset];
+    }
+}
+
+// Computes each repeated operand once. When both operands of an operation are the same pure expression, as in
+// `(a * b) + (a * b)`, the instructions of the second operand are replaced by a `Dup` of the value of the first. Pure
+// expressions only read variables and operate on ints and bools, so computing one once leaves out no side effects, and
+// an error such as a division by zero still happens in the first operand. Operations are checked from last to first,
+// so that an operand that is replaced isn't also searched for repeats.
+fn eliminate_common_subexpressions(bytecode: &[u8], ptr_size: u8, offsets: &mut [usize]) -> Vec<u8> {
+    let instructions: Vec<Instruction<'_>> = instruction_bytes(bytecode, usize::from(ptr_size));
+    let mut replacements: HashMap<usize, Vec<u8>> = HashMap::new();
+    for index in (0..instructions.len()).rev() {
+        let (op, offset, _) = instructions[index];
+        let Some(size) = operand_size(op).filter(|_| !replacements.contains_key(&offset)) else {
+            continue;
+        };
+        // The location marker of an operation that can fail comes right before it, and is kept with it.
+        let end: usize = instructions[..index]
+            .iter()
+            .rposition(|(op, _, _)| *op != OpCode::Location)
+            .map_or(0, |last| last + 1);
+        let Some(right) = operand_start(&instructions[..end], size) else {
+            continue;
+        };
+        let Some(left) = operand_start(&instructions[..right], size) else {
+            continue;
+        };
+        if same_instructions(&instructions[left..right], &instructions[right..end]) {
+            replacements.insert(instructions[right].1, vec![OpCode::Dup as u8, size]);
+            for (_, offset, _) in &instructions[right + 1..end] {
+                replacements.insert(*offset, Vec::new());
+            }
+        }
+    }
+    let (rewritten, new_offsets): (Vec<u8>, HashMap<usize, usize>) =
+        rewrite(bytecode, ptr_size, &instructions, |(_, offset, _)| replacements.get(offset).cloned());
+    move_offsets(offsets, &new_offsets);
+    rewritten
+}
+
+// Gets the size of both operands of an operation on two values of the same type, or None for other instructions.
+fn operand_size(op: OpCode) -> Option<u8> {
+    match op {
+        OpCode::AddInt
+        | OpCode::SubtractInt
+        | OpCode::MultiplyInt
+        | OpCode::DivideInt
+        | OpCode::ModuloInt
+        | OpCode::MinInt
+        | OpCode::MaxInt
+        | OpCode::PowInt
+        | OpCode::AndInt
+        | OpCode::XorInt
+        | OpCode::OrInt
+        | OpCode::LeftShiftInt
+        | OpCode::RightShiftInt
+        | OpCode::LessInt
+        | OpCode::LessEqualInt
+        | OpCode::GreaterInt
+        | OpCode::GreaterEqualInt
+        | OpCode::EqualityInt
+        | OpCode::InequalityInt => Some(4),
+        OpCode::AndByte | OpCode::XorByte | OpCode::OrByte | OpCode::EqualityByte | OpCode::InequalityByte => Some(1),
+        _ => None,
+    }
+}
+
+// Gets the number of bytes a pure instruction pops and pushes, or None if the instruction isn't pure. Strings are left
+// out, as each push of a string literal creates a new string.
+#[allow(clippy::match_same_arms)] // Opcodes are grouped the same way as in the OpCode enum.
+fn pure_effect(op: OpCode) -> Option<(usize, usize)> {
+    Some(match op {
+        OpCode::PushInt | OpCode::GetInt => (0, 4),
+        OpCode::PushByte | OpCode::GetBool => (0, 1),
+        OpCode::Location => (0, 0),
+        OpCode::IntToBool => (4, 1),
+        OpCode::BoolToInt => (1, 4),
+        OpCode::MinusInt | OpCode::ComplementInt | OpCode::AbsInt | OpCode::SqrtInt => (4, 4),
+        OpCode::ClampInt => (12, 4),
+        OpCode::AddInt
+        | OpCode::SubtractInt
+        | OpCode::MultiplyInt
+        | OpCode::DivideInt
+        | OpCode::ModuloInt
+        | OpCode::MinInt
+        | OpCode::MaxInt
+        | OpCode::PowInt
+        | OpCode::AndInt
+        | OpCode::XorInt
+        | OpCode::OrInt
+        | OpCode::LeftShiftInt
+        | OpCode::RightShiftInt => (8, 4),
+        OpCode::LessInt
+        | OpCode::LessEqualInt
+        | OpCode::GreaterInt
+        | OpCode::GreaterEqualInt
+        | OpCode::EqualityInt
+        | OpCode::InequalityInt => (8, 1),
+        OpCode::Not => (1, 1),
+        OpCode::AndByte | OpCode::XorByte | OpCode::OrByte | OpCode::EqualityByte | OpCode::InequalityByte => (2, 1),
+        _ => return None,
+    })
+}
+
+// Finds the start of the pure expression that the instructions end with, which leaves a value of the given size.
+// Returns None if the instructions don't end with one.
+fn operand_start(instructions: &[Instruction], size: u8) -> Option<usize> {
+    let mut needed: usize = usize::from(size);
+    for (index, (op, _, _)) in instructions.iter().enumerate().rev() {
+        let (pops, pushes): (usize, usize) = pure_effect(*op)?;
+        if pushes > needed {
+            return None;
+        }
+        needed = needed - pushes + pops;
+        if needed == 0 {
+            return Some(index);
+        }
+    }
+    None
+}
+
+// Returns whether or not two expressions have the same instructions, ignoring where their errors are reported.
+fn same_instructions<'b>(first: &[Instruction<'b>], second: &[Instruction<'b>]) -> bool {
+    let without_locations = |instructions: &[Instruction<'b>]| -> Vec<&'b [u8]> {
+        instructions
+            .iter()
+            .filter(|(op, _, _)| *op != OpCode::Location)
+            .map(|(_, _, bytes)| *bytes)
+            .collect()
+    };
+    without_locations(first) == without_locations(second)
+}
+
+// Fuses each operation on two ints with the instructions that push its operands, when those read a variable or push a
+// literal, so that `a + b * c` runs as two `RegisterOp` instructions instead of five. An operation whose right operand
+// is a fused operation with no operands on the stack can still read its left operand from a variable or the bytecode,
+// as the fused operation can't change any variables. Nothing is fused across a jump or call target, as code that
+// jumps there expects the operands on the stack.
+fn use_registers(bytecode: &[u8], ptr_size: u8, offsets: &mut [usize]) -> Vec<u8> {
+    let instructions: Vec<Instruction<'_>> = instruction_bytes(bytecode, usize::from(ptr_size));
+    let targets: Vec<usize> = branch_targets(&instructions, usize::from(ptr_size));
+    let mut replacements: HashMap<usize, Vec<u8>> = HashMap::new();
+    // The indices of the instructions that are kept, not counting location markers, along with whether or not each
+    // one is a fused operation with no operands on the stack.
+    let mut kept: Vec<(usize, bool)> = Vec::new();
+    for (index, (op, offset, _)) in instructions.iter().enumerate() {
+        if *op == OpCode::Location {
+            continue;
+        }
+        let Some(fused) = fuse_operands(&instructions, &kept, index, &targets) else {
+            kept.push((index, false));
+            continue;
+        };
+        for removed in fused.removed {
+            replacements.insert(instructions[removed].1, Vec::new());
+        }
+        replacements.insert(*offset, fused.bytes);
+        kept.truncate(kept.len() - fused.used);
+        kept.push((index, !fused.reads_stack));
+    }
+    let (rewritten, new_offsets): (Vec<u8>, HashMap<usize, usize>) =
+        rewrite(bytecode, ptr_size, &instructions, |(_, offset, _)| replacements.get(offset).cloned());
+    move_offsets(offsets, &new_offsets);
+    rewritten
+}
+
+// An operation fused with the instructions that push its operands.
+struct FusedOp {
+    // The number of kept instructions before the operation that push its operands.
+    used: usize,
+    // The indices of the instructions that are removed.
+    removed: Vec<usize>,
+    // The `RegisterOp` that replaces the operation.
+    bytes: Vec<u8>,
+    // Whether or not any operand is still popped from the stack.
+    reads_stack: bool,
+}
+
+// Chooses the sources of the operands of the operation at the index, given the instructions kept before it. Returns
+// None if no operand can be fused, or if an instruction from the first operand to the operation is a target.
+fn fuse_operands(
+    instructions: &[Instruction],
+    kept: &[(usize, bool)],
+    index: usize,
+    targets: &[usize],
+) -> Option<FusedOp> {
+    instructions[index].0.register_result_size()?;
+    let (&(right, right_is_fused), before) = kept.split_last()?;
+    let right_source: Option<(OperandSource, [u8; 4])> = register_source(&instructions[right]);
+    let left_source: Option<(OperandSource, [u8; 4])> =
+        before.last().and_then(|(left, _)| register_source(&instructions[*left]));
+    if right_source.is_none() && !(right_is_fused && left_source.is_some()) {
+        return None;
+    }
+    let used: usize = 1 + usize::from(left_source.is_some());
+    let first: usize = kept[kept.len() - used].0;
+    if instructions[first..=index].iter().any(|(_, offset, _)| targets.contains(offset)) {
+        return None;
+    }
+    let removed: Vec<usize> = [(right, right_source), (first, left_source)]
+        .into_iter()
+        .filter(|(_, source)| source.is_some())
+        .map(|(index, _)| index)
+        .collect();
+    let (left, right): ((OperandSource, [u8; 4]), (OperandSource, [u8; 4])) = (
+        left_source.unwrap_or((OperandSource::Stack, [0; 4])),
+        right_source.unwrap_or((OperandSource::Stack, [0; 4])),
+    );
+    let sources: u8 = left.0 as u8 | (right.0 as u8) << 4;
+    let mut bytes: Vec<u8> = vec![OpCode::RegisterOp as u8, instructions[index].0 as u8, sources];
+    bytes.extend_from_slice(&left.1);
+    bytes.extend_from_slice(&right.1);
+    Some(FusedOp {
+        used,
+        removed,
+        bytes,
+        reads_stack: left.0 == OperandSource::Stack || right.0 == OperandSource::Stack,
+    })
+}
+
+// Gets where a `RegisterOp` can read the value pushed by an instruction, or None if it must be left on the stack.
+fn register_source(instruction: &Instruction) -> Option<(OperandSource, [u8; 4])> {
+    let (op, _, bytes) = instruction;
+    let mut value: [u8; 4] = [0; 4];
+    match op {
+        OpCode::PushInt => {
+            value.copy_from_slice(&bytes[1..5]);
+            Some((OperandSource::Immediate, value))
+        }
+        OpCode::GetInt => {
+            value[..BYTES_PER_VAR].copy_from_slice(&bytes[1..=BYTES_PER_VAR]);
+            Some((OperandSource::Register, value))
+        }
+        _ => None,
+    }
+}
+
+// Gets the offsets that calls, jumps, and function names refer to.
+fn branch_targets(instructions: &[Instruction], ptr_size: usize) -> Vec<usize> {
+    instructions
+        .iter()
+        .filter_map(|(op, offset, bytes)| match op {
+            OpCode::Call | OpCode::TailCall | OpCode::FunctionName => Some(read_ptr(&bytes[1..], ptr_size)),
+            OpCode::JumpIfOk => Some(offset + bytes.len() + read_ptr(&bytes[1..], ptr_size)),
+            _ => None,
+        })
+        .collect()
+}
+
+// Chooses the literals to put in the constant pool, in the order they are first pushed. A literal is only chosen if
+// that makes the bytecode smaller, as each load takes 3 bytes and the constant takes the push instruction and a
+// `Constant` opcode. The pool can hold up to 65536 constants.
+fn choose_constants<'b>(instructions: &[Instruction<'b>]) -> Vec<&'b [u8]> {
+    let mut literals: Vec<(&[u8], usize)> = Vec::new();
+    for (op, _, bytes) in instructions {
+        if matches!(op, OpCode::PushInt | OpCode::PushString) {
+            match literals.iter_mut().find(|(literal, _)| literal == bytes) {
+                Some((_, uses)) => *uses += 1,
+                None => literals.push((bytes, 1)),
+            }
+        }
+    }
+    literals
+        .into_iter()
+        .filter(|(literal, uses)| uses * 3 + literal.len() + 1 < uses * literal.len())
+        .map(|(literal, _)| literal)
+        .take(usize::from(u16::MAX) + 1)
+        .collect()
+}
+
+// Reads a little-endian number with a length of ptr_size from the start of an operand.
+fn read_ptr(operand: &[u8], ptr_size: usize) -> usize {
+    let mut bytes: [u8; (usize::BITS / 8) as usize] = [0; (usize::BITS / 8) as usize];
+    let length: usize = usize::min(ptr_size, bytes.len());
+    bytes[..length].copy_from_slice(&operand[..length]);
+    usize::from_le_bytes(bytes)
+}
+
+// Gets the lowest instruction set level that supports every instruction in the bytecode.
+fn required_isa_level(bytecode: &[u8], ptr_size: usize) -> IsaLevel {
+    instructions(bytecode, ptr_size)
+        .into_iter()
+        .map(|(op, _)| op.isa_level())
+        .max()
+        .unwrap_or(IsaLevel::V1)
+}
+
+// Gets each instruction generated by the compiler along with its offset.
+fn instructions(bytecode: &[u8], ptr_size: usize) -> Vec<(OpCode, usize)> {
+    let mut instructions: Vec<(OpCode, usize)> = Vec::new();
+    let mut index: usize = code_start(bytecode);
+    while index < bytecode.len() {
+        let op: Option<OpCode> = num_traits::FromPrimitive::from_u8(bytecode[index]);
+        let op: OpCode = op.expect("the compiler only generates valid opcodes");
+        instructions.push((op, index));
+        index += 1;
+        index += op
+            .operand_length(ptr_size, &bytecode[index..])
+            .expect("the compiler only generates complete operands");
+    }
+    instructions
+}
+
+// Gets each instruction generated by the compiler along with its offset and its bytes.
+fn instruction_bytes(bytecode: &[u8], ptr_size: usize) -> Vec<Instruction<'_>> {
+    let offsets: Vec<(OpCode, usize)> = instructions(bytecode, ptr_size);
+    offsets
+        .iter()
+        .enumerate()
+        .map(|(position, (op, offset))| {
+            let end: usize = offsets.get(position + 1).map_or(bytecode.len(), |(_, next)| *next);
+            (*op, *offset, &bytecode[*offset..end])
+        })
+        .collect()
+}
+
+// Gets a marker giving the line and column of the token to the instruction that follows it, which is generated before
+// each instruction that can fail at runtime. The markers are moved into the line table once the code is generated.
+fn location(token: Token, ptr_size: u8) -> Vec<u8> {
+    let mut marker: Vec<u8> = vec![OpCode::Location as u8];
+    marker.append(&mut usize_to_ptr_size(token.line, ptr_size));
+    marker.append(&mut usize_to_ptr_size(token.col, ptr_size));
+    marker
+}
+
+// Gets the marker of the line a statement starts on, which is generated before its first instruction so that a
+// breakpoint can be set on any line with a statement. Statements with no tokens of their own get no marker.
+fn statement_location(statement: &Expression, ptr_size: u8) -> Vec<u8> {
+    statement
+        .first_token()
+        .map_or_else(Vec::new, |token| location(token, ptr_size))
+}
+
+// Gets the operand of a call to a host function, which is the number of arguments followed by the length of the name
+// of the host function and the bytes of its name.
+fn host_call_operand(arg_count: usize, name: &str) -> Vec<u8> {
+    let mut operand: Vec<u8> = vec![arg_count.try_into().expect("host functions take at most 255 arguments")];
+    operand.extend_from_slice(&u32::try_from(name.len()).expect("names fit in a u32").to_le_bytes());
+    operand.extend_from_slice(name.as_bytes());
+    operand
+}
+
+// Converts a usize value to a list of bytes with a length of ptr_size. The value is widened to 64 bits first, so the
+// bytes are the same whatever the pointer size of the host.
+fn usize_to_ptr_size(value: usize, ptr_size: u8) -> Vec<u8> {
+    let bytes: [u8; 8] = (value as u64).to_le_bytes();
+    let mut bytes: Vec<u8> = bytes[..usize::min(ptr_size.into(), bytes.len())].to_vec();
+    bytes.resize(ptr_size.into(), 0);
     bytes
 }
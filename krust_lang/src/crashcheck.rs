@@ -0,0 +1,198 @@
+//! The module for checking a corpus of inputs for crashes in the compiler and VM. Inputs found by fuzzing can be saved
+//! to a directory so that every input that once crashed krust is checked again from then on.
+
+use crate::{compiler, events, lexer, parser, util::log, verifier, vm};
+use compiler::{compile, CompilerOptions, CompilerOutput};
+use events::Stage;
+use lexer::{lex, LexerOutput};
+use log::{is_error, Log};
+use parser::{parse, ParserOutput, PARSER_STACK_BYTES};
+use verifier::{verify, VerifierOutput};
+use vm::Vm;
+
+use std::any::Any;
+use std::fs::{read, read_dir};
+use std::io;
+use std::panic::{catch_unwind, set_hook, take_hook, AssertUnwindSafe, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long an input can take to compile and run before it is reported as a hang, if no other limit is given.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The name of the threads that inputs are checked on, whose panics aren't printed.
+const THREAD_NAME: &str = "crashcheck";
+
+// A function that is called when a thread panics.
+type PanicHook = Box<dyn Fn(&PanicHookInfo<'_>) + Send + Sync>;
+
+/// A way that an input crashed krust.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Crash {
+    /// A stage panicked with the given message.
+    Panic { stage: Stage, message: String },
+    /// A stage was still running once the time limit was reached.
+    Hang { stage: Stage },
+    /// The compiler generated bytecode that the verifier rejected.
+    VerifierFailure { logs: Vec<Log> },
+}
+
+/// The result of checking a single file in a corpus.
+pub struct CaseResult {
+    pub path: PathBuf,
+    /// How the input crashed krust, if it did. Inputs that fail to compile or have runtime errors haven't crashed.
+    pub crash: Option<Crash>,
+}
+
+// A message sent from the thread checking an input.
+enum Message {
+    Started(Stage),
+    Finished(Option<Crash>),
+    Panicked(String),
+}
+
+/// Compiles the source code, verifies the bytecode, and runs it, reporting any crash. The input is checked on another
+/// thread so that hangs can be detected. A thread that hangs is left running, as it can't be stopped from outside.
+/// Panics are reported as crashes instead of being printed with a backtrace.
+#[must_use]
+pub fn check_input(source: String, options: CompilerOptions, timeout: Duration) -> Option<Crash> {
+    without_panic_output(|| check_on_thread(source, options, timeout))
+}
+
+// Checks the input on a thread of its own, waiting for it until the timeout.
+fn check_on_thread(source: String, options: CompilerOptions, timeout: Duration) -> Option<Crash> {
+    let (sender, receiver): (Sender<Message>, Receiver<Message>) = channel();
+    let spawned: io::Result<thread::JoinHandle<()>> = thread::Builder::new()
+        .name(THREAD_NAME.to_string())
+        .stack_size(PARSER_STACK_BYTES)
+        .spawn(move || {
+            let result: thread::Result<Option<Crash>> =
+                catch_unwind(AssertUnwindSafe(|| run_stages(&source, options, &sender)));
+            let _ = sender.send(match result {
+                Ok(crash) => Message::Finished(crash),
+                Err(payload) => Message::Panicked(panic_message(&*payload)),
+            });
+        });
+    if let Err(error) = spawned {
+        return Some(Crash::Panic {
+            stage: Stage::Lex,
+            message: format!("could not start a thread: {error}"),
+        });
+    }
+
+    let deadline: Instant = Instant::now() + timeout;
+    let mut stage: Stage = Stage::Lex;
+    loop {
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(Message::Started(next)) => stage = next,
+            Ok(Message::Finished(crash)) => return crash,
+            Ok(Message::Panicked(message)) => return Some(Crash::Panic { stage, message }),
+            Err(RecvTimeoutError::Timeout) => return Some(Crash::Hang { stage }),
+            Err(RecvTimeoutError::Disconnected) => {
+                return Some(Crash::Panic {
+                    stage,
+                    message: "the thread stopped without a result".to_string(),
+                });
+            }
+        }
+    }
+}
+
+/// Checks every file in the directory and its subdirectories, in order of their paths. Files that aren't valid UTF-8
+/// are checked with the invalid bytes replaced, as fuzzers often produce them.
+///
+/// # Errors
+/// Returns an error if the directory or one of its subdirectories can't be read.
+pub fn check_corpus(
+    dir: &Path,
+    options: CompilerOptions,
+    timeout: Duration,
+) -> io::Result<Vec<CaseResult>> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    collect_files(dir, &mut paths)?;
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let source: String = String::from_utf8_lossy(&read(&path)?).into_owned();
+            Ok(CaseResult {
+                crash: check_input(source, options, timeout),
+                path,
+            })
+        })
+        .collect()
+}
+
+// Runs each stage on the source code, reporting the start of each one so a hang or panic can be traced to it.
+fn run_stages(source: &str, options: CompilerOptions, sender: &Sender<Message>) -> Option<Crash> {
+    let start = |stage: Stage| {
+        let _ = sender.send(Message::Started(stage));
+    };
+    start(Stage::Lex);
+    let lex_output: LexerOutput = lex(source);
+    start(Stage::Parse);
+    let parser_output: ParserOutput = parse(lex_output);
+    start(Stage::Compile);
+    let compiler_output: CompilerOutput = compile(parser_output, options);
+    let bytecode: Vec<u8> = compiler_output.bytecode?;
+    start(Stage::Verify);
+    let verifier_output: VerifierOutput = verify(&bytecode);
+    if is_error(&verifier_output.logs) {
+        return Some(Crash::VerifierFailure {
+            logs: verifier_output.logs,
+        });
+    }
+    start(Stage::Run);
+    let mut vm: Vm = Vm::new(bytecode);
+    vm.set_output(io::sink());
+    vm.set_error_output(io::sink());
+    let _ = vm.run();
+    None
+}
+
+// Runs the check with a panic hook that prints nothing for panics on the threads checking inputs, and puts the previous
+// hook back afterwards. Panics on any other thread still go to the previous hook.
+fn without_panic_output<T>(check: impl FnOnce() -> T) -> T {
+    let previous: Arc<PanicHook> = Arc::new(take_hook());
+    let fallback: Arc<PanicHook> = Arc::clone(&previous);
+    set_hook(Box::new(move |info| {
+        if thread::current().name() != Some(THREAD_NAME) {
+            fallback(info);
+        }
+    }));
+    let result: T = check();
+    // Dropping the silent hook drops its handle to the previous one.
+    drop(take_hook());
+    match Arc::try_unwrap(previous) {
+        Ok(previous) => set_hook(previous),
+        Err(previous) => set_hook(Box::new(move |info| previous(info))),
+    }
+    result
+}
+
+// Adds the paths of the files in the directory and its subdirectories.
+fn collect_files(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in read_dir(dir)? {
+        let path: PathBuf = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Gets the message that a thread panicked with.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
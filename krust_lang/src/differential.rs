@@ -0,0 +1,269 @@
+//! The module for differential testing, which runs the same program with the VM and with the interpreter and checks
+//! that they agree. As the two share nothing after the parser, any difference is a bug in one of them. Random
+//! programs to check can be generated with the strategies here.
+
+use crate::{compiler, interpreter, lexer, parser, pass_manager, util::log, vm};
+use compiler::{compile_with_passes, CompilerOptions, CompilerOutput};
+use interpreter::{prepare, InterpreterOutput, STACK_BYTES};
+use lexer::lex;
+use log::all_to_string;
+use parser::parse;
+use pass_manager::PassManager;
+use vm::{Value, Vm};
+
+use proptest::prelude::*;
+use std::fmt;
+use std::io;
+use std::panic::resume_unwind;
+use std::thread;
+
+/// What running a program gave, with every diagnostic converted to a string.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Execution {
+    /// The diagnostics from compiling the program.
+    pub compile_logs: Vec<String>,
+    /// The value the program gave, or None if it didn't compile or stopped with an error.
+    pub value: Option<Value>,
+    /// The lines the program printed.
+    pub output: Vec<String>,
+    /// The error that stopped the program, along with its stack trace.
+    pub errors: Vec<String>,
+}
+
+/// A program that the VM and the interpreter ran differently.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Mismatch {
+    pub code: String,
+    pub vm: Execution,
+    pub interpreter: Execution,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "the VM and the interpreter disagree on:\n{}", self.code)?;
+        let fields: [(&str, String, String); 4] = [
+            (
+                "compile logs",
+                format!("{:?}", self.vm.compile_logs),
+                format!("{:?}", self.interpreter.compile_logs),
+            ),
+            (
+                "value",
+                format!("{:?}", self.vm.value),
+                format!("{:?}", self.interpreter.value),
+            ),
+            (
+                "output",
+                format!("{:?}", self.vm.output),
+                format!("{:?}", self.interpreter.output),
+            ),
+            (
+                "errors",
+                format!("{:?}", self.vm.errors),
+                format!("{:?}", self.interpreter.errors),
+            ),
+        ];
+        for (name, vm, interpreter) in fields {
+            if vm != interpreter {
+                writeln!(
+                    f,
+                    "{name}:\n  VM:          {vm}\n  interpreter: {interpreter}"
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compiles the code to bytecode and runs it with the VM, discarding what it writes.
+#[must_use]
+pub fn run_vm(code: &str, options: CompilerOptions) -> Execution {
+    let output: CompilerOutput = compile_with_passes(
+        parse(lex(code)),
+        options,
+        false,
+        &PassManager::with_opt_level(options.opt_level),
+    );
+    let mut execution: Execution = Execution {
+        compile_logs: all_to_string(&output.logs),
+        value: None,
+        output: Vec::new(),
+        errors: Vec::new(),
+    };
+    if let Some(bytecode) = output.bytecode {
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.set_output(io::sink());
+        vm.set_error_output(io::sink());
+        match vm.run() {
+            Ok(value) => execution.value = Some(value),
+            Err(logs) => execution.errors = all_to_string(&logs),
+        }
+        execution.output = vm.output();
+    }
+    execution
+}
+
+/// Runs the code with the interpreter, discarding what it writes. The interpreter runs on its own thread with a stack
+/// of `STACK_BYTES`, so that deep recursion stops at the call depth limit as it does in the VM.
+///
+/// # Panics
+/// Panics if the interpreter's thread can't be started.
+#[must_use]
+pub fn run_interpreter(code: &str, options: CompilerOptions) -> Execution {
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(STACK_BYTES)
+            .spawn_scoped(scope, || {
+                let output: InterpreterOutput = prepare(
+                    parse(lex(code)),
+                    options,
+                    false,
+                    &PassManager::with_opt_level(options.opt_level),
+                );
+                let mut execution: Execution = Execution {
+                    compile_logs: all_to_string(&output.logs),
+                    value: None,
+                    output: Vec::new(),
+                    errors: Vec::new(),
+                };
+                if let Some(mut interpreter) = output.interpreter {
+                    interpreter.set_output(io::sink());
+                    interpreter.set_error_output(io::sink());
+                    match interpreter.run() {
+                        Ok(value) => execution.value = Some(value),
+                        Err(logs) => execution.errors = all_to_string(&logs),
+                    }
+                    execution.output = interpreter.output();
+                }
+                execution
+            })
+            .unwrap_or_else(|error| panic!("could not start the interpreter's thread: {error}"))
+            .join()
+            .unwrap_or_else(|panic| resume_unwind(panic))
+    })
+}
+
+/// Runs the code with both the VM and the interpreter, giving what it did if they agree.
+///
+/// # Errors
+/// Gives both executions if the diagnostics, value, output, or errors of the two differ.
+///
+/// # Panics
+/// Panics if the interpreter's thread can't be started.
+pub fn compare(code: &str, options: CompilerOptions) -> Result<Execution, Box<Mismatch>> {
+    let vm: Execution = run_vm(code, options);
+    let interpreter: Execution = run_interpreter(code, options);
+    if vm == interpreter {
+        Ok(vm)
+    } else {
+        Err(Box::new(Mismatch {
+            code: code.to_string(),
+            vm,
+            interpreter,
+        }))
+    }
+}
+
+// The binary operators on ints that give ints.
+pub(crate) const INT_OPERATORS: [&str; 10] = ["+", "-", "*", "/", "%", "<<", ">>", "&", "|", "^"];
+// The binary operators on ints that give bools.
+pub(crate) const COMPARISONS: [&str; 6] = ["<", "<=", ">", ">=", "==", "!="];
+// The binary operators on bools that give bools.
+pub(crate) const BOOL_OPERATORS: [&str; 5] = ["&", "|", "^", "==", "!="];
+
+/// Generates well-typed int expressions up to the given depth, which can use the int variables and bool variables
+/// given. The expressions can fail at runtime, such as by dividing by zero or indexing past the end of a list.
+pub fn int_expression(
+    depth: u32,
+    ints: &'static [&'static str],
+    bools: &'static [&'static str],
+) -> BoxedStrategy<String> {
+    let leaf: BoxedStrategy<String> = if ints.is_empty() {
+        any::<i32>().prop_map(|value| format!("({value})")).boxed()
+    } else {
+        prop_oneof![
+            any::<i32>().prop_map(|value| format!("({value})")),
+            proptest::sample::select(ints).prop_map(ToString::to_string),
+        ]
+        .boxed()
+    };
+    if depth == 0 {
+        return leaf;
+    }
+    let int = || int_expression(depth - 1, ints, bools);
+    prop_oneof![
+        2 => leaf,
+        4 => (int(), proptest::sample::select(&INT_OPERATORS[..]), int())
+            .prop_map(|(a, op, b)| format!("({a} {op} {b})")),
+        1 => int().prop_map(|a| format!("(-{a})")),
+        1 => int().prop_map(|a| format!("(~{a})")),
+        1 => bool_expression(depth - 1, ints, bools).prop_map(|a| format!("((int) {a})")),
+        1 => int().prop_map(|a| format!("abs({a})")),
+        1 => int().prop_map(|a| format!("sqrt({a})")),
+        1 => (int(), int()).prop_map(|(a, b)| format!("min({a}, {b})")),
+        1 => (int(), int()).prop_map(|(a, b)| format!("max({a}, {b})")),
+        1 => (int(), int()).prop_map(|(a, b)| format!("pow({a}, {b})")),
+        1 => (int(), int(), int()).prop_map(|(a, b, c)| format!("clamp({a}, {b}, {c})")),
+        1 => (proptest::collection::vec(int(), 0..4), 0..5)
+            .prop_map(|(items, index)| format!("list<int> {{{}}}[{index}]", items.join(", "))),
+    ]
+    .boxed()
+}
+
+/// Generates well-typed bool expressions up to the given depth, which can use the int variables and bool variables
+/// given.
+pub fn bool_expression(
+    depth: u32,
+    ints: &'static [&'static str],
+    bools: &'static [&'static str],
+) -> BoxedStrategy<String> {
+    let leaf: BoxedStrategy<String> = if bools.is_empty() {
+        any::<bool>().prop_map(|value| value.to_string()).boxed()
+    } else {
+        prop_oneof![
+            any::<bool>().prop_map(|value| value.to_string()),
+            proptest::sample::select(bools).prop_map(ToString::to_string),
+        ]
+        .boxed()
+    };
+    if depth == 0 {
+        return leaf;
+    }
+    let boolean = || bool_expression(depth - 1, ints, bools);
+    let int = || int_expression(depth - 1, ints, bools);
+    prop_oneof![
+        2 => leaf,
+        3 => (boolean(), proptest::sample::select(&BOOL_OPERATORS[..]), boolean())
+            .prop_map(|(a, op, b)| format!("({a} {op} {b})")),
+        3 => (int(), proptest::sample::select(&COMPARISONS[..]), int())
+            .prop_map(|(a, op, b)| format!("({a} {op} {b})")),
+        1 => boolean().prop_map(|a| format!("(!{a})")),
+        1 => int().prop_map(|a| format!("((bool) {a})")),
+    ]
+    .boxed()
+}
+
+/// Generates well-typed programs that declare variables and call a function, ending with an int expression. The
+/// function and the main program can start with an assertion, which fails about half the time.
+pub fn program(depth: u32) -> BoxedStrategy<String> {
+    let assertion = |condition: BoxedStrategy<String>, message: &'static str| {
+        proptest::option::weighted(0.25, condition)
+            .prop_map(move |condition| {
+                condition.map_or_else(String::new, |condition| format!("assert({condition}, \"{message}\"); "))
+            })
+    };
+    (
+        int_expression(depth, &[], &[]),
+        bool_expression(depth, &[], &[]),
+        assertion(bool_expression(depth, &["a"], &["p"]), "in f"),
+        int_expression(depth, &["a"], &["p"]),
+        assertion(bool_expression(depth, &["x"], &["q"]), "in main"),
+        int_expression(depth, &["x", "f(x, q)"], &["q"]),
+    )
+        .prop_map(|(x, q, check, body, main_check, last)| {
+            format!(
+                "int x = {x};\nbool q = {q};\nfn f(a: int, p: bool) -> int {{ {check}{body} }}\n{main_check}{last}"
+            )
+        })
+        .boxed()
+}
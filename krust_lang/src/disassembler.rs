@@ -0,0 +1,289 @@
+//! The module for converting bytecode back into readable instructions.
+
+use crate::{compiler, util::log};
+use compiler::{
+    code_start, read_register_operand, IsaLevel, OpCode, OperandSource, BYTES_PER_VAR,
+    DETAILED_ERRORS_FLAG, HEADER_SIZE,
+};
+use log::{ErrorType, Log, LogType};
+
+use num_traits::FromPrimitive;
+use std::collections::HashMap;
+
+/// How the disassembler refers to locations in the bytecode.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AddressMode {
+    /// Each instruction is preceded by its offset, and calls and jumps give the offset of their target.
+    Offsets,
+    /// Offsets are left out, and the targets of calls and jumps are given labels instead. The output only changes
+    /// where the instructions themselves change, so it can be compared between versions of the compiler.
+    Labels,
+}
+
+/// The output given by the disassembler.
+pub struct DisassemblerOutput {
+    /// The lines of the disassembly, starting with a description of the header.
+    pub lines: Vec<String>,
+    /// Errors for bytecode that could not be read. The lines stop at the first error.
+    pub logs: Vec<Log>,
+}
+
+// An instruction read from the bytecode.
+struct Instruction<'b> {
+    offset: usize,
+    op: OpCode,
+    operand: &'b [u8],
+}
+
+/// Converts bytecode into a line for each instruction, with its operands.
+#[must_use]
+pub fn disassemble(bytecode: &[u8], mode: AddressMode) -> DisassemblerOutput {
+    let mut output: DisassemblerOutput = DisassemblerOutput {
+        lines: Vec::new(),
+        logs: Vec::new(),
+    };
+    let isa_level: Option<IsaLevel> = bytecode
+        .get(2)
+        .and_then(|level| FromPrimitive::from_u8(*level));
+    let (Some(isa_level), Some(&ptr_size), true) = (
+        isa_level,
+        bytecode.first(),
+        bytecode.len() >= HEADER_SIZE && bytecode.len() >= code_start(bytecode),
+    ) else {
+        push_error(&mut output.logs, ErrorType::InvalidBytecodeHeader);
+        return output;
+    };
+    let ptr_size: usize = ptr_size as usize;
+    output.lines.push(format!(
+        "; pointer size: {}-bit, detailed errors: {}, instruction set level: {}",
+        ptr_size * 8,
+        bytecode[1] & DETAILED_ERRORS_FLAG != 0,
+        isa_level as u8
+    ));
+
+    let instructions: Vec<Instruction> = read_instructions(bytecode, ptr_size, &mut output.logs);
+    let locations: HashMap<usize, String> = get_locations(&instructions, ptr_size);
+    let labels: HashMap<usize, String> = match mode {
+        AddressMode::Offsets => HashMap::new(),
+        AddressMode::Labels => get_labels(&instructions, ptr_size),
+    };
+    let target = |offset: usize| {
+        labels
+            .get(&offset)
+            .cloned()
+            .unwrap_or_else(|| format!("@{offset}"))
+    };
+    for instruction in &instructions {
+        let mut text: String = format_instruction(instruction, ptr_size, &target);
+        if let Some(location) = locations.get(&instruction.offset) {
+            text.push(' ');
+            text.push_str(location);
+        }
+        match mode {
+            AddressMode::Offsets => output
+                .lines
+                .push(format!("{:>6}  {text}", instruction.offset)),
+            AddressMode::Labels => {
+                if let Some(label) = labels.get(&instruction.offset) {
+                    output.lines.push(format!("{label}:"));
+                }
+                output.lines.push(format!("    {text}"));
+            }
+        }
+    }
+    output
+}
+
+// Reads every instruction after the header. Stops at the first instruction that can't be read and reports it.
+fn read_instructions<'b>(
+    bytecode: &'b [u8],
+    ptr_size: usize,
+    logs: &mut Vec<Log>,
+) -> Vec<Instruction<'b>> {
+    let mut instructions: Vec<Instruction> = Vec::new();
+    let mut index: usize = code_start(bytecode);
+    while index < bytecode.len() {
+        let offset: usize = index;
+        let op: Option<OpCode> = FromPrimitive::from_u8(bytecode[offset]);
+        let Some(op) = op else {
+            push_error(logs, ErrorType::InvalidOpCode(offset, bytecode[offset]));
+            break;
+        };
+        let operand_start: usize = offset + 1;
+        let Some(length) = op
+            .operand_length(ptr_size, &bytecode[operand_start..])
+            .filter(|length| operand_start + length <= bytecode.len())
+        else {
+            push_error(logs, ErrorType::IncompleteOperand(offset));
+            break;
+        };
+        index = operand_start + length;
+        instructions.push(Instruction {
+            offset,
+            op,
+            operand: &bytecode[operand_start..index],
+        });
+    }
+    instructions
+}
+
+// Gives a label to each offset that is the target of a call or a jump. Functions are labelled "fn0", "fn1", and so
+// on, and jump targets "L0", "L1", and so on, both in the order they appear in the bytecode.
+fn get_labels(instructions: &[Instruction], ptr_size: usize) -> HashMap<usize, String> {
+    let mut functions: Vec<usize> = Vec::new();
+    let mut jumps: Vec<usize> = Vec::new();
+    for instruction in instructions {
+        match instruction.op {
+            OpCode::Call | OpCode::TailCall | OpCode::FunctionName => {
+                functions.push(read_uint(instruction.operand, 0, ptr_size));
+            }
+            OpCode::JumpIfOk => jumps.push(jump_target(instruction, ptr_size)),
+            _ => {}
+        }
+    }
+    functions.sort_unstable();
+    functions.dedup();
+    jumps.sort_unstable();
+    jumps.dedup();
+    let mut labels: HashMap<usize, String> = HashMap::new();
+    for (index, offset) in jumps.into_iter().enumerate() {
+        labels.insert(offset, format!("L{index}"));
+    }
+    for (index, offset) in functions.into_iter().enumerate() {
+        labels.insert(offset, format!("fn{index}"));
+    }
+    labels
+}
+
+// Gets the line and column of each instruction in the line table, by offset, formatted to follow the instruction.
+fn get_locations(instructions: &[Instruction], ptr_size: usize) -> HashMap<usize, String> {
+    instructions
+        .iter()
+        .filter(|instruction| instruction.op == OpCode::LineTable)
+        .flat_map(|instruction| instruction.operand[4..].chunks_exact(3 * ptr_size))
+        .map(|entry| {
+            (
+                read_uint(entry, 0, ptr_size),
+                format_location(entry, ptr_size, ptr_size),
+            )
+        })
+        .collect()
+}
+
+// Formats the line and column that start at an offset in an operand.
+fn format_location(operand: &[u8], start: usize, ptr_size: usize) -> String {
+    format!(
+        "(line {}:{})",
+        read_uint(operand, start, ptr_size),
+        read_uint(operand, start + ptr_size, ptr_size)
+    )
+}
+
+// Formats an instruction as its name followed by its operands.
+fn format_instruction(
+    instruction: &Instruction,
+    ptr_size: usize,
+    target: &dyn Fn(usize) -> String,
+) -> String {
+    let operand: &[u8] = instruction.operand;
+    let read = |start: usize, length: usize| read_uint(operand, start, length);
+    let operands: Vec<String> = match instruction.op {
+        OpCode::PushInt => {
+            vec![i32::from_le_bytes([operand[0], operand[1], operand[2], operand[3]]).to_string()]
+        }
+        OpCode::PushByte
+        | OpCode::Dup
+        | OpCode::PrintList
+        | OpCode::LenList
+        | OpCode::ListPush
+        | OpCode::ListPop
+        | OpCode::ListIndex
+        | OpCode::OkResult
+        | OpCode::PrintResult
+        | OpCode::UnwrapResult
+        | OpCode::Return => vec![operand[0].to_string()],
+        OpCode::PushString => vec![format!("{:?}", String::from_utf8_lossy(&operand[4..]))],
+        OpCode::LoadConst => vec![u16::from_le_bytes([operand[0], operand[1]]).to_string()],
+        // The constant is shown as the push instruction that loading it runs.
+        OpCode::Constant => {
+            let push: Instruction = Instruction {
+                offset: instruction.offset + 1,
+                op: FromPrimitive::from_u8(operand[0]).expect("only pushes have a length"),
+                operand: &operand[1..],
+            };
+            vec![format_instruction(&push, ptr_size, target)]
+        }
+        OpCode::GetInt
+        | OpCode::GetBool
+        | OpCode::GetNullableInt
+        | OpCode::GetNullableBool
+        | OpCode::SetInt
+        | OpCode::SetBool
+        | OpCode::SetNullableInt
+        | OpCode::SetNullableBool => vec![format!("var{}", read(0, BYTES_PER_VAR))],
+        // The operation is followed by its left and right operands.
+        OpCode::RegisterOp => {
+            let (op, sources): (OpCode, [(OperandSource, u32); 2]) =
+                read_register_operand(operand).expect("the operand was read");
+            let mut operands: Vec<String> = vec![format!("{op:?}")];
+            operands.extend(sources.map(|(source, value)| match source {
+                OperandSource::Stack => "stack".to_string(),
+                OperandSource::Register => format!("var{value}"),
+                OperandSource::Immediate => value.cast_signed().to_string(),
+            }));
+            operands
+        }
+        OpCode::PrintMap | OpCode::MapInsert | OpCode::MapGet | OpCode::MapRemove => {
+            vec![operand[0].to_string(), operand[1].to_string()]
+        }
+        OpCode::JumpIfOk => vec![target(jump_target(instruction, ptr_size))],
+        OpCode::Call | OpCode::TailCall => vec![
+            target(read(0, ptr_size)),
+            read(ptr_size, ptr_size).to_string(),
+            operand[2 * ptr_size].to_string(),
+        ],
+        OpCode::BindParam => vec![read(0, ptr_size).to_string(), operand[ptr_size].to_string()],
+        OpCode::FunctionName => vec![
+            target(read(0, ptr_size)),
+            format!("{:?}", String::from_utf8_lossy(&operand[ptr_size + 4..])),
+        ],
+        // The entries are shown after the instructions they belong to.
+        OpCode::LineTable => vec![read(0, 4).to_string()],
+        OpCode::Location => vec![format_location(operand, 0, ptr_size)],
+        OpCode::CallHost => vec![
+            format!("{:?}", String::from_utf8_lossy(&operand[5..])),
+            operand[0].to_string(),
+        ],
+        _ => Vec::new(),
+    };
+    let mut text: String = format!("{:?}", instruction.op);
+    for operand in operands {
+        text.push(' ');
+        text.push_str(&operand);
+    }
+    text
+}
+
+// Gets the offset that a jump goes to, which is counted from the end of the jump.
+fn jump_target(instruction: &Instruction, ptr_size: usize) -> usize {
+    instruction.offset + 1 + instruction.operand.len() + read_uint(instruction.operand, 0, ptr_size)
+}
+
+// Reads a little-endian unsigned value from part of an operand. Bytes that don't fit in a usize are ignored.
+fn read_uint(operand: &[u8], start: usize, length: usize) -> usize {
+    let mut bytes: [u8; (usize::BITS / 8) as usize] = [0; (usize::BITS / 8) as usize];
+    for (byte, value) in bytes.iter_mut().zip(&operand[start..start + length]) {
+        *byte = *value;
+    }
+    usize::from_le_bytes(bytes)
+}
+
+// Adds an error to the list of logs.
+fn push_error(logs: &mut Vec<Log>, error: ErrorType) {
+    logs.push(Log {
+        log_type: LogType::Error(error),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+}
@@ -0,0 +1,99 @@
+//! The module for testing the code in Markdown documentation, so that tutorials for the language stay correct as the
+//! compiler changes. Code blocks fenced with ```` ```krust ```` are compiled and run, and their output is compared with the
+//! comments of the form `// => output` in the block.
+
+use crate::{compiler, lexer, parser, util::log, vm};
+use compiler::{compile, CompilerOptions, CompilerOutput};
+use lexer::lex;
+use log::{is_error, Log};
+use parser::parse;
+use vm::Vm;
+
+use std::io;
+
+/// A fenced block of krust code in a Markdown file.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Snippet {
+    /// The line of the file that the code starts on.
+    pub line: usize,
+    pub code: String,
+    /// The output the code should print, one line for each `// =>` comment.
+    pub expected: Vec<String>,
+    /// Whether or not the block is marked "ignore", such as with ```` ```krust,ignore ````, so that it isn't run.
+    pub ignored: bool,
+}
+
+/// The result of testing a snippet.
+pub enum Outcome {
+    Passed,
+    Ignored,
+    /// The snippet didn't compile, had a runtime error, or printed something other than what was expected.
+    Failed {
+        output: Vec<String>,
+        logs: Vec<Log>,
+    },
+}
+
+const EXPECTED_OUTPUT_MARKER: &str = "// =>";
+
+/// Gets the krust code blocks in a Markdown file. Blocks in other languages are skipped.
+#[must_use]
+pub fn extract_snippets(markdown: &str) -> Vec<Snippet> {
+    let mut snippets: Vec<Snippet> = Vec::new();
+    // The info string of the block being read and the snippet being built from it, if it's krust code.
+    let mut block: Option<Option<Snippet>> = None;
+    for (index, line) in markdown.lines().enumerate() {
+        let trimmed: &str = line.trim_start();
+        let Some(info) = trimmed.strip_prefix("```") else {
+            if let Some(Some(snippet)) = &mut block {
+                snippet.code.push_str(line);
+                snippet.code.push('\n');
+                if let Some((_, expected)) = line.split_once(EXPECTED_OUTPUT_MARKER) {
+                    snippet.expected.push(expected.trim().to_string());
+                }
+            }
+            continue;
+        };
+        match block.take() {
+            Some(Some(snippet)) => snippets.push(snippet),
+            Some(None) => {}
+            None => {
+                let mut attributes = info.split([',', ' ']).filter(|word| !word.is_empty());
+                block = Some((attributes.next() == Some("krust")).then(|| Snippet {
+                    line: index + 2,
+                    code: String::new(),
+                    expected: Vec::new(),
+                    ignored: attributes.any(|attribute| attribute == "ignore"),
+                }));
+            }
+        }
+    }
+    snippets
+}
+
+/// Compiles and runs a snippet, and checks that it prints the expected output without errors. The snippet can't
+/// access files.
+#[must_use]
+pub fn test_snippet(snippet: &Snippet, options: CompilerOptions) -> Outcome {
+    if snippet.ignored {
+        return Outcome::Ignored;
+    }
+    let compiler_output: CompilerOutput = compile(parse(lex(&snippet.code)), options);
+    let Some(bytecode) = compiler_output.bytecode else {
+        return Outcome::Failed {
+            output: Vec::new(),
+            logs: compiler_output.logs,
+        };
+    };
+    // The output and errors are reported with the outcome, so they aren't printed as well.
+    let mut vm: Vm = Vm::new(bytecode);
+    vm.set_output(io::sink());
+    vm.set_error_output(io::sink());
+    let logs: Vec<Log> = vm.run().err().unwrap_or_default();
+    let output: Vec<String> = vm.output();
+    if output == snippet.expected && !is_error(&logs) {
+        Outcome::Passed
+    } else {
+        Outcome::Failed { output, logs }
+    }
+}
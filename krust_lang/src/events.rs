@@ -0,0 +1,235 @@
+//! The module for reporting the progress of a build as JSON lines, so that build systems and editors can show it
+//! while large builds run.
+
+use crate::util::log::{Log, Severity};
+use std::fmt::Write as _;
+use std::io::{stderr, stdout, Write};
+use std::time::{Duration, Instant};
+
+/// The streams that events can be written to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EventStream {
+    Stdout,
+    Stderr,
+}
+
+impl EventStream {
+    /// Gets the stream with the given name, as used on the command line.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "stdout" => Some(Self::Stdout),
+            "stderr" => Some(Self::Stderr),
+            _ => None,
+        }
+    }
+}
+
+/// The stages of a build that report when they start and finish.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    Lex,
+    Parse,
+    Compile,
+    InternalChecks,
+    Verify,
+    Run,
+}
+
+impl Stage {
+    /// Gets the name of the stage used in events.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lex => "lex",
+            Self::Parse => "parse",
+            Self::Compile => "compile",
+            Self::InternalChecks => "internal_checks",
+            Self::Verify => "verify",
+            Self::Run => "run",
+        }
+    }
+}
+
+/// Something that happened while building a file.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    StageStarted {
+        file: String,
+        stage: Stage,
+    },
+    StageFinished {
+        file: String,
+        stage: Stage,
+        duration: Duration,
+    },
+    ArtifactWritten {
+        file: String,
+        path: String,
+    },
+    /// The number of logs produced for the file, by severity.
+    Diagnostics {
+        file: String,
+        errors: usize,
+        warnings: usize,
+        total: usize,
+    },
+}
+
+impl Event {
+    /// Counts the logs produced for a file.
+    #[must_use]
+    pub fn diagnostics(file: &str, logs: &[Log]) -> Self {
+        let count = |severity: Severity| {
+            logs.iter()
+                .filter(|log| log.log_type.severity() == severity)
+                .count()
+        };
+        Self::Diagnostics {
+            file: file.to_string(),
+            errors: count(Severity::Error),
+            warnings: count(Severity::Warning),
+            total: logs.len(),
+        }
+    }
+
+    /// Converts the event to a line for people to read, as printed by `--verbose`.
+    #[must_use]
+    pub fn to_text(&self) -> String {
+        match self {
+            Self::StageStarted { file, stage } => format!("{file}: {} started", stage.name()),
+            Self::StageFinished {
+                file,
+                stage,
+                duration,
+            } => format!("{file}: {} took {duration:.3?}", stage.name()),
+            Self::ArtifactWritten { file, path } => format!("{file}: wrote {path}"),
+            Self::Diagnostics {
+                file,
+                errors,
+                warnings,
+                total,
+            } => {
+                let plural = |count: usize| if count == 1 { "" } else { "s" };
+                format!(
+                    "{file}: {errors} error{}, {warnings} warning{}, {total} log{} in total",
+                    plural(*errors),
+                    plural(*warnings),
+                    plural(*total)
+                )
+            }
+        }
+    }
+
+    /// Converts the event to a single line of JSON.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        match self {
+            Self::StageStarted { file, stage } => format!(
+                "{{\"event\":\"stage_started\",\"file\":{},\"stage\":\"{}\"}}",
+                json_string(file),
+                stage.name()
+            ),
+            Self::StageFinished {
+                file,
+                stage,
+                duration,
+            } => format!(
+                "{{\"event\":\"stage_finished\",\"file\":{},\"stage\":\"{}\",\"duration_ms\":{}}}",
+                json_string(file),
+                stage.name(),
+                duration.as_millis()
+            ),
+            Self::ArtifactWritten { file, path } => format!(
+                "{{\"event\":\"artifact_written\",\"file\":{},\"path\":{}}}",
+                json_string(file),
+                json_string(path)
+            ),
+            Self::Diagnostics {
+                file,
+                errors,
+                warnings,
+                total,
+            } => format!(
+                "{{\"event\":\"diagnostics\",\"file\":{},\"errors\":{errors},\"warnings\":{warnings},\"total\":{total}}}",
+                json_string(file)
+            ),
+        }
+    }
+}
+
+/// Quotes a string for JSON, escaping the characters that can't appear in a JSON string as they are.
+#[must_use]
+pub fn json_string(value: &str) -> String {
+    let mut quoted: String = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32); // Writing to a string can't fail.
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Writes events to a stream as JSON lines. Events are dropped if no stream was chosen. The time each stage takes can
+/// also be printed to stderr for people to read.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct EventLog {
+    stream: Option<EventStream>,
+    timings: bool,
+}
+
+impl EventLog {
+    /// Creates an event log that writes to the given stream, if any.
+    #[must_use]
+    pub fn new(stream: Option<EventStream>) -> Self {
+        Self {
+            stream,
+            timings: false,
+        }
+    }
+
+    /// Sets whether or not the time each stage takes is printed to stderr when it finishes.
+    #[must_use]
+    pub fn with_timings(self, timings: bool) -> Self {
+        Self { timings, ..self }
+    }
+
+    /// Writes an event to the stream.
+    pub fn emit(&self, event: &Event) {
+        // A build shouldn't fail because its progress couldn't be reported.
+        let _ = match self.stream {
+            Some(EventStream::Stdout) => writeln!(stdout(), "{}", event.to_json()),
+            Some(EventStream::Stderr) => writeln!(stderr(), "{}", event.to_json()),
+            None => Ok(()),
+        };
+    }
+
+    /// Runs a stage of the build for a file, emitting events when it starts and finishes.
+    pub fn stage<T>(&self, file: &str, stage: Stage, run: impl FnOnce() -> T) -> T {
+        self.emit(&Event::StageStarted {
+            file: file.to_string(),
+            stage,
+        });
+        let start: Instant = Instant::now();
+        let output: T = run();
+        let finished: Event = Event::StageFinished {
+            file: file.to_string(),
+            stage,
+            duration: start.elapsed(),
+        };
+        if self.timings {
+            eprintln!("{}", finished.to_text());
+        }
+        self.emit(&finished);
+        output
+    }
+}
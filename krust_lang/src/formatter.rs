@@ -0,0 +1,423 @@
+//! The module for the code formatter, which reprints source code with the same spacing and layout however it was
+//! written. The code is parsed first so that only code without errors is formatted, but it is reprinted from its tokens
+//! rather than from the AST, as the AST has already replaced `typeof` and flags with their values and has no comments.
+//! Comments are kept, as are the blank lines between statements, with at most one in a row.
+//!
+//! Each statement goes on its own line, and the bodies of functions, traits, impls, and flags are indented by four
+//! spaces with a statement or flag on each line. Binary operators have a space on each side, commas and colons have a
+//! space after them, and brackets have no space inside them. Parentheses that only wrap a whole statement, argument,
+//! element, index, or value being assigned, as in `int x = (1 + 2);`, are removed.
+
+use crate::{lexer, parser, util::log};
+use lexer::{lex, LexerOutput, Token, TokenType};
+use log::{is_error, ErrorType, Log, LogType};
+use parser::{parse, ParserOutput};
+
+use std::ops::Range;
+
+/// The text of each level of indentation.
+pub const INDENT: &str = "    ";
+
+/// The output given by the formatter.
+pub struct FormatterOutput {
+    /// The formatted code, or None if the code has errors.
+    pub formatted: Option<String>,
+    pub logs: Vec<Log>,
+}
+
+/// Formats the code. Code with errors isn't formatted, and the logs from parsing it are given instead.
+#[must_use]
+pub fn format(file_text: &str) -> FormatterOutput {
+    let lex_output: LexerOutput = lex(file_text);
+    let source: String = lex_output.file_text.clone();
+    let tokens: Vec<Token> = lex_output.tokens.clone();
+    let parse_output: ParserOutput = parse(lex_output);
+    let mut logs: Vec<Log> = parse_output.logs;
+    if is_error(&logs) {
+        return FormatterOutput { formatted: None, logs };
+    }
+
+    let removed: Vec<bool> = redundant_parens(&tokens);
+    let mut printer: Printer = Printer::new(&source);
+    let formatted: String = printer.print(&tokens, &removed);
+    // Formatting only changes the space between the tokens it writes, so the formatted code must have the same tokens.
+    let written: Vec<(TokenType, String)> = printer
+        .written
+        .iter()
+        .map(|token| (token.token_type, token.to_string(&source)))
+        .collect();
+    let formatted_tokens: Vec<(TokenType, String)> = lex(&formatted)
+        .tokens
+        .iter()
+        .filter(|token| token.token_type != TokenType::EOF)
+        .map(|token| (token.token_type, token.to_string(&formatted)))
+        .collect();
+    if written != formatted_tokens {
+            logs.push(Log {
+            log_type: LogType::Error(ErrorType::InternalCompilerError(
+                "formatting changed the tokens of the code".to_string(),
+            )),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return FormatterOutput { formatted: None, logs };
+    }
+    FormatterOutput { formatted: Some(formatted), logs }
+}
+
+// The kinds of brackets, which decide how the tokens inside them are laid out.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Bracket {
+    // The body of a function, trait, or impl, with a statement on each line.
+    Block,
+    // The body of flags, with a flag on each line.
+    Flags,
+    // The '<' of a list or result type or of the type parameters of a function.
+    Generic,
+    // Any other bracket, whose contents stay on one line.
+    Inline,
+}
+
+// Writes the formatted code.
+#[allow(clippy::struct_excessive_bools)] // Each is a separate fact about the tokens written so far.
+struct Printer<'a> {
+    source: &'a str,
+    out: String,
+    // The tokens written, in order.
+    written: Vec<Token>,
+    brackets: Vec<Bracket>,
+    // The number of brackets open when the body of the declaration being read starts, and the kind of the body.
+    pending_body: Option<(usize, Bracket)>,
+    // Whether or not the next token goes on a new line.
+    newline: bool,
+    // The number of line breaks in the source before the next token, which keeps a blank line if it is at least 2.
+    line_breaks: usize,
+    // Whether or not the last thing written opened a block, so that an empty block is written as "{}".
+    opened_block: bool,
+    // The last two tokens written, the latest first.
+    last: [Option<Token>; 2],
+    // Whether or not the last token written ends an operand, so that a '-' or '!' after it is a binary or postfix
+    // operator rather than a prefix one.
+    operand_end: bool,
+    // Whether or not the last token written was a prefix operator.
+    prefix: bool,
+    // Whether or not the last token written closed a block, after which a ';' isn't needed.
+    closed_block: bool,
+    // Whether or not the last token written closed a '<', after which the parameters of a function have no space.
+    closed_generic: bool,
+}
+
+impl<'a> Printer<'a> {
+    // Creates a printer for the code.
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            out: String::new(),
+            written: Vec::new(),
+            brackets: Vec::new(),
+            pending_body: None,
+            newline: false,
+            line_breaks: 0,
+            opened_block: false,
+            last: [None, None],
+            operand_end: false,
+            prefix: false,
+            closed_block: false,
+            closed_generic: false,
+        }
+    }
+
+    // Prints the tokens other than those that are removed, along with the comments between them.
+    fn print(&mut self, tokens: &[Token], removed: &[bool]) -> String {
+        let mut gap_start: usize = 0;
+        for (token, removed) in tokens.iter().zip(removed) {
+            let range: Range<usize> = if token.token_type == TokenType::EOF {
+                self.source.len()..self.source.len()
+            } else {
+                token.span().byte_range
+            };
+            self.gap(&self.source[gap_start..range.start]);
+            gap_start = range.end;
+            if token.token_type != TokenType::EOF && !removed {
+                self.token(*token);
+            }
+        }
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        std::mem::take(&mut self.out)
+    }
+
+    // Writes the comments in the space between two tokens. A comment on the same line as the token before it stays
+    // on that line, and the others go on their own lines.
+    fn gap(&mut self, gap: &str) {
+        self.line_breaks = 0;
+        for (index, line) in gap.split('\n').enumerate() {
+            if index > 0 {
+                self.line_breaks += 1;
+            }
+            let Some(start) = line.find("//") else {
+                continue;
+            };
+            let comment: &str = line[start..].trim_end();
+            if index == 0 && !self.out.is_empty() {
+                self.out.push(' ');
+            } else {
+                self.start_line(false);
+            }
+            self.out.push_str(comment);
+            self.newline = true;
+            self.opened_block = false;
+            self.line_breaks = 0;
+        }
+    }
+
+    // Ends the line if the next token goes on a new line, keeping one blank line if there was one in the source, and
+    // indents the new line. Blank lines aren't kept at the start or end of a block.
+    fn start_line(&mut self, closing: bool) {
+        if self.out.is_empty() {
+            return;
+        }
+        if self.newline || !self.out.ends_with('\n') {
+            self.out.push('\n');
+            if self.line_breaks >= 2 && !self.opened_block && !closing {
+                self.out.push('\n');
+            }
+        }
+        self.newline = false;
+        let depth: usize = self.brackets.iter().filter(|bracket| matches!(bracket, Bracket::Block | Bracket::Flags)).count();
+        // A line that starts inside brackets on one line, which only happens after a comment, is indented once more.
+        let continued: bool = self.brackets.last().is_some_and(|bracket| matches!(bracket, Bracket::Generic | Bracket::Inline));
+        self.out.push_str(&INDENT.repeat(depth + usize::from(continued)));
+    }
+
+    // Writes the text of a token, on a new line if one is needed and otherwise after a space if space is true.
+    fn write(&mut self, token: Token, space: bool) {
+        if self.newline {
+            self.start_line(token.token_type == TokenType::RightBrace);
+        } else if space && !self.out.is_empty() && !self.out.ends_with(' ') && !self.out.ends_with('\n') {
+            self.out.push(' ');
+        } else if self.out.ends_with('\n') {
+            self.start_line(false);
+        }
+        self.out.push_str(&token.to_string(self.source));
+        self.written.push(token);
+        self.opened_block = false;
+        self.closed_block = false;
+        self.closed_generic = false;
+        self.last = [Some(token), self.last[0]];
+    }
+
+    // Writes the token with the space and line breaks around it.
+    fn token(&mut self, token: Token) {
+        let depth: usize = self.brackets.len();
+        let (operand_end, prefix): (bool, bool) = (self.operand_end, self.prefix);
+        self.operand_end = false;
+        self.prefix = false;
+        match token.token_type {
+            // The ';' after the body of a declaration is optional, so it is left out.
+            TokenType::Semicolon if self.closed_block => {}
+            TokenType::Semicolon => {
+                self.write(token, false);
+                if self.pending_body.is_some_and(|(body_depth, _)| body_depth == depth) {
+                    self.pending_body = None;
+                }
+                if matches!(self.brackets.last(), None | Some(Bracket::Block)) {
+                    self.newline = true;
+                }
+            }
+            TokenType::LeftBrace => self.open_brace(token, depth),
+            TokenType::RightBrace => self.close_brace(token),
+            TokenType::LeftParen | TokenType::LeftBracket => {
+                let space: bool = token.token_type == TokenType::LeftParen
+                    && !prefix
+                    && !self.closed_generic
+                    && !self.last[0].is_some_and(|last| matches!(last.token_type, TokenType::Other | TokenType::TypeOf))
+                    && !self.opens_inline();
+                self.write(token, space);
+                self.brackets.push(Bracket::Inline);
+            }
+            TokenType::RightParen | TokenType::RightBracket => {
+                self.brackets.pop();
+                self.write(token, false);
+                self.operand_end = true;
+            }
+            TokenType::Less if self.starts_generic() => {
+                self.write(token, false);
+                self.brackets.push(Bracket::Generic);
+            }
+            TokenType::Greater if self.brackets.last() == Some(&Bracket::Generic) => {
+                self.brackets.pop();
+                self.write(token, false);
+                self.closed_generic = true;
+            }
+            TokenType::Comma => {
+                self.write(token, false);
+                if self.brackets.last() == Some(&Bracket::Flags) {
+                    self.newline = true;
+                }
+            }
+            TokenType::Colon | TokenType::Dot => self.write(token, false),
+            TokenType::Minus | TokenType::Tilde | TokenType::ExclamationMark if !operand_end => {
+                self.write(token, !prefix && !self.opens_inline());
+                self.prefix = true;
+            }
+            // A postfix operator, or the '?' of a nullable type.
+            TokenType::ExclamationMark | TokenType::QuestionMark => {
+                self.write(token, false);
+                self.operand_end = true;
+            }
+            token_type => {
+                if let TokenType::Fn | TokenType::Trait | TokenType::Impl = token_type {
+                    self.pending_body = Some((depth, Bracket::Block));
+                } else if token_type == TokenType::Flags {
+                    self.pending_body = Some((depth, Bracket::Flags));
+                }
+                // The value type of a map type follows its key type straight away, as in "map[int]bool".
+                let after_key: bool = self.last[0].is_some_and(|last| last.token_type == TokenType::RightBracket)
+                    && is_type_keyword(token_type);
+                let space: bool = !prefix && !after_key && !self.opens_inline() && !self.after_dot();
+                self.write(token, space);
+                self.operand_end = matches!(
+                    token_type,
+                    TokenType::Other
+                        | TokenType::IntLiteral(_)
+                        | TokenType::StringLiteral
+                        | TokenType::True
+                        | TokenType::False
+                        | TokenType::Null
+                        | TokenType::Int
+                        | TokenType::Bool
+                );
+            }
+        }
+    }
+
+    // Writes a '{', which starts the body of a declaration if one is being read and otherwise starts a literal.
+    fn open_brace(&mut self, token: Token, depth: usize) {
+        self.write(token, true);
+        if let Some((_, body)) = self.pending_body.filter(|(body_depth, _)| *body_depth == depth) {
+            self.pending_body = None;
+            self.brackets.push(body);
+            self.newline = true;
+            self.opened_block = true;
+        } else {
+            self.brackets.push(Bracket::Inline);
+        }
+    }
+
+    // Writes a '}', which goes on its own line if it ends the body of a declaration with anything in it.
+    fn close_brace(&mut self, token: Token) {
+        if let Some(Bracket::Block | Bracket::Flags) = self.brackets.pop() {
+            self.newline = !self.opened_block;
+            self.write(token, false);
+            self.newline = true;
+            self.closed_block = true;
+        } else {
+            self.write(token, false);
+            self.operand_end = true;
+        }
+    }
+
+    // Returns whether or not the last token written opened a bracket, after which there is no space.
+    fn opens_inline(&self) -> bool {
+        // A '<' only opens a bracket in a type, as elsewhere it is a comparison.
+        self.last[0].is_some_and(|last| match last.token_type {
+            TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => {
+                matches!(self.brackets.last(), Some(Bracket::Inline | Bracket::Generic))
+            }
+            TokenType::Less => self.brackets.last() == Some(&Bracket::Generic),
+            _ => false,
+        })
+    }
+
+    // Returns whether or not the last token written was a '.', after which there is no space.
+    fn after_dot(&self) -> bool {
+        self.last[0].is_some_and(|last| last.token_type == TokenType::Dot)
+    }
+
+    // Returns whether or not a '<' starts a list or result type or the type parameters of a function.
+    fn starts_generic(&self) -> bool {
+        match self.last {
+            [Some(last), _] if matches!(last.token_type, TokenType::List | TokenType::Result) => true,
+            [Some(name), Some(keyword)] => name.token_type == TokenType::Other && keyword.token_type == TokenType::Fn,
+            _ => false,
+        }
+    }
+}
+
+// Returns whether or not the token type is a keyword that starts a type.
+fn is_type_keyword(token_type: TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::Int | TokenType::Bool | TokenType::String | TokenType::Map | TokenType::List | TokenType::Result
+    )
+}
+
+// Finds the parentheses that can be removed without changing what the code means, which are those wrapping a whole
+// statement, argument, list element, index, or value being assigned. Parentheses with a '=' inside are kept, as the
+// value of an assignment in parentheses is printed by scripts.
+fn redundant_parens(tokens: &[Token]) -> Vec<bool> {
+    let mut removed: Vec<bool> = vec![false; tokens.len()];
+    let mut open: Vec<usize> = Vec::new();
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        match token.token_type {
+            TokenType::LeftParen => open.push(index),
+            TokenType::RightParen => {
+                if let Some(start) = open.pop() {
+                    pairs.push((start, index));
+                }
+            }
+            _ => {}
+        }
+    }
+    // Removing a pair can make the pair around it redundant, as in "((x))", so the pairs are checked until none are
+    // removed.
+    let mut changed: bool = true;
+    while changed {
+        changed = false;
+        for &(start, end) in &pairs {
+            if removed[start] {
+                continue;
+            }
+            let kept = |range: Range<usize>| range.filter(|index| !removed[*index]).map(|index| tokens[index].token_type);
+            let mut inside = kept(start + 1..end);
+            let before: Option<TokenType> = kept(0..start).next_back();
+            let after: Option<TokenType> = kept(end + 1..tokens.len()).next();
+            let first: Option<TokenType> = inside.next();
+            let removable: bool = first.is_some_and(|first| !is_type_keyword(first))
+                && !kept(start + 1..end).any(|token_type| token_type == TokenType::Equals)
+                && matches!(
+                    before,
+                    None | Some(
+                        TokenType::Semicolon
+                            | TokenType::LeftBrace
+                            | TokenType::RightBrace
+                            | TokenType::LeftParen
+                            | TokenType::LeftBracket
+                            | TokenType::Comma
+                            | TokenType::Equals
+                    )
+                )
+                && matches!(
+                    after,
+                    Some(
+                        TokenType::Semicolon
+                            | TokenType::RightBrace
+                            | TokenType::RightParen
+                            | TokenType::RightBracket
+                            | TokenType::Comma
+                            | TokenType::EOF
+                    )
+                );
+            if removable {
+                removed[start] = true;
+                removed[end] = true;
+                changed = true;
+            }
+        }
+    }
+    removed
+}
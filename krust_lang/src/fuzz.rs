@@ -0,0 +1,137 @@
+//! The module for fuzzing, with entry points that cargo-fuzz targets can call with any input. Input that isn't valid is
+//! reported as logs, so a panic or hang in any of them is a bug. Parsed programs can also be generated with `Arbitrary`,
+//! which gives well-typed code that gets past the parser and type checker to the later stages.
+//!
+//! ```ignore
+//! fuzz_target!(|data: &[u8]| {
+//!     let _ = krust::fuzz::fuzz_run_bytecode(data);
+//! });
+//! ```
+
+use crate::{differential, lexer, parser, util::log, vm};
+use differential::{BOOL_OPERATORS, COMPARISONS, INT_OPERATORS};
+use lexer::lex;
+use log::Log;
+use parser::{parse, Expression, ParserOutput};
+use vm::Vm;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+use std::fmt::Write as _;
+use std::io;
+
+/// The most instructions that `fuzz_run_bytecode` runs, so that the fuzzer isn't stuck on bytecode that runs for a
+/// long time.
+pub const FUZZ_FUEL: u64 = 1_000_000;
+
+// How deeply the expressions of generated programs can be nested.
+const MAX_DEPTH: u32 = 4;
+
+/// Lexes the bytes as source code, returning the errors found. Bytes that aren't valid UTF-8 are replaced, as fuzzers
+/// often produce them.
+#[must_use]
+pub fn fuzz_lex(data: &[u8]) -> Vec<Log> {
+    lex(&String::from_utf8_lossy(data)).logs
+}
+
+/// Lexes and parses the source code, returning the errors found. Code nested too deeply is reported, but parsing it
+/// still needs a stack of `PARSER_STACK_BYTES`, which the main thread has.
+#[must_use]
+pub fn fuzz_parse(source: &str) -> Vec<Log> {
+    parse(lex(source)).logs
+}
+
+/// Verifies the bytes as bytecode and runs them if they pass, returning the errors found by the verifier or the
+/// runtime error that stopped the program. The program can't access files or read input, and its output is discarded.
+#[must_use]
+pub fn fuzz_run_bytecode(data: &[u8]) -> Vec<Log> {
+    let mut vm: Vm = Vm::new(data.to_vec());
+    vm.set_verify(true);
+    vm.set_input(io::empty());
+    vm.set_output(io::sink());
+    vm.set_error_output(io::sink());
+    vm.run_with_fuel(FUZZ_FUEL).err().unwrap_or_default()
+}
+
+impl<'a> Arbitrary<'a> for ParserOutput {
+    /// Generates a well-typed program and parses it. The program declares ints and bools, and sometimes a function, and
+    /// ends with an int expression. It can still fail at runtime, such as by dividing by zero.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(parse(lex(&program(u)?)))
+    }
+}
+
+impl<'a> Arbitrary<'a> for Expression {
+    /// Generates the AST of a well-typed program. Its tokens point into the source of the program, which is the
+    /// `file_text` of the `ParserOutput` generated from the same data.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        ParserOutput::arbitrary(u).map(|output| output.expr)
+    }
+}
+
+// Generates the source code of a well-typed program.
+fn program(u: &mut Unstructured) -> Result<String> {
+    let mut code: String = String::new();
+    let mut ints: Vec<String> = Vec::new();
+    let mut bools: Vec<String> = Vec::new();
+    let has_function: bool = u.arbitrary()?;
+    if has_function {
+        let body: String = int_expression(u, MAX_DEPTH, &["a".to_string()], &["p".to_string()])?;
+        let _ = writeln!(code, "fn f(a: int, p: bool) -> int {{ {body} }}"); // Writing to a string can't fail.
+    }
+    for index in 0..u.int_in_range(0..=4)? {
+        if u.arbitrary()? {
+            let value: String = int_expression(u, MAX_DEPTH, &ints, &bools)?;
+            let _ = writeln!(code, "int i{index} = {value};");
+            ints.push(format!("i{index}"));
+        } else {
+            let value: String = bool_expression(u, MAX_DEPTH, &ints, &bools)?;
+            let _ = writeln!(code, "bool b{index} = {value};");
+            bools.push(format!("b{index}"));
+        }
+    }
+    if let (true, Some(int), Some(bool)) = (has_function, ints.last(), bools.last()) {
+        ints.push(format!("f({int}, {bool})"));
+    }
+    code.push_str(&int_expression(u, MAX_DEPTH, &ints, &bools)?);
+    Ok(code)
+}
+
+// Generates an int expression up to the given depth, which can use the int variables and bool variables given.
+fn int_expression(u: &mut Unstructured, depth: u32, ints: &[String], bools: &[String]) -> Result<String> {
+    if depth == 0 || u.ratio(1, 3)? {
+        return if !ints.is_empty() && u.arbitrary()? {
+            u.choose(ints).cloned()
+        } else {
+            Ok(format!("({})", i32::arbitrary(u)?))
+        };
+    }
+    let int = |u: &mut Unstructured| int_expression(u, depth - 1, ints, bools);
+    Ok(match u.int_in_range(0..=5)? {
+        0 => format!("(-{})", int(u)?),
+        1 => format!("(~{})", int(u)?),
+        2 => format!("((int) {})", bool_expression(u, depth - 1, ints, bools)?),
+        3 => format!("{}({}, {})", u.choose(&["min", "max", "pow"])?, int(u)?, int(u)?),
+        _ => format!("({} {} {})", int(u)?, u.choose(&INT_OPERATORS)?, int(u)?),
+    })
+}
+
+// Generates a bool expression up to the given depth, which can use the int variables and bool variables given.
+fn bool_expression(u: &mut Unstructured, depth: u32, ints: &[String], bools: &[String]) -> Result<String> {
+    if depth == 0 || u.ratio(1, 3)? {
+        return if !bools.is_empty() && u.arbitrary()? {
+            u.choose(bools).cloned()
+        } else {
+            Ok(bool::arbitrary(u)?.to_string())
+        };
+    }
+    let boolean = |u: &mut Unstructured| bool_expression(u, depth - 1, ints, bools);
+    Ok(match u.int_in_range(0..=3)? {
+        0 => format!("(!{})", boolean(u)?),
+        1 => format!("({} {} {})", boolean(u)?, u.choose(&BOOL_OPERATORS)?, boolean(u)?),
+        _ => {
+            let left: String = int_expression(u, depth - 1, ints, bools)?;
+            let right: String = int_expression(u, depth - 1, ints, bools)?;
+            format!("({left} {} {right})", u.choose(&COMPARISONS)?)
+        }
+    })
+}
@@ -0,0 +1,140 @@
+//! The module for checking the invariants that the passes of the compiler rely on. These checks catch bugs in the
+//! compiler itself rather than in the code being compiled, and are enabled with the `-internal_checks` flag.
+
+use crate::{compiler, lexer, parser, util::log, verifier, visit};
+use compiler::{compile, CompilerOptions, CompilerOutput};
+use lexer::Token;
+use log::{ErrorType, Log, LogType};
+use parser::{Expression, ParserOutput};
+use verifier::{verify, VerifierOutput};
+use visit::children;
+
+/// Checks code that compiled without errors. Every expression in the AST must have a type, the generated bytecode
+/// (including the addresses of its calls) must pass the verifier, and every statement must leave only the variables
+/// it declares on the stack. Each violation is reported as an internal compiler error.
+#[must_use]
+pub fn check_internals(
+    expr: &Expression,
+    file_text: &str,
+    bytecode: &[u8],
+    options: CompilerOptions,
+) -> Vec<Log> {
+    let mut logs: Vec<Log> = Vec::new();
+    check_types(expr, &mut logs);
+    if !logs.is_empty() {
+        return logs; // The code can't be compiled again without types.
+    }
+    let output: VerifierOutput = verify(bytecode);
+    if output.logs.is_empty() {
+        check_stack_balance(expr, file_text, options, &mut logs);
+    } else {
+        push_error(
+            &mut logs,
+            "the generated bytecode failed verification.",
+            None,
+        );
+        logs.extend(output.logs);
+    }
+    logs
+}
+
+// Checks that every expression in the AST has a type. The children of an expression without a type are not checked
+// to avoid repeating the error.
+fn check_types(expr: &Expression, logs: &mut Vec<Log>) {
+    if expr.get_type().is_none() {
+        push_error(
+            logs,
+            "an expression has no type after parsing without errors.",
+            location(expr),
+        );
+        return;
+    }
+    for child in children(expr) {
+        check_types(child, logs);
+    }
+}
+
+// Checks that each statement at the top level of the code leaves only its variables on the stack. The code is
+// compiled up to and including each statement in turn, and the first statement that leaves any other values on the
+// stack is reported.
+fn check_stack_balance(
+    expr: &Expression,
+    file_text: &str,
+    options: CompilerOptions,
+    logs: &mut Vec<Log>,
+) {
+    let Expression::ExpressionList { list } = expr else {
+        return;
+    };
+    for end in 1..=list.len() {
+        let output: CompilerOutput = compile(
+            ParserOutput {
+                file_text: file_text.to_string(),
+                expr: Expression::ExpressionList {
+                    list: list[..end].to_vec(),
+                },
+                logs: Vec::new(),
+            },
+            options,
+        );
+        let Some(bytecode) = output.bytecode else {
+            push_error(
+                logs,
+                "the code up to a statement could not be compiled on its own.",
+                location(&list[end - 1]),
+            );
+            return;
+        };
+        let unused_stack_size: usize = verify(&bytecode).unused_stack_size;
+        if unused_stack_size != 0 {
+            push_error(
+                logs,
+                &format!("the statement leaves {unused_stack_size} unused bytes on the stack."),
+                location(&list[end - 1]),
+            );
+            return;
+        }
+    }
+}
+
+// Gets the token that an expression was created from, if it has one.
+fn own_token(expr: &Expression) -> Option<Token> {
+    match expr {
+        Expression::Binary { op: token, .. }
+        | Expression::Call { token, .. }
+        | Expression::FunctionCall { token, .. }
+        | Expression::HostCall { token, .. }
+        | Expression::MethodCall { token, .. }
+        | Expression::FunctionDeclaration { token, .. }
+        | Expression::TraitDeclaration { token, .. }
+        | Expression::FlagsDeclaration { token, .. }
+        | Expression::Literal { token, .. }
+        | Expression::MapLiteral { token, .. }
+        | Expression::ListLiteral { token, .. }
+        | Expression::Index { token, .. }
+        | Expression::Postfix { op: token, .. }
+        | Expression::StringLiteral { token, .. }
+        | Expression::Unary { op: token, .. }
+        | Expression::Variable { token, .. } => Some(*token),
+        _ => None,
+    }
+}
+
+// Gets the line and column where an expression starts, if it contains any tokens.
+fn location(expr: &Expression) -> Option<(usize, usize)> {
+    own_token(expr)
+        .map(|token| (token.line, token.col))
+        .into_iter()
+        .chain(children(expr).into_iter().filter_map(location))
+        .min()
+}
+
+// Adds an internal compiler error to the list of logs.
+fn push_error(logs: &mut Vec<Log>, context: &str, line_and_col: Option<(usize, usize)>) {
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::InternalCompilerError(context.to_string())),
+        line_and_col,
+        span: None,
+        notes: Vec::new(),
+    });
+}
@@ -0,0 +1,892 @@
+//! The module for the tree-walking interpreter, which runs the typed AST directly instead of compiling it to bytecode.
+//! It gives the same output and runtime errors as the VM, so that changes to the semantics of the language can be
+//! checked against two independent engines, and code can be run without generating any bytecode.
+//!
+//! Each function call that a program makes is a call in the interpreter, so deep recursion needs a large native
+//! stack. Programs that may recurse deeply should be interpreted on a thread with a stack of `STACK_BYTES`.
+
+use crate::{batch, compiler, lexer, lint, optimizer, parser, pass_manager, util::log, vm};
+use batch::LinkedSource;
+use compiler::{
+    check_unused_values, collect_functions, instance_name, resolve_method, split_tail_call, CompilerOptions, Instance,
+};
+use lexer::{Token, TokenType};
+use lint::lint;
+use log::{is_error, DiagnosticSink, ErrorType, Log, LogType, RenderOptions};
+use optimizer::fold_constants;
+use parser::{Builtin, Expression, ParserOutput, Type};
+use pass_manager::{CompilerPass, PassManager};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Write};
+use std::rc::Rc;
+use vm::{default_host_functions, stack_trace, ActiveCall, HostFunction, Value, VmConfig};
+
+/// The size of the native stack, in bytes, that the interpreter needs to make as many nested function calls as the
+/// VM allows by default.
+pub const STACK_BYTES: usize = 256 * 1024 * 1024;
+
+/// The output given when code is prepared for the interpreter.
+pub struct InterpreterOutput {
+    pub file_text: String,
+    /// The interpreter for the code, or None if the code has errors.
+    pub interpreter: Option<Interpreter>,
+    pub logs: Vec<Log>,
+}
+
+/// Prepares the parsed code to be run by the interpreter. Constants are folded first and calls in tail position reuse
+/// the call they are made from if the passes do so for bytecode, so that the program behaves the same as its bytecode
+/// would. The value of every statement at the top level is printed if the code is a script, as with
+/// `compiler::compile_script`.
+#[must_use]
+pub fn prepare(
+    parser_output: ParserOutput,
+    options: CompilerOptions,
+    script: bool,
+    passes: &PassManager,
+) -> InterpreterOutput {
+    let mut logs: Vec<Log> = parser_output.logs;
+    logs.append(&mut check_unused_values(&parser_output.expr, &parser_output.file_text, script));
+    logs.append(&mut lint(&parser_output.expr, &parser_output.file_text, &options.diagnostics));
+    logs = options.diagnostics.apply(logs);
+    let mut interpreter: Option<Interpreter> = None;
+    if !is_error(&logs) {
+        let mut expr: Expression = parser_output.expr;
+        if passes.is_enabled(CompilerPass::ConstantFolding) {
+            expr = fold_constants(&expr, &mut logs);
+            logs = options.diagnostics.apply(logs);
+        }
+        if !is_error(&logs) {
+            interpreter = Some(Interpreter {
+                expr: Rc::new(expr),
+                file_text: parser_output.file_text.clone(),
+                script,
+                tail_calls: passes.is_enabled(CompilerPass::TailCalls),
+                detailed_err: options.detailed_err,
+                args: Vec::new(),
+                host_functions: default_host_functions(),
+                file_access: false,
+                source: None,
+                config: VmConfig::default(),
+                output: Box::new(io::stdout()),
+                errors: Box::new(io::stderr()),
+                render: RenderOptions::default(),
+                sink: None,
+                printed: Vec::new(),
+                vars: Vec::new(),
+                frames: Vec::new(),
+                functions: Vec::new(),
+            });
+        }
+    }
+    InterpreterOutput {
+        file_text: parser_output.file_text,
+        interpreter,
+        logs,
+    }
+}
+
+/// Runs a program by evaluating its typed AST. It is set up the same way as the VM, with the same defaults.
+#[allow(clippy::struct_excessive_bools)] // Each is a separate setting of the run.
+pub struct Interpreter {
+    expr: Rc<Expression>,
+    file_text: String,
+    script: bool,
+    tail_calls: bool,
+    detailed_err: bool,
+    args: Vec<String>,
+    host_functions: HashMap<String, HostFunction>,
+    file_access: bool,
+    source: Option<LinkedSource>,
+    config: VmConfig,
+    output: Box<dyn Write>,
+    errors: Box<dyn Write>,
+    render: RenderOptions, // How runtime errors are written.
+    sink: Option<Box<dyn DiagnosticSink>>, // Where runtime errors are reported instead of errors, if anywhere.
+    printed: Vec<Value>,        // The values printed by the last run.
+    vars: Vec<(Token, Object)>, // The variables of the running function, by the tokens of their declarations.
+    frames: Vec<ActiveCall>,    // The active function calls, from the outermost.
+    functions: Vec<(Instance, Rc<Function>)>, // The instances called so far.
+}
+
+// An instance of a function, with the types in its body bound to the types it was called with.
+struct Function {
+    name: String,
+    params: Vec<Token>,
+    body: Expression,
+}
+
+// A value while the program runs. Lists and maps are shared between the places they are stored, like the handles
+// the VM uses for them, so changing one changes it everywhere.
+#[derive(Clone)]
+enum Object {
+    Void,
+    Int(i32),
+    Bool(bool),
+    String(String),
+    Null,
+    List(Rc<RefCell<Vec<Object>>>),
+    Map(Rc<RefCell<BTreeMap<Key, Object>>>),
+    Result(Result<Box<Object>, String>),
+}
+
+// The key of a map, which is ordered the same way as the keys of a printed map.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Key {
+    Int(i32),
+    Bool(bool),
+}
+
+// Why evaluation stopped before the expression gave a value.
+enum Stop {
+    Error(Vec<Log>), // A runtime error, along with its stack trace.
+    Return(Object),  // An error result returned early by the '?' operator.
+}
+
+impl Object {
+    fn int(&self) -> i32 {
+        match self {
+            Self::Int(value) => *value,
+            _ => panic!("the parser should have checked that this is an int"),
+        }
+    }
+
+    fn bool(&self) -> bool {
+        match self {
+            Self::Bool(value) => *value,
+            _ => panic!("the parser should have checked that this is a bool"),
+        }
+    }
+
+    fn string(&self) -> &str {
+        match self {
+            Self::String(value) => value,
+            _ => panic!("the parser should have checked that this is a string"),
+        }
+    }
+
+    fn list(&self) -> &RefCell<Vec<Self>> {
+        match self {
+            Self::List(list) => list,
+            _ => panic!("the parser should have checked that this is a list"),
+        }
+    }
+
+    fn map(&self) -> &RefCell<BTreeMap<Key, Self>> {
+        match self {
+            Self::Map(map) => map,
+            _ => panic!("the parser should have checked that this is a map"),
+        }
+    }
+
+    fn key(&self) -> Key {
+        match self {
+            Self::Int(value) => Key::Int(*value),
+            Self::Bool(value) => Key::Bool(*value),
+            _ => panic!("the parser should have checked that map keys are ints or bools"),
+        }
+    }
+
+    // Gets the value of a variable of the type before anything is assigned to it.
+    fn default_of(var_type: Option<&Type>) -> Self {
+        match var_type {
+            Some(Type::Int) => Self::Int(0),
+            Some(Type::Bool) => Self::Bool(false),
+            Some(Type::Nullable(_)) => Self::Null,
+            Some(Type::String) => Self::String(String::new()),
+            Some(Type::Map(..)) => Self::Map(Rc::default()),
+            Some(Type::List(_)) => Self::List(Rc::default()),
+            // Results start as an error with an empty message.
+            Some(Type::Result(_)) => Self::Result(Err(String::new())),
+            _ => Self::Void,
+        }
+    }
+
+    // Gets the value as the VM would print it.
+    fn to_value(&self) -> Value {
+        match self {
+            Self::Void => Value::Void,
+            Self::Int(value) => Value::Int(*value),
+            Self::Bool(value) => Value::Bool(*value),
+            Self::String(value) => Value::String(value.clone()),
+            Self::Null => Value::Null,
+            Self::List(list) => Value::List(list.borrow().iter().map(Self::to_value).collect()),
+            Self::Map(map) => Value::Map(
+                map.borrow()
+                    .iter()
+                    .map(|(key, value)| {
+                        let key: Value = match key {
+                            Key::Int(key) => Value::Int(*key),
+                            Key::Bool(key) => Value::Bool(*key),
+                        };
+                        (key, value.to_value())
+                    })
+                    .collect(),
+            ),
+            Self::Result(result) => Value::Result(
+                result
+                    .as_ref()
+                    .map(|value| Box::new(value.to_value()))
+                    .map_err(Clone::clone),
+            ),
+        }
+    }
+
+    // Checks whether or not two ints, bools, or nullable values are equal.
+    fn equals(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Int(left), Self::Int(right)) => left == right,
+            (Self::Bool(left), Self::Bool(right)) => left == right,
+            (Self::Null, Self::Null) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Interpreter {
+    /// Sets the arguments given to the program, which it can get with `args()`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    /// Sets whether or not the program can read and write files with `read_file` and `write_file`. File access is
+    /// disabled by default so that untrusted programs can't use it.
+    pub fn set_file_access(&mut self, file_access: bool) {
+        self.file_access = file_access;
+    }
+
+    /// Sets the files that the program was compiled from, so that runtime errors are printed with the file they happen
+    /// in and the line in that file.
+    pub fn set_linked_source(&mut self, source: LinkedSource) {
+        self.source = Some(source);
+    }
+
+    /// Sets the limits that the program runs within. Only the call depth applies, as the interpreter has no stack or
+    /// heap of its own.
+    pub fn set_config(&mut self, config: VmConfig) {
+        self.config = config;
+    }
+
+    /// Sets where the values the program prints are written, which is stdout by default. The printed values are also
+    /// kept for `output` whatever the stream is.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    /// Sets where runtime errors are written, which is stderr by default. The errors are also returned by `run`.
+    pub fn set_error_output(&mut self, errors: impl Write + 'static) {
+        self.errors = Box::new(errors);
+    }
+
+    /// Sets how runtime errors written to the error output are rendered, which is as plain text by default.
+    pub fn set_render_options(&mut self, options: RenderOptions) {
+        self.render = options;
+    }
+
+    /// Sets a sink that runtime errors are reported to instead of being written to the error output, like
+    /// `Vm::set_diagnostic_sink`. The errors are still returned by `run`.
+    pub fn set_diagnostic_sink(&mut self, sink: impl DiagnosticSink + 'static) {
+        self.sink = Some(Box::new(sink));
+    }
+
+    /// Registers a function that the program can call by name, replacing any host function with the same name.
+    pub fn register_host_function(
+        &mut self,
+        name: &str,
+        function: impl FnMut(&[i32]) -> Result<i32, String> + 'static,
+    ) {
+        self.host_functions
+            .insert(name.to_string(), Box::new(function));
+    }
+
+    /// Runs the program from the start, giving the last value it printed like `Vm::run`.
+    ///
+    /// # Errors
+    /// Returns the error that stopped the program, along with its stack trace. What it printed before the error can
+    /// still be read with `output`.
+    #[allow(clippy::missing_panics_doc)] // Should never actually panic.
+    pub fn run(&mut self) -> Result<Value, Vec<Log>> {
+        self.printed.clear();
+        self.vars.clear();
+        self.frames.clear();
+        let expr: Rc<Expression> = Rc::clone(&self.expr);
+        match self.run_program(&expr) {
+            Ok(()) => Ok(self.printed.last().cloned().unwrap_or(Value::Void)),
+            Err(Stop::Error(logs)) => {
+                if let Some(sink) = &mut self.sink {
+                    sink.report_all(&logs);
+                    return Err(logs);
+                }
+                for log in &logs {
+                    let _ = match &self.source {
+                        Some(source) => writeln!(self.errors, "{}", source.describe(log, self.render)),
+                        None => writeln!(self.errors, "{}", log.render(None, self.render)),
+                    };
+                }
+                Err(logs)
+            }
+            Err(Stop::Return(_)) => panic!("'?' should only be used in functions"),
+        }
+    }
+
+    /// Gets the lines printed by the last run.
+    #[must_use]
+    pub fn output(&self) -> Vec<String> {
+        self.printed.iter().map(ToString::to_string).collect()
+    }
+
+    // Runs the top level of the code and prints the value of its last expression. If the code is a script, the
+    // statements at the top level are printed instead of discarded, unless they declare or assign a variable.
+    fn run_program(&mut self, expr: &Expression) -> Result<(), Stop> {
+        let value: Object = match expr {
+            Expression::ExpressionList { list } => {
+                let mut value: Object = Object::Void;
+                for entry in list {
+                    value = match &**entry {
+                        Expression::Statement { expr }
+                            if self.script
+                                && !matches!(**expr, Expression::VariableDeclaration { .. })
+                                && !matches!(&**expr, Expression::Binary { op, .. } if op.token_type == TokenType::Equals) =>
+                        {
+                            let value: Object = self.eval(expr)?;
+                            self.print(&value, expr.get_type().as_ref());
+                            Object::Void
+                        }
+                        _ => self.eval(entry)?,
+                    };
+                }
+                value
+            }
+            _ => self.eval(expr)?,
+        };
+        self.print(&value, expr.get_type().as_ref());
+        Ok(())
+    }
+
+    // Prints a value of the type, unless the type has no values.
+    fn print(&mut self, value: &Object, value_type: Option<&Type>) {
+        if matches!(value_type, Some(Type::Void | Type::Type) | None) {
+            return;
+        }
+        let value: Value = value.to_value();
+        // A failed write, such as to a closed pipe, doesn't stop the program, as its output is still kept.
+        let _ = writeln!(self.output, "{value}");
+        self.printed.push(value);
+    }
+
+    #[allow(clippy::too_many_lines)] // Necessary for all the different expression types.
+    fn eval(&mut self, expr: &Expression) -> Result<Object, Stop> {
+        match expr {
+            Expression::Binary {
+                left, op, right, ..
+            } => self.eval_binary(left, *op, right),
+            Expression::Call {
+                token,
+                function,
+                args,
+                ..
+            } => {
+                let args: Vec<Object> = self.eval_args(args)?;
+                self.call_builtin(*token, *function, &args)
+            }
+            Expression::HostCall {
+                token, name, args, ..
+            } => {
+                let args: Vec<Object> = self.eval_args(args)?;
+                self.call_host(*token, name, &args)
+            }
+            Expression::Cast { expr_type, expr } => {
+                let value: Object = self.eval(expr)?;
+                Ok(match (value, expr_type) {
+                    (Object::Int(value), Some(Type::Bool)) => Object::Bool(value != 0),
+                    (Object::Bool(value), Some(Type::Int)) => Object::Int(i32::from(value)),
+                    // Nullable values and results hold their values as they are.
+                    (value, _) => value,
+                })
+            }
+            Expression::FunctionCall {
+                token,
+                declaration,
+                args,
+                type_args,
+                ..
+            } => {
+                let args: Vec<Object> = self.eval_args(args)?;
+                self.call(*token, &(*declaration, type_args.clone()), args)
+            }
+            // Method calls are instanced the same way as in the compiler, using the trait method and the type of the
+            // value it is called on.
+            Expression::MethodCall {
+                token,
+                method,
+                args,
+                ..
+            } => {
+                let instance: Instance = (
+                    *method,
+                    vec![args[0]
+                        .get_type()
+                        .expect("any \"None\" should have a parsing error")],
+                );
+                let args: Vec<Object> = self.eval_args(args)?;
+                self.call(*token, &instance, args)
+            }
+            Expression::ExpressionList { list } => {
+                let mut value: Object = Object::Void;
+                for expr in list {
+                    value = self.eval(expr)?;
+                }
+                Ok(value)
+            }
+            Expression::Grouping { expr, .. } => self.eval(expr),
+            Expression::Literal { token, .. } => Ok(match token.token_type {
+                TokenType::IntLiteral(value) => Object::Int(value.cast_signed()),
+                TokenType::True => Object::Bool(true),
+                TokenType::False => Object::Bool(false),
+                TokenType::Null => Object::Null,
+                _ => panic!("all literals should have been accounted for"),
+            }),
+            Expression::MapLiteral { entries, .. } => {
+                let mut map: BTreeMap<Key, Object> = BTreeMap::new();
+                for (key, value) in entries {
+                    let key: Key = self.eval(key)?.key();
+                    let value: Object = self.eval(value)?;
+                    map.insert(key, value);
+                }
+                Ok(Object::Map(Rc::new(RefCell::new(map))))
+            }
+            Expression::ListLiteral { elements, .. } => {
+                let elements: Vec<Object> = self.eval_args(elements)?;
+                Ok(Object::List(Rc::new(RefCell::new(elements))))
+            }
+            Expression::Index {
+                token, expr, index, ..
+            } => {
+                let list: Object = self.eval(expr)?;
+                let index: i32 = self.eval(index)?.int();
+                let element: Option<Object> = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| list.list().borrow().get(index).cloned());
+                element.ok_or_else(|| self.error(ErrorType::IndexOutOfRange, *token))
+            }
+            Expression::Postfix { op, expr, .. } => {
+                let value: Object = self.eval(expr)?;
+                match (op.token_type, value) {
+                    (TokenType::ExclamationMark, Object::Null) => {
+                        Err(self.error(ErrorType::NullUnwrap, *op))
+                    }
+                    (TokenType::ExclamationMark, Object::Result(Err(message))) => {
+                        Err(self.error(ErrorType::ErrUnwrap(message), *op))
+                    }
+                    (TokenType::QuestionMark, error @ Object::Result(Err(_))) => {
+                        Err(Stop::Return(error))
+                    }
+                    (_, Object::Result(Ok(value))) => Ok(*value),
+                    // A nullable value that isn't null is its inner value.
+                    (_, value) => Ok(value),
+                }
+            }
+            Expression::Statement { expr } => {
+                self.eval(expr)?;
+                Ok(Object::Void)
+            }
+            Expression::StringLiteral { value, .. } => Ok(Object::String(value.clone())),
+            Expression::Unary { op, expr, .. } => {
+                let value: Object = self.eval(expr)?;
+                Ok(match op.token_type {
+                    TokenType::Minus => Object::Int(value.int().wrapping_neg()),
+                    TokenType::Tilde => Object::Int(!value.int()),
+                    TokenType::ExclamationMark => Object::Bool(!value.bool()),
+                    _ => panic!("all unary operators should have been accounted for"),
+                })
+            }
+            // Variables that aren't initialized have no value, as in the compiler.
+            Expression::Variable {
+                initialized, token, ..
+            } => Ok(if *initialized {
+                self.vars
+                    .iter()
+                    .rev()
+                    .find(|(other, _)| other == token)
+                    .map(|(_, value)| value.clone())
+                    .expect("variable should have been declared")
+            } else {
+                Object::Void
+            }),
+            Expression::VariableDeclaration { initialized_var } => {
+                Ok(self.declare(initialized_var))
+            }
+            // Declarations only matter where they are used, and type expressions don't occur in isolation.
+            Expression::CastOp { .. }
+            | Expression::FunctionDeclaration { .. }
+            | Expression::TraitDeclaration { .. }
+            | Expression::FlagsDeclaration { .. }
+            | Expression::ExternDeclaration { .. }
+            | Expression::ImplDeclaration { .. }
+            | Expression::Type { .. }
+            | Expression::Void => Ok(Object::Void),
+            Expression::Error { .. } | Expression::EOF | Expression::Null => {
+                panic!("all expression types should have been accounted for")
+            }
+        }
+    }
+
+    // Evaluates the expressions in order.
+    fn eval_args(&mut self, args: &[Box<Expression>]) -> Result<Vec<Object>, Stop> {
+        args.iter().map(|arg| self.eval(arg)).collect()
+    }
+
+    // Declares a variable with the default value of its type, which is the value of the declaration.
+    fn declare(&mut self, initialized_var: &Expression) -> Object {
+        let Expression::Variable {
+            token, expr_type, ..
+        } = initialized_var
+        else {
+            panic!("variable declarations should always contain variables.")
+        };
+        let value: Object = Object::default_of(expr_type.as_ref());
+        self.vars.push((*token, value.clone()));
+        value
+    }
+
+    // Evaluates a binary expression or an assignment. Ints wrap on overflow, as they do in the VM.
+    fn eval_binary(
+        &mut self,
+        left: &Expression,
+        op: Token,
+        right: &Expression,
+    ) -> Result<Object, Stop> {
+        if op.token_type == TokenType::Equals {
+            let mut var: &Expression = left;
+            if let Expression::VariableDeclaration { initialized_var } = left {
+                self.declare(initialized_var);
+                var = initialized_var;
+            }
+            let value: Object = self.eval(right)?;
+            if let Expression::Variable {
+                initialized: true,
+                token,
+                ..
+            } = var
+            {
+                if let Some((_, stored)) =
+                    self.vars.iter_mut().rev().find(|(other, _)| other == token)
+                {
+                    *stored = value.clone();
+                }
+            }
+            return Ok(value);
+        }
+        let left: Object = self.eval(left)?;
+        let right: Object = self.eval(right)?;
+        Ok(match (op.token_type, &left, &right) {
+            (TokenType::Equality, ..) => Object::Bool(left.equals(&right)),
+            (TokenType::Inequality, ..) => Object::Bool(!left.equals(&right)),
+            (TokenType::Ampersand, Object::Bool(left), Object::Bool(right)) => {
+                Object::Bool(left & right)
+            }
+            (TokenType::Bar, Object::Bool(left), Object::Bool(right)) => Object::Bool(left | right),
+            (TokenType::Caret, Object::Bool(left), Object::Bool(right)) => {
+                Object::Bool(left ^ right)
+            }
+            (TokenType::Less, ..) => Object::Bool(left.int() < right.int()),
+            (TokenType::LessEqual, ..) => Object::Bool(left.int() <= right.int()),
+            (TokenType::Greater, ..) => Object::Bool(left.int() > right.int()),
+            (TokenType::GreaterEqual, ..) => Object::Bool(left.int() >= right.int()),
+            (TokenType::Slash | TokenType::Percent, _, Object::Int(0)) => {
+                return Err(self.error(ErrorType::DivideByZero, op));
+            }
+            (token_type, ..) => {
+                let (left, right): (i32, i32) = (left.int(), right.int());
+                Object::Int(match token_type {
+                    TokenType::Plus => left.wrapping_add(right),
+                    TokenType::Minus => left.wrapping_sub(right),
+                    TokenType::Star => left.wrapping_mul(right),
+                    TokenType::Slash => left.wrapping_div(right),
+                    TokenType::Percent => left.wrapping_rem_euclid(right),
+                    TokenType::Ampersand => left & right,
+                    TokenType::Bar => left | right,
+                    TokenType::Caret => left ^ right,
+                    TokenType::LeftShift => shift(left, right),
+                    // A right shift is a left shift by the negated amount.
+                    TokenType::RightShift => shift(left, right.wrapping_neg()),
+                    _ => panic!("invalid token found at head of binary expression."),
+                })
+            }
+        })
+    }
+
+    // Calls a built-in function with its evaluated arguments.
+    #[allow(clippy::too_many_lines)] // Each built-in function is only a few lines.
+    fn call_builtin(
+        &mut self,
+        token: Token,
+        function: Builtin,
+        args: &[Object],
+    ) -> Result<Object, Stop> {
+        let int = |index: usize| args[index].int();
+        Ok(match function {
+            Builtin::Abs => Object::Int(int(0).wrapping_abs()),
+            Builtin::Min => Object::Int(int(0).min(int(1))),
+            Builtin::Max => Object::Int(int(0).max(int(1))),
+            Builtin::Pow => Object::Int(pow(int(0), int(1))),
+            Builtin::Sqrt if int(0) < 0 => {
+                return Err(self.error(ErrorType::NegativeSquareRoot, token));
+            }
+            Builtin::Sqrt => Object::Int(int(0).isqrt()),
+            // The lower bound is prioritized if the bounds overlap.
+            Builtin::Clamp => Object::Int(int(0).min(int(2)).max(int(1))),
+            Builtin::Len => {
+                let length: usize = match &args[0] {
+                    Object::List(list) => list.borrow().len(),
+                    Object::Map(map) => map.borrow().len(),
+                    value => value.string().chars().count(),
+                };
+                Object::Int(i32::try_from(length).unwrap_or(i32::MAX))
+            }
+            Builtin::Substring => {
+                let value: &str = args[0].string();
+                let length: usize = value.chars().count();
+                let range: Option<(usize, usize)> = usize::try_from(int(1))
+                    .ok()
+                    .zip(usize::try_from(int(2)).ok());
+                match range {
+                    Some((start, end)) if start <= end && end <= length => {
+                        Object::String(value.chars().skip(start).take(end - start).collect())
+                    }
+                    _ => return Err(self.error(ErrorType::SubstringOutOfRange, token)),
+                }
+            }
+            Builtin::Contains => Object::Bool(args[0].string().contains(args[1].string())),
+            Builtin::Insert => {
+                args[0]
+                    .map()
+                    .borrow_mut()
+                    .insert(args[1].key(), args[2].clone());
+                args[0].clone()
+            }
+            Builtin::Get => args[0]
+                .map()
+                .borrow()
+                .get(&args[1].key())
+                .cloned()
+                .unwrap_or(Object::Null),
+            Builtin::Remove => args[0]
+                .map()
+                .borrow_mut()
+                .remove(&args[1].key())
+                .unwrap_or(Object::Null),
+            Builtin::Push => {
+                args[0].list().borrow_mut().push(args[1].clone());
+                args[0].clone()
+            }
+            Builtin::Pop => args[0].list().borrow_mut().pop().unwrap_or(Object::Null),
+            Builtin::Ok => Object::Result(Ok(Box::new(args[0].clone()))),
+            Builtin::Err => Object::Result(Err(args[0].string().to_string())),
+            Builtin::IsOk => Object::Bool(matches!(args[0], Object::Result(Ok(_)))),
+            Builtin::Panic => {
+                return Err(self.error(ErrorType::Panic(args[0].string().to_string()), token));
+            }
+            Builtin::Assert => {
+                if !args[0].bool() {
+                    return Err(self.error(ErrorType::AssertionFailed(args[1].string().to_string()), token));
+                }
+                Object::Void
+            }
+            Builtin::ToString => Object::String(int(0).to_string()),
+            Builtin::ParseInt => {
+                let text: &str = args[0].string();
+                Object::Result(match text.parse::<i32>() {
+                    Ok(value) => Ok(Box::new(Object::Int(value))),
+                    Err(_) => Err(format!("\"{text}\" is not a valid int.")),
+                })
+            }
+            Builtin::Args => Object::List(Rc::new(RefCell::new(
+                self.args.iter().cloned().map(Object::String).collect(),
+            ))),
+            // These are provided by the host functions of the same name that are registered by default.
+            Builtin::RandomInt | Builtin::NowMillis => {
+                return self.call_host(token, function.name(), args);
+            }
+            Builtin::ReadFile => {
+                let path: &str = args[0].string();
+                let result: Result<String, ErrorType> = if self.file_access {
+                    fs::read_to_string(path).map_err(|error| {
+                        ErrorType::FileReadFailed(path.to_string(), error.to_string())
+                    })
+                } else {
+                    Err(ErrorType::FileAccessDisabled)
+                };
+                Object::String(result.map_err(|error| self.error(error, token))?)
+            }
+            Builtin::WriteFile => {
+                let path: &str = args[0].string();
+                let result: Result<(), ErrorType> = if self.file_access {
+                    fs::write(path, args[1].string()).map_err(|error| {
+                        ErrorType::FileWriteFailed(path.to_string(), error.to_string())
+                    })
+                } else {
+                    Err(ErrorType::FileAccessDisabled)
+                };
+                result.map_err(|error| self.error(error, token))?;
+                Object::Void
+            }
+        })
+    }
+
+    // Calls a host function with the ints as its arguments.
+    fn call_host(&mut self, token: Token, name: &str, args: &[Object]) -> Result<Object, Stop> {
+        let args: Vec<i32> = args.iter().map(Object::int).collect();
+        let result: Result<i32, ErrorType> = match self.host_functions.get_mut(name) {
+            Some(function) => function(&args)
+                .map_err(|message| ErrorType::HostFunctionFailed(name.to_string(), message)),
+            None => Err(ErrorType::UnknownHostFunction(name.to_string())),
+        };
+        result
+            .map(Object::Int)
+            .map_err(|error| self.error(error, token))
+    }
+
+    // Calls an instance of a function with the arguments. If tail calls are enabled, a call of the same instance at
+    // the end of its body reuses the call instead of making a new one, so that the call depth doesn't increase.
+    fn call(
+        &mut self,
+        token: Token,
+        instance: &Instance,
+        mut args: Vec<Object>,
+    ) -> Result<Object, Stop> {
+        if self.frames.len() >= self.config.max_call_depth {
+            return Err(self.error(
+                ErrorType::CallDepthExceeded(self.config.max_call_depth),
+                token,
+            ));
+        }
+        let function: Rc<Function> = self.function(instance);
+        self.frames
+            .push((function.name.clone(), self.location(token)));
+        let caller_vars: Vec<(Token, Object)> = std::mem::take(&mut self.vars);
+        let tail_call = split_tail_call(&function.body, instance).filter(|_| self.tail_calls);
+        let value: Result<Object, Stop> = loop {
+            self.vars = function.params.iter().copied().zip(args).collect();
+            let Some((statements, tail_args)) = tail_call else {
+                break self.eval(&function.body);
+            };
+            let next_args: Result<Vec<Object>, Stop> = self
+                .eval_args(statements)
+                .and_then(|_| self.eval_args(tail_args));
+            match next_args {
+                Ok(next_args) => args = next_args,
+                Err(stop) => break Err(stop),
+            }
+        };
+        self.vars = caller_vars;
+        self.frames.pop();
+        match value {
+            Err(Stop::Return(value)) => Ok(value),
+            value => value,
+        }
+    }
+
+    // Gets an instance of a function, with the types in its body bound to the types of the instance. Methods are
+    // resolved to the method of the impl block for the type they are called on.
+    fn function(&mut self, instance: &Instance) -> Rc<Function> {
+        if let Some((_, function)) = self.functions.iter().find(|(other, _)| other == instance) {
+            return Rc::clone(function);
+        }
+        let expr: Rc<Expression> = Rc::clone(&self.expr);
+        let mut declarations: Vec<&Expression> = Vec::new();
+        collect_functions(&expr, &mut declarations);
+        let (token, type_args): &Instance = instance;
+        let declaration: Option<&Expression> = resolve_method(&declarations, *token, type_args).or_else(|| {
+            declarations.iter().copied().find(|declaration| {
+                matches!(declaration, Expression::FunctionDeclaration { token: other, .. } if other == token)
+            })
+        });
+        let Some(Expression::FunctionDeclaration {
+            type_params,
+            params,
+            body,
+            ..
+        }) = declaration
+        else {
+            panic!("called functions should have been declared")
+        };
+        let substitutions: HashMap<String, Type> = type_params
+            .iter()
+            .cloned()
+            .zip(type_args.iter().cloned())
+            .collect();
+        let function: Rc<Function> = Rc::new(Function {
+            name: instance_name(&declarations, &self.file_text, instance),
+            params: params
+                .iter()
+                .filter_map(|param| match **param {
+                    Expression::Variable { token, .. } => Some(token),
+                    _ => None,
+                })
+                .collect(),
+            body: body.substitute_types(&substitutions),
+        });
+        self.functions
+            .push((instance.clone(), Rc::clone(&function)));
+        function
+    }
+
+    // Gets the line and column of the token, if runtime errors give them.
+    fn location(&self, token: Token) -> Option<(usize, usize)> {
+        self.detailed_err.then_some((token.line, token.col))
+    }
+
+    // Creates a runtime error at the token. Like in the VM, errors in a function, panics, and failed assertions are
+    // followed by a stack trace.
+    fn error(&self, error: ErrorType, token: Token) -> Stop {
+        let panic: bool = matches!(error, ErrorType::Panic(_) | ErrorType::AssertionFailed(_));
+        let line_and_col: Option<(usize, usize)> = self.location(token);
+        let mut logs: Vec<Log> = vec![Log {
+            log_type: LogType::Error(error),
+            line_and_col,
+            span: None,
+            notes: Vec::new(),
+        }];
+        if !self.frames.is_empty() || panic {
+            logs.append(&mut stack_trace(&self.frames, line_and_col));
+        }
+        Stop::Error(logs)
+    }
+}
+
+// Shifts an int left by the amount, or right if the amount is negative. Shifting by more than the width of an int
+// gives 0, or -1 for a negative int shifted right.
+fn shift(value: i32, amount: i32) -> i32 {
+    if amount > 31 {
+        0
+    } else if amount < -31 {
+        if value < 0 {
+            -1
+        } else {
+            0
+        }
+    } else if amount >= 0 {
+        value.wrapping_shl(amount.unsigned_abs())
+    } else {
+        value >> amount.unsigned_abs()
+    }
+}
+
+// Raises an int to a power. Ints other than 1 and -1 raised to a negative power give 0, as their reciprocals aren't
+// ints.
+fn pow(base: i32, exponent: i32) -> i32 {
+    match u32::try_from(exponent) {
+        Ok(exponent) => base.wrapping_pow(exponent),
+        Err(_) if base == 1 => 1,
+        Err(_) if base == -1 => {
+            if exponent % 2 == 0 {
+                1
+            } else {
+                -1
+            }
+        }
+        Err(_) => 0,
+    }
+}
@@ -2,13 +2,14 @@
 
 use crate::util::{log, trie};
 use log::{ErrorType, Log, LogType};
-use std::collections::HashMap;
-use std::num::ParseIntError;
+use std::ops::Range;
+use std::sync::OnceLock;
 
 use trie::Node;
 
 /// A token representing an indivisible piece of the source code.
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Token {
     pub token_type: TokenType,
 
@@ -16,12 +17,41 @@ pub struct Token {
     pub line: usize,
     pub col: usize,
 
+    // The position of the token in the source, in bytes. While lexing these count characters instead, and they are
+    // converted to bytes once every token is found.
     start: usize,
     length: usize,
+    width: usize, // The number of characters in the token, which is the number of columns it takes up.
+}
+
+/// A range of the source code, such as the text of a token or of a whole expression.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    /// The line and column of the first character.
+    pub start: (usize, usize),
+    /// The line and column just after the last character.
+    pub end: (usize, usize),
+    /// The position of the text in the source, in bytes.
+    pub byte_range: Range<usize>,
+}
+
+impl Span {
+    /// Gets the smallest span that covers both this span and the other one.
+    #[must_use]
+    pub fn to(&self, other: &Self) -> Self {
+        Self {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+            byte_range: self.byte_range.start.min(other.byte_range.start)
+                ..self.byte_range.end.max(other.byte_range.end),
+        }
+    }
 }
 
 /// The allowed types of tokens.
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TokenType {
     // Single character tokens.
     Plus,
@@ -38,8 +68,16 @@ pub enum TokenType {
     Bar,
     LeftParen,
     RightParen,
+    LeftBracket,
+    RightBracket,
+    LeftBrace,
+    RightBrace,
+    Colon,
     Semicolon,
     Equals,
+    QuestionMark,
+    Comma,
+    Dot,
 
     // Multi-character tokens.
     LessEqual,
@@ -48,15 +86,29 @@ pub enum TokenType {
     RightShift,
     Equality,
     Inequality,
+    Arrow,
 
     // Literals
     IntLiteral(u32),
+    StringLiteral,
 
     // Keywords
     True,
     False,
     Int,
     Bool,
+    String,
+    Map,
+    List,
+    Result,
+    Null,
+    Fn,
+    TypeOf,
+    Trait,
+    Impl,
+    For,
+    Flags,
+    Extern,
 
     Other, // User defined tokens, like variable names.
 
@@ -72,7 +124,34 @@ impl Token {
         if self.token_type == TokenType::EOF {
             "EOF".to_string()
         } else {
-            source[self.start..(self.start + self.length)].to_string()
+            source[self.start..self.start + self.length].to_string()
+        }
+    }
+
+    /// Describes the token as its line and column, its type, and its text, such as `1:5 Plus "+"`. The end of the file
+    /// has no text, so it is described as just `2:1 EOF`.
+    #[must_use]
+    pub fn describe(&self, source: &str) -> String {
+        if self.token_type == TokenType::EOF {
+            format!("{}:{} EOF", self.line, self.col)
+        } else {
+            format!(
+                "{}:{} {:?} {:?}",
+                self.line,
+                self.col,
+                self.token_type,
+                self.to_string(source)
+            )
+        }
+    }
+
+    /// Gets the part of the source the token covers. Tokens never cross lines.
+    #[must_use]
+    pub fn span(&self) -> Span {
+        Span {
+            start: (self.line, self.col),
+            end: (self.line, self.col + self.width),
+            byte_range: self.start..self.start + self.length,
         }
     }
 }
@@ -86,66 +165,158 @@ pub struct LexerOutput {
 
 /// Lexes the file given in the command line.
 #[must_use]
-pub fn lex(file_text: String) -> LexerOutput {
+pub fn lex(file_text: &str) -> LexerOutput {
+    // The trie is only created once, the first time it's needed.
+    static KEYWORD_TRIE: OnceLock<Node<char, TokenType>> = OnceLock::new();
+    lex_with_keywords(file_text, KEYWORD_TRIE.get_or_init(keyword_trie))
+}
+
+/// The keywords of the language and the types of their tokens.
+pub const KEYWORDS: [(&str, TokenType); 16] = [
+    ("true", TokenType::True),
+    ("false", TokenType::False),
+    ("int", TokenType::Int),
+    ("bool", TokenType::Bool),
+    ("string", TokenType::String),
+    ("map", TokenType::Map),
+    ("list", TokenType::List),
+    ("result", TokenType::Result),
+    ("null", TokenType::Null),
+    ("fn", TokenType::Fn),
+    ("typeof", TokenType::TypeOf),
+    ("trait", TokenType::Trait),
+    ("impl", TokenType::Impl),
+    ("for", TokenType::For),
+    ("flags", TokenType::Flags),
+    ("extern", TokenType::Extern),
+];
+
+/// The operators that are written with more than one character.
+pub const MULTI_CHAR_OPERATORS: [&str; 7] = ["<=", ">=", "<<", ">>", "==", "!=", "->"];
+
+/// Creates the trie used to detect keywords. This can be reused between calls to `lex_with_keywords`.
+#[must_use]
+pub fn keyword_trie() -> Node<char, TokenType> {
+    Node::new_with_string(
+        KEYWORDS
+            .iter()
+            .map(|(keyword, token_type)| ((*keyword).to_string(), *token_type))
+            .collect(),
+    )
+}
+
+/// Lexes the file using an already created keyword trie.
+#[must_use]
+pub fn lex_with_keywords(file_text: &str, trie: &Node<char, TokenType>) -> LexerOutput {
     // Prepare fields for output.
     let mut tokens: Vec<Token> = Vec::new();
     let mut logs: Vec<Log> = Vec::new();
 
+    // The characters are collected once so that looking ahead takes constant time.
+    let chars: Vec<char> = file_text.chars().collect();
+
     // Values for the tokens.
     let mut index: usize = 0;
     let mut line: usize = 1;
     let mut col: usize = 1;
 
-    // Create the trie for the lexer so it only has to be made once.
-    let trie: Node<char, TokenType> = Node::new_with_string(vec![
-        ("true".to_string(), TokenType::True),
-        ("false".to_string(), TokenType::False),
-        ("int".to_string(), TokenType::Int),
-        ("bool".to_string(), TokenType::Bool),
-    ]);
-
     // Loop through each token until the end of the file is found.
-    loop {
-        let output: Option<LexerOutput> = get_token(
-            &file_text,
-            &mut tokens,
-            &mut logs,
-            &mut index,
-            &mut line,
-            &mut col,
-            &trie,
-        );
-        if let Some(out) = output {
-            return out;
+    while !get_token(
+        &chars,
+        &mut tokens,
+        &mut logs,
+        &mut index,
+        &mut line,
+        &mut col,
+        trie,
+    ) {}
+    convert_to_byte_positions(&chars, &mut tokens);
+    // The spans of errors also count characters until now.
+    for log in &mut logs {
+        if let Some(span) = &mut log.span {
+            span.byte_range = byte_position(&chars, span.byte_range.start)
+                ..byte_position(&chars, span.byte_range.end);
+        }
+    }
+    LexerOutput {
+        file_text: file_text.to_string(),
+        tokens,
+        logs,
+    }
+}
+
+// Converts the positions of the tokens from characters to bytes. The tokens are in order and don't overlap, so this
+// only has to walk the source once.
+fn convert_to_byte_positions(chars: &[char], tokens: &mut [Token]) {
+    let mut char_index: usize = 0;
+    let mut byte_index: usize = 0;
+    let mut advance_to = |target: usize| -> usize {
+        while char_index < target {
+            byte_index += chars[char_index].len_utf8();
+            char_index += 1;
         }
+        byte_index
+    };
+    for token in tokens {
+        let start: usize = advance_to(token.start);
+        let end: usize = advance_to(token.start + token.length);
+        token.start = start;
+        token.length = end - start;
+    }
+}
+
+// Gets the byte position of a character in the source.
+fn byte_position(chars: &[char], char_index: usize) -> usize {
+    chars[..char_index].iter().map(|c| c.len_utf8()).sum()
+}
+
+// Gets the span of some characters on a line, which is counted in characters until the tokens are converted to bytes.
+fn char_span(line: usize, col: usize, start: usize, length: usize) -> Span {
+    Span {
+        start: (line, col),
+        end: (line, col + length),
+        byte_range: start..start + length,
+    }
+}
+
+// Gets the token type of a token that is always a single character.
+fn single_char_token_type(c: char) -> Option<TokenType> {
+    match c {
+        '+' => Some(TokenType::Plus),
+        '-' => Some(TokenType::Minus),
+        '*' => Some(TokenType::Star),
+        '/' => Some(TokenType::Slash),
+        '%' => Some(TokenType::Percent),
+        '~' => Some(TokenType::Tilde),
+        '&' => Some(TokenType::Ampersand),
+        '^' => Some(TokenType::Caret),
+        '|' => Some(TokenType::Bar),
+        '(' => Some(TokenType::LeftParen),
+        ')' => Some(TokenType::RightParen),
+        '[' => Some(TokenType::LeftBracket),
+        ']' => Some(TokenType::RightBracket),
+        '{' => Some(TokenType::LeftBrace),
+        '}' => Some(TokenType::RightBrace),
+        ':' => Some(TokenType::Colon),
+        ';' => Some(TokenType::Semicolon),
+        '?' => Some(TokenType::QuestionMark),
+        ',' => Some(TokenType::Comma),
+        '.' => Some(TokenType::Dot),
+        _ => None,
     }
 }
 
-// Gets the next token in the code.
+// Gets the next token in the code. Returns true once the end of the file is reached.
 fn get_token(
-    file_text: &String,
+    chars: &[char],
     tokens: &mut Vec<Token>,
     logs: &mut Vec<Log>,
     index: &mut usize,
     line: &mut usize,
     col: &mut usize,
     trie: &Node<char, TokenType>,
-) -> Option<LexerOutput> {
-    let c: Option<char> = file_text.chars().nth(*index);
-    let token_dict: HashMap<char, TokenType> = HashMap::from([
-        ('+', TokenType::Plus),
-        ('-', TokenType::Minus),
-        ('*', TokenType::Star),
-        ('/', TokenType::Slash),
-        ('%', TokenType::Percent),
-        ('~', TokenType::Tilde),
-        ('&', TokenType::Ampersand),
-        ('^', TokenType::Caret),
-        ('|', TokenType::Bar),
-        ('(', TokenType::LeftParen),
-        (')', TokenType::RightParen),
-        (';', TokenType::Semicolon),
-    ]);
+) -> bool {
+    let c: Option<char> = chars.get(*index).copied();
 
     // EOF
     if c.is_none() {
@@ -155,42 +326,61 @@ fn get_token(
             col: *col,
             start: *index,
             length: 0,
+            width: 0,
         });
-        return Some(LexerOutput {
-            file_text: file_text.to_string(),
-            tokens: tokens.clone(),
-            logs: logs.clone(),
-        });
+        return true;
     }
     let c: char = c.expect("should be valid as error handled earlier");
+    // Comments, which continue to the end of the line.
+    if c == '/' && chars.get(*index + 1) == Some(&'/') {
+        let length: usize = chars[*index..].iter().take_while(|c| **c != '\n').count();
+        *index += length;
+        *col += length;
+    }
+    // The arrow before the return type of a function, which would otherwise be lexed as '-' and '>'.
+    else if c == '-' && chars.get(*index + 1) == Some(&'>') {
+        tokens.push(Token {
+            token_type: TokenType::Arrow,
+            line: *line,
+            col: *col,
+            start: *index,
+            length: 2,
+            width: 2,
+        });
+        *index += 2;
+        *col += 2;
+    }
     // Single character tokens.
-    if token_dict.contains_key(&c) {
+    else if let Some(token_type) = single_char_token_type(c) {
         tokens.push(Token {
-            token_type: token_dict[&c],
+            token_type,
             line: *line,
             col: *col,
             start: *index,
             length: 1,
+            width: 1,
         });
         *index += 1;
         *col += 1;
     } else if c == ' ' || c == '\t' || c == '\n' || c == '\r' {
-        handle_white_space(file_text, c, line, col, index);
-    } else if handle_equals(file_text, tokens, line, col, index)
-        || handle_ineq(file_text, tokens, line, col, index)
+        handle_white_space(chars, c, line, col, index);
+    } else if handle_equals(chars, tokens, line, col, index)
+        || handle_ineq(chars, tokens, line, col, index)
     {
     } else if c.is_ascii_digit() {
-        handle_number(file_text, tokens, logs, line, col, index);
+        handle_number(chars, tokens, logs, line, col, index);
+    } else if c == '"' {
+        handle_string(chars, tokens, logs, line, col, index);
     } else {
-        handle_other(file_text, tokens, line, col, index, trie);
+        handle_other(chars, tokens, line, col, index, trie);
     }
 
-    None
+    false
 }
 
 // Handles white space.
 fn handle_white_space(
-    file_text: &str,
+    chars: &[char],
     c: char,
     line: &mut usize,
     col: &mut usize,
@@ -200,7 +390,7 @@ fn handle_white_space(
     if c == '\r' || c == '\n' {
         *line += 1;
         *col = 1;
-        if c == '\r' && file_text.chars().nth(*index + 1) == Some('\n') {
+        if c == '\r' && chars.get(*index + 1).copied() == Some('\n') {
             *index += 1;
         }
     } else {
@@ -211,15 +401,15 @@ fn handle_white_space(
 
 // Handles tokens using the equals sign.
 fn handle_equals(
-    file_text: &str,
+    chars: &[char],
     tokens: &mut Vec<Token>,
     line: &mut usize,
     col: &mut usize,
     index: &mut usize,
 ) -> bool {
-    let c: Option<char> = file_text.chars().nth(*index);
+    let c: Option<char> = chars.get(*index).copied();
     if c == Some('=') {
-        let c: Option<char> = file_text.chars().nth(*index + 1);
+        let c: Option<char> = chars.get(*index + 1).copied();
         if c == Some('=') {
             let token: Token = Token {
                 token_type: TokenType::Equality,
@@ -227,6 +417,7 @@ fn handle_equals(
                 col: *col,
                 start: *index,
                 length: 2,
+                width: 2,
             };
             tokens.push(token);
             *index += 2;
@@ -239,6 +430,7 @@ fn handle_equals(
                 col: *col,
                 start: *index,
                 length: 1,
+                width: 1,
             };
             tokens.push(token);
             *index += 1;
@@ -246,7 +438,7 @@ fn handle_equals(
             true
         }
     } else if c == Some('!') {
-        let c: Option<char> = file_text.chars().nth(*index + 1);
+        let c: Option<char> = chars.get(*index + 1).copied();
         if c == Some('=') {
             let token: Token = Token {
                 token_type: TokenType::Inequality,
@@ -254,6 +446,7 @@ fn handle_equals(
                 col: *col,
                 start: *index,
                 length: 2,
+                width: 2,
             };
             tokens.push(token);
             *index += 2;
@@ -266,6 +459,7 @@ fn handle_equals(
                 col: *col,
                 start: *index,
                 length: 1,
+                width: 1,
             };
             tokens.push(token);
             *index += 1;
@@ -279,14 +473,14 @@ fn handle_equals(
 
 // Handles inequality and shift tokens.
 fn handle_ineq(
-    file_text: &str,
+    chars: &[char],
     tokens: &mut Vec<Token>,
     line: &mut usize,
     col: &mut usize,
     index: &mut usize,
 ) -> bool {
     handle_ineq_char(
-        file_text,
+        chars,
         tokens,
         line,
         col,
@@ -294,7 +488,7 @@ fn handle_ineq(
         '<',
         [TokenType::Less, TokenType::LeftShift, TokenType::LessEqual],
     ) || handle_ineq_char(
-        file_text,
+        chars,
         tokens,
         line,
         col,
@@ -310,7 +504,7 @@ fn handle_ineq(
 
 // Handles characters that are used in inequalities ('<', '>').
 fn handle_ineq_char(
-    file_text: &str,
+    chars: &[char],
     tokens: &mut Vec<Token>,
     line: &mut usize,
     col: &mut usize,
@@ -318,9 +512,9 @@ fn handle_ineq_char(
     ineq: char,
     token_list: [TokenType; 3], // Order should be strict inequality, shift, inequality.
 ) -> bool {
-    let c: Option<char> = file_text.chars().nth(*index);
+    let c: Option<char> = chars.get(*index).copied();
     if c == Some(ineq) {
-        let c: Option<char> = file_text.chars().nth(*index + 1);
+        let c: Option<char> = chars.get(*index + 1).copied();
         if c == Some(ineq) {
             let token: Token = Token {
                 token_type: token_list[1],
@@ -328,6 +522,7 @@ fn handle_ineq_char(
                 col: *col,
                 start: *index,
                 length: 2,
+                width: 2,
             };
             tokens.push(token);
             *index += 2;
@@ -340,6 +535,7 @@ fn handle_ineq_char(
                 col: *col,
                 start: *index,
                 length: 2,
+                width: 2,
             };
             tokens.push(token);
             *index += 2;
@@ -352,6 +548,7 @@ fn handle_ineq_char(
                 col: *col,
                 start: *index,
                 length: 1,
+                width: 1,
             };
             tokens.push(token);
             *index += 1;
@@ -363,48 +560,97 @@ fn handle_ineq_char(
     }
 }
 
-// Handles numerical tokens.
+// The suffixes that can follow an integer literal to select its type, along with the name of the type. Only "int"
+// is currently supported; the others are reserved for when their types exist.
+const INT_LITERAL_SUFFIXES: [(&str, &str); 3] = [("i", "int"), ("l", "long"), ("u", "uint")];
+
+// Handles numerical tokens, including any type suffix directly after the digits.
 fn handle_number(
-    file_text: &str,
+    chars: &[char],
     tokens: &mut Vec<Token>,
     logs: &mut Vec<Log>,
     line: &mut usize,
     col: &mut usize,
     index: &mut usize,
 ) {
-    let mut length: usize = 1;
-    while is_digit_option(file_text.chars().nth(*index + length)) {
+    let mut digits: usize = 1;
+    while is_digit_option(chars.get(*index + digits).copied()) {
+        digits += 1;
+    }
+    let mut length: usize = digits;
+    while chars
+        .get(*index + length)
+        .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_')
+    {
         length += 1;
     }
-    let int_literal: Result<u32, ParseIntError> =
-        file_text[*index..(*index + length)].parse::<u32>();
-    let token_type: TokenType = get_int_literal_token_type(int_literal);
+    let int_literal: Option<u32> = parse_digits(&chars[*index..*index + digits]);
+    let mut token_type: TokenType = get_int_literal_token_type(int_literal);
+    let suffix: &[char] = &chars[*index + digits..*index + length];
+    let suffix_error: Option<ErrorType> = match INT_LITERAL_SUFFIXES
+        .iter()
+        .find(|(name, _)| name.chars().eq(suffix.iter().copied()))
+    {
+        _ if suffix.is_empty() => None,
+        Some((_, "int")) => None,
+        Some((_, type_name)) => Some(ErrorType::UnsupportedIntegerSuffix(
+            suffix.iter().collect(),
+            (*type_name).to_string(),
+        )),
+        None => Some(ErrorType::InvalidIntegerSuffix(suffix.iter().collect())),
+    };
     let token: Token = Token {
         token_type,
         line: *line,
         col: *col,
         start: *index,
         length,
+        width: length,
     };
     if token.token_type == TokenType::Error {
         logs.push(Log {
             log_type: LogType::Error(ErrorType::UnrepresentableIntegerLiteral(
-                token.to_string(file_text),
+                chars[*index..*index + length].iter().collect(),
             )),
             line_and_col: Some((*line, *col)),
+            span: Some(token.span()),
+            notes: Vec::new(),
         });
+    } else if let Some(error) = suffix_error {
+        logs.push(Log {
+            log_type: LogType::Error(error),
+            line_and_col: Some((*line, *col + digits)),
+            span: Some(char_span(
+                *line,
+                *col + digits,
+                *index + digits,
+                length - digits,
+            )),
+            notes: Vec::new(),
+        });
+        token_type = TokenType::Error;
     }
-    tokens.push(token);
+    tokens.push(Token {
+        token_type,
+        ..token
+    });
     *index += length;
     *col += length;
 }
 
+// Gets the value of a string of digits, or None if it doesn't fit in a u32. This stops at the first digit that
+// overflows, so long literals don't have to be copied or read in full.
+fn parse_digits(digits: &[char]) -> Option<u32> {
+    digits.iter().try_fold(0_u32, |value: u32, digit: &char| {
+        value.checked_mul(10)?.checked_add(digit.to_digit(10)?)
+    })
+}
+
 // Converts an integer literal to a token type.
-fn get_int_literal_token_type(int_literal: Result<u32, ParseIntError>) -> TokenType {
-    if int_literal.is_err() {
+fn get_int_literal_token_type(int_literal: Option<u32>) -> TokenType {
+    let Some(value) = int_literal else {
         return TokenType::Error;
-    }
-    let value: u32 = int_literal.expect("should be valid as error handled earlier.");
+    };
 
     // 0x8000_0000 is the largest possible absolute value of an i32.
     if value > 0x8000_0000_u32 {
@@ -422,9 +668,70 @@ fn is_digit_option(c_option: Option<char>) -> bool {
     }
 }
 
+// Handles string literals. Escape sequences are checked here and replaced by the parser.
+fn handle_string(
+    chars: &[char],
+    tokens: &mut Vec<Token>,
+    logs: &mut Vec<Log>,
+    line: &mut usize,
+    col: &mut usize,
+    index: &mut usize,
+) {
+    let mut length: usize = 1;
+    let mut token_type: TokenType = TokenType::StringLiteral;
+    loop {
+        match chars.get(*index + length).copied() {
+            Some('"') => {
+                length += 1;
+                break;
+            }
+            None | Some('\n' | '\r') => {
+                logs.push(Log {
+                    log_type: LogType::Error(ErrorType::UnterminatedStringLiteral),
+                    line_and_col: Some((*line, *col)),
+                    span: Some(char_span(*line, *col, *index, length)),
+                    notes: Vec::new(),
+                });
+                token_type = TokenType::Error;
+                break;
+            }
+            Some('\\') => {
+                let escaped: Option<char> = chars.get(*index + length + 1).copied();
+                match escaped {
+                    Some('n' | 't' | '0' | '"' | '\\') => length += 2,
+                    None | Some('\n' | '\r') => length += 1, // Reported as unterminated.
+                    Some(other) => {
+                        logs.push(Log {
+                            log_type: LogType::Error(ErrorType::InvalidEscapeSequence(format!(
+                                "\\{other}"
+                            ))),
+                            line_and_col: Some((*line, *col + length)),
+                            span: Some(char_span(*line, *col + length, *index + length, 2)),
+                            notes: Vec::new(),
+                        });
+                        token_type = TokenType::Error;
+                        length += 2;
+                    }
+                }
+            }
+            Some(_) => length += 1,
+        }
+    }
+    tokens.push(Token {
+        token_type,
+        line: *line,
+        col: *col,
+        start: *index,
+        length,
+        width: length,
+    });
+    *index += length;
+    *col += length;
+}
+
 // Handles keywords and other tokens.
 fn handle_other(
-    file_text: &str,
+    chars: &[char],
     tokens: &mut Vec<Token>,
     line: &mut usize,
     col: &mut usize,
@@ -432,11 +739,10 @@ fn handle_other(
     trie: &Node<char, TokenType>,
 ) {
     let mut length: usize = 1;
-    while !is_token_separator(file_text.chars().nth(*index + length)) {
+    while !is_token_separator(chars.get(*index + length).copied()) {
         length += 1;
     }
-    let token_string: String = file_text[*index..*index + length].to_string();
-    let token_type: Option<TokenType> = trie.search_with_string(&token_string);
+    let token_type: Option<TokenType> = trie.search(&chars[*index..*index + length]);
     let token_type: TokenType = if let Some(t) = token_type {
         t
     } else {
@@ -448,6 +754,7 @@ fn handle_other(
         col: *col,
         start: *index,
         length,
+        width: length,
     };
     tokens.push(token);
     *index += length;
@@ -2,10 +2,39 @@
 #![deny(clippy::pedantic)]
 #![allow(clippy::module_name_repetitions)] // Many modules have types of the form "<Name>Output", and if they all were just "Output" it would get confusing.
 
+pub mod api;
+pub mod ast_printer;
+pub mod backend;
+pub mod batch;
+pub mod bench;
+pub mod cancel;
+pub mod cli;
 pub mod cli_reader;
 pub mod compiler;
+pub mod crashcheck;
+pub mod differential;
+pub mod disassembler;
+pub mod doctest;
+pub mod events;
+pub mod formatter;
+pub mod fuzz;
+pub mod internal_checks;
+pub mod interpreter;
 pub mod lexer;
+pub mod lint;
+pub mod metadata;
+pub mod optimizer;
 pub mod parser;
+pub mod pass_manager;
+pub mod profiler;
+pub mod project;
+pub mod snapshot;
+pub mod test_runner;
+pub mod verifier;
+pub mod visit;
 pub mod vm;
+pub mod watch;
 
 pub mod util;
+
+pub use api::{compile_source, run_source, Diagnostics, Program};
@@ -0,0 +1,214 @@
+//! The module for the linter, which looks for code that works but could be written more clearly. Each rule gives its
+//! own warning, and the warnings of the linter are allowed unless they are turned on, such as with `-W=lints` or
+//! `-W=shadowed-variable`. Comparisons of a value with itself are already found by the parser, as W0003.
+//!
+//! The rules are:
+//! - `redundant-parens` (W0006): parentheses around a whole value, such as `int x = (1 + 2);`.
+//! - `constant-condition` (W0007): a condition that only uses constants, such as `!(1 < 2)`.
+//! - `shadowed-variable` (W0008): a variable that shadows one declared earlier in the same function.
+//!
+//! Once a lint is turned on, `--deny warnings` and the other warning flags apply to it as to any other warning.
+
+use crate::{lexer, optimizer, parser, util::log, visit};
+use lexer::{Span, Token, TokenType};
+use log::{DiagnosticConfig, Log, LogType, WarningLevel, WarningType};
+use optimizer::fold_constants;
+use parser::{is_same_expression, Expression, Type};
+use visit::children;
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// Lints the typed AST of the source. Only the rules whose warnings aren't allowed are run, so the linter costs
+/// nothing when it is off.
+#[must_use]
+pub fn lint(expr: &Expression, source: &str, diagnostics: &DiagnosticConfig) -> Vec<Log> {
+    let enabled = |warning: WarningType| diagnostics.level(&warning) != WarningLevel::Allow;
+    let mut linter: Linter = Linter {
+        source,
+        parens: enabled(WarningType::RedundantParens),
+        conditions: enabled(WarningType::ConstantCondition(false)),
+        shadows: enabled(WarningType::ShadowedVariable(String::new(), 0)),
+        reported: HashSet::new(),
+        logs: Vec::new(),
+    };
+    if linter.parens || linter.conditions || linter.shadows {
+        linter.visit(expr, false, &mut HashMap::new());
+    }
+    linter.logs
+}
+
+// The state of the linter as it walks the AST.
+#[allow(clippy::struct_excessive_bools)] // Each bool is whether or not a rule runs.
+struct Linter<'a> {
+    source: &'a str,
+    parens: bool,
+    conditions: bool,
+    shadows: bool,
+    // The byte ranges of the parentheses already reported, as the parser copies some expressions, such as the values
+    // of flags, to where they are used.
+    reported: HashSet<Range<usize>>,
+    logs: Vec<Log>,
+}
+
+impl Linter<'_> {
+    // Lints an expression and its children. Parentheses around the expression are redundant if it is a whole value,
+    // such as an argument or a statement, rather than an operand. The scope has the variables declared so far in the
+    // function or the top level, by name.
+    fn visit(&mut self, expr: &Expression, whole_value: bool, scope: &mut HashMap<String, Token>) {
+        if self.conditions && self.check_condition(expr) {
+            // Only the outermost constant condition is reported.
+            self.conditions = false;
+            self.visit_children(expr, whole_value, scope);
+            self.conditions = true;
+        } else {
+            self.visit_children(expr, whole_value, scope);
+        }
+    }
+
+    // Lints the children of an expression.
+    fn visit_children(&mut self, expr: &Expression, whole_value: bool, scope: &mut HashMap<String, Token>) {
+        match expr {
+            Expression::Grouping { expr, .. } => {
+                if whole_value && self.parens {
+                    self.check_parens(expr);
+                }
+                self.visit(expr, true, scope);
+            }
+            Expression::Binary { left, op, right, .. } if op.token_type == TokenType::Equals => {
+                self.visit(left, false, scope);
+                self.visit(right, true, scope);
+            }
+            Expression::Call { args, .. }
+            | Expression::FunctionCall { args, .. }
+            | Expression::HostCall { args, .. }
+            | Expression::ListLiteral { elements: args, .. }
+            | Expression::ExpressionList { list: args } => {
+                for arg in args {
+                    self.visit(arg, true, scope);
+                }
+            }
+            Expression::MethodCall { args, .. } => {
+                for (index, arg) in args.iter().enumerate() {
+                    // The value the method is called on is an operand of the ".".
+                    self.visit(arg, index != 0, scope);
+                }
+            }
+            Expression::MapLiteral { entries, .. } => {
+                for (key, value) in entries {
+                    self.visit(key, true, scope);
+                    self.visit(value, true, scope);
+                }
+            }
+            Expression::Index { expr, index, .. } => {
+                self.visit(expr, false, scope);
+                self.visit(index, true, scope);
+            }
+            Expression::Statement { expr } => self.visit(expr, true, scope),
+            Expression::FlagsDeclaration { members, .. } => {
+                for (_, value) in members {
+                    self.visit(value, true, scope);
+                }
+            }
+            Expression::FunctionDeclaration { params, defaults, body, .. } => {
+                // Functions can't see the variables around them, so their bodies start with only their parameters.
+                let mut function_scope: HashMap<String, Token> = HashMap::new();
+                for param in params {
+                    if let Expression::Variable { token, .. } = &**param {
+                        function_scope.insert(token.to_string(self.source), *token);
+                    }
+                }
+                for default in defaults {
+                    self.visit(default, true, &mut function_scope);
+                }
+                self.visit(body, false, &mut function_scope);
+            }
+            Expression::VariableDeclaration { initialized_var } => {
+                if let Expression::Variable { token, .. } = &**initialized_var {
+                    self.declare(*token, scope);
+                }
+            }
+            _ => {
+                for child in children(expr) {
+                    self.visit(child, false, scope);
+                }
+            }
+        }
+    }
+
+    // Warns about parentheses around an expression that is a whole value. Parentheses around assignments and casts
+    // are left alone, as they make the code easier to read.
+    fn check_parens(&mut self, inner: &Expression) {
+        match inner {
+            Expression::Binary { op, .. } if op.token_type == TokenType::Equals => return,
+            Expression::Cast { .. } | Expression::Error { .. } | Expression::Null => return,
+            _ => {}
+        }
+        let Some(span) = inner.span() else {
+            return;
+        };
+        // Expressions copied by the parser aren't in parentheses where they are used, so the parentheses are looked
+        // for in the source, on the same lines as the expression.
+        let before: &str = self.source[..span.byte_range.start].trim_end_matches([' ', '\t']);
+        let after: &str = self.source[span.byte_range.end..].trim_start_matches([' ', '\t']);
+        if !before.ends_with('(') || !after.starts_with(')') {
+            return;
+        }
+        let start: usize = before.len() - 1;
+        let end: usize = self.source.len() - after.len() + 1;
+        let span: Span = Span {
+            start: (span.start.0, span.start.1 - (span.byte_range.start - start)),
+            end: (span.end.0, span.end.1 + (end - span.byte_range.end)),
+            byte_range: start..end,
+        };
+        if self.reported.insert(span.byte_range.clone()) {
+            self.logs.push(Log {
+                log_type: LogType::Warning(WarningType::RedundantParens),
+                line_and_col: Some(span.start),
+                span: Some(span),
+                notes: Vec::new(),
+            });
+        }
+    }
+
+    // Warns about a comparison or logical operation that only uses constants, so its value never changes. Returns
+    // whether or not the expression was warned about.
+    fn check_condition(&mut self, expr: &Expression) -> bool {
+        let op: Token = match expr {
+            // Comparisons of a value with itself already have their own warning.
+            Expression::Binary { left, op, right, .. } if !is_same_expression(left, right, self.source) => *op,
+            Expression::Unary { op, .. } if op.token_type == TokenType::ExclamationMark => *op,
+            _ => return false,
+        };
+        if expr.get_type() != Some(Type::Bool) {
+            return false;
+        }
+        let always: bool = match fold_constants(expr, &mut Vec::new()) {
+            Expression::Literal { token, .. } if token.token_type == TokenType::True => true,
+            Expression::Literal { token, .. } if token.token_type == TokenType::False => false,
+            _ => return false,
+        };
+        self.logs.push(Log {
+            log_type: LogType::Warning(WarningType::ConstantCondition(always)),
+            line_and_col: Some((op.line, op.col)),
+            span: Some(op.span()),
+            notes: Vec::new(),
+        });
+        true
+    }
+
+    // Adds a variable to the scope, warning if it shadows a variable of the same name.
+    fn declare(&mut self, token: Token, scope: &mut HashMap<String, Token>) {
+        let name: String = token.to_string(self.source);
+        if let Some(shadowed) = scope.insert(name.clone(), token) {
+            if self.shadows {
+                self.logs.push(Log {
+                    log_type: LogType::Warning(WarningType::ShadowedVariable(name, shadowed.line)),
+                    line_and_col: Some((token.line, token.col)),
+                    span: Some(token.span()),
+                    notes: Vec::new(),
+                });
+            }
+        }
+    }
+}
@@ -1,290 +1,4615 @@
 #![deny(clippy::all)]
 #![deny(clippy::pedantic)]
 
-use krust::cli_reader::{read_command_line, CLIInfo};
-use krust::compiler::{compile, CompilerOutput};
-use krust::lexer::{lex, LexerOutput};
-use krust::parser::{parse, ParserOutput};
-use krust::util::log::{ErrorType, Log, LogType};
-use krust::vm;
+use krust::backend::{c, wasm, Backend};
+use krust::batch::{LinkedSource, SourcePath};
+use krust::bench::{time_runs, BenchConfig};
+use krust::cli::{completions, from_env, help, version, Invocation};
+use krust::cli_reader::{CliOptions, Command, Emit, ExitStatus};
+use krust::compiler::{check, compile, compile_with_passes, serialize_bytecode, CompilerOptions, CompilerOutput, SizeEntry};
+use krust::crashcheck::{check_corpus, Crash};
+use krust::disassembler::{disassemble, AddressMode, DisassemblerOutput};
+use krust::doctest::{extract_snippets, test_snippet, Outcome};
+use krust::events::{Event, EventLog, Stage};
+use krust::formatter::{format, FormatterOutput};
+use krust::internal_checks::check_internals;
+use krust::interpreter::{prepare, InterpreterOutput, STACK_BYTES};
+use krust::lexer::{lex, LexerOutput, Span};
+use krust::metadata::{hash_bytes, Metadata};
+use krust::parser::{parse, Expression, ParserOutput};
+use krust::pass_manager::PassManager;
+use krust::profiler::ProfileFormat;
+use krust::test_runner::{run_tests, TestOutcome, TestRunOutput};
+use krust::util::log::{
+    filter_by_severity, is_error, limit_errors, ColorChoice, ErrorFormat, ErrorType, JsonSink, Log, LogType,
+    RenderOptions, Severity, DEFAULT_ERROR_LIMIT,
+};
+use krust::util::number_format::NumberFormat;
+use krust::verifier::{verify, VerifierOutput};
+use krust::vm::{load_bytecode, Vm};
+use krust::watch::{Watcher, DEBOUNCE};
 
-use std::fs::read_to_string;
+use std::fs::{read, read_to_string, write};
+use std::io::{stderr, stdout, IsTerminal, Write};
+use std::iter::once;
+use std::panic::resume_unwind;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::thread;
+use std::time::Duration;
 
 pub enum FileInput {
     FilePath(String),
-    FileText(String)
+    FileText(String),
+    Linked(LinkedSource)
 }
 
 impl FileInput {
     fn get_file_text(&self) -> String{
         match self {
             Self::FilePath(path) => read_to_string(path).expect("this method should only be called on FilePath variant if previously checked."),
-            Self::FileText(text) => text.clone()
+            Self::FileText(text) => text.clone(),
+            Self::Linked(source) => source.file_text.clone()
         }
     }
+
+    // Gets the name used for the input in progress events.
+    fn get_name(&self) -> &str {
+        match self {
+            Self::FilePath(path) => path,
+            Self::FileText(_) => "<text>",
+            Self::Linked(source) => &source.name
+        }
+    }
+
+    // Gets the path of the file the input was read from, if it is a single file.
+    fn file_path(&self) -> Option<&str> {
+        match self {
+            Self::FilePath(path) => Some(path),
+            _ => None
+        }
+    }
+
+    // Gets the files that were linked into the input, if it is many files.
+    fn linked_source(&self) -> Option<&LinkedSource> {
+        match self {
+            Self::Linked(source) => Some(source),
+            _ => None
+        }
+    }
+}
+
+// Gets the input for a source file, which is linked with the files after it if there are any.
+fn source_input(file_path: &str, linked_paths: &[String]) -> FileInput {
+    if linked_paths.is_empty() {
+        return FileInput::FilePath(file_path.to_string());
+    }
+    let files: Vec<(SourcePath, String)> = once(file_path)
+        .chain(linked_paths.iter().map(String::as_str))
+        .map(|path| {
+            (SourcePath::from(path), read_to_string(path).expect("should be valid as error handled in command line reader"))
+        })
+        .collect();
+    FileInput::Linked(LinkedSource::link(files))
+}
+
+// The options for running code other than the compiler options.
+#[allow(clippy::struct_excessive_bools)] // Each is a separate option of the run.
+struct RunOptions {
+    /// Whether or not the code is only run if the invariants between compiler passes hold.
+    internal_checks: bool,
+    /// Where the progress of each stage is sent.
+    events: EventLog,
+    /// The arguments given to the program.
+    program_args: Vec<String>,
+    /// Whether or not the value of each statement at the top level is printed.
+    script: bool,
+    /// Whether or not the program can read and write files.
+    file_access: bool,
+    /// The passes of the compiler that run, and the pass whose output is dumped.
+    passes: PassManager,
+    /// What the code is compiled to.
+    backend: Backend,
+    /// The format of the profile printed once the program has run, if it is profiled.
+    profile: Option<ProfileFormat>,
+    /// Whether or not the code is run by the interpreter instead of the VM.
+    interpret: bool,
+    /// Where the built program is written, if not next to the source file.
+    output: Option<String>,
+    /// How logs are printed.
+    render: RenderOptions,
+    /// The most errors printed for a file, where 0 means there is no limit.
+    error_limit: usize,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            internal_checks: false,
+            events: EventLog::default(),
+            program_args: Vec::new(),
+            script: false,
+            file_access: false,
+            passes: PassManager::default(),
+            backend: Backend::default(),
+            profile: None,
+            interpret: false,
+            output: None,
+            render: RenderOptions::default(),
+            error_limit: DEFAULT_ERROR_LIMIT,
+        }
+    }
+}
+
+#[allow(clippy::too_many_lines)] // Each subcommand is only a few lines.
+fn main() {
+    // The help, version, and completions are printed to stdout, so they can be piped or saved.
+    let cli_output: (Option<CliOptions>, Vec<Log>) = match from_env() {
+        (Some(Invocation::Command(options)), logs) => (Some(*options), logs),
+        (Some(Invocation::Help(command)), _) => return print!("{}", help(command)),
+        (Some(Invocation::Version), _) => return println!("{}", version()),
+        (Some(Invocation::Completions(shell)), _) => return print!("{}", completions(shell)),
+        (None, logs) => (None, logs),
+    };
+    let color: ColorChoice = cli_output.0.as_ref().map_or(ColorChoice::Auto, |cli_info| cli_info.color);
+    let render: RenderOptions = RenderOptions {
+        format: cli_output.0.as_ref().map_or(ErrorFormat::Human, |cli_info| cli_info.error_format),
+        colored: color.enabled(),
+        numbers: cli_output.0.as_ref().map_or(NumberFormat::default(), |cli_info| cli_info.number_format),
+    };
+    let min_severity: Severity = cli_output
+        .0
+        .as_ref()
+        .map_or(Severity::Info, |cli_info| cli_info.min_severity);
+    for log in filter_by_severity(&cli_output.1, min_severity) {
+        eprintln!("{}", log.render(None, render));
+    }
+
+    if let Some(cli_output) = cli_output.0 {
+        match cli_output.command {
+            Command::Run | Command::Script | Command::Build | Command::Watch => {
+                let options: RunOptions = RunOptions {
+                    internal_checks: cli_output.internal_checks,
+                    events: EventLog::new(cli_output.log_json).with_timings(cli_output.verbose),
+                    program_args: cli_output.program_args,
+                    script: cli_output.command == Command::Script,
+                    file_access: !cli_output.sandbox,
+                    passes: cli_output.passes,
+                    backend: cli_output.backend,
+                    profile: cli_output.profile,
+                    interpret: cli_output.interpret,
+                    output: cli_output.output,
+                    render,
+                    error_limit: cli_output.error_limit,
+                };
+                let mode: AddressMode = if cli_output.no_addresses { AddressMode::Labels } else { AddressMode::Offsets };
+                if options.backend != Backend::Bytecode && (cli_output.command != Command::Build || cli_output.emit.is_some()) {
+                    eprintln!("{}", Log {
+                        log_type: LogType::Error(ErrorType::CLITargetOnlyBuilds(options.backend.name().to_string())),
+                        line_and_col: None,
+                        span: None,
+                        notes: Vec::new(),
+                    }.render(None, render));
+                    exit(ExitStatus::UsageError.code());
+                } else if options.output.is_some() && (cli_output.command != Command::Build || cli_output.emit.is_some()) {
+                    eprintln!("{}", Log {
+                        log_type: LogType::Error(ErrorType::CLIOutputOnlyBuilds("--output".to_string())),
+                        line_and_col: None,
+                        span: None,
+                        notes: Vec::new(),
+                    }.render(None, render));
+                    exit(ExitStatus::UsageError.code());
+                } else if let (Some(emit), false) = (cli_output.emit, options.script) {
+                    exit_with(ExitStatus::from_logs(&emit_file(&cli_output.file_path, emit, mode, cli_output.options, min_severity, &options).1));
+                } else if cli_output.command == Command::Build {
+                    if !build_file(&cli_output.file_path, &cli_output.linked_paths, cli_output.options, min_severity, &options) {
+                        exit(ExitStatus::CompileError.code());
+                    }
+                } else if cli_output.command == Command::Watch {
+                    watch_file(&cli_output.file_path, &cli_output.linked_paths, cli_output.options, min_severity, &options);
+                } else if is_compiled_program(&cli_output.file_path) {
+                    exit_with(ExitStatus::from_logs(&run_bytecode_file(&cli_output.file_path, min_severity, &options).1));
+                } else {
+                    let file_input: FileInput = source_input(&cli_output.file_path, &cli_output.linked_paths);
+                    exit_with(ExitStatus::from_logs(&run(&file_input, cli_output.options, min_severity, &options).1));
+                }
+            }
+            Command::Verify => {
+                if !verify_file(&cli_output.file_path, render) {
+                    exit(ExitStatus::CompileError.code());
+                }
+            }
+            Command::Inspect => {
+                if !inspect_file(&cli_output.file_path, render) {
+                    exit(ExitStatus::CompileError.code());
+                }
+            }
+            Command::Doctest => {
+                if !doctest_file(&cli_output.file_path, cli_output.options, render) {
+                    exit(ExitStatus::RuntimeError.code());
+                }
+            }
+            Command::Test => {
+                let options: RunOptions = RunOptions { render, error_limit: cli_output.error_limit, ..RunOptions::default() };
+                exit_with(test_file(&cli_output.file_path, cli_output.options, cli_output.filter.as_deref(), min_severity, &options));
+            }
+            Command::Bench => {
+                let options: RunOptions = RunOptions { render, error_limit: cli_output.error_limit, ..RunOptions::default() };
+                exit_with(bench_file(&cli_output.file_path, cli_output.options, cli_output.bench, min_severity, &options));
+            }
+            Command::Check => {
+                let options: RunOptions = RunOptions {
+                    events: EventLog::new(cli_output.log_json).with_timings(cli_output.verbose),
+                    passes: cli_output.passes,
+                    render,
+                    error_limit: cli_output.error_limit,
+                    ..RunOptions::default()
+                };
+                if !check_file(&cli_output.file_path, &cli_output.linked_paths, cli_output.options, min_severity, &options) {
+                    exit(ExitStatus::CompileError.code());
+                }
+            }
+            Command::Fmt => {
+                if !fmt_file(&cli_output.file_path, cli_output.check_formatting, render) {
+                    exit(ExitStatus::CompileError.code());
+                }
+            }
+            Command::Crashcheck => {
+                if !crashcheck_dir(&cli_output.file_path, cli_output.options, cli_output.timeout, render) {
+                    exit(ExitStatus::RuntimeError.code());
+                }
+            }
+            Command::Disassemble => {
+                let mode: AddressMode = if cli_output.no_addresses { AddressMode::Labels } else { AddressMode::Offsets };
+                if !disassemble_file(&cli_output.file_path, mode, render) {
+                    exit(ExitStatus::CompileError.code());
+                }
+            }
+        }
+    } else {
+        // The command line couldn't be read, or a warning about it was denied.
+        exit(ExitStatus::UsageError.code());
+    }
+}
+
+// Exits with the status if the command failed, so that scripts can tell why.
+fn exit_with(status: ExitStatus) {
+    if status != ExitStatus::Success {
+        exit(status.code());
+    }
+}
+
+// Reads the bytecode from a compiled program file, printing an error if the file is corrupt.
+fn read_bytecode_file(file_path: &str, render: RenderOptions) -> Option<Vec<u8>> {
+    let file: Vec<u8> = read(file_path).expect("should be valid as error handled in command line reader");
+    load_bytecode(&file).map_err(|log| eprintln!("{}", log.render(None, render))).ok()
+}
+
+// Verifies the bytecode in the file without running it and prints a report. Returns whether or not the bytecode is valid.
+fn verify_file(file_path: &str, render: RenderOptions) -> bool {
+    let Some(bytecode) = read_bytecode_file(file_path, render) else {
+        return false;
+    };
+    let output: VerifierOutput = verify(&bytecode);
+
+    println!("file: {file_path}");
+    println!("size: {} bytes", bytecode.len());
+    if let Some(ptr_size) = output.ptr_size {
+        println!("pointer size: {}-bit", ptr_size * 8);
+    }
+    if let Some(detailed_err) = output.detailed_err {
+        println!("detailed errors: {detailed_err}");
+    }
+    if let Some(isa_level) = output.isa_level {
+        println!("instruction set level: {}", isa_level as u8);
+    }
+    println!("instructions: {}", output.instruction_count);
+    for log in &output.logs {
+        eprintln!("{}", log.render(None, render));
+    }
+    if is_error(&output.logs) {
+        let count: usize = output.logs.len();
+        println!(
+            "status: failed ({count} error{})",
+            if count == 1 { "" } else { "s" }
+        );
+        false
+    } else {
+        println!("status: ok");
+        true
+    }
+}
+
+// Prints how the bytecode file was built. Returns whether or not the metadata could be read.
+fn inspect_file(file_path: &str, render: RenderOptions) -> bool {
+    let Some(bytecode) = read_bytecode_file(file_path, render) else {
+        return false;
+    };
+    let Some(metadata) = Metadata::read(&bytecode) else {
+        eprintln!("{}", Log { log_type: LogType::Error(ErrorType::InvalidBytecodeMetadata), line_and_col: None, span: None, notes: Vec::new() }.render(None, render));
+        return false;
+    };
+    println!("file: {file_path}");
+    println!("compiler version: {}", metadata.compiler_version);
+    println!(
+        "target: {}-bit pointers, {}-endian, detailed errors: {}, instruction set level: {}",
+        u16::from(metadata.options.target.ptr_size) * 8,
+        metadata.options.target.endianness.name(),
+        metadata.options.detailed_err,
+        metadata.options.target.features as u8
+    );
+    println!("required instruction set level: {}", bytecode[2]);
+    println!("optimization level: {}", metadata.options.opt_level as u8);
+    println!(
+        "optimizations: {}",
+        if metadata.optimizations.is_empty() { "none".to_string() } else { metadata.optimizations.join(", ") }
+    );
+    println!("source hash: {:016x}", metadata.source_hash);
+    true
+}
+
+// Prints the instructions in the bytecode file. Returns whether or not the whole file could be read.
+fn disassemble_file(file_path: &str, mode: AddressMode, render: RenderOptions) -> bool {
+    let Some(bytecode) = read_bytecode_file(file_path, render) else {
+        return false;
+    };
+    let output: DisassemblerOutput = disassemble(&bytecode, mode);
+    for line in &output.lines {
+        println!("{line}");
+    }
+    for log in &output.logs {
+        eprintln!("{}", log.render(None, render));
+    }
+    output.logs.is_empty()
+}
+
+// Tests the krust code blocks in the Markdown file and prints a report. Returns whether or not every block passed.
+fn doctest_file(file_path: &str, compiler_options: CompilerOptions, render: RenderOptions) -> bool {
+    let markdown: String =
+        read_to_string(file_path).expect("should be valid as error handled in command line reader");
+    let (mut passed, mut failed, mut ignored): (usize, usize, usize) = (0, 0, 0);
+    for snippet in extract_snippets(&markdown) {
+        match test_snippet(&snippet, compiler_options) {
+            Outcome::Passed => {
+                println!("test {file_path}:{} ... ok", snippet.line);
+                passed += 1;
+            }
+            Outcome::Ignored => {
+                println!("test {file_path}:{} ... ignored", snippet.line);
+                ignored += 1;
+            }
+            Outcome::Failed { output, logs } => {
+                println!("test {file_path}:{} ... FAILED", snippet.line);
+                println!("  expected: {:?}", snippet.expected);
+                println!("  found: {output:?}");
+                for log in &logs {
+                    println!("  {}", log.render(None, render));
+                }
+                failed += 1;
+            }
+        }
+    }
+    println!(
+        "doctest result: {}. {passed} passed; {failed} failed; {ignored} ignored",
+        if failed == 0 { "ok" } else { "FAILED" }
+    );
+    failed == 0
+}
+
+// Runs the tests in the source file whose names contain the filter, printing the outcome of each one and a summary.
+// Returns the status that krust exits with, which is a runtime error if any test failed.
+fn test_file(
+    file_path: &str, compiler_options: CompilerOptions, filter: Option<&str>, min_severity: Severity, options: &RunOptions,
+) -> ExitStatus {
+    let file_text: String = read_to_string(file_path).expect("should be valid as error handled in command line reader");
+    let output: TestRunOutput = run_tests(&file_text, compiler_options, filter);
+    let logs: Vec<Log> = limit_errors(&filter_by_severity(&output.logs, min_severity), options.error_limit);
+    print_logs(&logs, &file_text, Some(file_path), None, options.render);
+    if is_error(&output.logs) {
+        return ExitStatus::from_logs(&output.logs);
+    }
+    let (mut passed, mut failed): (usize, usize) = (0, 0);
+    for (case, outcome) in &output.results {
+        match outcome {
+            TestOutcome::Passed => {
+                println!("test {} ({file_path}:{}) ... ok", case.name, case.line);
+                passed += 1;
+            }
+            TestOutcome::Failed { output, logs } => {
+                println!("test {} ({file_path}:{}) ... FAILED", case.name, case.line);
+                if !output.is_empty() {
+                    println!("  output: {output:?}");
+                }
+                for log in logs {
+                    println!("  {}", log.render(None, options.render));
+                }
+                failed += 1;
+            }
+        }
+    }
+    println!(
+        "test result: {}. {passed} passed; {failed} failed; {} filtered out",
+        if failed == 0 { "ok" } else { "FAILED" },
+        output.filtered_out
+    );
+    if failed == 0 { ExitStatus::Success } else { ExitStatus::RuntimeError }
+}
+
+// Compiles the code in the file, or loads the bytecode if it's a compiled program, and runs it the number of times in
+// the config, printing how long the timed runs took. Returns the status that krust exits with.
+fn bench_file(
+    file_path: &str, compiler_options: CompilerOptions, config: BenchConfig, min_severity: Severity, options: &RunOptions,
+) -> ExitStatus {
+    let bytecode: Vec<u8> = if is_compiled_program(file_path) {
+        let Some(bytecode) = read_bytecode_file(file_path, options.render) else {
+            return ExitStatus::CompileError;
+        };
+        bytecode
+    } else {
+        let file_text: String = read_to_string(file_path).expect("should be valid as error handled in command line reader");
+        let output: CompilerOutput = compile(parse(lex(&file_text)), compiler_options);
+        let logs: Vec<Log> = limit_errors(&filter_by_severity(&output.logs, min_severity), options.error_limit);
+        print_logs(&logs, &file_text, Some(file_path), None, options.render);
+        let Some(bytecode) = output.bytecode else {
+            return ExitStatus::from_logs(&output.logs);
+        };
+        bytecode
+    };
+    match time_runs(bytecode, config) {
+        Ok(result) => {
+            let plural = |count: usize| if count == 1 { "" } else { "s" };
+            println!(
+                "bench {file_path}: {} run{} after {} warmup{}",
+                config.iterations,
+                plural(config.iterations),
+                config.warmups,
+                plural(config.warmups)
+            );
+            println!("{}", result.to_table());
+            ExitStatus::Success
+        }
+        Err(logs) => {
+            for log in filter_by_severity(&logs, min_severity) {
+                eprintln!("{}", log.render(None, options.render));
+            }
+            ExitStatus::from_logs(&logs)
+        }
+    }
+}
+
+// Prints the output of the compiler for the code in the file instead of running it, loading the bytecode instead if
+// the file is a compiled program. Logs less severe than min_severity are not shown or returned. Returns the lines
+// printed and the logs, which only contain errors if the output couldn't be printed in full.
+fn emit_file(
+    file_path: &str,
+    emit: Emit,
+    mode: AddressMode,
+    compiler_options: CompilerOptions,
+    min_severity: Severity,
+    options: &RunOptions,
+) -> (Vec<String>, Vec<Log>) {
+    let (lines, logs): (Vec<String>, Vec<Log>) = match emit {
+        Emit::Disasm => emit_disassembly(file_path, mode, compiler_options, min_severity, options),
+        Emit::Ast => emit_ast(file_path, min_severity, options),
+        Emit::Tokens => emit_tokens(file_path, min_severity, options),
+        Emit::C => emit_c(file_path, compiler_options, min_severity, options),
+        Emit::Hash => emit_hash(file_path, compiler_options, min_severity, options),
+        Emit::Size => emit_size(file_path, compiler_options, min_severity, options),
+    };
+    options.events.emit(&Event::diagnostics(file_path, &logs));
+    (lines, logs)
+}
+
+// Prints the disassembly of the bytecode compiled from the file, or loaded from it if it's a compiled program.
+fn emit_disassembly(
+    file_path: &str,
+    mode: AddressMode,
+    compiler_options: CompilerOptions,
+    min_severity: Severity,
+    options: &RunOptions,
+) -> (Vec<String>, Vec<Log>) {
+    let (bytecode, mut logs): (Option<Vec<u8>>, Vec<Log>) = if is_compiled_program(file_path) {
+        let file: Vec<u8> = read(file_path).expect("should be valid as error handled in command line reader");
+        match load_bytecode(&file) {
+            Ok(bytecode) => (Some(bytecode), Vec::new()),
+            Err(log) => {
+                eprintln!("{}", log.render(None, options.render));
+                (None, vec![log])
+            }
+        }
+    } else {
+        compile_input(&FileInput::FilePath(file_path.to_string()), compiler_options, min_severity, options)
+    };
+    let mut lines: Vec<String> = Vec::new();
+    if let Some(bytecode) = bytecode {
+        let output: DisassemblerOutput = disassemble(&bytecode, mode);
+        for line in &output.lines {
+            println!("{line}");
+        }
+        for log in &output.logs {
+            eprintln!("{}", log.render(None, options.render));
+        }
+        lines = output.lines;
+        logs.extend(output.logs);
+    }
+    (lines, logs)
+}
+
+// Prints the hash of the compiled program file for the code in the file, or of the file itself if it's a compiled
+// program, as 16 hex digits.
+fn emit_hash(file_path: &str, compiler_options: CompilerOptions, min_severity: Severity, options: &RunOptions) -> (Vec<String>, Vec<Log>) {
+    let (artifact, logs): (Option<Vec<u8>>, Vec<Log>) = if is_compiled_program(file_path) {
+        (Some(read(file_path).expect("should be valid as error handled in command line reader")), Vec::new())
+    } else {
+        let (bytecode, logs): (Option<Vec<u8>>, Vec<Log>) =
+            compile_input(&FileInput::FilePath(file_path.to_string()), compiler_options, min_severity, options);
+        (bytecode.map(|bytecode| serialize_bytecode(&bytecode)), logs)
+    };
+    let mut lines: Vec<String> = Vec::new();
+    if let Some(artifact) = artifact {
+        lines.push(format!("{:016x}", hash_bytes(&artifact)));
+        println!("{}", lines[0]);
+    }
+    (lines, logs)
+}
+
+// Prints the bytes taken up by each part of the bytecode for the code in the file, and the share of the total each one
+// has. The report is printed even if the bytecode is too large, as that is when it's needed most.
+fn emit_size(file_path: &str, compiler_options: CompilerOptions, min_severity: Severity, options: &RunOptions) -> (Vec<String>, Vec<Log>) {
+    let Some(file_text) = read_source(file_path, Emit::Size, options.render) else {
+        return (Vec::new(), vec![needs_source_log(Emit::Size)]);
+    };
+    let lex_output: LexerOutput = options.events.stage(file_path, Stage::Lex, || lex(&file_text));
+    let parse_output: ParserOutput = options.events.stage(file_path, Stage::Parse, || parse(lex_output));
+    let mut compiler_output: CompilerOutput = options.events.stage(file_path, Stage::Compile, || {
+        compile_with_passes(parse_output, compiler_options, options.script, &options.passes)
+    });
+    if compiler_output.sizes.is_empty() {
+        compiler_output.logs.push(Log { log_type: LogType::Error(ErrorType::CantCompile), line_and_col: None, span: None, notes: Vec::new() });
+        return print_emitted(Vec::new(), file_path, &file_text, &compiler_output.logs, min_severity, options);
+    }
+    let total: usize = compiler_output.sizes.iter().map(|entry| entry.size).sum();
+    let mut lines: Vec<String> = vec![format!("{:>8}  {:>6}  part", "bytes", "share")];
+    for SizeEntry { name, line_and_col, size } in &compiler_output.sizes {
+        #[allow(clippy::cast_precision_loss)] // The share is only shown to one decimal place.
+        let share: f64 = *size as f64 * 100.0 / total as f64;
+        let location: String = line_and_col.map_or_else(String::new, |(line, col)| format!("{line}:{col} "));
+        lines.push(format!("{size:>8}  {share:>5.1}%  {location}{name}"));
+    }
+    lines.push(format!("{total:>8}  100.0%  total"));
+    print_emitted(lines, file_path, &file_text, &compiler_output.logs, min_severity, options)
+}
+
+// Prints the tree built by the parser for the code in the file. The tree is printed even if the code has errors, as
+// seeing how the parser recovered from them is often the point.
+fn emit_ast(file_path: &str, min_severity: Severity, options: &RunOptions) -> (Vec<String>, Vec<Log>) {
+    let Some(file_text) = read_source(file_path, Emit::Ast, options.render) else {
+        return (Vec::new(), vec![needs_source_log(Emit::Ast)]);
+    };
+    let lex_output: LexerOutput = options.events.stage(file_path, Stage::Lex, || lex(&file_text));
+    let parse_output: ParserOutput = options.events.stage(file_path, Stage::Parse, || parse(lex_output));
+    let lines: Vec<String> = parse_output.expr.to_tree_string(&parse_output.file_text).lines().map(str::to_string).collect();
+    print_emitted(lines, file_path, &file_text, &parse_output.logs, min_severity, options)
+}
+
+// Prints each token the lexer finds in the file, including the ones after an error, so that the output can be shared
+// when reporting a bug in the lexer.
+fn emit_tokens(file_path: &str, min_severity: Severity, options: &RunOptions) -> (Vec<String>, Vec<Log>) {
+    let Some(file_text) = read_source(file_path, Emit::Tokens, options.render) else {
+        return (Vec::new(), vec![needs_source_log(Emit::Tokens)]);
+    };
+    let lex_output: LexerOutput = options.events.stage(file_path, Stage::Lex, || lex(&file_text));
+    let lines: Vec<String> = lex_output.tokens.iter().map(|token| token.describe(&lex_output.file_text)).collect();
+    print_emitted(lines, file_path, &file_text, &lex_output.logs, min_severity, options)
+}
+
+// Prints the code in the file transpiled to C, which can be saved and compiled to a native program.
+fn emit_c(file_path: &str, compiler_options: CompilerOptions, min_severity: Severity, options: &RunOptions) -> (Vec<String>, Vec<Log>) {
+    let Some(file_text) = read_source(file_path, Emit::C, options.render) else {
+        return (Vec::new(), vec![needs_source_log(Emit::C)]);
+    };
+    let lex_output: LexerOutput = options.events.stage(file_path, Stage::Lex, || lex(&file_text));
+    let parse_output: ParserOutput = options.events.stage(file_path, Stage::Parse, || parse(lex_output));
+    let mut c_output: c::COutput = options.events.stage(file_path, Stage::Compile, || c::transpile(parse_output, compiler_options));
+    let Some(source) = c_output.source else {
+        c_output.logs.push(Log { log_type: LogType::Error(ErrorType::CantCompile), line_and_col: None, span: None, notes: Vec::new() });
+        return print_emitted(Vec::new(), file_path, &file_text, &c_output.logs, min_severity, options);
+    };
+    print_emitted(source.lines().map(str::to_string).collect(), file_path, &file_text, &c_output.logs, min_severity, options)
+}
+
+// Reads the source code in the file, reporting an error if the file is a compiled program, which the output needs the
+// source of.
+fn read_source(file_path: &str, emit: Emit, render: RenderOptions) -> Option<String> {
+    if is_compiled_program(file_path) {
+        eprintln!("{}", needs_source_log(emit).render(None, render));
+        return None;
+    }
+    Some(FileInput::FilePath(file_path.to_string()).get_file_text())
+}
+
+// Creates the error for an output that can't be printed for a compiled program.
+fn needs_source_log(emit: Emit) -> Log {
+    Log { log_type: LogType::Error(ErrorType::CLIEmitNeedsSource(emit.name().to_string())), line_and_col: None, span: None, notes: Vec::new() }
+}
+
+// Prints the lines of an output and the logs at least as severe as min_severity up to the error limit, returning both.
+fn print_emitted(
+    lines: Vec<String>, file_path: &str, file_text: &str, logs: &[Log], min_severity: Severity, options: &RunOptions,
+) -> (Vec<String>, Vec<Log>) {
+    for line in &lines {
+        println!("{line}");
+    }
+    let logs: Vec<Log> = limit_errors(&filter_by_severity(logs, min_severity), options.error_limit);
+    print_logs(&logs, file_text, Some(file_path), None, options.render);
+    (lines, logs)
+}
+
+// Returns whether or not the file is a compiled program rather than source code.
+fn is_compiled_program(file_path: &str) -> bool {
+    Path::new(file_path).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("krustc"))
+}
+
+// Formats the file in place, or only checks that it is formatted if check is true. Returns whether or not the file was
+// formatted, or was already formatted when checking.
+fn fmt_file(file_path: &str, check: bool, render: RenderOptions) -> bool {
+    let file_text: String = read_to_string(file_path).expect("should be valid as error handled in command line reader");
+    let output: FormatterOutput = format(&file_text);
+    // Warnings are about what the code does, not how it is written, so only the errors that stop formatting are shown.
+    print_logs(&filter_by_severity(&output.logs, Severity::Error), &file_text, Some(file_path), None, render);
+    let Some(formatted) = output.formatted else {
+        return false;
+    };
+    if formatted == file_text {
+        return true;
+    }
+    if check {
+        let line: usize = formatted
+            .lines()
+            .zip(file_text.lines())
+            .position(|(formatted, original)| formatted != original)
+            .unwrap_or_else(|| formatted.lines().count().min(file_text.lines().count()))
+            + 1;
+        eprintln!("{}", Log {
+            log_type: LogType::Error(ErrorType::NotFormatted(file_path.to_string(), line)),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        }.render(None, render));
+        return false;
+    }
+    if let Err(error) = write(file_path, formatted) {
+        eprintln!("{}", Log {
+            log_type: LogType::Error(ErrorType::FileWriteFailed(file_path.to_string(), error.to_string())),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        }.render(None, render));
+        return false;
+    }
+    true
+}
+
+// Compiles and runs every file in the directory and prints a report. Returns whether or not no file crashed krust.
+fn crashcheck_dir(dir: &str, compiler_options: CompilerOptions, timeout: Duration, render: RenderOptions) -> bool {
+    let Ok(results) = check_corpus(Path::new(dir), compiler_options, timeout) else {
+        eprintln!("{}", Log { log_type: LogType::Error(ErrorType::CLICantOpenFile(dir.to_string())), line_and_col: None, span: None, notes: Vec::new() }.render(None, render));
+        return false;
+    };
+    let mut crashed: usize = 0;
+    for result in &results {
+        let path: String = result.path.display().to_string();
+        match &result.crash {
+            None => println!("check {path} ... ok"),
+            Some(Crash::Panic { stage, message }) => println!("check {path} ... PANICKED during {}: {message}", stage.name()),
+            Some(Crash::Hang { stage }) => {
+                println!("check {path} ... HUNG during {} (over {}ms)", stage.name(), timeout.as_millis());
+            }
+            Some(Crash::VerifierFailure { logs }) => {
+                println!("check {path} ... FAILED VERIFICATION");
+                for log in logs {
+                    println!("  {}", log.render(None, render));
+                }
+            }
+        }
+        if result.crash.is_some() {
+            crashed += 1;
+        }
+    }
+    println!(
+        "crashcheck result: {}. {} passed; {crashed} crashed",
+        if crashed == 0 { "ok" } else { "FAILED" },
+        results.len() - crashed
+    );
+    crashed == 0
+}
+
+// Compiles the code in the file, printing the logs and any dump. Logs less severe than min_severity are not shown or
+// returned. Returns the bytecode if the code compiled and passed the internal checks.
+fn compile_input(file_input: &FileInput, compiler_options: CompilerOptions, min_severity: Severity, options: &RunOptions) -> (Option<Vec<u8>>, Vec<Log>) {
+    let RunOptions { internal_checks, events, script, passes, .. } = options;
+    let name: &str = file_input.get_name();
+    let lex_output: LexerOutput = events.stage(name, Stage::Lex, || lex(&file_input.get_file_text()));
+    let parse_output: ParserOutput = events.stage(name, Stage::Parse, || parse(lex_output));
+    let expr: Option<Expression> = internal_checks.then(|| parse_output.expr.clone());
+    let mut compiler_output: CompilerOutput =
+        events.stage(name, Stage::Compile, || compile_with_passes(parse_output, compiler_options, *script, passes));
+    if let Some(dump) = &compiler_output.dump {
+        eprintln!("{dump}");
+    }
+
+    if let (Some(expr), Some(bytecode)) = (expr, &compiler_output.bytecode) {
+        let mut internal_logs: Vec<Log> = events.stage(name, Stage::InternalChecks, || {
+            check_internals(&expr, &compiler_output.file_text, bytecode, compiler_options)
+        });
+        if is_error(&internal_logs) {
+            compiler_output.bytecode = None;
+        }
+        compiler_output.logs.append(&mut internal_logs);
+    }
+
+    let logs: Vec<Log> =
+        print_compiler_logs(file_input, &compiler_output.file_text, &compiler_output.logs, compiler_output.bytecode.is_some(), min_severity, options);
+    (compiler_output.bytecode, logs)
+}
+
+// Checks the code in the file and the files linked with it for errors without running it. Returns whether or not the
+// code has no errors.
+fn check_file(
+    file_path: &str, linked_paths: &[String], compiler_options: CompilerOptions, min_severity: Severity, options: &RunOptions,
+) -> bool {
+    let file_input: FileInput = source_input(file_path, linked_paths);
+    !is_error(&check_input(&file_input, compiler_options, min_severity, options))
+}
+
+// Checks the code in the file for errors without generating bytecode, printing the logs. Logs less severe than
+// min_severity are not shown or returned.
+fn check_input(file_input: &FileInput, compiler_options: CompilerOptions, min_severity: Severity, options: &RunOptions) -> Vec<Log> {
+    let name: &str = file_input.get_name();
+    let lex_output: LexerOutput = options.events.stage(name, Stage::Lex, || lex(&file_input.get_file_text()));
+    let parse_output: ParserOutput = options.events.stage(name, Stage::Parse, || parse(lex_output));
+    let file_text: String = parse_output.file_text.clone();
+    let compiler_logs: Vec<Log> =
+        options.events.stage(name, Stage::Compile, || check(parse_output, compiler_options, options.script, &options.passes));
+    let logs: Vec<Log> = print_compiler_logs(file_input, &file_text, &compiler_logs, !is_error(&compiler_logs), min_severity, options);
+    options.events.emit(&Event::diagnostics(name, &logs));
+    logs
+}
+
+// Compiles the code in the file to a WebAssembly module, printing the logs. Logs less severe than min_severity are not
+// shown or returned.
+fn compile_wasm(file_input: &FileInput, compiler_options: CompilerOptions, min_severity: Severity, options: &RunOptions) -> (Option<Vec<u8>>, Vec<Log>) {
+    let name: &str = file_input.get_name();
+    let lex_output: LexerOutput = options.events.stage(name, Stage::Lex, || lex(&file_input.get_file_text()));
+    let parse_output: ParserOutput = options.events.stage(name, Stage::Parse, || parse(lex_output));
+    let wasm_output: wasm::WasmOutput = options.events.stage(name, Stage::Compile, || wasm::compile(parse_output, compiler_options));
+    let logs: Vec<Log> = print_compiler_logs(file_input, &wasm_output.file_text, &wasm_output.logs, wasm_output.module.is_some(), min_severity, options);
+    (wasm_output.module, logs)
+}
+
+// Prints the logs of a compiler at least as severe as min_severity, up to the error limit, followed by an error if the
+// code didn't compile. Returns the printed logs.
+fn print_compiler_logs(
+    file_input: &FileInput, file_text: &str, compiler_logs: &[Log], compiled: bool, min_severity: Severity, options: &RunOptions,
+) -> Vec<Log> {
+    let mut logs: Vec<Log> = limit_errors(&filter_by_severity(compiler_logs, min_severity), options.error_limit);
+    if !compiled {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CantCompile),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+    print_logs(&logs, file_text, file_input.file_path(), file_input.linked_source(), options.render);
+    logs
+}
+
+// Prints the logs, each followed by the source it is about, which is in the given text or in the linked files. A log
+// about the same code as the log before it, such as a note about an error, doesn't show the source again. JSON logs
+// name the file they are about, which is the given file if they aren't in the linked files.
+fn print_logs(logs: &[Log], file_text: &str, file: Option<&str>, source: Option<&LinkedSource>, render: RenderOptions) {
+    let mut last_span: Option<&Span> = None;
+    for log in logs {
+        let repeated: bool = log.span.is_some() && log.span.as_ref() == last_span;
+        match source {
+            Some(source) if repeated => eprintln!("{}", source.describe(log, render)),
+            Some(source) => eprintln!("{}", source.render(log, render)),
+            None if render.format == ErrorFormat::Json => eprintln!("{}", log.to_json(file, render.numbers)),
+            None if repeated => eprintln!("{}", log.render(None, render)),
+            None => eprintln!("{}", log.render(Some(file_text), render)),
+        }
+        last_span = log.span.as_ref();
+    }
+}
+
+// Runs the code in the file. Logs less severe than min_severity are not shown or returned.
+// TODO: Print every compiler thing before the program actually runs.
+fn run(file_input: &FileInput, compiler_options: CompilerOptions, min_severity: Severity, options: &RunOptions) -> (Vec<String>, Vec<Log>) {
+    if options.interpret {
+        return interpret(file_input, compiler_options, min_severity, options);
+    }
+    let (bytecode, mut logs): (Option<Vec<u8>>, Vec<Log>) = compile_input(file_input, compiler_options, min_severity, options);
+    let mut output: Vec<String> = Vec::new();
+    if let Some(bytecode) = bytecode {
+        output = run_bytecode(file_input, bytecode, false, min_severity, options, &mut logs);
+    }
+    options.events.emit(&Event::diagnostics(file_input.get_name(), &logs));
+
+    (output, logs)
+}
+
+// Runs the code in the file with the interpreter instead of the VM. The interpreter runs on a thread with a large stack,
+// as each function call the program makes is a call in the interpreter. Logs less severe than min_severity are not
+// shown or returned.
+fn interpret(file_input: &FileInput, compiler_options: CompilerOptions, min_severity: Severity, options: &RunOptions) -> (Vec<String>, Vec<Log>) {
+    let name: &str = file_input.get_name();
+    let lex_output: LexerOutput = options.events.stage(name, Stage::Lex, || lex(&file_input.get_file_text()));
+    let parse_output: ParserOutput = options.events.stage(name, Stage::Parse, || parse(lex_output));
+    let run = || {
+        let InterpreterOutput { file_text, interpreter, logs } = prepare(parse_output, compiler_options, options.script, &options.passes);
+        let mut logs: Vec<Log> = print_compiler_logs(file_input, &file_text, &logs, interpreter.is_some(), min_severity, options);
+        let Some(mut interpreter) = interpreter else {
+            return (Vec::new(), logs);
+        };
+        match file_input {
+            FileInput::Linked(source) => interpreter.set_linked_source(source.clone()),
+            FileInput::FilePath(path) if options.render.format == ErrorFormat::Json => {
+                interpreter.set_diagnostic_sink(JsonSink::new(stderr(), Some(path)).with_number_format(options.render.numbers));
+            }
+            _ => {}
+        }
+        interpreter.set_args(options.program_args.clone());
+        interpreter.set_file_access(options.file_access);
+        interpreter.set_render_options(options.render);
+        let run_logs: Vec<Log> = options.events.stage(name, Stage::Run, || interpreter.run()).err().unwrap_or_default();
+        logs.extend(filter_by_severity(&run_logs, min_severity));
+        (interpreter.output(), logs)
+    };
+    let (output, logs): (Vec<String>, Vec<Log>) = thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(STACK_BYTES)
+            .spawn_scoped(scope, run)
+            .expect("the thread should be able to start")
+            .join()
+            .unwrap_or_else(|panic| resume_unwind(panic))
+    });
+    options.events.emit(&Event::diagnostics(name, &logs));
+
+    (output, logs)
+}
+
+// Runs a compiled program file, printing an error instead if the file is corrupt. Logs less severe than min_severity
+// are not returned.
+fn run_bytecode_file(file_path: &str, min_severity: Severity, options: &RunOptions) -> (Vec<String>, Vec<Log>) {
+    let file: Vec<u8> = read(file_path).expect("should be valid as error handled in command line reader");
+    let mut logs: Vec<Log> = Vec::new();
+    let mut output: Vec<String> = Vec::new();
+    match load_bytecode(&file) {
+        Ok(bytecode) => {
+            let file_input: FileInput = FileInput::FilePath(file_path.to_string());
+            output = run_bytecode(&file_input, bytecode, true, min_severity, options, &mut logs);
+        }
+        Err(log) => {
+            eprintln!("{}", log.render(None, options.render));
+            logs.push(log);
+        }
+    }
+    options.events.emit(&Event::diagnostics(file_path, &logs));
+
+    (output, logs)
+}
+
+// Runs bytecode with the program arguments and file access in the options, adding its logs to logs. Bytecode that
+// didn't come from the compiler should be verified first, so that it can't crash the VM. Runtime errors are printed with
+// the file they happen in if the bytecode was compiled from many files, or as JSON naming the file it came from.
+// Returns what the program printed.
+fn run_bytecode(
+    file_input: &FileInput, bytecode: Vec<u8>, verify: bool, min_severity: Severity, options: &RunOptions, logs: &mut Vec<Log>,
+) -> Vec<String> {
+    let mut vm: Vm = Vm::new(bytecode);
+    vm.set_verify(verify);
+    match file_input {
+        FileInput::Linked(source) => vm.set_linked_source(source.clone()),
+        FileInput::FilePath(path) if options.render.format == ErrorFormat::Json => {
+            vm.set_diagnostic_sink(JsonSink::new(stderr(), Some(path)).with_number_format(options.render.numbers));
+        }
+        _ => {}
+    }
+    vm.set_args(options.program_args.clone());
+    vm.set_file_access(options.file_access);
+    vm.set_render_options(options.render);
+    vm.set_profiling(options.profile.is_some());
+    let run_logs: Vec<Log> = options.events.stage(file_input.get_name(), Stage::Run, || vm.run()).err().unwrap_or_default();
+    logs.extend(filter_by_severity(&run_logs, min_severity));
+    if let Some(format) = options.profile {
+        eprintln!("{}", vm.profile().format(format));
+    }
+    vm.output()
+}
+
+// Compiles the code in the file, linked with any files after it, for the backend in the options and writes the result to
+// a file with the same name as the first file and the extension of the backend, which is "krustc" for compiled
+// programs. Returns whether or not the file was written.
+fn build_file(
+    file_path: &str, linked_paths: &[String], compiler_options: CompilerOptions, min_severity: Severity, options: &RunOptions,
+) -> bool {
+    let file_input: FileInput = source_input(file_path, linked_paths);
+    let (artifact, mut logs): (Option<Vec<u8>>, Vec<Log>) = match options.backend {
+        Backend::Bytecode => {
+            let (bytecode, logs): (Option<Vec<u8>>, Vec<Log>) = compile_input(&file_input, compiler_options, min_severity, options);
+            (bytecode.map(|bytecode| serialize_bytecode(&bytecode)), logs)
+        }
+        Backend::Wasm => compile_wasm(&file_input, compiler_options, min_severity, options),
+    };
+    let mut built: bool = false;
+    if let Some(artifact) = artifact {
+        let output_path: String = options.output.clone().unwrap_or_else(|| {
+            Path::new(file_path).with_extension(options.backend.file_extension()).display().to_string()
+        });
+        match write(&output_path, artifact) {
+            Ok(()) => {
+                options.events.emit(&Event::ArtifactWritten { file: file_input.get_name().to_string(), path: output_path });
+                built = true;
+            }
+            Err(error) => {
+                logs.push(Log {
+                    log_type: LogType::Error(ErrorType::FileWriteFailed(output_path, error.to_string())),
+                    line_and_col: None,
+                    span: None,
+                    notes: Vec::new(),
+                });
+                eprintln!("{}", logs.iter().last().expect("list was just pushed to").render(None, options.render));
+            }
+        }
+    }
+    options.events.emit(&Event::diagnostics(file_input.get_name(), &logs));
+    built
+}
+
+// Runs the file, and runs it again whenever it or the files linked with it change, first clearing the screen if the
+// output goes to a terminal. It only stops when the process is stopped, such as with Ctrl+C.
+fn watch_file(
+    file_path: &str, linked_paths: &[String], compiler_options: CompilerOptions, min_severity: Severity, options: &RunOptions,
+) -> ! {
+    let paths: Vec<PathBuf> = once(file_path).chain(linked_paths.iter().map(String::as_str)).map(PathBuf::from).collect();
+    let mut watcher: Watcher = Watcher::new(paths.clone(), DEBOUNCE);
+    loop {
+        if stdout().is_terminal() {
+            print!("\x1b[2J\x1b[H");
+            // The screen has to be cleared before any errors are printed to stderr.
+            let _ = stdout().flush();
+        }
+        // A file being saved can be briefly missing, and is read again once it is back.
+        if let Some(missing) = paths.iter().find(|path| !path.is_file()) {
+            eprintln!("{}", Log {
+                log_type: LogType::Error(ErrorType::CLICantOpenFile(missing.display().to_string())),
+                line_and_col: None,
+                span: None,
+                notes: Vec::new(),
+            }.render(None, options.render));
+        } else {
+            run(&source_input(file_path, linked_paths), compiler_options, min_severity, options);
+        }
+        eprintln!("watching \"{file_path}\" for changes...");
+        watcher.wait_for_change();
+    }
 }
 
-fn main() {
-    let cli_output: (Option<CLIInfo>, Vec<Log>) = read_command_line();
-    for log in cli_output.1 {
-        eprintln!("{log}");
+/// The module for running tests.
+#[cfg(test)]
+mod tests {
+    use super::{build_file, check_input, emit_file, fmt_file, run, run_bytecode_file, RunOptions};
+    use super::FileInput;
+    use krust::backend::{wasm, Backend};
+    use krust::{compile_source, run_source, Program};
+    use krust::cli::{completions, help, parse_args, Invocation, Shell, FLAGS};
+    use krust::cli_reader::{read_args, CliOptions, Command, Emit, ExitStatus};
+    use krust::batch::{compile_many, FileResult, LinkedSource, SourceMap, SourcePath};
+    use krust::bench::{synthetic_program, time_runs, BenchConfig, BenchResult, ProgramSize};
+    use krust::cancel::{compile_with_cancellation, CancellationToken, Pass, PartialCompilerOutput};
+    use krust::compiler::{
+        code_start, compile, compile_with_passes, serialize_bytecode, swap_byte_order, CompilerOptions, CompilerOutput,
+        Endianness, IsaLevel, OpCode, Section, Target, FILE_HEADER_SIZE,
+    };
+    use krust::crashcheck::{check_corpus, CaseResult, Crash, DEFAULT_TIMEOUT};
+    use krust::differential::{compare, program, Execution};
+    use krust::disassembler::{disassemble, AddressMode, DisassemblerOutput};
+    use krust::doctest::{extract_snippets, test_snippet, Outcome, Snippet};
+    use krust::events::{Event, EventStream, Stage};
+    use krust::formatter::{format, FormatterOutput};
+    use krust::fuzz::{fuzz_lex, fuzz_parse, fuzz_run_bytecode};
+    use krust::internal_checks::check_internals;
+    use krust::interpreter::{prepare, Interpreter};
+    use krust::lexer::{lex, LexerOutput, Span, TokenType};
+    use krust::metadata::{hash_bytes, hash_source, Metadata};
+    use krust::util::messages::{localize, localize_with, LocaleProvider, MessageCatalog};
+    use krust::util::number_format::NumberFormat;
+    use krust::util::suggestion::{closest, edit_distance};
+    use krust::parser::{parse, Expression, ParserOutput, MAX_NESTING_DEPTH, PARSER_STACK_BYTES};
+    use krust::pass_manager::{CompilerPass, OptLevel, PassManager};
+    use krust::profiler::Profile;
+    use krust::test_runner::{run_tests, TestCase, TestOutcome, TestRunOutput};
+    use krust::project::ProjectConfig;
+    use krust::snapshot::{check_dir, check_snapshot, snapshot, SnapshotOutcome};
+    use krust::util::log;
+    use krust::verifier::verify;
+    use krust::vm::test_func::shift_int;
+    use krust::visit::{children, fold_children, walk, ExpressionFolder, ExpressionVisitor};
+    use krust::vm::{load_bytecode, RunStatus, Step, Value, Vm, VmConfig};
+    use krust::watch::Watcher;
+
+    use log::{
+        all_to_string, filter_by_severity, is_error, limit_errors, CollectingSink, ColorChoice,
+        DiagnosticConfig, DiagnosticSink, ErrorFormat, ErrorType, InfoType, JsonSink, Log, LogType, Note, RenderOptions,
+        Severity, TerminalSink, WarningLevel, WarningType, DEFAULT_ERROR_LIMIT,
+    };
+
+    use arbitrary::{Arbitrary, Unstructured};
+    use proptest::prelude::*;
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // Runs the given code and checks the output against out and err.
+    fn test_code(code: &str, out: &[String], err: &[String]) {
+        let out_err = run(
+            &FileInput::FileText(code.to_string()),
+            CompilerOptions::default(),
+            Severity::Info,
+            &RunOptions { internal_checks: true, ..RunOptions::default() },
+        );
+        assert_eq!(out_err.0, out);
+        assert_eq!(all_to_string(&out_err.1), err);
+    }
+
+    // Runs the given code with overflows of constants allowed and checks the output against out, for testing that ints
+    // wrap the same way whether or not they are folded.
+    fn test_wrapping_code(code: &str, out: &[String]) {
+        let mut diagnostics: DiagnosticConfig = DiagnosticConfig::default();
+        diagnostics.set("constant-overflow", WarningLevel::Allow);
+        let out_err = run(
+            &FileInput::FileText(code.to_string()),
+            CompilerOptions { diagnostics, ..CompilerOptions::default() },
+            Severity::Info,
+            &RunOptions { internal_checks: true, ..RunOptions::default() },
+        );
+        assert_eq!(out_err.0, out);
+        assert_eq!(all_to_string(&out_err.1), Vec::<String>::new());
+    }
+
+    // Gets the warning for comparing a value with itself, where the value is the first thing in the code.
+    fn self_comparison_warning(value: &str, op: &str, always: bool) -> Vec<String> {
+        vec![format!(
+            "warning (line 1:{}): comparing a value with itself using \"{op}\" is always {always}; this may be a typo.",
+            value.len() + 1
+        )]
+    }
+
+    // Runs the verifier on the given bytecode and checks the errors against err.
+    fn test_verify(bytecode: &[u8], err: &[String]) {
+        assert_eq!(all_to_string(&verify(bytecode).logs), err);
+    }
+
+    // Compiles the given code and checks that the verifier accepts it.
+    fn test_verify_code(code: &str) {
+        let bytecode: Vec<u8> = compile(parse(lex(code)), CompilerOptions::new(8, true, IsaLevel::LATEST))
+            .bytecode
+            .expect("code should compile");
+        test_verify(&bytecode, &[]);
+    }
+
+    #[test]
+    fn empty() {
+        test_code("", &Vec::new(), &Vec::new());
+    }
+
+    #[test]
+    fn above_max_int() {
+        test_code(
+            format!("{}", 0x8000_0001u32).as_str(),
+            &Vec::new(),
+            &[
+                format!(
+                    "error (line 1:1): int literal \"{}\" must be at most 2,147,483,648 (0x8000_0000).",
+                    0x8000_0001u32
+                ),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn max_int_no_sign() {
+        test_code(
+            format!("{}", 0x8000_0000u32).as_str(), 
+            &Vec::new(), 
+            &[
+                "error (line 1:1): the int literal 2,147,483,648 must be preceded by a unary \'-\' operator.".to_string(),
+                "error: could not compile due to errors.".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn max_pos_int() {
+        test_code(
+            format!("{}", 0x8000_0000u32 - 1).as_str(),
+            &[format!("{}", 0x8000_0000u32 - 1)],
+            &Vec::new(),
+        );
+    }
+
+    #[test]
+    fn below_min_int() {
+        test_code(
+            format!("-{}", 0x8000_0001u32).as_str(),
+            &Vec::new(),
+            &[
+                format!(
+                    "error (line 1:2): int literal \"{}\" must be at most 2,147,483,648 (0x8000_0000).",
+                    0x8000_0001u32
+                ),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn min_int() {
+        test_code(
+            format!("-{}", 0x8000_0000u32).as_str(),
+            &[format!("-{}", 0x8000_0000u32)],
+            &Vec::new(),
+        );
+    }
+
+    #[test]
+    fn open_left_paren() {
+        test_code(
+            "(",
+            &Vec::new(),
+            &[
+                "error (line 1:2): unexpected end of file.".to_string(),
+                "error (line 1:2): expected \')\' following \'(\'.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn error_recovery() {
+        test_code(
+            "int a = 3 4;\nint b = (2;\nmax(1 2) + 1;\n(5 6) + true;\n}\na + b",
+            &Vec::new(),
+            &[
+                "error (line 1:11): unexpected token \"4\".".to_string(),
+                "error (line 2:11): expected ')' following '('.".to_string(),
+                "error (line 3:7): expected ')' following '('.".to_string(),
+                "error (line 4:4): expected ')' following '('.".to_string(),
+                "error (line 5:1): unexpected token \"}\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "fn f() -> int { 1 2; 3 }\nf() + true",
+            &Vec::new(),
+            &[
+                "error (line 1:19): expected '}' following '{'.".to_string(),
+                "error (line 2:5): the operator \"+\" has no definition over the types \"int\" and \"bool\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "1 +",
+            &Vec::new(),
+            &[
+                "error (line 1:4): unexpected end of file.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "x +",
+            &Vec::new(),
+            &[
+                "error (line 1:4): unexpected end of file.".to_string(),
+                "error (line 1:1): expected a variable declaration for x".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        let expr: Expression = parse(lex("1 2; 3")).expr;
+        assert!(matches!(
+            &expr,
+            Expression::ExpressionList { list } if matches!(&*list[1], Expression::Statement { expr } if matches!(**expr, Expression::Error { .. }))
+        ));
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(edit_distance("itn", "int"), 1);
+        assert_eq!(edit_distance("boool", "bool"), 1);
+        assert_eq!(edit_distance("", "fn"), 2);
+        assert_eq!(closest("--pointer-size", ["-pointer_size", "--passes"]), Some("-pointer_size"));
+        assert_eq!(closest("x", ["fn", "for"]), None);
+        assert_eq!(closest("int", ["int"]), None);
+        test_code(
+            "itn x = 5;\n1 =< 2;",
+            &Vec::new(),
+            &[
+                "error (line 1:5): unexpected token \"x\".".to_string(),
+                "help (line 1:1): did you mean \"int\"?".to_string(),
+                "error (line 2:3): unexpected token \"=\".".to_string(),
+                "help (line 2:3): did you mean \"<=\"?".to_string(),
+                "error (line 1:1): expected a variable declaration for itn".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn open_right_paren() {
+        test_code(
+            ")",
+            &Vec::new(),
+            &[
+                "error (line 1:1): unexpected token \")\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn empty_parens() {
+        test_code(
+            "()",
+            &Vec::new(),
+            &[
+                "error (line 1:2): expected expression within parentheses.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn bad_type_unary() {
+        test_code(
+            "!1",
+            &Vec::new(),
+            &[
+                "error (line 1:1): the operator \"!\" has no definition over the type \"int\"."
+                    .to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn bad_types_binary() {
+        test_code(
+            "1+true",
+            &Vec::new(),
+            &["error (line 1:2): the operator \"+\" has no definition over the types \"int\" and \"bool\".".to_string(),
+                "error: could not compile due to errors.".to_string()],
+        );
+    }
+
+    #[test]
+    fn statements_no_output_test() {
+        test_code(
+            "true;",
+            &Vec::new(),
+            &Vec::new(),
+        );
+    }
+    #[test]
+    fn statement_and_expression() {
+        test_code(
+            "true; false",
+            &["false".to_string()],
+            &Vec::new(),
+        );
+    }
+
+    #[test]
+    fn chained_assignments() {
+        test_code(
+            "int a = int b = 1; a",
+            &["1".to_string()],
+            &Vec::new(),
+        );
+    }
+
+    #[test]
+    fn assignments_as_values() {
+        test_code(
+            "(int a = 6) * a",
+            &["36".to_string()],
+            &Vec::new(),
+        );
+    }
+
+    #[test]
+    fn vars_of_same_name() {
+        test_code(
+            "int var = 1; bool var = true; var",
+            &["true".to_string()],
+            &Vec::new(),
+        );
+    }
+
+    #[test]
+    fn chained_vars_of_same_name() {
+        test_code(
+            "int var = 1; int var = var + 2; var",
+            &["3".to_string()],
+            &Vec::new(),
+        );
+    }
+
+    #[test]
+    fn no_declaration() {
+        test_code(
+            "var = 1;",
+            &Vec::new(),
+            &[
+                "error (line 1:1): expected a variable declaration for var".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn reassignment() {
+        test_code("int a = 1; a = 2; a", &["2".to_string()], &Vec::new());
+    }
+
+    #[test]
+    fn null_literal() {
+        test_code("null", &["null".to_string()], &Vec::new());
+    }
+
+    #[test]
+    fn nullable_set_to_null() {
+        test_code("int? a = 1; a = null; a", &["null".to_string()], &Vec::new());
+    }
+
+    #[test]
+    fn nullable_compare_null() {
+        test_code(
+            "int? a; bool? b = true; (a == null) & (b != null)",
+            &["true".to_string()],
+            &["info (line 1:6): the variable \"a\" has been initialized but hasn't been set to a value. It will instead take the default value of the type.".to_string()],
+        );
+    }
+
+    #[test]
+    fn unwrap_null() {
+        test_code(
+            "int? a = null; a! + 1",
+            &Vec::new(),
+            &["error (line 1:17): attempted to unwrap a null value.".to_string()],
+        );
+    }
+
+    #[test]
+    fn unwrap_non_nullable() {
+        test_code(
+            "1!",
+            &Vec::new(),
+            &[
+                "error (line 1:2): the operator \"!\" has no definition over the type \"int\"."
+                    .to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn null_to_non_nullable() {
+        test_code(
+            "int a = null;",
+            &Vec::new(),
+            &[
+                "error (line 1:7): The variable \"a\" has type \"int\", so it can not be assigned a value of type \"null\"".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn nullable_without_unwrap() {
+        test_code(
+            "int? a = 1; a + 1",
+            &Vec::new(),
+            &[
+                "error (line 1:15): the operator \"+\" has no definition over the types \"int?\" and \"int\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn math_functions() {
+        for (code, out) in [
+            ("abs(-5)", "5"),
+            ("min(3, -2)", "-2"),
+            ("max(3, -2)", "3"),
+            ("pow(2, 10)", "1024"),
+            ("pow(-1, -3) + pow(2, -1)", "-1"),
+            ("sqrt(17)", "4"),
+            ("clamp(12, 0, 10)", "10"),
+            ("abs(-2147483648)", "-2147483648"),
+        ] {
+            test_code(code, &[out.to_string()], &Vec::new());
+        }
+    }
+
+    #[test]
+    fn sqrt_negative() {
+        test_code(
+            "int a = -4; sqrt(a)",
+            &Vec::new(),
+            &["error (line 1:13): attempted to take the square root of a negative number.".to_string()],
+        );
+    }
+
+    #[test]
+    fn function_wrong_arg_count() {
+        test_code(
+            "min(1)",
+            &Vec::new(),
+            &[
+                "error (line 1:1): the function \"min\" takes 2 arguments but was given 1.".to_string(),
+                "note (line 1:1): the function \"min\" takes arguments of the types \"int\" and \"int\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn function_wrong_types() {
+        test_code(
+            "clamp(1, true, 2)",
+            &Vec::new(),
+            &[
+                "error (line 1:1): the function \"clamp\" has no definition over the types \"int\", \"bool\", and \"int\".".to_string(),
+                "note (line 1:1): the function \"clamp\" takes arguments of the types \"int\", \"int\", and \"int\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn unknown_function() {
+        test_code(
+            "foo(1)",
+            &Vec::new(),
+            &[
+                "error (line 1:1): the function \"foo\" does not exist.".to_string(),
+                "help (line 1:1): the built-in functions are \"abs\", \"min\", \"max\", \"pow\", \"sqrt\", \"clamp\", \"len\", \"substring\", \"contains\", \"insert\", \"get\", \"remove\", \"push\", \"pop\", \"ok\", \"err\", \"is_ok\", \"panic\", \"assert\", \"to_string\", \"parse_int\", \"args\", \"random_int\", \"now_millis\", \"read_file\", and \"write_file\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn min_severity() {
+        let code: FileInput = FileInput::FileText("int a; sqrt(true)".to_string());
+        let all: Vec<Log> = run(&code, CompilerOptions::new(8, true, IsaLevel::V2), Severity::Info, &RunOptions::default()).1;
+        assert_eq!(
+            all.iter().map(|log| log.log_type.severity()).collect::<Vec<Severity>>(),
+            [Severity::Error, Severity::Note, Severity::Info, Severity::Error]
+        );
+        let errors: Vec<Log> = run(&code, CompilerOptions::new(8, true, IsaLevel::V2), Severity::Warning, &RunOptions::default()).1;
+        assert_eq!(
+            all_to_string(&errors),
+            [
+                "error (line 1:8): the function \"sqrt\" has no definition over the type \"bool\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ]
+        );
+        assert_eq!(filter_by_severity(&all, Severity::Note).len(), 3);
+    }
+
+    #[test]
+    fn run_without_detailed_errors() {
+        let out_err = run(
+            &FileInput::FileText("10 / 2 + sqrt(16)".to_string()),
+            CompilerOptions::new(8, false, IsaLevel::V2),
+            Severity::Info,
+            &RunOptions { internal_checks: true, ..RunOptions::default() },
+        );
+        assert_eq!(out_err.0, ["9".to_string()]);
+        assert!(out_err.1.is_empty());
+    }
+
+    #[test]
+    fn script() {
+        let code: &str = "int x = 3;\nx = 4;\nx * 2;\n\"hi\";\nfn f(a: int) -> int { a + 1 }\nf(x);\nlist<int>{1, 2};\n x";
+        let out_err = run(
+            &FileInput::FileText(code.to_string()),
+            CompilerOptions::new(8, true, IsaLevel::LATEST),
+            Severity::Info,
+            &RunOptions { internal_checks: true, script: true, ..RunOptions::default() },
+        );
+        assert_eq!(out_err.0, ["8", "hi", "5", "[1, 2]", "4"].map(ToString::to_string));
+        assert!(out_err.1.is_empty());
+    }
+
+    #[test]
+    fn doctest() {
+        let markdown: &str = "# Guide\n\n```krust\n// Doubles x.\nint x = 3;\nx * 2 // => 6\n```\n\n```rust\nfn main() {}\n```\n\n```krust,ignore\nnot krust(\n```\n\n```krust\n\"a\" // => b\n```\n";
+        let snippets: Vec<Snippet> = extract_snippets(markdown);
+        assert_eq!(snippets.len(), 3);
+        assert_eq!((snippets[0].line, snippets[0].expected.clone()), (4, vec!["6".to_string()]));
+        assert!(snippets[1].ignored);
+
+        let options: CompilerOptions = CompilerOptions::new(8, true, IsaLevel::LATEST);
+        assert!(matches!(test_snippet(&snippets[0], options), Outcome::Passed));
+        assert!(matches!(test_snippet(&snippets[1], options), Outcome::Ignored));
+        let Outcome::Failed { output, logs } = test_snippet(&snippets[2], options) else {
+            panic!("the snippet should fail as it prints a different value");
+        };
+        assert_eq!(output, ["a".to_string()]);
+        assert!(logs.is_empty());
+    }
+
+    #[test]
+    fn test_runner() {
+        test_code("assert(1 < 2, \"fine\"); 1", &["1".to_string()], &Vec::new());
+        test_code(
+            "assert(2 < 1, \"two is not less than one\")",
+            &Vec::new(),
+            &["error (line 1:1): assertion failed: two is not less than one".to_string(), "note (line 1:1): in the main program.".to_string()],
+        );
+
+        let code: &str = "fn add(a: int, b: int) -> int { a + b }\nfn test_add() { assert(add(1, 2) == 3, \"1 + 2 is 3\"); }\nfn test_broken() -> int { assert(add(2, 2) == 5, \"2 + 2 is 5\"); 1 }\nadd(1, 2)";
+        let options: CompilerOptions = CompilerOptions::new(8, true, IsaLevel::LATEST);
+        let output: TestRunOutput = run_tests(code, options, None);
+        assert!(output.logs.is_empty());
+        assert_eq!(output.filtered_out, 0);
+        assert_eq!(
+            output.results.iter().map(|(case, _)| case.clone()).collect::<Vec<TestCase>>(),
+            [TestCase { name: "test_add".to_string(), line: 2 }, TestCase { name: "test_broken".to_string(), line: 3 }]
+        );
+        assert!(matches!(output.results[0].1, TestOutcome::Passed));
+        let TestOutcome::Failed { output: printed, logs } = &output.results[1].1 else {
+            panic!("the assertion in the test should fail");
+        };
+        assert!(printed.is_empty());
+        assert_eq!(
+            all_to_string(logs),
+            [
+                "error (line 3:27): assertion failed: 2 + 2 is 5",
+                "note (line 3:27): in the function \"test_broken\".",
+                "note (line 3:4): in the main program.",
+            ]
+        );
+
+        let output: TestRunOutput = run_tests(code, options, Some("add"));
+        assert_eq!((output.results.len(), output.filtered_out), (1, 1));
+        let output: TestRunOutput = run_tests("fn test_typo() { assert(1, \"no\"); }", options, None);
+        assert!(output.results.is_empty());
+        assert!(is_error(&output.logs));
+    }
+
+    #[test]
+    fn benchmarking() {
+        let options: CompilerOptions = CompilerOptions::new(8, true, IsaLevel::LATEST);
+        let compile_program = |code: &str| {
+            let output: CompilerOutput = compile(parse(lex(code)), options);
+            assert!(!is_error(&output.logs), "{:?}", all_to_string(&output.logs));
+            output.bytecode.expect("the program should compile")
+        };
+        let sizes: Vec<usize> = ProgramSize::ALL.iter().map(|size| synthetic_program(*size).len()).collect();
+        assert!(sizes[0] < sizes[1] && sizes[1] < sizes[2]);
+        // The large program takes too long to compile without optimizations to be tested here.
+
+        let config: BenchConfig = BenchConfig { warmups: 1, iterations: 3 };
+        for size in [ProgramSize::Small, ProgramSize::Medium] {
+            let Ok(result) = time_runs(compile_program(&synthetic_program(size)), config) else {
+                panic!("the {} program should run", size.name());
+            };
+            assert_eq!(result.times.len(), 3);
+        }
+        let errors: Vec<Log> = time_runs(compile_program("panic(\"stop\")"), config).expect_err("the program should fail");
+        assert_eq!(all_to_string(&errors)[0], "error (line 1:1): the program panicked: stop");
+
+        let result: BenchResult = BenchResult { times: [3, 1, 2, 10].map(Duration::from_millis).to_vec() };
+        assert_eq!(
+            (result.min(), result.median(), result.mean(), result.max()),
+            (Duration::from_millis(1), Duration::from_micros(2500), Duration::from_millis(4), Duration::from_millis(10))
+        );
+        assert_eq!(result.to_table().lines().next(), Some("min            1.000ms"));
+    }
+
+    #[test]
+    fn fuzzing() {
+        assert!(is_error(&fuzz_lex(&[b'1', b'"', 0xff])));
+        assert!(fuzz_lex(b"1 + 2").is_empty());
+        assert!(is_error(&fuzz_parse("fn (")));
+        for source in ["1 +", "-", "!", "1 + 2 *"] {
+            assert!(is_error(&fuzz_parse(source)), "{source}");
+        }
+        // Nesting past the limit is reported instead of overflowing the stack, which would abort the whole process.
+        for source in [
+            format!("{}1{}", "(".repeat(150), ")".repeat(150)),
+            format!("{}1", "-".repeat(10_000)),
+            format!("{}1{}", "max(1, ".repeat(150), ")".repeat(150)),
+        ] {
+            let logs: Vec<Log> = thread::Builder::new()
+                .stack_size(PARSER_STACK_BYTES)
+                .spawn(move || fuzz_parse(&source))
+                .expect("the thread should be able to start")
+                .join()
+                .expect("parsing shouldn't panic");
+            assert!(logs.iter().any(|log| log.log_type == LogType::Error(ErrorType::NestedTooDeeply(MAX_NESTING_DEPTH))));
+        }
+        assert!(fuzz_parse("int x = 1; x + 2").is_empty());
+        assert!(is_error(&fuzz_run_bytecode(&[])));
+        assert!(is_error(&fuzz_run_bytecode(&[8, 1, 1, 0, 0, OpCode::PushInt as u8, 1, 0, 0, 0, 255])));
+
+        let options: CompilerOptions = CompilerOptions::new(8, true, IsaLevel::LATEST);
+        for seed in 0..64_u32 {
+            let data: Vec<u8> = (0..512_u32).map(|index| (index.wrapping_mul(seed * 2 + 1) ^ seed.wrapping_mul(97)).to_le_bytes()[0]).collect();
+            let output: ParserOutput = ParserOutput::arbitrary(&mut Unstructured::new(&data)).expect("any data gives a program");
+            assert!(!is_error(&output.logs), "{}\n{:?}", output.file_text, all_to_string(&output.logs));
+            // The expression generated from the same data is the one the program parses to.
+            let expr: Expression = Expression::arbitrary(&mut Unstructured::new(&data)).expect("any data gives a program");
+            assert_eq!(expr.to_tree_string(&output.file_text), output.expr.to_tree_string(&output.file_text));
+            if let Some(bytecode) = compile(output, options).bytecode {
+                assert!(verify(&bytecode).logs.is_empty());
+                let _ = fuzz_run_bytecode(&bytecode);
+            }
+        }
+    }
+
+    #[test]
+    fn snapshots() {
+        let options: CompilerOptions = CompilerOptions::new(8, true, IsaLevel::LATEST);
+        let dir: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots");
+        let results: Vec<(PathBuf, SnapshotOutcome)> = check_dir(&dir, options).expect("the snapshots should be readable");
+        assert!(!results.is_empty());
+        for (path, outcome) in results {
+            if let SnapshotOutcome::Mismatched { diff } = outcome {
+                panic!("the snapshot of {} changed:\n{diff}", path.display());
+            }
+        }
+
+        let text: String = snapshot("int x = true; x", options);
+        assert!(text.starts_with("=== tokens ===\n"));
+        assert!(text.contains("\n=== disassembly ===\n\n=== diagnostics ===\nerror[E"));
+
+        let path: PathBuf = std::env::temp_dir().join("krust_snapshot.snap");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(check_snapshot(&path, "1 + 2", options).unwrap(), SnapshotOutcome::Written);
+        assert_eq!(check_snapshot(&path, "1 + 2", options).unwrap(), SnapshotOutcome::Matched);
+        let SnapshotOutcome::Mismatched { diff } = check_snapshot(&path, "1 + 3", options).unwrap() else {
+            panic!("changing the source should change the snapshot");
+        };
+        assert!(diff.starts_with("@@ line "));
+        assert!(diff.lines().any(|line| line.starts_with('-') && line.contains('2')));
+        assert!(diff.lines().any(|line| line.starts_with('+') && line.contains('3')));
+    }
+
+    #[test]
+    fn string_literal() {
+        test_code("string s = \"a \\\"quoted\\\" word\"; s", &["a \"quoted\" word".to_string()], &Vec::new());
+    }
+
+    #[test]
+    fn string_functions() {
+        for (code, out) in [
+            ("len(\"héllo\")", "5"),
+            ("len(\"\")", "0"),
+            ("substring(\"日本語\", 1, 3)", "本語"),
+            ("substring(\"hello\", 2, 2)", ""),
+            ("contains(\"naïve\", \"ï\")", "true"),
+            ("contains(\"hello\", \"world\")", "false"),
+        ] {
+            test_code(code, &[out.to_string()], &Vec::new());
+        }
+    }
+
+    #[test]
+    fn substring_out_of_range() {
+        test_code(
+            "substring(\"héllo\", 3, 6)",
+            &Vec::new(),
+            &["error (line 1:1): substring indices are out of range.".to_string()],
+        );
+    }
+
+    #[test]
+    fn unterminated_string() {
+        test_code(
+            "\"hello",
+            &Vec::new(),
+            &[
+                "error (line 1:1): string literal is missing a closing '\"'.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn invalid_escape_sequence() {
+        test_code(
+            "\"a\\qb\"",
+            &Vec::new(),
+            &[
+                "error (line 1:3): invalid escape sequence \"\\q\" in string literal.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn map_literal() {
+        test_code(
+            "map[int]bool {3: true, -1: false, 2: true}",
+            &["{-1: false, 2: true, 3: true}".to_string()],
+            &Vec::new(),
+        );
+        test_code("map[bool]int {}", &["{}".to_string()], &Vec::new());
+    }
+
+    #[test]
+    fn map_functions() {
+        for (code, out) in [
+            ("map[int]int m = map[int]int {1: 10}; insert(m, 2, 20); get(m, 2)", "20"),
+            ("map[int]int m = map[int]int {1: 10}; get(m, 2)", "null"),
+            ("map[int]int m = map[int]int {1: 10}; insert(m, 1, 11); len(m)", "1"),
+            ("map[int]int m = map[int]int {1: 10}; remove(m, 1)", "10"),
+            ("map[int]int m = map[int]int {1: 10}; remove(m, 1); m", "{}"),
+            ("len(insert(insert(map[bool]bool {}, true, false), false, true))", "2"),
+        ] {
+            test_code(code, &[out.to_string()], &Vec::new());
+        }
+    }
+
+    #[test]
+    fn heap_variable_defaults() {
+        test_code(
+            "map[int]int m; string s; insert(m, len(s), 1)",
+            &["{0: 1}".to_string()],
+            &[
+                "info (line 1:13): the variable \"m\" has been initialized but hasn't been set to a value. It will instead take the default value of the type.".to_string(),
+                "info (line 1:23): the variable \"s\" has been initialized but hasn't been set to a value. It will instead take the default value of the type.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn invalid_map_entry() {
+        test_code(
+            "map[int]int {1: true}",
+            &Vec::new(),
+            &[
+                "error (line 1:15): the map type \"map[int]int\" can not contain an entry with a key of type \"int\" and a value of type \"bool\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn invalid_map_type() {
+        test_code(
+            "map[string]int m;",
+            &Vec::new(),
+            &[
+                "error (line 1:1): expected a map type of the form \"map[key]value\", where the key and value are \"int\" or \"bool\".".to_string(),
+                "error (line 1:4): unexpected token \"[\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn map_function_without_map() {
+        test_code(
+            "get(1, 2)",
+            &Vec::new(),
+            &[
+                "error (line 1:1): the function \"get\" has no definition over the types \"int\" and \"int\".".to_string(),
+                "note (line 1:1): the function \"get\" takes arguments of the types \"map[K]V\" and \"K\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn int_literal_suffix() {
+        test_code("5i + 2", &["7".to_string()], &Vec::new());
+        test_code("-2147483648i", &["-2147483648".to_string()], &Vec::new());
+        test_code(
+            "5l * 2u",
+            &Vec::new(),
+            &[
+                "error (line 1:2): the suffix \"l\" gives a literal the type \"long\", which is not supported yet.".to_string(),
+                "error (line 1:7): the suffix \"u\" gives a literal the type \"uint\", which is not supported yet.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "12abc",
+            &Vec::new(),
+            &[
+                "error (line 1:3): invalid suffix \"abc\" on an int literal; the valid suffixes are \"i\", \"l\", and \"u\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn pathological_lexing() {
+        // Lexing used to take quadratic time, so these took hours. The bound leaves plenty of room for slow machines.
+        let start: Instant = Instant::now();
+        let digits: String = "9".repeat(2_000_000);
+        let output: LexerOutput = lex(&digits);
+        assert_eq!(output.tokens.iter().map(|token| token.token_type).collect::<Vec<TokenType>>(), [TokenType::Error, TokenType::EOF]);
+        assert_eq!(output.logs.len(), 1);
+        let operators: String = "<<=!".repeat(100_000);
+        let output: LexerOutput = lex(&operators);
+        assert_eq!(output.tokens.len(), 300_001);
+        assert_eq!(output.tokens[299_999].to_string(&output.file_text), "!");
+        let identifiers: String = "é_x ".repeat(100_000);
+        let output: LexerOutput = lex(&identifiers);
+        assert_eq!(output.tokens[99_999].to_string(&output.file_text), "é_x");
+        assert!(start.elapsed() < Duration::from_secs(20));
+    }
+
+    #[test]
+    fn list_literal() {
+        test_code("list<int> {3, -1, 2}", &["[3, -1, 2]".to_string()], &Vec::new());
+        test_code("list<bool> {}", &["[]".to_string()], &Vec::new());
+    }
+
+    #[test]
+    fn list_functions() {
+        for (code, out) in [
+            ("list<int> l = list<int> {1}; push(l, 2); l", "[1, 2]"),
+            ("list<int> l = list<int> {1, 2}; pop(l)", "2"),
+            ("list<int> l = list<int> {1, 2}; pop(l); l", "[1]"),
+            ("pop(list<bool> {})", "null"),
+            ("list<int> l = list<int> {4, 5, 6}; l[1] + len(l)", "8"),
+            ("len(push(push(list<bool> {}, true), false))", "2"),
+        ] {
+            test_code(code, &[out.to_string()], &Vec::new());
+        }
+    }
+
+    #[test]
+    fn list_growth() {
+        test_code(
+            "list<int> l = list<int> {}; int i = 0; i = len(push(l, 7)); i = len(push(l, 8)); i = len(push(l, 9)); l[2] * len(l)",
+            &["27".to_string()],
+            &Vec::new(),
+        );
+    }
+
+    #[test]
+    fn list_index_out_of_range() {
+        test_code(
+            "list<int> l = list<int> {1, 2}; l[2]",
+            &Vec::new(),
+            &["error (line 1:34): list index is out of range.".to_string()],
+        );
+        test_code(
+            "list<int> {1}[-1]",
+            &Vec::new(),
+            &["error (line 1:14): list index is out of range.".to_string()],
+        );
+    }
+
+    #[test]
+    fn invalid_list_index() {
+        test_code(
+            "list<int> {1}[true]",
+            &Vec::new(),
+            &[
+                "error (line 1:14): the operator \"[]\" has no definition over the types \"list<int>\" and \"bool\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn invalid_list_element() {
+        test_code(
+            "list<int> {1, true}",
+            &Vec::new(),
+            &[
+                "error (line 1:15): the list type \"list<int>\" can not contain an element of type \"bool\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn functions() {
+        test_code(
+            "fn square(x: int) -> int { x * x } square(3) + square(4)",
+            &["25".to_string()],
+            &Vec::new(),
+        );
+        test_code(
+            "fn add_all(a: int, b: int?) -> int { int c = a; c + b! }; add_all(1, 2)",
+            &["3".to_string()],
+            &Vec::new(),
+        );
+        test_code(
+            "fn first(l: list<int>) -> int? { pop(l) } fn twice(l: list<int>) -> int { first(l)! + first(l)! } twice(list<int> {1, 2})",
+            &["3".to_string()],
+            &Vec::new(),
+        );
+        test_code("fn nothing(a: bool) { a; } nothing(true); 1", &["1".to_string()], &Vec::new());
+    }
+
+    #[test]
+    fn generic_functions() {
+        test_code(
+            "fn id<T>(x: T) -> T { T y = x; y } id(5) + len(id(\"abc\")); id(true)",
+            &["true".to_string()],
+            &["warning (line 1:42): the value computed by \"+\" is never used; this may be a typo.".to_string()],
+        );
+        test_code(
+            "fn pick<T>(a: T, b: T, first: bool) -> T { a } fn wrap<U>(u: U) -> U { pick(u, u, true) } wrap(map[int]int {1: 2})",
+            &["{1: 2}".to_string()],
+            &Vec::new(),
+        );
+    }
+
+    #[test]
+    fn inconsistent_type_argument() {
+        test_code(
+            "fn pick<T>(a: T, b: T) -> T { a } pick(1, true)",
+            &Vec::new(),
+            &[
+                "error (line 1:35): the type parameter \"T\" of the function \"pick\" can not be both \"int\" and \"bool\".".to_string(),
+                "note (line 1:35): the function \"pick\" takes arguments of the types \"T\" and \"T\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn invalid_generic_operation() {
+        test_code(
+            "fn double<T>(x: T) -> T { x + x } double(1)",
+            &Vec::new(),
+            &[
+                "error (line 1:29): the operator \"+\" has no definition over the types \"T\" and \"T\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn invalid_functions() {
+        test_code(
+            "fn f<T>() -> int { 1 } f()",
+            &Vec::new(),
+            &[
+                "error (line 1:4): the type parameter \"T\" of the function \"f\" must be the type of a parameter so that it can be inferred.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "fn f(a: int) -> bool { a }",
+            &Vec::new(),
+            &[
+                "error (line 1:4): the function \"f\" should return the type \"bool\" but its body has the type \"int\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "fn f(a: foo) { a; }",
+            &Vec::new(),
+            &[
+                "error (line 1:9): expected a type in function declaration.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "fn f(a: int) -> int { a } f(1, 2)",
+            &Vec::new(),
+            &[
+                "error (line 1:27): the function \"f\" takes 1 argument but was given 2.".to_string(),
+                "note (line 1:27): the function \"f\" takes arguments of the type \"int\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn traits() {
+        test_code(
+            "trait Describe { fn describe(self) -> string; fn twice(self, n: int) -> Self; }
+            impl Describe for int { fn describe(self) -> string { \"int\" } fn twice(self, n: int) -> int { self * n * 2 } }
+            impl Describe for bool { fn twice(self, n: int) -> bool { self } fn describe(self) -> string { \"bool\" } }
+            len(1.describe()) + 3.twice(2) + len(true.twice(0).describe())",
+            &["19".to_string()],
+            &Vec::new(),
+        );
+    }
+
+    #[test]
+    fn trait_bounds() {
+        test_code(
+            "trait Size { fn size(self) -> int; }
+            impl Size for string { fn size(self) -> int { len(self) } }
+            impl Size for list<int> { fn size(self) -> int { len(self) } }
+            fn total<T: Size, U: Size>(a: T, b: U) -> int { a.size() + b.size() }
+            total(\"abc\", list<int> {1, 2}) + total(\"d\", \"ef\")",
+            &["8".to_string()],
+            &Vec::new(),
+        );
+        test_verify_code(
+            "trait Size { fn size(self) -> int; } impl Size for int { fn size(self) -> int { self } }
+            fn f<T: Size>(a: T) -> int { a.size() } f(1) + 2.size()",
+        );
+    }
+
+    #[test]
+    fn invalid_traits() {
+        test_code(
+            "trait Size { fn size(self) -> int; } fn f<T: Size>(a: T) -> int { a.size() } f(true)",
+            &Vec::new(),
+            &[
+                "error (line 1:78): the type \"bool\" does not implement the trait \"Size\", which is required by the function \"f\".".to_string(),
+                "note (line 1:78): the function \"f\" takes arguments of the type \"T\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "trait Size { fn size(self) -> int; fn grow(self) -> Self; } impl Size for int { fn size(self) -> bool { true } fn shrink(self) -> int { self } }",
+            &Vec::new(),
+            &[
+                "error (line 1:84): the method \"size\" does not match its signature in the trait \"Size\".".to_string(),
+                "error (line 1:115): \"shrink\" is not a method of the trait \"Size\".".to_string(),
+                "error (line 1:66): the implementation of the trait \"Size\" for the type \"int\" is missing the method \"grow\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "trait Size { fn size(self) -> int; } impl Size for int { } 1.size()",
+            &Vec::new(),
+            &[
+                "error (line 1:43): the implementation of the trait \"Size\" for the type \"int\" is missing the method \"size\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "1.size()",
+            &Vec::new(),
+            &[
+                "error (line 1:3): the type \"int\" has no method named \"size\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "impl Size for int { fn size(self) -> int { self } }",
+            &Vec::new(),
+            &[
+                "error (line 1:6): the trait \"Size\" does not exist.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn recursion_limit() {
+        // Only the innermost calls are listed in the stack trace.
+        let mut logs: Vec<String> = vec!["error (line 1:29): more than 10,000 function calls were active at once.".to_string()];
+        logs.append(&mut vec!["note (line 1:29): in the function \"forever\".".to_string(); 16]);
+        logs.push("note: in 9984 more function calls.".to_string());
+        logs.push("note (line 1:46): in the main program.".to_string());
+        test_code("fn forever(a: int) -> int { forever(a) + 1 } forever(1)", &Vec::new(), &logs);
+    }
+
+    #[test]
+    fn vm_config() {
+        let bytecode: Vec<u8> = compile(parse(lex("fn f(n: int) -> int { f(n - 1) + n }\nf(1)")), CompilerOptions::default())
+            .bytecode.expect("code should compile");
+        let run_with = |config: VmConfig| -> Vec<Log> {
+            let mut vm: Vm = Vm::new(bytecode.clone());
+            vm.set_error_output(std::io::sink());
+            vm.set_config(config);
+            vm.run().err().unwrap_or_default()
+        };
+        let logs: Vec<Log> = run_with(VmConfig { max_stack_bytes: 256, ..VmConfig::default() });
+        assert!(matches!(logs[..], [Log { log_type: LogType::Error(ErrorType::VmStackOverflow(_, 256)), .. }, ..]));
+        assert!(logs[0].to_string().starts_with("error: the stack grew past its limit of 256 bytes at offset "));
+        assert_eq!(
+            all_to_string(&run_with(VmConfig { max_call_depth: 5, ..VmConfig::default() })),
+            [
+                "error (line 1:23): more than 5 function calls were active at once.".to_string(),
+                "note (line 1:23): in the function \"f\".".to_string(),
+                "note (line 1:23): in the function \"f\".".to_string(),
+                "note (line 1:23): in the function \"f\".".to_string(),
+                "note (line 1:23): in the function \"f\".".to_string(),
+                "note (line 1:23): in the function \"f\".".to_string(),
+                "note (line 2:1): in the main program.".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn fuel() {
+        let bytecode = |code: &str| -> Vec<u8> {
+            compile(parse(lex(code)), CompilerOptions::default()).bytecode.expect("code should compile")
+        };
+        let mut vm: Vm = Vm::new(bytecode("fn f(n: int) -> int { f(n) } f(0)"));
+        assert_eq!(vm.run_with_fuel(1000).ok(), Some(RunStatus::OutOfFuel));
+        assert!(vm.is_paused());
+        assert_eq!(vm.resume_with_fuel(1000).ok(), Some(RunStatus::OutOfFuel));
+
+        // Running one instruction at a time gives the same result as running them all at once.
+        let mut vm: Vm = Vm::new(bytecode("fn f(n: int) -> int { n * 2 } f(3) + f(4)"));
+        let mut status: Option<RunStatus> = vm.run_with_fuel(0).ok();
+        let mut steps: u64 = 0;
+        while status == Some(RunStatus::OutOfFuel) {
+            status = vm.resume_with_fuel(1).ok();
+            steps += 1;
+        }
+        assert_eq!(status, Some(RunStatus::Finished(Value::Int(14))));
+        assert!(!vm.is_paused());
+        assert_eq!(vm.run_with_fuel(steps).ok(), Some(RunStatus::Finished(Value::Int(14))));
+        assert_eq!(vm.run_with_fuel(steps - 1).ok(), Some(RunStatus::OutOfFuel));
+    }
+
+    #[test]
+    fn vm_step() {
+        let bytecode: Vec<u8> = compile(parse(lex("int a = 6; a * 7")), CompilerOptions::default().with_detailed_errors(false))
+            .bytecode.expect("code should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.set_output(std::io::sink());
+        let mut steps: Vec<Step> = Vec::new();
+        loop {
+            let step: Step = vm.step().expect("the program should run");
+            let ip: Option<usize> = step.ip;
+            steps.push(step);
+            if ip.is_none() {
+                break;
+            }
+        }
+        let ops: Vec<Option<OpCode>> = steps.iter().map(|step| step.op).collect();
+        assert_eq!(ops.first(), Some(&Some(OpCode::AllocInt)));
+        assert_eq!(ops.last(), Some(&Some(OpCode::PrintInt)));
+        assert!(steps.windows(2).all(|pair| pair[0].ip == Some(pair[1].offset)));
+        let multiply: Option<&Step> = steps.iter().find(|step| step.op == Some(OpCode::MultiplyInt));
+        assert_eq!(multiply.map(|step| step.stack.clone()), Some(vec![6, 0, 0, 0, 42, 0, 0, 0]));
+        assert_eq!(steps.last().map(|step| step.stack.clone()), Some(vec![6, 0, 0, 0]));
+        assert_eq!(vm.output(), ["42".to_string()]);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let code: &str = "fn f(n: int) -> int {\n    10 / n\n}\nf(2) + f(5)";
+        let bytecode: Vec<u8> = compile(parse(lex(code)), CompilerOptions::default()).bytecode.expect("code should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.set_output(std::io::sink());
+        assert!(vm.set_breakpoint(2));
+        assert!(!vm.set_breakpoint(3));
+        // The run pauses at the start of the statement and again before the division, in each call.
+        for _ in 0..4 {
+            assert_eq!(vm.resume().ok(), Some(RunStatus::Breakpoint(2)));
+            assert!(vm.is_paused());
+        }
+        assert_eq!(vm.resume().ok(), Some(RunStatus::Finished(Value::Int(7))));
+        assert_eq!(vm.run().ok(), Some(Value::Int(7)));
+        vm.remove_breakpoint(2);
+        assert_eq!(vm.resume().ok(), Some(RunStatus::Finished(Value::Int(7))));
+
+        // Lines with statements that can't fail can be stopped at too.
+        let code: &str = "fn sum(n: int, acc: int) -> int { acc + n }\nint a = sum(1, 2);\nint b = a + 1;\nb * 2";
+        let bytecode: Vec<u8> = compile(parse(lex(code)), CompilerOptions::default()).bytecode.expect("code should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.set_output(std::io::sink());
+        assert!(vm.set_breakpoint(1));
+        assert!(vm.set_breakpoint(4));
+        assert_eq!(vm.resume().ok(), Some(RunStatus::Breakpoint(1)));
+        assert_eq!(vm.resume().ok(), Some(RunStatus::Breakpoint(4)));
+        assert_eq!(vm.resume().ok(), Some(RunStatus::Finished(Value::Int(8))));
+    }
+
+    #[test]
+    fn profile() {
+        let profile_of = |code: &str| -> Profile {
+            let bytecode: Vec<u8> = compile(parse(lex(code)), CompilerOptions::default()).bytecode.expect("code should compile");
+            let mut vm: Vm = Vm::new(bytecode);
+            vm.set_output(std::io::sink());
+            vm.set_error_output(std::io::sink());
+            let _ = vm.run();
+            assert_eq!(vm.profile(), Profile::default());
+            vm.set_profiling(true);
+            let _ = vm.run();
+            vm.profile()
+        };
+        let profile: Profile = profile_of("fn g(n: int) -> int { n + 1 }\nfn f(n: int) -> int { g(n) * 2 }\nf(3) + g(1)");
+        let mut functions: Vec<(bool, u64, u64)> = profile.functions.iter()
+            .map(|function| (function.name.is_some(), function.calls, function.instructions))
+            .collect();
+        functions.sort_unstable();
+        assert_eq!(functions, [(false, 1, 7), (true, 1, 6), (true, 2, 10)]);
+        let count = |op: OpCode| profile.ops.iter().find(|other| other.op == op).map(|other| other.count);
+        assert_eq!((count(OpCode::Call), count(OpCode::Return), count(OpCode::AddInt)), (Some(3), Some(3), Some(3)));
+        assert_eq!(profile.ops.iter().map(|op| op.count).sum::<u64>(), 23);
+        assert!(profile.to_table().starts_with("instruction"));
+        assert!(profile.to_json().starts_with("{\"ops\":[{\"op\":"));
+        assert!(profile.to_json().contains("{\"name\":null,\"calls\":1,\"instructions\":7,"));
+
+        // Functions are only named in programs that can panic.
+        let profile: Profile = profile_of("fn p(x: int) -> int { panic(\"no\"); x }\np(1)");
+        assert!(profile.functions.iter().any(|function| function.name.as_deref() == Some("p") && function.calls == 1));
+    }
+
+    #[test]
+    fn default_params() {
+        test_code(
+            "fn f(x: int, y: int = 10, s: string = \"a\") -> int { x + y + len(s) } f(1) + f(1, 2) + f(1, 2, \"bc\")",
+            &["21".to_string()],
+            &Vec::new(),
+        );
+        test_code(
+            "fn f(x: int, y: int = -(2 * 3)) -> int { x * y } f(2)",
+            &["-12".to_string()],
+            &Vec::new(),
+        );
+        test_code(
+            "fn f(x: int, y: int = x) -> int { x + y } fn g(z: int = 1, w: bool) -> int { z } f(1)",
+            &Vec::new(),
+            &[
+                "error (line 1:14): the default value of the parameter \"y\" must be a constant, such as a literal.".to_string(),
+                "error (line 1:60): the parameter \"w\" needs a default value because it follows a parameter with one.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "fn f(x: int = true) -> int { x } f()",
+            &Vec::new(),
+            &[
+                "error (line 1:6): the default value of the parameter \"x\" should have the type \"int\" but has the type \"bool\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "fn f(x: int, y: int = 1) -> int { x + y } f()",
+            &Vec::new(),
+            &[
+                "error (line 1:43): the function \"f\" takes 1 to 2 arguments but was given 0.".to_string(),
+                "note (line 1:43): the function \"f\" takes arguments of the types \"int\" and \"int\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn flags() {
+        test_code(
+            "flags Permissions { READ = 1, WRITE = 1 << 1, ALL = Permissions.READ | Permissions.WRITE, } \
+            int p = Permissions.READ; (p | Permissions.WRITE) == Permissions.ALL",
+            &["true".to_string()],
+            &Vec::new(),
+        );
+        test_code(
+            "flags F { A = 6 } fn f(x: int) -> int { x ^ F.A } f(F.A & 3)",
+            &["4".to_string()],
+            &Vec::new(),
+        );
+        test_code(
+            "int x = 1; flags F { A = x, B = true, A = 2 } F.C + F",
+            &Vec::new(),
+            &[
+                "error (line 1:22): the value of the flag \"A\" must be a constant int, such as a literal.".to_string(),
+                "error (line 1:29): the value of the flag \"B\" must be a constant int, such as a literal.".to_string(),
+                "error (line 1:39): the flags \"F\" already have a flag named \"A\".".to_string(),
+                "error (line 1:49): the flags \"F\" have no flag named \"C\".".to_string(),
+                "error (line 1:53): the flags \"F\" can only be used to get one of their flags, such as with \"F.NAME\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "flags F { A 1 } 2",
+            &Vec::new(),
+            &[
+                "error (line 1:13): expected '=' in flags declaration.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn tail_calls() {
+        // Each call replaces the last one, so the recursion only stops at the division by zero.
+        test_code(
+            "fn countdown(n: int) -> int { 100 / n; countdown(n - 1) } countdown(20000)",
+            &Vec::new(),
+            &[
+                "warning (line 1:35): the value computed by \"/\" is never used; this may be a typo.".to_string(),
+                "error (line 1:35): division by zero.".to_string(),
+                "note (line 1:35): in the function \"countdown\".".to_string(),
+                "note (line 1:59): in the main program.".to_string(),
+            ],
+        );
+        test_code(
+            "trait Count { fn count(self) -> int; }
+            impl Count for int { fn count(self) -> int { 100 / self; ((self - 1).count()) } }
+            20000.count()",
+            &Vec::new(),
+            &[
+                "warning (line 2:62): the value computed by \"/\" is never used; this may be a typo.".to_string(),
+                "error (line 2:62): division by zero.".to_string(),
+                "note (line 2:62): in the function \"int.count\".".to_string(),
+                "note (line 3:19): in the main program.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn pass_manager() {
+        let code: &str = "fn countdown(n: int) -> int { 100 / n; countdown(n - 1) } countdown(20000)";
+        let mut passes: PassManager = PassManager::default();
+        assert!(!passes.disable(CompilerPass::Codegen));
+        assert!(passes.disable(CompilerPass::TailCalls));
+        passes.set_print_after(CompilerPass::Codegen);
+        let output: CompilerOutput = compile_with_passes(parse(lex(code)), CompilerOptions::new(8, true, IsaLevel::LATEST), false, &passes);
+        let dump: String = output.dump.expect("codegen always runs");
+        assert!(dump.contains("Call @") && !dump.contains("TailCall"));
+        let logs: Vec<Log> = Vm::new(output.bytecode.expect("code should compile")).run().err().unwrap_or_default();
+        assert_eq!(
+            all_to_string(&logs)[..2],
+            [
+                "error (line 1:40): more than 10,000 function calls were active at once.".to_string(),
+                "note (line 1:40): in the function \"countdown\".".to_string(),
+            ]
+        );
+
+        passes.set_passes(&[CompilerPass::Verify]);
+        passes.set_print_after(CompilerPass::TailCalls);
+        assert!(passes.is_enabled(CompilerPass::Parse) && !passes.is_enabled(CompilerPass::FunctionNames));
+        let output: CompilerOutput = compile_with_passes(parse(lex(code)), CompilerOptions::new(8, true, IsaLevel::LATEST), false, &passes);
+        assert!(output.dump.is_none());
+        assert_eq!(
+            all_to_string(&output.logs),
+            ["warning (line 1:35): the value computed by \"/\" is never used; this may be a typo."]
+        );
+    }
+
+    #[test]
+    fn optimization_levels() {
+        let enabled = |opt_level: OptLevel| -> Vec<&str> {
+            let passes: PassManager = PassManager::with_opt_level(opt_level);
+            CompilerPass::ALL.into_iter().filter(|pass| passes.is_enabled(*pass)).map(CompilerPass::name).collect()
+        };
+        assert_eq!(enabled(OptLevel::O0), ["parse", "codegen", "function-names"]);
+        assert_eq!(enabled(OptLevel::O1), ["parse", "constant-folding", "codegen", "function-names", "constant-pool"]);
+        assert_eq!(
+            enabled(OptLevel::O2),
+            ["parse", "constant-folding", "tail-calls", "codegen", "function-names", "common-subexpressions", "constant-pool"]
+        );
+        assert_eq!(PassManager::with_opt_level(OptLevel::default()), PassManager::default());
+
+        // Without folding, the division by zero is only found at runtime.
+        let code: &str = "5 % (3 - 3)";
+        let options: CompilerOptions = CompilerOptions { opt_level: OptLevel::O0, ..CompilerOptions::default() };
+        let output: CompilerOutput = compile(parse(lex(code)), options);
+        let bytecode: Vec<u8> = output.bytecode.expect("code should compile");
+        assert_eq!(all_to_string(&Vm::new(bytecode.clone()).run().err().unwrap_or_default()), ["error (line 1:3): division by zero.".to_string()]);
+        let metadata: Metadata = Metadata::read(&bytecode).expect("the compiler always writes metadata");
+        assert_eq!((metadata.options.opt_level, metadata.optimizations), (OptLevel::O0, vec!["function-names".to_string()]));
+        let options: CompilerOptions = CompilerOptions { opt_level: OptLevel::O1, ..CompilerOptions::default() };
+        assert!(compile(parse(lex(code)), options).bytecode.is_none());
+    }
+
+    #[test]
+    fn constant_folding() {
+        let mut passes: PassManager = PassManager::default();
+        passes.set_print_after(CompilerPass::Codegen);
+        let output: CompilerOutput = compile_with_passes(parse(lex("2 + 3 * 4")), CompilerOptions::new(8, true, IsaLevel::LATEST), false, &passes);
+        let dump: String = output.dump.expect("codegen always runs");
+        assert_eq!(dump.lines().skip(1).map(|line| line.split_whitespace().skip(1).collect::<Vec<&str>>().join(" ")).collect::<Vec<String>>(), ["Location (line 1:3)", "PushInt 14", "PrintInt"]);
+
+        // Folding gives the same results as running the operations.
+        for code in [
+            "-(1 << 33) + (-8 >> 1) * ~0 - (7 % -3) + 2147483647 + 1",
+            "int x = 5; x * (2 - 3) + (int) (bool) 3",
+            "(1 < 2) & !(true ^ false) | (3 == 3) & (4 != 5)",
+        ] {
+            let mut outputs: Vec<Option<Value>> = Vec::new();
+            for enabled in [true, false] {
+                let mut passes: PassManager = PassManager::default();
+                if !enabled {
+                    passes.disable(CompilerPass::ConstantFolding);
+                }
+                let output: CompilerOutput = compile_with_passes(parse(lex(code)), CompilerOptions::new(8, true, IsaLevel::LATEST), false, &passes);
+                outputs.push(Vm::new(output.bytecode.expect("code should compile")).run().ok());
+            }
+            assert_eq!(outputs[0], outputs[1]);
+        }
+
+        test_code(
+            "5 % (3 - 3)",
+            &Vec::new(),
+            &[
+                "error (line 1:3): this constant expression divides by zero, which would always fail.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        // A divisor that is always zero fails even if the value being divided isn't known.
+        test_code(
+            "fn f(x: int) -> int { x / (2 - 2) } f(1)",
+            &Vec::new(),
+            &[
+                "error (line 1:25): the divisor is always zero, so this would always fail.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "int x = 3; x % 0",
+            &Vec::new(),
+            &[
+                "error (line 1:14): the divisor is always zero, so this would always fail.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        // Divisors that aren't constant are left to fail at runtime.
+        test_code(
+            "fn f(x: int, y: int) -> int { x / y } f(1, 0)",
+            &Vec::new(),
+            &[
+                "error (line 1:33): division by zero.".to_string(),
+                "note (line 1:33): in the function \"f\".".to_string(),
+                "note (line 1:39): in the main program.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn results() {
+        test_code("ok(5)", &["ok(5)".to_string()], &Vec::new());
+        test_code("err(\"bad input\")", &["err(bad input)".to_string()], &Vec::new());
+        test_code(
+            "result<bool> r = ok(true); result<int> e = err(\"no\"); is_ok(r) & !is_ok(e) & r!",
+            &["true".to_string()],
+            &Vec::new(),
+        );
+        test_code(
+            "result<int> r = err(\"bad input\"); r! + 1",
+            &Vec::new(),
+            &["error (line 1:36): attempted to unwrap an error result: bad input".to_string()],
+        );
+        test_code(
+            "result<int> r; r",
+            &["err()".to_string()],
+            &["info (line 1:13): the variable \"r\" has been initialized but hasn't been set to a value. It will instead take the default value of the type.".to_string()],
+        );
+    }
+
+    #[test]
+    fn try_operator() {
+        // The division is never reached when the argument is an error.
+        let code: &str = "fn divide(r: result<int>) -> result<int> { bool b = true; int x = r?; ok(100 / x) }
+            fn twice(r: result<int>) -> result<int> { ok(divide(r)? * 2) }";
+        test_code(&format!("{code} twice(ok(5))"), &["ok(40)".to_string()], &Vec::new());
+        test_code(&format!("{code} twice(err(\"bad\"))"), &["err(bad)".to_string()], &Vec::new());
+        test_code(
+            &format!("{code} twice(ok(0))"),
+            &Vec::new(),
+            &[
+                "error (line 1:78): division by zero.".to_string(),
+                "note (line 1:78): in the function \"divide\".".to_string(),
+                "note (line 2:58): in the function \"twice\".".to_string(),
+                "note (line 2:76): in the main program.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn invalid_try_operator() {
+        test_code(
+            "ok(1)?",
+            &Vec::new(),
+            &[
+                "error (line 1:6): the operator \"?\" can only be used in the body of a function that returns a result.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "fn f(r: result<int>) -> int { r? } fn g(a: int) -> result<int> { ok(a?) }",
+            &Vec::new(),
+            &[
+                "error (line 1:32): the operator \"?\" can only be used in the body of a function that returns a result.".to_string(),
+                "error (line 1:70): the operator \"?\" has no definition over the type \"int\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "fn f(r: result<int>) -> int { 1 } f(ok(true))",
+            &Vec::new(),
+            &[
+                "error (line 1:35): the function \"f\" has no definition over the type \"result<bool>\".".to_string(),
+                "note (line 1:35): the function \"f\" takes arguments of the type \"result<int>\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+        test_code(
+            "result<string>",
+            &Vec::new(),
+            &[
+                "error (line 1:1): expected a result type of the form \"result<value>\", where the value is \"int\" or \"bool\".".to_string(),
+                "error (line 1:15): unexpected end of file.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn string_conversions() {
+        test_code("to_string(-42)", &["-42".to_string()], &Vec::new());
+        test_code(
+            "string s = to_string(2147483647); len(s) * 10 + (int) contains(s, \"47\")",
+            &["101".to_string()],
+            &Vec::new(),
+        );
+        test_code(
+            "parse_int(\"+17\")! + parse_int(\"-2147483648\")! + len(to_string(5))",
+            &["-2147483630".to_string()],
+            &Vec::new(),
+        );
+        test_code("parse_int(\"12 \")", &["err(\"12 \" is not a valid int.)".to_string()], &Vec::new());
+        test_code(
+            "parse_int(\"2147483648\")!",
+            &Vec::new(),
+            &["error (line 1:24): attempted to unwrap an error result: \"2147483648\" is not a valid int.".to_string()],
+        );
+    }
+
+    #[test]
+    fn panics() {
+        test_code(
+            "int a = 1; panic(\"stop\"); a",
+            &Vec::new(),
+            &[
+                "error (line 1:12): the program panicked: stop".to_string(),
+                "note (line 1:12): in the main program.".to_string(),
+            ],
+        );
+        test_code(
+            "fn check(x: int) -> int { panic(\"bad value\"); x }\n\
+            fn validate<T>(x: T) -> int { check(1) + 1 }\n\
+            trait Run { fn run(self) -> int; }\n\
+            impl Run for bool { fn run(self) -> int { validate(self) * 2 } }\n\
+            true.run()",
+            &Vec::new(),
+            &[
+                "error (line 1:27): the program panicked: bad value".to_string(),
+                "note (line 1:27): in the function \"check\".".to_string(),
+                "note (line 2:31): in the function \"validate<bool>\".".to_string(),
+                "note (line 4:43): in the function \"bool.run\".".to_string(),
+                "note (line 5:6): in the main program.".to_string(),
+            ],
+        );
+        let bytecode: Vec<u8> = compile(parse(lex("fn f() -> int { panic(\"no\"); 1 } f()")), CompilerOptions::new(8, false, IsaLevel::V4))
+            .bytecode
+            .expect("code should compile");
+        assert_eq!(
+            all_to_string(&krust::vm::run(&bytecode).1),
+            [
+                "error: the program panicked: no".to_string(),
+                "note: in the function \"f\".".to_string(),
+                "note: in the main program.".to_string(),
+            ]
+        );
+        test_code(
+            "panic(1)",
+            &Vec::new(),
+            &[
+                "error (line 1:1): the function \"panic\" has no definition over the type \"int\".".to_string(),
+                "note (line 1:1): the function \"panic\" takes arguments of the type \"string\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn isa_levels() {
+        let output: CompilerOutput = compile(parse(lex("1 + 2")), CompilerOptions::new(8, true, IsaLevel::V1));
+        assert_eq!(output.bytecode.expect("code should compile")[2], IsaLevel::V1 as u8);
+        let output: CompilerOutput = compile(parse(lex("fn f() -> int { 1 } f()")), CompilerOptions::new(8, true, IsaLevel::V1));
+        assert_eq!(output.bytecode, None);
+        assert_eq!(
+            all_to_string(&output.logs),
+            ["error: the code needs instruction set level 2 (function calls), but level 1 was targeted.".to_string()]
+        );
+        assert_eq!(
+            all_to_string(&krust::vm::run(&[8, 1, 14, 0, 0]).1),
+            ["error: this program requires instruction set level 14, but only levels 1 to 13 are supported.".to_string()]
+        );
+    }
+
+    #[test]
+    fn type_of() {
+        test_code(
+            "int? a = 1; typeof(a!)",
+            &["int".to_string()],
+            &["note (line 1:13): the expression has the type \"int\".".to_string()],
+        );
+        test_code(
+            "list<int> l; len(typeof(pop(l)))",
+            &["4".to_string()],
+            &[
+                "note (line 1:18): the expression has the type \"int?\".".to_string(),
+                "info (line 1:11): the variable \"l\" has been initialized but hasn't been set to a value. It will instead take the default value of the type.".to_string(),
+            ],
+        );
+        test_code(
+            "fn f<T>(x: T) -> string { typeof(x) } f(map[int]bool {})",
+            &["T".to_string()],
+            &["note (line 1:27): the expression has the type \"T\".".to_string()],
+        );
+        test_code(
+            "typeof 1",
+            &Vec::new(),
+            &[
+                "error (line 1:8): expected '(' following \"typeof\".".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn self_comparison() {
+        test_code(
+            "1 == 1",
+            &["true".to_string()],
+            &["warning (line 1:3): comparing a value with itself using \"==\" is always true; this may be a typo.".to_string()],
+        );
+        test_code(
+            "int x = 3; (-x) < (-x)",
+            &["false".to_string()],
+            &["warning (line 1:17): comparing a value with itself using \"<\" is always false; this may be a typo.".to_string()],
+        );
+        test_code("int x = 3; int y = 3; (x == y) & (1 != 2)", &["true".to_string()], &Vec::new());
+    }
+
+    #[test]
+    fn warning_levels() {
+        let compile_with = |diagnostics: DiagnosticConfig| {
+            let options: CompilerOptions = CompilerOptions { diagnostics, ..CompilerOptions::default() };
+            let output: CompilerOutput = compile(parse(lex("1 == 1")), options);
+            (output.bytecode.is_some(), all_to_string(&output.logs))
+        };
+        let mut diagnostics: DiagnosticConfig = DiagnosticConfig::default();
+        assert!(diagnostics.set("self-comparison", WarningLevel::Allow));
+        assert_eq!(compile_with(diagnostics), (true, Vec::new()));
+        assert!(diagnostics.set("warnings", WarningLevel::Deny));
+        assert_eq!(
+            compile_with(diagnostics),
+            (
+                false,
+                vec![
+                    "error (line 1:3): comparing a value with itself using \"==\" is always true; this may be a typo.".to_string(),
+                    "note (line 1:3): the warning \"self-comparison\" (W0003) is denied.".to_string(),
+                ]
+            )
+        );
+        assert!(diagnostics.set("W0003", WarningLevel::Warn));
+        assert!(compile_with(diagnostics).0);
+        assert!(!diagnostics.set("W0000", WarningLevel::Warn));
+        assert!(!diagnostics.set("unknown", WarningLevel::Warn));
+    }
+
+    #[test]
+    fn unused_values() {
+        let unused = |line_and_col: &str, op: &str| {
+            format!("warning (line {line_and_col}): the value computed by \"{op}\" is never used; this may be a typo.")
+        };
+        test_code(
+            "int x = 1;\nx == 2;\n-x;\nx = 3;\n(x + 1);\nfn f(y: int) -> int { y * 2; y }\nf(x)",
+            &["3".to_string()],
+            &[unused("2:3", "=="), unused("3:1", "-"), unused("5:4", "+"), unused("6:25", "*")],
+        );
+
+        // The values of the statements at the top level of a script are printed, so they are used.
+        let output: CompilerOutput = compile_with_passes(parse(lex("1 + 2;")), CompilerOptions::default(), true, &PassManager::default());
+        assert!(output.logs.is_empty());
+
+        let mut diagnostics: DiagnosticConfig = DiagnosticConfig::default();
+        assert!(diagnostics.set("unused-value", WarningLevel::Allow));
+        let options: CompilerOptions = CompilerOptions { diagnostics, ..CompilerOptions::default() };
+        assert!(compile(parse(lex("1 + 2; 3")), options).logs.is_empty());
+    }
+
+    #[test]
+    fn constant_overflows() {
+        test_code(
+            "2147483647 + 1",
+            &["-2147483648".to_string()],
+            &["warning (line 1:12): the result 2147483648 doesn't fit in an int, so this constant expression wraps around to -2147483648.".to_string()],
+        );
+        test_code(
+            "65536 * (65536 - 1)",
+            &["-65536".to_string()],
+            &["warning (line 1:7): the result 4294901760 doesn't fit in an int, so this constant expression wraps around to -65536.".to_string()],
+        );
+        // The smallest int can be written, and shifts and modulos never overflow.
+        test_code(
+            "-2147483648 % -1 + (1 << 31) + -2147483648",
+            &["0".to_string()],
+            &["warning (line 1:30): the result -4294967296 doesn't fit in an int, so this constant expression wraps around to 0.".to_string()],
+        );
+        test_code("(-2147483647 - 1) / 1", &["-2147483648".to_string()], &Vec::new());
+
+        // The interpreter folds constants too.
+        let out_err = run(
+            &FileInput::FileText("-2147483648 / -1".to_string()),
+            CompilerOptions::default(),
+            Severity::Info,
+            &RunOptions { interpret: true, ..RunOptions::default() },
+        );
+        assert_eq!(out_err.0, ["-2147483648".to_string()]);
+        assert_eq!(
+            all_to_string(&out_err.1),
+            ["warning (line 1:13): the result 2147483648 doesn't fit in an int, so this constant expression wraps around to -2147483648."]
+        );
+
+        let mut diagnostics: DiagnosticConfig = DiagnosticConfig::default();
+        assert!(diagnostics.set("constant-overflow", WarningLevel::Deny));
+        let options: CompilerOptions = CompilerOptions { diagnostics, ..CompilerOptions::default() };
+        let output: CompilerOutput = compile(parse(lex("2147483647 + 1")), options);
+        assert!(output.bytecode.is_none());
+        assert_eq!(
+            all_to_string(&output.logs)[1],
+            "note (line 1:12): the warning \"constant-overflow\" (W0005) is denied."
+        );
+    }
+
+    #[test]
+    fn linting() {
+        let code: &str = "int x = (1 + 2); int x = x * 2; fn f(a: int) -> bool { int a = 1; (a > 0) | !(1 < 2) } f((x))";
+        let lint = |diagnostics: DiagnosticConfig| -> Vec<String> {
+            let options: CompilerOptions = CompilerOptions { diagnostics, ..CompilerOptions::default() };
+            all_to_string(&compile(parse(lex(code)), options).logs)
+        };
+        // Lints are off until they are turned on, even when every warning is denied.
+        let mut diagnostics: DiagnosticConfig = DiagnosticConfig::default();
+        assert!(lint(diagnostics).is_empty());
+        assert!(diagnostics.set("warnings", WarningLevel::Deny));
+        assert!(lint(diagnostics).is_empty());
+
+        let mut diagnostics: DiagnosticConfig = DiagnosticConfig::default();
+        assert!(diagnostics.set("lints", WarningLevel::Warn));
+        assert_eq!(
+            lint(diagnostics),
+            [
+                "warning (line 1:9): these parentheses are redundant.",
+                "warning (line 1:22): the variable \"x\" shadows the variable of the same name declared on line 1.",
+                "warning (line 1:60): the variable \"a\" shadows the variable of the same name declared on line 1.",
+                "warning (line 1:77): this condition is always false, as it only uses constants.",
+                "warning (line 1:90): these parentheses are redundant.",
+            ]
+        );
+
+        // Lints that are on take the level of every warning, and each can be turned off by its name or code.
+        assert!(diagnostics.set("warnings", WarningLevel::Deny));
+        assert!(diagnostics.set("W0006", WarningLevel::Allow));
+        assert!(diagnostics.set("constant-condition", WarningLevel::Allow));
+        assert_eq!(
+            lint(diagnostics),
+            [
+                "error (line 1:22): the variable \"x\" shadows the variable of the same name declared on line 1.",
+                "note (line 1:22): the warning \"shadowed-variable\" (W0008) is denied.",
+                "error (line 1:60): the variable \"a\" shadows the variable of the same name declared on line 1.",
+                "note (line 1:60): the warning \"shadowed-variable\" (W0008) is denied.",
+            ]
+        );
+    }
+
+    #[test]
+    fn project_config() {
+        let text: &str = "# A project.\nentry = \"src/main.txt\"\npointer_size = 32\nopt_level = 0 # no folding\n\n[warnings]\nlints = 'warn'\nW0008 = \"deny\"\n";
+        let config: ProjectConfig = ProjectConfig::parse(text, "krust.toml").expect("the config is valid");
+        assert_eq!(config.entry.as_deref(), Some("src/main.txt"));
+        assert_eq!(config.pointer_size, Some(32));
+        assert_eq!(config.opt_level, Some(OptLevel::O0));
+        assert_eq!(config.flags(), ["-pointer_size=32", "-O0", "-W=lints", "--deny=W0008"]);
+
+        // The flags give the config's settings to the command line, as when krust finds the config.
+        let (cli_options, logs) = read_args(config.flags().into_iter().chain(["snapshots/arithmetic.txt".to_string()]).collect());
+        assert!(logs.is_empty());
+        let cli_options: CliOptions = cli_options.expect("the flags are valid");
+        assert_eq!(cli_options.options.target.ptr_size, 4);
+        assert!(!cli_options.passes.is_enabled(CompilerPass::ConstantFolding));
+        let output: CompilerOutput = compile(parse(lex("int x = (1); int x = 2; x")), cli_options.options);
+        assert_eq!(
+            all_to_string(&output.logs),
+            [
+                "warning (line 1:9): these parentheses are redundant.",
+                "error (line 1:18): the variable \"x\" shadows the variable of the same name declared on line 1.",
+                "note (line 1:18): the warning \"shadowed-variable\" (W0008) is denied.",
+            ]
+        );
+
+        // Every invalid line is reported.
+        let text: &str = "entry = main.txt\npointer_size = 12\nopt_level = 2\ncolor = \"always\"\n[warnings]\nself-comparison = \"ignore\"\nquiet\n[build]\nanything = 1";
+        let logs: Vec<Log> = ProjectConfig::parse(text, "krust.toml").expect_err("the config is invalid");
+        assert_eq!(
+            all_to_string(&logs),
+            [
+                "error: line 1 of \"krust.toml\" gives \"entry\" a value that isn't a string.",
+                "error: line 2 of \"krust.toml\" gives \"pointer_size\" a value that isn't a multiple of 8 from 8 to 2040.",
+                "error: line 4 of \"krust.toml\" has \"color\", which isn't a setting of a krust project.",
+                "error: line 6 of \"krust.toml\" gives \"self-comparison\" a value that isn't one of \"allow\", \"warn\", or \"deny\".",
+                "error: line 7 of \"krust.toml\" should be a section, such as \"[warnings]\", or a setting, such as \"opt_level = 2\".",
+                "error: line 8 of \"krust.toml\" has \"[build]\", which isn't a setting of a krust project.",
+            ]
+        );
+    }
+
+    #[test]
+    fn error_limits() {
+        let undeclared = |index: usize| format!("error (line 1:{}): expected a variable declaration for v{index:02}", 5 * index + 1);
+        let code: String = (0..23).map(|index| format!("v{index:02};")).collect::<Vec<String>>().join(" ");
+        let mut expected: Vec<String> = (0..DEFAULT_ERROR_LIMIT).map(undeclared).collect();
+        expected.push("error: aborting due to 20 previous errors; 3 not shown.".to_string());
+        expected.push("error: could not compile due to errors.".to_string());
+        test_code(&code, &Vec::new(), &expected);
+        // The limit is an option of each run, so other runs keep the default.
+        let (_, logs) = run(&FileInput::FileText(code), CompilerOptions::default(), Severity::Info, &RunOptions { error_limit: 2, ..RunOptions::default() });
+        assert_eq!(all_to_string(&logs[2..]), ["error: aborting due to 2 previous errors; 21 not shown.", "error: could not compile due to errors."]);
+
+        // A limit of 0, or of at least the number of errors, keeps every log.
+        let logs: Vec<Log> = parse(lex("v0; v1")).logs;
+        assert_eq!(all_to_string(&limit_errors(&logs, 0)), all_to_string(&logs));
+        assert_eq!(all_to_string(&limit_errors(&logs, 2)), all_to_string(&logs));
+        assert_eq!(
+            all_to_string(&limit_errors(&logs, 1)),
+            [logs[0].to_string(), "error: aborting due to 1 previous error; 1 not shown.".to_string()]
+        );
+    }
+
+    #[test]
+    fn localized_messages() {
+        let catalog: MessageCatalog = MessageCatalog::from_text(
+            "# A message pack with a translated error.\nE0004 = aucun argument.\n\nE0105 : not a template\nW0003 = {1} {9} {x} {0}",
+        );
+        assert_eq!(catalog.template("E0004"), Some("aucun argument.".to_string()));
+        assert_eq!(catalog.template("E0105"), None);
+        // Placeholders without an argument are left as they are.
+        assert_eq!(
+            localize("E0001", &catalog.template("W0003").unwrap_or_default(), &["==".to_string(), "true".to_string()]),
+            "true {9} {x} =="
+        );
+
+        // Only the codes in the catalog are translated, and the others stay in English.
+        let log = |error_type: ErrorType| Log { log_type: LogType::Error(error_type), line_and_col: None, span: None, notes: Vec::new() };
+        assert_eq!(log(ErrorType::CLINoArgs).message_in(&catalog), "aucun argument.");
+        assert_eq!(log(ErrorType::CLINoArgs).message(), "no command line arguments.");
+        assert_eq!(log(ErrorType::InvalidOpCode(1200, 10)).message_in(&catalog), "invalid opcode 0x0a at offset 1200.");
+        assert_eq!(localize_with(&catalog, "E0004", "no command line arguments.", &[]), "aucun argument.");
+    }
+
+    #[test]
+    fn color_choice() {
+        assert_eq!(ColorChoice::from_name("always"), Some(ColorChoice::Always));
+        assert_eq!(ColorChoice::from_name("never"), Some(ColorChoice::Never));
+        assert_eq!(ColorChoice::from_name("auto"), Some(ColorChoice::default()));
+        assert_eq!(ColorChoice::from_name("sometimes"), None);
+        assert!(ColorChoice::Always.enabled());
+        assert!(!ColorChoice::Never.enabled());
+
+        // Logs are only colored when they are rendered in color, and are otherwise plain text.
+        let log: Log = Log { log_type: LogType::Error(ErrorType::CantCompile), line_and_col: None, span: None, notes: Vec::new() };
+        assert_eq!(format!("{log:#}"), "error[E0089]: could not compile due to errors.");
+        assert_eq!(log.render(None, RenderOptions::default()), "error[E0089]: could not compile due to errors.");
+        let colored: RenderOptions = RenderOptions { colored: true, ..RenderOptions::default() };
+        assert_eq!(log.render(None, colored), "\x1b[1;31merror[E0089]\x1b[0m: could not compile due to errors.");
+        let sink: Capture = Capture::default();
+        TerminalSink::new(sink.clone(), None).with_colors(true).report(&log);
+        assert_eq!(String::from_utf8_lossy(&sink.0.borrow()), log.render(None, colored) + "\n");
+    }
+
+    #[test]
+    fn spans() {
+        let code: &str = "int x = 3;\nx + 40";
+        let output: LexerOutput = lex(code);
+        assert_eq!(output.tokens[5].span(), Span { start: (2, 1), end: (2, 2), byte_range: 11..12 });
+        assert_eq!(output.tokens[7].span(), Span { start: (2, 5), end: (2, 7), byte_range: 15..17 });
+        let Expression::ExpressionList { list } = parse(lex(code)).expr else {
+            panic!("the parser should output an expression list")
+        };
+        assert_eq!(list[1].span(), Some(Span { start: (2, 1), end: (2, 7), byte_range: 11..17 }));
+
+        // Lexer errors count characters until the positions are converted to bytes.
+        let logs: Vec<Log> = lex("\"é\\q\"").logs;
+        assert_eq!(logs[0].span, Some(Span { start: (1, 3), end: (1, 5), byte_range: 3..5 }));
+
+        let logs: Vec<Log> = parse(lex("1 + (true & false)")).logs;
+        assert_eq!(logs[0].span, Some(Span { start: (1, 1), end: (1, 18), byte_range: 0..17 }));
+    }
+
+    #[test]
+    fn snippets() {
+        let code: &str = "int x = 3;\n\tint y = x + (true &\nfalse);";
+        let logs: Vec<Log> = parse(lex(code)).logs;
+        assert_eq!(
+            logs[0].render(Some(code), RenderOptions::default()),
+            "error[E0041] (line 2:12): the operator \"+\" has no definition over the types \"int\" and \"bool\".\n  |\n2 | \tint y = x + (true &\n  | \t        ^^^^^^^^^^^\n  note (line 2:10): the left operand has the type \"int\".\n    |\n  2 | \tint y = x + (true &\n    | \t        ^\n  note (line 2:15): the right operand has the type \"bool\".\n    |\n  2 | \tint y = x + (true &\n    | \t             ^^^^^^\n  help (line 2:15): the right operand should have the type \"int\".\n    |\n  2 | \tint y = x + (true &\n    | \t             ^^^^^^"
+        );
+        let log: Log = Log { log_type: LogType::Error(ErrorType::CantCompile), line_and_col: None, span: None, notes: Vec::new() };
+        assert_eq!(log.render(Some(code), RenderOptions::default()), "error[E0089]: could not compile due to errors.");
+
+        let source: LinkedSource = LinkedSource::link(vec![
+            (SourcePath::from("lib.txt"), "1;\n2;".to_string()),
+            (SourcePath::from("main.txt"), "\"é\" + true".to_string()),
+        ]);
+        let logs: Vec<Log> = parse(lex(&source.file_text)).logs;
+        assert_eq!(
+            source.render(&logs[0], RenderOptions::default()),
+            "main.txt: error[E0041] (line 1:5): the operator \"+\" has no definition over the types \"string\" and \"bool\".\n  |\n1 | \"é\" + true\n  | ^^^^^^^^^^\n  note (line 1:1): the left operand has the type \"string\".\n    |\n  1 | \"é\" + true\n    | ^^^\n  note (line 1:7): the right operand has the type \"bool\".\n    |\n  1 | \"é\" + true\n    |       ^^^^"
+        );
+    }
+
+    #[test]
+    fn codes() {
+        let log = |log_type: LogType| Log { log_type, line_and_col: None, span: None, notes: Vec::new() };
+        assert_eq!(log(LogType::Error(ErrorType::FatalError)).code(), Some("E0001".to_string()));
+        assert_eq!(
+            format!("{:?}", log(LogType::Error(ErrorType::FatalError))),
+            "Log { log_type: Error(FatalError), line_and_col: None, span: None, notes: [] }"
+        );
+        // Runtime errors can be debug printed, so the result of a run can be unwrapped.
+        let bytecode: Vec<u8> = compile(parse(lex("6 * 7")), CompilerOptions::default()).bytecode.expect("code should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.set_output(std::io::sink());
+        assert_eq!(vm.run().unwrap(), Value::Int(42));
+        assert_eq!(log(LogType::Error(ErrorType::CantCompile)).code(), Some("E0089".to_string()));
+        assert_eq!(log(LogType::Warning(WarningType::SelfComparison("x".to_string(), true))).code(), Some("W0003".to_string()));
+        assert_eq!(log(LogType::Info(InfoType::NewVarNotSet("x".to_string()))).code(), None);
+
+        let failed: Log = log(LogType::Error(ErrorType::CantCompile));
+        assert_eq!(format!("{failed}"), "error: could not compile due to errors.");
+        assert_eq!(format!("{failed:#}"), "error[E0089]: could not compile due to errors.");
+    }
+
+    #[test]
+    fn json_logs() {
+        let logs: Vec<Log> = parse(lex("int a = 1;\n\"a\\\\\" + true")).logs;
+        assert_eq!(
+            logs[0].to_json(Some("main.txt"), NumberFormat::default()),
+            "{\"code\":\"E0041\",\"severity\":\"error\",\"message\":\"the operator \\\"+\\\" has no definition over the types \\\"string\\\" and \\\"bool\\\".\",\"file\":\"main.txt\",\"line\":2,\"col\":7,\"span\":{\"start\":{\"line\":2,\"col\":1},\"end\":{\"line\":2,\"col\":13},\"byte_start\":11,\"byte_end\":23},\"notes\":[{\"code\":null,\"severity\":\"note\",\"message\":\"the left operand has the type \\\"string\\\".\",\"file\":\"main.txt\",\"line\":2,\"col\":1,\"span\":{\"start\":{\"line\":2,\"col\":1},\"end\":{\"line\":2,\"col\":6},\"byte_start\":11,\"byte_end\":16},\"notes\":[]},{\"code\":null,\"severity\":\"note\",\"message\":\"the right operand has the type \\\"bool\\\".\",\"file\":\"main.txt\",\"line\":2,\"col\":9,\"span\":{\"start\":{\"line\":2,\"col\":9},\"end\":{\"line\":2,\"col\":13},\"byte_start\":19,\"byte_end\":23},\"notes\":[]}]}"
+        );
+        let log: Log = Log { log_type: LogType::Info(InfoType::NewVarNotSet("x".to_string())), line_and_col: None, span: None, notes: Vec::new() };
+        assert_eq!(
+            log.to_json(None, NumberFormat::default()),
+            "{\"code\":null,\"severity\":\"info\",\"message\":\"the variable \\\"x\\\" has been initialized but hasn't been set to a value. It will instead take the default value of the type.\",\"file\":null,\"line\":null,\"col\":null,\"span\":null,\"notes\":[]}"
+        );
+        // Only the logs rendered as JSON are JSON, whatever other logs are rendered as.
+        assert_eq!(log.render(None, RenderOptions { format: ErrorFormat::Json, ..RenderOptions::default() }), log.to_json(None, NumberFormat::default()));
+        assert!(format!("{log:#}").starts_with("info: the variable"));
+    }
+
+    #[test]
+    fn log_notes() {
+        let logs: Vec<Log> = parse(lex("1 + true")).logs;
+        let notes: Vec<Log> = logs[0].notes.iter().map(Note::to_log).collect();
+        assert_eq!(
+            all_to_string(&notes),
+            [
+                "note (line 1:1): the left operand has the type \"int\".",
+                "note (line 1:5): the right operand has the type \"bool\".",
+                "help (line 1:5): the right operand should have the type \"int\".",
+            ]
+        );
+        assert_eq!(notes[1].span, Some(Span { start: (1, 5), end: (1, 9), byte_range: 4..8 }));
+        assert_eq!(
+            format!("{:#}", logs[0]),
+            "error[E0041] (line 1:3): the operator \"+\" has no definition over the types \"int\" and \"bool\".\n  note (line 1:1): the left operand has the type \"int\".\n  note (line 1:5): the right operand has the type \"bool\".\n  help (line 1:5): the right operand should have the type \"int\"."
+        );
+
+        // Only the types of the operands are known for a value that can't be indexed.
+        let logs: Vec<Log> = parse(lex("true[0]")).logs;
+        assert_eq!(logs[0].notes.len(), 2);
+        let logs: Vec<Log> = parse(lex("list<int> a = list<int> {1};\na[true]")).logs;
+        assert_eq!(
+            all_to_string(&[logs[0].notes[2].to_log()]),
+            ["help (line 2:3): the index should have the type \"int\"."]
+        );
+    }
+
+    #[test]
+    fn internal_checks() {
+        let expr: Expression = parse(lex("1; 2")).expr;
+        assert!(check_internals(&expr, "1; 2", &compile(parse(lex("1; 2")), CompilerOptions::new(8, true, IsaLevel::V2)).bytecode.expect("code should compile"), CompilerOptions::new(8, true, IsaLevel::V2)).is_empty());
+        assert_eq!(
+            all_to_string(&check_internals(&expr, "1; 2", &[8, 1, 1, 0, 0, OpCode::PushInt as u8, 1, 2], CompilerOptions::new(8, true, IsaLevel::V2))),
+            [
+                "error: internal compiler error: the generated bytecode failed verification.".to_string(),
+                "error: the instruction at offset 5 is missing operand bytes.".to_string(),
+            ]
+        );
+
+        // Removing the statement around the first literal leaves its value on the stack.
+        let Expression::ExpressionList { mut list } = expr else {
+            panic!("the parser should output an expression list")
+        };
+        if let Expression::Statement { expr } = *list[0].clone() {
+            list[0] = expr;
+        }
+        let expr: Expression = Expression::ExpressionList { list };
+        let bytecode: Vec<u8> = compile(ParserOutput { file_text: "1; 2".to_string(), expr: expr.clone(), logs: Vec::new() }, CompilerOptions::new(8, true, IsaLevel::V2))
+            .bytecode
+            .expect("code should compile");
+        assert_eq!(
+            all_to_string(&check_internals(&expr, "1; 2", &bytecode, CompilerOptions::new(8, true, IsaLevel::V2))),
+            ["error (line 1:4): internal compiler error: the statement leaves 4 unused bytes on the stack.".to_string()]
+        );
+
+        let expr: Expression = Expression::ExpressionList { list: vec![Box::new(Expression::Null)] };
+        assert_eq!(
+            all_to_string(&check_internals(&expr, "", &bytecode, CompilerOptions::new(8, true, IsaLevel::V2))),
+            ["error: internal compiler error: an expression has no type after parsing without errors.".to_string()]
+        );
+    }
+
+    #[test]
+    fn verify_compiled_code() {
+        test_verify_code("int a = 3; bool? b = true; int? c; c = a * 2; ((a / c!) == 0) & b!");
+        test_verify_code("clamp(sqrt(9), min(1, abs(-2)), max(3, pow(2, 2)))");
+        test_verify_code("string s = \"abc\"; contains(substring(s, 0, len(s)), \"b\")");
+        test_verify_code("map[int]bool m; insert(m, 1, true); get(m, 1)! & (len(m) == 1); m");
+        test_verify_code("list<int> l; push(l, 1); (pop(l)! + l[0]) == len(l); l");
+        test_verify_code("fn id<T>(x: T) -> T { x } fn f(a: int?) -> bool { id(a)! == 1 } f(id(1)) & id(true)");
+        test_verify_code("fn f(r: result<bool>) -> result<int> { int a = 1; ok(a + (int) r?) } is_ok(f(err(\"e\"))) & (f(ok(true))! == 2)");
+    }
+
+    #[test]
+    fn verify_bad_header() {
+        test_verify(&[8], &["error: the bytecode header is invalid.".to_string()]);
+    }
+
+    #[test]
+    fn verify_invalid_opcode() {
+        test_verify(&[8, 1, 1, 0, 0, 0xff], &["error: invalid opcode 0xff at offset 5.".to_string()]);
+    }
+
+    #[test]
+    fn verify_incomplete_operand() {
+        test_verify(
+            &[8, 1, 1, 0, 0, OpCode::PushInt as u8, 1, 2],
+            &["error: the instruction at offset 5 is missing operand bytes.".to_string()],
+        );
+    }
+
+    #[test]
+    fn verify_stack_underflow() {
+        test_verify(
+            &[8, 1, 1, 0, 0, OpCode::PushByte as u8, 1, OpCode::PopInt as u8],
+            &["error: the instruction at offset 7 pops more values than are on the stack.".to_string()],
+        );
+    }
+
+    #[test]
+    fn verify_jumps() {
+        let jump = |distance: u8| [OpCode::JumpIfOk as u8, distance, 0, 0, 0, 0, 0, 0, 0];
+        let code: Vec<u8> = [&[8, 1, 3, 0, 0, OpCode::PushInt as u8, 0, 0, 0, 0][..], &jump(2), &[OpCode::PopInt as u8]].concat();
+        test_verify(&code, &["error: the jump at offset 10 does not go to the start of an instruction.".to_string()]);
+        let code: Vec<u8> =
+            [&[8, 1, 3, 0, 0, OpCode::PushInt as u8, 0, 0, 0, 0][..], &jump(1), &[OpCode::PopInt as u8, OpCode::PopInt as u8]].concat();
+        test_verify(
+            &code,
+            &["error: the stack at offset 20 differs depending on whether it is reached by a jump.".to_string()],
+        );
+    }
+
+    #[test]
+    fn verify_isa_level() {
+        test_verify(
+            &[8, 1, 0, 0, 0],
+            &["error: this program requires instruction set level 0, but only levels 1 to 13 are supported.".to_string()],
+        );
+        test_verify(
+            &[8, 1, 1, 0, 0, OpCode::Halt as u8],
+            &["error: the instruction at offset 5 needs instruction set level 2, but the header only allows level 1.".to_string()],
+        );
+    }
+
+    #[test]
+    fn disassembly() {
+        let code: &str = "fn f(r: result<int>) -> result<int> { ok(r? + 1) } is_ok(f(ok(1)))";
+        let bytecode: Vec<u8> = compile(parse(lex(code)), CompilerOptions::new(8, true, IsaLevel::LATEST)).bytecode.expect("code should compile");
+        let output: DisassemblerOutput = disassemble(&bytecode, AddressMode::Offsets);
+        assert!(output.logs.is_empty());
+        assert_eq!(output.lines[0], "; pointer size: 64-bit, detailed errors: true, instruction set level: 10");
+        // Offsets follow the metadata, whose length depends on the version of the compiler.
+        let start: usize = code_start(&bytecode);
+        assert_eq!(output.lines[3], format!("{:>6}  Call @{} 4 4 (line 1:58)", start + 7, start + 28));
+        assert_eq!(output.lines[9], format!("{:>6}  JumpIfOk @{}", start + 41, start + 52));
+
+        let labels: Vec<String> = disassemble(&bytecode, AddressMode::Labels).lines;
+        assert_eq!(
+            labels[1..],
+            [
+                "    PushInt 1 (line 1:52)",
+                "    OkResult 4",
+                "    Call fn0 4 4 (line 1:58)",
+                "    IsOkResult",
+                "    PrintBool",
+                "    Halt",
+                "fn0:",
+                "    BindParam 0 4",
+                "    GetInt var0 (line 1:39)",
+                "    JumpIfOk L0",
+                "    Return 4",
+                "L0:",
+                "    UnwrapResult 4 (line 1:43)",
+                "    PushInt 1",
+                "    AddInt",
+                "    OkResult 4",
+                "    Return 4",
+                "    FunctionName fn0 \"f\"",
+                "    LineTable 4",
+            ]
+        );
+        // Only the header changes when the offsets do.
+        let bytecode: Vec<u8> = compile(parse(lex(code)), CompilerOptions::new(4, true, IsaLevel::LATEST)).bytecode.expect("code should compile");
+        assert_eq!(disassemble(&bytecode, AddressMode::Labels).lines[1..], labels[1..]);
+
+        let output: DisassemblerOutput = disassemble(&[8, 1, 1, 0, 0, OpCode::PushInt as u8, 1, 0, 0, 0, 255], AddressMode::Labels);
+        assert_eq!(output.lines, ["; pointer size: 64-bit, detailed errors: true, instruction set level: 1", "    PushInt 1"]);
+        assert_eq!(all_to_string(&output.logs), ["error: invalid opcode 0xff at offset 10.".to_string()]);
+    }
+
+    #[test]
+    fn artifact_metadata() {
+        let code: &str = "fn f(n: int) -> int { f(n - 1) } 1";
+        let options: CompilerOptions = CompilerOptions::new(8, true, IsaLevel::LATEST);
+        let bytecode: Vec<u8> = compile(parse(lex(code)), options).bytecode.expect("code should compile");
+        let metadata: Metadata = Metadata::read(&bytecode).expect("the compiler always writes metadata");
+        assert_eq!(metadata.compiler_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(metadata.options, options);
+        assert_eq!(
+            metadata.optimizations,
+            ["constant-folding", "tail-calls", "function-names", "common-subexpressions", "constant-pool"].map(ToString::to_string)
+        );
+        assert_eq!(metadata.source_hash, hash_source(code));
+        assert_eq!(Metadata::decode(&metadata.encode()), Some(metadata));
+
+        let mut passes: PassManager = PassManager::default();
+        passes.disable(CompilerPass::TailCalls);
+        let bytecode: Vec<u8> =
+            compile_with_passes(parse(lex(code)), options, false, &passes).bytecode.expect("code should compile");
+        let metadata: Metadata = Metadata::read(&bytecode).expect("the compiler always writes metadata");
+        assert_eq!(
+            metadata.optimizations,
+            ["constant-folding", "function-names", "common-subexpressions", "constant-pool"].map(ToString::to_string)
+        );
+
+        assert_eq!(hash_source(""), 0xcbf2_9ce4_8422_2325);
+        assert_ne!(hash_source("1 + 2"), hash_source("1 + 3"));
+        assert_eq!(Metadata::read(&[8, 1, 1, 0, 0]), None);
+        assert_eq!(Metadata::read(&[8, 1, 1, 4, 0, 0]), None);
+    }
+
+    #[test]
+    fn bytecode_file_format() {
+        let code: &str = "fn f() -> int { panic(\"no\"); 1 } f()";
+        let bytecode: Vec<u8> = compile(parse(lex(code)), CompilerOptions::default()).bytecode.expect("code should compile");
+        let file: Vec<u8> = serialize_bytecode(&bytecode);
+        assert_eq!(file[..FILE_HEADER_SIZE], [b'K', b'R', b'B', b'C', 2, 0, 8, 3]);
+        assert_eq!(file[FILE_HEADER_SIZE..FILE_HEADER_SIZE + 5], [Section::ConstantPool as u8, 0, 0, 0, 0]);
+        assert!(load_bytecode(&file) == Ok(bytecode.clone()));
+        // The function names and the line table are moved to the debug info section.
+        let code_length: usize = u32::from_le_bytes(file[14..18].try_into().expect("4 bytes")) as usize;
+        assert_eq!(file[18 + code_length], Section::DebugInfo as u8);
+        assert_eq!(file[18 + code_length + 5], OpCode::FunctionName as u8);
+
+        let corrupt = |file: &[u8]| -> Vec<String> {
+            all_to_string(&[load_bytecode(file).expect_err("the file is corrupt")])
+        };
+        assert_eq!(
+            corrupt(&bytecode),
+            ["error: the compiled program is corrupt: it doesn't start with the magic bytes of a compiled program.".to_string()]
+        );
+        let mut newer: Vec<u8> = file.clone();
+        newer[4] = 3;
+        assert_eq!(
+            corrupt(&newer),
+            ["error: the compiled program is corrupt: it uses version 3 of the format, but only version 2 is supported.".to_string()]
+        );
+        assert_eq!(
+            corrupt(&file[..file.len() - 1]),
+            ["error: the compiled program is corrupt: the debug info section is cut off.".to_string()]
+        );
+        let mut other_ptr_size: Vec<u8> = file.clone();
+        other_ptr_size[6] = 4;
+        assert_eq!(
+            corrupt(&other_ptr_size),
+            ["error: the compiled program is corrupt: the pointer size of the file doesn't match the code section.".to_string()]
+        );
+        let mut repeated: Vec<u8> = file.clone();
+        repeated[7] = 4;
+        repeated.extend_from_slice(&[Section::ConstantPool as u8, 0, 0, 0, 0]);
+        assert_eq!(
+            corrupt(&repeated),
+            ["error: the compiled program is corrupt: it has more than one constant pool section.".to_string()]
+        );
+    }
+
+    #[test]
+    fn build_and_run_bytecode() {
+        let dir: PathBuf = std::env::temp_dir().join("krust_build");
+        std::fs::create_dir_all(&dir).expect("temp dir should be writable");
+        let source: String = dir.join("program.txt").display().to_string();
+        let compiled: String = dir.join("program.krustc").display().to_string();
+        std::fs::write(&source, "len(args()) * 21").expect("temp dir should be writable");
+        let _ = std::fs::remove_file(&compiled);
+
+        let options: RunOptions = RunOptions { program_args: vec!["a".to_string(), "b".to_string()], ..RunOptions::default() };
+        assert!(build_file(&source, &[], CompilerOptions::default(), Severity::Info, &options));
+        let (output, logs): (Vec<String>, Vec<Log>) = run_bytecode_file(&compiled, Severity::Info, &options);
+        assert_eq!((output, logs.is_empty()), (vec!["42".to_string()], true));
+
+        std::fs::write(&source, "1 + true").expect("temp dir should be writable");
+        assert!(!build_file(&source, &[], CompilerOptions::default(), Severity::Info, &options));
+        std::fs::write(&compiled, "1 + 2").expect("temp dir should be writable");
+        let (output, logs): (Vec<String>, Vec<Log>) = run_bytecode_file(&compiled, Severity::Info, &options);
+        assert!(output.is_empty());
+        assert_eq!(
+            all_to_string(&logs),
+            ["error: the compiled program is corrupt: it doesn't start with the magic bytes of a compiled program.".to_string()]
+        );
+    }
+
+    #[test]
+    fn build_to_output_path() {
+        let dir: PathBuf = std::env::temp_dir().join("krust_output");
+        std::fs::create_dir_all(&dir).expect("temp dir should be writable");
+        let source: String = dir.join("program.txt").display().to_string();
+        let chosen: String = dir.join("chosen.bin").display().to_string();
+        std::fs::write(&source, "6 * 7").expect("temp dir should be writable");
+        let _ = std::fs::remove_file(dir.join("program.krustc"));
+        let _ = std::fs::remove_file(&chosen);
+
+        let options: RunOptions = RunOptions { output: Some(chosen.clone()), ..RunOptions::default() };
+        assert!(build_file(&source, &[], CompilerOptions::default(), Severity::Info, &options));
+        assert!(!dir.join("program.krustc").exists());
+        let (output, logs): (Vec<String>, Vec<Log>) = run_bytecode_file(&chosen, Severity::Info, &options);
+        assert_eq!((output, logs.is_empty()), (vec!["42".to_string()], true));
+
+        // A path in a directory that doesn't exist can't be written.
+        let missing: String = dir.join("missing").join("chosen.bin").display().to_string();
+        let options: RunOptions = RunOptions { output: Some(missing), ..RunOptions::default() };
+        assert!(!build_file(&source, &[], CompilerOptions::default(), Severity::Info, &options));
+    }
+
+    #[test]
+    fn check_without_running() {
+        let check = |code: &str| -> Vec<String> {
+            let logs: Vec<Log> =
+                check_input(&FileInput::FileText(code.to_string()), CompilerOptions::default(), Severity::Info, &RunOptions::default());
+            all_to_string(&logs)
+        };
+        // Errors that only happen when the program runs aren't found, as it isn't run.
+        assert!(check("int x = 0;\n1 / x").is_empty());
+        assert!(check("panic(\"stop\")").is_empty());
+        assert_eq!(
+            check("int x = true;\n1 / 0"),
+            [
+                "error (line 1:7): The variable \"x\" has type \"int\", so it can not be assigned a value of type \"bool\"".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ]
+        );
+        assert_eq!(
+            check("int x = 3;\nx % 0"),
+            [
+                "error (line 2:3): the divisor is always zero, so this would always fail.".to_string(),
+                "error: could not compile due to errors.".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn big_endian_target() {
+        let code: &str = "fn f(x: int) -> int { x * 300 }\nstring s = \"abc\";\nf(2) / (len(s) - 3)";
+        let compile_for = |endianness: Endianness| -> Vec<u8> {
+            let options: CompilerOptions =
+                CompilerOptions { target: Target { endianness, ..Target::default() }, ..CompilerOptions::default() };
+            compile(parse(lex(code)), options).bytecode.expect("code should compile")
+        };
+        let little: Vec<u8> = compile_for(Endianness::Little);
+        let big: Vec<u8> = compile_for(Endianness::Big);
+        assert_eq!(Metadata::read(&big).map(|metadata| metadata.options.target.endianness), Some(Endianness::Big));
+        // Only the flags in the header and the metadata differ in memory.
+        assert_eq!(big[code_start(&big)..], little[code_start(&little)..]);
+
+        // The numbers in the file are written with their most significant byte first.
+        let file: Vec<u8> = serialize_bytecode(&big);
+        assert!(file.windows(5).any(|window| window == [OpCode::PushInt as u8, 0, 0, 1, 44]));
+        assert!(!serialize_bytecode(&little).windows(5).any(|window| window == [OpCode::PushInt as u8, 0, 0, 1, 44]));
+        let loaded: Vec<u8> = load_bytecode(&file).unwrap_or_default();
+        assert_eq!(loaded, big);
+        assert_eq!(all_to_string(&Vm::new(loaded).run().err().unwrap_or_default()), ["error (line 3:6): division by zero.".to_string()]);
+
+        let ptr_size: usize = usize::from(big[0]);
+        let swapped: Vec<u8> = swap_byte_order(&big[code_start(&big)..], ptr_size, false);
+        assert_eq!(swap_byte_order(&swapped, ptr_size, true), big[code_start(&big)..]);
+    }
+
+    #[test]
+    fn size_report() {
+        let code: &str = "fn square(x: int) -> int { x * x }\nint a = square(5);\nstring s = \"some text\";\n\
+            square(a) + a * a / 2";
+        let mut registers: PassManager = PassManager::default();
+        registers.enable(CompilerPass::Registers);
+        // The parts are moved along with the code by every pass, so they always add up to the whole bytecode.
+        for passes in [PassManager::with_opt_level(OptLevel::O0), PassManager::default(), registers] {
+            let output: CompilerOutput = compile_with_passes(parse(lex(code)), CompilerOptions::default(), false, &passes);
+            let bytecode: Vec<u8> = output.bytecode.expect("code should compile");
+            assert_eq!(output.sizes.iter().map(|entry| entry.size).sum::<usize>(), bytecode.len());
+            let parts: Vec<(&str, Option<(usize, usize)>)> =
+                output.sizes.iter().map(|entry| (entry.name.as_str(), entry.line_and_col)).collect();
+            assert_eq!(
+                parts,
+                [
+                    ("header", None),
+                    ("int a = square(5);", Some((2, 5))),
+                    ("string s = \"some text\";", Some((3, 8))),
+                    ("square(a) + a * a / 2", Some((4, 1))),
+                    ("fn square", Some((1, 4))),
+                    ("debug info", None),
+                ]
+            );
+        }
+
+        let options: CompilerOptions = CompilerOptions { max_bytecode_size: Some(60), ..CompilerOptions::default() };
+        let output: CompilerOutput = compile(parse(lex(code)), options);
+        assert!(output.bytecode.is_none());
+        let size: usize = output.sizes.iter().map(|entry| entry.size).sum();
+        assert_eq!(
+            all_to_string(&output.logs),
+            [format!(
+                "error: could not compile as bytecode was too large ({size} bytes, over the limit of 60); use \
+                \"--emit=size\" to see what takes up the most space."
+            )]
+        );
+        let options: CompilerOptions = CompilerOptions { max_bytecode_size: Some(size), ..CompilerOptions::default() };
+        assert!(compile(parse(lex(code)), options).bytecode.is_some());
+
+        let dir: PathBuf = std::env::temp_dir().join("krust_emit_size");
+        std::fs::create_dir_all(&dir).expect("temp dir should be writable");
+        let source: String = dir.join("program.txt").display().to_string();
+        std::fs::write(&source, code).expect("temp dir should be writable");
+        let (lines, logs): (Vec<String>, Vec<Log>) = emit_file(
+            &source,
+            Emit::Size,
+            AddressMode::Labels,
+            CompilerOptions { max_bytecode_size: Some(60), ..CompilerOptions::default() },
+            Severity::Info,
+            &RunOptions::default(),
+        );
+        // The report is still printed when the bytecode is too large.
+        assert_eq!(logs.len(), 1);
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "   bytes   share  part");
+        assert!(lines[4].ends_with("%  4:1 square(a) + a * a / 2"));
+        assert_eq!(lines[7], format!("{size:>8}  100.0%  total"));
+    }
+
+    #[test]
+    fn build_wasm() {
+        let dir: PathBuf = std::env::temp_dir().join("krust_build_wasm");
+        std::fs::create_dir_all(&dir).expect("temp dir should be writable");
+        let source: String = dir.join("program.txt").display().to_string();
+        let module: String = dir.join("program.wasm").display().to_string();
+        std::fs::write(&source, "int x = 6;\nx = x * 7;\nx / (x - 42) > 0").expect("temp dir should be writable");
+        let _ = std::fs::remove_file(&module);
+
+        let options: RunOptions = RunOptions { backend: Backend::Wasm, ..RunOptions::default() };
+        assert!(build_file(&source, &[], CompilerOptions::default(), Severity::Info, &options));
+        let file: Vec<u8> = std::fs::read(&module).expect("the module should have been written");
+        assert_eq!(file[..8], *b"\0asm\x01\0\0\0");
+        // The exported main function is named in the export section.
+        assert!(file.windows(4).any(|window| window == b"main"));
+
+        let compile_wasm = |code: &str| -> wasm::WasmOutput {
+            wasm::compile(parse(lex(code)), CompilerOptions::default())
+        };
+        assert!(compile_wasm("").module.is_some());
+        let output: wasm::WasmOutput = compile_wasm("int x = 1;\nstring s = \"a\";\nabs(x) + -x");
+        assert!(output.module.is_none());
+        assert_eq!(
+            all_to_string(&output.logs),
+            [
+                "error (line 2:8): the wasm backend doesn't support strings yet.".to_string(),
+                "error (line 3:1): the wasm backend doesn't support the built-in function \"abs\" yet.".to_string(),
+            ]
+        );
+
+        std::fs::write(&source, "list<int> {1}").expect("temp dir should be writable");
+        assert!(!build_file(&source, &[], CompilerOptions::default(), Severity::Info, &options));
+    }
+
+    #[test]
+    fn verify_before_running() {
+        // Adding with nothing on the stack would make the VM panic if it ran.
+        let bytecode: Vec<u8> = vec![8, 1, 1, 0, 0, OpCode::AddInt as u8, OpCode::Halt as u8];
+        let expected: Vec<String> =
+            vec!["error: the instruction at offset 5 pops more values than are on the stack.".to_string()];
+        let mut vm: Vm = Vm::new(bytecode.clone());
+        vm.set_verify(true);
+        assert_eq!(all_to_string(&vm.precompile().expect_err("the bytecode is invalid")), expected);
+        let logs: Vec<Log> = vm.run().err().unwrap_or_default();
+        assert_eq!((vm.output().is_empty(), all_to_string(&logs)), (true, expected.clone()));
+
+        // Compiled program files are always verified, as they could have been changed since they were built.
+        let path: PathBuf = std::env::temp_dir().join("krust_unverified.krustc");
+        std::fs::write(&path, serialize_bytecode(&bytecode)).expect("temp dir should be writable");
+        let (output, logs): (Vec<String>, Vec<Log>) =
+            run_bytecode_file(&path.display().to_string(), Severity::Info, &RunOptions::default());
+        assert_eq!((output.is_empty(), all_to_string(&logs)), (true, expected));
+    }
+
+    #[test]
+    fn constant_pool() {
+        // The calls, the jump of "?", and the function names all move when the literals are pooled.
+        let code: &str = "fn f(r: result<int>) -> result<int> { ok(r? + 1000 + 1000) }
+            fn g(r: result<int>) -> result<int> { int x = r?; panic(\"a long message\"); ok(x) }
+            string s = \"a long message\"; is_ok(f(ok(1000))) & !is_ok(g(err(\"e\"))) & (len(s) + 1000 == 1014)";
+        let compile_for = |isa_level: IsaLevel| {
+            compile(parse(lex(code)), CompilerOptions::new(8, true, isa_level)).bytecode.expect("code should compile")
+        };
+        let (unpooled, pooled): (Vec<u8>, Vec<u8>) = (compile_for(IsaLevel::V8), compile_for(IsaLevel::V9));
+        assert_eq!((unpooled[2], pooled[2]), (IsaLevel::V4 as u8, IsaLevel::V9 as u8));
+        assert!(pooled.len() < unpooled.len());
+        assert_eq!(krust::vm::run(&unpooled).0, ["true".to_string()]);
+        assert_eq!(krust::vm::run(&pooled).0, ["true".to_string()]);
+        let loaded: Vec<u8> = load_bytecode(&serialize_bytecode(&pooled)).expect("the file is valid");
+        assert!(loaded == pooled);
+
+        let lines: Vec<String> = disassemble(&pooled, AddressMode::Labels).lines;
+        let constants: Vec<&String> = lines.iter().filter(|line| line.contains("Constant")).collect();
+        assert_eq!(constants, ["    Constant PushString \"a long message\"", "    Constant PushInt 1000"]);
+        assert_eq!(lines.iter().filter(|line| *line == "    LoadConst 1").count(), 4);
+        // Literals that wouldn't take less space in the pool stay inline.
+        assert!(lines.contains(&"    PushString \"e\"".to_string()) && lines.contains(&"    PushInt 1014".to_string()));
+
+        // A load of a constant that isn't in the pool is rejected by the verifier.
+        let mut bytecode: Vec<u8> = pooled.clone();
+        let load: usize = (code_start(&bytecode)..bytecode.len())
+            .find(|index| bytecode[*index] == OpCode::LoadConst as u8)
+            .expect("a constant is loaded");
+        bytecode[load + 1] = 2;
+        assert_eq!(
+            all_to_string(&verify(&bytecode).logs),
+            [format!("error: the load at offset {load} refers to a constant that is not in the constant pool.")]
+        );
+    }
+
+    #[test]
+    fn common_subexpressions() {
+        let code: &str = "fn f(n: int) -> int { n }\nint a = 3; int b = 0;\nf(a) + f(a);\n\
+            ((a * b) + (a * b)) * ((a * b) + (a * b));\nmin(a / b, a / b)";
+        let disassemble_with = |options: CompilerOptions, passes: &PassManager| -> Vec<String> {
+            let bytecode: Vec<u8> =
+                compile_with_passes(parse(lex(code)), options, false, passes).bytecode.expect("code should compile");
+            disassemble(&bytecode, AddressMode::Labels).lines
+        };
+        let lines: Vec<String> = disassemble_with(CompilerOptions::default(), &PassManager::default());
+        // Calls aren't pure, so both are kept, and an operand that is already copied isn't searched again.
+        assert_eq!(lines.iter().filter(|line| line.starts_with("    Call")).count(), 2);
+        assert_eq!(lines.iter().filter(|line| *line == "    Dup 4").count(), 3);
+        assert_eq!(lines.iter().filter(|line| *line == "    MultiplyInt").count(), 2);
+        // The division by zero is still reported where the first operand is.
+        assert!(lines.contains(&"    DivideInt (line 5:7)".to_string()));
+        assert_eq!(
+            all_to_string(&run(&FileInput::FileText(code.to_string()), CompilerOptions::default(), Severity::Info,
+                &RunOptions::default()).1),
+            [
+                "warning (line 3:6): the value computed by \"+\" is never used; this may be a typo.",
+                "warning (line 4:21): the value computed by \"*\" is never used; this may be a typo.",
+                "error (line 5:7): division by zero.",
+            ]
+        );
+        test_code("int a = 3; int b = 4;\n(a * b) + (a * b) == (a * b) * 2", &["true".to_string()], &Vec::new());
+
+        // Nothing is copied when the pass is off or the target can't copy values.
+        let mut passes: PassManager = PassManager::default();
+        passes.disable(CompilerPass::CommonSubexpressions);
+        for (options, passes) in
+            [(CompilerOptions::default(), &passes), (CompilerOptions::new(8, true, IsaLevel::V10), &PassManager::default())]
+        {
+            let lines: Vec<String> = disassemble_with(options, passes);
+            assert!(!lines.iter().any(|line| line.contains("Dup")));
+            assert_eq!(lines.iter().filter(|line| *line == "    MultiplyInt").count(), 5);
+        }
+    }
+
+    #[test]
+    fn register_encoding() {
+        let code: &str = "fn f(x: int) -> int { x * 2 }\nint a = 6; int b = 3; int c = 0;\nint d = a + b * c;\n\
+            d < a + 1 == (b >= 2);\nf(d) + a / c";
+        let mut passes: PassManager = PassManager::default();
+        passes.enable(CompilerPass::Registers);
+        let compile_with = |options: CompilerOptions, passes: &PassManager| -> Vec<u8> {
+            compile_with_passes(parse(lex(code)), options, true, passes).bytecode.expect("code should compile")
+        };
+        let stack: Vec<u8> = compile_with(CompilerOptions::default(), &PassManager::default());
+        let registers: Vec<u8> = compile_with(CompilerOptions::default(), &passes);
+        let lines: Vec<String> = disassemble(&registers, AddressMode::Labels).lines;
+        for line in [
+            "RegisterOp MultiplyInt var0 2 (line 1:25)",
+            "RegisterOp MultiplyInt var1 var2",
+            "RegisterOp AddInt var0 stack",
+            "RegisterOp LessInt var3 stack",
+            "RegisterOp GreaterEqualInt var1 2",
+            "RegisterOp DivideInt var0 var2 (line 5:10)",
+        ] {
+            assert!(lines.contains(&format!("    {line}")), "missing {line}");
+        }
+        assert!(lines.len() + 8 < disassemble(&stack, AddressMode::Labels).lines.len());
+        assert_eq!(verify(&registers).logs.len(), 0);
+        // Both encodings give the same output and report the division by zero in the same place.
+        let (stack_out, stack_logs) = krust::vm::run(&stack);
+        let (register_out, register_logs) = krust::vm::run(&registers);
+        assert_eq!(stack_out, register_out);
+        assert_eq!(all_to_string(&register_logs), ["error (line 5:10): division by zero.".to_string()]);
+        assert_eq!(all_to_string(&stack_logs), all_to_string(&register_logs));
+
+        // The pass does nothing when the target has no register instructions.
+        let options: CompilerOptions = CompilerOptions::new(8, true, IsaLevel::V11);
+        assert_eq!(disassemble(&compile_with(options, &passes), AddressMode::Labels).lines,
+            disassemble(&compile_with(options, &PassManager::default()), AddressMode::Labels).lines);
+        // Registers are checked against the types of the variables.
+        let mut bytecode: Vec<u8> = vec![8, 1, 12, 0, 0, OpCode::AllocBool as u8, OpCode::RegisterOp as u8];
+        bytecode.extend_from_slice(&[OpCode::AddInt as u8, 0x21, 0, 0, 0, 0, 1, 0, 0, 0]);
+        test_verify(
+            &bytecode,
+            &["error: the instruction at offset 6 accesses a variable that does not exist or has a different type.".to_string()],
+        );
+    }
+
+    #[test]
+    fn line_table() {
+        let code: &str = "fn f(l: list<int>, i: int) -> int {\n    l[i] / i\n}\nf(list<int>{1, 2}, 0) + f(list<int>{}, 1)";
+        let compile_with = |options: CompilerOptions| compile(parse(lex(code)), options).bytecode.expect("code should compile");
+        let bytecode: Vec<u8> = compile_with(CompilerOptions::default());
+        assert_eq!(
+            all_to_string(&krust::vm::run(&bytecode).1),
+            [
+                "error (line 2:10): division by zero.".to_string(),
+                "note (line 2:10): in the function \"f\".".to_string(),
+                "note (line 4:1): in the main program.".to_string(),
+            ]
+        );
+        let lines: Vec<String> = disassemble(&bytecode, AddressMode::Labels).lines;
+        assert!(lines.contains(&"    ListIndex 4 (line 2:6)".to_string()) && lines.contains(&"    DivideInt (line 2:10)".to_string()));
+        assert_eq!(lines.last().map(String::as_str), Some("    LineTable 6"));
+
+        // Without a line table, the instructions are the same and errors have no location.
+        for options in [CompilerOptions::new(8, true, IsaLevel::V9), CompilerOptions::new(8, false, IsaLevel::LATEST)] {
+            let without: Vec<u8> = compile_with(options);
+            assert_eq!(
+                all_to_string(&krust::vm::run(&without).1),
+                [
+                    "error: division by zero.".to_string(),
+                    "note: in the function \"f\".".to_string(),
+                    "note: in the main program.".to_string(),
+                ]
+            );
+            let without_lines: Vec<String> = disassemble(&without, AddressMode::Labels).lines;
+            let located: Vec<String> =
+                lines[1..lines.len() - 1].iter().map(|line| line.split(" (line").next().unwrap_or(line).to_string()).collect();
+            assert_eq!(without_lines[1..], located);
+        }
+    }
+
+    #[test]
+    fn emit_disassembly() {
+        let dir: PathBuf = std::env::temp_dir().join("krust_emit");
+        std::fs::create_dir_all(&dir).expect("temp dir should be writable");
+        let source: String = dir.join("program.txt").display().to_string();
+        let compiled: String = dir.join("program.krustc").display().to_string();
+        std::fs::write(&source, "fn f(n: int) -> int {\n    10 / n\n}\nf(2)").expect("temp dir should be writable");
+
+        let options: RunOptions = RunOptions::default();
+        let (lines, logs): (Vec<String>, Vec<Log>) =
+            emit_file(&source, Emit::Disasm, AddressMode::Labels, CompilerOptions::default(), Severity::Info, &options);
+        assert!(logs.is_empty());
+        assert!(lines.contains(&"    DivideInt (line 2:8)".to_string()));
+        // The compiled program gives the same instructions as its source.
+        assert!(build_file(&source, &[], CompilerOptions::default(), Severity::Info, &options));
+        let (compiled_lines, logs): (Vec<String>, Vec<Log>) =
+            emit_file(&compiled, Emit::Disasm, AddressMode::Labels, CompilerOptions::default(), Severity::Info, &options);
+        assert_eq!((compiled_lines[1..].to_vec(), logs.is_empty()), (lines[1..].to_vec(), true));
+
+        std::fs::write(&source, "1 + true").expect("temp dir should be writable");
+        let (lines, logs): (Vec<String>, Vec<Log>) =
+            emit_file(&source, Emit::Disasm, AddressMode::Offsets, CompilerOptions::default(), Severity::Info, &options);
+        assert!(lines.is_empty() && is_error(&logs));
+    }
+
+    #[test]
+    fn emit_ast() {
+        let dir: PathBuf = std::env::temp_dir().join("krust_emit_ast");
+        std::fs::create_dir_all(&dir).expect("temp dir should be writable");
+        let source: String = dir.join("program.txt").display().to_string();
+        std::fs::write(&source, "int x = 1 + 2;\n-x * (3 % 2)").expect("temp dir should be writable");
+
+        let options: RunOptions = RunOptions::default();
+        let (lines, logs): (Vec<String>, Vec<Log>) =
+            emit_file(&source, Emit::Ast, AddressMode::Labels, CompilerOptions::default(), Severity::Info, &options);
+        assert!(logs.is_empty());
+        assert_eq!(lines, [
+            "ExpressionList: int",
+            "  Statement: void",
+            "    Binary \"=\" (1:7, bytes 6..7): int",
+            "      VariableDeclaration: int",
+            "        Variable \"x\" (1:5, bytes 4..5): int",
+            "      Binary \"+\" (1:11, bytes 10..11): int",
+            "        Literal \"1\" (1:9, bytes 8..9): int",
+            "        Literal \"2\" (1:13, bytes 12..13): int",
+            "  Binary \"*\" (2:4, bytes 18..19): int",
+            "    Unary \"-\" (2:1, bytes 15..16): int",
+            "      Variable \"x\" (1:5, bytes 4..5): int",
+            "    Grouping: int",
+            "      Binary \"%\" (2:9, bytes 23..24): int",
+            "        Literal \"3\" (2:7, bytes 21..22): int",
+            "        Literal \"2\" (2:11, bytes 25..26): int",
+        ]);
+
+        // The tree is printed along with the errors when the code doesn't parse.
+        std::fs::write(&source, "flags F { A = 1, B = F.A }\n1 + true").expect("temp dir should be writable");
+        let (lines, logs): (Vec<String>, Vec<Log>) =
+            emit_file(&source, Emit::Ast, AddressMode::Labels, CompilerOptions::default(), Severity::Info, &options);
+        assert!(is_error(&logs));
+        assert_eq!(lines[2..6], [
+            "    FlagsDeclaration \"F\" (1:7, bytes 6..7): void",
+            "      Flag \"A\" (1:11, bytes 10..11)",
+            "        Literal \"1\" (1:15, bytes 14..15): int",
+            "      Flag \"B\" (1:18, bytes 17..18)",
+        ]);
+
+        // Compiled programs have no tree to print.
+        let compiled: String = dir.join("program.krustc").display().to_string();
+        std::fs::write(&compiled, "").expect("temp dir should be writable");
+        let (lines, logs): (Vec<String>, Vec<Log>) =
+            emit_file(&compiled, Emit::Ast, AddressMode::Labels, CompilerOptions::default(), Severity::Info, &options);
+        assert_eq!((lines.is_empty(), all_to_string(&logs)),
+            (true, vec!["error: the output \"ast\" needs source code, not a compiled program.".to_string()]));
+    }
+
+    #[test]
+    fn emit_tokens() {
+        let dir: PathBuf = std::env::temp_dir().join("krust_emit_tokens");
+        std::fs::create_dir_all(&dir).expect("temp dir should be writable");
+        let source: String = dir.join("program.txt").display().to_string();
+        std::fs::write(&source, "int x = 12 + \"a\";\n1 5x").expect("temp dir should be writable");
+
+        let options: RunOptions = RunOptions::default();
+        let (lines, logs): (Vec<String>, Vec<Log>) =
+            emit_file(&source, Emit::Tokens, AddressMode::Labels, CompilerOptions::default(), Severity::Info, &options);
+        // Tokens after an error are still printed.
+        assert_eq!(lines, [
+            "1:1 Int \"int\"",
+            "1:5 Other \"x\"",
+            "1:7 Equals \"=\"",
+            "1:9 IntLiteral(12) \"12\"",
+            "1:12 Plus \"+\"",
+            "1:14 StringLiteral \"\\\"a\\\"\"",
+            "1:17 Semicolon \";\"",
+            "2:1 IntLiteral(1) \"1\"",
+            "2:3 Error \"5x\"",
+            "2:5 EOF",
+        ]);
+        assert_eq!(all_to_string(&logs),
+            ["error (line 2:4): invalid suffix \"x\" on an int literal; the valid suffixes are \"i\", \"l\", and \"u\"."]);
+
+        let compiled: String = dir.join("program.krustc").display().to_string();
+        std::fs::write(&compiled, "").expect("temp dir should be writable");
+        let (lines, logs): (Vec<String>, Vec<Log>) =
+            emit_file(&compiled, Emit::Tokens, AddressMode::Labels, CompilerOptions::default(), Severity::Info, &options);
+        assert_eq!((lines.is_empty(), all_to_string(&logs)),
+            (true, vec!["error: the output \"tokens\" needs source code, not a compiled program.".to_string()]));
+    }
+
+    #[test]
+    fn emit_c() {
+        let dir: PathBuf = std::env::temp_dir().join("krust_emit_c");
+        std::fs::create_dir_all(&dir).expect("temp dir should be writable");
+        let source: String = dir.join("program.txt").display().to_string();
+        std::fs::write(&source, "int x = 7;\nx = x / (x - 7);\nx > 0").expect("temp dir should be writable");
+
+        let options: RunOptions = RunOptions::default();
+        let (lines, logs): (Vec<String>, Vec<Log>) =
+            emit_file(&source, Emit::C, AddressMode::Labels, CompilerOptions::default(), Severity::Info, &options);
+        assert!(logs.is_empty());
+        let main: usize = lines.iter().position(|line| line == "int main(void) {").expect("the file should have a main");
+        // Each value is stored in a temporary, so operands are evaluated in the same order as in krust.
+        assert_eq!(lines[main + 1..], [
+            "    int32_t v0 = 0;",
+            "    v0 = 7;",
+            "    int32_t t0 = v0;",
+            "    int32_t t1 = v0;",
+            "    int32_t t2 = krust_sub(t1, 7);",
+            "    int32_t t3 = krust_div(t0, t2, 2, 7);",
+            "    v0 = t3;",
+            "    (void)t3;",
+            "    int32_t t4 = v0;",
+            "    int32_t t5 = t4 > 0;",
+            "    puts(t5 ? \"true\" : \"false\");",
+            "    return 0;",
+            "}",
+        ]);
+
+        std::fs::write(&source, "int x = 1;\nstring s = \"a\";\nabs(x) + -x").expect("temp dir should be writable");
+        let (lines, logs): (Vec<String>, Vec<Log>) =
+            emit_file(&source, Emit::C, AddressMode::Labels, CompilerOptions::default(), Severity::Info, &options);
+        assert_eq!((lines.is_empty(), all_to_string(&logs)), (true, vec![
+            "error (line 2:8): the C backend doesn't support strings yet.".to_string(),
+            "error (line 3:1): the C backend doesn't support the built-in function \"abs\" yet.".to_string(),
+            "error: could not compile due to errors.".to_string(),
+        ]));
     }
 
-    if cli_output.0.is_some() {
-        let cli_output: CLIInfo = cli_output.0.expect("checked by if statement");
-        run(FileInput::FilePath(cli_output.file_path), cli_output.cli_args);
+    #[test]
+    fn reproducible_output() {
+        let code: &str = "trait Describe { fn describe(self) -> string; }\n\
+            impl Describe for int { fn describe(self) -> string { \"int\" } }\n\
+            impl Describe for bool { fn describe(self) -> string { \"bool\" } }\n\
+            fn id<T>(x: T) -> T { x }\n\
+            fn show<T: Describe>(x: T) -> string { x.describe() }\n\
+            int a = id(3) + id(3) * 3;\n\
+            bool b = id(true);\n\
+            show(a);\n\
+            show(b)";
+        // Each thread has its own hash map keys, so the bytes would differ between threads if the compiler depended on
+        // the order of a hash map.
+        let outputs: Vec<Vec<u8>> = (0..8)
+            .map(|_| {
+                std::thread::spawn(move || {
+                    let bytecode: Vec<u8> = compile(parse(lex(code)), CompilerOptions::new(4, true, IsaLevel::LATEST))
+                        .bytecode
+                        .expect("code should compile");
+                    serialize_bytecode(&bytecode)
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|thread| thread.join().expect("the thread shouldn't panic"))
+            .collect();
+        assert!(outputs.iter().all(|output| *output == outputs[0]));
+
+        let dir: PathBuf = std::env::temp_dir().join("krust_emit_hash");
+        std::fs::create_dir_all(&dir).expect("temp dir should be writable");
+        let source: String = dir.join("program.txt").display().to_string();
+        let compiled: String = dir.join("program.krustc").display().to_string();
+        std::fs::write(&source, code).expect("temp dir should be writable");
+        let options: RunOptions = RunOptions::default();
+        let (lines, logs): (Vec<String>, Vec<Log>) =
+            emit_file(&source, Emit::Hash, AddressMode::Labels, CompilerOptions::default(), Severity::Info, &options);
+        assert!(logs.is_empty());
+        // The hash is of the file that build writes, so hashing the compiled program gives the same hash.
+        assert!(build_file(&source, &[], CompilerOptions::default(), Severity::Info, &options));
+        let file: Vec<u8> = std::fs::read(&compiled).expect("the program should have been written");
+        assert_eq!(lines, [format!("{:016x}", hash_bytes(&file))]);
+        let (compiled_lines, _): (Vec<String>, Vec<Log>) =
+            emit_file(&compiled, Emit::Hash, AddressMode::Labels, CompilerOptions::default(), Severity::Info, &options);
+        assert_eq!(compiled_lines, lines);
     }
-}
 
-// Runs the code in the file.
-// TODO: Print every compiler thing before the program actually runs.
-fn run(file_input: FileInput, cli_args: [u8; 2]) -> (Vec<String>, Vec<Log>) {
-    let lex_output: LexerOutput = lex(file_input.get_file_text());
-    let parse_output: ParserOutput = parse(lex_output);
-    let compiler_output: CompilerOutput = compile(parse_output, cli_args);
-    let mut output: Vec<String> = Vec::new();
-    let mut logs: Vec<Log> = Vec::new();
+    #[test]
+    fn formatting() {
+        let code: &str = "flags P { A = 1, B = 2 };\ntrait T { fn t(self) -> int; }\nimpl T for int { fn t(self) -> int { self*2 } }\n\n\n\
+            fn f<U: T>(x:U, y: int = (3))->int{int z=x.t()+y ; z}   // keep\nlist<int> l=list<int>{1,(2)};\n\
+            map[int]bool m = map[int]bool{1:true};\nint? n = null;\n// own line\nint a = (-f(1) + ~P.A) * (l[(0)]);\n(a);\nbool b = !(a==3);";
+        let formatted: String = format(code).formatted.expect("the code has no errors");
+        assert_eq!(
+            formatted,
+            "flags P {\n    A = 1,\n    B = 2\n}\ntrait T {\n    fn t(self) -> int;\n}\nimpl T for int {\n    fn t(self) -> int {\n        self * 2\n    }\n}\n\n\
+            fn f<U: T>(x: U, y: int = 3) -> int {\n    int z = x.t() + y;\n    z\n} // keep\nlist<int> l = list<int> {1, 2};\n\
+            map[int]bool m = map[int]bool {1: true};\nint? n = null;\n// own line\nint a = (-f(1) + ~P.A) * (l[0]);\na;\nbool b = !(a == 3);\n"
+        );
+        // Formatting code that is already formatted doesn't change it.
+        assert_eq!(format(&formatted).formatted, Some(formatted));
+        // A '<' inside parentheses or arguments is a comparison, not the start of a type.
+        let comparisons: &str = "fn g(x: bool) -> bool {\n    x\n}\nint a = 1;\nbool b = !(a < 2);\ng(a < 2) & g(max(a, 0) < 2)\n";
+        assert_eq!(format(comparisons).formatted, Some(comparisons.to_string()));
+        // A value assigned in parentheses is printed by scripts, so the parentheses are kept.
+        assert_eq!(format("int x = 1;\n(x = 2);").formatted, Some("int x = 1;\n(x = 2);\n".to_string()));
+        // Code with errors isn't formatted.
+        let output: FormatterOutput = format("int x = true;");
+        assert!(output.formatted.is_none());
+        assert!(log::is_error(&output.logs));
 
-    for log in compiler_output.logs {
-        eprintln!("{log}");
-        logs.push(log);
+        let dir: PathBuf = std::env::temp_dir().join("krust_fmt");
+        std::fs::create_dir_all(&dir).expect("temp dir should be writable");
+        let file: String = dir.join("program.txt").display().to_string();
+        std::fs::write(&file, "int x=1;\nx").expect("temp dir should be writable");
+        assert!(!fmt_file(&file, true, RenderOptions::default()));
+        assert_eq!(std::fs::read_to_string(&file).expect("the file was just written"), "int x=1;\nx");
+        assert!(fmt_file(&file, false, RenderOptions::default()));
+        assert_eq!(std::fs::read_to_string(&file).expect("the file was just written"), "int x = 1;\nx\n");
+        assert!(fmt_file(&file, true, RenderOptions::default()));
     }
-    if let Some(bytecode) = compiler_output.bytecode {
-        let out_log: (Vec<String>, Vec<Log>) = vm::run(&bytecode);
-        output.append(&mut out_log.0.clone());
-        for log in out_log.1 {
-            logs.push(log);
-        }
-    } else {
-        logs.push(Log {
-            log_type: LogType::Error(ErrorType::CantCompile),
-            line_and_col: None,
+
+    #[test]
+    fn watch_for_changes() {
+        let dir: PathBuf = std::env::temp_dir().join("krust_watch");
+        std::fs::create_dir_all(&dir).expect("temp dir should be writable");
+        let file: PathBuf = dir.join("program.txt");
+        std::fs::write(&file, "1 + 2").expect("temp dir should be writable");
+        let mut watcher: Watcher = Watcher::new(vec![file.clone()], Duration::from_millis(50));
+        assert!(!watcher.changed());
+        std::fs::write(&file, "1 + 23").expect("temp dir should be writable");
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+        std::fs::remove_file(&file).expect("the file was just written");
+        assert!(watcher.changed());
+
+        // Several writes in a row are one change, which is reported once they stop.
+        let writer = std::thread::spawn({
+            let file: PathBuf = file.clone();
+            move || {
+                for code in ["1", "1 +", "1 + 2"] {
+                    std::fs::write(&file, code).expect("temp dir should be writable");
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+            }
         });
-        eprintln!("{}", logs.iter().last().expect("list was just pushed to"));
+        watcher.wait_for_change();
+        writer.join().expect("the thread shouldn't panic");
+        assert!(!watcher.changed());
     }
 
-    (output, logs)
-}
+    #[test]
+    fn crashcheck() {
+        let dir: PathBuf = std::env::temp_dir().join("krust_crashcheck");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("hangs")).expect("temp dir should be writable");
+        std::fs::write(dir.join("a.txt"), "1 + 2").expect("temp dir should be writable");
+        std::fs::write(dir.join("b.txt"), b"1 + \xff true").expect("temp dir should be writable");
+        std::fs::write(dir.join("c.txt"), "1 / 0").expect("temp dir should be writable");
+        // The tail call reuses its frame, so the recursion never ends.
+        std::fs::write(dir.join("hangs").join("d.txt"), "fn f(n: int) -> int { f(n) } f(0)")
+            .expect("temp dir should be writable");
 
-/// The module for running tests.
-#[cfg(test)]
-mod tests {
-    use super::run;
-    use super::FileInput;
-    use krust::util::log;
-    use krust::vm::test_func::shift_int;
+        let results: Vec<CaseResult> = check_corpus(&dir, CompilerOptions::new(8, true, IsaLevel::LATEST), Duration::from_millis(500))
+            .expect("the corpus should be readable");
+        assert_eq!(
+            results.iter().map(|result| result.path.strip_prefix(&dir).expect("in the corpus")).collect::<Vec<_>>(),
+            ["a.txt", "b.txt", "c.txt", "hangs/d.txt"].map(Path::new)
+        );
+        assert!(results[..3].iter().all(|result| result.crash.is_none()));
+        assert!(matches!(results[3].crash, Some(Crash::Hang { stage: Stage::Run })));
+        assert!(check_corpus(&dir.join("missing"), CompilerOptions::new(8, true, IsaLevel::V2), DEFAULT_TIMEOUT).is_err());
+    }
 
-    use log::all_to_string;
+    #[test]
+    fn batch_compilation() {
+        let dir: SourcePath = std::env::temp_dir().join("krust_batch_compilation");
+        std::fs::create_dir_all(&dir).expect("temp dir should be writable");
+        let paths: Vec<SourcePath> = vec![dir.join("a.txt"), dir.join("missing.txt"), dir.join("b.txt")];
+        std::fs::write(&paths[0], "1 + 2").expect("temp dir should be writable");
+        std::fs::write(&paths[2], "1 + true").expect("temp dir should be writable");
+        let _ = std::fs::remove_file(&paths[1]);
 
-    use proptest::prelude::*;
+        let (results, source_map): (Vec<FileResult>, SourceMap) = compile_many(&paths, CompilerOptions::new(8, true, IsaLevel::V2));
+        assert_eq!(results.len(), 3);
+        assert!(results[0].output.bytecode.is_some());
+        assert_eq!(results[1].file_id, None);
+        assert_eq!(
+            all_to_string(&results[1].output.logs),
+            [format!("error: could not open file \"{}\".", paths[1].display())]
+        );
+        assert_eq!(
+            all_to_string(&results[2].output.logs),
+            ["error (line 1:3): the operator \"+\" has no definition over the types \"int\" and \"bool\".".to_string()]
+        );
+        assert_eq!(source_map.len(), 2);
+        assert_eq!(source_map.file_text(results[2].file_id.expect("file exists")), Some("1 + true"));
+    }
 
-    // Runs the given code and checks the output against out and err.
-    fn test_code(code: &str, out: &[String], err: &[String]) {
-        let out_err = run(
-            FileInput::FileText(code.to_string()),
+    #[test]
+    fn linked_files() {
+        let link = |main: &str| {
+            LinkedSource::link(vec![
+                (SourcePath::from("lib.txt"), "fn double(x: int) -> int {\n    x * 2\n}\n1 + 2".to_string()),
+                (SourcePath::from("main.txt"), main.to_string()),
+            ])
+        };
+        let run_linked = |main: &str| {
+            let source: LinkedSource = link(main);
+            let (out, logs): (Vec<String>, Vec<Log>) =
+                run(&FileInput::Linked(link(main)), CompilerOptions::default(), Severity::Info, &RunOptions::default());
+            (out, logs.iter().map(|log| source.describe(log, RenderOptions::default())).collect::<Vec<String>>())
+        };
+        // The last line of the library becomes a statement once the files are linked, so its value is thrown away.
+        let unused: String =
+            "lib.txt: warning[W0004] (line 4:3): the value computed by \"+\" is never used; this may be a typo.".to_string();
+
+        assert_eq!(run_linked("double(21)"), (vec!["42".to_string()], vec![unused.clone()]));
+        assert_eq!(
+            run_linked("int a = double(4);\na / (a - 8)").1,
+            [unused.clone(), "main.txt: error[E0105] (line 2:3): division by zero.".to_string()]
+        );
+        assert_eq!(
+            run_linked("double(true)").1,
             [
-                (usize::BITS / 8)
-                    .try_into()
-                    .expect("length of usize shouldn't be over 1024 bits"),
-                1,
-            ],
+                "main.txt: error[E0048] (line 1:1): the function \"double\" has no definition over the type \"bool\".".to_string(),
+                "main.txt: note (line 1:1): the function \"double\" takes arguments of the type \"int\".".to_string(),
+                unused,
+                "error[E0089]: could not compile due to errors.".to_string(),
+            ]
         );
-        assert_eq!(out_err.0, out);
-        assert_eq!(all_to_string(&out_err.1), err);
+        let source: LinkedSource = link("double(21)");
+        assert_eq!(source.locate(4), Some((&SourcePath::from("lib.txt"), 4)));
+        assert_eq!(source.locate(6), Some((&SourcePath::from("main.txt"), 1)));
     }
 
     #[test]
-    fn empty() {
-        test_code("", &Vec::new(), &Vec::new());
+    fn compile_not_cancelled() {
+        let output: PartialCompilerOutput =
+            compile_with_cancellation("1 + true", CompilerOptions::new(8, true, IsaLevel::V2), &CancellationToken::new());
+        assert_eq!(output.cancelled_at, None);
+        assert_eq!(
+            all_to_string(&output.logs),
+            ["error (line 1:3): the operator \"+\" has no definition over the types \"int\" and \"bool\".".to_string()]
+        );
+        let output: PartialCompilerOutput =
+            compile_with_cancellation("1 + 2", CompilerOptions::new(8, true, IsaLevel::V2), &CancellationToken::new());
+        assert_eq!(output.bytecode, compile(parse(lex("1 + 2")), CompilerOptions::new(8, true, IsaLevel::V2)).bytecode);
     }
 
     #[test]
-    fn above_max_int() {
-        test_code(
-            format!("{}", 0x8000_0001u32).as_str(),
-            &Vec::new(),
-            &[
-                format!(
-                    "error (line 1:1): int literal \"{}\" must be at most {}.",
-                    0x8000_0001u32, 0x8000_0000u32
-                ),
-                "error: could not compile due to errors.".to_string(),
-            ],
-        );
+    fn compile_cancelled() {
+        let token: CancellationToken = CancellationToken::new();
+        token.clone().cancel();
+        assert!(token.is_cancelled());
+        let output: PartialCompilerOutput = compile_with_cancellation("1 + 2", CompilerOptions::new(8, true, IsaLevel::V2), &token);
+        assert_eq!(output.cancelled_at, Some(Pass::Lex));
+        assert!(output.bytecode.is_none());
+        assert!(output.logs.is_empty());
     }
 
     #[test]
-    fn max_int_no_sign() {
-        test_code(
-            format!("{}", 0x8000_0000u32).as_str(), 
-            &Vec::new(), 
-            &[
-                format!("error (line 1:1): the int literal {} must be preceded by a unary \'-\' operator.", 0x8000_0000u32),
-                "error: could not compile due to errors.".to_string()
+    fn compile_cancelled_between_passes() {
+        let source: &str = "fn double(x: int) -> int { x * (1 + 1) }\ndouble(2) + double(2)";
+        let options: CompilerOptions = CompilerOptions::new(8, true, IsaLevel::LATEST);
+        // The lexer, the parser, and the start of the compiler each check the token before the passes do.
+        let expected: [Option<Pass>; 6] = [
+            Some(Pass::Compiler(CompilerPass::ConstantFolding)),
+            Some(Pass::Compiler(CompilerPass::Codegen)),
+            Some(Pass::Compiler(CompilerPass::FunctionNames)),
+            Some(Pass::Compiler(CompilerPass::CommonSubexpressions)),
+            Some(Pass::Compiler(CompilerPass::ConstantPool)),
+            None,
+        ];
+        for (checks, cancelled_at) in (3..).zip(expected) {
+            let token: CancellationToken = CancellationToken::new();
+            token.cancel_after(checks);
+            let output: PartialCompilerOutput = compile_with_cancellation(source, options, &token);
+            assert_eq!(output.cancelled_at, cancelled_at, "cancelled after {checks} checks");
+            assert_eq!(output.bytecode.is_some(), cancelled_at.is_none());
+            assert_eq!(token.is_cancelled(), cancelled_at.is_some());
+        }
+
+        let token: CancellationToken = CancellationToken::new();
+        let mut passes: PassManager = PassManager::new();
+        passes.set_cancellation(token.clone());
+        passes.set_print_after(CompilerPass::ConstantFolding);
+        token.cancel_after(1);
+        let output: CompilerOutput = compile_with_passes(parse(lex(source)), options, false, &passes);
+        assert_eq!(output.cancelled_at, Some(CompilerPass::Codegen));
+        assert!(output.bytecode.is_none() && output.sizes.is_empty());
+        assert!(output.dump.is_some_and(|dump| dump.contains("Literal")));
+    }
+
+    #[test]
+    fn precompiled_vm() {
+        let bytecode: Vec<u8> = compile(parse(lex("string s = \"héllo\"; len(s) + len(\"!\")")), CompilerOptions::new(8, true, IsaLevel::V5))
+            .bytecode
+            .expect("code should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        assert!(!vm.is_precompiled());
+        assert!(vm.precompile().is_ok());
+        assert!(vm.is_precompiled());
+        for _ in 0..3 {
+            assert_eq!(vm.run().ok(), Some(Value::Int(6)));
+            assert_eq!(vm.output(), ["6".to_string()]);
+        }
+
+        let mut vm: Vm = Vm::new(vec![8, 1, 14, 0, 0]);
+        let expected: Vec<String> =
+            vec!["error: this program requires instruction set level 14, but only levels 1 to 13 are supported.".to_string()];
+        assert_eq!(all_to_string(&vm.precompile().expect_err("the level is unsupported")), expected);
+        assert_eq!(all_to_string(&vm.run().err().unwrap_or_default()), expected);
+    }
+
+    #[test]
+    fn vm_values() {
+        let bytecode = |code: &str| -> Vec<u8> {
+            compile(parse(lex(code)), CompilerOptions::default()).bytecode.expect("code should compile")
+        };
+        let cases: [(&str, Value); 9] = [
+            ("2 * 21", Value::Int(42)),
+            ("1 < 2", Value::Bool(true)),
+            ("substring(\"abc\", 1, 3)", Value::String("bc".to_string())),
+            ("int a = 1;", Value::Void),
+            ("map[int]int m = map[int]int {1: 10}; get(m, 2)", Value::Null),
+            ("list<int> {1, 2}", Value::List(vec![Value::Int(1), Value::Int(2)])),
+            ("map[int]bool {2: true, -1: false}", Value::Map(vec![
+                (Value::Int(-1), Value::Bool(false)),
+                (Value::Int(2), Value::Bool(true)),
+            ])),
+            ("ok(5)", Value::Result(Ok(Box::new(Value::Int(5))))),
+            ("err(\"no\")", Value::Result(Err("no".to_string()))),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(Vm::new(bytecode(code)).run().ok(), Some(expected));
+        }
+        assert_eq!(
+            Value::List(vec![Value::String("a".to_string()), Value::String("\"b\"".to_string())]).to_string(),
+            "[\"a\", \"\\\"b\\\"\"]"
+        );
+        assert_eq!(
+            all_to_string(&Vm::new(bytecode("fn p(x: int) -> int { panic(\"no\"); x }\np(1)")).run().err().unwrap_or_default()),
+            [
+                "error (line 1:23): the program panicked: no".to_string(),
+                "note (line 1:23): in the function \"p\".".to_string(),
+                "note (line 2:1): in the main program.".to_string(),
             ]
         );
     }
 
+    // A writer that appends to a buffer that the test can still read once the VM owns the writer.
+    #[derive(Clone, Default)]
+    struct Capture(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
     #[test]
-    fn max_pos_int() {
-        test_code(
-            format!("{}", 0x8000_0000u32 - 1).as_str(),
-            &[format!("{}", 0x8000_0000u32 - 1)],
-            &Vec::new(),
+    fn vm_output_streams() {
+        let code: &str = "int x = 3;\nx * 2;\nfn p(y: int) -> int { panic(\"no\"); y }\np(x)";
+        let bytecode: Vec<u8> = compile_with_passes(parse(lex(code)), CompilerOptions::default(), true, &PassManager::default())
+            .bytecode.expect("code should compile");
+        let (output, errors): (Capture, Capture) = (Capture::default(), Capture::default());
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.set_output(output.clone());
+        vm.set_error_output(errors.clone());
+        assert!(vm.run().is_err());
+        assert_eq!(String::from_utf8_lossy(&output.0.borrow()), "6\n");
+        assert_eq!(
+            String::from_utf8_lossy(&errors.0.borrow()),
+            "error[E0119] (line 3:23): the program panicked: no\nnote (line 3:23): in the function \"p\".\nnote (line 4:1): in the main program.\n"
         );
+        assert_eq!(vm.output(), ["6".to_string()]);
     }
 
     #[test]
-    fn below_min_int() {
+    fn diagnostic_sinks() {
+        let code: &str = "int x = 1;\nx + true";
+        let logs: Vec<Log> = parse(lex(code)).logs;
+        let mut collecting: CollectingSink = CollectingSink::default();
+        collecting.report_all(&logs);
+        assert_eq!(all_to_string(&collecting.logs), all_to_string(&logs));
+
+        let terminal: Capture = Capture::default();
+        TerminalSink::new(terminal.clone(), Some(code)).report(&logs[0]);
+        assert_eq!(String::from_utf8_lossy(&terminal.0.borrow()), logs[0].render(Some(code), RenderOptions::default()) + "\n");
+        let json: Capture = Capture::default();
+        JsonSink::new(json.clone(), Some("main.txt")).report(&logs[0]);
+        assert_eq!(String::from_utf8_lossy(&json.0.borrow()), logs[0].to_json(Some("main.txt"), NumberFormat::default()) + "\n");
+
+        // Runtime errors go to the sink instead of the error output.
+        let code: &str = "fn f(x: int, y: int) -> int { x / y } f(1, 0)";
+        let errors: Capture = Capture::default();
+        let sink: Rc<RefCell<CollectingSink>> = Rc::default();
+        let mut vm: Vm = Vm::new(compile(parse(lex(code)), CompilerOptions::default()).bytecode.expect("code should compile"));
+        vm.set_error_output(errors.clone());
+        vm.set_diagnostic_sink(Rc::clone(&sink));
+        let vm_errors: Vec<Log> = vm.run().expect_err("the program should divide by zero");
+        assert_eq!(all_to_string(&sink.borrow().logs), all_to_string(&vm_errors));
+        assert!(errors.0.borrow().is_empty());
+
+        let sink: Rc<RefCell<CollectingSink>> = Rc::default();
+        let mut interpreter: Interpreter = prepare(parse(lex(code)), CompilerOptions::default(), false, &PassManager::default())
+            .interpreter
+            .expect("code should compile");
+        interpreter.set_error_output(std::io::sink());
+        interpreter.set_diagnostic_sink(Rc::clone(&sink));
+        assert!(interpreter.run().is_err());
+        assert_eq!(all_to_string(&sink.borrow().logs), all_to_string(&vm_errors));
+    }
+
+    #[test]
+    fn program_args() {
+        test_code("args()", &["[]".to_string()], &Vec::new());
         test_code(
-            format!("-{}", 0x8000_0001u32).as_str(),
+            "list<string> l = list<string>{\"a\", \"\\\"b\\\"\"}; push(l, \"c\")",
+            &["[\"a\", \"\\\"b\\\"\", \"c\"]".to_string()],
             &Vec::new(),
-            &[
-                format!(
-                    "error (line 1:2): int literal \"{}\" must be at most {}.",
-                    0x8000_0001u32, 0x8000_0000u32
-                ),
-                "error: could not compile due to errors.".to_string(),
-            ],
         );
+        test_code(
+            "list<string> l = list<string>{\"a\", \"bc\"}; len(l) * 10 + len(l[1])",
+            &["22".to_string()],
+            &Vec::new(),
+        );
+
+        let bytecode: Vec<u8> = compile(
+            parse(lex("list<string> a = args(); parse_int(a[0])! + len(a[1]) + len(a)")),
+            CompilerOptions::new(8, true, IsaLevel::LATEST),
+        )
+        .bytecode
+        .expect("code should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.set_args(vec!["41".to_string(), "-x".to_string()]);
+        assert_eq!(vm.run().ok(), Some(Value::Int(45)));
     }
 
     #[test]
-    fn min_int() {
-        test_code(
-            format!("-{}", 0x8000_0000u32).as_str(),
-            &[format!("-{}", 0x8000_0000u32)],
-            &Vec::new(),
+    fn vm_reset() {
+        let bytecode: Vec<u8> = compile(
+            parse(lex("list<string> a = args(); int n = parse_int(a[0])!; n * len(a)")),
+            CompilerOptions::new(8, true, IsaLevel::LATEST),
+        )
+        .bytecode
+        .expect("code should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.set_args(vec!["7".to_string(), "x".to_string()]);
+        assert_eq!(vm.run().ok(), Some(Value::Int(14)));
+        assert_eq!(vm.heap_size(), 4);
+        vm.reset();
+        assert_eq!(vm.heap_size(), 0);
+        assert!(vm.is_precompiled());
+        for _ in 0..2 {
+            assert_eq!(vm.run().ok(), Some(Value::Int(14)));
+            assert_eq!(vm.heap_size(), 4);
+        }
+    }
+
+    #[test]
+    fn garbage_collection() {
+        let vm_for = |code: &str, config: VmConfig| -> Vm {
+            let bytecode: Vec<u8> = compile(parse(lex(code)), CompilerOptions::default()).bytecode.expect("code should compile");
+            let mut vm: Vm = Vm::new(bytecode);
+            vm.set_error_output(std::io::sink());
+            vm.set_config(config);
+            vm
+        };
+        // Each call makes a string that is garbage once the next call replaces it, so the heap stays small.
+        let mut vm: Vm = vm_for("fn f(n: int) -> int { string s = to_string(n); f(n + len(s)) } f(0)", VmConfig::default());
+        assert_eq!(vm.run_with_fuel(200_000).ok(), Some(RunStatus::OutOfFuel));
+        assert!(vm.heap_size() <= 2048, "{} objects are on the heap", vm.heap_size());
+
+        // The strings of every active call are still reachable, so they can't be freed.
+        let config: VmConfig = VmConfig { max_heap_objects: 100, ..VmConfig::default() };
+        let mut vm: Vm = vm_for("fn f(n: int) -> int { string s = to_string(n); f(n + 1) + len(s) } f(0)", config);
+        assert_eq!(
+            all_to_string(&vm.run().err().unwrap_or_default())[0],
+            "error: the program ran out of memory, as more than 100 objects were on the heap at once."
         );
+        assert_eq!(vm.heap_size(), 101);
     }
 
     #[test]
-    fn open_left_paren() {
+    fn host_functions() {
+        test_code("int r = random_int(-3, 5); clamp(r, -3, 5) == r", &["true".to_string()], &Vec::new());
+        test_code("random_int(7, 7) + random_int(-2147483648, 2147483647) * 0", &["7".to_string()], &Vec::new());
+        test_code("now_millis() >= 0", &["true".to_string()], &Vec::new());
         test_code(
-            "(",
+            "int a = 5;\nrandom_int(a, 3)",
             &Vec::new(),
-            &[
-                "error (line 1:2): unexpected end of file.".to_string(),
-                "error (line 1:2): expected \')\' following \'(\'.".to_string(),
-                "error: could not compile due to errors.".to_string(),
-            ],
+            &["error (line 2:1): the host function \"random_int\" failed: the lower bound 5 is greater than the upper bound 3."
+                .to_string()],
         );
-    }
 
-    #[test]
-    fn open_right_paren() {
+        let bytecode: Vec<u8> = compile(parse(lex("random_int(4, 2) + now_millis()")), CompilerOptions::new(8, true, IsaLevel::LATEST))
+            .bytecode
+            .expect("code should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.register_host_function("random_int", |args: &[i32]| Ok(args[0] * 100 + args[1]));
+        vm.register_host_function("now_millis", |_args: &[i32]| Ok(10));
+        assert_eq!(vm.run().ok(), Some(Value::Int(412)));
+        vm.register_host_function("now_millis", |_args: &[i32]| Err("the clock is unavailable.".to_string()));
+        assert_eq!(
+            all_to_string(&vm.run().err().unwrap_or_default()),
+            ["error (line 1:20): the host function \"now_millis\" failed: the clock is unavailable.".to_string()]
+        );
+
+        let code: &str = "extern fn scale(x: int, factor: int = 10) -> int;\nfn twice(x: int) -> int { scale(x, 2) }\ntwice(scale(4))";
+        let bytecode: Vec<u8> = compile(parse(lex(code)), CompilerOptions::new(8, true, IsaLevel::LATEST))
+            .bytecode
+            .expect("code should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.register_host_function("scale", |args: &[i32]| Ok(args[0] * args[1]));
+        assert_eq!(vm.run().ok(), Some(Value::Int(80)));
         test_code(
-            ")",
+            "extern fn missing(x: int) -> int;\nmissing(1)",
+            &Vec::new(),
+            &["error (line 2:1): the host function \"missing\" has not been registered with the VM.".to_string()],
+        );
+        test_code(
+            "extern fn f(s: string) -> int;",
             &Vec::new(),
             &[
-                "error (line 1:1): unexpected token \")\".".to_string(),
-                "error (line 1:2): unexpected end of file.".to_string(),
+                "error (line 1:11): the host function \"f\" can only take up to 255 ints and must return an int.".to_string(),
                 "error: could not compile due to errors.".to_string(),
             ],
         );
-    }
-
-    #[test]
-    fn empty_parens() {
         test_code(
-            "()",
+            "extern fn f(x: int) -> int;\nf(true)",
             &Vec::new(),
             &[
-                "error (line 1:2): expected expression within parentheses.".to_string(),
+                "error (line 2:1): the function \"f\" has no definition over the type \"bool\".".to_string(),
+                "note (line 2:1): the function \"f\" takes arguments of the type \"int\".".to_string(),
                 "error: could not compile due to errors.".to_string(),
             ],
         );
-    }
-
-    #[test]
-    fn bad_type_unary() {
         test_code(
-            "!1",
+            "extern f(x: int) -> int;\n1",
             &Vec::new(),
             &[
-                "error (line 1:1): the operator \"!\" has no definition over the type \"int\"."
-                    .to_string(),
+                "error (line 1:8): expected \"fn\" in function declaration.".to_string(),
                 "error: could not compile due to errors.".to_string(),
             ],
         );
+
+        let mut bytecode: Vec<u8> = vec![8, 1, IsaLevel::LATEST as u8, 0, 0, OpCode::CallHost as u8, 0, 3, 0, 0, 0];
+        bytecode.extend_from_slice(b"abc");
+        bytecode.extend_from_slice(&[OpCode::PrintInt as u8, OpCode::LineTable as u8, 1, 0, 0, 0]);
+        bytecode.extend_from_slice(&[5, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            all_to_string(&krust::vm::run(&bytecode).1),
+            ["error (line 1:1): the host function \"abc\" has not been registered with the VM.".to_string()]
+        );
     }
 
     #[test]
-    fn bad_types_binary() {
+    fn file_access() {
         test_code(
-            "1+true",
+            "string s = \"a.txt\";\nlen(read_file(s))",
             &Vec::new(),
-            &["error (line 1:2): the operator \"+\" has no definition over the types \"int\" and \"bool\".".to_string(),
-                "error: could not compile due to errors.".to_string()],
+            &["error (line 2:5): file access is disabled for this program.".to_string()],
         );
+
+        let path: String = std::env::temp_dir()
+            .join(format!("krust_file_access_{}.txt", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let code: String = format!("string p = {path:?}; write_file(p, \"a \\\"b\\\"\"); read_file(p)");
+        let bytecode: Vec<u8> = compile(parse(lex(&code)), CompilerOptions::new(8, true, IsaLevel::LATEST))
+            .bytecode
+            .expect("code should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.set_file_access(true);
+        assert_eq!(vm.run().ok(), Some(Value::String("a \"b\"".to_string())));
+        std::fs::remove_file(&path).expect("the file was just written");
+
+        let bytecode: Vec<u8> = compile(parse(lex(&format!("read_file({:?})", path + ".missing"))), CompilerOptions::new(8, true, IsaLevel::LATEST))
+            .bytecode
+            .expect("code should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.set_file_access(true);
+        let logs: Vec<String> = all_to_string(&vm.run().err().unwrap_or_default());
+        assert_eq!(logs.len(), 1);
+        assert!(logs[0].starts_with("error (line 1:1): could not read the file \""), "{logs:?}");
     }
 
     #[test]
-    fn statements_no_output_test() {
-        test_code(
-            "true;",
-            &Vec::new(),
-            &Vec::new(),
-        );
+    fn number_formats() {
+        assert_eq!(NumberFormat::GROUPED.number(1234), "1234");
+        assert_eq!(NumberFormat::GROUPED.number(12_345), "12,345");
+        assert_eq!(NumberFormat::GROUPED.number(1_234_567), "1,234,567");
+        assert_eq!(NumberFormat::GROUPED.bound(2048), "2048 (0x800)");
+        assert_eq!(NumberFormat::GROUPED.bound(65_535), "65,535 (0xffff)");
+        assert_eq!(NumberFormat::GROUPED.bound(0x8000_0000), "2,147,483,648 (0x8000_0000)");
+        assert_eq!(NumberFormat::GROUPED.bound(10_000), "10,000");
+        assert_eq!(NumberFormat::GROUPED.bound(255), "255");
+        assert_eq!(NumberFormat::PLAIN.bound(0x8000_0000), "2147483648");
+        let underscores: NumberFormat = NumberFormat { group_separator: Some('_'), hex_alternates: false };
+        assert_eq!(underscores.bound(1 << 16), "65_536");
+        assert_eq!(NumberFormat::from_name("plain"), Some(NumberFormat::PLAIN));
+        assert_eq!(NumberFormat::from_name("local"), None);
+
+        // Each log is written in the format it is rendered with, whatever other logs were rendered with.
+        let log: Log = Log { log_type: LogType::Error(ErrorType::OutOfMemory(65_536)), line_and_col: None, span: None, notes: Vec::new() };
+        let plain: RenderOptions = RenderOptions { numbers: NumberFormat::PLAIN, ..RenderOptions::default() };
+        assert!(log.render(None, plain).contains(" 65536 "), "{}", log.render(None, plain));
+        assert!(log.render(None, RenderOptions::default()).contains(" 65,536 "));
+        assert!(log.to_json(None, NumberFormat::PLAIN).contains(" 65536 "));
+        assert_eq!(log.message(), log.message_with(NumberFormat::GROUPED));
     }
+
     #[test]
-    fn statement_and_expression() {
-        test_code(
-            "true; false",
-            &["false".to_string()],
-            &Vec::new(),
+    fn json_events() {
+        assert_eq!(
+            Event::StageStarted { file: "dir\\\"a\".txt".to_string(), stage: Stage::Lex }.to_json(),
+            "{\"event\":\"stage_started\",\"file\":\"dir\\\\\\\"a\\\".txt\",\"stage\":\"lex\"}"
+        );
+        assert_eq!(
+            Event::StageFinished {
+                file: "a.txt".to_string(),
+                stage: Stage::InternalChecks,
+                duration: Duration::from_micros(12_500),
+            }
+            .to_json(),
+            "{\"event\":\"stage_finished\",\"file\":\"a.txt\",\"stage\":\"internal_checks\",\"duration_ms\":12}"
+        );
+        assert_eq!(
+            Event::ArtifactWritten { file: "a.txt".to_string(), path: "a\n.exe".to_string() }.to_json(),
+            "{\"event\":\"artifact_written\",\"file\":\"a.txt\",\"path\":\"a\\n.exe\"}"
         );
+        let logs: Vec<Log> = compile(parse(lex("int a; 1 + true")), CompilerOptions::new(8, true, IsaLevel::V2)).logs;
+        assert_eq!(
+            Event::diagnostics("a.txt", &logs).to_json(),
+            "{\"event\":\"diagnostics\",\"file\":\"a.txt\",\"errors\":1,\"warnings\":0,\"total\":2}"
+        );
+        assert_eq!(EventStream::from_name("stdout"), Some(EventStream::Stdout));
+        assert_eq!(EventStream::from_name("file"), None);
+
+        // The lines printed by --verbose.
+        let finished: Event = Event::StageFinished { file: "a.txt".to_string(), stage: Stage::Compile, duration: Duration::from_micros(1500) };
+        assert_eq!(finished.to_text(), "a.txt: compile took 1.500ms");
+        assert_eq!(Event::diagnostics("a.txt", &logs).to_text(), "a.txt: 1 error, 0 warnings, 2 logs in total");
     }
 
     #[test]
-    fn chained_assignments() {
-        test_code(
-            format!("int a = int b = 1; a").as_str(),
-            &["1".to_string()],
-            &Vec::new(),
+    fn exit_statuses() {
+        let options: CompilerOptions = CompilerOptions::new(8, true, IsaLevel::LATEST);
+        let codes: Vec<i32> = [ExitStatus::Success, ExitStatus::CompileError, ExitStatus::RuntimeError, ExitStatus::UsageError].map(ExitStatus::code).to_vec();
+        assert_eq!(codes, [0, 1, 2, 3]);
+
+        assert_eq!(ExitStatus::from_logs(&compile(parse(lex("1 == 1")), options).logs), ExitStatus::Success);
+        assert_eq!(ExitStatus::from_logs(&compile(parse(lex("int x = true;")), options).logs), ExitStatus::CompileError);
+        let bytecode: Vec<u8> = compile(parse(lex("list<int> {1}[2]")), options).bytecode.expect("the program should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.set_error_output(std::io::sink());
+        assert_eq!(ExitStatus::from_logs(&vm.run().err().unwrap_or_default()), ExitStatus::RuntimeError);
+        assert_eq!(ExitStatus::from_logs(&verify(&[1, 2, 3]).logs), ExitStatus::CompileError);
+        let error = |error_type: ErrorType| Log { log_type: LogType::Error(error_type), line_and_col: None, span: None, notes: Vec::new() };
+        assert_eq!(ExitStatus::from_logs(&[error(ErrorType::CLIUnrecognizedArg("--x".to_string()))]), ExitStatus::UsageError);
+        assert_eq!(ExitStatus::from_logs(&[error(ErrorType::ConfigInvalidLine("krust.toml".to_string(), 1))]), ExitStatus::UsageError);
+        assert_eq!(
+            ExitStatus::from_logs(&[error(ErrorType::DeniedWarning(WarningType::CLITargetLargerThanMachine(128)))]),
+            ExitStatus::UsageError
         );
+        assert_eq!(ExitStatus::from_logs(&[error(ErrorType::DeniedWarning(WarningType::RedundantParens))]), ExitStatus::CompileError);
     }
 
     #[test]
-    fn assignments_as_values() {
-        test_code(
-            format!("(int a = 6) * a").as_str(),
-            &["36".to_string()],
-            &Vec::new(),
-        );
+    fn command_line() {
+        let args = |args: &[&str]| args.iter().map(|arg| (*arg).to_string()).collect::<Vec<String>>();
+        assert!(matches!(parse_args(args(&["--help"])), (Some(Invocation::Help(None)), _)));
+        assert!(matches!(parse_args(args(&["build", "-h"])), (Some(Invocation::Help(Some(Command::Build))), _)));
+        assert!(matches!(parse_args(args(&["-V", "--color=purple"])), (Some(Invocation::Version), _)));
+        assert!(matches!(parse_args(args(&["completions", "fish"])), (Some(Invocation::Completions(Shell::Fish)), _)));
+        let (invocation, logs) = parse_args(args(&["completions", "tcsh"]));
+        assert!(invocation.is_none());
+        assert_eq!(all_to_string(&logs), ["error: command \"completions\" requires a shell from \"bash\", \"zsh\", or \"fish\"."]);
+
+        // The help flag belongs to the program after "--", and invalid values give the same errors as before.
+        let (invocation, logs) = parse_args(args(&["snapshots/arithmetic.txt", "--color=purple", "--", "--help"]));
+        assert!(invocation.is_none());
+        assert_eq!(all_to_string(&logs), ["error: compiler flag \"--color\" requires one of \"auto\", \"always\", or \"never\"."]);
+        // Flags that take one of a list of values say so with "or".
+        for (flag, message) in [
+            ("--target=jvm", "compiler flag \"--target\" requires a target from \"bytecode\" or \"wasm\"."),
+            ("--encoding=x", "compiler flag \"--encoding\" requires an encoding from \"stack\" or \"register\"."),
+        ] {
+            let (_, logs) = read_args(args(&["snapshots/arithmetic.txt", flag]));
+            assert!(all_to_string(&logs).contains(&format!("error: {message}")), "{flag}");
+        }
+        let (options, logs) = read_args(args(&["check", "snapshots/arithmetic.txt", "-O1", "--quiet"]));
+        let options = options.expect("the arguments are valid");
+        assert!(logs.is_empty());
+        assert_eq!((options.command, options.min_severity), (Command::Check, Severity::Error));
+        assert!(options.passes.is_enabled(CompilerPass::ConstantFolding) && !options.passes.is_enabled(CompilerPass::TailCalls));
+        assert_eq!(all_to_string(&read_args(Vec::new()).1), all_to_string(&[Log { log_type: LogType::Error(ErrorType::CLINoArgs), line_and_col: None, span: None, notes: Vec::new() }]));
+
+        // The built program can't overwrite an input, must be recognizable as a program, and isn't taken from a flag.
+        for (output, message) in [
+            (&["-o", "./snapshots/arithmetic.txt"][..], "the output path \"./snapshots/arithmetic.txt\" is one of the input files, which would be overwritten."),
+            (&["--output=out.bin"], "the output path \"out.bin\" should end in \".krustc\", so that krust recognizes the program it holds."),
+            (&["-o", "--quiet"], "compiler flag \"-o\" requires an argument."),
+        ] {
+            let (options, logs) = read_args([&["build", "snapshots/arithmetic.txt"][..], output].concat().iter().map(|arg| (*arg).to_string()).collect());
+            assert!(options.is_none());
+            assert_eq!(all_to_string(&logs), [format!("error: {message}")], "{output:?}");
+        }
+        let (options, _) = read_args(args(&["build", "snapshots/arithmetic.txt", "--target=wasm", "-o", "out/app.wasm"]));
+        assert_eq!(options.expect("the arguments are valid").output.as_deref(), Some("out/app.wasm"));
+
+        let text: String = help(None);
+        for flag in FLAGS {
+            assert!(text.contains(&format!("  {}{} ", flag.name, flag.value)), "{} isn't in the help", flag.name);
+        }
+        assert!(Command::ALL.iter().all(|command| text.contains(&format!("  {} ", command.name()))));
+        assert!(help(Some(Command::Doctest)).contains("Usage: krust doctest <FILE.md> [FLAGS]"));
+        assert!(help(Some(Command::Run)).contains("Usage: krust run <FILE.txt|FILE.krustc>... [FLAGS]"));
+        for shell in Shell::ALL {
+            let script: String = completions(shell);
+            assert!(script.contains("crashcheck") && script.contains("sandbox"), "{} completions are missing words", shell.name());
+        }
+        assert!(completions(Shell::Bash).contains(" --emit=disasm ") && completions(Shell::Zsh).contains(" -O2 "));
+        assert!(completions(Shell::Fish).contains("complete -c krust -l target -r -a 'bytecode wasm'"));
     }
 
     #[test]
-    fn vars_of_same_name() {
-        test_code(
-            format!("int var = 1; bool var = true; var").as_str(),
-            &["true".to_string()],
-            &Vec::new(),
+    fn embedding() {
+        let options: CompilerOptions = CompilerOptions::default();
+        assert!(matches!(run_source("int x = 6 * 7;", options), Ok(Value::Void)));
+        let program: Program = compile_source("int x = 2;\nbool b = x == x;\nx * 21", options).expect("the program should compile");
+        assert!(!program.warnings.has_errors());
+        assert_eq!(
+            all_to_string(&program.warnings.logs),
+            ["warning (line 2:12): comparing a value with itself using \"==\" is always true; this may be a typo."]
         );
+        let mut vm: Vm = program.vm();
+        vm.set_output(std::io::sink());
+        assert_eq!(vm.run().ok(), Some(Value::Int(42)));
+
+        let Err(diagnostics) = compile_source("int x = true;\nx", options) else { panic!("the program shouldn't compile") };
+        assert!(diagnostics.has_errors());
+        assert_eq!(diagnostics.source(), "int x = true;\nx");
+        assert_eq!(diagnostics.errors().count(), 1);
+        assert!(diagnostics.to_string().contains("int x = true;"), "the error should show its line: {diagnostics}");
+        let Err(diagnostics) = compile_source("int x = 1;\nlen(list<int> {}) / 0", options) else { panic!("the divisor is zero") };
+        assert_eq!(all_to_string(&diagnostics.logs), ["error (line 2:19): the divisor is always zero, so this would always fail."]);
     }
 
     #[test]
-    fn chained_vars_of_same_name() {
-        test_code(
-            format!("int var = 1; int var = var + 2; var").as_str(),
-            &["3".to_string()],
-            &Vec::new(),
-        );
+    fn compiler_options_builder() {
+        let options: CompilerOptions = CompilerOptions::default()
+            .with_ptr_size(4)
+            .with_endianness(Endianness::Big)
+            .with_isa_level(IsaLevel::V1)
+            .with_detailed_errors(false)
+            .with_opt_level(OptLevel::O0)
+            .with_max_bytecode_size(1024);
+        let mut expected: CompilerOptions = CompilerOptions::new(4, false, IsaLevel::V1);
+        expected.target.endianness = Endianness::Big;
+        expected.opt_level = OptLevel::O0;
+        expected.max_bytecode_size = Some(1024);
+        assert_eq!(options, expected);
+        assert!(options.validate().is_ok());
+
+        let options: CompilerOptions = CompilerOptions::default().with_ptr_size(0);
+        assert_eq!(all_to_string(&options.validate().err().unwrap_or_default()), ["error: the target's pointers must be at least one byte."]);
+        let output: CompilerOutput = compile(parse(lex("1 + 2")), options);
+        assert!(output.bytecode.is_none());
+        assert_eq!(output.logs.len(), 1);
     }
 
     #[test]
-    fn no_declaration() {
-        test_code(
-            format!("var = 1;").as_str(),
-            &Vec::new(),
-            &[
-                "error (line 1:1): expected a variable declaration for var".to_string(),
-                "error: could not compile due to errors.".to_string(),
-            ],
+    fn visitors() {
+        // Counts the int literals, skipping the bodies of functions.
+        struct LiteralCounter(usize);
+        impl ExpressionVisitor for LiteralCounter {
+            fn visit_expression(&mut self, expr: &Expression) {
+                match expr {
+                    Expression::Literal { token, .. } if matches!(token.token_type, TokenType::IntLiteral(_)) => self.0 += 1,
+                    Expression::FunctionDeclaration { .. } => {}
+                    _ => walk(self, expr),
+                }
+            }
+        }
+        // Adds one to each int literal.
+        struct Incrementer;
+        impl ExpressionFolder for Incrementer {
+            fn fold_expression(&mut self, expr: Expression) -> Expression {
+                match expr {
+                    Expression::Literal { mut token, expr_type } => {
+                        if let TokenType::IntLiteral(value) = token.token_type {
+                            token.token_type = TokenType::IntLiteral(value + 1);
+                        }
+                        Expression::Literal { token, expr_type }
+                    }
+                    expr => fold_children(self, expr),
+                }
+            }
+        }
+
+        let code: &str = "fn f(a: int) -> int { a * 10 }\nint x = 2;\nf(x + 3) + list<int> {4, 5}[0]";
+        let parser_output: ParserOutput = parse(lex(code));
+        let mut counter: LiteralCounter = LiteralCounter(0);
+        counter.visit_expression(&parser_output.expr);
+        assert_eq!(counter.0, 5);
+        assert!(children(&parser_output.expr).len() >= 3);
+
+        let expr: Expression = Incrementer.fold_expression(parser_output.expr);
+        let options: CompilerOptions = CompilerOptions::new(8, true, IsaLevel::LATEST);
+        let folded: ParserOutput = ParserOutput { file_text: parser_output.file_text, expr, logs: parser_output.logs };
+        let bytecode: Vec<u8> = compile(folded, options).bytecode.expect("the folded program should compile");
+        let mut vm: Vm = Vm::new(bytecode);
+        vm.set_output(std::io::sink());
+        // f(3 + 4) is 7 * 11, and the list is {5, 6} indexed at 1.
+        assert_eq!(vm.run().ok(), Some(Value::Int(83)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let code: &str = "fn f(a: int) -> int { a * 2 }\nlist<int> l = list<int> {f(1), 3};\nstring s = \"hi\";\nl[0] + len(s)";
+        let lex_output: LexerOutput = lex(code);
+        let json: String = serde_json::to_string(&lex_output.tokens).expect("tokens should serialize");
+        assert_eq!(serde_json::from_str::<Vec<krust::lexer::Token>>(&json).ok(), Some(lex_output.tokens.clone()));
+
+        let parser_output: ParserOutput = parse(lex_output);
+        let json: String = serde_json::to_string(&parser_output.expr).expect("the AST should serialize");
+        let expr: Expression = serde_json::from_str(&json).expect("the AST should deserialize");
+        assert_eq!(format!("{expr:?}"), format!("{:?}", parser_output.expr));
+        let list_type: krust::parser::Type = krust::parser::Type::List(Box::new(krust::parser::Type::Int));
+        let json: String = serde_json::to_string(&list_type).expect("types should serialize");
+        assert_eq!(serde_json::from_str::<krust::parser::Type>(&json).ok(), Some(list_type));
+
+        let logs: Vec<Log> = compile(parse(lex("int x = true;\nx + \"a\"")), CompilerOptions::default()).logs;
+        assert!(!logs.is_empty());
+        let json: String = serde_json::to_string(&logs).expect("logs should serialize");
+        assert!(serde_json::from_str::<Vec<Log>>(&json).is_ok_and(|round_trip| round_trip == logs));
+    }
+
+    #[test]
+    fn interpreter() {
+        let programs: [&str; 14] = [
+            "fn twice(n: int) -> int { n * 2 }\ntwice(2147483647) + (-7 / 2) + (-7 % 2) + (1 << 33) + (-8 >> 40)",
+            "pow(-1, -3) + pow(3, 4) + sqrt(50) + abs(-2147483648) + clamp(9, 5, 2) + min(3, -3) + max(3, -3)",
+            "list<int> l = list<int> {3, 1};\nlist<int> other = l;\npush(other, 2);\npop(l);\nl",
+            "map[int]bool m = map[int]bool {3: true, -1: false};\ninsert(m, 2, true);\nremove(m, 3);\nm",
+            "map[bool]int m = map[bool]int {true: 1};\nget(m, false)",
+            "string s = \"héllo\";\nsubstring(s, 1, 4)",
+            "list<string> {to_string(-5), \"a\"}",
+            "fn half(r: result<int>) -> result<int> { ok(r? / 2) }\nhalf(parse_int(\"x\"))",
+            "trait Size { fn size(self) -> int; }\nimpl Size for bool { fn size(self) -> int { 1 } }\n\
+                fn total<T: Size>(x: T) -> int { x.size() * 10 }\ntotal(true)",
+            "fn f(n: int) -> int { list<int> {1}[n] }\nfn g(n: int) -> int { f(n) + 1 }\ng(0) + g(3)",
+            "int? x = null;\nx!",
+            "fn count(n: int) -> int { int x = 1000 / (5000 - n); count(n + 1) }\ncount(0)",
+            "assert(1 > 2, \"no\")",
+            "fn check(n: int) -> int { assert(n < 3, \"too big\"); n }\ncheck(1) + check(5)",
+        ];
+        for code in programs {
+            if let Err(mismatch) = compare(code, CompilerOptions::default()) {
+                panic!("{mismatch}");
+            }
+        }
+        // Deep recursion stops at the call depth limit in both, rather than overflowing the interpreter's stack.
+        let execution: Execution = compare("fn f(n: int) -> int { f(n + 1) + 1 }\nf(0)", CompilerOptions::default())
+            .unwrap_or_else(|mismatch| panic!("{mismatch}"));
+        assert_eq!(execution.errors.len(), 19);
+
+        let out_err = run(
+            &FileInput::FileText("int x = 3;\nx * 2;\nfn f(a: int) -> int { a + 1 }\nf(x)".to_string()),
+            CompilerOptions::default(),
+            Severity::Info,
+            &RunOptions { script: true, interpret: true, ..RunOptions::default() },
         );
+        assert_eq!(out_err.0, ["6", "4"].map(ToString::to_string));
+        assert!(out_err.1.is_empty());
     }
 
     proptest! {
+        #[test]
+        fn differential(code in program(3)) {
+            if let Err(mismatch) = compare(&code, CompilerOptions::default()) {
+                prop_assert!(false, "{}", mismatch);
+            }
+        }
+
         #[test]
         fn random_int(value in proptest::num::i32::ANY) {
             test_code(
@@ -312,6 +4637,15 @@ mod tests {
             );
         }
 
+        #[test]
+        fn var_nullable_int(value in proptest::num::i32::ANY) {
+            test_code(
+                format!("int? var = {value}; var! == {value}").as_str(),
+                &["true".to_string()],
+                &Vec::new()
+            );
+        }
+
         #[test]
         fn int_to_bool(value in proptest::num::i32::ANY) {
             test_code(
@@ -325,35 +4659,32 @@ mod tests {
         fn bool_to_int(value in proptest::bool::ANY) {
             test_code(
                 format!("(int) {value}").as_str(),
-                &[format!("{}", value as i32)],
+                &[format!("{}", i32::from(value))],
                 &Vec::new()
             );
         }
 
         #[test]
         fn add_ints(a in proptest::num::i32::ANY, b in proptest::num::i32::ANY) {
-            test_code(
+            test_wrapping_code(
                 format!("{a}+{b}").as_str(),
-                &[format!("{}", i32::wrapping_add(a, b))],
-                &Vec::new()
+                &[format!("{}", i32::wrapping_add(a, b))]
             );
         }
 
         #[test]
         fn sub_ints(a in proptest::num::i32::ANY, b in proptest::num::i32::ANY) {
-            test_code(
+            test_wrapping_code(
                 format!("{a}-{b}").as_str(),
-                &[format!("{}", i32::wrapping_sub(a, b))],
-                &Vec::new()
+                &[format!("{}", i32::wrapping_sub(a, b))]
             );
         }
 
         #[test]
         fn mul_ints(a in proptest::num::i32::ANY, b in proptest::num::i32::ANY) {
-            test_code(
+            test_wrapping_code(
                 format!("{a}*{b}").as_str(),
-                &[format!("{}", i32::wrapping_mul(a, b))],
-                &Vec::new()
+                &[format!("{}", i32::wrapping_mul(a, b))]
             );
         }
 
@@ -366,10 +4697,9 @@ mod tests {
                 |b| *b != 0
             )
         ) {
-            test_code(
+            test_wrapping_code(
                 format!("{a}/{b}").as_str(),
-                &[format!("{}", i32::wrapping_div(a, b))],
-                &Vec::new()
+                &[format!("{}", i32::wrapping_div(a, b))]
             );
         }
 
@@ -378,7 +4708,10 @@ mod tests {
             test_code(
                 format!("{a}/0").as_str(),
                 &Vec::new(),
-                &[format!("error (line 1:{}): division by zero.", format!("{a}").chars().count() + 1)]
+                &[
+                    format!("error (line 1:{}): this constant expression divides by zero, which would always fail.", format!("{a}").chars().count() + 1),
+                    "error: could not compile due to errors.".to_string(),
+                ]
             );
         }
 
@@ -403,7 +4736,10 @@ mod tests {
             test_code(
                 format!("{a}%0").as_str(),
                 &Vec::new(),
-                &[format!("error (line 1:{}): division by zero.", format!("{a}").chars().count() + 1)]
+                &[
+                    format!("error (line 1:{}): this constant expression divides by zero, which would always fail.", format!("{a}").chars().count() + 1),
+                    "error: could not compile due to errors.".to_string(),
+                ]
             );
         }
 
@@ -412,7 +4748,7 @@ mod tests {
             test_code(
                 format!("{a}<{a}").as_str(),
                 &["false".to_owned()],
-                &Vec::new()
+                &self_comparison_warning(&a.to_string(), "<", false)
             );
         }
 
@@ -431,7 +4767,7 @@ mod tests {
             test_code(
                 format!("{a}<={a}").as_str(),
                 &["true".to_owned()],
-                &Vec::new()
+                &self_comparison_warning(&a.to_string(), "<=", true)
             );
         }
 
@@ -450,7 +4786,7 @@ mod tests {
             test_code(
                 format!("{a}>{a}").as_str(),
                 &["false".to_owned()],
-                &Vec::new()
+                &self_comparison_warning(&a.to_string(), ">", false)
             );
         }
 
@@ -469,7 +4805,7 @@ mod tests {
             test_code(
                 format!("{a}>={a}").as_str(),
                 &["true".to_owned()],
-                &Vec::new()
+                &self_comparison_warning(&a.to_string(), ">=", true)
             );
         }
 
@@ -578,7 +4914,7 @@ mod tests {
             test_code(
                 format!("{a}=={a}").as_str(),
                 &["true".to_owned()],
-                &Vec::new()
+                &self_comparison_warning(&a.to_string(), "==", true)
             );
         }
 
@@ -597,7 +4933,7 @@ mod tests {
             test_code(
                 format!("{a}=={a}").as_str(),
                 &["true".to_owned()],
-                &Vec::new()
+                &self_comparison_warning(&a.to_string(), "==", true)
             );
         }
 
@@ -617,7 +4953,7 @@ mod tests {
             test_code(
                 format!("{a}!={a}").as_str(),
                 &["false".to_owned()],
-                &Vec::new()
+                &self_comparison_warning(&a.to_string(), "!=", false)
             );
         }
 
@@ -636,7 +4972,7 @@ mod tests {
             test_code(
                 format!("{a}!={a}").as_str(),
                 &["false".to_owned()],
-                &Vec::new()
+                &self_comparison_warning(&a.to_string(), "!=", false)
             );
         }
 
@@ -668,10 +5004,9 @@ mod tests {
             b in proptest::num::i32::ANY,
             c in proptest::num::i32::ANY
         ) {
-            test_code(
+            test_wrapping_code(
                 format!("{a}+{b}*{c}").as_str(),
-                &[format!("{}", i32::wrapping_add(a, i32::wrapping_mul(b, c)))],
-                &Vec::new()
+                &[format!("{}", i32::wrapping_add(a, i32::wrapping_mul(b, c)))]
             );
         }
 
@@ -695,10 +5030,9 @@ mod tests {
             b in proptest::num::i32::ANY,
             c in proptest::num::i32::ANY
         ) {
-            test_code(
+            test_wrapping_code(
                 format!("{a} & {b} + {c}").as_str(),
-                &[format!("{}", a  & i32::wrapping_add(b, c))],
-                &Vec::new()
+                &[format!("{}", a  & i32::wrapping_add(b, c))]
             );
         }
 
@@ -711,7 +5045,7 @@ mod tests {
         ) {
             test_code(
                 format!("{a} | {b} ^ {c} & {d}").as_str(),
-                &[format!("{}", a | (b ^ (c & d)))],
+                &[format!("{}", a || (b ^ (c & d)))],
                 &Vec::new()
             );
         }
@@ -723,10 +5057,9 @@ mod tests {
             b in proptest::num::i32::ANY,
             c in proptest::num::i32::ANY
         ) {
-            test_code(
+            test_wrapping_code(
                 format!("{a}*({b}+{c})").as_str(),
-                &[format!("{}", i32::wrapping_mul(a, i32::wrapping_add(b, c)))],
-                &Vec::new()
+                &[format!("{}", i32::wrapping_mul(a, i32::wrapping_add(b, c)))]
             );
         }
     }
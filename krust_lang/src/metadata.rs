@@ -0,0 +1,145 @@
+//! The module for the metadata in the bytecode header, which records how a program was built so that bug reports
+//! about compiled programs can be traced back to the compiler and options that produced them.
+
+use crate::{compiler, pass_manager, util::log::DiagnosticConfig};
+use compiler::{
+    CompilerOptions, Endianness, IsaLevel, Target, BIG_ENDIAN_FLAG, DETAILED_ERRORS_FLAG,
+    HEADER_SIZE,
+};
+use pass_manager::{CompilerPass, OptLevel, PassKind, PassManager};
+
+use num_traits::FromPrimitive;
+
+/// How a program was built.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Metadata {
+    /// The version of krust that compiled the program.
+    pub compiler_version: String,
+    /// The options that the program was compiled with.
+    pub options: CompilerOptions,
+    /// The names of the optimization passes that ran, in order.
+    pub optimizations: Vec<String>,
+    /// The FNV-1a hash of the source code.
+    pub source_hash: u64,
+}
+
+impl Metadata {
+    /// Creates the metadata for a program being compiled by this version of krust.
+    #[must_use]
+    pub fn new(source: &str, options: CompilerOptions, passes: &PassManager) -> Self {
+        Self {
+            compiler_version: env!("CARGO_PKG_VERSION").to_string(),
+            options,
+            optimizations: CompilerPass::ALL
+                .into_iter()
+                .filter(|pass| pass.kind() == PassKind::Optimization && passes.is_enabled(*pass))
+                .map(|pass| pass.name().to_string())
+                .collect(),
+            source_hash: hash_source(source),
+        }
+    }
+
+    /// Reads the metadata from the header of the bytecode. Returns None if the bytecode has no metadata or it can't
+    /// be read.
+    #[must_use]
+    pub fn read(bytecode: &[u8]) -> Option<Self> {
+        let length: usize = usize::from(u16::from_le_bytes([*bytecode.get(3)?, *bytecode.get(4)?]));
+        Self::decode(bytecode.get(HEADER_SIZE..HEADER_SIZE + length)?)
+    }
+
+    /// Converts the metadata to bytes. Strings are preceded by their length as a single byte, the options are a byte
+    /// each, and the list of optimizations is preceded by the number of optimizations.
+    ///
+    /// # Panics
+    /// Panics if a string is longer than 255 bytes or there are more than 255 optimizations.
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        push_string(&mut bytes, &self.compiler_version);
+        bytes.extend_from_slice(&[
+            self.options.target.ptr_size,
+            self.options.header_flags(),
+            self.options.target.features as u8,
+            self.options.opt_level as u8,
+        ]);
+        bytes.push(u8::try_from(self.optimizations.len()).expect("there are less than 256 passes"));
+        for optimization in &self.optimizations {
+            push_string(&mut bytes, optimization);
+        }
+        bytes.extend_from_slice(&self.source_hash.to_le_bytes());
+        bytes
+    }
+
+    /// Reads metadata from bytes written by `encode`. Returns None if the bytes aren't valid metadata.
+    #[must_use]
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut index: usize = 0;
+        let compiler_version: String = read_string(bytes, &mut index)?;
+        let options: CompilerOptions = decode_options(bytes.get(index..index + 4)?)?;
+        index += 4;
+        let count: u8 = *bytes.get(index)?;
+        index += 1;
+        let optimizations: Vec<String> = (0..count)
+            .map(|_| read_string(bytes, &mut index))
+            .collect::<Option<Vec<String>>>()?;
+        let source_hash: u64 = u64::from_le_bytes(bytes.get(index..index + 8)?.try_into().ok()?);
+        (index + 8 == bytes.len()).then_some(Self {
+            compiler_version,
+            options,
+            optimizations,
+            source_hash,
+        })
+    }
+}
+
+/// Hashes source code with 64-bit FNV-1a, which gives the same hash on every machine and version of Rust.
+#[must_use]
+pub fn hash_source(source: &str) -> u64 {
+    hash_bytes(source.as_bytes())
+}
+
+/// Hashes bytes with 64-bit FNV-1a, like `hash_source`. `--emit=hash` uses this to hash compiled program files.
+#[must_use]
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash: u64, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// Reads the options from their four bytes. The second byte holds the same flags as the bytecode header.
+fn decode_options(bytes: &[u8]) -> Option<CompilerOptions> {
+    if bytes[1] & !(DETAILED_ERRORS_FLAG | BIG_ENDIAN_FLAG) != 0 {
+        return None;
+    }
+    Some(CompilerOptions {
+        target: Target {
+            ptr_size: bytes[0],
+            endianness: if bytes[1] & BIG_ENDIAN_FLAG == 0 {
+                Endianness::Little
+            } else {
+                Endianness::Big
+            },
+            features: IsaLevel::from_u8(bytes[2])?,
+        },
+        detailed_err: bytes[1] & DETAILED_ERRORS_FLAG != 0,
+        opt_level: OptLevel::from_u8(bytes[3])?,
+        // The limit isn't stored, as it never changes the bytecode.
+        max_bytecode_size: None,
+        // Neither are the warning levels, as warnings never change the bytecode either.
+        diagnostics: DiagnosticConfig::default(),
+    })
+}
+
+// Adds a string to the bytes, preceded by its length.
+fn push_string(bytes: &mut Vec<u8>, value: &str) {
+    bytes.push(u8::try_from(value.len()).expect("metadata strings are short"));
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+// Reads a string preceded by its length, moving the index past it.
+fn read_string(bytes: &[u8], index: &mut usize) -> Option<String> {
+    let length: usize = usize::from(*bytes.get(*index)?);
+    let value: &[u8] = bytes.get(*index + 1..*index + 1 + length)?;
+    *index += 1 + length;
+    String::from_utf8(value.to_vec()).ok()
+}
@@ -0,0 +1,242 @@
+//! The module for the optimizations done on the AST between parsing and generating bytecode.
+
+use crate::{lexer, parser, util::log, visit};
+use lexer::{Token, TokenType};
+use log::{ErrorType, Log, LogType, WarningType};
+use parser::{Expression, Type};
+use visit::{fold_children, ExpressionFolder};
+
+/// Evaluates the parts of the code that only use constants, so that `2 + 3 * 4` is compiled to a single `PushInt`.
+/// The results are the same as if the code ran in the VM, with ints wrapping on overflow, but overflows are warned
+/// about as they are rarely meant. Divisions and modulos whose divisor is a constant zero are reported as errors, as
+/// they would always fail at runtime.
+#[must_use]
+pub fn fold_constants(expr: &Expression, logs: &mut Vec<Log>) -> Expression {
+    ConstantFolder { logs }.fold_expression(expr.clone())
+}
+
+// Folds the constants of an AST, keeping the logs of the operations it folds.
+struct ConstantFolder<'a> {
+    logs: &'a mut Vec<Log>,
+}
+
+impl ExpressionFolder for ConstantFolder<'_> {
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        let mut fold = |expr: Box<Expression>| Box::new(self.fold_expression(*expr));
+        match expr {
+            Expression::Binary {
+                left,
+                op,
+                right,
+                expr_type,
+            } => {
+                let (left, right): (Box<Expression>, Box<Expression>) = (fold(left), fold(right));
+                fold_binary(left, op, right, expr_type, self.logs)
+            }
+            Expression::Cast { expr_type, expr } => fold_cast(fold(expr), expr_type),
+            Expression::Unary {
+                op,
+                expr,
+                expr_type,
+            } => fold_unary(op, fold(expr), expr_type),
+            Expression::Grouping { expr, expr_type } => match *fold(expr) {
+                literal @ Expression::Literal { .. } => literal,
+                expr => Expression::Grouping {
+                    expr: Box::new(expr),
+                    expr_type,
+                },
+            },
+            // Flags are replaced by their values where they are used, so their declarations are left alone.
+            Expression::FlagsDeclaration { .. } => expr,
+            expr => fold_children(self, expr),
+        }
+    }
+}
+
+// A constant value that can be written as a literal.
+#[derive(Clone, Copy)]
+enum Constant {
+    Int(i32),
+    Bool(bool),
+}
+
+impl Constant {
+    // Gets the value of an expression if it is a literal.
+    fn from_expression(expr: &Expression) -> Option<Self> {
+        let Expression::Literal { token, .. } = expr else {
+            return None;
+        };
+        match token.token_type {
+            TokenType::IntLiteral(value) => Some(Self::Int(value.cast_signed())),
+            TokenType::True => Some(Self::Bool(true)),
+            TokenType::False => Some(Self::Bool(false)),
+            _ => None,
+        }
+    }
+
+    // Creates a literal with the value, using the token of the operation that produced it for its position.
+    fn to_expression(self, mut token: Token) -> Expression {
+        let expr_type: Type = match self {
+            Self::Int(value) => {
+                token.token_type = TokenType::IntLiteral(value.cast_unsigned());
+                Type::Int
+            }
+            Self::Bool(value) => {
+                token.token_type = if value {
+                    TokenType::True
+                } else {
+                    TokenType::False
+                };
+                Type::Bool
+            }
+        };
+        Expression::Literal {
+            token,
+            expr_type: Some(expr_type),
+        }
+    }
+}
+
+// Folds a binary operation whose operands have already been folded.
+fn fold_binary(
+    left: Box<Expression>,
+    op: Token,
+    right: Box<Expression>,
+    expr_type: Option<Type>,
+    logs: &mut Vec<Log>,
+) -> Expression {
+    let value: Option<Constant> = match (
+        Constant::from_expression(&left),
+        Constant::from_expression(&right),
+    ) {
+        (Some(Constant::Int(a)), Some(Constant::Int(b))) => fold_int_binary(a, op, b, logs),
+        (None, Some(Constant::Int(0))) if matches!(op.token_type, TokenType::Slash | TokenType::Percent) => {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::DivideByConstantZero),
+                line_and_col: Some((op.line, op.col)),
+                span: Some(op.span()),
+                notes: Vec::new(),
+            });
+            None
+        }
+        (Some(Constant::Bool(a)), Some(Constant::Bool(b))) => match op.token_type {
+            TokenType::Ampersand => Some(Constant::Bool(a & b)),
+            TokenType::Bar => Some(Constant::Bool(a | b)),
+            TokenType::Caret => Some(Constant::Bool(a ^ b)),
+            TokenType::Equality => Some(Constant::Bool(a == b)),
+            TokenType::Inequality => Some(Constant::Bool(a != b)),
+            _ => None,
+        },
+        _ => None,
+    };
+    match value {
+        Some(value) => value.to_expression(op),
+        None => Expression::Binary {
+            left,
+            op,
+            right,
+            expr_type,
+        },
+    }
+}
+
+// Evaluates an operation on two ints the same way the VM does, warning if it overflows. Returns None if the operation
+// can't be folded.
+fn fold_int_binary(a: i32, op: Token, b: i32, logs: &mut Vec<Log>) -> Option<Constant> {
+    let value: Constant = match op.token_type {
+        TokenType::Slash | TokenType::Percent if b == 0 => {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::ConstantDivideByZero),
+                line_and_col: Some((op.line, op.col)),
+                span: Some(op.span()),
+                notes: Vec::new(),
+            });
+            return None;
+        }
+        TokenType::Plus => Constant::Int(a.wrapping_add(b)),
+        TokenType::Minus => Constant::Int(a.wrapping_sub(b)),
+        TokenType::Star => Constant::Int(a.wrapping_mul(b)),
+        TokenType::Slash => Constant::Int(a.wrapping_div(b)),
+        TokenType::Percent => Constant::Int(a.wrapping_rem_euclid(b)),
+        TokenType::Ampersand => Constant::Int(a & b),
+        TokenType::Bar => Constant::Int(a | b),
+        TokenType::Caret => Constant::Int(a ^ b),
+        TokenType::LeftShift => Constant::Int(shift(a, b)),
+        TokenType::RightShift => Constant::Int(shift(a, b.wrapping_neg())),
+        TokenType::Less => Constant::Bool(a < b),
+        TokenType::LessEqual => Constant::Bool(a <= b),
+        TokenType::Greater => Constant::Bool(a > b),
+        TokenType::GreaterEqual => Constant::Bool(a >= b),
+        TokenType::Equality => Constant::Bool(a == b),
+        TokenType::Inequality => Constant::Bool(a != b),
+        _ => return None,
+    };
+    if let (Constant::Int(wrapped), Some(exact)) = (value, exact_int_binary(a, op, b)) {
+        if i64::from(wrapped) != exact {
+            logs.push(Log {
+                log_type: LogType::Warning(WarningType::ConstantOverflow(exact, wrapped)),
+                line_and_col: Some((op.line, op.col)),
+                span: Some(op.span()),
+                notes: Vec::new(),
+            });
+        }
+    }
+    Some(value)
+}
+
+// Gets the result of an arithmetic operation on two ints without wrapping. Shifts and modulos never overflow, as
+// shifting bits out is what they do and the result of a modulo is smaller than the divisor.
+fn exact_int_binary(a: i32, op: Token, b: i32) -> Option<i64> {
+    let (a, b): (i64, i64) = (i64::from(a), i64::from(b));
+    match op.token_type {
+        TokenType::Plus => Some(a + b),
+        TokenType::Minus => Some(a - b),
+        TokenType::Star => Some(a * b),
+        TokenType::Slash => a.checked_div(b),
+        _ => None,
+    }
+}
+
+// Shifts an int left by b bits, or right if b is negative, the same way the VM does.
+fn shift(a: i32, b: i32) -> i32 {
+    match b {
+        32.. => 0,
+        ..=-32 => a >> 31,
+        0.. => a.wrapping_shl(b.cast_unsigned()),
+        _ => a.wrapping_shr(b.wrapping_neg().cast_unsigned()),
+    }
+}
+
+// Folds a unary operation whose operand has already been folded.
+fn fold_unary(op: Token, expr: Box<Expression>, expr_type: Option<Type>) -> Expression {
+    let value: Option<Constant> = match (op.token_type, Constant::from_expression(&expr)) {
+        (TokenType::Minus, Some(Constant::Int(value))) => Some(Constant::Int(value.wrapping_neg())),
+        (TokenType::Tilde, Some(Constant::Int(value))) => Some(Constant::Int(!value)),
+        (TokenType::ExclamationMark, Some(Constant::Bool(value))) => Some(Constant::Bool(!value)),
+        _ => None,
+    };
+    match value {
+        Some(value) => value.to_expression(op),
+        None => Expression::Unary {
+            op,
+            expr,
+            expr_type,
+        },
+    }
+}
+
+// Folds a cast whose operand has already been folded. Only casts between ints and bools are folded, as the other
+// casts change how the value is stored.
+fn fold_cast(expr: Box<Expression>, expr_type: Option<Type>) -> Expression {
+    let value: Option<Constant> = match (Constant::from_expression(&expr), &expr_type) {
+        (Some(Constant::Int(value)), Some(Type::Int)) => Some(Constant::Int(value)),
+        (Some(Constant::Int(value)), Some(Type::Bool)) => Some(Constant::Bool(value != 0)),
+        (Some(Constant::Bool(value)), Some(Type::Int)) => Some(Constant::Int(i32::from(value))),
+        (Some(Constant::Bool(value)), Some(Type::Bool)) => Some(Constant::Bool(value)),
+        _ => None,
+    };
+    match (value, &*expr) {
+        (Some(value), Expression::Literal { token, .. }) => value.to_expression(*token),
+        _ => Expression::Cast { expr_type, expr },
+    }
+}
@@ -1,43 +1,314 @@
 //! The module for parsing the tokens and creating the AST.
-use crate::{lexer, util::log};
-use lexer::{LexerOutput, Token, TokenType};
-use log::{ErrorType, InfoType, Log, LogType};
+use crate::{lexer, util::log, util::suggestion::closest};
+use lexer::{LexerOutput, Span, Token, TokenType};
+use log::{ErrorType, HelpType, InfoType, Log, LogType, Note, NoteType, WarningType};
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter, Result};
+use std::sync::OnceLock;
+
+/// The most levels that expressions and declarations can be nested in each other, such as by parentheses, calls, or
+/// unary operators. Anything nested more deeply is reported instead of parsed, so that it can't overflow the stack.
+pub const MAX_NESTING_DEPTH: usize = 64;
+
+/// The size of the native stack, in bytes, that parsing and compiling code nested `MAX_NESTING_DEPTH` deep needs in an
+/// unoptimized build. This is the size of the main thread's stack on most platforms, so other threads that parse code
+/// from anywhere should be given this much.
+pub const PARSER_STACK_BYTES: usize = 8 * 1024 * 1024;
 
 /// The types in this language.
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
     Int,
     Bool,
-    Void, // Nothing type.
+    String,
+    Map(Box<Type>, Box<Type>), // The key and value types. Both must be int or bool.
+    List(Box<Type>),           // The element type, which must be int or bool.
+    Nullable(Box<Type>),       // A value of the inner type or null.
+    Result(Box<Type>),         // A value of the inner type or an error message. The inner type must be int or bool.
+    Generic(String),           // A type parameter of a generic function, named by the function.
+    Null,                // The type of the null literal.
+    Err,                 // The type of an error result before it is given a value type.
+    Void,                // Nothing type.
     Type,
 }
 
 impl Type {
     /// Get the types that this type can be casted to.
-    fn valid_casts(self) -> Vec<Self> {
+    fn valid_casts(&self) -> Vec<Self> {
+        match self {
+            Self::Int | Self::Bool => vec![
+                Self::Int,
+                Self::Bool,
+                Self::Nullable(Box::new(self.clone())),
+            ],
+            Self::Null => vec![
+                Self::Null,
+                Self::Nullable(Box::new(Self::Int)),
+                Self::Nullable(Box::new(Self::Bool)),
+            ],
+            Self::Err => vec![
+                Self::Err,
+                Self::Result(Box::new(Self::Int)),
+                Self::Result(Box::new(Self::Bool)),
+            ],
+            Self::String
+            | Self::Map(..)
+            | Self::List(_)
+            | Self::Nullable(_)
+            | Self::Result(_)
+            | Self::Generic(_)
+            | Self::Void
+            | Self::Type => {
+                vec![self.clone()]
+            }
+        }
+    }
+
+    /// Returns whether or not a value of the given type can be implicitly converted to this type.
+    #[must_use]
+    pub fn can_coerce_from(&self, other: &Self) -> bool {
+        match self {
+            Self::Nullable(inner) => *other == Self::Null || **inner == *other,
+            Self::Result(_) => *other == Self::Err,
+            _ => false,
+        }
+    }
+
+    /// Gets the name of the type without quotes.
+    #[must_use]
+    pub fn name(&self) -> String {
+        match self {
+            Self::Int => "int".to_string(),
+            Self::Bool => "bool".to_string(),
+            Self::String => "string".to_string(),
+            Self::Map(key, value) => format!("map[{}]{}", key.name(), value.name()),
+            Self::List(element) => format!("list<{}>", element.name()),
+            Self::Nullable(inner) => format!("{}?", inner.name()),
+            Self::Result(inner) => format!("result<{}>", inner.name()),
+            Self::Generic(name) => name.clone(),
+            Self::Null => "null".to_string(),
+            Self::Err => "err".to_string(),
+            Self::Void => "void".to_string(),
+            Self::Type => "type".to_string(),
+        }
+    }
+
+    /// Replaces the type parameters in this type with the types they are bound to. Type parameters that are not
+    /// bound are left as they are.
+    #[must_use]
+    pub fn substitute(&self, substitutions: &HashMap<String, Type>) -> Self {
         match self {
-            Self::Int | Self::Bool => vec![Self::Int, Self::Bool],
-            Self::Void => vec![Self::Void],
-            Self::Type => vec![Self::Type],
+            Self::Generic(name) => substitutions.get(name).cloned().unwrap_or(self.clone()),
+            Self::Map(key, value) => Self::Map(
+                Box::new(key.substitute(substitutions)),
+                Box::new(value.substitute(substitutions)),
+            ),
+            Self::List(element) => Self::List(Box::new(element.substitute(substitutions))),
+            Self::Nullable(inner) => Self::Nullable(Box::new(inner.substitute(substitutions))),
+            Self::Result(inner) => Self::Result(Box::new(inner.substitute(substitutions))),
+            _ => self.clone(),
         }
     }
 }
 
 impl Display for Type {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "\"{}\"", self.name())
+    }
+}
+
+/// The functions built into the language.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Builtin {
+    Abs,
+    Min,
+    Max,
+    Pow,
+    Sqrt,
+    Clamp,
+    Len,
+    Substring,
+    Contains,
+    Insert,
+    Get,
+    Remove,
+    Push,
+    Pop,
+    Ok,
+    Err,
+    IsOk,
+    Panic,
+    Assert,
+    ToString,
+    ParseInt,
+    Args,
+    RandomInt,
+    NowMillis,
+    ReadFile,
+    WriteFile,
+}
+
+impl Builtin {
+    /// Every built-in function.
+    pub const ALL: [Self; 26] = [
+        Self::Abs,
+        Self::Min,
+        Self::Max,
+        Self::Pow,
+        Self::Sqrt,
+        Self::Clamp,
+        Self::Len,
+        Self::Substring,
+        Self::Contains,
+        Self::Insert,
+        Self::Get,
+        Self::Remove,
+        Self::Push,
+        Self::Pop,
+        Self::Ok,
+        Self::Err,
+        Self::IsOk,
+        Self::Panic,
+        Self::Assert,
+        Self::ToString,
+        Self::ParseInt,
+        Self::Args,
+        Self::RandomInt,
+        Self::NowMillis,
+        Self::ReadFile,
+        Self::WriteFile,
+    ];
+
+    // Gets the built-in function with the given name.
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|function| function.name() == name)
+    }
+
+    /// Gets the name used to call the function.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Abs => "abs",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Pow => "pow",
+            Self::Sqrt => "sqrt",
+            Self::Clamp => "clamp",
+            Self::Len => "len",
+            Self::Substring => "substring",
+            Self::Contains => "contains",
+            Self::Insert => "insert",
+            Self::Get => "get",
+            Self::Remove => "remove",
+            Self::Push => "push",
+            Self::Pop => "pop",
+            Self::Ok => "ok",
+            Self::Err => "err",
+            Self::IsOk => "is_ok",
+            Self::Panic => "panic",
+            Self::Assert => "assert",
+            Self::ToString => "to_string",
+            Self::ParseInt => "parse_int",
+            Self::Args => "args",
+            Self::RandomInt => "random_int",
+            Self::NowMillis => "now_millis",
+            Self::ReadFile => "read_file",
+            Self::WriteFile => "write_file",
+        }
+    }
+
+    // Gets the types of the arguments of the function and the type it outputs, given the types of the arguments
+    // it was called with. Functions over maps, lists, and results take their types from the first argument. Returns
+    // None if the first argument is not a type that the function needs.
+    fn signature(self, args: &[Type]) -> Option<(Vec<Type>, Type)> {
+        let map_types: Option<(Type, &Type, &Type)> = match args.first() {
+            Some(map @ Type::Map(key, value)) => Some((map.clone(), key, value)),
+            _ => None,
+        };
+        let list_types: Option<(Type, &Type)> = match args.first() {
+            Some(list @ Type::List(element)) => Some((list.clone(), element)),
+            _ => None,
+        };
+        Some(match self {
+            Self::Abs | Self::Sqrt => (vec![Type::Int], Type::Int),
+            Self::Min | Self::Max | Self::Pow | Self::RandomInt => (vec![Type::Int, Type::Int], Type::Int),
+            Self::Clamp => (vec![Type::Int, Type::Int, Type::Int], Type::Int),
+            Self::Len => match (map_types, list_types) {
+                (Some((container, ..)), _) | (_, Some((container, _))) => {
+                    (vec![container], Type::Int)
+                }
+                (None, None) => (vec![Type::String], Type::Int),
+            },
+            Self::Substring => (vec![Type::String, Type::Int, Type::Int], Type::String),
+            Self::Contains => (vec![Type::String, Type::String], Type::Bool),
+            Self::Insert => {
+                let (map, key, value) = map_types?;
+                (vec![map.clone(), key.clone(), value.clone()], map)
+            }
+            Self::Get | Self::Remove => {
+                let (map, key, value) = map_types?;
+                (vec![map, key.clone()], Type::Nullable(Box::new(value.clone())))
+            }
+            Self::Push => {
+                let (list, element) = list_types?;
+                (vec![list.clone(), element.clone()], list)
+            }
+            // There are no nullable strings, so elements can't be popped from lists of strings.
+            Self::Pop => match list_types? {
+                (_, Type::String) => return None,
+                (list, element) => (vec![list], Type::Nullable(Box::new(element.clone()))),
+            },
+            Self::Ok => match args.first() {
+                Some(value @ (Type::Int | Type::Bool)) => {
+                    (vec![value.clone()], Type::Result(Box::new(value.clone())))
+                }
+                _ => return None,
+            },
+            Self::Err => (vec![Type::String], Type::Err),
+            Self::Panic => (vec![Type::String], Type::Void),
+            Self::Assert => (vec![Type::Bool, Type::String], Type::Void),
+            Self::ToString => (vec![Type::Int], Type::String),
+            Self::ParseInt => (vec![Type::String], Type::Result(Box::new(Type::Int))),
+            Self::Args => (Vec::new(), Type::List(Box::new(Type::String))),
+            Self::NowMillis => (Vec::new(), Type::Int),
+            Self::ReadFile => (vec![Type::String], Type::String),
+            Self::WriteFile => (vec![Type::String, Type::String], Type::Void),
+            Self::IsOk => match args.first() {
+                Some(result @ (Type::Result(_) | Type::Err)) => (vec![result.clone()], Type::Bool),
+                _ => return None,
+            },
+        })
+    }
+
+    // Describes the types of the arguments of the function for diagnostics.
+    fn describe_args(self) -> Vec<String> {
+        let generic = |args: &[&str]| args.iter().map(|arg| format!("\"{arg}\"")).collect();
         match self {
-            Self::Int => write!(f, "\"int\""),
-            Self::Bool => write!(f, "\"bool\""),
-            Self::Void => write!(f, "\"void\""),
-            Self::Type => write!(f, "\"type\""),
+            Self::Len => vec!["\"string\", \"map[K]V\", or \"list<T>\"".to_string()],
+            Self::Insert => generic(&["map[K]V", "K", "V"]),
+            Self::Get | Self::Remove => generic(&["map[K]V", "K"]),
+            Self::Push => generic(&["list<T>", "T"]),
+            Self::Pop => generic(&["list<T>"]),
+            Self::Ok => vec!["\"int\" or \"bool\"".to_string()],
+            Self::IsOk => generic(&["result<T>"]),
+            _ => self
+                .signature(&[])
+                .expect("only functions over maps, lists, and results need arguments to get a signature")
+                .0
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
         }
     }
 }
 
 /// An enum represetning the possible types of expressions.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expression {
     Binary {
         left: Box<Expression>,
@@ -45,10 +316,60 @@ pub enum Expression {
         right: Box<Expression>,
         expr_type: Option<Type>,
     },
+    Call {
+        token: Token,
+        function: Builtin,
+        args: Vec<Box<Expression>>,
+        expr_type: Option<Type>,
+    },
     Cast {
         expr_type: Option<Type>,
         expr: Box<Expression>,
     },
+    FunctionCall {
+        token: Token,
+        declaration: Token, // The name token of the function's declaration.
+        args: Vec<Box<Expression>>,
+        type_args: Vec<Type>, // The types bound to the function's type parameters, in order.
+        expr_type: Option<Type>,
+    },
+    HostCall {
+        token: Token,
+        name: String, // The name of the host function, which the VM looks up when it is called.
+        args: Vec<Box<Expression>>,
+        expr_type: Option<Type>,
+    },
+    FunctionDeclaration {
+        token: Token,
+        type_params: Vec<String>,
+        type_bounds: Vec<Option<String>>, // The trait each type parameter must implement, if any.
+        params: Vec<Box<Expression>>,     // Initialized variables holding the arguments.
+        defaults: Vec<Box<Expression>>,   // The default values of the last parameters, in order.
+        return_type: Type,
+        body: Box<Expression>,
+    },
+    TraitDeclaration {
+        token: Token,
+        methods: Vec<Box<Expression>>, // Function declarations without bodies, where "self" has the type "Self".
+    },
+    FlagsDeclaration {
+        token: Token,
+        members: Vec<(Token, Box<Expression>)>, // The name of each flag and its constant int value.
+    },
+    ExternDeclaration {
+        signature: Box<Expression>, // A function declaration without a body.
+    },
+    ImplDeclaration {
+        trait_token: Token, // The name token of the trait's declaration.
+        impl_type: Type,
+        methods: Vec<Box<Expression>>, // In the same order as the methods of the trait.
+    },
+    MethodCall {
+        token: Token,
+        method: Token, // The name token of the method in the trait's declaration.
+        args: Vec<Box<Expression>>, // The value the method is called on, followed by the other arguments.
+        expr_type: Option<Type>,
+    },
     CastOp {
         expr_type: Type,
     },
@@ -63,9 +384,34 @@ pub enum Expression {
         token: Token,
         expr_type: Option<Type>,
     },
+    MapLiteral {
+        token: Token,
+        entries: Vec<(Box<Expression>, Box<Expression>)>,
+        expr_type: Option<Type>,
+    },
+    ListLiteral {
+        token: Token,
+        elements: Vec<Box<Expression>>,
+        expr_type: Option<Type>,
+    },
+    Index {
+        token: Token, // The '[' token.
+        expr: Box<Expression>,
+        index: Box<Expression>,
+        expr_type: Option<Type>,
+    },
+    Postfix {
+        op: Token,
+        expr: Box<Expression>,
+        expr_type: Option<Type>,
+    },
     Statement {
         expr: Box<Expression>,
     },
+    StringLiteral {
+        token: Token,
+        value: String, // The text of the literal with escape sequences replaced.
+    },
     Type {
         value: Type,
     },
@@ -77,13 +423,18 @@ pub enum Expression {
     Void,
     Variable {
         initialized: bool,
-        token: Token,
+        token: Token,     // The name token of the variable's declaration.
+        use_token: Token, // The name token where the variable is used, which for a declaration is the same token.
         expr_type: Option<Type>,
     },
     VariableDeclaration {
         initialized_var: Box<Expression>,
     },
 
+    /// Code that couldn't be parsed, which has already been reported. Its token is where the error was found.
+    Error {
+        token: Token,
+    },
     EOF,
     Null,
 }
@@ -106,26 +457,323 @@ impl Expression {
     pub fn get_type(&self) -> Option<Type> {
         match &self {
             Self::Binary { expr_type, .. }
+            | Self::Call { expr_type, .. }
             | Self::Cast { expr_type, .. }
+            | Self::FunctionCall { expr_type, .. }
+            | Self::HostCall { expr_type, .. }
+            | Self::MethodCall { expr_type, .. }
             | Self::Grouping { expr_type, .. }
             | Self::Literal { expr_type, .. }
+            | Self::MapLiteral { expr_type, .. }
+            | Self::ListLiteral { expr_type, .. }
+            | Self::Index { expr_type, .. }
+            | Self::Postfix { expr_type, .. }
             | Self::Unary { expr_type, .. }
-            | Self::Variable { expr_type, .. } => *expr_type,
+            | Self::Variable { expr_type, .. } => expr_type.clone(),
 
-            Self::CastOp { expr_type } => Some(*expr_type),
+            Self::CastOp { expr_type } => Some(expr_type.clone()),
 
             Self::ExpressionList { list } => match list.last() {
                 None => Some(Type::Void),
                 Some(expr) => expr.get_type(),
             },
 
-            Self::Statement { .. } | Self::Void => Some(Type::Void),
+            Self::FunctionDeclaration { .. }
+            | Self::TraitDeclaration { .. }
+            | Self::FlagsDeclaration { .. }
+            | Self::ExternDeclaration { .. }
+            | Self::ImplDeclaration { .. }
+            | Self::Statement { .. }
+            | Self::Void => Some(Type::Void),
+
+            Self::StringLiteral { .. } => Some(Type::String),
 
             Self::Type { .. } => Some(Type::Type),
 
             Self::VariableDeclaration { initialized_var } => initialized_var.get_type(),
 
-            Self::EOF | Self::Null => None,
+            Self::Error { .. } | Self::EOF | Self::Null => None,
+        }
+    }
+
+    /// Gets a token of the expression to report errors at, which is the first one found in the expression or its
+    /// children. Uses of variables are skipped, as their tokens are those of the declarations.
+    #[must_use]
+    pub fn first_token(&self) -> Option<Token> {
+        match self {
+            Self::Binary { left, op, .. } => left.first_token().or(Some(*op)),
+            Self::Call { token, .. }
+            | Self::FunctionCall { token, .. }
+            | Self::HostCall { token, .. }
+            | Self::FunctionDeclaration { token, .. }
+            | Self::TraitDeclaration { token, .. }
+            | Self::FlagsDeclaration { token, .. }
+            | Self::MethodCall { token, .. }
+            | Self::Literal { token, .. }
+            | Self::MapLiteral { token, .. }
+            | Self::ListLiteral { token, .. }
+            | Self::StringLiteral { token, .. }
+            | Self::Error { token } => Some(*token),
+            Self::ImplDeclaration { trait_token, .. } => Some(*trait_token),
+            Self::Index { expr, token, .. } => expr.first_token().or(Some(*token)),
+            Self::Postfix { expr, op, .. } => expr.first_token().or(Some(*op)),
+            Self::Unary { op, .. } => Some(*op),
+            Self::Cast { expr, .. }
+            | Self::Grouping { expr, .. }
+            | Self::Statement { expr }
+            | Self::ExternDeclaration { signature: expr } => expr.first_token(),
+            Self::VariableDeclaration { initialized_var } => match **initialized_var {
+                Self::Variable { token, .. } => Some(token),
+                _ => initialized_var.first_token(),
+            },
+            Self::ExpressionList { list } => list.iter().find_map(|expr| expr.first_token()),
+            Self::CastOp { .. }
+            | Self::Type { .. }
+            | Self::Variable { .. }
+            | Self::Void
+            | Self::EOF
+            | Self::Null => None,
+        }
+    }
+
+    /// Gets the part of the source covered by the tokens of the expression and its children, or None if it has no
+    /// tokens. Closing brackets aren't kept in the expression, so the span ends at the last token before them.
+    #[must_use]
+    pub fn span(&self) -> Option<Span> {
+        let mut spans = self.tokens().into_iter().map(|token| token.span());
+        let first: Span = spans.next()?;
+        Some(spans.fold(first, |span, next| span.to(&next)))
+    }
+
+    // Gets the tokens of the expression and its children that are part of it in the source. Declarations that
+    // expressions refer to, such as the function a call runs, are left out.
+    fn tokens(&self) -> Vec<Token> {
+        let children = |exprs: &[Box<Self>]| -> Vec<Token> { exprs.iter().flat_map(|expr| expr.tokens()).collect() };
+        match self {
+            Self::Binary { left, op, right, .. } => [left.tokens(), vec![*op], right.tokens()].concat(),
+            Self::Call { token, args, .. }
+            | Self::FunctionCall { token, args, .. }
+            | Self::HostCall { token, args, .. }
+            | Self::MethodCall { token, args, .. }
+            | Self::ListLiteral { token, elements: args, .. } => [vec![*token], children(args)].concat(),
+            Self::FunctionDeclaration { token, params, defaults, body, .. } => {
+                [vec![*token], children(params), children(defaults), body.tokens()].concat()
+            }
+            Self::TraitDeclaration { token, methods } => [vec![*token], children(methods)].concat(),
+            Self::FlagsDeclaration { token, members } => {
+                let mut tokens: Vec<Token> = vec![*token];
+                for (name, value) in members {
+                    tokens.push(*name);
+                    tokens.append(&mut value.tokens());
+                }
+                tokens
+            }
+            Self::ImplDeclaration { methods, .. } => children(methods),
+            Self::MapLiteral { token, entries, .. } => {
+                let mut tokens: Vec<Token> = vec![*token];
+                for (key, value) in entries {
+                    tokens.append(&mut key.tokens());
+                    tokens.append(&mut value.tokens());
+                }
+                tokens
+            }
+            Self::Index { token, expr, index, .. } => [expr.tokens(), vec![*token], index.tokens()].concat(),
+            Self::Postfix { op, expr, .. } | Self::Unary { op, expr, .. } => [vec![*op], expr.tokens()].concat(),
+            Self::Literal { token, .. } | Self::StringLiteral { token, .. } | Self::Error { token } => vec![*token],
+            Self::Variable { use_token, .. } => vec![*use_token],
+            Self::Cast { expr, .. }
+            | Self::Grouping { expr, .. }
+            | Self::Statement { expr }
+            | Self::ExternDeclaration { signature: expr } => expr.tokens(),
+            Self::VariableDeclaration { initialized_var } => initialized_var.tokens(),
+            Self::ExpressionList { list } => children(list),
+            Self::CastOp { .. } | Self::Type { .. } | Self::Void | Self::EOF | Self::Null => Vec::new(),
+        }
+    }
+
+    // Gets a copy of this expression for a use of it at the token, which is a use of a variable if the expression is
+    // the variable's declaration.
+    fn used_at(&self, token: Token) -> Self {
+        match self {
+            Self::Variable { initialized, token: declaration, expr_type, .. } => Self::Variable {
+                initialized: *initialized,
+                token: *declaration,
+                use_token: token,
+                expr_type: expr_type.clone(),
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// Replaces the type parameters in the types of this expression and its children with the types they are bound
+    /// to. This is used to create each instance of a generic function.
+    #[must_use]
+    #[allow(clippy::too_many_lines)] // Necessary for all the different expression types.
+    pub fn substitute_types(&self, substitutions: &HashMap<String, Type>) -> Self {
+        let sub = |expr: &Self| Box::new(expr.substitute_types(substitutions));
+        let sub_type = |expr_type: &Option<Type>| {
+            expr_type
+                .as_ref()
+                .map(|expr_type| expr_type.substitute(substitutions))
+        };
+        match self {
+            Self::Binary {
+                left,
+                op,
+                right,
+                expr_type,
+            } => Self::Binary {
+                left: sub(left),
+                op: *op,
+                right: sub(right),
+                expr_type: sub_type(expr_type),
+            },
+            Self::Call {
+                token,
+                function,
+                args,
+                expr_type,
+            } => Self::Call {
+                token: *token,
+                function: *function,
+                args: args.iter().map(|arg| sub(arg)).collect(),
+                expr_type: sub_type(expr_type),
+            },
+            Self::Cast { expr_type, expr } => Self::Cast {
+                expr_type: sub_type(expr_type),
+                expr: sub(expr),
+            },
+            Self::CastOp { expr_type } => Self::CastOp {
+                expr_type: expr_type.substitute(substitutions),
+            },
+            Self::ExpressionList { list } => Self::ExpressionList {
+                list: list.iter().map(|expr| sub(expr)).collect(),
+            },
+            Self::FunctionCall {
+                token,
+                declaration,
+                args,
+                type_args,
+                expr_type,
+            } => Self::FunctionCall {
+                token: *token,
+                declaration: *declaration,
+                args: args.iter().map(|arg| sub(arg)).collect(),
+                type_args: type_args
+                    .iter()
+                    .map(|type_arg| type_arg.substitute(substitutions))
+                    .collect(),
+                expr_type: sub_type(expr_type),
+            },
+            Self::HostCall {
+                token,
+                name,
+                args,
+                expr_type,
+            } => Self::HostCall {
+                token: *token,
+                name: name.clone(),
+                args: args.iter().map(|arg| sub(arg)).collect(),
+                expr_type: sub_type(expr_type),
+            },
+            Self::MethodCall {
+                token,
+                method,
+                args,
+                expr_type,
+            } => Self::MethodCall {
+                token: *token,
+                method: *method,
+                args: args.iter().map(|arg| sub(arg)).collect(),
+                expr_type: sub_type(expr_type),
+            },
+            Self::Grouping { expr, expr_type } => Self::Grouping {
+                expr: sub(expr),
+                expr_type: sub_type(expr_type),
+            },
+            Self::Literal { token, expr_type } => Self::Literal {
+                token: *token,
+                expr_type: sub_type(expr_type),
+            },
+            Self::MapLiteral {
+                token,
+                entries,
+                expr_type,
+            } => Self::MapLiteral {
+                token: *token,
+                entries: entries
+                    .iter()
+                    .map(|(key, value)| (sub(key), sub(value)))
+                    .collect(),
+                expr_type: sub_type(expr_type),
+            },
+            Self::ListLiteral {
+                token,
+                elements,
+                expr_type,
+            } => Self::ListLiteral {
+                token: *token,
+                elements: elements.iter().map(|element| sub(element)).collect(),
+                expr_type: sub_type(expr_type),
+            },
+            Self::Index {
+                token,
+                expr,
+                index,
+                expr_type,
+            } => Self::Index {
+                token: *token,
+                expr: sub(expr),
+                index: sub(index),
+                expr_type: sub_type(expr_type),
+            },
+            Self::Postfix {
+                op,
+                expr,
+                expr_type,
+            } => Self::Postfix {
+                op: *op,
+                expr: sub(expr),
+                expr_type: sub_type(expr_type),
+            },
+            Self::Statement { expr } => Self::Statement { expr: sub(expr) },
+            Self::Type { value } => Self::Type {
+                value: value.substitute(substitutions),
+            },
+            Self::Unary {
+                op,
+                expr,
+                expr_type,
+            } => Self::Unary {
+                op: *op,
+                expr: sub(expr),
+                expr_type: sub_type(expr_type),
+            },
+            Self::Variable {
+                initialized,
+                token,
+                use_token,
+                expr_type,
+            } => Self::Variable {
+                initialized: *initialized,
+                token: *token,
+                use_token: *use_token,
+                expr_type: sub_type(expr_type),
+            },
+            Self::VariableDeclaration { initialized_var } => Self::VariableDeclaration {
+                initialized_var: sub(initialized_var),
+            },
+            // Declarations are instanced separately, so their types are left alone.
+            Self::FunctionDeclaration { .. }
+            | Self::TraitDeclaration { .. }
+            | Self::FlagsDeclaration { .. }
+            | Self::ExternDeclaration { .. }
+            | Self::ImplDeclaration { .. }
+            | Self::StringLiteral { .. }
+            | Self::Void
+            | Self::Error { .. }
+            | Self::EOF
+            | Self::Null => self.clone(),
         }
     }
 }
@@ -144,6 +792,12 @@ struct OpList {
     list: Vec<Operator>,
 }
 impl OpList {
+    // Gets the operators used by the language. They are only created once, as every expression looks through them.
+    fn op_lists() -> &'static [Self; 8] {
+        static OP_LISTS: OnceLock<[OpList; 8]> = OnceLock::new();
+        OP_LISTS.get_or_init(Self::get_op_lists)
+    }
+
     // Gets the default list of operators used by the language.
     #[allow(clippy::too_many_lines)] // Decreasing lines only makes this function more dense.
     fn get_op_lists() -> [Self; 8] {
@@ -190,6 +844,38 @@ impl OpList {
                         input: vec![Type::Bool, Type::Bool],
                         output: Type::Bool,
                     },
+                    Operator {
+                        token: TokenType::Equality,
+                        input: vec![
+                            Type::Nullable(Box::new(Type::Int)),
+                            Type::Nullable(Box::new(Type::Int)),
+                        ],
+                        output: Type::Bool,
+                    },
+                    Operator {
+                        token: TokenType::Equality,
+                        input: vec![
+                            Type::Nullable(Box::new(Type::Bool)),
+                            Type::Nullable(Box::new(Type::Bool)),
+                        ],
+                        output: Type::Bool,
+                    },
+                    Operator {
+                        token: TokenType::Inequality,
+                        input: vec![
+                            Type::Nullable(Box::new(Type::Int)),
+                            Type::Nullable(Box::new(Type::Int)),
+                        ],
+                        output: Type::Bool,
+                    },
+                    Operator {
+                        token: TokenType::Inequality,
+                        input: vec![
+                            Type::Nullable(Box::new(Type::Bool)),
+                            Type::Nullable(Box::new(Type::Bool)),
+                        ],
+                        output: Type::Bool,
+                    },
                 ],
             },
             Self {
@@ -314,34 +1000,18 @@ impl OpList {
 
     // Returns whether or not one of the operators is associated a specific token.
     fn contains(&self, token: TokenType) -> bool {
-        let list: Vec<Operator> = self.list.clone();
-        for op in list {
-            if op.token == token {
-                return true;
-            }
-        }
-        false
-    }
-
-    // Returns a list of valid input and output types for this
-    fn valid_arg_types(&self, token: TokenType) -> Vec<(Vec<Type>, Type)> {
-        let list: Vec<Operator> = self.list.clone();
-        let mut valid_types: Vec<(Vec<Type>, Type)> = Vec::new();
-        for op in list {
-            if op.token == token {
-                valid_types.push((op.input, op.output));
-            }
-        }
-        valid_types
+        self.list.iter().any(|op| op.token == token)
     }
 
-    // Gets the output type of an operator given by the tokens and the given inputs.
+    // Gets the output type of an operator given by the tokens and its operands, which are each given with a description
+    // such as "left operand". Errors are reported at the token, and cover the span of the operator and its operands.
     fn get_output_type(
         &self,
         token: &Token,
-        input: Vec<Option<Type>>,
+        operands: &[(&str, &Expression)],
         log_info: &mut (&mut Vec<Log>, &String),
     ) -> Option<Type> {
+        let input: Vec<Option<Type>> = operands.iter().map(|(_, operand)| operand.get_type()).collect();
         if input.contains(&None) {
             return None;
         }
@@ -354,19 +1024,22 @@ impl OpList {
             temp
         };
 
-        let valid_types: Vec<(Vec<Type>, Type)> = self.valid_arg_types(token.token_type);
-        let mut valid_inputs: Vec<Vec<Type>> = Vec::new();
-        for value in valid_types.clone() {
-            valid_inputs.push(value.clone().0);
-        }
-        for i in 0..valid_inputs.len() {
-            if valid_inputs[i] == input {
-                return Some(valid_types[i].1);
-            }
+        if let Some(op) = self
+            .list
+            .iter()
+            .find(|op| op.token == token.token_type && op.input == input)
+        {
+            return Some(op.output.clone());
         }
 
         let logs: &mut Vec<Log> = log_info.0;
         let source: &String = log_info.1;
+        let definitions: Vec<&Vec<Type>> = self
+            .list
+            .iter()
+            .filter(|op| op.token == token.token_type)
+            .map(|op| &op.input)
+            .collect();
         logs.push(Log {
             log_type: LogType::Error(ErrorType::InvalidArgsForOperator(
                 token.to_string(source),
@@ -379,6 +1052,8 @@ impl OpList {
                 },
             )),
             line_and_col: Some((token.line, token.col)),
+            span: Some(cover(*token, &operands.iter().map(|(_, operand)| *operand).collect::<Vec<_>>())),
+            notes: operand_notes(operands, &definitions),
         });
         None
     }
@@ -387,7 +1062,7 @@ impl OpList {
 /// Parse the output from the lexer.
 #[must_use]
 pub fn parse(lex_output: LexerOutput) -> ParserOutput {
-    let mut logs: Vec<Log> = lex_output.logs.clone();
+    let mut logs: Vec<Log> = lex_output.logs;
     let mut index: usize = 0;
     let tokens: Vec<Token> = lex_output.tokens;
     let mut var_list: HashMap<String, Expression> = HashMap::new();
@@ -398,28 +1073,17 @@ pub fn parse(lex_output: LexerOutput) -> ParserOutput {
         &lex_output.file_text,
         &mut var_list,
     );
-    if index < tokens.len() && tokens[index].token_type != TokenType::EOF {
-        logs.push(Log {
-            log_type: LogType::Error(ErrorType::UnexpectedToken(
-                tokens[index].to_string(&lex_output.file_text),
-            )),
-            line_and_col: Some((tokens[index].line, tokens[index].col)),
-        });
-    }
-    improve_ast(
-        Box::new(expr.clone()),
-        None,
-        &mut logs,
-        &lex_output.file_text,
-    );
+    improve_ast(&expr, None, &mut logs, &lex_output.file_text);
     ParserOutput {
-        file_text: lex_output.file_text.clone(),
+        file_text: lex_output.file_text,
         expr,
         logs,
     }
 }
 
-// Gets a list of expressions from the token list. These should all be statements except for the last one.
+// Gets a list of expressions from the token list. These should all be statements except for the last one. An
+// expression that isn't followed by a semicolon or the end of the file is reported, and the tokens up to the end of
+// its statement are replaced by an error, so that the statements after it are still parsed.
 fn get_expression_list(
     tokens: &Vec<Token>,
     logs: &mut Vec<Log>,
@@ -428,18 +1092,127 @@ fn get_expression_list(
     var_list: &mut HashMap<String, Expression>,
 ) -> Expression {
     let mut list: Vec<Box<Expression>> = Vec::new();
-    let mut is_stmt: bool = true;
-    while is_stmt {
-        let next_expr: Expression = get_statement(tokens, logs, index, source, var_list);
-        if let Expression::Statement { .. } = next_expr {
-        } else {
-            is_stmt = false;
+    loop {
+        let next_expr: Expression = get_statement(tokens, logs, index, source, var_list, 0);
+        if matches!(next_expr, Expression::Statement { .. }) {
+            list.push(Box::new(next_expr));
+            continue;
+        }
+        if next_expr.is_eof() || tokens.get(*index).is_none_or(|token| token.token_type == TokenType::EOF) {
+            list.push(Box::new(next_expr));
+            break;
+        }
+        let token: Token = tokens[*index];
+        // The token may have already been reported by the expression it ended.
+        if logs.last().is_none_or(|log| log.span != Some(token.span())) {
+            push_unexpected_token(tokens, *index, logs, source);
+        }
+        list.push(Box::new(Expression::Statement {
+            expr: Box::new(next_expr),
+        }));
+        list.push(Box::new(Expression::Statement {
+            expr: Box::new(Expression::Error { token }),
+        }));
+        synchronize(tokens, index, None);
+        // There is no block for a '}' to close here, so it is skipped too.
+        if tokens[*index].token_type == TokenType::RightBrace {
+            *index += 1;
         }
-        list.push(Box::new(next_expr));
     }
     Expression::ExpressionList { list }
 }
 
+// Skips tokens after a syntax error up to a point where parsing can go on, so that the errors after it are reported
+// too. This is just after the closing token, if one is given, or else just after the next ';'. Skipping stops early
+// before a ';' or '}' that ends the statement or block the error is in, or at the end of the file. Brackets that are
+// opened in the skipped tokens are skipped along with their contents.
+fn synchronize(tokens: &[Token], index: &mut usize, closer: Option<TokenType>) {
+    let mut braces: usize = 0;
+    let mut brackets: usize = 0;
+    loop {
+        match tokens[*index].token_type {
+            TokenType::EOF => return,
+            TokenType::LeftBrace => braces += 1,
+            TokenType::RightBrace if braces == 0 => return,
+            TokenType::RightBrace => braces -= 1,
+            _ if braces > 0 => {}
+            TokenType::Semicolon => {
+                if closer.is_none() {
+                    *index += 1;
+                }
+                return;
+            }
+            TokenType::LeftParen | TokenType::LeftBracket => brackets += 1,
+            TokenType::RightParen | TokenType::RightBracket if brackets > 0 => brackets -= 1,
+            token_type if Some(token_type) == closer => {
+                *index += 1;
+                return;
+            }
+            _ => {}
+        }
+        *index += 1;
+    }
+}
+
+// Reports the token at the position as unexpected. If it or the name before it looks like a misspelled keyword, or it
+// and a symbol next to it look like an operator written backwards, such as "=<", the fix is suggested too.
+fn push_unexpected_token(tokens: &[Token], position: usize, logs: &mut Vec<Log>, source: &str) {
+    let token: Token = tokens[position];
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::UnexpectedToken(token.to_string(source))),
+        line_and_col: Some((token.line, token.col)),
+        span: Some(token.span()),
+        notes: Vec::new(),
+    });
+    if let Some((span, suggestion)) = suggest_fix(tokens, position, source) {
+        logs.push(Log {
+            log_type: LogType::Help(HelpType::DidYouMean(suggestion)),
+            line_and_col: Some(span.start),
+            span: Some(span),
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Finds what was probably meant around an unexpected token, and the part of the source it replaces.
+fn suggest_fix(tokens: &[Token], position: usize, source: &str) -> Option<(Span, String)> {
+    let previous: Option<Token> = position.checked_sub(1).map(|i| tokens[i]);
+    for name in [previous, Some(tokens[position])].into_iter().flatten() {
+        if name.token_type == TokenType::Other {
+            let keyword: Option<&str> = closest(
+                &name.to_string(source),
+                lexer::KEYWORDS.iter().map(|(keyword, _)| *keyword),
+            );
+            if let Some(keyword) = keyword {
+                return Some((name.span(), keyword.to_string()));
+            }
+        }
+    }
+    let next: Option<Token> = tokens.get(position + 1).copied();
+    let pairs = [(previous, Some(tokens[position])), (Some(tokens[position]), next)];
+    for (first, second) in pairs {
+        let (Some(first), Some(second)) = (first, second) else {
+            continue;
+        };
+        let is_symbol = |token: Token| !matches!(token.token_type, TokenType::Other | TokenType::EOF);
+        if !is_symbol(first) || !is_symbol(second) || first.span().byte_range.end != second.span().byte_range.start {
+            continue;
+        }
+        let text: String = first.to_string(source) + &second.to_string(source);
+        let mut sorted: Vec<char> = text.chars().collect();
+        sorted.sort_unstable();
+        let operator: Option<&&str> = lexer::MULTI_CHAR_OPERATORS.iter().find(|operator| {
+            let mut operator_chars: Vec<char> = operator.chars().collect();
+            operator_chars.sort_unstable();
+            **operator != text && operator_chars == sorted
+        });
+        if let Some(operator) = operator {
+            return Some((first.span().to(&second.span()), (*operator).to_string()));
+        }
+    }
+    None
+}
+
 // Gets a statement from the token list.
 fn get_statement(
     tokens: &Vec<Token>,
@@ -447,21 +1220,45 @@ fn get_statement(
     index: &mut usize,
     source: &String,
     var_list: &mut HashMap<String, Expression>,
+    depth: usize,
 ) -> Expression {
     if let TokenType::EOF = tokens[*index].token_type {
         return Expression::Void;
     }
+    if let Some(error) = check_depth(tokens, logs, index, depth) {
+        if tokens[*index].token_type == TokenType::Semicolon {
+            *index += 1;
+            return Expression::Statement {
+                expr: Box::new(error),
+            };
+        }
+        return error;
+    }
+    let declaration: Option<Expression> = match tokens[*index].token_type {
+        TokenType::Fn => Some(handle_function(tokens, logs, index, source, var_list, depth + 1)),
+        TokenType::Trait => Some(handle_trait(tokens, logs, index, source, var_list, depth + 1)),
+        TokenType::Impl => Some(handle_impl(tokens, logs, index, source, var_list, depth + 1)),
+        TokenType::Flags => Some(handle_flags(tokens, logs, index, source, var_list, depth + 1)),
+        TokenType::Extern => Some(handle_extern(tokens, logs, index, source, var_list, depth + 1)),
+        _ => None,
+    };
+    if let Some(declaration) = declaration {
+        // Declarations end with a '}', so the semicolon after them is optional.
+        if tokens[*index].token_type == TokenType::Semicolon {
+            *index += 1;
+        }
+        return Expression::Statement {
+            expr: Box::new(declaration),
+        };
+    }
 
-    let mut expr: Expression = get_expression(tokens, logs, index, source, var_list);
+    let mut expr: Expression = get_expression(tokens, logs, index, source, var_list, depth);
     if let Expression::VariableDeclaration { initialized_var } = &expr {
         if let Expression::Variable { token, .. } = **initialized_var {
             var_list.insert(token.to_string(source), *initialized_var.clone());
         }
     }
 
-    if let TokenType::EOF = tokens[*index - 1].token_type {
-        return expr;
-    }
     if let TokenType::Semicolon = tokens[*index].token_type {
         expr = Expression::Statement {
             expr: Box::new(expr),
@@ -478,8 +1275,9 @@ fn get_expression(
     index: &mut usize,
     source: &String,
     var_list: &mut HashMap<String, Expression>,
+    depth: usize,
 ) -> Expression {
-    handle_assignment(tokens, logs, index, source, var_list).unwrap_or(Expression::Null)
+    handle_assignment(tokens, logs, index, source, var_list, depth + 1).unwrap_or(Expression::Null)
 }
 
 // Get a primary expression (literals and grouping expressions).
@@ -489,6 +1287,7 @@ fn get_primary(
     index: &mut usize,
     source: &String,
     var_list: &mut HashMap<String, Expression>,
+    depth: usize,
 ) -> Expression {
     let token: Token = tokens[*index];
     *index += 1;
@@ -501,96 +1300,2041 @@ fn get_primary(
             token,
             expr_type: Some(Type::Bool),
         },
+        TokenType::Null => Expression::Literal {
+            token,
+            expr_type: Some(Type::Null),
+        },
+        TokenType::StringLiteral => Expression::StringLiteral {
+            token,
+            value: unescape(&token.to_string(source)),
+        },
         TokenType::Error => Expression::Literal {
             token,
             expr_type: None,
         },
-        TokenType::LeftParen => handle_paren(tokens, logs, index, source, var_list),
+        TokenType::LeftParen => handle_paren(tokens, logs, index, source, var_list, depth),
+        TokenType::TypeOf => handle_typeof(token, tokens, logs, index, source, var_list, depth),
         TokenType::EOF => {
             logs.push(Log {
                 log_type: LogType::Error(ErrorType::UnexpectedEOF),
                 line_and_col: Some((token.line, token.col)),
+                span: Some(token.span()),
+                notes: Vec::new(),
             });
+            *index -= 1; // The end of the file is left to be seen by whatever expected more tokens.
             Expression::EOF
         }
-        TokenType::Int => Expression::Type { value: Type::Int },
-        TokenType::Bool => Expression::Type { value: Type::Bool },
+        TokenType::Int => get_type_suffix(tokens, index, Type::Int),
+        TokenType::Bool => get_type_suffix(tokens, index, Type::Bool),
+        TokenType::String => Expression::Type {
+            value: Type::String,
+        }, // Strings can not be nullable.
+        TokenType::Map => handle_map(token, tokens, logs, index, source, var_list, depth),
+        TokenType::List => handle_list(token, tokens, logs, index, source, var_list, depth),
+        TokenType::Result => {
+            if let Some(value) = get_result_type(tokens, index) {
+                Expression::Type { value }
+            } else {
+                logs.push(Log {
+                    log_type: LogType::Error(ErrorType::ExpectedResultType),
+                    line_and_col: Some((token.line, token.col)),
+                    span: Some(token.span()),
+                    notes: Vec::new(),
+                });
+                Expression::Null
+            }
+        }
         TokenType::Other => {
+            if tokens[*index].token_type == TokenType::LeftParen {
+                return handle_call(token, tokens, logs, index, source, var_list, depth);
+            }
             let key: &String = &token.to_string(source);
-            if var_list.contains_key(key) {
-                var_list[key].clone()
+            if let Some(Expression::FunctionDeclaration { .. } | Expression::ExternDeclaration { .. }) =
+                var_list.get(key)
+            {
+                logs.push(Log {
+                    log_type: LogType::Error(ErrorType::FunctionUsedAsValue(key.clone())),
+                    line_and_col: Some((token.line, token.col)),
+                    span: Some(token.span()),
+                    notes: Vec::new(),
+                });
+                Expression::Null
+            } else if let Some(Expression::FlagsDeclaration { members, .. }) = var_list.get(key) {
+                get_flag(token, members, tokens, logs, index, source)
+            } else if let Some(var) = var_list.get(key) {
+                var.used_at(token)
             } else {
                 Expression::Variable {
                     initialized: false,
                     token,
+                    use_token: token,
                     expr_type: None,
                 }
             }
         }
         _ => {
-            logs.push(Log {
-                log_type: LogType::Error(ErrorType::UnexpectedToken(token.to_string(source))),
-                line_and_col: Some((token.line, token.col)),
-            });
-            get_expression(tokens, logs, index, source, var_list)
+            push_unexpected_token(tokens, *index - 1, logs, source);
+            // A token that ends the expression is left for what it ends, so that the code after it is still parsed.
+            if ENDING_TOKENS.contains(&token.token_type) {
+                *index -= 1;
+                return Expression::Error { token };
+            }
+            get_expression(tokens, logs, index, source, var_list, depth)
         }
     }
 }
 
-// Handles primary expressions that use parentheses.
-fn handle_paren(
+// The tokens that end an expression without being part of it.
+const ENDING_TOKENS: [TokenType; 5] = [
+    TokenType::Semicolon,
+    TokenType::Comma,
+    TokenType::RightParen,
+    TokenType::RightBracket,
+    TokenType::RightBrace,
+];
+
+// Handles the "typeof" operator, which is replaced by the name of the type of its operand at compile time. The
+// operand is never run. A note with the type is also reported so it can be seen without running the code.
+fn handle_typeof(
+    token: Token,
     tokens: &Vec<Token>,
     logs: &mut Vec<Log>,
     index: &mut usize,
     source: &String,
     var_list: &mut HashMap<String, Expression>,
+    depth: usize,
 ) -> Expression {
-    if tokens[*index].token_type == TokenType::RightParen {
+    if tokens[*index].token_type != TokenType::LeftParen {
         logs.push(Log {
-            log_type: LogType::Error(ErrorType::ExpectedExpressionInParens),
+            log_type: LogType::Error(ErrorType::ExpectedOpenParen(token.to_string(source))),
             line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+            span: Some(tokens[*index].span()),
+            notes: Vec::new(),
         });
-        *index += 1;
-        return Expression::Grouping {
-            expr: Box::new(Expression::Null),
-            expr_type: None,
-        };
+        return Expression::Null;
     }
-    let expr: Expression = get_expression(tokens, logs, index, source, var_list);
-    if matches!(expr, Expression::EOF) {
-        logs.push(Log {
-            log_type: LogType::Error(ErrorType::ExpectedCloseParen),
-            line_and_col: Some((tokens[*index - 1].line, tokens[*index - 1].col)),
-        });
-        return expr;
+    *index += 1;
+    let expr: Expression = handle_paren(tokens, logs, index, source, var_list, depth);
+    let Some(expr_type) = expr.get_type() else {
+        return expr; // The operand already has an error.
+    };
+    logs.push(Log {
+        log_type: LogType::Note(NoteType::ExpressionType(expr_type.to_string())),
+        line_and_col: Some((token.line, token.col)),
+        span: Some(token.span()),
+        notes: Vec::new(),
+    });
+    Expression::StringLiteral {
+        token,
+        value: expr_type.name(),
     }
+}
 
-    if tokens[*index].token_type == TokenType::RightParen {
-        *index += 1;
+// Removes the quotes around a string literal and replaces its escape sequences. These were checked by the lexer.
+fn unescape(literal: &str) -> String {
+    let mut value: String = String::new();
+    let mut chars = literal[1..literal.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            value.push(match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(other) => other,
+                None => break,
+            });
+        } else {
+            value.push(c);
+        }
+    }
+    value
+}
+
+// Handles the suffixes that can follow a type keyword, such as '?' for nullable types.
+fn get_type_suffix(tokens: &[Token], index: &mut usize, value: Type) -> Expression {
+    if tokens[*index].token_type == TokenType::QuestionMark {
+        *index += 1;
+        return Expression::Type {
+            value: Type::Nullable(Box::new(value)),
+        };
+    }
+    Expression::Type { value }
+}
+
+// Gets the span covering the token and the expressions, such as an operator and its operands.
+fn cover(token: Token, exprs: &[&Expression]) -> Span {
+    exprs
+        .iter()
+        .filter_map(|expr| expr.span())
+        .fold(token.span(), |span, next| span.to(&next))
+}
+
+// Gets the notes for an operator that has no definition over the types of its operands. Each operand is pointed at
+// with its type, and if changing the type of just one operand would match one of the definitions, the types it could
+// have are suggested.
+fn operand_notes(operands: &[(&str, &Expression)], definitions: &[&Vec<Type>]) -> Vec<Note> {
+    let input: Vec<Option<Type>> = operands.iter().map(|(_, operand)| operand.get_type()).collect();
+    let mut notes: Vec<Note> = Vec::new();
+    for (i, (description, operand)) in operands.iter().enumerate() {
+        let (Some(span), Some(operand_type)) = (operand.span(), &input[i]) else {
+            continue;
+        };
+        notes.push(Note {
+            log_type: LogType::Note(NoteType::OperandType(description.to_string(), operand_type.to_string())),
+            span: Some(span.clone()),
+        });
+        let mut types: Vec<String> = Vec::new();
+        for definition in definitions {
+            let others_match: bool = definition.len() == input.len()
+                && (0..input.len()).all(|j| j == i || input[j].as_ref() == Some(&definition[j]));
+            if others_match && !types.contains(&definition[i].to_string()) {
+                types.push(definition[i].to_string());
+            }
+        }
+        if !types.is_empty() {
+            notes.push(Note {
+                log_type: LogType::Help(HelpType::OperandNeedsType(description.to_string(), types)),
+                span: Some(span),
+            });
+        }
+    }
+    notes
+}
+
+// Gets a primary expression followed by any number of postfix operators.
+fn get_postfix(
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Expression {
+    let mut expr: Expression = get_primary(tokens, logs, index, source, var_list, depth);
+    // Postfix operators are not applied to expressions that already failed to parse.
+    while !expr.is_eof() && !matches!(expr, Expression::Null) {
+        let op: Token = tokens[*index];
+        match op.token_type {
+            TokenType::ExclamationMark => {
+                *index += 1;
+                let expr_type: Option<Type> = match expr.get_type() {
+                    Some(Type::Nullable(inner) | Type::Result(inner)) => Some(*inner),
+                    Some(other) => {
+                        logs.push(Log {
+                            log_type: LogType::Error(ErrorType::InvalidArgsForOperator(
+                                op.to_string(source),
+                                vec![other.to_string()],
+                            )),
+                            line_and_col: Some((op.line, op.col)),
+                            span: Some(cover(op, &[&expr])),
+                            notes: operand_notes(&[("operand", &expr)], &[]),
+                        });
+                        None
+                    }
+                    None => None,
+                };
+                expr = Expression::Postfix {
+                    op,
+                    expr: Box::new(expr),
+                    expr_type,
+                };
+            }
+            TokenType::QuestionMark => {
+                *index += 1;
+                let expr_type: Option<Type> = get_try_type(op, &expr, logs, source, var_list);
+                expr = Expression::Postfix {
+                    op,
+                    expr: Box::new(expr),
+                    expr_type,
+                };
+            }
+            TokenType::LeftBracket => {
+                expr = handle_index(expr, tokens, logs, index, source, var_list, depth);
+            }
+            TokenType::Dot => {
+                expr = handle_method_call(expr, tokens, logs, index, source, var_list, depth);
+            }
+            _ => break,
+        }
+    }
+    expr
+}
+
+// Gets the type of a '?' operator, which gives the value of a result or returns its error from the current function.
+// The function must return a result so that the error can be returned.
+fn get_try_type(
+    op: Token,
+    expr: &Expression,
+    logs: &mut Vec<Log>,
+    source: &str,
+    var_list: &HashMap<String, Expression>,
+) -> Option<Type> {
+    let value: Type = match expr.get_type()? {
+        Type::Result(value) => *value,
+        other => {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::InvalidArgsForOperator(
+                    op.to_string(source),
+                    vec![other.to_string()],
+                )),
+                line_and_col: Some((op.line, op.col)),
+                span: Some(cover(op, &[expr])),
+                notes: operand_notes(&[("operand", expr)], &[]),
+            });
+            return None;
+        }
+    };
+    if let Some(Expression::Type {
+        value: Type::Result(_),
+    }) = var_list.get(RETURN_TYPE_KEY)
+    {
+        Some(value)
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::TryOutsideResultFunction),
+            line_and_col: Some((op.line, op.col)),
+            span: Some(op.span()),
+            notes: Vec::new(),
+        });
+        None
+    }
+}
+
+// Handles indexing into a list with square brackets.
+fn handle_index(
+    expr: Expression,
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Expression {
+    let token: Token = tokens[*index];
+    *index += 1;
+    let list_index: Expression = get_expression(tokens, logs, index, source, var_list, depth);
+    if tokens[*index].token_type == TokenType::RightBracket {
+        *index += 1;
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::ExpectedCloseBracket),
+            line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+            span: Some(tokens[*index].span()),
+            notes: Vec::new(),
+        });
+    }
+
+    let expr_type: Option<Type> = match (expr.get_type(), list_index.get_type()) {
+        (Some(Type::List(element)), Some(Type::Int)) => Some(*element),
+        (Some(list_type), Some(index_type)) => {
+            // Only a list can be indexed, and only with an int.
+            let definition: Vec<Type> = vec![list_type.clone(), Type::Int];
+            let definitions: &[&Vec<Type>] = if matches!(list_type, Type::List(_)) { &[&definition] } else { &[] };
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::InvalidArgsForOperator(
+                    "[]".to_string(),
+                    vec![list_type.to_string(), index_type.to_string()],
+                )),
+                line_and_col: Some((token.line, token.col)),
+                span: Some(cover(token, &[&expr, &list_index])),
+                notes: operand_notes(&[("indexed value", &expr), ("index", &list_index)], definitions),
+            });
+            None
+        }
+        _ => None,
+    };
+    Expression::Index {
+        token,
+        expr: Box::new(expr),
+        index: Box::new(list_index),
+        expr_type,
+    }
+}
+
+// Wraps an expression in an implicit cast if its type can be coerced to the target type.
+fn coerce(expr: Expression, target: &Type) -> Expression {
+    match expr.get_type() {
+        Some(expr_type) if target.can_coerce_from(&expr_type) => Expression::Cast {
+            expr_type: Some(target.clone()),
+            expr: Box::new(expr),
+        },
+        _ => expr,
+    }
+}
+
+// Coerces the operands of an equality operator to a common type if possible.
+fn coerce_operands(left: Expression, right: Expression) -> (Expression, Expression) {
+    match (left.get_type(), right.get_type()) {
+        (Some(left_type), Some(right_type)) => {
+            if left_type.can_coerce_from(&right_type) {
+                (left, coerce(right, &left_type))
+            } else if right_type.can_coerce_from(&left_type) {
+                (coerce(left, &right_type), right)
+            } else {
+                (left, right)
+            }
+        }
+        _ => (left, right),
+    }
+}
+
+// Handles primary expressions that use parentheses.
+fn handle_paren(
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Expression {
+    if tokens[*index].token_type == TokenType::RightParen {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::ExpectedExpressionInParens),
+            line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+            span: Some(tokens[*index].span()),
+            notes: Vec::new(),
+        });
+        *index += 1;
+        return Expression::Grouping {
+            expr: Box::new(Expression::Null),
+            expr_type: None,
+        };
+    }
+    let expr: Expression = get_expression(tokens, logs, index, source, var_list, depth);
+    if matches!(expr, Expression::EOF) {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::ExpectedCloseParen),
+            line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+            span: Some(tokens[*index].span()),
+            notes: Vec::new(),
+        });
+        return expr;
+    }
+
+    if tokens[*index].token_type == TokenType::RightParen {
+        *index += 1;
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::ExpectedCloseParen),
+            line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+            span: Some(tokens[*index].span()),
+            notes: Vec::new(),
+        });
+        synchronize(tokens, index, Some(TokenType::RightParen));
+        return Expression::Grouping {
+            expr: Box::new(Expression::Error { token: tokens[*index - 1] }),
+            expr_type: None,
+        };
+    }
+    if let Expression::Type { value } = expr {
+        Expression::CastOp { expr_type: value }
+    } else {
+        let expr: Box<Expression> = Box::new(expr);
+        let expr_type: Option<Type> = expr.get_type();
+        Expression::Grouping { expr, expr_type }
+    }
+}
+
+// Handles calls to built-in functions.
+fn handle_call(
+    token: Token,
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Expression {
+    let args: Vec<Box<Expression>> = match get_call_args(tokens, logs, index, source, var_list, depth) {
+        Ok(args) => args,
+        Err(expr) => return *expr,
+    };
+
+    let name: String = token.to_string(source);
+    if let Some(declaration @ Expression::FunctionDeclaration { .. }) = var_list.get(&name) {
+        return handle_function_call(token, declaration, args, logs, source, var_list);
+    }
+    if let Some(Expression::ExternDeclaration { signature }) = var_list.get(&name) {
+        let Expression::FunctionCall {
+            args, expr_type, ..
+        } = handle_function_call(token, signature, args, logs, source, var_list)
+        else {
+            panic!("calls to function declarations are always function calls")
+        };
+        return Expression::HostCall {
+            token,
+            name,
+            args,
+            expr_type,
+        };
+    }
+    let Some(function) = Builtin::from_name(&name) else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::UnknownFunction(name)),
+            line_and_col: Some((token.line, token.col)),
+            span: Some(token.span()),
+            notes: Vec::new(),
+        });
+        logs.push(Log {
+            log_type: LogType::Help(HelpType::BuiltinFunctions(
+                Builtin::ALL
+                    .iter()
+                    .map(|function| format!("\"{}\"", function.name()))
+                    .collect(),
+            )),
+            line_and_col: Some((token.line, token.col)),
+            span: Some(token.span()),
+            notes: Vec::new(),
+        });
+        return Expression::Null;
+    };
+    let expr_type: Option<Type> = get_call_type(token, function, &args, logs);
+    Expression::Call {
+        token,
+        function,
+        args,
+        expr_type,
+    }
+}
+
+// Gets the arguments of a call, starting at the '('. If the end of the file is reached or the arguments aren't closed
+// by a ')', the error is reported and the expression to use instead of the call is returned.
+#[allow(clippy::vec_box)] // The arguments are moved into the call expression.
+fn get_call_args(
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> std::result::Result<Vec<Box<Expression>>, Box<Expression>> {
+    *index += 1; // Skip the '('.
+    let mut args: Vec<Box<Expression>> = Vec::new();
+    if tokens[*index].token_type == TokenType::RightParen {
+        *index += 1;
+        return Ok(args);
+    }
+    loop {
+        let arg: Expression = get_expression(tokens, logs, index, source, var_list, depth);
+        if arg.is_eof() {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::ExpectedCloseParen),
+                line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+                span: Some(tokens[*index].span()),
+                notes: Vec::new(),
+            });
+            return Err(Box::new(Expression::EOF));
+        }
+        args.push(Box::new(arg));
+        match tokens[*index].token_type {
+            TokenType::Comma => *index += 1,
+            TokenType::RightParen => {
+                *index += 1;
+                break;
+            }
+            _ => {
+                logs.push(Log {
+                    log_type: LogType::Error(ErrorType::ExpectedCloseParen),
+                    line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+                    span: Some(tokens[*index].span()),
+                    notes: Vec::new(),
+                });
+                // The arguments are incomplete, so the call isn't checked against them.
+                let token: Token = tokens[*index];
+                synchronize(tokens, index, Some(TokenType::RightParen));
+                return Err(Box::new(Expression::Error { token }));
+            }
+        }
+    }
+    Ok(args)
+}
+
+// Checks the arguments of a call to a built-in function and gets the type it outputs.
+fn get_call_type(
+    token: Token,
+    function: Builtin,
+    args: &[Box<Expression>],
+    logs: &mut Vec<Log>,
+) -> Option<Type> {
+    // Arguments without a type already have an error.
+    let arg_types: Vec<Type> = args
+        .iter()
+        .map(|arg| arg.get_type())
+        .collect::<Option<Vec<Type>>>()?;
+    if let Some((input, output)) = function.signature(&arg_types) {
+        if input == arg_types {
+            return Some(output);
+        }
+    }
+
+    let expected: Vec<String> = function.describe_args();
+    let error: ErrorType = if args.len() == expected.len() {
+        ErrorType::InvalidArgsForFunction(
+            function.name().to_string(),
+            arg_types.iter().map(ToString::to_string).collect(),
+        )
+    } else {
+        ErrorType::InvalidArgCountForFunction(
+            function.name().to_string(),
+            expected.len(),
+            args.len(),
+        )
+    };
+    push_call_error(logs, &token, error, function.name().to_string(), expected);
+    None
+}
+
+// Logs an error with a call, along with a note giving the signature of the function called.
+fn push_call_error(logs: &mut Vec<Log>, token: &Token, error: ErrorType, name: String, params: Vec<String>) {
+    logs.push(Log {
+        log_type: LogType::Error(error),
+        line_and_col: Some((token.line, token.col)),
+        span: Some(token.span()),
+        notes: Vec::new(),
+    });
+    logs.push(Log {
+        log_type: LogType::Note(NoteType::FunctionSignature(name, params)),
+        line_and_col: Some((token.line, token.col)),
+        span: Some(token.span()),
+        notes: Vec::new(),
+    });
+}
+
+// Handles map types and map literals, which start with the "map" keyword.
+fn handle_map(
+    token: Token,
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Expression {
+    let Some(map_type) = get_map_type(tokens, index) else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::ExpectedMapType),
+            line_and_col: Some((token.line, token.col)),
+            span: Some(token.span()),
+            notes: Vec::new(),
+        });
+        return Expression::Null;
+    };
+    if tokens[*index].token_type != TokenType::LeftBrace {
+        return Expression::Type { value: map_type };
+    }
+    *index += 1;
+
+    let mut entries: Vec<(Box<Expression>, Box<Expression>)> = Vec::new();
+    let mut expr_type: Option<Type> = Some(map_type.clone());
+    while tokens[*index].token_type != TokenType::RightBrace {
+        let key: Expression = get_expression(tokens, logs, index, source, var_list, depth);
+        if key.is_eof() {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::ExpectedCloseBrace),
+                line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+                span: Some(tokens[*index].span()),
+                notes: Vec::new(),
+            });
+            return key;
+        }
+        if tokens[*index].token_type != TokenType::Colon {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::ExpectedColonInMapEntry),
+                line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+                span: Some(tokens[*index].span()),
+                notes: Vec::new(),
+            });
+            return Expression::Null;
+        }
+        let colon: Token = tokens[*index];
+        *index += 1;
+        let value: Expression = get_expression(tokens, logs, index, source, var_list, depth);
+        if value.is_eof() {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::ExpectedCloseBrace),
+                line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+                span: Some(tokens[*index].span()),
+                notes: Vec::new(),
+            });
+            return value;
+        }
+
+        if let (Some(key_type), Some(value_type)) = (key.get_type(), value.get_type()) {
+            if Type::Map(Box::new(key_type.clone()), Box::new(value_type.clone())) != map_type {
+                logs.push(Log {
+                    log_type: LogType::Error(ErrorType::InvalidMapEntry(
+                        map_type.to_string(),
+                        [key_type.to_string(), value_type.to_string()],
+                    )),
+                    line_and_col: Some((colon.line, colon.col)),
+                    span: Some(colon.span()),
+                    notes: Vec::new(),
+                });
+                expr_type = None;
+            }
+        } else {
+            expr_type = None;
+        }
+        entries.push((Box::new(key), Box::new(value)));
+
+        match tokens[*index].token_type {
+            TokenType::Comma => *index += 1,
+            TokenType::RightBrace => {}
+            _ => {
+                logs.push(Log {
+                    log_type: LogType::Error(ErrorType::ExpectedCloseBrace),
+                    line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+                    span: Some(tokens[*index].span()),
+                    notes: Vec::new(),
+                });
+                return Expression::Null;
+            }
+        }
+    }
+    *index += 1;
+    Expression::MapLiteral {
+        token,
+        entries,
+        expr_type,
+    }
+}
+
+// Gets the type of the elements of a map or list from a type keyword. Only ints and bools can be elements.
+fn element_type(token: &Token) -> Option<Type> {
+    match token.token_type {
+        TokenType::Int => Some(Type::Int),
+        TokenType::Bool => Some(Type::Bool),
+        _ => None,
+    }
+}
+
+// Gets the "[key]value" part of a map type. Returns None if it is not a valid map type.
+fn get_map_type(tokens: &[Token], index: &mut usize) -> Option<Type> {
+    let is_type = |offset: usize, token_type: TokenType| {
+        tokens
+            .get(*index + offset)
+            .is_some_and(|token| token.token_type == token_type)
+    };
+    if !is_type(0, TokenType::LeftBracket) || !is_type(2, TokenType::RightBracket) {
+        return None;
+    }
+    let key: Type = element_type(&tokens[*index + 1])?;
+    let value: Type = element_type(tokens.get(*index + 3)?)?;
+    *index += 4;
+    Some(Type::Map(Box::new(key), Box::new(value)))
+}
+
+// Gets an element type written in angle brackets, such as the "<element>" part of a list type. Returns None if it is
+// not a valid element type in angle brackets.
+fn get_bracketed_element(
+    tokens: &[Token],
+    index: &mut usize,
+    element_type: fn(&Token) -> Option<Type>,
+) -> Option<Type> {
+    if tokens[*index].token_type != TokenType::Less
+        || tokens.get(*index + 2).map(|token| token.token_type) != Some(TokenType::Greater)
+    {
+        return None;
+    }
+    let element: Type = element_type(&tokens[*index + 1])?;
+    *index += 3;
+    Some(element)
+}
+
+// Gets the "<element>" part of a list type. Unlike other element types, the elements of a list can also be strings.
+// Returns None if it is not a valid list type.
+fn get_list_type(tokens: &[Token], index: &mut usize) -> Option<Type> {
+    let element = |token: &Token| match token.token_type {
+        TokenType::String => Some(Type::String),
+        _ => element_type(token),
+    };
+    Some(Type::List(Box::new(get_bracketed_element(tokens, index, element)?)))
+}
+
+// Gets the "<value>" part of a result type. Returns None if it is not a valid result type.
+fn get_result_type(tokens: &[Token], index: &mut usize) -> Option<Type> {
+    Some(Type::Result(Box::new(get_bracketed_element(tokens, index, element_type)?)))
+}
+
+// Handles list types and list literals, which start with the "list" keyword.
+fn handle_list(
+    token: Token,
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Expression {
+    let Some(list_type) = get_list_type(tokens, index) else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::ExpectedListType),
+            line_and_col: Some((token.line, token.col)),
+            span: Some(token.span()),
+            notes: Vec::new(),
+        });
+        return Expression::Null;
+    };
+    if tokens[*index].token_type != TokenType::LeftBrace {
+        return Expression::Type { value: list_type };
+    }
+    *index += 1;
+
+    let mut elements: Vec<Box<Expression>> = Vec::new();
+    let mut expr_type: Option<Type> = Some(list_type.clone());
+    while tokens[*index].token_type != TokenType::RightBrace {
+        let element_token: Token = tokens[*index];
+        let element: Expression = get_expression(tokens, logs, index, source, var_list, depth);
+        if element.is_eof() {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::ExpectedCloseBrace),
+                line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+                span: Some(tokens[*index].span()),
+                notes: Vec::new(),
+            });
+            return element;
+        }
+        if let Some(element_type) = element.get_type() {
+            if Type::List(Box::new(element_type.clone())) != list_type {
+                logs.push(Log {
+                    log_type: LogType::Error(ErrorType::InvalidListElement(
+                        list_type.to_string(),
+                        element_type.to_string(),
+                    )),
+                    line_and_col: Some((element_token.line, element_token.col)),
+                    span: Some(element_token.span()),
+                    notes: Vec::new(),
+                });
+                expr_type = None;
+            }
+        } else {
+            expr_type = None;
+        }
+        elements.push(Box::new(element));
+
+        match tokens[*index].token_type {
+            TokenType::Comma => *index += 1,
+            TokenType::RightBrace => {}
+            _ => {
+                logs.push(Log {
+                    log_type: LogType::Error(ErrorType::ExpectedCloseBrace),
+                    line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+                    span: Some(tokens[*index].span()),
+                    notes: Vec::new(),
+                });
+                return Expression::Null;
+            }
+        }
+    }
+    *index += 1;
+    Expression::ListLiteral {
+        token,
+        elements,
+        expr_type,
+    }
+}
+
+// Handles variable assignment.
+// Gets the type written in a function signature. Type parameters are looked up in the scope of the function.
+fn get_type_annotation(
+    tokens: &[Token],
+    index: &mut usize,
+    source: &str,
+    scope: &HashMap<String, Expression>,
+) -> Option<Type> {
+    let token: Token = tokens[*index];
+    *index += 1;
+    match token.token_type {
+        TokenType::Int | TokenType::Bool => {
+            let value: Type = element_type(&token)?;
+            if tokens[*index].token_type == TokenType::QuestionMark {
+                *index += 1;
+                return Some(Type::Nullable(Box::new(value)));
+            }
+            Some(value)
+        }
+        TokenType::String => Some(Type::String),
+        TokenType::Map => get_map_type(tokens, index),
+        TokenType::List => get_list_type(tokens, index),
+        TokenType::Result => get_result_type(tokens, index),
+        TokenType::Other => match scope.get(&token.to_string(source)) {
+            Some(Expression::Type { value }) => Some(value.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Checks that the next token has the given type and skips it. Otherwise, reports what was expected in the function
+// declaration.
+fn expect_in_function(
+    tokens: &[Token],
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    token_type: TokenType,
+    expected: &str,
+) -> Option<()> {
+    if tokens[*index].token_type == token_type {
+        *index += 1;
+        Some(())
     } else {
+        push_function_error(tokens[*index], logs, expected);
+        None
+    }
+}
+
+// Reports what was expected at a token in a function declaration.
+fn push_function_error(token: Token, logs: &mut Vec<Log>, expected: &str) {
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::InvalidFunctionDeclaration(expected.to_string())),
+        line_and_col: Some((token.line, token.col)),
+        span: Some(token.span()),
+        notes: Vec::new(),
+    });
+}
+
+// The key in the scope of a function body that holds the function's return type. It can't be a variable name.
+const RETURN_TYPE_KEY: &str = "->";
+
+// The kinds of function declarations. Methods take "self" as their first parameter, and the methods declared in a
+// trait and host functions have no body.
+#[derive(Clone, PartialEq)]
+enum FunctionKind {
+    Function,
+    Signature,
+    Extern,
+    Method(Type), // A method in an impl block for the given type.
+}
+
+// Skips the rest of a declaration that could not be read, up to the brace that closes its body, to avoid cascading
+// errors.
+fn skip_declaration(tokens: &[Token], index: &mut usize, start: usize) {
+    *index = start;
+    let mut depth: usize = 0;
+    while tokens[*index].token_type != TokenType::EOF {
+        *index += 1;
+        match tokens[*index - 1].token_type {
+            TokenType::LeftBrace => depth += 1,
+            TokenType::RightBrace if depth <= 1 => break,
+            TokenType::RightBrace => depth -= 1,
+            _ => {}
+        }
+    }
+}
+
+// Handles function declarations of the form "fn name<T: Trait, ...>(param: type, ...) -> type { body }". The type
+// parameters, their bounds, and the return type are optional. The body is only checked once, with the type
+// parameters left generic; the compiler creates an instance of it for each set of types the function is called with.
+fn handle_function(
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Expression {
+    let start: usize = *index;
+    get_function(tokens, logs, index, source, var_list, &FunctionKind::Function, depth).unwrap_or_else(|| {
+        skip_declaration(tokens, index, start);
+        Expression::Null
+    })
+}
+
+// Gets a function declaration. Returns None if the declaration could not be read.
+#[allow(clippy::too_many_lines)] // Each part of the declaration is read in order.
+fn get_function(
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    kind: &FunctionKind,
+    depth: usize,
+) -> Option<Expression> {
+    *index += 1; // Skip the "fn".
+    let token: Token = tokens[*index];
+    expect_in_function(tokens, logs, index, TokenType::Other, "a function name")?;
+    let name: String = token.to_string(source);
+
+    // Functions can only see other functions, traits, and flags, not the variables around them.
+    let mut scope: HashMap<String, Expression> = var_list
+        .iter()
+        .filter(|(_, expr)| {
+            matches!(
+                expr,
+                Expression::FunctionDeclaration { .. }
+                    | Expression::TraitDeclaration { .. }
+                    | Expression::FlagsDeclaration { .. }
+                    | Expression::ExternDeclaration { .. }
+            )
+        })
+        .map(|(name, expr)| (name.clone(), expr.clone()))
+        .collect();
+    let self_type: Option<Type> = match kind {
+        FunctionKind::Function | FunctionKind::Extern => None,
+        FunctionKind::Signature => Some(Type::Generic("Self".to_string())),
+        FunctionKind::Method(impl_type) => Some(impl_type.clone()),
+    };
+    if let Some(self_type) = &self_type {
+        scope.insert(
+            "Self".to_string(),
+            Expression::Type {
+                value: self_type.clone(),
+            },
+        );
+    }
+
+    let mut type_params: Vec<String> = Vec::new();
+    let mut type_bounds: Vec<Option<String>> = Vec::new();
+    if *kind == FunctionKind::Function && tokens[*index].token_type == TokenType::Less {
+        *index += 1;
+        loop {
+            let param: Token = tokens[*index];
+            expect_in_function(tokens, logs, index, TokenType::Other, "a type parameter")?;
+            let param_name: String = param.to_string(source);
+            type_params.push(param_name.clone());
+            scope.insert(
+                param_name.clone(),
+                Expression::Type {
+                    value: Type::Generic(param_name.clone()),
+                },
+            );
+            let mut type_bound: Option<String> = None;
+            if tokens[*index].token_type == TokenType::Colon {
+                *index += 1;
+                let bound: String = tokens[*index].to_string(source);
+                let Some(Expression::TraitDeclaration { methods, .. }) = var_list.get(&bound) else {
+                    logs.push(Log {
+                        log_type: LogType::Error(ErrorType::UnknownTrait(bound)),
+                        line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+                        span: Some(tokens[*index].span()),
+                        notes: Vec::new(),
+                    });
+                    return None;
+                };
+                *index += 1;
+                // The methods of the trait can be called on values of the type parameter.
+                for method in methods {
+                    scope.insert(
+                        format!("{param_name}.{}", declaration_name(method, source)),
+                        method_signature(method, &Type::Generic(param_name.clone())),
+                    );
+                }
+                type_bound = Some(bound);
+            }
+            type_bounds.push(type_bound);
+            if tokens[*index].token_type == TokenType::Comma {
+                *index += 1;
+            } else {
+                expect_in_function(tokens, logs, index, TokenType::Greater, "'>'")?;
+                break;
+            }
+        }
+    }
+
+    expect_in_function(tokens, logs, index, TokenType::LeftParen, "'('")?;
+    let mut params: Vec<Box<Expression>> = Vec::new();
+    let mut defaults: Vec<Box<Expression>> = Vec::new();
+    if let Some(self_type) = self_type {
+        let param: Token = tokens[*index];
+        if param.token_type != TokenType::Other || param.to_string(source) != "self" {
+            push_function_error(param, logs, "\"self\"");
+            return None;
+        }
+        *index += 1;
+        let var: Expression = Expression::Variable {
+            initialized: true,
+            token: param,
+            use_token: param,
+            expr_type: Some(self_type),
+        };
+        scope.insert("self".to_string(), var.clone());
+        params.push(Box::new(var));
+        if tokens[*index].token_type == TokenType::Comma {
+            *index += 1;
+        } else if tokens[*index].token_type != TokenType::RightParen {
+            expect_in_function(tokens, logs, index, TokenType::RightParen, "')'")?;
+        }
+    }
+    while tokens[*index].token_type != TokenType::RightParen {
+        let param: Token = tokens[*index];
+        expect_in_function(tokens, logs, index, TokenType::Other, "a parameter name")?;
+        expect_in_function(tokens, logs, index, TokenType::Colon, "':'")?;
+        let Some(param_type) = get_type_annotation(tokens, index, source, &scope) else {
+            push_function_error(tokens[*index - 1], logs, "a type");
+            return None;
+        };
+        if tokens[*index].token_type == TokenType::Equals {
+            *index += 1;
+            let default: Expression = get_expression(tokens, logs, index, source, &mut scope, depth);
+            if default.is_eof() {
+                push_function_error(tokens[*index], logs, "a default value");
+                return None;
+            }
+            defaults.push(Box::new(check_default(default, param, &param_type, logs, source)));
+        } else if !defaults.is_empty() {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::MissingDefault(param.to_string(source))),
+                line_and_col: Some((param.line, param.col)),
+                span: Some(param.span()),
+                notes: Vec::new(),
+            });
+        }
+        let var: Expression = Expression::Variable {
+            initialized: true,
+            token: param,
+            use_token: param,
+            expr_type: Some(param_type),
+        };
+        scope.insert(param.to_string(source), var.clone());
+        params.push(Box::new(var));
+        if tokens[*index].token_type == TokenType::Comma {
+            *index += 1;
+        } else if tokens[*index].token_type != TokenType::RightParen {
+            expect_in_function(tokens, logs, index, TokenType::RightParen, "')'")?;
+        }
+    }
+    *index += 1;
+
+    let mut return_type: Type = Type::Void;
+    if tokens[*index].token_type == TokenType::Arrow {
+        *index += 1;
+        let Some(value) = get_type_annotation(tokens, index, source, &scope) else {
+            push_function_error(tokens[*index - 1], logs, "a return type");
+            return None;
+        };
+        return_type = value;
+    }
+
+    // Type parameters must be inferred from the arguments of a call.
+    for type_param in &type_params {
+        let generic: Option<Type> = Some(Type::Generic(type_param.clone()));
+        if !params.iter().any(|param| param.get_type() == generic) {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::UninferableTypeParameter(
+                    type_param.clone(),
+                    name.clone(),
+                )),
+                line_and_col: Some((token.line, token.col)),
+                span: Some(token.span()),
+                notes: Vec::new(),
+            });
+        }
+    }
+    let mut declaration: Expression = Expression::FunctionDeclaration {
+        token,
+        type_params: type_params.clone(),
+        type_bounds,
+        params: params.clone(),
+        defaults,
+        return_type: return_type.clone(),
+        body: Box::new(Expression::Void),
+    };
+    match kind {
+        FunctionKind::Function => {
+            if var_list.contains_key(&name) {
+                logs.push(Log {
+                    log_type: LogType::Error(ErrorType::DuplicateFunction(name.clone())),
+                    line_and_col: Some((token.line, token.col)),
+                    span: Some(token.span()),
+                    notes: Vec::new(),
+                });
+            }
+            // The function is added to its own scope before its body is read so that it can call itself.
+            scope.insert(name.clone(), declaration.clone());
+        }
+        FunctionKind::Signature | FunctionKind::Extern => {
+            expect_in_function(tokens, logs, index, TokenType::Semicolon, "';'")?;
+            return Some(declaration);
+        }
+        // Methods are added to the variable list by their impl block.
+        FunctionKind::Method(_) => {}
+    }
+    expect_in_function(tokens, logs, index, TokenType::LeftBrace, "'{'")?;
+    scope.insert(
+        RETURN_TYPE_KEY.to_string(),
+        Expression::Type {
+            value: return_type.clone(),
+        },
+    );
+    let mut body: Expression = get_block(tokens, logs, index, source, &mut scope, depth)?;
+    check_return_type(token, &name, &mut body, &return_type, logs);
+
+    if let Expression::FunctionDeclaration { body: old_body, .. } = &mut declaration {
+        **old_body = body;
+    }
+    if *kind == FunctionKind::Function {
+        var_list.insert(name, declaration.clone());
+    }
+    Some(declaration)
+}
+
+// Checks that the default value of a parameter is a constant of the parameter's type, coercing it if needed.
+fn check_default(
+    default: Expression,
+    param: Token,
+    param_type: &Type,
+    logs: &mut Vec<Log>,
+    source: &str,
+) -> Expression {
+    let Some(default_type) = default.get_type() else {
+        return default; // The value already has an error.
+    };
+    let name: String = param.to_string(source);
+    let default: Expression = coerce(default, param_type);
+    if !is_constant(&default) {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::NonConstantDefault(name)),
+            line_and_col: Some((param.line, param.col)),
+            span: Some(param.span()),
+            notes: Vec::new(),
+        });
+    } else if default.get_type().as_ref() != Some(param_type) {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::InvalidDefaultType(
+                name,
+                param_type.to_string(),
+                default_type.to_string(),
+            )),
+            line_and_col: Some((param.line, param.col)),
+            span: Some(param.span()),
+            notes: Vec::new(),
+        });
+        return Expression::Null; // Calls that use the value shouldn't repeat the error.
+    }
+    default
+}
+
+// Returns whether or not the expression is made only of literals, so that it has the same value wherever it is used.
+fn is_constant(expr: &Expression) -> bool {
+    match expr {
+        Expression::Literal { .. } | Expression::StringLiteral { .. } => true,
+        Expression::Binary { left, right, .. } => is_constant(left) && is_constant(right),
+        Expression::Cast { expr, .. } | Expression::Grouping { expr, .. } | Expression::Unary { expr, .. } => {
+            is_constant(expr)
+        }
+        _ => false,
+    }
+}
+
+// Checks that the body of a function has its return type, coercing the last expression of the body if needed.
+fn check_return_type(
+    token: Token,
+    name: &str,
+    body: &mut Expression,
+    return_type: &Type,
+    logs: &mut Vec<Log>,
+) {
+    if let (Some(body_type), Expression::ExpressionList { list }) = (body.get_type(), &mut *body) {
+        if body_type != *return_type {
+            if let Some(last) = list.last_mut() {
+                **last = coerce(*last.clone(), return_type);
+            }
+        }
+    }
+    if let Some(body_type) = body.get_type() {
+        if body_type != *return_type {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::InvalidReturnType(
+                    name.to_string(),
+                    return_type.to_string(),
+                    body_type.to_string(),
+                )),
+                line_and_col: Some((token.line, token.col)),
+                span: Some(token.span()),
+                notes: Vec::new(),
+            });
+        }
+    }
+}
+
+// Gets the statements in a block, up to and including the closing brace.
+fn get_block(
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Option<Expression> {
+    let mut list: Vec<Box<Expression>> = Vec::new();
+    while tokens[*index].token_type != TokenType::RightBrace {
+        if tokens[*index].token_type == TokenType::EOF {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::ExpectedCloseBrace),
+                line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+                span: Some(tokens[*index].span()),
+                notes: Vec::new(),
+            });
+            return None;
+        }
+        let expr: Expression = get_statement(tokens, logs, index, source, var_list, depth);
+        if expr.is_eof() {
+            return None;
+        }
+        let is_stmt: bool = matches!(expr, Expression::Statement { .. });
+        list.push(Box::new(expr));
+        if !is_stmt {
+            if tokens[*index].token_type != TokenType::RightBrace {
+                let token: Token = tokens[*index];
+                logs.push(Log {
+                    log_type: LogType::Error(ErrorType::ExpectedCloseBrace),
+                    line_and_col: Some((token.line, token.col)),
+                    span: Some(token.span()),
+                    notes: Vec::new(),
+                });
+                // The rest of the statement is skipped so that the rest of the block is still parsed.
+                synchronize(tokens, index, None);
+                if tokens[*index].token_type == TokenType::EOF {
+                    return None;
+                }
+                list.push(Box::new(Expression::Error { token }));
+                continue;
+            }
+            break;
+        }
+    }
+    *index += 1;
+    Some(Expression::ExpressionList { list })
+}
+
+// Checks that the next token has the given type and skips it. Otherwise, reports the error.
+fn expect_in_declaration(
+    tokens: &[Token],
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    token_type: TokenType,
+    error: ErrorType,
+) -> Option<()> {
+    if tokens[*index].token_type == token_type {
+        *index += 1;
+        Some(())
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(error),
+            line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+            span: Some(tokens[*index].span()),
+            notes: Vec::new(),
+        });
+        None
+    }
+}
+
+// Gets the name of a function declaration.
+fn declaration_name(declaration: &Expression, source: &str) -> String {
+    let Expression::FunctionDeclaration { token, .. } = declaration else {
+        panic!("only function declarations have names")
+    };
+    token.to_string(source)
+}
+
+// Gets the signature of a trait method with "Self" replaced by the given type.
+fn method_signature(signature: &Expression, self_type: &Type) -> Expression {
+    let Expression::FunctionDeclaration {
+        token,
+        type_params,
+        type_bounds,
+        params,
+        defaults,
+        return_type,
+        body,
+    } = signature
+    else {
+        panic!("trait methods are function declarations")
+    };
+    let substitutions: HashMap<String, Type> =
+        HashMap::from([("Self".to_string(), self_type.clone())]);
+    Expression::FunctionDeclaration {
+        token: *token,
+        type_params: type_params.clone(),
+        type_bounds: type_bounds.clone(),
+        params: params
+            .iter()
+            .map(|param| Box::new(param.substitute_types(&substitutions)))
+            .collect(),
+        defaults: defaults.clone(),
+        return_type: return_type.substitute(&substitutions),
+        body: body.clone(),
+    }
+}
+
+// Handles declarations of host functions of the form "extern fn name(param: int, ...) -> int;". They are called like
+// other functions, but run the function with the same name that the program's host registered with the VM. The VM
+// passes ints to host functions and gets an int back, so those are the only types they can use.
+fn handle_extern(
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Expression {
+    *index += 1; // Skip the "extern".
+    if tokens[*index].token_type != TokenType::Fn {
+        push_function_error(tokens[*index], logs, "\"fn\"");
+        skip_to_semicolon(tokens, index);
+        return Expression::Null;
+    }
+    let token: Token = tokens[*index + 1];
+    let name: String = token.to_string(source);
+    let duplicate: bool = var_list.contains_key(&name);
+    let Some(signature) = get_function(tokens, logs, index, source, var_list, &FunctionKind::Extern, depth) else {
+        skip_to_semicolon(tokens, index);
+        return Expression::Null;
+    };
+    check_extern(&signature, token, name.clone(), duplicate, logs);
+    let declaration: Expression = Expression::ExternDeclaration {
+        signature: Box::new(signature),
+    };
+    var_list.insert(name, declaration.clone());
+    declaration
+}
+
+// Skips the rest of a declaration that could not be read, up to the semicolon that ends it, to avoid cascading errors.
+fn skip_to_semicolon(tokens: &[Token], index: &mut usize) {
+    while ![TokenType::Semicolon, TokenType::EOF].contains(&tokens[*index].token_type) {
+        *index += 1;
+    }
+}
+
+// Reports a host function declared with the name of another declaration, or with types the VM can't pass to it.
+fn check_extern(signature: &Expression, token: Token, name: String, duplicate: bool, logs: &mut Vec<Log>) {
+    let Expression::FunctionDeclaration {
+        params,
+        return_type,
+        ..
+    } = signature
+    else {
+        return;
+    };
+    let error: Option<ErrorType> = if duplicate {
+        Some(ErrorType::DuplicateFunction(name))
+    } else if params.len() > usize::from(u8::MAX)
+        || params.iter().any(|param| param.get_type() != Some(Type::Int))
+        || *return_type != Type::Int
+    {
+        Some(ErrorType::InvalidHostFunction(name))
+    } else {
+        None
+    };
+    if let Some(error) = error {
+        logs.push(Log {
+            log_type: LogType::Error(error),
+            line_and_col: Some((token.line, token.col)),
+            span: Some(token.span()),
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Handles trait declarations of the form "trait Name { fn method(self, param: type, ...) -> type; ... }". Within the
+// trait, "Self" is the type that implements it.
+fn handle_trait(
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Expression {
+    let start: usize = *index;
+    get_trait(tokens, logs, index, source, var_list, depth).unwrap_or_else(|| {
+        skip_declaration(tokens, index, start);
+        Expression::Null
+    })
+}
+
+// Gets a trait declaration. Returns None if the declaration could not be read.
+fn get_trait(
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Option<Expression> {
+    *index += 1; // Skip the "trait".
+    let token: Token = tokens[*index];
+    let expected = |expected: &str| ErrorType::InvalidTraitDeclaration(expected.to_string());
+    expect_in_declaration(tokens, logs, index, TokenType::Other, expected("a trait name"))?;
+    let name: String = token.to_string(source);
+    if var_list.contains_key(&name) {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::DuplicateTrait(name.clone())),
+            line_and_col: Some((token.line, token.col)),
+            span: Some(token.span()),
+            notes: Vec::new(),
+        });
+    }
+
+    expect_in_declaration(tokens, logs, index, TokenType::LeftBrace, expected("'{'"))?;
+    let mut methods: Vec<Box<Expression>> = Vec::new();
+    while tokens[*index].token_type != TokenType::RightBrace {
+        if tokens[*index].token_type != TokenType::Fn {
+            logs.push(Log {
+                log_type: LogType::Error(expected("\"fn\" or '}'")),
+                line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+                span: Some(tokens[*index].span()),
+                notes: Vec::new(),
+            });
+            return None;
+        }
+        let method_token: Token = tokens[*index + 1];
+        let method: Expression =
+            get_function(tokens, logs, index, source, var_list, &FunctionKind::Signature, depth)?;
+        let method_name: String = method_token.to_string(source);
+        if methods
+            .iter()
+            .any(|other| declaration_name(other, source) == method_name)
+        {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::DuplicateFunction(method_name)),
+                line_and_col: Some((method_token.line, method_token.col)),
+                span: Some(method_token.span()),
+                notes: Vec::new(),
+            });
+        }
+        methods.push(Box::new(method));
+    }
+    *index += 1;
+
+    let declaration: Expression = Expression::TraitDeclaration { token, methods };
+    var_list.insert(name, declaration.clone());
+    Some(declaration)
+}
+
+// Handles flags declarations of the form "flags Name { FLAG = value, ... }", which group related constant ints. Each
+// flag is used as "Name.FLAG" and has the type "int", so flags can be combined with the bitwise operators. The value
+// of a flag can use the flags declared before it.
+fn handle_flags(
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Expression {
+    let start: usize = *index;
+    get_flags(tokens, logs, index, source, var_list, depth).unwrap_or_else(|| {
+        skip_declaration(tokens, index, start);
+        Expression::Null
+    })
+}
+
+// Gets a flags declaration. Returns None if the declaration could not be read.
+fn get_flags(
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Option<Expression> {
+    *index += 1; // Skip the "flags".
+    let token: Token = tokens[*index];
+    let expected = |expected: &str| ErrorType::InvalidFlagsDeclaration(expected.to_string());
+    expect_in_declaration(tokens, logs, index, TokenType::Other, expected("a name"))?;
+    let name: String = token.to_string(source);
+    if var_list.contains_key(&name) {
         logs.push(Log {
-            log_type: LogType::Error(ErrorType::ExpectedCloseParen),
-            line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+            log_type: LogType::Error(ErrorType::DuplicateTrait(name.clone())),
+            line_and_col: Some((token.line, token.col)),
+            span: Some(token.span()),
+            notes: Vec::new(),
         });
     }
-    if let Expression::Type { value } = expr {
-        Expression::CastOp { expr_type: value }
+
+    expect_in_declaration(tokens, logs, index, TokenType::LeftBrace, expected("'{'"))?;
+    let mut members: Vec<(Token, Box<Expression>)> = Vec::new();
+    while tokens[*index].token_type != TokenType::RightBrace {
+        let flag: Token = tokens[*index];
+        expect_in_declaration(tokens, logs, index, TokenType::Other, expected("a flag name or '}'"))?;
+        expect_in_declaration(tokens, logs, index, TokenType::Equals, expected("'='"))?;
+        let value: Expression = get_expression(tokens, logs, index, source, var_list, depth);
+        if value.is_eof() {
+            return None;
+        }
+        let flag_name: String = flag.to_string(source);
+        if members.iter().any(|(other, _)| other.to_string(source) == flag_name) {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::DuplicateFlag(name.clone(), flag_name)),
+                line_and_col: Some((flag.line, flag.col)),
+                span: Some(flag.span()),
+                notes: Vec::new(),
+            });
+        } else {
+            members.push((flag, Box::new(check_flag(value, flag, logs, source))));
+            // The declaration so far is visible to the values of the flags after it.
+            var_list.insert(
+                name.clone(),
+                Expression::FlagsDeclaration {
+                    token,
+                    members: members.clone(),
+                },
+            );
+        }
+        if tokens[*index].token_type != TokenType::Comma {
+            break;
+        }
+        *index += 1;
+    }
+    expect_in_declaration(tokens, logs, index, TokenType::RightBrace, expected("',' or '}'"))?;
+
+    let declaration: Expression = Expression::FlagsDeclaration { token, members };
+    var_list.insert(name, declaration.clone());
+    Some(declaration)
+}
+
+// Checks that the value of a flag is a constant int.
+fn check_flag(value: Expression, flag: Token, logs: &mut Vec<Log>, source: &str) -> Expression {
+    match value.get_type() {
+        None => value, // The value already has an error.
+        Some(Type::Int) if is_constant(&value) => value,
+        Some(_) => {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::NonConstantFlag(flag.to_string(source))),
+                line_and_col: Some((flag.line, flag.col)),
+                span: Some(flag.span()),
+                notes: Vec::new(),
+            });
+            Expression::Null // Uses of the flag shouldn't repeat the error.
+        }
+    }
+}
+
+// Gets a flag of a flags declaration, which is used as its constant value. The index should be at the '.' after the
+// name of the flags.
+fn get_flag(
+    token: Token,
+    members: &[(Token, Box<Expression>)],
+    tokens: &[Token],
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &str,
+) -> Expression {
+    let name: String = token.to_string(source);
+    let flag: Option<Token> = tokens.get(*index + 1).copied();
+    let Some(flag) = flag.filter(|flag| {
+        tokens[*index].token_type == TokenType::Dot && flag.token_type == TokenType::Other
+    }) else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FlagsUsedAsValue(name)),
+            line_and_col: Some((token.line, token.col)),
+            span: Some(token.span()),
+            notes: Vec::new(),
+        });
+        return Expression::Null;
+    };
+    *index += 2;
+    let flag_name: String = flag.to_string(source);
+    let Some((_, value)) = members
+        .iter()
+        .find(|(other, _)| other.to_string(source) == flag_name)
+    else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::UnknownFlag(name, flag_name)),
+            line_and_col: Some((flag.line, flag.col)),
+            span: Some(flag.span()),
+            notes: Vec::new(),
+        });
+        return Expression::Null;
+    };
+    Expression::Grouping {
+        expr: value.clone(),
+        expr_type: value.get_type(),
+    }
+}
+
+// Handles impl blocks of the form "impl Trait for type { fn method(self, param: type, ...) -> type { body } ... }",
+// which implement every method of the trait for the type.
+fn handle_impl(
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Expression {
+    let start: usize = *index;
+    get_impl(tokens, logs, index, source, var_list, depth).unwrap_or_else(|| {
+        skip_declaration(tokens, index, start);
+        Expression::Null
+    })
+}
+
+// Gets an impl block. Returns None if the block could not be read or is missing methods.
+fn get_impl(
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Option<Expression> {
+    *index += 1; // Skip the "impl".
+    let token: Token = tokens[*index];
+    let expected = |expected: &str| ErrorType::InvalidImplDeclaration(expected.to_string());
+    expect_in_declaration(tokens, logs, index, TokenType::Other, expected("a trait name"))?;
+    let trait_name: String = token.to_string(source);
+    let Some(Expression::TraitDeclaration {
+        token: trait_token,
+        methods: signatures,
+    }) = var_list.get(&trait_name).cloned()
+    else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::UnknownTrait(trait_name)),
+            line_and_col: Some((token.line, token.col)),
+            span: Some(token.span()),
+            notes: Vec::new(),
+        });
+        return None;
+    };
+    expect_in_declaration(tokens, logs, index, TokenType::For, expected("\"for\""))?;
+    let Some(impl_type) = get_type_annotation(tokens, index, source, &HashMap::new()) else {
+        logs.push(Log {
+            log_type: LogType::Error(expected("a type")),
+            line_and_col: Some((tokens[*index - 1].line, tokens[*index - 1].col)),
+            span: Some(tokens[*index - 1].span()),
+            notes: Vec::new(),
+        });
+        return None;
+    };
+
+    // The methods are added to the variable list before their bodies are read so that they can call each other.
+    register_methods(token, &signatures, &impl_type, logs, source, var_list)?;
+
+    expect_in_declaration(tokens, logs, index, TokenType::LeftBrace, expected("'{'"))?;
+    let mut methods: Vec<Option<Box<Expression>>> = vec![None; signatures.len()];
+    let mut is_valid: bool = true;
+    while tokens[*index].token_type != TokenType::RightBrace {
+        if tokens[*index].token_type != TokenType::Fn {
+            logs.push(Log {
+                log_type: LogType::Error(expected("\"fn\" or '}'")),
+                line_and_col: Some((tokens[*index].line, tokens[*index].col)),
+                span: Some(tokens[*index].span()),
+                notes: Vec::new(),
+            });
+            return None;
+        }
+        let method_token: Token = tokens[*index + 1];
+        let kind: FunctionKind = FunctionKind::Method(impl_type.clone());
+        let method: Expression = get_function(tokens, logs, index, source, var_list, &kind, depth)?;
+        let method_name: String = method_token.to_string(source);
+        let position: Option<usize> = signatures
+            .iter()
+            .position(|signature| declaration_name(signature, source) == method_name);
+        let error: Option<ErrorType> = match position {
+            None => Some(ErrorType::InvalidTraitMethod(trait_name.clone(), method_name)),
+            Some(position) if methods[position].is_some() => Some(ErrorType::DuplicateMethod(
+                impl_type.to_string(),
+                method_name,
+            )),
+            Some(position) => {
+                let signature: Expression = method_signature(&signatures[position], &impl_type);
+                let error: Option<ErrorType> = (!same_signature(&method, &signature)).then(|| {
+                    ErrorType::MismatchedMethodSignature(method_name, trait_name.clone())
+                });
+                methods[position] = Some(Box::new(method));
+                error
+            }
+        };
+        if let Some(error) = error {
+            logs.push(Log {
+                log_type: LogType::Error(error),
+                line_and_col: Some((method_token.line, method_token.col)),
+                span: Some(method_token.span()),
+                notes: Vec::new(),
+            });
+            is_valid = false;
+        }
+    }
+    *index += 1;
+
+    for (signature, method) in signatures.iter().zip(&methods) {
+        if method.is_none() {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::MissingTraitMethod(
+                    trait_name.clone(),
+                    declaration_name(signature, source),
+                    impl_type.to_string(),
+                )),
+                line_and_col: Some((token.line, token.col)),
+                span: Some(token.span()),
+                notes: Vec::new(),
+            });
+        }
+    }
+    if !is_valid || methods.iter().any(Option::is_none) {
+        return Some(Expression::Null);
+    }
+    Some(Expression::ImplDeclaration {
+        trait_token,
+        impl_type,
+        methods: methods.into_iter().flatten().collect(),
+    })
+}
+
+// Adds the signatures of the methods of a trait to the variable list for the type implementing it. Methods are found
+// using keys of the form "type.method", which can't conflict with other names.
+fn register_methods(
+    token: Token,
+    signatures: &[Box<Expression>],
+    impl_type: &Type,
+    logs: &mut Vec<Log>,
+    source: &str,
+    var_list: &mut HashMap<String, Expression>,
+) -> Option<()> {
+    for signature in signatures {
+        let method_name: String = declaration_name(signature, source);
+        let key: String = format!("{}.{method_name}", impl_type.name());
+        if var_list.contains_key(&key) {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::DuplicateMethod(
+                    impl_type.to_string(),
+                    method_name,
+                )),
+                line_and_col: Some((token.line, token.col)),
+                span: Some(token.span()),
+                notes: Vec::new(),
+            });
+            return None;
+        }
+        var_list.insert(key, method_signature(signature, impl_type));
+    }
+    Some(())
+}
+
+// Returns whether or not two function declarations have the same parameter and return types.
+fn same_signature(first: &Expression, second: &Expression) -> bool {
+    let signature = |declaration: &Expression| match declaration {
+        Expression::FunctionDeclaration {
+            params,
+            return_type,
+            ..
+        } => (
+            params
+                .iter()
+                .map(|param| param.get_type())
+                .collect::<Vec<Option<Type>>>(),
+            return_type.clone(),
+        ),
+        _ => panic!("only function declarations have signatures"),
+    };
+    signature(first) == signature(second)
+}
+
+// Handles calls to functions declared in the code. The types of the arguments are used to bind the type parameters
+// of the function, which must be bound to the same type everywhere they are used and implement the trait bounding
+// them.
+#[allow(clippy::vec_box)] // The arguments are moved into the call expression.
+fn handle_function_call(
+    token: Token,
+    declaration: &Expression,
+    mut args: Vec<Box<Expression>>,
+    logs: &mut Vec<Log>,
+    source: &str,
+    var_list: &HashMap<String, Expression>,
+) -> Expression {
+    let Expression::FunctionDeclaration {
+        token: declaration,
+        type_params,
+        type_bounds,
+        params,
+        defaults,
+        return_type,
+        ..
+    } = declaration
+    else {
+        panic!("only function declarations can be called")
+    };
+    // Trailing arguments that are left out take their default values.
+    let required: usize = params.len() - defaults.len();
+    if (required..params.len()).contains(&args.len()) {
+        args.extend(defaults[args.len() - required..].iter().cloned());
+    }
+    let name: String = token.to_string(source);
+    let param_types: Vec<Type> = params
+        .iter()
+        .map(|param| param.get_type().expect("parameters always have a type"))
+        .collect();
+    let mut substitutions: HashMap<String, Type> = HashMap::new();
+    let mut error: Option<ErrorType> = None;
+    let mut arg_has_error: bool = false;
+    if args.len() == params.len() {
+        for (arg, param_type) in args.iter_mut().zip(&param_types) {
+            let Some(arg_type) = arg.get_type() else {
+                arg_has_error = true; // The argument already has an error.
+                continue;
+            };
+            if let Type::Generic(type_param) = param_type {
+                if [Type::Null, Type::Void, Type::Type].contains(&arg_type) {
+                    error = Some(ErrorType::InvalidTypeArgument(
+                        type_param.clone(),
+                        arg_type.to_string(),
+                    ));
+                } else if let Some(bound) = substitutions.get(type_param) {
+                    if *bound != arg_type {
+                        error = Some(ErrorType::InconsistentTypeArgument(
+                            type_param.clone(),
+                            name.clone(),
+                            [bound.to_string(), arg_type.to_string()],
+                        ));
+                    }
+                } else {
+                    substitutions.insert(type_param.clone(), arg_type);
+                }
+            } else {
+                **arg = coerce(*arg.clone(), param_type);
+                if arg.get_type().as_ref() != Some(param_type) {
+                    error = Some(ErrorType::InvalidArgsForFunction(
+                        name.clone(),
+                        args_to_strings(&args),
+                    ));
+                    break;
+                }
+            }
+        }
     } else {
-        let expr: Box<Expression> = Box::new(expr);
-        let expr_type: Option<Type> = expr.get_type();
-        Expression::Grouping { expr, expr_type }
+        error = Some(arg_count_error(name.clone(), required, params.len(), args.len()));
+    }
+    for (type_param, type_bound) in type_params.iter().zip(type_bounds) {
+        if let (Some(type_arg), Some(type_bound)) = (substitutions.get(type_param), type_bound) {
+            if error.is_none() && !implements(type_arg, type_bound, var_list, source) {
+                error = Some(ErrorType::UnsatisfiedTraitBound(
+                    type_arg.to_string(),
+                    type_bound.clone(),
+                    name.clone(),
+                ));
+            }
+        }
+    }
+
+    let expr_type: Option<Type> = match error {
+        None if arg_has_error => None,
+        None => Some(return_type.substitute(&substitutions)),
+        Some(error) => {
+            let params: Vec<String> = param_types.iter().map(ToString::to_string).collect();
+            push_call_error(logs, &token, error, name, params);
+            None
+        }
+    };
+    Expression::FunctionCall {
+        token,
+        declaration: *declaration,
+        args,
+        type_args: type_params
+            .iter()
+            .map(|type_param| substitutions.get(type_param).cloned().unwrap_or(Type::Void))
+            .collect(),
+        expr_type,
     }
 }
 
-// Handles variable assignment.
+// Gets the error for a call with the wrong number of arguments, where only the first required parameters have no
+// default value.
+fn arg_count_error(name: String, required: usize, param_count: usize, arg_count: usize) -> ErrorType {
+    if required == param_count {
+        ErrorType::InvalidArgCountForFunction(name, param_count, arg_count)
+    } else {
+        ErrorType::InvalidArgRangeForFunction(name, required, param_count, arg_count)
+    }
+}
+
+// Returns whether or not the type implements every method of the trait.
+fn implements(
+    value_type: &Type,
+    trait_name: &str,
+    var_list: &HashMap<String, Expression>,
+    source: &str,
+) -> bool {
+    let Some(Expression::TraitDeclaration { methods, .. }) = var_list.get(trait_name) else {
+        return false;
+    };
+    methods.iter().all(|method| {
+        let Expression::FunctionDeclaration { token, .. } = **method else {
+            return false;
+        };
+        let key: String = format!("{}.{}", value_type.name(), token.to_string(source));
+        matches!(var_list.get(&key), Some(Expression::FunctionDeclaration { token: other, .. }) if *other == token)
+    })
+}
+
+// Handles method calls of the form "value.method(args)". The method is found at compile time using the type of the
+// value, which is passed as the first argument.
+fn handle_method_call(
+    receiver: Expression,
+    tokens: &Vec<Token>,
+    logs: &mut Vec<Log>,
+    index: &mut usize,
+    source: &String,
+    var_list: &mut HashMap<String, Expression>,
+    depth: usize,
+) -> Expression {
+    *index += 1; // Skip the '.'.
+    let token: Token = tokens[*index];
+    if token.token_type != TokenType::Other || tokens[*index + 1].token_type != TokenType::LeftParen {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::UnexpectedToken(token.to_string(source))),
+            line_and_col: Some((token.line, token.col)),
+            span: Some(token.span()),
+            notes: Vec::new(),
+        });
+        return Expression::Null;
+    }
+    *index += 1;
+    let mut args: Vec<Box<Expression>> = match get_call_args(tokens, logs, index, source, var_list, depth) {
+        Ok(args) => args,
+        Err(expr) => return *expr,
+    };
+    let Some(receiver_type) = receiver.get_type() else {
+        return Expression::Null; // The value already has an error.
+    };
+    let method: String = token.to_string(source);
+    let Some(signature @ Expression::FunctionDeclaration { .. }) =
+        var_list.get(&format!("{}.{method}", receiver_type.name()))
+    else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::UnknownMethod(receiver_type.to_string(), method)),
+            line_and_col: Some((token.line, token.col)),
+            span: Some(token.span()),
+            notes: Vec::new(),
+        });
+        return Expression::Null;
+    };
+    args.insert(0, Box::new(receiver));
+    match handle_function_call(token, signature, args, logs, source, var_list) {
+        Expression::FunctionCall {
+            token,
+            declaration,
+            args,
+            expr_type,
+            ..
+        } => Expression::MethodCall {
+            token,
+            method: declaration,
+            args,
+            expr_type,
+        },
+        _ => panic!("function calls are handled as function calls"),
+    }
+}
+
+// Gets the types of the arguments of a call as strings for diagnostics.
+fn args_to_strings(args: &[Box<Expression>]) -> Vec<String> {
+    args.iter()
+        .map(|arg| arg.get_type().map_or("?".to_string(), |arg_type| arg_type.to_string()))
+        .collect()
+}
+
 fn handle_assignment(
     tokens: &Vec<Token>,
     logs: &mut Vec<Log>,
     index: &mut usize,
     source: &String,
     var_list: &mut HashMap<String, Expression>,
+    depth: usize,
 ) -> Option<Expression> {
-    let mut expr: Expression = get_variable_declaration(tokens, logs, index, source, var_list)?;
+    let mut expr: Expression = get_variable_declaration(tokens, logs, index, source, var_list, depth)?;
     let mut var: Expression = expr.clone();
     let mut is_declaration: bool = false;
     if let Expression::VariableDeclaration { initialized_var } = var {
@@ -598,14 +3342,19 @@ fn handle_assignment(
         is_declaration = true;
     }
     if let Expression::Variable {
-        token, expr_type, ..
+        token,
+        ref expr_type,
+        ..
     } = var
     {
         let op = tokens[*index];
         if let TokenType::Equals = tokens[*index].token_type {
             *index += 1;
-            let mut expr_type: Option<Type> = expr_type;
-            let assignment: Expression = get_expression(tokens, logs, index, source, var_list);
+            let mut expr_type: Option<Type> = expr_type.clone();
+            let mut assignment: Expression = get_expression(tokens, logs, index, source, var_list, depth);
+            if let Some(target) = &expr_type {
+                assignment = coerce(assignment, target);
+            }
             if assignment.get_type() != expr_type {
                 if assignment.get_type().is_some() && expr_type.is_some() {
                     logs.push(Log {
@@ -614,6 +3363,8 @@ fn handle_assignment(
                             [expr_type?.to_string(), assignment.get_type()?.to_string()], // Both types are not null here.
                         )),
                         line_and_col: Some((op.line, op.col)),
+                        span: Some(cover(op, &[&expr, &assignment])),
+                        notes: Vec::new(),
                     });
                 }
                 expr_type = None;
@@ -640,17 +3391,22 @@ fn get_variable_declaration(
     index: &mut usize,
     source: &String,
     var_list: &mut HashMap<String, Expression>,
+    depth: usize,
 ) -> Option<Expression> {
     let old_index: usize = *index;
-    let expr: Expression = get_operators(tokens, logs, index, 0, source, var_list)?;
-    if let Expression::Type { value } = expr {
+    let expr: Expression = get_operators(tokens, logs, index, 0, source, var_list, depth)?;
+    if let Expression::Type { ref value } = expr {
+        let value: Type = value.clone();
         if tokens[*index].token_type == TokenType::RightParen { return Some(expr); }
-        let var: Option<Expression> = get_operators(tokens, logs, index, 0, source, var_list);
+        let token: Token = tokens[*index];
+        let var: Option<Expression> = get_operators(tokens, logs, index, 0, source, var_list, depth);
         if let Some(var) = var {
-            if let Expression::Variable { token, .. } = var {
+            // A variable that shadows another one has the same name but its own token.
+            if let Expression::Variable { .. } = var {
                 let new_var: Expression = Expression::Variable {
                     initialized: true,
                     token,
+                    use_token: token,
                     expr_type: Some(value),
                 };
                 return Some(Expression::VariableDeclaration {
@@ -661,6 +3417,8 @@ fn get_variable_declaration(
             logs.push(Log {
                 log_type: LogType::Error(ErrorType::ExpectedVariableDeclaration(value.to_string())),
                 line_and_col: Some((tokens[old_index].line, tokens[old_index].col)),
+                span: Some(tokens[old_index].span()),
+                notes: Vec::new(),
             });
             return var;
         }
@@ -677,59 +3435,227 @@ fn get_operators(
     precendence: usize,
     source: &String,
     var_list: &mut HashMap<String, Expression>,
+    depth: usize,
 ) -> Option<Expression> {
-    let operator_list: &[OpList] = &OpList::get_op_lists();
+    if let Some(error) = check_depth(tokens, logs, index, depth) {
+        return Some(error);
+    }
+    let operator_list: &[OpList] = OpList::op_lists();
     if precendence >= operator_list.len() {
-        get_cast(tokens, logs, index, source, var_list)
+        get_cast(tokens, logs, index, source, var_list, depth)
     } else if operator_list[precendence].arg_count()? == 1 {
-        if operator_list[precendence].contains(tokens[*index].token_type) {
-            let op: Token = tokens[*index];
+        let Some(&op) = peek_token(tokens, *index, logs) else {
+            return Some(Expression::EOF);
+        };
+        if operator_list[precendence].contains(op.token_type) {
             *index += 1;
             let expr: Expression =
-                get_operators(tokens, logs, index, precendence, source, var_list)?;
-            let expr_type: Option<Type> = operator_list[precendence].get_output_type(
-                &op,
-                vec![expr.get_type()],
-                &mut (logs, source),
-            );
-            return Some(Expression::Unary {
-                op,
-                expr: Box::new(expr),
-                expr_type,
-            });
+                get_operators(tokens, logs, index, precendence, source, var_list, depth + 1)?;
+            return Some(unary_expression(&operator_list[precendence], op, expr, logs, source));
         }
-        return get_operators(tokens, logs, index, precendence + 1, source, var_list);
+        get_operators(tokens, logs, index, precendence + 1, source, var_list, depth)
     } else if operator_list[precendence].arg_count()? == 2 {
         let mut expr: Expression =
-            get_operators(tokens, logs, index, precendence + 1, source, var_list)?;
+            get_operators(tokens, logs, index, precendence + 1, source, var_list, depth)?;
         if let Expression::CastOp { .. } = expr { return Some(expr); }
-        while !expr.is_eof() && operator_list[precendence].contains(tokens[*index].token_type) {
-            let op: Token = tokens[*index];
+        while !expr.is_eof() {
+            let Some(&op) = peek_token(tokens, *index, logs) else {
+                return Some(expr);
+            };
+            if !operator_list[precendence].contains(op.token_type) {
+                break;
+            }
             *index += 1;
             let right: Expression =
-                get_operators(tokens, logs, index, precendence + 1, source, var_list)?;
-            let type_list: Vec<Option<Type>> = vec![expr.get_type(), right.get_type()];
+                get_operators(tokens, logs, index, precendence + 1, source, var_list, depth)?;
             let is_eof: bool = right.is_eof();
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                op,
-                right: Box::new(right),
-                expr_type: operator_list[precendence].get_output_type(
-                    &op,
-                    type_list,
-                    &mut (logs, source),
-                ),
-            };
+            expr = binary_expression(&operator_list[precendence], expr, op, right, logs, source);
             if is_eof {
                 return Some(expr);
             }
         }
-        return Some(expr);
+        Some(expr)
     } else {
         panic!("currently no other options for operators' argument counts.")
     }
 }
 
+// Builds a unary expression and checks its type. This is kept out of get_operators, which recurses for every level of
+// precedence, so that its temporaries aren't on the stack for each of them.
+fn unary_expression(operators: &OpList, op: Token, expr: Expression, logs: &mut Vec<Log>, source: &String) -> Expression {
+    let expr_type: Option<Type> = operators.get_output_type(&op, &[("operand", &expr)], &mut (logs, source));
+    Expression::Unary {
+        op,
+        expr: Box::new(expr),
+        expr_type,
+    }
+}
+
+// Builds a binary expression and checks its type, coercing the operands of equality operators to a common type. Like
+// unary_expression, this is kept out of get_operators.
+fn binary_expression(
+    operators: &OpList,
+    left: Expression,
+    op: Token,
+    right: Expression,
+    logs: &mut Vec<Log>,
+    source: &String,
+) -> Expression {
+    check_self_comparison(&left, op, &right, logs, source);
+    let (left, right): (Expression, Expression) =
+        if [TokenType::Equality, TokenType::Inequality].contains(&op.token_type) {
+            coerce_operands(left, right)
+        } else {
+            (left, right)
+        };
+    let expr_type: Option<Type> = operators.get_output_type(
+        &op,
+        &[("left operand", &left), ("right operand", &right)],
+        &mut (logs, source),
+    );
+    Expression::Binary {
+        left: Box::new(left),
+        op,
+        right: Box::new(right),
+        expr_type,
+    }
+}
+
+// Reports the expression or statement at the index if it is nested more deeply than MAX_NESTING_DEPTH, returning an
+// error in its place. Its tokens are skipped up to the token that ends it, such as the ')' of the parentheses it is in.
+fn check_depth(tokens: &[Token], logs: &mut Vec<Log>, index: &mut usize, depth: usize) -> Option<Expression> {
+    if depth <= MAX_NESTING_DEPTH {
+        return None;
+    }
+    let token: Token = tokens[*index];
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::NestedTooDeeply(MAX_NESTING_DEPTH)),
+        line_and_col: Some((token.line, token.col)),
+        span: Some(token.span()),
+        notes: Vec::new(),
+    });
+    let mut brackets: usize = 0;
+    loop {
+        match tokens[*index].token_type {
+            TokenType::EOF => break,
+            TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace | TokenType::Semicolon | TokenType::Comma
+                if brackets == 0 =>
+            {
+                break
+            }
+            TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => brackets += 1,
+            TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace => brackets -= 1,
+            _ => {}
+        }
+        *index += 1;
+    }
+    Some(Expression::Error { token })
+}
+
+// Gets the token at the index without moving past it. If the tokens have run out, the end of the file is reported
+// unless it just was.
+fn peek_token<'t>(tokens: &'t [Token], index: usize, logs: &mut Vec<Log>) -> Option<&'t Token> {
+    let token: Option<&Token> = tokens.get(index);
+    if token.is_none()
+        && !logs
+            .last()
+            .is_some_and(|log| matches!(log.log_type, LogType::Error(ErrorType::UnexpectedEOF)))
+    {
+        let last: Option<&Token> = tokens.last();
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::UnexpectedEOF),
+            line_and_col: last.map(|last| (last.line, last.col)),
+            span: last.map(Token::span),
+            notes: Vec::new(),
+        });
+    }
+    token
+}
+
+// Warns about comparisons between an expression and itself, which always have the same result.
+fn check_self_comparison(
+    left: &Expression,
+    op: Token,
+    right: &Expression,
+    logs: &mut Vec<Log>,
+    source: &str,
+) {
+    let always: bool = match op.token_type {
+        TokenType::Equality | TokenType::LessEqual | TokenType::GreaterEqual => true,
+        TokenType::Inequality | TokenType::Less | TokenType::Greater => false,
+        _ => return,
+    };
+    if is_same_expression(left, right, source) {
+        logs.push(Log {
+            log_type: LogType::Warning(WarningType::SelfComparison(op.to_string(source), always)),
+            line_and_col: Some((op.line, op.col)),
+            span: Some(op.span()),
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Returns whether or not two expressions always have the same value. Only variables, literals, and operators on them
+// are checked, so this may return false for some expressions that are the same.
+pub(crate) fn is_same_expression(left: &Expression, right: &Expression, source: &str) -> bool {
+    match (left, right) {
+        (Expression::Literal { token: left, .. }, Expression::Literal { token: right, .. }) => {
+            left.token_type == right.token_type
+        }
+        (
+            Expression::Variable {
+                token: left,
+                initialized: true,
+                ..
+            },
+            Expression::Variable {
+                token: right,
+                initialized: true,
+                ..
+            },
+        ) => left.to_string(source) == right.to_string(source),
+        (
+            Expression::StringLiteral { value: left, .. },
+            Expression::StringLiteral { value: right, .. },
+        ) => left == right,
+        (Expression::Grouping { expr: left, .. }, Expression::Grouping { expr: right, .. }) => {
+            is_same_expression(left, right, source)
+        }
+        (
+            Expression::Unary {
+                op: left_op,
+                expr: left,
+                ..
+            },
+            Expression::Unary {
+                op: right_op,
+                expr: right,
+                ..
+            },
+        ) => left_op.token_type == right_op.token_type && is_same_expression(left, right, source),
+        (
+            Expression::Binary {
+                left: left_a,
+                op: left_op,
+                right: left_b,
+                ..
+            },
+            Expression::Binary {
+                left: right_a,
+                op: right_op,
+                right: right_b,
+                ..
+            },
+        ) => {
+            left_op.token_type == right_op.token_type
+                && left_op.token_type != TokenType::Equals
+                && is_same_expression(left_a, right_a, source)
+                && is_same_expression(left_b, right_b, source)
+        }
+        _ => false,
+    }
+}
+
 // Handle casts.
 fn get_cast(
     tokens: &Vec<Token>,
@@ -737,29 +3663,31 @@ fn get_cast(
     index: &mut usize,
     source: &String,
     var_list: &mut HashMap<String, Expression>,
+    depth: usize,
 ) -> Option<Expression> {
     let old_index: usize = *index;
-    let expr: Expression = get_primary(tokens, logs, index, source, var_list);
+    let expr: Expression = get_postfix(tokens, logs, index, source, var_list, depth);
     if let Expression::CastOp { expr_type } = expr {
-        let right: Option<Expression> = get_operators(tokens, logs, index, OpList::get_op_lists().len() - 1, source, var_list); // Check for unary operations first.
+        let right: Option<Expression> = get_operators(tokens, logs, index, OpList::op_lists().len() - 1, source, var_list, depth + 1); // Check for unary operations first.
         if let Some(right) = right {
             Some(Expression::Cast {
-                expr_type: if right.get_type().is_some()
-                    && expr_type
-                        .valid_casts()
-                        .contains(&right.get_type().expect("checked by if"))
+                expr_type: if right
+                    .get_type()
+                    .is_some_and(|right_type| right_type.valid_casts().contains(&expr_type))
                 {
                     Some(expr_type)
                 } else {
                     logs.push(Log {
                         log_type: LogType::Error(ErrorType::InvalidTypesForCast(
-                            expr_type.to_string(),
                             match right.get_type() {
                                 Option::Some(t) => t.to_string(),
                                 Option::None => "none".to_string(),
                             },
+                            expr_type.to_string(),
                         )),
                         line_and_col: Some((tokens[old_index].line, tokens[old_index].col)),
+                        span: Some(tokens[old_index].span()),
+                        notes: Vec::new(),
                     });
                     None
                 },
@@ -771,6 +3699,8 @@ fn get_cast(
                     expr_type.to_string(),
                 )),
                 line_and_col: Some((tokens[old_index].line, tokens[old_index].col)),
+                span: Some(tokens[old_index].span()),
+                notes: Vec::new(),
             });
             right
         }
@@ -780,90 +3710,134 @@ fn get_cast(
 }
 
 // Simplify and correct the AST.
+#[allow(clippy::too_many_lines)] // Necessary for all the different expression types.
 fn improve_ast(
-    expr: Box<Expression>,
-    parent: Option<Box<Expression>>,
+    expr: &Expression,
+    parent: Option<&Expression>,
     logs: &mut Vec<Log>,
     source: &String,
 ) {
-    match *expr {
+    match expr {
         Expression::Binary {
-            ref left,
-            ref right,
+            left,
+            right,
+            ..
+        }
+        | Expression::Index {
+            expr: left,
+            index: right,
             ..
         } => {
-            improve_ast(left.clone(), Some(expr.clone()), logs, source);
-            improve_ast(right.clone(), Some(expr), logs, source);
+            improve_ast(left, Some(expr), logs, source);
+            improve_ast(right, Some(expr), logs, source);
+        }
+        Expression::Call { args, .. }
+        | Expression::FunctionCall { args, .. }
+        | Expression::HostCall { args, .. }
+        | Expression::MethodCall { args, .. }
+        | Expression::ImplDeclaration {
+            methods: args, ..
+        } => {
+            for arg in args {
+                improve_ast(arg, Some(expr), logs, source);
+            }
         }
-        Expression::ExpressionList { ref list } => {
+        Expression::MapLiteral { entries, .. } => {
+            for (key, value) in entries {
+                improve_ast(key, Some(expr), logs, source);
+                improve_ast(value, Some(expr), logs, source);
+            }
+        }
+        Expression::ExpressionList { list }
+        | Expression::ListLiteral {
+            elements: list, ..
+        } => {
             for element in list {
-                improve_ast(element.clone(), Some(expr.clone()), logs, source);
+                improve_ast(element, Some(expr), logs, source);
             }
         }
         Expression::Grouping {
-            expr: ref child, ..
+            expr: child, ..
         }
         | Expression::Unary {
-            expr: ref child, ..
+            expr: child, ..
+        }
+        | Expression::Postfix {
+            expr: child, ..
         }
-        | Expression::Statement { expr: ref child } => {
-            improve_ast(child.clone(), Some(expr), logs, source);
+        | Expression::Statement { expr: child } => {
+            improve_ast(child, Some(expr), logs, source);
+        }
+        Expression::FunctionDeclaration {
+            defaults,
+            body: child,
+            ..
+        } => {
+            for default in defaults {
+                improve_ast(default, Some(expr), logs, source);
+            }
+            improve_ast(child, Some(expr), logs, source);
+        }
+        Expression::FlagsDeclaration { members, .. } => {
+            for (_, value) in members {
+                improve_ast(value, Some(expr), logs, source);
+            }
         }
         Expression::Literal { token, .. } => {
-            if token.token_type == TokenType::IntLiteral(0x8000_0000u32) {
-                let mut preceded_by_unary: bool = false;
-                if let Some(boxed_expr) = parent {
-                    if let Expression::Unary { .. } = *boxed_expr {
-                        preceded_by_unary = true;
-                    }
-                }
-                if !preceded_by_unary {
-                    logs.push(Log {
-                        log_type: LogType::Error(ErrorType::UnnegatedMinimumIntegerLiteral),
-                        line_and_col: Some((token.line, token.col)),
-                    });
-                }
+            if token.token_type == TokenType::IntLiteral(0x8000_0000u32)
+                && !matches!(parent, Some(Expression::Unary { .. }))
+            {
+                logs.push(Log {
+                    log_type: LogType::Error(ErrorType::UnnegatedMinimumIntegerLiteral),
+                    line_and_col: Some((token.line, token.col)),
+                    span: Some(token.span()),
+                    notes: Vec::new(),
+                });
             }
         }
         Expression::Variable {
             initialized, token, ..
         } => {
             if !initialized {
-                if let Some(parent) = parent {
-                    if let Expression::VariableDeclaration { .. } = *parent {
-                        return;
-                    }
+                if let Some(Expression::VariableDeclaration { .. }) = parent {
+                    return;
                 }
                 logs.push(Log {
                     log_type: LogType::Error(ErrorType::ExpectedVariableDeclaration(
                         token.to_string(source),
                     )),
                     line_and_col: Some((token.line, token.col)),
+                    span: Some(token.span()),
+                    notes: Vec::new(),
                 });
             }
         }
         Expression::VariableDeclaration { initialized_var } => {
-            if let Some(parent) = parent {
-                if let Expression::Binary { op, .. } = *parent {
-                    if let TokenType::Equals = op.token_type {
-                        return;
-                    }
+            if let Some(Expression::Binary { op, .. }) = parent {
+                if let TokenType::Equals = op.token_type {
+                    return;
                 }
             }
 
             // Always true.
-            if let Expression::Variable { token, .. } = *initialized_var {
+            if let Expression::Variable { token, .. } = &**initialized_var {
                 logs.push(Log {
                     log_type: LogType::Info(InfoType::NewVarNotSet(token.to_string(source))),
                     line_and_col: Some((token.line, token.col)),
+                    span: Some(token.span()),
+                    notes: Vec::new(),
                 });
             }
         }
 
         Expression::Cast { .. }
         | Expression::CastOp { .. }
+        | Expression::TraitDeclaration { .. }
+        | Expression::ExternDeclaration { .. }
+        | Expression::Error { .. }
         | Expression::EOF
         | Expression::Null
+        | Expression::StringLiteral { .. }
         | Expression::Type { .. }
         | Expression::Void => {}
     }
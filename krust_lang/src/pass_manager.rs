@@ -0,0 +1,243 @@
+//! The module for the pass manager, which decides which passes of the compiler run and which pass has its output
+//! dumped. Compiler developers use it to find the pass responsible for a miscompilation by turning passes off one at a
+//! time and comparing the output of each pass.
+
+use crate::cancel::CancellationToken;
+use num_derive::FromPrimitive;
+
+/// How much the compiler optimizes the code, which decides the passes that run by default. Each level runs the
+/// passes of the levels below it.
+#[derive(FromPrimitive, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum OptLevel {
+    /// Runs no passes that change the code, so the bytecode follows the source as closely as possible.
+    O0 = 0,
+    /// Evaluates constant expressions at compile time and moves repeated literals into a constant pool.
+    O1 = 1,
+    /// Also makes calls in tail position reuse their call frame and computes repeated operands once.
+    #[default]
+    O2 = 2,
+}
+
+impl OptLevel {
+    /// The highest optimization level.
+    pub const MAX: Self = Self::O2;
+}
+
+/// What a pass does, which decides whether or not it can be turned off.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PassKind {
+    /// A pass that is needed to produce any bytecode. It can't be disabled.
+    Required,
+    /// A pass that changes the generated code without changing what it does.
+    Optimization,
+    /// A pass that checks the code without changing it.
+    Analysis,
+}
+
+/// A pass of the compiler that is run by the pass manager.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompilerPass {
+    /// Builds and type checks the AST.
+    Parse,
+    /// Evaluates the operations on constants at compile time, replacing them with their values.
+    ConstantFolding,
+    /// Makes a function that calls itself as its last expression reuse its call frame. The calls are found while
+    /// generating the bytecode, so its output is the AST given to codegen.
+    TailCalls,
+    /// Generates and links the bytecode.
+    Codegen,
+    /// Adds the names of the functions to the bytecode, so that stack traces can name them.
+    FunctionNames,
+    /// Replaces the second of two identical pure operands, as in `(a * b) + (a * b)`, with a copy of the value of the
+    /// first. Copying needs instruction set level 11, so the pass does nothing when targeting a lower level.
+    CommonSubexpressions,
+    /// Fuses each operation on two ints with the instructions that read its operands from variables or push them as
+    /// literals, which is the register encoding chosen with `--encoding=register`. It only runs when asked for, and
+    /// needs instruction set level 12, so the pass does nothing when targeting a lower level.
+    Registers,
+    /// Moves the literals that are pushed more than once into a constant pool when that makes the bytecode smaller.
+    /// The pool needs instruction set level 9, so the pass does nothing when targeting a lower level.
+    ConstantPool,
+    /// Runs the verifier on the generated bytecode, reporting any failure as an internal compiler error.
+    Verify,
+}
+
+impl CompilerPass {
+    /// Every pass, in the order they run.
+    pub const ALL: [Self; 9] = [
+        Self::Parse,
+        Self::ConstantFolding,
+        Self::TailCalls,
+        Self::Codegen,
+        Self::FunctionNames,
+        Self::CommonSubexpressions,
+        Self::Registers,
+        Self::ConstantPool,
+        Self::Verify,
+    ];
+
+    /// Gets the name of the pass, as used on the command line.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Parse => "parse",
+            Self::ConstantFolding => "constant-folding",
+            Self::TailCalls => "tail-calls",
+            Self::Codegen => "codegen",
+            Self::FunctionNames => "function-names",
+            Self::CommonSubexpressions => "common-subexpressions",
+            Self::Registers => "registers",
+            Self::ConstantPool => "constant-pool",
+            Self::Verify => "verify",
+        }
+    }
+
+    /// Gets the pass with the given name.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|pass| pass.name() == name)
+    }
+
+    /// Gets what the pass does.
+    #[must_use]
+    pub fn kind(self) -> PassKind {
+        match self {
+            Self::Parse | Self::Codegen => PassKind::Required,
+            Self::ConstantFolding
+            | Self::TailCalls
+            | Self::FunctionNames
+            | Self::CommonSubexpressions
+            | Self::Registers
+            | Self::ConstantPool => PassKind::Optimization,
+            Self::Verify => PassKind::Analysis,
+        }
+    }
+
+    /// Gets the lowest optimization level that runs the pass when no passes are chosen, or None if the pass only runs
+    /// when asked for, as analysis passes and the register encoding do. The function names are kept at every level, as they are only used to
+    /// name functions in stack traces.
+    #[must_use]
+    pub fn opt_level(self) -> Option<OptLevel> {
+        match self {
+            Self::Parse | Self::Codegen | Self::FunctionNames => Some(OptLevel::O0),
+            Self::ConstantFolding | Self::ConstantPool => Some(OptLevel::O1),
+            Self::TailCalls | Self::CommonSubexpressions => Some(OptLevel::O2),
+            Self::Registers | Self::Verify => None,
+        }
+    }
+
+    /// Returns whether or not the pass runs at the optimization level when no passes are chosen.
+    #[must_use]
+    pub fn is_default(self, opt_level: OptLevel) -> bool {
+        self.opt_level().is_some_and(|level| level <= opt_level)
+    }
+}
+
+/// Chooses which passes of the compiler run, and which pass has its output dumped. The compilation can also be
+/// cancelled between passes through a token.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PassManager {
+    enabled: Vec<CompilerPass>,
+    print_after: Option<CompilerPass>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl PassManager {
+    /// Creates a pass manager that runs the default passes and dumps nothing.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a pass manager that runs the default passes of the optimization level and dumps nothing.
+    #[must_use]
+    pub fn with_opt_level(opt_level: OptLevel) -> Self {
+        let mut passes: Self = Self::default();
+        passes.set_opt_level(opt_level);
+        passes
+    }
+
+    /// Runs only the default passes of the optimization level, replacing any passes chosen before.
+    pub fn set_opt_level(&mut self, opt_level: OptLevel) {
+        self.enabled = CompilerPass::ALL
+            .into_iter()
+            .filter(|pass| pass.is_default(opt_level))
+            .collect();
+    }
+
+    /// Runs only the given passes, along with the required passes.
+    pub fn set_passes(&mut self, passes: &[CompilerPass]) {
+        self.enabled = CompilerPass::ALL
+            .into_iter()
+            .filter(|pass| pass.kind() == PassKind::Required || passes.contains(pass))
+            .collect();
+    }
+
+    /// Runs a pass along with the passes chosen before.
+    pub fn enable(&mut self, pass: CompilerPass) {
+        if !self.is_enabled(pass) {
+            self.enabled.push(pass);
+        }
+    }
+
+    /// Stops a pass from running. Returns false without changing anything if the pass is required.
+    pub fn disable(&mut self, pass: CompilerPass) -> bool {
+        if pass.kind() == PassKind::Required {
+            return false;
+        }
+        self.enabled.retain(|other| *other != pass);
+        true
+    }
+
+    /// Returns whether or not the pass will run.
+    #[must_use]
+    pub fn is_enabled(&self, pass: CompilerPass) -> bool {
+        self.enabled.contains(&pass)
+    }
+
+    /// Dumps the output of the pass once it has run. Only one pass can be dumped at a time.
+    pub fn set_print_after(&mut self, pass: CompilerPass) {
+        self.print_after = Some(pass);
+    }
+
+    /// Gets the pass whose output is dumped, if any.
+    #[must_use]
+    pub fn print_after(&self) -> Option<CompilerPass> {
+        self.print_after
+    }
+
+    /// Returns whether or not the output of the pass should be dumped, which is only done if the pass runs.
+    #[must_use]
+    pub fn should_print_after(&self, pass: CompilerPass) -> bool {
+        self.print_after == Some(pass) && self.is_enabled(pass)
+    }
+
+    /// Checks the token before each pass that runs, stopping the compilation once it is cancelled.
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancellation = Some(token);
+    }
+
+    /// Checks the token before the pass runs.
+    ///
+    /// # Errors
+    /// Returns the pass if the compilation was cancelled, so that the compiler can report where it stopped.
+    pub fn check_cancelled(&self, pass: CompilerPass) -> Result<(), CompilerPass> {
+        match &self.cancellation {
+            Some(token) if token.check() => Err(pass),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        Self {
+            enabled: CompilerPass::ALL
+                .into_iter()
+                .filter(|pass| pass.is_default(OptLevel::default()))
+                .collect(),
+            print_after: None,
+            cancellation: None,
+        }
+    }
+}
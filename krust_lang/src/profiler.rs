@@ -0,0 +1,164 @@
+//! The module for the reports of the VM's profiling mode, which counts how many times each instruction runs and how
+//! long it takes, so that slow programs and slow instructions can be found.
+
+use crate::{compiler, events};
+use compiler::OpCode;
+use events::json_string;
+use std::fmt::Write as _;
+use std::time::Duration;
+
+/// The formats that a profile can be printed in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProfileFormat {
+    /// A table for people to read.
+    Table,
+    /// A single line of JSON for tools.
+    Json,
+}
+
+impl ProfileFormat {
+    /// Every profile format.
+    pub const ALL: [Self; 2] = [Self::Table, Self::Json];
+
+    /// Gets the name of the format, as used on the command line.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Table => "table",
+            Self::Json => "json",
+        }
+    }
+
+    /// Gets the format with the given name.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.name() == name)
+    }
+}
+
+/// The number of times instructions with an opcode ran and the time spent running them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OpProfile {
+    pub op: OpCode,
+    pub count: u64,
+    pub time: Duration,
+}
+
+/// The number of times a function was called, and the instructions run in its body and the time spent running them.
+/// The functions it calls aren't counted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FunctionProfile {
+    /// The name of the function, or None for the main program.
+    pub name: Option<String>,
+    pub calls: u64,
+    pub instructions: u64,
+    pub time: Duration,
+}
+
+/// What a profiled run spent its time on, with the opcodes and functions that took the longest first.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Profile {
+    pub ops: Vec<OpProfile>,
+    pub functions: Vec<FunctionProfile>,
+}
+
+impl Profile {
+    /// Gets the time spent running instructions.
+    #[must_use]
+    pub fn total_time(&self) -> Duration {
+        self.ops.iter().map(|op| op.time).sum()
+    }
+
+    /// Prints the profile in the given format.
+    #[must_use]
+    pub fn format(&self, format: ProfileFormat) -> String {
+        match format {
+            ProfileFormat::Table => self.to_table(),
+            ProfileFormat::Json => self.to_json(),
+        }
+    }
+
+    /// Converts the profile to a table of the opcodes followed by a table of the functions, each with the share of
+    /// the total time that it took.
+    #[must_use]
+    pub fn to_table(&self) -> String {
+        let total: Duration = self.total_time();
+        let share = |time: Duration| {
+            if total.is_zero() {
+                0.0
+            } else {
+                100.0 * time.as_secs_f64() / total.as_secs_f64()
+            }
+        };
+        let mut table: String = format!(
+            "{:<24}{:>12}{:>14}{:>8}",
+            "instruction", "count", "time", "share"
+        );
+        // Writing to a string can't fail.
+        for op in &self.ops {
+            let _ = write!(
+                table,
+                "\n{:<24}{:>12}{:>14}{:>7.1}%",
+                format!("{:?}", op.op),
+                op.count,
+                format!("{:.3?}", op.time),
+                share(op.time)
+            );
+        }
+        let _ = write!(
+            table,
+            "\n\n{:<24}{:>12}{:>14}{:>14}{:>8}",
+            "function", "calls", "instructions", "time", "share"
+        );
+        for function in &self.functions {
+            let _ = write!(
+                table,
+                "\n{:<24}{:>12}{:>14}{:>14}{:>7.1}%",
+                function.name.as_deref().unwrap_or("<main program>"),
+                function.calls,
+                function.instructions,
+                format!("{:.3?}", function.time),
+                share(function.time)
+            );
+        }
+        table
+    }
+
+    /// Converts the profile to a single line of JSON, with times in nanoseconds.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let ops: Vec<String> = self
+            .ops
+            .iter()
+            .map(|op| {
+                format!(
+                    "{{\"op\":\"{:?}\",\"count\":{},\"time_ns\":{}}}",
+                    op.op,
+                    op.count,
+                    op.time.as_nanos()
+                )
+            })
+            .collect();
+        let functions: Vec<String> = self
+            .functions
+            .iter()
+            .map(|function| {
+                format!(
+                    "{{\"name\":{},\"calls\":{},\"instructions\":{},\"time_ns\":{}}}",
+                    function
+                        .name
+                        .as_deref()
+                        .map_or_else(|| "null".to_string(), json_string),
+                    function.calls,
+                    function.instructions,
+                    function.time.as_nanos()
+                )
+            })
+            .collect();
+        format!(
+            "{{\"ops\":[{}],\"functions\":[{}]}}",
+            ops.join(","),
+            functions.join(",")
+        )
+    }
+}
@@ -0,0 +1,244 @@
+//! The module for project config files, named `krust.toml`, which hold the options that every build of a project uses
+//! so that they don't have to be given as flags each time. Only the parts of TOML that these files need are read:
+//! `[section]` headers, `key = value` lines whose values are strings or integers, and `#` comments.
+//!
+//! The config is found in the current directory or one of its parents. Its `entry` is run by a bare `krust`, and
+//! `pointer_size`, `opt_level`, and the levels of the `[warnings]` section apply to every build. Flags given on the
+//! command line win over the config, and invalid lines are reported with their line numbers (E0128 to E0130).
+//! Library users load the config with `ProjectConfig::load` or `ProjectConfig::parse`, and pass its `flags` to
+//! `cli_reader::read_args` before their own.
+//!
+//! ```toml
+//! entry = "src/main.txt"
+//! pointer_size = 32
+//! opt_level = 2
+//!
+//! [warnings]
+//! lints = "warn"
+//! shadowed-variable = "deny"
+//! ```
+
+use crate::{pass_manager, util::log};
+use log::{DiagnosticConfig, ErrorType, Log, LogType, WarningLevel};
+use pass_manager::OptLevel;
+
+use num_traits::FromPrimitive;
+use std::env::current_dir;
+use std::fs::read_to_string;
+use std::iter::repeat_n;
+use std::path::{Path, PathBuf};
+
+/// The name of the config file of a project.
+pub const CONFIG_FILE_NAME: &str = "krust.toml";
+
+/// The settings of a project. Settings that the config file doesn't give are None, so that the defaults are used.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ProjectConfig {
+    /// The source file that is used when no file is given, relative to the directory of the config file.
+    pub entry: Option<String>,
+    /// The size of a pointer of the target, in bits.
+    pub pointer_size: Option<u16>,
+    /// How much the compiler optimizes the code.
+    pub opt_level: Option<OptLevel>,
+    /// The levels given to warnings in the `[warnings]` section, in order. A warning is given by its name or code, or
+    /// as "lints" or "warnings", as with the `-W` flag.
+    pub warnings: Vec<(String, WarningLevel)>,
+}
+
+impl ProjectConfig {
+    /// Reads the config file at the path.
+    ///
+    /// # Errors
+    /// Returns the errors found if the file can't be read or isn't a valid config.
+    pub fn load(path: &Path) -> Result<Self, Vec<Log>> {
+        let Ok(text) = read_to_string(path) else {
+            return Err(vec![Log {
+                log_type: LogType::Error(ErrorType::CLICantOpenFile(path.display().to_string())),
+                line_and_col: None,
+                span: None,
+                notes: Vec::new(),
+            }]);
+        };
+        Self::parse(&text, &path.display().to_string())
+    }
+
+    /// Reads the text of a config file, whose name is used in errors.
+    ///
+    /// # Errors
+    /// Returns every line that isn't a valid section or setting.
+    pub fn parse(text: &str, file_name: &str) -> Result<Self, Vec<Log>> {
+        let mut config: Self = Self::default();
+        let mut logs: Vec<Log> = Vec::new();
+        let mut error = |error: ErrorType| {
+            logs.push(Log {
+                log_type: LogType::Error(error),
+                line_and_col: None,
+                span: None,
+                notes: Vec::new(),
+            });
+        };
+        let mut section: Option<String> = None;
+        for (index, line) in text.lines().enumerate() {
+            let line_number: usize = index + 1;
+            let line: &str = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                let name: &str = name.trim();
+                if name != "warnings" {
+                    error(ErrorType::ConfigUnknownSetting(file_name.to_string(), line_number, format!("[{name}]")));
+                }
+                section = Some(name.to_string());
+                continue;
+            }
+            let Some((key, value)) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), parse_value(value.trim())))
+                .filter(|(key, _)| is_bare_key(key))
+            else {
+                error(ErrorType::ConfigInvalidLine(file_name.to_string(), line_number));
+                continue;
+            };
+            let invalid_value = |expected: &str| {
+                ErrorType::ConfigInvalidValue(file_name.to_string(), line_number, key.to_string(), expected.to_string())
+            };
+            match (section.as_deref(), key) {
+                (None, "entry") => match value {
+                    Some(Value::String(entry)) => config.entry = Some(entry),
+                    _ => error(invalid_value("a string")),
+                },
+                (None, "pointer_size") => match value {
+                    Some(Value::Integer(bits)) if (8..2048).contains(&bits) && bits % 8 == 0 => {
+                        config.pointer_size = bits.try_into().ok();
+                    }
+                    _ => error(invalid_value("a multiple of 8 from 8 to 2040")),
+                },
+                (None, "opt_level") => match value.and_then(|value| {
+                    if let Value::Integer(level) = value { OptLevel::from_i64(level) } else { None }
+                }) {
+                    Some(level) => config.opt_level = Some(level),
+                    None => error(invalid_value(&format!("an optimization level from 0 to {}", OptLevel::MAX as u8))),
+                },
+                (Some("warnings"), name) => {
+                    if !DiagnosticConfig::default().set(name, WarningLevel::Warn) {
+                        error(ErrorType::ConfigUnknownSetting(file_name.to_string(), line_number, name.to_string()));
+                        continue;
+                    }
+                    let level: Option<WarningLevel> = match value {
+                        Some(Value::String(level)) => match level.as_str() {
+                            "allow" => Some(WarningLevel::Allow),
+                            "warn" => Some(WarningLevel::Warn),
+                            "deny" => Some(WarningLevel::Deny),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    match level {
+                        Some(level) => config.warnings.push((name.to_string(), level)),
+                        None => error(invalid_value("one of \"allow\", \"warn\", or \"deny\"")),
+                    }
+                }
+                // The settings of an unknown section were already reported with the section.
+                (Some(section), _) if section != "warnings" => {}
+                (_, key) => error(ErrorType::ConfigUnknownSetting(file_name.to_string(), line_number, key.to_string())),
+            }
+        }
+        if logs.is_empty() {
+            Ok(config)
+        } else {
+            Err(logs)
+        }
+    }
+
+    /// Gets the compiler flags that give the same settings as the config, other than the entry file. The command line
+    /// reads these before its own flags, so that flags given on the command line win over the config.
+    #[must_use]
+    pub fn flags(&self) -> Vec<String> {
+        let mut flags: Vec<String> = Vec::new();
+        if let Some(bits) = self.pointer_size {
+            flags.push(format!("-pointer_size={bits}"));
+        }
+        if let Some(level) = self.opt_level {
+            flags.push(format!("-O{}", level as u8));
+        }
+        for (name, level) in &self.warnings {
+            let flag: &str = match level {
+                WarningLevel::Allow => "-A",
+                WarningLevel::Warn => "-W",
+                WarningLevel::Deny => "--deny",
+            };
+            flags.push(format!("{flag}={name}"));
+        }
+        flags
+    }
+}
+
+/// Finds the config file of the project that the current directory is in, which is the closest one in it or in one of
+/// its parents. The path is relative to the current directory.
+#[must_use]
+pub fn find() -> Option<PathBuf> {
+    let dir: PathBuf = current_dir().ok()?;
+    dir.ancestors()
+        .position(|ancestor| ancestor.join(CONFIG_FILE_NAME).is_file())
+        .map(|levels| repeat_n("..", levels).collect::<PathBuf>().join(CONFIG_FILE_NAME))
+}
+
+// A value in a config file.
+enum Value {
+    String(String),
+    Integer(i64),
+}
+
+// Parses the text after the "=" of a setting, which has already had its comment removed.
+fn parse_value(text: &str) -> Option<Value> {
+    if let Some(literal) = text.strip_prefix('\'').and_then(|text| text.strip_suffix('\'')) {
+        return (!literal.contains('\'')).then(|| Value::String(literal.to_string()));
+    }
+    if let Some(quoted) = text.strip_prefix('"').and_then(|text| text.strip_suffix('"')) {
+        let mut value: String = String::new();
+        let mut chars = quoted.chars();
+        while let Some(char) = chars.next() {
+            match char {
+                '"' => return None,
+                '\\' => value.push(match chars.next()? {
+                    'n' => '\n',
+                    't' => '\t',
+                    escaped @ ('"' | '\\') => escaped,
+                    _ => return None,
+                }),
+                char => value.push(char),
+            }
+        }
+        return Some(Value::String(value));
+    }
+    text.replace('_', "").parse().ok().map(Value::Integer)
+}
+
+// Returns whether or not the key can be written without quotes, which are the only keys that config files use.
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_' || char == '-')
+}
+
+// Removes the comment from the end of a line, if it has one. A "#" in a string doesn't start a comment.
+fn strip_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut escaped: bool = false;
+    for (index, char) in line.char_indices() {
+        match (quote, char) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(open), char) if char == open && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(char),
+            (None, '#') => return &line[..index],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
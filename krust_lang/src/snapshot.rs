@@ -0,0 +1,174 @@
+//! The module for snapshot testing, which records what each stage of the compiler makes of a source file: its tokens,
+//! its AST, the disassembly of its bytecode, and its diagnostics. A snapshot is kept next to its source file, and a
+//! change to any stage shows up as a diff of the snapshot, rather than as a failed assertion on a list of bytes.
+//! Snapshots that don't exist yet are written, and every snapshot is rewritten if `KRUST_UPDATE_SNAPSHOTS` is set.
+
+use crate::{compiler, disassembler, lexer, parser, util::log};
+use compiler::{compile, CompilerOptions, CompilerOutput};
+use disassembler::{disassemble, AddressMode, DisassemblerOutput};
+use lexer::{lex, LexerOutput};
+use log::Log;
+use parser::{parse, ParserOutput};
+
+use std::env::var_os;
+use std::fmt::Write as _;
+use std::fs::{read_dir, read_to_string, write};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The extension of snapshot files, which have the same name as their source file.
+pub const SNAPSHOT_EXTENSION: &str = "snap";
+/// The environment variable that rewrites every snapshot with the current output when it is set.
+pub const UPDATE_VAR: &str = "KRUST_UPDATE_SNAPSHOTS";
+
+/// The result of checking a source file against its snapshot.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SnapshotOutcome {
+    Matched,
+    /// The snapshot didn't exist or was being updated, so it was written.
+    Written,
+    /// The snapshot is different from the current output. The diff has the lines of the snapshot that were removed,
+    /// starting with "-", and the lines of the output that were added, starting with "+".
+    Mismatched { diff: String },
+}
+
+/// Takes a snapshot of each stage of compiling the source. The disassembly gives labels rather than offsets, so that
+/// it only changes where the instructions do, and it is left out if the source doesn't compile.
+#[must_use]
+pub fn snapshot(source: &str, options: CompilerOptions) -> String {
+    let lex_output: LexerOutput = lex(source);
+    let tokens: Vec<String> = lex_output.tokens.iter().map(|token| token.describe(source)).collect();
+    let parser_output: ParserOutput = parse(lex_output);
+    let ast: String = parser_output.expr.to_tree_string(source);
+    let compiler_output: CompilerOutput = compile(parser_output, options);
+    let mut logs: Vec<Log> = compiler_output.logs;
+    let disassembly: Vec<String> = match compiler_output.bytecode {
+        Some(bytecode) => {
+            let mut output: DisassemblerOutput = disassemble(&bytecode, AddressMode::Labels);
+            logs.append(&mut output.logs);
+            output.lines
+        }
+        None => Vec::new(),
+    };
+    let diagnostics: Vec<String> = logs.iter().map(describe_log).collect();
+    let mut output: String = String::new();
+    for (name, lines) in [
+        ("tokens", tokens),
+        ("ast", ast.lines().map(str::to_string).collect()),
+        ("disassembly", disassembly),
+        ("diagnostics", diagnostics),
+    ] {
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        let _ = writeln!(output, "=== {name} ==="); // Writing to a string can't fail.
+        for line in lines {
+            output.push_str(line.trim_end());
+            output.push('\n');
+        }
+    }
+    output
+}
+
+/// Compares the snapshot of the source with the one stored at the path, writing it if there isn't one yet or if
+/// snapshots are being updated.
+///
+/// # Errors
+/// Returns an error if the stored snapshot can't be read or written.
+pub fn check_snapshot(path: &Path, source: &str, options: CompilerOptions) -> io::Result<SnapshotOutcome> {
+    let actual: String = snapshot(source, options);
+    if !path.exists() || var_os(UPDATE_VAR).is_some() {
+        write(path, actual)?;
+        return Ok(SnapshotOutcome::Written);
+    }
+    // Snapshots checked out on Windows may have their line endings changed.
+    let expected: String = read_to_string(path)?.replace("\r\n", "\n");
+    if expected == actual {
+        Ok(SnapshotOutcome::Matched)
+    } else {
+        Ok(SnapshotOutcome::Mismatched {
+            diff: diff_lines(&expected, &actual),
+        })
+    }
+}
+
+/// Checks every `.txt` file in the directory against the snapshot next to it, in order of their paths.
+///
+/// # Errors
+/// Returns an error if the directory, a source file, or a snapshot can't be read, or if a snapshot can't be written.
+pub fn check_dir(dir: &Path, options: CompilerOptions) -> io::Result<Vec<(PathBuf, SnapshotOutcome)>> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for entry in read_dir(dir)? {
+        let path: PathBuf = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "txt") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let source: String = read_to_string(&path)?;
+            let outcome: SnapshotOutcome = check_snapshot(&path.with_extension(SNAPSHOT_EXTENSION), &source, options)?;
+            Ok((path, outcome))
+        })
+        .collect()
+}
+
+// Writes a log with its code and location but without colors or the line of source, so that snapshots don't depend on
+// the terminal. Its notes are written below it, indented.
+fn describe_log(log: &Log) -> String {
+    let mut output: String = log.log_type.severity().name().to_string();
+    if let Some(code) = log.code() {
+        let _ = write!(output, "[{code}]");
+    }
+    if let Some((line, col)) = log.line_and_col {
+        let _ = write!(output, " (line {line}:{col})");
+    }
+    let _ = write!(output, ": {}", log.message());
+    for note in &log.notes {
+        let _ = write!(output, "\n  {}", describe_log(&note.to_log()));
+    }
+    output
+}
+
+// Gets the lines that were removed from and added to the expected text, with the unchanged lines between them left
+// out. Each group of changes starts with the line numbers it is at in both texts.
+fn diff_lines(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    // The length of the longest common subsequence of the lines from each pair of positions to the ends.
+    let mut lengths: Vec<Vec<usize>> = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lengths[i][j] = if expected[i] == actual[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut output: String = String::new();
+    let (mut i, mut j): (usize, usize) = (0, 0);
+    let mut in_change: bool = false;
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            in_change = false;
+            i += 1;
+            j += 1;
+            continue;
+        }
+        if !in_change {
+            let _ = writeln!(output, "@@ line {} -> line {} @@", i + 1, j + 1);
+            in_change = true;
+        }
+        if j == actual.len() || (i < expected.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            let _ = writeln!(output, "-{}", expected[i]);
+            i += 1;
+        } else {
+            let _ = writeln!(output, "+{}", actual[j]);
+            j += 1;
+        }
+    }
+    output
+}
@@ -0,0 +1,156 @@
+//! The module for running the tests written in krust. A test is a function at the top level whose name starts with
+//! `test_` and that takes no parameters. Each test is compiled into its own program, which has the declarations of the
+//! file but none of its other top-level code, and is run on its own VM. A test fails if it has a runtime error, such as
+//! a call to `assert` with a false condition or to `panic`.
+//!
+//! `krust test math.txt` prints each test as `ok` or `FAILED` along with its location and errors, followed by a
+//! summary, and `--filter=text` only runs the tests whose names contain the text. A false `assert(condition,
+//! "message")`, which needs instruction set level 13, fails with `error[E0131]` at the line and column of the call.
+
+use crate::{compiler, lexer, parser, util::log, vm};
+use compiler::{compile, CompilerOptions, CompilerOutput};
+use lexer::lex;
+use log::{is_error, Log};
+use parser::{parse, Expression, ParserOutput};
+use vm::Vm;
+
+use std::io;
+
+/// The prefix of the names of test functions.
+pub const TEST_PREFIX: &str = "test_";
+
+/// A test function in a source file.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TestCase {
+    pub name: String,
+    /// The line that the test is declared on.
+    pub line: usize,
+}
+
+/// The result of running a test.
+pub enum TestOutcome {
+    Passed,
+    /// The test had a runtime error, such as a failed assertion. The errors give the line and column where it failed.
+    Failed {
+        output: Vec<String>,
+        logs: Vec<Log>,
+    },
+}
+
+/// The output of running the tests of a file.
+pub struct TestRunOutput {
+    /// The tests that were run and their outcomes, in the order they are declared.
+    pub results: Vec<(TestCase, TestOutcome)>,
+    /// The number of tests left out by the filter.
+    pub filtered_out: usize,
+    /// The logs of compiling the file, which has errors if no tests could be run.
+    pub logs: Vec<Log>,
+}
+
+/// Compiles the source and runs each of its tests whose name contains the filter, or every test if there is no
+/// filter. No tests are run if the source has errors. The tests can't access files.
+#[must_use]
+pub fn run_tests(source: &str, options: CompilerOptions, filter: Option<&str>) -> TestRunOutput {
+    let parser_output: ParserOutput = parse(lex(source));
+    let declarations: Vec<Box<Expression>> = top_level_declarations(&parser_output.expr);
+    let tests: Vec<(TestCase, Expression)> = find_tests(&declarations, source);
+    let compiler_output: CompilerOutput = compile(parser_output, options);
+    let mut output: TestRunOutput = TestRunOutput {
+        results: Vec::new(),
+        filtered_out: 0,
+        logs: compiler_output.logs,
+    };
+    if is_error(&output.logs) {
+        return output;
+    }
+    for (case, call) in tests {
+        if filter.is_some_and(|filter| !case.name.contains(filter)) {
+            output.filtered_out += 1;
+            continue;
+        }
+        let mut list: Vec<Box<Expression>> = declarations.clone();
+        list.push(Box::new(Expression::Statement { expr: Box::new(call) }));
+        let program: ParserOutput = ParserOutput {
+            file_text: source.to_string(),
+            expr: Expression::ExpressionList { list },
+            logs: Vec::new(),
+        };
+        let outcome: TestOutcome = run_test(program, options);
+        output.results.push((case, outcome));
+    }
+    output
+}
+
+// Gets the expressions at the top level that declare something, rather than run code.
+#[allow(clippy::vec_box)] // The declarations are copied into the expression list of each test.
+fn top_level_declarations(expr: &Expression) -> Vec<Box<Expression>> {
+    let Expression::ExpressionList { list } = expr else {
+        return Vec::new();
+    };
+    list.iter()
+        .filter(|expr| {
+            let expr: &Expression = match &***expr {
+                Expression::Statement { expr } => expr,
+                expr => expr,
+            };
+            matches!(
+                expr,
+                Expression::FunctionDeclaration { .. }
+                    | Expression::TraitDeclaration { .. }
+                    | Expression::FlagsDeclaration { .. }
+                    | Expression::ExternDeclaration { .. }
+                    | Expression::ImplDeclaration { .. }
+            )
+        })
+        .cloned()
+        .collect()
+}
+
+// Finds the test functions among the declarations, each with a call to it.
+fn find_tests(declarations: &[Box<Expression>], source: &str) -> Vec<(TestCase, Expression)> {
+    let mut tests: Vec<(TestCase, Expression)> = Vec::new();
+    for declaration in declarations {
+        let declaration: &Expression = match &**declaration {
+            Expression::Statement { expr } => expr,
+            expr => expr,
+        };
+        let Expression::FunctionDeclaration { token, type_params, params, return_type, .. } = declaration else {
+            continue;
+        };
+        let name: String = token.to_string(source);
+        if !name.starts_with(TEST_PREFIX) || !params.is_empty() || !type_params.is_empty() {
+            continue;
+        }
+        let call: Expression = Expression::FunctionCall {
+            token: *token,
+            declaration: *token,
+            args: Vec::new(),
+            type_args: Vec::new(),
+            expr_type: Some(return_type.clone()),
+        };
+        tests.push((TestCase { name, line: token.line }, call));
+    }
+    tests
+}
+
+// Compiles and runs the program of a test.
+fn run_test(program: ParserOutput, options: CompilerOptions) -> TestOutcome {
+    let compiler_output: CompilerOutput = compile(program, options);
+    let Some(bytecode) = compiler_output.bytecode else {
+        return TestOutcome::Failed {
+            output: Vec::new(),
+            logs: compiler_output.logs,
+        };
+    };
+    // The output and errors are reported with the outcome, so they aren't printed as well.
+    let mut vm: Vm = Vm::new(bytecode);
+    vm.set_output(io::sink());
+    vm.set_error_output(io::sink());
+    let logs: Vec<Log> = vm.run().err().unwrap_or_default();
+    let output: Vec<String> = vm.output();
+    if is_error(&logs) {
+        TestOutcome::Failed { output, logs }
+    } else {
+        TestOutcome::Passed
+    }
+}
@@ -1,31 +1,382 @@
 //! The module for debug messages.
 
-use colored::{control::set_override, ColoredString, Colorize};
+use crate::backend::Backend;
+use crate::cli::Shell;
+use crate::cli_reader::{Emit, Encoding};
+use crate::lexer::Span;
+use crate::compiler::{Endianness, IsaLevel};
+use crate::events::json_string;
+use crate::pass_manager::{CompilerPass, OptLevel};
+use crate::profiler::ProfileFormat;
+use crate::util::messages::{localize, localize_with, LocaleProvider, ERROR_MESSAGES, WARNING_MESSAGES};
+use crate::util::number_format::NumberFormat;
+use colored::Color;
+use std::env::var_os;
+use std::cell::RefCell;
 use std::fmt::{Display, Formatter, Result};
+use std::io::{stderr, IsTerminal, Stderr, Write};
+use std::rc::Rc;
 
 /// An enum representing anything that can be logged.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LogType {
     Info(InfoType),
+    Note(NoteType),
+    Help(HelpType),
     Warning(WarningType),
     Error(ErrorType),
 }
 
+/// How important a log is. Severities are ordered from least to most important.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Info,
+    Note,
+    Help,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// Gets the severity with the given name, as used on the command line.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "info" => Some(Self::Info),
+            "note" => Some(Self::Note),
+            "help" => Some(Self::Help),
+            "warning" => Some(Self::Warning),
+            "error" => Some(Self::Error),
+            _ => None,
+        }
+    }
+
+    /// Gets the name of the severity, as used on the command line and in JSON diagnostics.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Note => "note",
+            Self::Help => "help",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// How logs are printed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ErrorFormat {
+    /// Each log is written as a sentence, followed by the line of code it is about. This is the default.
+    #[default]
+    Human,
+    /// Each log is written as a JSON object on its own line, for editors and other tools, with its code, severity,
+    /// message, file, line and column, span, and notes. Fields that aren't known are `null`.
+    Json,
+}
+
+impl ErrorFormat {
+    /// Gets the format with the given name, as used on the command line.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "human" => Some(Self::Human),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// When logs are printed in color.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ColorChoice {
+    /// Colors are used if logs are printed to a terminal and the `NO_COLOR` environment variable isn't set. This is the
+    /// default.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Gets the choice with the given name, as used on the command line.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Self::Auto),
+            "always" => Some(Self::Always),
+            "never" => Some(Self::Never),
+            _ => None,
+        }
+    }
+
+    /// Decides whether or not logs printed to the standard error stream should be colored.
+    #[must_use]
+    pub fn enabled(self) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && stderr().is_terminal(),
+        }
+    }
+}
+
+/// How logs are written when they are printed, which the caller chooses for each log so that nothing depends on what
+/// another caller chose.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct RenderOptions {
+    /// Whether logs are written as text or as JSON.
+    pub format: ErrorFormat,
+    /// Whether or not text is colored, as it is for a terminal. JSON is never colored.
+    pub colored: bool,
+    /// How numbers in messages are written.
+    pub numbers: NumberFormat,
+}
+
+/// The most errors printed for a file unless another limit is chosen.
+pub const DEFAULT_ERROR_LIMIT: usize = 20;
+
+// Makes the text bold, and the color if one is given, if the text is colored.
+fn bold(text: &str, color: Option<Color>, colored: bool) -> String {
+    if !colored {
+        return text.to_string();
+    }
+    match color {
+        Some(color) => format!("\x1b[1;{}m{text}\x1b[0m", color.to_fg_str()),
+        None => format!("\x1b[1m{text}\x1b[0m"),
+    }
+}
+
+impl LogType {
+    /// Gets the severity of the log type.
+    #[must_use]
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::Info(_) => Severity::Info,
+            Self::Note(_) => Severity::Note,
+            Self::Help(_) => Severity::Help,
+            Self::Warning(_) => Severity::Warning,
+            Self::Error(_) => Severity::Error,
+        }
+    }
+}
+
 /// An enum representing any possible info message.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InfoType {
     NewVarNotSet(String),
 }
 
+/// An enum representing any possible note. Notes give extra context about the log before them.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NoteType {
+    FunctionSignature(String, Vec<String>),
+    ExpressionType(String),
+    StackFrame(Option<String>), // The function that was running, or None for the main program.
+    OmittedStackFrames(usize), // The number of function calls left out of a stack trace.
+    DeniedWarning(String, String), // The name and code of a warning that was turned into an error.
+    OperandType(String, String), // An operand of an operator, such as "left operand", and its type.
+}
+
+/// An enum representing any possible help message. Help messages suggest how to fix the log before them.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HelpType {
+    BuiltinFunctions(Vec<String>),
+    DidYouMean(String), // What a misspelled keyword, operator, or flag probably was.
+    OperandNeedsType(String, Vec<String>), // An operand, and the types it could have for its operator to be defined.
+}
+
 /// An enum representing any possible warning.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum WarningType {
     CLIArgRoundedDownU16(String, u16),
     CLITargetLargerThanMachine(usize),
+    SelfComparison(String, bool),
+    UnusedValue(String), // The operator whose value is thrown away.
+    ConstantOverflow(i64, i32), // The exact result of a constant operation, and the value it wraps around to.
+    RedundantParens,
+    ConstantCondition(bool), // The value the condition always has.
+    ShadowedVariable(String, usize), // The name of the variable, and the line of the declaration it shadows.
+}
+
+/// The names of the warnings, as used on the command line, in the order of their numbers.
+pub const WARNING_NAMES: [&str; 8] = [
+    "rounded-argument",
+    "target-larger-than-machine",
+    "self-comparison",
+    "unused-value",
+    "constant-overflow",
+    "redundant-parens",
+    "constant-condition",
+    "shadowed-variable",
+];
+
+/// The names of the warnings given by the linter, which are allowed unless they are turned on, as they are about
+/// style rather than likely mistakes.
+pub const LINT_NAMES: [&str; 3] = ["redundant-parens", "constant-condition", "shadowed-variable"];
+
+impl WarningType {
+    /// Gets the number in the code of the warning, which is 3 for W0003. Numbers are never changed or reused, so new
+    /// warnings take the next free number.
+    #[must_use]
+    pub fn number(&self) -> u16 {
+        match self {
+            Self::CLIArgRoundedDownU16(..) => 1,
+            Self::CLITargetLargerThanMachine(..) => 2,
+            Self::SelfComparison(..) => 3,
+            Self::UnusedValue(..) => 4,
+            Self::ConstantOverflow(..) => 5,
+            Self::RedundantParens => 6,
+            Self::ConstantCondition(..) => 7,
+            Self::ShadowedVariable(..) => 8,
+        }
+    }
+
+    /// Gets the arguments of the message of the warning, which go where the template has "{0}", "{1}", and so on, with
+    /// numbers written in the given format.
+    #[must_use]
+    pub fn args(&self, numbers: NumberFormat) -> Vec<String> {
+        match self {
+            Self::CLIArgRoundedDownU16(arg, value) => vec![arg.clone(), numbers.number((*value).into())],
+            Self::CLITargetLargerThanMachine(ptr_size) => vec![ptr_size.to_string(), usize::BITS.to_string()],
+            Self::SelfComparison(op, always) => vec![op.clone(), always.to_string()],
+            Self::UnusedValue(op) => vec![op.clone()],
+            Self::ConstantOverflow(exact, wrapped) => vec![exact.to_string(), wrapped.to_string()],
+            Self::RedundantParens => Vec::new(),
+            Self::ConstantCondition(always) => vec![always.to_string()],
+            Self::ShadowedVariable(name, line) => vec![name.clone(), line.to_string()],
+        }
+    }
+
+    /// Gets the name of the warning, as used on the command line.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        WARNING_NAMES[usize::from(self.number()) - 1]
+    }
+
+    /// Returns whether or not the warning is given by the linter.
+    #[must_use]
+    pub fn is_lint(&self) -> bool {
+        LINT_NAMES.contains(&self.name())
+    }
+}
+
+/// How a warning is reported.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum WarningLevel {
+    /// The warning isn't reported.
+    Allow,
+    /// The warning is reported as a warning. This is the default.
+    #[default]
+    Warn,
+    /// The warning is reported as an error, so the code doesn't compile.
+    Deny,
+}
+
+/// Decides how each warning is reported. Warnings that weren't given their own level take the level of all warnings,
+/// except for lints, which are allowed until they are turned on.
+///
+/// On the command line, `-A=self-comparison` allows a warning, `-W=self-comparison` shows it, and
+/// `--deny=self-comparison` makes it an error. A warning is given by its name, by its code (`W0003`), or as `warnings`
+/// for every warning, and later flags win over earlier ones.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct DiagnosticConfig {
+    /// The level of warnings that weren't given their own level.
+    pub warnings: WarningLevel,
+    // The levels given to single warnings, in the order of their numbers.
+    levels: [Option<WarningLevel>; WARNING_NAMES.len()],
+}
+
+impl DiagnosticConfig {
+    /// Sets the level of the warning with the given name or code, such as "self-comparison" or "W0003", of every lint
+    /// if the name is "lints", or of every warning if the name is "warnings". Lints that are off stay off when the
+    /// level of every warning is set. Every earlier level of the warnings is replaced. Returns whether or not the name
+    /// is of a warning.
+    pub fn set(&mut self, name: &str, level: WarningLevel) -> bool {
+        if name == "warnings" {
+            self.warnings = level;
+            for (own, name) in self.levels.iter_mut().zip(WARNING_NAMES) {
+                *own = match *own {
+                    Some(own) if own != WarningLevel::Allow && LINT_NAMES.contains(&name) => Some(level),
+                    _ => None,
+                };
+            }
+            return true;
+        }
+        if name == "lints" {
+            for (own, name) in self.levels.iter_mut().zip(WARNING_NAMES) {
+                if LINT_NAMES.contains(&name) {
+                    *own = Some(level);
+                }
+            }
+            return true;
+        }
+        let index: Option<usize> = WARNING_NAMES.iter().position(|warning| *warning == name).or_else(|| {
+            let number: usize = name.strip_prefix('W')?.parse().ok()?;
+            (1..=WARNING_NAMES.len()).contains(&number).then(|| number - 1)
+        });
+        if let Some(index) = index {
+            self.levels[index] = Some(level);
+        }
+        index.is_some()
+    }
+
+    /// Gets the level of the warning.
+    #[must_use]
+    pub fn level(&self, warning: &WarningType) -> WarningLevel {
+        self.levels[usize::from(warning.number()) - 1].unwrap_or(if warning.is_lint() {
+            WarningLevel::Allow
+        } else {
+            self.warnings
+        })
+    }
+
+    /// Removes the warnings that are allowed and turns the warnings that are denied into errors, each followed by a
+    /// note saying which warning was denied. Other logs are left as they are.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)] // Should never actually panic.
+    pub fn apply(&self, logs: Vec<Log>) -> Vec<Log> {
+        let mut applied: Vec<Log> = Vec::new();
+        for log in logs {
+            let LogType::Warning(warning) = &log.log_type else {
+                applied.push(log);
+                continue;
+            };
+            match self.level(warning) {
+                WarningLevel::Allow => {}
+                WarningLevel::Warn => applied.push(log),
+                WarningLevel::Deny => {
+                    let note: NoteType = NoteType::DeniedWarning(
+                        warning.name().to_string(),
+                        log.code().expect("warnings have codes"),
+                    );
+                    applied.push(Log {
+                        log_type: LogType::Error(ErrorType::DeniedWarning(warning.clone())),
+                        line_and_col: log.line_and_col,
+                        span: log.span.clone(),
+                        notes: log.notes,
+                    });
+                    applied.push(Log {
+                        log_type: LogType::Note(note),
+                        line_and_col: log.line_and_col,
+                        span: log.span,
+                        notes: Vec::new(),
+                    });
+                }
+            }
+        }
+        applied
+    }
 }
 
 /// An enum representing any possible error.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ErrorType {
     FatalError,
 
@@ -37,17 +388,50 @@ pub enum ErrorType {
     CLIRequiresNumArgLessThanU16(String, u16),
     CLIRequiresNumArgAtLeastU16(String, u16),
     CLIRequiresBoolArg(String),
+    CLIRequiresSeverityArg(String),
+    CLIRequiresIsaLevelArg(String),
+    CLIRequiresOptLevelArg(String),
+    CLIRequiresStreamArg(String),
+    CLIRequiresNumberFormatArg(String),
+    CLIRequiresPassArg(String),
+    CLIRequiresEmitArg(String),
+    CLIEmitNeedsSource(String),
+    CLIRequiresTargetArg(String),
+    CLITargetOnlyBuilds(String),
+    CLIOutputOnlyBuilds(String),
+    NotFormatted(String, usize),
+    ConfigInvalidLine(String, usize), // The name of the config file, and the line.
+    ConfigUnknownSetting(String, usize, String), // The name of the config file, the line, and the setting or section.
+    ConfigInvalidValue(String, usize, String, String), // The same, followed by a description of the valid values.
+    AssertionFailed(String),
+    CLIRequiresShellArg(String),
+    ZeroPointerSize,
+    NestedTooDeeply(usize),
+    CLIOutputIsInput(String),
+    CLIOutputExtension(String, String), // The output path, and the extension the target's programs have.
+    CLIRequiresEncodingArg(String),
+    CLIRequiresEndiannessArg(String),
+    CLIRequiresProfileArg(String),
+    CLIRequiresErrorFormatArg(String),
+    CLIRequiresWarningArg(String),
+    CLIRequiresColorArg(String),
+    CLIRequiredPass(String),
     CLIUnrecognizedArg(String),
     CLICantOpenFile(String),
     CLINoFile,
     CLIFileToBig(usize),
 
     UnrepresentableIntegerLiteral(String),
+    InvalidIntegerSuffix(String),
+    UnsupportedIntegerSuffix(String, String),
+    UnterminatedStringLiteral,
+    InvalidEscapeSequence(String),
 
     UnexpectedEOF,
     UnexpectedToken(String),
     ExpectedExpressionInParens,
     ExpectedCloseParen,
+    ExpectedOpenParen(String),
     ExpectedVariableDeclaration(String),
     InvalidTypesForCast(String, String),
     ExpectedExpressionAfterCast(String),
@@ -55,189 +439,895 @@ pub enum ErrorType {
     InvalidArgsForAssignment(String, [String; 2]),
     UnnegatedMinimumIntegerLiteral,
     UndeclaredVariable(String),
+    UnknownFunction(String),
+    InvalidArgCountForFunction(String, usize, usize),
+    InvalidArgRangeForFunction(String, usize, usize, usize),
+    InvalidArgsForFunction(String, Vec<String>),
+    ExpectedMapType,
+    ExpectedColonInMapEntry,
+    ExpectedCloseBrace,
+    InvalidMapEntry(String, [String; 2]),
+    ExpectedListType,
+    InvalidListElement(String, String),
+    ExpectedResultType,
+    TryOutsideResultFunction,
+    ExpectedCloseBracket,
+    InvalidFunctionDeclaration(String),
+    UninferableTypeParameter(String, String),
+    NonConstantDefault(String),
+    InvalidDefaultType(String, String, String),
+    MissingDefault(String),
+    DuplicateFunction(String),
+    InvalidHostFunction(String),
+    InvalidReturnType(String, String, String),
+    FunctionUsedAsValue(String),
+    InvalidTypeArgument(String, String),
+    InconsistentTypeArgument(String, String, [String; 2]),
+    InvalidTraitDeclaration(String),
+    InvalidImplDeclaration(String),
+    UnknownTrait(String),
+    DuplicateTrait(String),
+    DuplicateMethod(String, String),
+    InvalidTraitMethod(String, String),
+    MissingTraitMethod(String, String, String),
+    MismatchedMethodSignature(String, String),
+    UnknownMethod(String, String),
+    UnsatisfiedTraitBound(String, String, String),
+    InvalidFlagsDeclaration(String),
+    NonConstantFlag(String),
+    DuplicateFlag(String, String),
+    UnknownFlag(String, String),
+    FlagsUsedAsValue(String),
 
-    ExcessiveBytecode,
+    ExcessiveBytecode(usize, usize),
     TooManyVariables(usize),
+    IsaLevelNotTargeted(u8, String, u8),
+    ConstantDivideByZero,
+    DivideByConstantZero,
+    UnsupportedByBackend(String, String),
 
     CantCompile,
+    TooManyErrors(usize, usize), // The number of errors shown, and the number left out.
+    InternalCompilerError(String),
+
+    InvalidBytecodeHeader,
+    InvalidBytecodeMetadata,
+    CorruptBytecode(String),
+    InvalidOpCode(usize, u8),
+    IncompleteOperand(usize),
+    InvalidVariableAccess(usize),
+    StackUnderflow(usize),
+    InvalidCallTarget(usize),
+    InvalidConstant(usize),
+    InvalidJumpTarget(usize),
+    MismatchedStackAtJump(usize),
+    InstructionAboveIsaLevel(usize, u8, u8),
 
     CompiledForDifferentTarget(usize),
+    UnsupportedIsaLevel(u8),
     DivideByZero,
+    NullUnwrap,
+    NegativeSquareRoot,
+    SubstringOutOfRange,
+    IndexOutOfRange,
+    CallDepthExceeded(usize),
+    VmStackOverflow(usize, usize),
+    OutOfMemory(usize),
+    UnknownHostFunction(String),
+    HostFunctionFailed(String, String),
+    FileAccessDisabled,
+    FileReadFailed(String, String),
+    FileWriteFailed(String, String),
+    ErrUnwrap(String),
+    Panic(String),
+    DeniedWarning(WarningType),
+}
+
+impl ErrorType {
+    /// Gets the number in the code of the error, which is 12 for E0012. Numbers are never changed or reused, so new
+    /// errors take the next free number.
+    #[must_use]
+    #[allow(clippy::too_many_lines)] // Necessary for all the different error types.
+    pub fn number(&self) -> u16 {
+        match self {
+            Self::FatalError => 1,
+            Self::CLIMultipleFiles => 2,
+            Self::CLICantReadArgs => 3,
+            Self::CLINoArgs => 4,
+            Self::CLIRequiresArg(..) => 5,
+            Self::CLIRequiresNumArg(..) => 6,
+            Self::CLIRequiresNumArgLessThanU16(..) => 7,
+            Self::CLIRequiresNumArgAtLeastU16(..) => 8,
+            Self::CLIRequiresBoolArg(..) => 9,
+            Self::CLIRequiresSeverityArg(..) => 10,
+            Self::CLIRequiresIsaLevelArg(..) => 11,
+            Self::CLIRequiresOptLevelArg(..) => 12,
+            Self::CLIRequiresStreamArg(..) => 13,
+            Self::CLIRequiresNumberFormatArg(..) => 14,
+            Self::CLIRequiresPassArg(..) => 15,
+            Self::CLIRequiresEmitArg(..) => 16,
+            Self::CLIEmitNeedsSource(..) => 17,
+            Self::CLIRequiresTargetArg(..) => 18,
+            Self::CLITargetOnlyBuilds(..) => 19,
+            Self::CLIRequiresEncodingArg(..) => 20,
+            Self::CLIRequiresEndiannessArg(..) => 21,
+            Self::CLIRequiresProfileArg(..) => 22,
+            Self::CLIRequiredPass(..) => 23,
+            Self::CLIUnrecognizedArg(..) => 24,
+            Self::CLICantOpenFile(..) => 25,
+            Self::CLINoFile => 26,
+            Self::CLIFileToBig(..) => 27,
+            Self::UnrepresentableIntegerLiteral(..) => 28,
+            Self::InvalidIntegerSuffix(..) => 29,
+            Self::UnsupportedIntegerSuffix(..) => 30,
+            Self::UnterminatedStringLiteral => 31,
+            Self::InvalidEscapeSequence(..) => 32,
+            Self::UnexpectedEOF => 33,
+            Self::UnexpectedToken(..) => 34,
+            Self::ExpectedExpressionInParens => 35,
+            Self::ExpectedCloseParen => 36,
+            Self::ExpectedOpenParen(..) => 37,
+            Self::ExpectedVariableDeclaration(..) => 38,
+            Self::InvalidTypesForCast(..) => 39,
+            Self::ExpectedExpressionAfterCast(..) => 40,
+            Self::InvalidArgsForOperator(..) => 41,
+            Self::InvalidArgsForAssignment(..) => 42,
+            Self::UnnegatedMinimumIntegerLiteral => 43,
+            Self::UndeclaredVariable(..) => 44,
+            Self::UnknownFunction(..) => 45,
+            Self::InvalidArgCountForFunction(..) => 46,
+            Self::InvalidArgRangeForFunction(..) => 47,
+            Self::InvalidArgsForFunction(..) => 48,
+            Self::ExpectedMapType => 49,
+            Self::ExpectedColonInMapEntry => 50,
+            Self::ExpectedCloseBrace => 51,
+            Self::InvalidMapEntry(..) => 52,
+            Self::ExpectedListType => 53,
+            Self::InvalidListElement(..) => 54,
+            Self::ExpectedResultType => 55,
+            Self::TryOutsideResultFunction => 56,
+            Self::ExpectedCloseBracket => 57,
+            Self::InvalidFunctionDeclaration(..) => 58,
+            Self::UninferableTypeParameter(..) => 59,
+            Self::NonConstantDefault(..) => 60,
+            Self::InvalidDefaultType(..) => 61,
+            Self::MissingDefault(..) => 62,
+            Self::DuplicateFunction(..) => 63,
+            Self::InvalidHostFunction(..) => 64,
+            Self::InvalidReturnType(..) => 65,
+            Self::FunctionUsedAsValue(..) => 66,
+            Self::InvalidTypeArgument(..) => 67,
+            Self::InconsistentTypeArgument(..) => 68,
+            Self::InvalidTraitDeclaration(..) => 69,
+            Self::InvalidImplDeclaration(..) => 70,
+            Self::UnknownTrait(..) => 71,
+            Self::DuplicateTrait(..) => 72,
+            Self::DuplicateMethod(..) => 73,
+            Self::InvalidTraitMethod(..) => 74,
+            Self::MissingTraitMethod(..) => 75,
+            Self::MismatchedMethodSignature(..) => 76,
+            Self::UnknownMethod(..) => 77,
+            Self::UnsatisfiedTraitBound(..) => 78,
+            Self::InvalidFlagsDeclaration(..) => 79,
+            Self::NonConstantFlag(..) => 80,
+            Self::DuplicateFlag(..) => 81,
+            Self::UnknownFlag(..) => 82,
+            Self::FlagsUsedAsValue(..) => 83,
+            Self::ExcessiveBytecode(..) => 84,
+            Self::TooManyVariables(..) => 85,
+            Self::IsaLevelNotTargeted(..) => 86,
+            Self::ConstantDivideByZero => 87,
+            Self::UnsupportedByBackend(..) => 88,
+            Self::CantCompile => 89,
+            Self::InternalCompilerError(..) => 90,
+            Self::InvalidBytecodeHeader => 91,
+            Self::InvalidBytecodeMetadata => 92,
+            Self::CorruptBytecode(..) => 93,
+            Self::InvalidOpCode(..) => 94,
+            Self::IncompleteOperand(..) => 95,
+            Self::InvalidVariableAccess(..) => 96,
+            Self::StackUnderflow(..) => 97,
+            Self::InvalidCallTarget(..) => 98,
+            Self::InvalidConstant(..) => 99,
+            Self::InvalidJumpTarget(..) => 100,
+            Self::MismatchedStackAtJump(..) => 101,
+            Self::InstructionAboveIsaLevel(..) => 102,
+            Self::CompiledForDifferentTarget(..) => 103,
+            Self::UnsupportedIsaLevel(..) => 104,
+            Self::DivideByZero => 105,
+            Self::NullUnwrap => 106,
+            Self::NegativeSquareRoot => 107,
+            Self::SubstringOutOfRange => 108,
+            Self::IndexOutOfRange => 109,
+            Self::CallDepthExceeded(..) => 110,
+            Self::VmStackOverflow(..) => 111,
+            Self::OutOfMemory(..) => 112,
+            Self::UnknownHostFunction(..) => 113,
+            Self::HostFunctionFailed(..) => 114,
+            Self::FileAccessDisabled => 115,
+            Self::FileReadFailed(..) => 116,
+            Self::FileWriteFailed(..) => 117,
+            Self::ErrUnwrap(..) => 118,
+            Self::Panic(..) => 119,
+            Self::CLIRequiresErrorFormatArg(..) => 120,
+            Self::CLIRequiresWarningArg(..) => 121,
+            Self::DeniedWarning(..) => 122,
+            Self::CLIRequiresColorArg(..) => 123,
+            Self::DivideByConstantZero => 124,
+            Self::TooManyErrors(..) => 125,
+            Self::CLIOutputOnlyBuilds(..) => 126,
+            Self::NotFormatted(..) => 127,
+            Self::ConfigInvalidLine(..) => 128,
+            Self::ConfigUnknownSetting(..) => 129,
+            Self::ConfigInvalidValue(..) => 130,
+            Self::AssertionFailed(..) => 131,
+            Self::CLIRequiresShellArg(..) => 132,
+            Self::ZeroPointerSize => 133,
+            Self::NestedTooDeeply(..) => 134,
+            Self::CLIOutputIsInput(..) => 135,
+            Self::CLIOutputExtension(..) => 136,
+        }
+    }
+
+    /// Returns whether or not the error is about how krust was used, such as a flag or a config file that isn't valid,
+    /// rather than about the code.
+    #[must_use]
+    pub fn is_usage_error(&self) -> bool {
+        matches!(
+            self,
+            Self::CLIMultipleFiles
+                | Self::CLICantReadArgs
+                | Self::CLINoArgs
+                | Self::CLIRequiresArg(..)
+                | Self::CLIRequiresNumArg(..)
+                | Self::CLIRequiresNumArgLessThanU16(..)
+                | Self::CLIRequiresNumArgAtLeastU16(..)
+                | Self::CLIRequiresBoolArg(..)
+                | Self::CLIRequiresSeverityArg(..)
+                | Self::CLIRequiresIsaLevelArg(..)
+                | Self::CLIRequiresOptLevelArg(..)
+                | Self::CLIRequiresStreamArg(..)
+                | Self::CLIRequiresNumberFormatArg(..)
+                | Self::CLIRequiresPassArg(..)
+                | Self::CLIRequiresEmitArg(..)
+                | Self::CLIEmitNeedsSource(..)
+                | Self::CLIRequiresTargetArg(..)
+                | Self::CLITargetOnlyBuilds(..)
+                | Self::CLIOutputOnlyBuilds(..)
+                | Self::CLIOutputIsInput(..)
+                | Self::CLIOutputExtension(..)
+                | Self::ConfigInvalidLine(..)
+                | Self::ConfigUnknownSetting(..)
+                | Self::ConfigInvalidValue(..)
+                | Self::CLIRequiresEncodingArg(..)
+                | Self::CLIRequiresEndiannessArg(..)
+                | Self::CLIRequiresProfileArg(..)
+                | Self::CLIRequiresErrorFormatArg(..)
+                | Self::CLIRequiresWarningArg(..)
+                | Self::CLIRequiresColorArg(..)
+                | Self::CLIRequiresShellArg(..)
+                | Self::CLIRequiredPass(..)
+                | Self::CLIUnrecognizedArg(..)
+                | Self::CLICantOpenFile(..)
+                | Self::CLINoFile
+                | Self::CLIFileToBig(..)
+                | Self::DeniedWarning(WarningType::CLIArgRoundedDownU16(..) | WarningType::CLITargetLargerThanMachine(..))
+        )
+    }
+
+    /// Returns whether or not the error stopped a program while it was running, rather than stopping it from being
+    /// compiled or loaded.
+    #[must_use]
+    pub fn is_runtime_error(&self) -> bool {
+        matches!(
+            self,
+            Self::FatalError
+                | Self::AssertionFailed(..)
+                | Self::DivideByZero
+                | Self::NullUnwrap
+                | Self::NegativeSquareRoot
+                | Self::SubstringOutOfRange
+                | Self::IndexOutOfRange
+                | Self::CallDepthExceeded(..)
+                | Self::VmStackOverflow(..)
+                | Self::OutOfMemory(..)
+                | Self::UnknownHostFunction(..)
+                | Self::HostFunctionFailed(..)
+                | Self::FileAccessDisabled
+                | Self::FileReadFailed(..)
+                | Self::FileWriteFailed(..)
+                | Self::ErrUnwrap(..)
+                | Self::Panic(..)
+        )
+    }
+
+    /// Gets the arguments of the message of the error, which go where the template has "{0}", "{1}", and so on, with
+    /// numbers written in the given format.
+    #[must_use]
+    #[allow(clippy::too_many_lines)] // Necessary for all the different error types.
+    pub fn args(&self, numbers: NumberFormat) -> Vec<String> {
+        let plural = |count: usize| if count == 1 { String::new() } else { "s".to_string() };
+        let quoted_list = |names: &[&str]| {
+            format_vec_string(&names.iter().map(|name| format!("\"{name}\"")).collect::<Vec<String>>(), "or").unwrap_or_default()
+        };
+        match self {
+            Self::FatalError
+            | Self::CLIMultipleFiles
+            | Self::CLICantReadArgs
+            | Self::CLINoArgs
+            | Self::CLINoFile
+            | Self::ZeroPointerSize
+            | Self::UnterminatedStringLiteral
+            | Self::UnexpectedEOF
+            | Self::ExpectedExpressionInParens
+            | Self::ExpectedCloseParen
+            | Self::ExpectedMapType
+            | Self::ExpectedColonInMapEntry
+            | Self::ExpectedCloseBrace
+            | Self::ExpectedListType
+            | Self::ExpectedResultType
+            | Self::TryOutsideResultFunction
+            | Self::ExpectedCloseBracket
+            | Self::ConstantDivideByZero
+            | Self::DivideByConstantZero
+            | Self::CantCompile
+            | Self::InvalidBytecodeHeader
+            | Self::InvalidBytecodeMetadata
+            | Self::DivideByZero
+            | Self::NullUnwrap
+            | Self::NegativeSquareRoot
+            | Self::SubstringOutOfRange
+            | Self::IndexOutOfRange
+            | Self::FileAccessDisabled => Vec::new(),
+
+            Self::CLIRequiresArg(arg)
+            | Self::CLIRequiresNumArg(arg)
+            | Self::CLIRequiresBoolArg(arg)
+            | Self::CLIRequiresSeverityArg(arg)
+            | Self::CLIRequiresStreamArg(arg)
+            | Self::CLIRequiresNumberFormatArg(arg)
+            | Self::CLIRequiresErrorFormatArg(arg)
+            | Self::CLIRequiresColorArg(arg)
+            | Self::CLIEmitNeedsSource(arg)
+            | Self::CLITargetOnlyBuilds(arg)
+            | Self::CLIOutputOnlyBuilds(arg)
+            | Self::CLIOutputIsInput(arg)
+            | Self::CLIRequiredPass(arg)
+            | Self::CLIUnrecognizedArg(arg)
+            | Self::CLICantOpenFile(arg)
+            | Self::InvalidIntegerSuffix(arg)
+            | Self::InvalidEscapeSequence(arg)
+            | Self::UnexpectedToken(arg)
+            | Self::ExpectedOpenParen(arg)
+            | Self::ExpectedVariableDeclaration(arg)
+            | Self::UndeclaredVariable(arg)
+            | Self::UnknownFunction(arg)
+            | Self::InvalidFunctionDeclaration(arg)
+            | Self::NonConstantDefault(arg)
+            | Self::MissingDefault(arg)
+            | Self::DuplicateFunction(arg)
+            | Self::InvalidHostFunction(arg)
+            | Self::FunctionUsedAsValue(arg)
+            | Self::InvalidTraitDeclaration(arg)
+            | Self::InvalidImplDeclaration(arg)
+            | Self::UnknownTrait(arg)
+            | Self::DuplicateTrait(arg)
+            | Self::InvalidFlagsDeclaration(arg)
+            | Self::NonConstantFlag(arg)
+            | Self::FlagsUsedAsValue(arg)
+            | Self::InternalCompilerError(arg)
+            | Self::CorruptBytecode(arg)
+            | Self::UnknownHostFunction(arg)
+            | Self::ErrUnwrap(arg)
+            | Self::Panic(arg)
+            | Self::AssertionFailed(arg) => vec![arg.clone()],
+
+            Self::UnsupportedIntegerSuffix(first, second)
+            | Self::InvalidTypesForCast(first, second)
+            | Self::InvalidListElement(first, second)
+            | Self::UninferableTypeParameter(first, second)
+            | Self::InvalidTypeArgument(first, second)
+            | Self::DuplicateMethod(first, second)
+            | Self::InvalidTraitMethod(first, second)
+            | Self::MismatchedMethodSignature(first, second)
+            | Self::UnknownMethod(first, second)
+            | Self::DuplicateFlag(first, second)
+            | Self::UnknownFlag(first, second)
+            | Self::UnsupportedByBackend(first, second)
+            | Self::HostFunctionFailed(first, second)
+            | Self::FileReadFailed(first, second)
+            | Self::FileWriteFailed(first, second)
+            | Self::CLIOutputExtension(first, second) => vec![first.clone(), second.clone()],
+
+            Self::InvalidDefaultType(first, second, third)
+            | Self::InvalidReturnType(first, second, third)
+            | Self::MissingTraitMethod(first, second, third)
+            | Self::UnsatisfiedTraitBound(first, second, third) => vec![first.clone(), second.clone(), third.clone()],
+
+            Self::CLIRequiresNumArgLessThanU16(arg, bound) | Self::CLIRequiresNumArgAtLeastU16(arg, bound)
+                => vec![arg.clone(), numbers.bound((*bound).into())],
+            Self::CLIRequiresIsaLevelArg(arg) => vec![arg.clone(), (IsaLevel::LATEST as u8).to_string()],
+            Self::CLIRequiresOptLevelArg(arg) => vec![arg.clone(), (OptLevel::MAX as u8).to_string()],
+            Self::CLIRequiresPassArg(arg) => vec![arg.clone(), quoted_list(&CompilerPass::ALL.map(CompilerPass::name))],
+            Self::CLIRequiresEmitArg(arg) => vec![arg.clone(), quoted_list(&Emit::ALL.map(Emit::name))],
+            Self::CLIRequiresTargetArg(arg) => vec![arg.clone(), quoted_list(&Backend::ALL.map(Backend::name))],
+            Self::CLIRequiresEncodingArg(arg) => vec![arg.clone(), quoted_list(&Encoding::ALL.map(Encoding::name))],
+            Self::CLIRequiresEndiannessArg(arg) => vec![arg.clone(), quoted_list(&Endianness::ALL.map(Endianness::name))],
+            Self::CLIRequiresProfileArg(arg) => vec![arg.clone(), quoted_list(&ProfileFormat::ALL.map(ProfileFormat::name))],
+            Self::CLIRequiresWarningArg(arg) => vec![arg.clone(), quoted_list(&WARNING_NAMES)],
+            Self::CLIRequiresShellArg(arg) => vec![arg.clone(), quoted_list(&Shell::ALL.map(Shell::name))],
+            Self::CLIFileToBig(ptr_size) => vec![ptr_size.to_string()],
+
+            Self::UnrepresentableIntegerLiteral(token) => vec![token.clone(), numbers.bound(0x_8000_0000)],
+            Self::ExpectedExpressionAfterCast(value) => vec![value.trim_matches('"').to_string()],
+            Self::InvalidArgsForOperator(name, types) | Self::InvalidArgsForFunction(name, types)
+                => vec![name.clone(), format_vec_string(types, "and").unwrap_or_default(), plural(types.len())],
+            Self::InvalidArgsForAssignment(var, types) | Self::InvalidMapEntry(var, types)
+                => vec![var.clone(), types[0].clone(), types[1].clone()],
+            Self::UnnegatedMinimumIntegerLiteral => vec![numbers.number(0x8000_0000)],
+            Self::InvalidArgCountForFunction(function, expected, found)
+                => vec![function.clone(), expected.to_string(), found.to_string(), plural(*expected)],
+            Self::InvalidArgRangeForFunction(function, min, max, found)
+                => vec![function.clone(), min.to_string(), max.to_string(), found.to_string()],
+            Self::InconsistentTypeArgument(type_param, function, [first, second])
+                => vec![type_param.clone(), function.clone(), first.clone(), second.clone()],
+
+            Self::ExcessiveBytecode(size, limit) => vec![numbers.number(*size as u64), numbers.number(*limit as u64)],
+            Self::TooManyVariables(bytes) => vec![numbers.bound(1 << (8 * bytes))],
+            Self::IsaLevelNotTargeted(level, features, target) => vec![level.to_string(), features.clone(), target.to_string()],
+            Self::TooManyErrors(shown, hidden) => vec![shown.to_string(), hidden.to_string(), plural(*shown)],
+            Self::NotFormatted(file, line) | Self::ConfigInvalidLine(file, line) => vec![file.clone(), line.to_string()],
+            Self::ConfigUnknownSetting(file, line, setting) => vec![file.clone(), line.to_string(), setting.clone()],
+            Self::ConfigInvalidValue(file, line, setting, expected) => {
+                vec![file.clone(), line.to_string(), setting.clone(), expected.clone()]
+            }
+
+            Self::InvalidOpCode(offset, byte) => vec![numbers.number(*offset as u64), format!("{byte:#04x}")],
+            Self::IncompleteOperand(offset)
+            | Self::InvalidVariableAccess(offset)
+            | Self::StackUnderflow(offset)
+            | Self::InvalidCallTarget(offset)
+            | Self::InvalidConstant(offset)
+            | Self::InvalidJumpTarget(offset)
+            | Self::MismatchedStackAtJump(offset) => vec![numbers.number(*offset as u64)],
+            Self::InstructionAboveIsaLevel(offset, level, header_level)
+                => vec![numbers.number(*offset as u64), level.to_string(), header_level.to_string()],
+
+            Self::CompiledForDifferentTarget(ptr_size) => vec![ptr_size.to_string(), usize::BITS.to_string()],
+            Self::UnsupportedIsaLevel(level) => vec![level.to_string(), (IsaLevel::LATEST as u8).to_string()],
+            Self::CallDepthExceeded(depth) | Self::NestedTooDeeply(depth) => vec![numbers.number(*depth as u64)],
+            Self::VmStackOverflow(offset, limit) => vec![numbers.number(*offset as u64), numbers.number(*limit as u64)],
+            Self::OutOfMemory(limit) => vec![numbers.number(*limit as u64)],
+            Self::DeniedWarning(warning) => vec![Log {
+                log_type: LogType::Warning(warning.clone()),
+                line_and_col: None,
+                span: None,
+                notes: Vec::new(),
+            }
+            .message_with(numbers)],
+        }
+    }
 }
 
 /// Represents all possible errors as well as helpful debug information when relevant.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Log {
     pub log_type: LogType,
     pub line_and_col: Option<(usize, usize)>,
+    /// The part of the source the log is about, if it is about a token or an expression rather than a point.
+    pub span: Option<Span>,
+    /// Notes and help about other parts of the source, which are printed under the log.
+    pub notes: Vec<Note>,
 }
 
-impl Display for Log {
-    #[allow(clippy::too_many_lines)] // Necessary for all the different log types.
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        if let LogType::Error(error_type) = self.log_type.clone() {
-            if error_type == ErrorType::FatalError {
-                let error: ColoredString =
-                    "fatal error; program terminated".to_string().red().bold();
-                return write!(f, "{error}");
-            }
+/// A note or help message attached to a log, about a part of the source of its own such as one of the operands of an
+/// operator.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Note {
+    /// This is always a `LogType::Note` or a `LogType::Help`.
+    pub log_type: LogType,
+    pub span: Option<Span>,
+}
+
+impl Note {
+    /// Converts the note to a log of its own, which is located at the start of its span.
+    #[must_use]
+    pub fn to_log(&self) -> Log {
+        Log {
+            log_type: self.log_type.clone(),
+            line_and_col: self.span.as_ref().map(|span| span.start),
+            span: self.span.clone(),
+            notes: Vec::new(),
         }
+    }
+}
 
-        let log_type: ColoredString = match self.log_type.clone() {
-            LogType::Info(_) => "info".to_string().white(),
-            LogType::Warning(_) => "warning".to_string().yellow(),
-            LogType::Error(_) => "error".to_string().red(),
-        }
-        .bold();
-
-        let mut message_is_bold: bool = true;
-        let message: String = {
-            match self.log_type.clone() {
-                LogType::Info(info_type) => { match info_type
-                {
-                    InfoType::NewVarNotSet(var)
-                        => format!("the variable \"{var}\" has been initialized but hasn't been set to a value. It will instead take the default value of the type."),
-                }},
-                LogType::Warning(warning_type) => {match warning_type
-                {
-                    WarningType::CLIArgRoundedDownU16(arg, value)
-                        => format!("argument of \"{arg}\" will be rounded down to the nearest multiple of {value}."),
-                    WarningType::CLITargetLargerThanMachine(ptr_size)
-                        => format!("warning: this program is being compiled for a {ptr_size}-bit machine, while this is only a {}-bit machine.", 
-                        usize::BITS)
-                }},
-                LogType::Error(error_type) => {match error_type
-                {
-                    ErrorType::FatalError => String::new(), // dealt with above
-
-                    ErrorType::CLIMultipleFiles => "command line contains multiple files.".to_string(),
-                    ErrorType::CLICantReadArgs => "could not read command line arguments.".to_string(),
-                    ErrorType::CLINoArgs => "no command line arguments.".to_string(),
-                    ErrorType::CLIRequiresArg(arg) 
-                        => format!("compiler flag \"{arg}\" requires an argument."),
-                    ErrorType::CLIRequiresNumArg(arg) 
-                        => format!("compiler flag \"{arg}\" requires a numerical argument."),
-                    ErrorType::CLIRequiresNumArgLessThanU16(arg, bound) 
-                        => format!("compiler flag \"{arg}\" requires an argument less than {bound}."),
-                    ErrorType::CLIRequiresNumArgAtLeastU16(arg, bound) 
-                        => format!("compiler flag \"{arg}\" requires an argument that's at least {bound}."),
-                    ErrorType::CLIRequiresBoolArg(arg) 
-                        => format!("compiler flag \"{arg}\" requires a boolean argument."),
-                    ErrorType::CLIUnrecognizedArg(arg)
-                        => format!("unrecognized argument \"{arg}\"."),
-                    ErrorType::CLICantOpenFile(path)
-                        => format!("could not open file \"{path}\"."),
-                    ErrorType::CLINoFile => "no source file entered.".to_string(),
-                    ErrorType::CLIFileToBig(ptr_size) 
-                        => format!("the file is too big to compile for a {ptr_size}-bit machine."),
-
-                    ErrorType::UnrepresentableIntegerLiteral(token) 
-                        => format!("int literal \"{token}\" must be at most {}.", 0x_8000_0000_u32),
-
-                    ErrorType::UnexpectedEOF => "unexpected end of file.".to_string(),
-                    ErrorType::UnexpectedToken(token)
-                        => format!("unexpected token \"{token}\"."),
-                    ErrorType::ExpectedExpressionInParens => "expected expression within parentheses.".to_string(),
-                    ErrorType::ExpectedCloseParen => "expected \')\' following \'(\'.".to_string(),
-                    ErrorType::ExpectedVariableDeclaration(value)
-                        => format!("expected a variable declaration for {value}"),
-                    ErrorType::InvalidTypesForCast(type_in, type_out)
-                        => format!("the type {type_in} can not be cast to type {type_out}"),
-                    ErrorType::ExpectedExpressionAfterCast(value)
-                        => format!(
-                            "expected an expression after cast \"({})\"", 
-                            value.trim_matches('\"')
-                        ),
-                    ErrorType::InvalidArgsForOperator(op, types)
-                        => format!("the operator \"{op}\" has no definition over the type{} {}.", 
-                            if types.len() == 1 {""} else {"s"},
-                            format_vec_string(&types).unwrap_or_default()),
-                    ErrorType::InvalidArgsForAssignment(var, types)
-                        => format!("The variable \"{var}\" has type {}, so it can not be assigned a value of type {}", types[0], types[1]),
-                    ErrorType::UnnegatedMinimumIntegerLiteral
-                        => format!("the int literal {} must be preceded by a unary \'-\' operator.", 0x8000_0000_u32),
-                    ErrorType::UndeclaredVariable(var)
-                        => format!("the variable \"{var}\" has not yet been declared."),
-
-                    ErrorType::ExcessiveBytecode => "could not compile as bytecode was too large.".to_string(),
-                    ErrorType::TooManyVariables(bytes)
-                        => format!("there are more than {} variables declared, which is more than the compiler can handle.", 1 << (8 * bytes)),
-
-                    ErrorType::CantCompile => {
-                        message_is_bold = false;
-                        "could not compile due to errors.".to_string()
-                    }
-                
-                    
-                    ErrorType::CompiledForDifferentTarget(ptr_size) 
-                        => format!("this program was compiled for a {ptr_size}-bit machine, while this is only a {}-bit machine.", usize::BITS),
-                    ErrorType::DivideByZero => "division by zero.".to_string(),
-                }},
-            }
-        };
+impl Log {
+    /// Gets the code of the log if it is an error or a warning, such as "E0012" or "W0003".
+    #[must_use]
+    pub fn code(&self) -> Option<String> {
+        match &self.log_type {
+            LogType::Error(error_type) => Some(format!("E{:04}", error_type.number())),
+            LogType::Warning(warning_type) => Some(format!("W{:04}", warning_type.number())),
+            _ => None,
+        }
+    }
 
-        let mut output: String = if self.line_and_col.is_none() {
-            format!("{log_type}: {message}")
-        } else {
+    /// Writes the log as a single line of JSON with its code, severity, message, location, the file it is about, if that
+    /// is known, and its notes, which are written the same way. Numbers in the messages are written in the given format.
+    #[must_use]
+    pub fn to_json(&self, file: Option<&str>, numbers: NumberFormat) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+        let (line, col): (Option<usize>, Option<usize>) = self.line_and_col.unzip();
+        let span: Option<String> = self.span.as_ref().map(|span| {
             format!(
-                "{log_type} (line {}:{}): {message}",
-                self.line_and_col.expect("checked by if statement").0,
-                self.line_and_col.expect("checked by if statement").1
+                "{{\"start\":{{\"line\":{},\"col\":{}}},\"end\":{{\"line\":{},\"col\":{}}},\"byte_start\":{},\"byte_end\":{}}}",
+                span.start.0, span.start.1, span.end.0, span.end.1, span.byte_range.start, span.byte_range.end
             )
+        });
+        let notes: Vec<String> = self.notes.iter().map(|note| note.to_log().to_json(file, numbers)).collect();
+        format!(
+            "{{\"code\":{},\"severity\":\"{}\",\"message\":{},\"file\":{},\"line\":{},\"col\":{},\"span\":{},\"notes\":[{}]}}",
+            optional(self.code().as_deref().map(json_string)),
+            self.log_type.severity().name(),
+            json_string(&self.message_with(numbers)),
+            optional(file.map(json_string)),
+            optional(line.map(|line| line.to_string())),
+            optional(col.map(|col| col.to_string())),
+            optional(span),
+            notes.join(","),
+        )
+    }
+
+    /// Writes the log the way it is printed, with its code, followed by the line of the source it is about with its
+    /// span underlined if the source is given. Logs without a span are written on their own, and JSON logs never have
+    /// the line of source. The notes of the log are written the same way below it, indented.
+    #[must_use]
+    pub fn render(&self, file_text: Option<&str>, options: RenderOptions) -> String {
+        if options.format == ErrorFormat::Json {
+            return self.to_json(None, options.numbers);
+        }
+        let mut output: String = self.headline(true, options);
+        if let Some(snippet) = file_text.and_then(|file_text| self.colored_snippet(file_text, options.colored)) {
+            output.push('\n');
+            output.push_str(&snippet);
+        }
+        output + &self.render_notes(file_text, options)
+    }
+
+    // Writes the first line of the log, with its severity, location, and message, and its code if it is asked for.
+    fn headline(&self, with_code: bool, options: RenderOptions) -> String {
+        let colored: bool = options.colored;
+        if self.log_type == LogType::Error(ErrorType::FatalError) {
+            return bold("fatal error; program terminated", Some(Color::Red), colored);
+        }
+
+        let name: &str = self.log_type.severity().name();
+        let label: String = match self.code() {
+            Some(code) if with_code => format!("{name}[{code}]"),
+            _ => name.to_string(),
+        };
+        let log_type: String = bold(&label, Some(self.color()), colored);
+
+        let message: String = self.message_with(options.numbers);
+
+        let rest: String = if let Some((line, col)) = self.line_and_col {
+            format!(" (line {line}:{col}): {message}")
+        } else {
+            format!(": {message}")
         };
-        if message_is_bold {
-            output = output.bold().to_string();
+        if self.log_type == LogType::Error(ErrorType::CantCompile) {
+            format!("{log_type}{rest}")
+        } else {
+            format!("{log_type}{}", bold(&rest, None, colored))
+        }
+    }
+
+    // Writes each note on the lines after the log, indented, with the line of the source it is about if the source is
+    // given.
+    fn render_notes(&self, file_text: Option<&str>, options: RenderOptions) -> String {
+        let mut output: String = String::new();
+        for note in &self.notes {
+            let log: Log = note.to_log();
+            let mut lines: String = log.headline(false, options);
+            if let Some(snippet) = file_text.and_then(|file_text| log.colored_snippet(file_text, options.colored)) {
+                lines.push('\n');
+                lines.push_str(&snippet);
+            }
+            for line in lines.lines() {
+                output.push_str("\n  ");
+                output.push_str(line);
+            }
+        }
+        output
+    }
+
+    /// Gets the line of the source that the log's span starts on, with the span underlined below it. A span that goes
+    /// on past the end of the line is underlined to the end of it.
+    #[must_use]
+    pub fn snippet(&self, file_text: &str) -> Option<String> {
+        self.colored_snippet(file_text, false)
+    }
+
+    // Gets the snippet of the source, colored if colored is true.
+    fn colored_snippet(&self, file_text: &str, colored: bool) -> Option<String> {
+        let span: &Span = self.span.as_ref()?;
+        let start: usize = span.byte_range.start;
+        file_text.get(start..)?;
+        let line_start: usize = file_text[..start].rfind('\n').map_or(0, |index| index + 1);
+        let line_end: usize = file_text[start..]
+            .find('\n')
+            .map_or(file_text.len(), |index| start + index);
+        let end: usize = span.byte_range.end.clamp(start, line_end);
+
+        // Tabs are kept before the underline so that it lines up with the text above it.
+        let indent: String = file_text[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let underline: String = "^".repeat(file_text[start..end].chars().count().max(1));
+        let line_number: String = span.start.0.to_string();
+        let gutter: String = " ".repeat(line_number.len());
+        let bar: String = bold("|", Some(Color::Blue), colored);
+        Some(format!(
+            "{gutter} {bar}\n{} {bar} {}\n{gutter} {bar} {indent}{}",
+            bold(&line_number, Some(Color::Blue), colored),
+            file_text[line_start..line_end].trim_end_matches('\r'),
+            bold(&underline, Some(self.color()), colored),
+        ))
+    }
+
+    /// Gets the message of the log, without its severity or location. The messages of errors and warnings come from
+    /// the current locale provider if it has them, and are in English otherwise. Numbers are written in the default
+    /// format.
+    #[must_use]
+    pub fn message(&self) -> String {
+        self.message_with(NumberFormat::default())
+    }
+
+    /// Gets the message of the log like `message`, but with numbers written in the given format.
+    #[must_use]
+    pub fn message_with(&self, numbers: NumberFormat) -> String {
+        self.message_from(localize, numbers)
+    }
+
+    /// Gets the message of the log like `message`, but with the messages of errors and warnings from the given locale
+    /// provider instead of the current one.
+    #[must_use]
+    pub fn message_in(&self, provider: &dyn LocaleProvider) -> String {
+        self.message_from(|code, english, args| localize_with(provider, code, english, args), NumberFormat::default())
+    }
+
+    // Gets the message of the log, where the messages of errors and warnings are written by localize from their codes,
+    // English templates, and arguments, and numbers are written in the given format.
+    fn message_from(&self, localize: impl Fn(&str, &str, &[String]) -> String, numbers: NumberFormat) -> String {
+        match self.log_type.clone() {
+            LogType::Info(info_type) => { match info_type
+            {
+                InfoType::NewVarNotSet(var)
+                    => format!("the variable \"{var}\" has been initialized but hasn't been set to a value. It will instead take the default value of the type."),
+            }},
+            LogType::Note(note_type) => { match note_type
+            {
+                NoteType::FunctionSignature(function, types)
+                    => format!("the function \"{function}\" takes arguments of the type{} {}.",
+                        if types.len() == 1 {""} else {"s"},
+                        format_vec_string(&types, "and").unwrap_or_default()),
+                NoteType::ExpressionType(expr_type) => format!("the expression has the type {expr_type}."),
+                NoteType::StackFrame(Some(function)) => format!("in the function \"{function}\"."),
+                NoteType::StackFrame(None) => "in the main program.".to_string(),
+                NoteType::OmittedStackFrames(count)
+                    => format!("in {} more function call{}.", numbers.number(count as u64), if count == 1 {""} else {"s"}),
+                NoteType::DeniedWarning(name, code) => format!("the warning \"{name}\" ({code}) is denied."),
+                NoteType::OperandType(operand, operand_type) => format!("the {operand} has the type {operand_type}."),
+            }},
+            LogType::Help(help_type) => { match help_type
+            {
+                HelpType::BuiltinFunctions(functions)
+                    => format!("the built-in functions are {}.", format_vec_string(&functions, "and").unwrap_or_default()),
+                HelpType::DidYouMean(suggestion) => format!("did you mean \"{suggestion}\"?"),
+                HelpType::OperandNeedsType(operand, types)
+                    => format!("the {operand} should have the type {}.", types.join(" or ")),
+            }},
+            LogType::Warning(warning_type) => localize(
+                &self.code().unwrap_or_default(),
+                WARNING_MESSAGES[usize::from(warning_type.number()) - 1],
+                &warning_type.args(numbers),
+            ),
+            LogType::Error(error_type) => localize(
+                &self.code().unwrap_or_default(),
+                ERROR_MESSAGES[usize::from(error_type.number()) - 1],
+                &error_type.args(numbers),
+            ),
+        }
+    }
+
+    // Gets the color of the log's severity.
+    fn color(&self) -> Color {
+        match self.log_type.severity() {
+            Severity::Info => Color::White,
+            Severity::Note => Color::Cyan,
+            Severity::Help => Color::Green,
+            Severity::Warning => Color::Yellow,
+            Severity::Error => Color::Red,
+        }
+    }
+}
+
+// The alternate form is the one printed by the command line, without colors. It writes the code of the log after its
+// severity, as in "error[E0012]", and its notes on the lines after it, or the whole log as JSON if that is the current
+// error format.
+impl Display for Log {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if !f.alternate() {
+            return write!(f, "{}", self.headline(false, RenderOptions::default()));
         }
-        write!(f, "{output}")
+        write!(f, "{}", self.render(None, RenderOptions::default()))
     }
 }
 
 /// Returns whether or not a list of logs contains an error.
 #[must_use]
-pub fn is_error(logs: &Vec<Log>) -> bool {
-    for log in logs {
-        if let LogType::Error(_) = log.log_type {
-            return true;
+pub fn is_error(logs: &[Log]) -> bool {
+    logs.iter().any(|log| log.log_type.severity() == Severity::Error)
+}
+
+/// Gets the logs that are at least as severe as the given severity.
+#[must_use]
+pub fn filter_by_severity(logs: &[Log], min_severity: Severity) -> Vec<Log> {
+    logs.iter()
+        .filter(|log| log.log_type.severity() >= min_severity)
+        .cloned()
+        .collect()
+}
+
+/// Gets the logs up to the error after the first `limit` errors, followed by an error saying how many were left out,
+/// so that badly broken code doesn't flood the terminal. The notes and help after each error that is kept are kept
+/// with it. Every log is kept if there are no more errors than the limit, or if the limit is 0.
+#[must_use]
+pub fn limit_errors(logs: &[Log], limit: usize) -> Vec<Log> {
+    let mut errors = logs.iter().enumerate().filter(|(_, log)| log.log_type.severity() == Severity::Error);
+    let Some((cutoff, _)) = errors.nth(limit).filter(|_| limit > 0) else {
+        return logs.to_vec();
+    };
+    let mut limited: Vec<Log> = logs[..cutoff].to_vec();
+    limited.push(Log {
+        log_type: LogType::Error(ErrorType::TooManyErrors(limit, errors.count() + 1)),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+    limited
+}
+
+/// Something that logs are reported to one at a time, so that embedders can send them to their own logging instead of
+/// printing them.
+pub trait DiagnosticSink {
+    /// Reports a single log.
+    fn report(&mut self, log: &Log);
+
+    /// Reports each of the logs in order.
+    fn report_all(&mut self, logs: &[Log]) {
+        for log in logs {
+            self.report(log);
         }
     }
-    false
 }
 
-/// Converts all logs into strings and disables colorizing. Used for testing.
-#[must_use]
-pub fn all_to_string(logs: &Vec<Log>) -> Vec<String> {
-    let mut strings: Vec<String> = Vec::new();
-    set_override(false);
-    for log in logs {
-        strings.push(
-            ColoredString::from(format!("{log}").as_str())
-                .clear()
-                .to_string(),
-        );
+/// Writes each log the way the command line prints it, followed by the line of source it is about if the source was
+/// given.
+pub struct TerminalSink<W: Write> {
+    output: W,
+    file_text: Option<String>,
+    options: RenderOptions,
+}
+
+impl<W: Write> TerminalSink<W> {
+    /// Creates a sink that writes to the output, showing the lines of the source that logs are about if it is given.
+    /// Logs are written as plain text unless colors are enabled with `with_colors`.
+    pub fn new(output: W, file_text: Option<&str>) -> Self {
+        Self {
+            output,
+            file_text: file_text.map(str::to_string),
+            options: RenderOptions::default(),
+        }
+    }
+
+    /// Sets whether or not logs are colored, as they are when the command line prints them to a terminal.
+    #[must_use]
+    pub fn with_colors(mut self, colored: bool) -> Self {
+        self.options.colored = colored;
+        self
+    }
+
+    /// Sets how numbers in the messages of logs are written.
+    #[must_use]
+    pub fn with_number_format(mut self, numbers: NumberFormat) -> Self {
+        self.options.numbers = numbers;
+        self
+    }
+}
+
+impl TerminalSink<Stderr> {
+    /// Creates a sink that writes to stderr, as the command line does.
+    #[must_use]
+    pub fn stderr(file_text: Option<&str>) -> Self {
+        Self::new(stderr(), file_text)
     }
-    strings
 }
 
-// Formats a vector of strings into a list with commas and "and".
-fn format_vec_string(vec: &[String]) -> Option<String> {
+impl<W: Write> DiagnosticSink for TerminalSink<W> {
+    fn report(&mut self, log: &Log) {
+        // Reporting a log shouldn't fail because the output was closed.
+        let _ = writeln!(self.output, "{}", log.render(self.file_text.as_deref(), self.options));
+    }
+}
+
+/// Writes each log as a single line of JSON.
+pub struct JsonSink<W: Write> {
+    output: W,
+    file: Option<String>,
+    numbers: NumberFormat,
+}
+
+impl<W: Write> JsonSink<W> {
+    /// Creates a sink that writes to the output, giving the file that logs are about if it is known.
+    pub fn new(output: W, file: Option<&str>) -> Self {
+        Self {
+            output,
+            file: file.map(str::to_string),
+            numbers: NumberFormat::default(),
+        }
+    }
+
+    /// Sets how numbers in the messages of logs are written.
+    #[must_use]
+    pub fn with_number_format(mut self, numbers: NumberFormat) -> Self {
+        self.numbers = numbers;
+        self
+    }
+}
+
+impl<W: Write> DiagnosticSink for JsonSink<W> {
+    fn report(&mut self, log: &Log) {
+        let _ = writeln!(self.output, "{}", log.to_json(self.file.as_deref(), self.numbers));
+    }
+}
+
+/// Keeps every log reported to it, in order.
+#[derive(Clone, Default)]
+pub struct CollectingSink {
+    pub logs: Vec<Log>,
+}
+
+impl DiagnosticSink for CollectingSink {
+    fn report(&mut self, log: &Log) {
+        self.logs.push(log.clone());
+    }
+}
+
+// A shared sink can be given to the VM while the embedder keeps a handle to read what was reported.
+impl<S: DiagnosticSink> DiagnosticSink for Rc<RefCell<S>> {
+    fn report(&mut self, log: &Log) {
+        self.borrow_mut().report(log);
+    }
+}
+
+/// Converts all logs into strings. Used for testing.
+#[must_use]
+pub fn all_to_string(logs: &[Log]) -> Vec<String> {
+    logs.iter().map(Log::to_string).collect()
+}
+
+// Formats a vector of strings into a list with commas and the conjunction, which is "and" for lists of things that all
+// apply and "or" for lists to choose one from.
+fn format_vec_string(vec: &[String], conjunction: &str) -> Option<String> {
     match vec.len() {
         0 => None,
         1 => Some(vec[0].clone()),
-        2 => Some({
-            let mut value: String = vec[0].clone();
-            value.push_str(" and ");
-            value.push_str(&vec[1]);
-            value
-        }),
-        _ => Some({
-            let mut value: String = vec[0].clone();
-            for item in vec.iter().take(vec.len() - 1).skip(1) {
-                value.push_str(", ");
-                value.push_str(item);
-            }
-            value.push_str(", and ");
-            value.push_str(&vec[vec.len() - 1]);
-            value
-        }),
+        2 => Some(format!("{} {conjunction} {}", vec[0], vec[1])),
+        _ => Some(format!("{}, {conjunction} {}", vec[..vec.len() - 1].join(", "), vec[vec.len() - 1])),
     }
 }
@@ -0,0 +1,255 @@
+//! The module for the text of error and warning messages, which can be replaced by a locale provider so that the
+//! messages are shown in another language.
+//!
+//! Each message is a template keyed by the code of its log, such as "E0012". The template has "{0}", "{1}", and so on
+//! where the arguments of the log go, which are given in the order of the fields of the log type, followed by any
+//! values the message needs such as the "s" of a plural.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// The English templates of the error messages, in the order of their numbers, so that E0012 is at index 11.
+pub const ERROR_MESSAGES: [&str; 136] = [
+    "program terminated.",
+    "command line contains multiple files.",
+    "could not read command line arguments.",
+    "no command line arguments.",
+    "compiler flag \"{0}\" requires an argument.",
+    "compiler flag \"{0}\" requires a numerical argument.",
+    "compiler flag \"{0}\" requires an argument less than {1}.",
+    "compiler flag \"{0}\" requires an argument that's at least {1}.",
+    "compiler flag \"{0}\" requires a boolean argument.",
+    "compiler flag \"{0}\" requires one of \"info\", \"note\", \"help\", \"warning\", or \"error\".",
+    "compiler flag \"{0}\" requires an instruction set level from 1 to {1}.",
+    "compiler flag \"{0}\" requires an optimization level from 0 to {1}, such as \"{0}1\".",
+    "compiler flag \"{0}\" requires either \"stdout\" or \"stderr\".",
+    "compiler flag \"{0}\" requires either \"grouped\" or \"plain\".",
+    "compiler flag \"{0}\" requires passes from {1}.",
+    "compiler flag \"{0}\" requires an output from {1}.",
+    "the output \"{0}\" needs source code, not a compiled program.",
+    "compiler flag \"{0}\" requires a target from {1}.",
+    "the target \"{0}\" can only be built, not run or printed.",
+    "compiler flag \"{0}\" requires an encoding from {1}.",
+    "compiler flag \"{0}\" requires a byte order from {1}.",
+    "compiler flag \"{0}\" requires a report format from {1}.",
+    "the pass \"{0}\" is required, so it can't be disabled.",
+    "unrecognized argument \"{0}\".",
+    "could not open file \"{0}\".",
+    "no source file entered.",
+    "the file is too big to compile for a {0}-bit machine.",
+    "int literal \"{0}\" must be at most {1}.",
+    "invalid suffix \"{0}\" on an int literal; the valid suffixes are \"i\", \"l\", and \"u\".",
+    "the suffix \"{0}\" gives a literal the type \"{1}\", which is not supported yet.",
+    "string literal is missing a closing '\"'.",
+    "invalid escape sequence \"{0}\" in string literal.",
+    "unexpected end of file.",
+    "unexpected token \"{0}\".",
+    "expected expression within parentheses.",
+    "expected ')' following '('.",
+    "expected '(' following \"{0}\".",
+    "expected a variable declaration for {0}",
+    "the type {0} can not be cast to type {1}",
+    "expected an expression after cast \"({0})\"",
+    "the operator \"{0}\" has no definition over the type{2} {1}.",
+    "The variable \"{0}\" has type {1}, so it can not be assigned a value of type {2}",
+    "the int literal {0} must be preceded by a unary '-' operator.",
+    "the variable \"{0}\" has not yet been declared.",
+    "the function \"{0}\" does not exist.",
+    "the function \"{0}\" takes {1} argument{3} but was given {2}.",
+    "the function \"{0}\" takes {1} to {2} arguments but was given {3}.",
+    "the function \"{0}\" has no definition over the type{2} {1}.",
+    "expected a map type of the form \"map[key]value\", where the key and value are \"int\" or \"bool\".",
+    "expected ':' between the key and value of a map entry.",
+    "expected '}' following '{'.",
+    "the map type {0} can not contain an entry with a key of type {1} and a value of type {2}.",
+    "expected a list type of the form \"list<element>\", where the element is \"int\", \"bool\", or \"string\".",
+    "the list type {0} can not contain an element of type {1}.",
+    "expected a result type of the form \"result<value>\", where the value is \"int\" or \"bool\".",
+    "the operator \"?\" can only be used in the body of a function that returns a result.",
+    "expected ']' following '['.",
+    "expected {0} in function declaration.",
+    "the type parameter \"{0}\" of the function \"{1}\" must be the type of a parameter so that it can be inferred.",
+    "the default value of the parameter \"{0}\" must be a constant, such as a literal.",
+    "the default value of the parameter \"{0}\" should have the type {1} but has the type {2}.",
+    "the parameter \"{0}\" needs a default value because it follows a parameter with one.",
+    "the function \"{0}\" has already been declared.",
+    "the host function \"{0}\" can only take up to 255 ints and must return an int.",
+    "the function \"{0}\" should return the type {1} but its body has the type {2}.",
+    "the function \"{0}\" can only be called.",
+    "the type parameter \"{0}\" can not be the type {1}.",
+    "the type parameter \"{0}\" of the function \"{1}\" can not be both {2} and {3}.",
+    "expected {0} in trait declaration.",
+    "expected {0} in impl block.",
+    "the trait \"{0}\" does not exist.",
+    "the name \"{0}\" has already been declared.",
+    "the type {0} already has a method named \"{1}\".",
+    "\"{1}\" is not a method of the trait \"{0}\".",
+    "the implementation of the trait \"{0}\" for the type {2} is missing the method \"{1}\".",
+    "the method \"{0}\" does not match its signature in the trait \"{1}\".",
+    "the type {0} has no method named \"{1}\".",
+    "the type {0} does not implement the trait \"{1}\", which is required by the function \"{2}\".",
+    "expected {0} in flags declaration.",
+    "the value of the flag \"{0}\" must be a constant int, such as a literal.",
+    "the flags \"{0}\" already have a flag named \"{1}\".",
+    "the flags \"{0}\" have no flag named \"{1}\".",
+    "the flags \"{0}\" can only be used to get one of their flags, such as with \"{0}.NAME\".",
+    "could not compile as bytecode was too large ({0} bytes, over the limit of {1}); use \"--emit=size\" to see what takes up the most space.",
+    "there are more than {0} variables declared, which is more than the compiler can handle.",
+    "the code needs instruction set level {0} ({1}), but level {2} was targeted.",
+    "this constant expression divides by zero, which would always fail.",
+    "the {0} backend doesn't support {1} yet.",
+    "could not compile due to errors.",
+    "internal compiler error: {0}",
+    "the bytecode header is invalid.",
+    "the bytecode header has no readable metadata.",
+    "the compiled program is corrupt: {0}.",
+    "invalid opcode {1} at offset {0}.",
+    "the instruction at offset {0} is missing operand bytes.",
+    "the instruction at offset {0} accesses a variable that does not exist or has a different type.",
+    "the instruction at offset {0} pops more values than are on the stack.",
+    "the call at offset {0} does not go to the start of a function.",
+    "the load at offset {0} refers to a constant that is not in the constant pool.",
+    "the jump at offset {0} does not go to the start of an instruction.",
+    "the stack at offset {0} differs depending on whether it is reached by a jump.",
+    "the instruction at offset {0} needs instruction set level {1}, but the header only allows level {2}.",
+    "this program was compiled for a {0}-bit machine, while this is only a {1}-bit machine.",
+    "this program requires instruction set level {0}, but only levels 1 to {1} are supported.",
+    "division by zero.",
+    "attempted to unwrap a null value.",
+    "attempted to take the square root of a negative number.",
+    "substring indices are out of range.",
+    "list index is out of range.",
+    "more than {0} function calls were active at once.",
+    "the stack grew past its limit of {1} bytes at offset {0}.",
+    "the program ran out of memory, as more than {0} objects were on the heap at once.",
+    "the host function \"{0}\" has not been registered with the VM.",
+    "the host function \"{0}\" failed: {1}",
+    "file access is disabled for this program.",
+    "could not read the file \"{0}\": {1}",
+    "could not write the file \"{0}\": {1}",
+    "attempted to unwrap an error result: {0}",
+    "the program panicked: {0}",
+    "compiler flag \"{0}\" requires either \"human\" or \"json\".",
+    "compiler flag \"{0}\" requires \"warnings\", \"lints\", or a warning from {1}.",
+    // A denied warning has the message of the warning.
+    "{0}",
+    "compiler flag \"{0}\" requires one of \"auto\", \"always\", or \"never\".",
+    "the divisor is always zero, so this would always fail.",
+    "aborting due to {0} previous error{2}; {1} not shown.",
+    "compiler flag \"{0}\" only chooses where \"build\" writes the program, so it can't be used to run or print it.",
+    "the file \"{0}\" is not formatted, starting at line {1}; run \"krust fmt {0}\" to format it.",
+    "line {1} of \"{0}\" should be a section, such as \"[warnings]\", or a setting, such as \"opt_level = 2\".",
+    "line {1} of \"{0}\" has \"{2}\", which isn't a setting of a krust project.",
+    "line {1} of \"{0}\" gives \"{2}\" a value that isn't {3}.",
+    "assertion failed: {0}",
+    "command \"{0}\" requires a shell from {1}.",
+    "the target's pointers must be at least one byte.",
+    "expressions can't be nested more than {0} levels deep.",
+    "the output path \"{0}\" is one of the input files, which would be overwritten.",
+    "the output path \"{0}\" should end in \".{1}\", so that krust recognizes the program it holds.",
+];
+
+/// The English templates of the warning messages, in the order of their numbers, so that W0003 is at index 2.
+pub const WARNING_MESSAGES: [&str; 8] = [
+    "argument of \"{0}\" will be rounded down to the nearest multiple of {1}.",
+    "warning: this program is being compiled for a {0}-bit machine, while this is only a {1}-bit machine.",
+    "comparing a value with itself using \"{0}\" is always {1}; this may be a typo.",
+    "the value computed by \"{0}\" is never used; this may be a typo.",
+    "the result {0} doesn't fit in an int, so this constant expression wraps around to {1}.",
+    "these parentheses are redundant.",
+    "this condition is always {0}, as it only uses constants.",
+    "the variable \"{0}\" shadows the variable of the same name declared on line {1}.",
+];
+
+/// Gives the templates of messages in another language.
+pub trait LocaleProvider {
+    /// Gets the template of the message with the given code, such as "E0012", or None if the message isn't translated,
+    /// in which case the English message is used.
+    fn template(&self, code: &str) -> Option<String>;
+}
+
+/// A set of templates for some of the messages, which can be read from a message pack.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct MessageCatalog {
+    templates: HashMap<String, String>,
+}
+
+impl MessageCatalog {
+    /// Reads a message pack, where each line is a code followed by `=` and its template, as in
+    /// `E0105 = division par zéro.`. Blank lines and lines starting with `#` are skipped, as are lines without a `=`.
+    #[must_use]
+    pub fn from_text(text: &str) -> Self {
+        let mut catalog: Self = Self::default();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            if let Some((code, template)) = line.split_once('=') {
+                catalog.insert(code.trim(), template.trim());
+            }
+        }
+        catalog
+    }
+
+    /// Sets the template of the message with the given code, replacing any template it had.
+    pub fn insert(&mut self, code: &str, template: &str) {
+        self.templates.insert(code.to_string(), template.to_string());
+    }
+}
+
+impl LocaleProvider for MessageCatalog {
+    fn template(&self, code: &str) -> Option<String> {
+        self.templates.get(code).cloned()
+    }
+}
+
+static LOCALE: RwLock<Option<Box<dyn LocaleProvider + Send + Sync>>> = RwLock::new(None);
+
+/// Sets the locale provider that messages are taken from from now on, or goes back to English if there is none.
+pub fn set_locale(provider: Option<Box<dyn LocaleProvider + Send + Sync>>) {
+    // A poisoned lock still holds a valid provider.
+    *LOCALE
+        .write()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = provider;
+}
+
+/// Writes the message with the given code, using the template of the current locale provider if it has one and the
+/// English template otherwise.
+#[must_use]
+pub fn localize(code: &str, english: &str, args: &[String]) -> String {
+    match LOCALE
+        .read()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .as_deref()
+    {
+        Some(provider) => localize_with(provider, code, english, args),
+        None => fill(english, args),
+    }
+}
+
+/// Writes the message with the given code, using the template of the given locale provider if it has one and the
+/// English template otherwise. The current locale provider isn't used.
+#[must_use]
+pub fn localize_with(provider: &dyn LocaleProvider, code: &str, english: &str, args: &[String]) -> String {
+    fill(provider.template(code).as_deref().unwrap_or(english), args)
+}
+
+// Replaces each "{n}" in the template with the nth argument. Braces around anything else, or around a number with no
+// argument, are left as they are.
+fn fill(template: &str, args: &[String]) -> String {
+    let mut message: String = String::new();
+    let mut rest: &str = template;
+    while let Some(start) = rest.find('{') {
+        message.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let arg: Option<&String> = rest[1..]
+            .split_once('}')
+            .and_then(|(index, _)| index.parse::<usize>().ok())
+            .and_then(|index| args.get(index));
+        if let Some(arg) = arg {
+            message.push_str(arg);
+            rest = &rest[rest.find('}').expect("the argument was closed") + 1..];
+        } else {
+            message.push('{');
+            rest = &rest[1..];
+        }
+    }
+    message + rest
+}
@@ -1,3 +1,6 @@
 // List of all utility modules.
 pub mod log;
+pub mod messages;
+pub mod number_format;
+pub mod suggestion;
 pub mod trie;
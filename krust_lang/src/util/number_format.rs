@@ -0,0 +1,81 @@
+//! The module for formatting the numbers that appear in diagnostic messages.
+
+/// How numbers are written in diagnostic messages.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct NumberFormat {
+    /// The character placed between groups of three digits, if any. Only numbers with five or more digits are
+    /// grouped, so that small values such as offsets and line numbers are left as they are.
+    pub group_separator: Option<char>,
+    /// Whether or not bounds that are powers of two (or one less than a power of two) are followed by their value in
+    /// hexadecimal.
+    pub hex_alternates: bool,
+}
+
+impl NumberFormat {
+    /// Groups digits with commas and gives hexadecimal alternates. This is the default.
+    pub const GROUPED: Self = Self {
+        group_separator: Some(','),
+        hex_alternates: true,
+    };
+
+    /// Writes numbers as plain decimal.
+    pub const PLAIN: Self = Self {
+        group_separator: None,
+        hex_alternates: false,
+    };
+
+    /// Gets the format with the given name, as used on the command line.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "grouped" => Some(Self::GROUPED),
+            "plain" => Some(Self::PLAIN),
+            _ => None,
+        }
+    }
+
+    /// Formats a number in decimal.
+    #[must_use]
+    pub fn number(self, value: u64) -> String {
+        let digits: String = value.to_string();
+        match self.group_separator {
+            Some(separator) if digits.len() > 4 => group_digits(&digits, 3, separator),
+            _ => digits,
+        }
+    }
+
+    /// Formats a number that is a limit, such as the largest int literal. Limits that are easier to recognize in
+    /// hexadecimal are also given in hexadecimal.
+    #[must_use]
+    pub fn bound(self, value: u64) -> String {
+        let is_round: bool = value.is_power_of_two() || value.wrapping_add(1).is_power_of_two();
+        if self.hex_alternates && is_round && value > 0xff {
+            format!("{} ({})", self.number(value), hex(value))
+        } else {
+            self.number(value)
+        }
+    }
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self::GROUPED
+    }
+}
+
+// Formats a number in hexadecimal, with an underscore between groups of four digits.
+fn hex(value: u64) -> String {
+    format!("0x{}", group_digits(&format!("{value:x}"), 4, '_'))
+}
+
+// Places the separator between groups of digits of the given size, counted from the right.
+fn group_digits(digits: &str, size: usize, separator: char) -> String {
+    let mut grouped: String = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(size) {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
@@ -0,0 +1,45 @@
+//! The module for suggesting what was meant by a misspelled keyword, operator, or flag.
+
+/// Counts the edits needed to turn one string into the other, where an edit inserts, removes, or replaces a character,
+/// or swaps two characters next to each other.
+#[must_use]
+pub fn edit_distance(from: &str, to: &str) -> usize {
+    let from: Vec<char> = from.chars().collect();
+    let to: Vec<char> = to.chars().collect();
+    // distances[i][j] is the distance between the first i characters of from and the first j characters of to.
+    let mut distances: Vec<Vec<usize>> = vec![vec![0; to.len() + 1]; from.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=from.len() {
+        for j in 1..=to.len() {
+            let cost: usize = usize::from(from[i - 1] != to[j - 1]);
+            let mut distance: usize = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && from[i - 1] == to[j - 2] && from[i - 2] == to[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[from.len()][to.len()]
+}
+
+/// Gets the candidate closest to the word, if one is close enough to be what was meant. A third of the characters of
+/// the word can be edited, or one character in words shorter than six, and the word can't be the candidate itself.
+/// The first candidate wins ties.
+#[must_use]
+pub fn closest<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let length: usize = word.chars().count();
+    let max_distance: usize = (length / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(word, candidate), candidate))
+        .filter(|(distance, _)| *distance > 0 && *distance <= max_distance && *distance < length)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
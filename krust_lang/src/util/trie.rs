@@ -17,15 +17,13 @@ pub struct Node<TKey, TValue> {
 
 impl<TKey: PartialEq + Clone, TValue: Clone> Node<TKey, TValue> {
     /// Returns the value corresponding with the key if found.
-    pub fn search(&self, key: &Vec<TKey>) -> Option<TValue> {
+    pub fn search(&self, key: &[TKey]) -> Option<TValue> {
         if key.is_empty() {
             return self.value.clone();
         }
         for child in &self.children {
             if child.key == Some(key[0].clone()) {
-                let mut key: Vec<TKey> = key.clone();
-                key.remove(0);
-                return child.search(&key);
+                return child.search(&key[1..]);
             }
         }
         None
@@ -47,7 +45,7 @@ impl<TKey: PartialEq + Clone, TValue: Clone> Node<TKey, TValue> {
 
     // Inserts a key-value pair into the trie, assuming no value is already associated with that key.
     // Returns whether or not the insertion was successful.
-    fn insert(&mut self, key: &Vec<TKey>, value: &TValue) -> bool {
+    fn insert(&mut self, key: &[TKey], value: &TValue) -> bool {
         if key.is_empty() {
             if self.value.is_none() {
                 self.value = Some(value.clone());
@@ -57,9 +55,7 @@ impl<TKey: PartialEq + Clone, TValue: Clone> Node<TKey, TValue> {
         }
         for child in &mut self.children {
             if child.key == Some(key[0].clone()) {
-                let mut key: Vec<TKey> = key.clone();
-                key.remove(0);
-                return child.insert(&key, value);
+                return child.insert(&key[1..], value);
             }
         }
         let mut new_child: Self = Self {
@@ -67,9 +63,7 @@ impl<TKey: PartialEq + Clone, TValue: Clone> Node<TKey, TValue> {
             children: Vec::new(),
             value: None,
         };
-        let mut key: Vec<TKey> = key.clone();
-        key.remove(0);
-        let value_to_return: bool = new_child.insert(&key, value);
+        let value_to_return: bool = new_child.insert(&key[1..], value);
         self.children.push(new_child);
         value_to_return
     }
@@ -0,0 +1,479 @@
+//! The module for verifying bytecode before it is run.
+
+use crate::{compiler, util::log};
+use compiler::{
+    code_start, read_register_operand, IsaLevel, OpCode, OperandSource, BIG_ENDIAN_FLAG,
+    BYTES_PER_VAR, DETAILED_ERRORS_FLAG, HEADER_SIZE,
+};
+use log::{ErrorType, Log, LogType};
+
+use num_traits::FromPrimitive;
+use std::collections::HashMap;
+
+/// The output given by the verifier.
+pub struct VerifierOutput {
+    /// The pointer size (in bytes) stored in the header, if the header could be read.
+    pub ptr_size: Option<usize>,
+    /// Whether or not runtime errors contain line info, if the header could be read.
+    pub detailed_err: Option<bool>,
+    /// The instruction set level stored in the header, if the header could be read.
+    pub isa_level: Option<IsaLevel>,
+    /// The number of instructions that were successfully checked.
+    pub instruction_count: usize,
+    /// The size (in bytes) of the values other than variables that the main code leaves on the stack.
+    pub unused_stack_size: usize,
+    pub logs: Vec<Log>,
+}
+
+// The stack that a jump leaves at its target, which must match the stack of any other way to reach the target.
+#[derive(PartialEq)]
+struct JumpState {
+    stack_size: usize,
+    stack_floor: usize,
+    var_sizes: Vec<usize>,
+}
+
+impl JumpState {
+    // Copies the state of the stack.
+    fn new(stack_size: usize, stack_floor: usize, var_sizes: &[usize]) -> Self {
+        Self {
+            stack_size,
+            stack_floor,
+            var_sizes: var_sizes.to_vec(),
+        }
+    }
+}
+
+// The places that instructions refer to, which are checked once every instruction has been read.
+#[derive(Default)]
+struct References {
+    // Functions follow the halt instruction and each end with a return instruction. Their stacks start empty, with
+    // their arguments below.
+    function_starts: Vec<usize>,
+    calls: Vec<(usize, usize)>, // The offsets of calls and their targets.
+    loads: Vec<(usize, usize)>, // The offsets of constant loads and their indices.
+    constant_count: usize,
+}
+
+impl References {
+    // Checks that the calls and constant loads go to functions and constants that exist, and that no jump target was
+    // skipped over. Returns the first error found, if any.
+    fn check(
+        &self,
+        jumps: &HashMap<usize, (usize, JumpState)>,
+        bytecode_len: usize,
+    ) -> Option<ErrorType> {
+        // Any targets left were skipped over, so they aren't at the start of an instruction.
+        if let Some(offset) = jumps.values().map(|(offset, _)| *offset).min() {
+            return Some(ErrorType::InvalidJumpTarget(offset));
+        }
+        for (offset, target) in &self.calls {
+            if !self.function_starts.contains(target) || *target >= bytecode_len {
+                return Some(ErrorType::InvalidCallTarget(*offset));
+            }
+        }
+        self.loads
+            .iter()
+            .find(|(_, index)| *index >= self.constant_count)
+            .map(|(offset, _)| ErrorType::InvalidConstant(*offset))
+    }
+}
+
+// The sizes (in bytes) of values that an instruction pops from and pushes to the stack.
+struct StackEffect {
+    pops: usize,
+    pushes: usize,
+}
+
+/// Checks that the bytecode is well-formed without running it.
+#[must_use]
+#[allow(clippy::missing_panics_doc)] // Should never actually panic.
+pub fn verify(bytecode: &[u8]) -> VerifierOutput {
+    let mut output: VerifierOutput = VerifierOutput {
+        ptr_size: None,
+        detailed_err: None,
+        isa_level: None,
+        instruction_count: 0,
+        unused_stack_size: 0,
+        logs: Vec::new(),
+    };
+    if !verify_header(bytecode, &mut output) {
+        return output;
+    }
+    let ptr_size: usize = output.ptr_size.expect("header was verified");
+    let isa_level: IsaLevel = output.isa_level.expect("header was verified");
+
+    let mut index: usize = code_start(bytecode);
+    let mut stack_size: usize = 0;
+    let mut stack_floor: usize = 0; // Values below this are variables.
+    let mut var_sizes: Vec<usize> = Vec::new();
+    let mut references: References = References::default();
+    // The targets of jumps that haven't been reached yet, with the offset of the first jump to each target. Jumps
+    // only go forward.
+    let mut jumps: HashMap<usize, (usize, JumpState)> = HashMap::new();
+    let mut in_main: bool = true;
+    let mut falls_through: bool = true; // Whether or not the last instruction continues to the next one.
+    while index < bytecode.len() {
+        let offset: usize = index;
+        if let Some((_, state)) = jumps.remove(&offset) {
+            if falls_through && state != JumpState::new(stack_size, stack_floor, &var_sizes) {
+                push_error(&mut output.logs, ErrorType::MismatchedStackAtJump(offset));
+                return output;
+            }
+            (stack_size, stack_floor, var_sizes) =
+                (state.stack_size, state.stack_floor, state.var_sizes);
+        }
+        falls_through = true;
+        let (op, operand): (OpCode, &[u8]) =
+            match read_instruction(bytecode, &mut index, ptr_size, isa_level) {
+                Ok(instruction) => instruction,
+                Err(error) => {
+                    push_error(&mut output.logs, error);
+                    return output;
+                }
+            };
+
+        let effect: Option<StackEffect> = get_stack_effect(op, operand, ptr_size, &var_sizes);
+        let Some(effect) = effect else {
+            push_error(&mut output.logs, ErrorType::InvalidVariableAccess(offset));
+            return output;
+        };
+        if stack_size < stack_floor + effect.pops {
+            push_error(&mut output.logs, ErrorType::StackUnderflow(offset));
+            return output;
+        }
+        stack_size = stack_size - effect.pops + effect.pushes;
+        if let Some(size) = alloc_size(op) {
+            // The variable's slot sits below the copy that is left as the value of the declaration.
+            var_sizes.push(size);
+            stack_floor = stack_size - size;
+        }
+        match op {
+            OpCode::BindParam => var_sizes.push(operand[ptr_size] as usize),
+            OpCode::Call | OpCode::TailCall => {
+                references
+                    .calls
+                    .push((offset, read_ptr_size(operand, ptr_size)));
+            }
+            OpCode::LoadConst => references.loads.push((
+                offset,
+                usize::from(u16::from_le_bytes([operand[0], operand[1]])),
+            )),
+            OpCode::Constant => references.constant_count += 1,
+            OpCode::JumpIfOk => {
+                let target: Option<usize> = index.checked_add(read_ptr_size(operand, ptr_size));
+                let state: JumpState = JumpState::new(stack_size, stack_floor, &var_sizes);
+                if let Err(error) = record_jump(&mut jumps, offset, target, state) {
+                    push_error(&mut output.logs, error);
+                    return output;
+                }
+            }
+            OpCode::Halt | OpCode::Return => {
+                if in_main {
+                    output.unused_stack_size = stack_size.saturating_sub(var_sizes.iter().sum());
+                    in_main = false;
+                }
+                // Code after an early return is only reached by a jump, so it doesn't start a function.
+                if !jumps.contains_key(&index) {
+                    references.function_starts.push(index);
+                }
+                falls_through = false;
+                stack_size = 0;
+                stack_floor = 0;
+                var_sizes.clear();
+            }
+            _ => {}
+        }
+        output.instruction_count += 1;
+    }
+    if in_main {
+        output.unused_stack_size = stack_size.saturating_sub(var_sizes.iter().sum());
+    }
+    if let Some(error) = references.check(&jumps, bytecode.len()) {
+        push_error(&mut output.logs, error);
+    }
+    output
+}
+
+// Reads the instruction at the index and moves the index past it. Returns an error if the instruction is invalid,
+// needs a higher instruction set level, or is missing operand bytes.
+fn read_instruction<'b>(
+    bytecode: &'b [u8],
+    index: &mut usize,
+    ptr_size: usize,
+    isa_level: IsaLevel,
+) -> Result<(OpCode, &'b [u8]), ErrorType> {
+    let offset: usize = *index;
+    let op: Option<OpCode> = FromPrimitive::from_u8(bytecode[offset]);
+    let op: OpCode = op.ok_or(ErrorType::InvalidOpCode(offset, bytecode[offset]))?;
+    if op.isa_level() > isa_level {
+        return Err(ErrorType::InstructionAboveIsaLevel(
+            offset,
+            op.isa_level() as u8,
+            isa_level as u8,
+        ));
+    }
+    let operand_start: usize = offset + 1;
+    let operand_length: usize = op
+        .operand_length(ptr_size, &bytecode[operand_start..])
+        .filter(|length| operand_start + length <= bytecode.len())
+        .ok_or(ErrorType::IncompleteOperand(offset))?;
+    *index = operand_start + operand_length;
+    Ok((op, &bytecode[operand_start..*index]))
+}
+
+// Records the stack that a jump leaves at its target. Returns an error if the target is out of range or another jump
+// to it leaves a different stack.
+fn record_jump(
+    jumps: &mut HashMap<usize, (usize, JumpState)>,
+    offset: usize,
+    target: Option<usize>,
+    state: JumpState,
+) -> Result<(), ErrorType> {
+    let target: usize = target.ok_or(ErrorType::InvalidJumpTarget(offset))?;
+    match jumps.get(&target) {
+        Some((_, other)) if *other != state => Err(ErrorType::MismatchedStackAtJump(target)),
+        Some(_) => Ok(()),
+        None => {
+            jumps.insert(target, (offset, state));
+            Ok(())
+        }
+    }
+}
+
+// Reads a value with a length of ptr_size from the start of an operand.
+fn read_ptr_size(operand: &[u8], ptr_size: usize) -> usize {
+    let mut bytes: [u8; (usize::BITS / 8) as usize] = [0; (usize::BITS / 8) as usize];
+    for (byte, value) in bytes.iter_mut().zip(&operand[..ptr_size]) {
+        *byte = *value;
+    }
+    usize::from_le_bytes(bytes)
+}
+
+// Checks the header of the bytecode. Returns whether or not the rest of the bytecode can be read.
+fn verify_header(bytecode: &[u8], output: &mut VerifierOutput) -> bool {
+    if bytecode.len() < HEADER_SIZE
+        || bytecode.len() < code_start(bytecode)
+        || bytecode[1] & !(DETAILED_ERRORS_FLAG | BIG_ENDIAN_FLAG) != 0
+    {
+        push_error(&mut output.logs, ErrorType::InvalidBytecodeHeader);
+        return false;
+    }
+    let ptr_size: usize = bytecode[0] as usize;
+    output.detailed_err = Some(bytecode[1] & DETAILED_ERRORS_FLAG != 0);
+    if ptr_size == 0 {
+        push_error(&mut output.logs, ErrorType::InvalidBytecodeHeader);
+        return false;
+    }
+    output.ptr_size = Some(ptr_size);
+    if ptr_size * 8
+        > usize::BITS
+            .try_into()
+            .expect("max value of usize must be less than the number of bits")
+    {
+        push_error(
+            &mut output.logs,
+            ErrorType::CompiledForDifferentTarget(ptr_size * 8),
+        );
+        return false;
+    }
+    let isa_level: Option<IsaLevel> = FromPrimitive::from_u8(bytecode[2]);
+    let Some(isa_level) = isa_level else {
+        push_error(
+            &mut output.logs,
+            ErrorType::UnsupportedIsaLevel(bytecode[2]),
+        );
+        return false;
+    };
+    output.isa_level = Some(isa_level);
+    true
+}
+
+// Gets the stack effect of an instruction. Returns None if the instruction accesses an invalid variable.
+#[allow(clippy::match_same_arms)] // Opcodes are grouped the same way as in the OpCode enum.
+fn get_stack_effect(
+    op: OpCode,
+    operand: &[u8],
+    ptr_size: usize,
+    var_sizes: &[usize],
+) -> Option<StackEffect> {
+    let effect = |pops: usize, pushes: usize| Some(StackEffect { pops, pushes });
+    match op {
+        OpCode::PushInt => effect(0, 4),
+        OpCode::PushByte => effect(0, 1),
+        OpCode::PushString | OpCode::LoadConst => effect(0, 4),
+        OpCode::PopInt | OpCode::PrintInt | OpCode::PrintString => effect(4, 0),
+        OpCode::PopByte | OpCode::PrintBool => effect(1, 0),
+        OpCode::Dup => effect(operand[0] as usize, 2 * operand[0] as usize),
+        OpCode::RegisterOp => get_register_effect(operand, var_sizes),
+        OpCode::PrintNull => effect(0, 0),
+        OpCode::PrintNullableInt => effect(5, 0),
+        OpCode::PrintNullableBool => effect(2, 0),
+
+        OpCode::AllocInt
+        | OpCode::AllocBool
+        | OpCode::AllocNullableInt
+        | OpCode::AllocNullableBool => effect(
+            0,
+            2 * alloc_size(op).expect("all alloc opcodes have a size"),
+        ),
+        OpCode::GetInt | OpCode::GetBool | OpCode::GetNullableInt | OpCode::GetNullableBool => {
+            effect(0, get_var_size(op, operand, var_sizes)?)
+        }
+        OpCode::SetInt | OpCode::SetBool | OpCode::SetNullableInt | OpCode::SetNullableBool => {
+            let size: usize = get_var_size(op, operand, var_sizes)?;
+            effect(size, size)
+        }
+
+        OpCode::Unwrap => effect(1, 0),
+
+        OpCode::IntToBool => effect(4, 1),
+        OpCode::BoolToInt => effect(1, 4),
+
+        OpCode::MinusInt | OpCode::ComplementInt | OpCode::AbsInt | OpCode::SqrtInt => effect(4, 4),
+        OpCode::MinInt | OpCode::MaxInt | OpCode::PowInt => effect(8, 4),
+        OpCode::ClampInt => effect(12, 4),
+
+        OpCode::LenString => effect(4, 4),
+        OpCode::SubstringString => effect(12, 4),
+        OpCode::ContainsString => effect(8, 1),
+        OpCode::IntToString | OpCode::ParseInt => effect(4, 4),
+
+        OpCode::NewMap => effect(0, 4),
+        OpCode::PrintMap => effect(4, 0),
+        OpCode::LenMap => effect(4, 4),
+        OpCode::MapInsert => effect(4 + map_size(operand), 4),
+        OpCode::MapGet | OpCode::MapRemove => {
+            effect(4 + operand[0] as usize, operand[1] as usize + 1)
+        }
+
+        OpCode::NewList => effect(0, 4),
+        OpCode::PrintList | OpCode::PrintStringList => effect(4, 0),
+        OpCode::LenList => effect(4, 4),
+        OpCode::ListPush => effect(4 + operand[0] as usize, 4),
+        OpCode::ListPop => effect(4, operand[0] as usize + 1),
+        OpCode::ListIndex => effect(8, operand[0] as usize),
+        OpCode::Args => effect(0, 4),
+        OpCode::CallHost => effect(4 * operand[0] as usize, 4),
+        OpCode::ReadFile => effect(4, 4),
+        OpCode::WriteFile => effect(8, 0),
+
+        OpCode::OkResult => effect(operand[0] as usize, 4),
+        OpCode::ErrResult => effect(4, 4),
+        OpCode::IsOkResult => effect(4, 1),
+        OpCode::UnwrapResult => effect(4, operand[0] as usize),
+        OpCode::PrintResult => effect(4, 0),
+        OpCode::JumpIfOk => effect(4, 4),
+
+        // A tail call never returns to the current function, but the return value it leaves is what the Return after
+        // it expects.
+        OpCode::Call | OpCode::TailCall => effect(
+            read_ptr_size(&operand[ptr_size..], ptr_size),
+            operand[2 * ptr_size] as usize,
+        ),
+        OpCode::BindParam
+        | OpCode::Halt
+        | OpCode::FunctionName
+        | OpCode::Constant
+        | OpCode::LineTable
+        | OpCode::Location => effect(0, 0),
+        OpCode::Return => effect(operand[0] as usize, 0),
+        OpCode::Panic => effect(4, 0),
+        OpCode::Assert => effect(5, 0),
+
+        OpCode::AddInt
+        | OpCode::SubtractInt
+        | OpCode::MultiplyInt
+        | OpCode::DivideInt
+        | OpCode::ModuloInt
+        | OpCode::AndInt
+        | OpCode::XorInt
+        | OpCode::OrInt
+        | OpCode::LeftShiftInt
+        | OpCode::RightShiftInt => effect(8, 4),
+
+        OpCode::LessInt
+        | OpCode::LessEqualInt
+        | OpCode::GreaterInt
+        | OpCode::GreaterEqualInt
+        | OpCode::EqualityInt
+        | OpCode::InequalityInt => effect(8, 1),
+
+        OpCode::Not => effect(1, 1),
+        OpCode::AndByte
+        | OpCode::XorByte
+        | OpCode::OrByte
+        | OpCode::EqualityByte
+        | OpCode::InequalityByte => effect(2, 1),
+
+        OpCode::EqualityNullableInt | OpCode::InequalityNullableInt => effect(10, 1),
+        OpCode::EqualityNullableBool | OpCode::InequalityNullableBool => effect(4, 1),
+    }
+}
+
+// Gets the size of the values of a variable accessed by a get or set instruction, if it matches the instruction.
+fn get_var_size(op: OpCode, operand: &[u8], var_sizes: &[usize]) -> Option<usize> {
+    let mut bytes: [u8; (usize::BITS / 8) as usize] = [0; (usize::BITS / 8) as usize];
+    bytes[..BYTES_PER_VAR].copy_from_slice(&operand[..BYTES_PER_VAR]);
+    let var_index: usize = usize::from_le_bytes(bytes);
+    let size: usize = match op {
+        OpCode::GetInt | OpCode::SetInt => 4,
+        OpCode::GetBool | OpCode::SetBool => 1,
+        OpCode::GetNullableInt | OpCode::SetNullableInt => 5,
+        OpCode::GetNullableBool | OpCode::SetNullableBool => 2,
+        _ => panic!("only get and set instructions access variables"),
+    };
+    if var_sizes.get(var_index) == Some(&size) {
+        Some(size)
+    } else {
+        None
+    }
+}
+
+// Gets the stack effect of a register instruction, which pops the operands that aren't read from variables or the
+// bytecode. Returns None if an operand is read from a variable that isn't an int.
+fn get_register_effect(operand: &[u8], var_sizes: &[usize]) -> Option<StackEffect> {
+    let (op, sources): (OpCode, [(OperandSource, u32); 2]) = read_register_operand(operand)?;
+    let mut pops: usize = 0;
+    for (source, value) in sources {
+        match source {
+            OperandSource::Stack => pops += 4,
+            OperandSource::Register => {
+                if var_sizes.get(usize::try_from(value).ok()?) != Some(&4) {
+                    return None;
+                }
+            }
+            OperandSource::Immediate => {}
+        }
+    }
+    Some(StackEffect {
+        pops,
+        pushes: op.register_result_size()?,
+    })
+}
+
+// Gets the size of a map entry from the operand of a map instruction.
+fn map_size(operand: &[u8]) -> usize {
+    operand[0] as usize + operand[1] as usize
+}
+
+// Gets the size of the variable allocated by an instruction, if it allocates one.
+fn alloc_size(op: OpCode) -> Option<usize> {
+    match op {
+        OpCode::AllocInt => Some(4),
+        OpCode::AllocBool => Some(1),
+        OpCode::AllocNullableInt => Some(5),
+        OpCode::AllocNullableBool => Some(2),
+        _ => None,
+    }
+}
+
+// Adds an error to the list of logs.
+fn push_error(logs: &mut Vec<Log>, error: ErrorType) {
+    logs.push(Log {
+        log_type: LogType::Error(error),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+}
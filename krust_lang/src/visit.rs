@@ -0,0 +1,162 @@
+//! The module for walking the AST. Passes that only need to handle a few kinds of expressions implement a visitor or a
+//! folder and leave the rest of the tree to the default walk, rather than matching on every kind of expression.
+
+use crate::parser;
+use parser::Expression;
+
+use std::mem::replace;
+
+/// Visits the expressions of an AST without changing them.
+pub trait ExpressionVisitor {
+    /// Visits an expression. By default this visits its children, so an implementation that handles some expressions
+    /// itself should call `walk` for the others.
+    fn visit_expression(&mut self, expr: &Expression) {
+        walk(self, expr);
+    }
+}
+
+/// Visits each child of the expression with the visitor.
+pub fn walk<V: ExpressionVisitor + ?Sized>(visitor: &mut V, expr: &Expression) {
+    for child in children(expr) {
+        visitor.visit_expression(child);
+    }
+}
+
+/// Builds a new AST from an old one, one expression at a time.
+pub trait ExpressionFolder {
+    /// Folds an expression. By default this folds its children and keeps the rest of it, so an implementation that
+    /// replaces some expressions itself should call `fold_children` for the others.
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        fold_children(self, expr)
+    }
+}
+
+/// Replaces each child of the expression with the result of folding it, in the order of `children`.
+pub fn fold_children<F: ExpressionFolder + ?Sized>(folder: &mut F, mut expr: Expression) -> Expression {
+    for child in children_mut(&mut expr) {
+        let old: Expression = replace(child, Expression::Void);
+        *child = folder.fold_expression(old);
+    }
+    expr
+}
+
+/// Gets the expressions directly contained in an expression, in the order they appear in the source. The methods of
+/// traits and the signatures of host functions aren't included, as they are only signatures.
+#[must_use]
+pub fn children(expr: &Expression) -> Vec<&Expression> {
+    match expr {
+        Expression::Binary { left, right, .. }
+        | Expression::Index {
+            expr: left,
+            index: right,
+            ..
+        } => vec![left, right],
+        Expression::Call { args, .. }
+        | Expression::FunctionCall { args, .. }
+        | Expression::HostCall { args, .. }
+        | Expression::MethodCall { args, .. }
+        | Expression::ExpressionList { list: args }
+        | Expression::ListLiteral { elements: args, .. }
+        | Expression::ImplDeclaration { methods: args, .. } => {
+            args.iter().map(AsRef::as_ref).collect()
+        }
+        Expression::FunctionDeclaration {
+            params,
+            defaults,
+            body,
+            ..
+        } => params
+            .iter()
+            .chain(defaults)
+            .map(AsRef::as_ref)
+            .chain([body.as_ref()])
+            .collect(),
+        Expression::MapLiteral { entries, .. } => entries
+            .iter()
+            .flat_map(|(key, value)| [key.as_ref(), value.as_ref()])
+            .collect(),
+        Expression::Cast { expr, .. }
+        | Expression::Grouping { expr, .. }
+        | Expression::Postfix { expr, .. }
+        | Expression::Statement { expr }
+        | Expression::Unary { expr, .. }
+        | Expression::VariableDeclaration {
+            initialized_var: expr,
+        } => vec![expr],
+        Expression::FlagsDeclaration { members, .. } => {
+            members.iter().map(|(_, value)| value.as_ref()).collect()
+        }
+        // The methods of a trait and host functions are only signatures.
+        Expression::TraitDeclaration { .. }
+        | Expression::ExternDeclaration { .. }
+        | Expression::CastOp { .. }
+        | Expression::Literal { .. }
+        | Expression::StringLiteral { .. }
+        | Expression::Type { .. }
+        | Expression::Variable { .. }
+        | Expression::Void
+        | Expression::Error { .. }
+        | Expression::EOF
+        | Expression::Null => Vec::new(),
+    }
+}
+
+/// Gets the expressions directly contained in an expression so they can be changed, in the same order as `children`.
+#[must_use]
+pub fn children_mut(expr: &mut Expression) -> Vec<&mut Expression> {
+    match expr {
+        Expression::Binary { left, right, .. }
+        | Expression::Index {
+            expr: left,
+            index: right,
+            ..
+        } => vec![left, right],
+        Expression::Call { args, .. }
+        | Expression::FunctionCall { args, .. }
+        | Expression::HostCall { args, .. }
+        | Expression::MethodCall { args, .. }
+        | Expression::ExpressionList { list: args }
+        | Expression::ListLiteral { elements: args, .. }
+        | Expression::ImplDeclaration { methods: args, .. } => {
+            args.iter_mut().map(AsMut::as_mut).collect()
+        }
+        Expression::FunctionDeclaration {
+            params,
+            defaults,
+            body,
+            ..
+        } => params
+            .iter_mut()
+            .chain(defaults)
+            .map(AsMut::as_mut)
+            .chain([body.as_mut()])
+            .collect(),
+        Expression::MapLiteral { entries, .. } => entries
+            .iter_mut()
+            .flat_map(|(key, value)| [key.as_mut(), value.as_mut()])
+            .collect(),
+        Expression::Cast { expr, .. }
+        | Expression::Grouping { expr, .. }
+        | Expression::Postfix { expr, .. }
+        | Expression::Statement { expr }
+        | Expression::Unary { expr, .. }
+        | Expression::VariableDeclaration {
+            initialized_var: expr,
+        } => vec![expr],
+        Expression::FlagsDeclaration { members, .. } => {
+            members.iter_mut().map(|(_, value)| value.as_mut()).collect()
+        }
+        // The methods of a trait and host functions are only signatures.
+        Expression::TraitDeclaration { .. }
+        | Expression::ExternDeclaration { .. }
+        | Expression::CastOp { .. }
+        | Expression::Literal { .. }
+        | Expression::StringLiteral { .. }
+        | Expression::Type { .. }
+        | Expression::Variable { .. }
+        | Expression::Void
+        | Expression::Error { .. }
+        | Expression::EOF
+        | Expression::Null => Vec::new(),
+    }
+}
@@ -1,20 +1,383 @@
 //! The module for the virtual machine used by the language.
 
-use crate::{compiler, util::log};
-use compiler::OpCode;
-use log::{is_error, ErrorType, Log, LogType};
+use crate::{batch, compiler, profiler, util::log, verifier};
+use batch::LinkedSource;
+use compiler::{
+    code_start, read_register_operand, swap_byte_order, IsaLevel, OpCode, OperandSource, Section,
+    BIG_ENDIAN_FLAG, FILE_HEADER_SIZE, FORMAT_VERSION, HEADER_SIZE, MAGIC, REGISTER_OPERAND_LENGTH,
+};
+use log::{is_error, DiagnosticSink, ErrorType, Log, LogType, NoteType, RenderOptions};
+use profiler::{FunctionProfile, OpProfile, Profile};
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
-use std::ops::{BitAnd, BitOr, BitXor, Not};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::ops::{BitAnd, BitOr, BitXor, Deref, DerefMut, Not};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use verifier::{verify, VerifierOutput};
 
 use num_traits::FromPrimitive;
 
 // Contains info about a runtime error that could happen.
 struct RuntimeError<'a, T> {
-    condition: &'a (dyn Fn(T) -> bool),
+    condition: &'a dyn Fn(T) -> bool,
     error: ErrorType,
-    index: &'a mut usize,
-    bytecode: &'a Vec<u8>,
+}
+
+// A map stored on the heap. The keys and values are stored as the bytes they would have on the stack.
+type Map = HashMap<Vec<u8>, Vec<u8>>;
+
+// An object stored on the heap. The stack refers to these using handles the size of an int.
+enum HeapObject {
+    String(String),
+    Map(Map),
+    // The elements of a list, stored as the bytes they would have on the stack one after another. The backing Vec
+    // doubles its capacity when it is full, so pushing is amortized constant time.
+    List(Vec<u8>),
+    // The bytes of the value of an ok result, or the message of an error result.
+    Result(Result<Vec<u8>, String>),
+}
+
+impl HeapObject {
+    // Gets the bytes of the object that can hold the handles of other objects.
+    fn handle_bytes(&self) -> Vec<&[u8]> {
+        match self {
+            Self::String(_) | Self::Result(Err(_)) => Vec::new(),
+            Self::Map(map) => map
+                .iter()
+                .flat_map(|(key, value)| [key.as_slice(), value.as_slice()])
+                .collect(),
+            Self::List(bytes) | Self::Result(Ok(bytes)) => vec![bytes],
+        }
+    }
+}
+
+// The number of objects on the heap that triggers the first collection.
+const FIRST_COLLECTION: usize = 1024;
+
+// The objects that a program has allocated. Objects that can no longer be reached are freed by a mark-and-sweep
+// collector, which runs between instructions once the heap has doubled in size since the last collection, and their
+// slots are reused by later objects so that the handles of live objects never change.
+#[derive(Default)]
+struct Heap {
+    objects: Vec<HeapObject>,
+    free: Vec<usize>,  // The slots of the objects that have been freed.
+    collect_at: usize, // The number of objects that triggers the next collection, or zero before the first one.
+}
+
+impl Deref for Heap {
+    type Target = [HeapObject];
+
+    fn deref(&self) -> &Self::Target {
+        &self.objects
+    }
+}
+
+impl DerefMut for Heap {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.objects
+    }
+}
+
+impl Heap {
+    // Gets the number of objects that haven't been freed.
+    fn live(&self) -> usize {
+        self.objects.len() - self.free.len()
+    }
+
+    // Returns whether or not enough objects have been allocated since the last collection to run another, or the heap
+    // has gone over its limit, in which case a collection is needed to know if the program is out of memory.
+    fn should_collect(&self, limit: usize) -> bool {
+        self.live()
+            >= self
+                .collect_at
+                .max(FIRST_COLLECTION)
+                .min(limit.saturating_add(1))
+    }
+
+    // Adds an object to a free slot, or to the end if there are none, and returns its handle.
+    fn alloc(&mut self, object: HeapObject) -> usize {
+        if let Some(handle) = self.free.pop() {
+            self.objects[handle] = object;
+            handle
+        } else {
+            self.objects.push(object);
+            self.objects.len() - 1
+        }
+    }
+
+    // Frees every object that can't be reached from the stack. The types of the values on the stack aren't known, so
+    // every four bytes that could be a handle are treated as one. This can keep an object that is no longer used
+    // alive, but never frees one that is.
+    fn collect(&mut self, stack: &[u8]) {
+        let mut marked: Vec<bool> = vec![false; self.objects.len()];
+        let mut pending: Vec<usize> = Vec::new();
+        mark_handles(stack, &mut marked, &mut pending);
+        while let Some(handle) = pending.pop() {
+            for bytes in self.objects[handle].handle_bytes() {
+                mark_handles(bytes, &mut marked, &mut pending);
+            }
+        }
+        for &handle in &self.free {
+            marked[handle] = true;
+        }
+        for (handle, marked) in marked.into_iter().enumerate() {
+            if !marked {
+                // The object is replaced so that its memory is freed now instead of when the slot is reused.
+                self.objects[handle] = HeapObject::String(String::new());
+                self.free.push(handle);
+            }
+        }
+        self.collect_at = 2 * self.live();
+    }
+
+    // Removes every object while keeping the memory allocated for them.
+    fn clear(&mut self) {
+        self.objects.clear();
+        self.free.clear();
+        self.collect_at = 0;
+    }
+}
+
+// Marks the objects with handles in the bytes that haven't been marked yet, adding them to the objects to scan.
+fn mark_handles(bytes: &[u8], marked: &mut [bool], pending: &mut Vec<usize>) {
+    for window in bytes.windows(4) {
+        let handle: i32 = i32::from_le_bytes([window[0], window[1], window[2], window[3]]);
+        if let Some(marked) = usize::try_from(handle)
+            .ok()
+            .and_then(|handle| marked.get_mut(handle))
+        {
+            if !*marked {
+                *marked = true;
+                pending.push(usize::try_from(handle).unwrap_or_default());
+            }
+        }
+    }
+}
+
+// The number of function calls listed in a stack trace, counting from the innermost.
+const MAX_STACK_TRACE_FRAMES: usize = 16;
+
+/// The maximum number of function calls that can be active at once, if no other limit is given.
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+/// The maximum size of the stack in bytes, if no other limit is given.
+pub const MAX_STACK_BYTES: usize = 8 * 1024 * 1024;
+
+/// The maximum number of objects on the heap, if no other limit is given.
+pub const MAX_HEAP_OBJECTS: usize = 16 * 1024 * 1024;
+
+/// The limits that a program runs within. A program that goes over a limit is stopped with an error instead of using
+/// up the memory of the host.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct VmConfig {
+    /// The largest the stack can grow, in bytes.
+    pub max_stack_bytes: usize,
+    /// The most function calls that can be active at once.
+    pub max_call_depth: usize,
+    /// The most objects, such as strings and lists, that can be on the heap at once. Objects that can no longer be
+    /// reached are freed first.
+    pub max_heap_objects: usize,
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        Self {
+            max_stack_bytes: MAX_STACK_BYTES,
+            max_call_depth: MAX_CALL_DEPTH,
+            max_heap_objects: MAX_HEAP_OBJECTS,
+        }
+    }
+}
+
+/// A function call that is active when a runtime error happens, given by the name of the function and the line and
+/// column of the call, if they are known.
+pub type ActiveCall = (String, Option<(usize, usize)>);
+
+// The state of a function call that is restored when the function returns.
+struct Frame {
+    return_index: usize,
+    stack_base: usize,                 // The start of the arguments on the stack.
+    var_list: Vec<usize>,              // The variables of the caller.
+    function: usize,                   // The address of the function that was called.
+    call_site: Option<(usize, usize)>, // The line and column of the call, if the line table has them.
+}
+
+/// A function that the embedder of the VM provides to programs. It is given the arguments of the call and returns
+/// the result, or a message saying why it failed.
+pub type HostFunction = Box<dyn FnMut(&[i32]) -> Result<i32, String>>;
+
+/// A value printed by a program. The value a run gives is the last one the program printed, which is the value of its
+/// last expression, or `Void` if it printed nothing. Values are displayed the same way the program prints them.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Value {
+    Void,
+    Int(i32),
+    Bool(bool),
+    String(String),
+    Null,
+    List(Vec<Value>),
+    /// The entries of a map, sorted by key.
+    Map(Vec<(Value, Value)>),
+    /// An ok result with its value, or an error result with its message.
+    Result(Result<Box<Value>, String>),
+}
+
+impl Value {
+    // Formats the value as an element of a list, a map, or a result, where strings are quoted.
+    fn element_string(&self) -> String {
+        match self {
+            Self::String(value) => format!("{value:?}"),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Void => Ok(()),
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Bool(value) => write!(f, "{value}"),
+            Self::String(value) => write!(f, "{value}"),
+            Self::Null => write!(f, "null"),
+            Self::List(elements) => {
+                let elements: Vec<String> = elements.iter().map(Self::element_string).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Self::Map(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| {
+                        format!("{}: {}", key.element_string(), value.element_string())
+                    })
+                    .collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+            Self::Result(Ok(value)) => write!(f, "ok({})", value.element_string()),
+            Self::Result(Err(message)) => write!(f, "err({message})"),
+        }
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+// What the embedder provides to a program. These are kept when the VM is reset.
+struct Natives {
+    args: Vec<String>,
+    host_functions: HashMap<String, HostFunction>,
+    file_access: bool, // Whether or not the program can read and write files.
+    source: Option<LinkedSource>, // The files the program was compiled from, if there were many.
+    config: VmConfig,
+    profiling: bool, // Whether or not the instructions that run are counted and timed.
+    output: Box<dyn Write>, // Where the values the program prints are written.
+    errors: Box<dyn Write>, // Where runtime errors are written.
+    render: RenderOptions, // How runtime errors are written.
+    sink: Option<Box<dyn DiagnosticSink>>, // Where runtime errors are reported instead, if anywhere.
+    #[allow(dead_code)] // Read by the input instructions, which the language doesn't have yet.
+    input: Box<dyn BufRead>,
+}
+
+impl Default for Natives {
+    fn default() -> Self {
+        Self {
+            args: Vec::new(),
+            host_functions: default_host_functions(),
+            file_access: false,
+            source: None,
+            config: VmConfig::default(),
+            profiling: false,
+            render: RenderOptions::default(),
+            output: Box::new(io::stdout()),
+            errors: Box::new(io::stderr()),
+            sink: None,
+            input: Box::new(BufReader::new(io::stdin())),
+        }
+    }
+}
+
+/// Creates the host functions used by built-in functions, such as `random_int` and `now_millis`, which are registered by
+/// default.
+#[must_use]
+pub fn default_host_functions() -> HashMap<String, HostFunction> {
+    let mut host_functions: HashMap<String, HostFunction> = HashMap::new();
+    host_functions.insert("random_int".to_string(), random_int());
+    host_functions.insert("now_millis".to_string(), now_millis());
+    host_functions
+}
+
+// Creates the host function for `random_int(lo, hi)`, which gives an int from lo to hi inclusive. The numbers come
+// from a xorshift generator seeded with the current time, so they are not suitable for cryptography.
+fn random_int() -> HostFunction {
+    let nanos: u128 = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos());
+    let mut state: u64 = u64::try_from(nanos & u128::from(u64::MAX)).unwrap_or_default() | 1;
+    Box::new(move |args: &[i32]| {
+        let &[lo, hi] = args else {
+            return Err("expected a lower and an upper bound.".to_string());
+        };
+        if lo > hi {
+            return Err(format!(
+                "the lower bound {lo} is greater than the upper bound {hi}."
+            ));
+        }
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let range: u64 = u64::try_from(i64::from(hi) - i64::from(lo) + 1)
+            .expect("the range is positive since lo <= hi");
+        let offset: i64 =
+            i64::try_from(state % range).expect("the offset is less than the range of an int");
+        Ok(i32::try_from(i64::from(lo) + offset).expect("the value is between lo and hi"))
+    })
+}
+
+// Creates the host function for `now_millis()`, which gives the number of milliseconds since the function was
+// created. Differences between calls can be used to time parts of a program. The value stops increasing after about
+// 24 days, once it reaches the largest int.
+fn now_millis() -> HostFunction {
+    let start: Instant = Instant::now();
+    Box::new(
+        move |_args: &[i32]| Ok(i32::try_from(start.elapsed().as_millis()).unwrap_or(i32::MAX)),
+    )
+}
+
+// The values a program creates while it runs.
+#[derive(Default)]
+struct State {
+    stack: Vec<u8>,
+    var_list: Vec<usize>, // The positions of the global variables on the stack.
+    heap: Heap,
+    frames: Vec<Frame>,
+    output: Vec<Value>,            // The values printed so far.
+    paused_at: Option<usize>,      // The next instruction to run, if the run ran out of fuel.
+    profile: Vec<(u64, Duration)>, // The runs of each instruction and the time they took, by offset, if profiling.
+}
+
+impl State {
+    // Removes every value while keeping the memory allocated for them, so that the next run doesn't need to grow it
+    // again.
+    fn clear(&mut self) {
+        self.stack.clear();
+        self.var_list.clear();
+        self.heap.clear();
+        self.frames.clear();
+        self.output.clear();
+        self.paused_at = None;
+        self.profile.clear();
+    }
 }
 
 // A trait for types that can be stored on the stack.
@@ -94,7 +457,7 @@ impl StackType for i32 {
     }
 
     fn push_to_stack(&self, stack: &mut Vec<u8>) {
-        stack.append(&mut self.to_le_bytes().to_vec());
+        stack.extend_from_slice(&self.to_le_bytes());
     }
 
     fn default() -> Self {
@@ -102,15 +465,10 @@ impl StackType for i32 {
     }
 
     fn pop_from_stack(stack: &mut Vec<u8>) -> Option<Self> {
-        if stack.len() < 4 {
-            None
-        } else {
-            let mut bytes: [u8; 4] = [0, 0, 0, 0];
-            for i in 0..4 {
-                bytes[4 - i - 1] = stack.pop().expect("stack was checked");
-            }
-            Some(Self::from_le_bytes(bytes))
-        }
+        let start: usize = stack.len().checked_sub(4)?;
+        let value: Self = Self::from_le_bytes(stack[start..].try_into().ok()?);
+        stack.truncate(start);
+        Some(value)
     }
 
     fn read_from_stack(stack: &[u8], index: usize) -> Option<Self> {
@@ -167,6 +525,65 @@ impl StackType for bool {
     add_eq!();
 }
 
+// A value that may be null. On the stack, the value is followed by a byte that is 0 if the value is null.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Nullable<T>(Option<T>);
+
+impl<T: Display> Display for Nullable<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(value) => write!(f, "{value}"),
+            None => write!(f, "null"),
+        }
+    }
+}
+
+impl<T: Into<Value>> From<Nullable<T>> for Value {
+    fn from(value: Nullable<T>) -> Self {
+        value.0.map_or(Self::Null, Into::into)
+    }
+}
+
+impl<T: StackType + PartialEq> StackType for Nullable<T> {
+    fn size() -> usize {
+        T::size() + 1
+    }
+
+    fn default() -> Self {
+        Self(None)
+    }
+
+    fn push_to_stack(&self, stack: &mut Vec<u8>) {
+        self.0.unwrap_or_else(T::default).push_to_stack(stack);
+        stack.push(u8::from(self.0.is_some()));
+    }
+
+    fn pop_from_stack(stack: &mut Vec<u8>) -> Option<Self> {
+        let flag: u8 = stack.pop()?;
+        let value: T = T::pop_from_stack(stack)?;
+        Some(Self(if flag == 0 { None } else { Some(value) }))
+    }
+
+    fn read_from_stack(stack: &[u8], index: usize) -> Option<Self> {
+        let value: T = T::read_from_stack(stack, index)?;
+        let flag: u8 = *stack.get(index + T::size())?;
+        Some(Self(if flag == 0 { None } else { Some(value) }))
+    }
+
+    fn write_to_stack(&self, stack: &mut Vec<u8>, index: usize) -> bool {
+        let flag_index: usize = index + T::size();
+        if flag_index >= stack.len() {
+            return false;
+        }
+        stack[flag_index] = u8::from(self.0.is_some());
+        self.0
+            .unwrap_or_else(T::default)
+            .write_to_stack(stack, index)
+    }
+
+    add_eq!();
+}
+
 // A trait for numerical types.
 trait NumType: StackType {
     // Negates the given value.
@@ -201,10 +618,25 @@ trait NumType: StackType {
 
     // Returns whether or not the value is 0.
     fn is_zero(a: Self) -> bool;
+
+    // Returns whether or not the value is less than 0.
+    fn is_negative(a: Self) -> bool;
+
+    // Gets the absolute value of the given value.
+    fn abs(a: Self) -> Self;
+
+    // Raises a to the power of b. Negative powers are truncated towards zero like division.
+    fn pow(a: Self, b: Self) -> Self;
+
+    // Gets the square root of the given value rounded down. Outputs zero if a is negative.
+    fn sqrt(a: Self) -> Self;
 }
 
 // A trait for integral types.
 trait IntegralType: NumType + Not + BitAnd + BitXor + BitOr {
+    // The number of bits in the type.
+    const BITS_ISIZE: isize;
+
     // Shifts a. If b is positive, shift left. Otherwise, shift right.
     fn shift(a: Self, b: Self) -> Self;
 }
@@ -263,15 +695,57 @@ macro_rules! implIntegralType {
             fn is_zero(a: Self) -> bool {
                 a == 0
             }
+
+            #[allow(unused_comparisons)]
+            fn is_negative(a: Self) -> bool {
+                a < 0 // a is never less than 0 for unsigned types.
+            }
+
+            fn abs(a: Self) -> Self {
+                if Self::is_negative(a) {
+                    <$type>::wrapping_neg(a)
+                } else {
+                    a
+                }
+            }
+
+            fn pow(a: Self, b: Self) -> Self {
+                if Self::is_negative(b) {
+                    // Only 1 and -1 have reciprocals that are integers.
+                    return if a == 1 {
+                        1
+                    } else if a == <$type>::wrapping_neg(1) {
+                        if b % 2 == 0 {
+                            1
+                        } else {
+                            a
+                        }
+                    } else {
+                        0
+                    };
+                }
+                <$type>::wrapping_pow(a, u32::try_from(b).unwrap_or(u32::MAX))
+            }
+
+            fn sqrt(a: Self) -> Self {
+                if Self::is_negative(a) {
+                    0
+                } else {
+                    <$type>::isqrt(a)
+                }
+            }
         }
 
         impl IntegralType for $type {
+            #[allow(clippy::cast_possible_wrap)] // The number of bits is always small.
+            const BITS_ISIZE: isize = <$type>::BITS as isize;
+
             #[allow(unused_comparisons)]
             fn shift(a: Self, b: Self) -> Self {
-                if b as isize > <$type>::BITS as isize - 1 {
+                if b as isize > Self::BITS_ISIZE - 1 {
                     return 0;
                 }
-                if (b as isize) < { -(<$type>::BITS as isize) + 1 } {
+                if (b as isize) < -Self::BITS_ISIZE + 1 {
                     return if a < 0 { <$type>::wrapping_neg(1) } else { 0 }; // syntax always valid but a is never less than 0 for unsigned types.
                 }
 
@@ -290,551 +764,2920 @@ macro_rules! implIntegralType {
 implIntegralType!(u8);
 implIntegralType!(i32);
 
-/// Runs the bytecode.
-#[allow(clippy::must_use_candidate)] // Compiling using krustc requires that this not be annotated as must use.
-pub fn run(bytecode: &Vec<u8>) -> (Vec<String>, Vec<Log>) {
-    let mut output: Vec<String> = Vec::new();
-    let mut logs: Vec<Log> = Vec::new();
+/// How a run given a limited amount of fuel or breakpoints stopped.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RunStatus {
+    /// The program finished, giving the value of its last expression.
+    Finished(Value),
+    /// The program ran out of fuel before it finished. The run can be continued with `Vm::resume_with_fuel`.
+    OutOfFuel,
+    /// The program paused before an instruction on a line with a breakpoint. The run can be continued with
+    /// `Vm::resume`.
+    Breakpoint(usize),
+}
 
-    let possible_error = handle_errors(bytecode, &mut output, &mut logs);
-    if let Some(error) = possible_error {
-        return (error.0.clone(), error.1.clone());
-    }
+/// The state of the VM after running a single instruction with `Vm::step`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Step {
+    /// The instruction that was run, or None if the program had no instructions left.
+    pub op: Option<OpCode>,
+    /// The offset of the instruction that was run.
+    pub offset: usize,
+    /// The offset of the next instruction to run, or None if the program has finished.
+    pub ip: Option<usize>,
+    /// The bytes on the stack after the instruction.
+    pub stack: Vec<u8>,
+}
 
-    let mut index: usize = 2;
-    let mut stack: Vec<u8> = Vec::new();
-    let mut var_list: Vec<usize> = Vec::new();
-    while index < bytecode.len() {
-        let curr_op: Option<OpCode> = FromPrimitive::from_u8(bytecode[index]);
-        index += 1;
+/// A program loaded into the VM. Embedders that run the same program many times can precompile it once, so that
+/// each run skips the setup that only depends on the bytecode.
+///
+/// `run` gives the value of the program's last expression, or `Value::Void` if it has none, or the error that stopped
+/// it along with its stack trace. What the program prints goes to stdout and its runtime errors to stderr unless
+/// another writer is given with `set_output` and `set_error_output`, and `output` gives the lines the last run
+/// printed. `reset` frees what a run left on the stack and heap while keeping the precompiled program.
+///
+/// ```
+/// use krust::compiler::{compile, CompilerOptions};
+/// use krust::lexer::lex;
+/// use krust::parser::parse;
+/// use krust::vm::{Value, Vm};
+///
+/// let bytecode: Vec<u8> = compile(parse(lex("6 * 7")), CompilerOptions::default()).bytecode.expect("it should compile");
+/// let mut vm: Vm = Vm::new(bytecode);
+/// vm.set_output(std::io::sink());
+/// vm.precompile().expect("the bytecode should be valid");
+/// assert_eq!(vm.run().ok(), Some(Value::Int(42)));
+/// vm.reset();
+/// assert_eq!(vm.run().ok(), Some(Value::Int(42)));
+/// ```
+pub struct Vm {
+    bytecode: Vec<u8>,
+    program: Option<Result<Program, Vec<Log>>>, // Set once the program has been precompiled.
+    verify: bool, // Whether or not the program is only run if it passes the verifier.
+    natives: Natives,
+    state: State,
+    breakpoints: HashSet<usize>, // The source lines that runs pause at.
+}
 
-        if let Some(op) = curr_op {
-            if match_op(
-                op,
-                bytecode,
-                &mut stack,
-                &mut index,
-                &mut output,
-                &mut logs,
-                &mut var_list,
-            ) {
-                for log in &logs {
-                    eprintln!("{log}");
-                }
-                return (output, logs);
-            }
-        } else {
-            logs.push(Log {
-                log_type: LogType::Error(ErrorType::FatalError),
-                line_and_col: None,
-            });
-            return (output, logs);
+impl Vm {
+    /// Loads the bytecode without running it.
+    #[must_use]
+    pub fn new(bytecode: Vec<u8>) -> Self {
+        Self {
+            bytecode,
+            program: None,
+            verify: false,
+            natives: Natives::default(),
+            state: State::default(),
+            breakpoints: HashSet::new(),
         }
     }
-    (output, logs)
-}
 
-// Handle any errors immediatly present in the bytecode.
-fn handle_errors<'o, 'e>(
-    bytecode: &Vec<u8>,
-    output: &'o mut Vec<String>,
-    logs: &'e mut Vec<Log>,
-) -> Option<(&'o Vec<String>, &'e Vec<Log>)> {
-    if bytecode.len() < 2 {
-        logs.push(Log {
-            log_type: LogType::Error(ErrorType::FatalError),
-            line_and_col: None,
-        });
-        return Some((output, logs));
+    /// Sets the arguments given to the program, which it can get with `args()`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.natives.args = args;
     }
-    let ptr_size: usize = bytecode[0] as usize;
-    if ptr_size * 8
-        > usize::BITS
-            .try_into()
-            .expect("max value of usize must be less than the number of bits")
-    {
-        logs.push(Log {
-            log_type: LogType::Error(ErrorType::CompiledForDifferentTarget(ptr_size * 8)),
-            line_and_col: None,
-        });
-        return Some((output, logs));
+
+    /// Sets whether or not the program can read and write files with `read_file` and `write_file`. File access is
+    /// disabled by default so that untrusted programs can't use it.
+    pub fn set_file_access(&mut self, file_access: bool) {
+        self.natives.file_access = file_access;
     }
-    None
-}
 
-// Runs a function given a specific op code.
-fn match_op(
-    op: OpCode,
-    bytecode: &Vec<u8>,
-    stack: &mut Vec<u8>,
-    index: &mut usize,
-    output: &mut Vec<String>,
-    logs: &mut Vec<Log>,
-    var_list: &mut Vec<usize>,
-) -> bool {
-    match op {
-        OpCode::PushInt => push::<i32>(bytecode, stack, index, logs),
-        OpCode::PushByte => push::<u8>(bytecode, stack, index, logs),
-        OpCode::PopInt => pop::<i32>(stack, logs),
-        OpCode::PopByte => pop::<u8>(stack, logs),
-        OpCode::PrintInt => print::<i32>(stack, output, logs),
-        OpCode::PrintBool => print::<bool>(stack, output, logs),
+    /// Sets the files that the program was compiled from, so that runtime errors are printed with the file they happen
+    /// in and the line in that file.
+    pub fn set_linked_source(&mut self, source: LinkedSource) {
+        self.natives.source = Some(source);
+    }
 
-        OpCode::AllocInt => alloc::<i32>(stack, var_list),
-        OpCode::AllocBool => alloc::<bool>(stack, var_list),
-        OpCode::GetInt => get::<i32>(bytecode, stack, index, logs, var_list),
-        OpCode::GetBool => get::<bool>(bytecode, stack, index, logs, var_list),
-        OpCode::SetInt => set::<i32>(bytecode, stack, index, logs, var_list),
-        OpCode::SetBool => set::<bool>(bytecode, stack, index, logs, var_list),
+    /// Sets the limits that the program runs within, such as the size of the stack.
+    pub fn set_config(&mut self, config: VmConfig) {
+        self.natives.config = config;
+    }
 
-        OpCode::IntToBool => cast_int_to_bool(stack, logs),
-        OpCode::BoolToInt => cast_bool_to_int(stack),
+    /// Sets whether or not runs are profiled, counting how many times each instruction runs and how long it takes.
+    /// Profiling makes programs run more slowly, so it is disabled by default.
+    pub fn set_profiling(&mut self, profiling: bool) {
+        self.natives.profiling = profiling;
+    }
 
-        OpCode::MinusInt => minus::<i32>(stack, logs),
-        OpCode::AddInt => add::<i32>(stack, logs),
-        OpCode::SubtractInt => subtract::<i32>(stack, logs),
-        OpCode::MultiplyInt => multiply::<i32>(stack, logs),
-        OpCode::DivideInt => divide::<i32>(bytecode, stack, index, logs),
-        OpCode::ModuloInt => modulo::<i32>(bytecode, stack, index, logs),
+    /// Sets where the values the program prints are written, which is stdout by default. The printed values are also
+    /// kept for `output` whatever the stream is, so `std::io::sink()` can be used to run a program silently.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.natives.output = Box::new(output);
+    }
 
-        OpCode::LessInt => less::<i32>(stack, logs),
-        OpCode::LessEqualInt => less_equal::<i32>(stack, logs),
-        OpCode::GreaterInt => greater::<i32>(stack, logs),
-        OpCode::GreaterEqualInt => greater_equal::<i32>(stack, logs),
+    /// Sets where runtime errors are written, which is stderr by default. The errors are also returned by `run`.
+    pub fn set_error_output(&mut self, errors: impl Write + 'static) {
+        self.natives.errors = Box::new(errors);
+    }
 
-        OpCode::Not => not(stack, logs),
+    /// Sets how runtime errors written to the error output are rendered, which is as plain text by default.
+    pub fn set_render_options(&mut self, options: RenderOptions) {
+        self.natives.render = options;
+    }
 
-        OpCode::ComplementInt => complement::<i32>(stack, logs),
-        OpCode::AndInt => and::<i32>(stack, logs),
-        OpCode::AndByte => and::<u8>(stack, logs),
-        OpCode::XorInt => xor::<i32>(stack, logs),
-        OpCode::XorByte => xor::<u8>(stack, logs),
-        OpCode::OrInt => or::<i32>(stack, logs),
-        OpCode::OrByte => or::<u8>(stack, logs),
+    /// Sets a sink that runtime errors are reported to instead of being written to the error output, so that they can
+    /// go to the embedder's own logging. The errors are still returned by `run`.
+    pub fn set_diagnostic_sink(&mut self, sink: impl DiagnosticSink + 'static) {
+        self.natives.sink = Some(Box::new(sink));
+    }
 
-        OpCode::LeftShiftInt => left_shift::<i32>(stack, logs),
-        OpCode::RightShiftInt => right_shift::<i32>(stack, logs),
+    /// Sets where the program reads its input from, which is stdin by default.
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.natives.input = Box::new(input);
+    }
 
-        OpCode::EqualityInt => equality::<i32>(stack, logs),
-        OpCode::EqualityByte => equality::<u8>(stack, logs),
-        OpCode::InequalityInt => inequality::<i32>(stack, logs),
-        OpCode::InequalityByte => inequality::<u8>(stack, logs),
-    };
-    is_error(logs)
-}
+    /// Sets whether or not the bytecode is checked by the verifier before it runs. A program that fails verification
+    /// isn't run, and its errors are returned instead, so bytecode that is loaded from a file or otherwise untrusted
+    /// can't make the VM panic partway through a run. Verification is disabled by default, as the compiler always
+    /// generates valid bytecode.
+    pub fn set_verify(&mut self, verify: bool) {
+        if self.verify != verify {
+            self.verify = verify;
+            self.program = None;
+        }
+    }
 
-// Pushes a value from the bytecode to the stack.
-fn push<T>(bytecode: &Vec<u8>, stack: &mut Vec<u8>, index: &mut usize, logs: &mut Vec<Log>)
-where
-    T: StackType,
-{
-    if *index + T::size() > bytecode.len() {
-        logs.push(Log {
-            log_type: LogType::Error(ErrorType::FatalError),
-            line_and_col: None,
-        });
+    /// Registers a function that the program can call by name, replacing any host function with the same name. The
+    /// host functions used by built-in functions, such as `random_int` and `now_millis`, are registered by default.
+    pub fn register_host_function(
+        &mut self,
+        name: &str,
+        function: impl FnMut(&[i32]) -> Result<i32, String> + 'static,
+    ) {
+        self.natives
+            .host_functions
+            .insert(name.to_string(), Box::new(function));
     }
-    for _i in 0..T::size() {
-        stack.push(bytecode[*index]);
-        *index += 1;
+
+    /// Checks the header of the bytecode, decodes each instruction along with the operands of those that run the most,
+    /// and decodes the string constants.
+    /// The bytecode is verified first if verification is enabled. This is done by the first run if it hasn't been done
+    /// already.
+    ///
+    /// # Errors
+    /// Returns the errors that stop the program from running, such as an unsupported instruction set level.
+    pub fn precompile(&mut self) -> Result<(), Vec<Log>> {
+        let program: &Result<Program, Vec<Log>> = self
+            .program
+            .get_or_insert_with(|| precompile_checked(&self.bytecode, self.verify));
+        program.as_ref().map(|_| ()).map_err(Clone::clone)
+    }
+
+    /// Returns whether or not the program has been precompiled.
+    #[must_use]
+    pub fn is_precompiled(&self) -> bool {
+        self.program.is_some()
+    }
+
+    /// Runs the program from the start, precompiling it first if needed. The stack, heap, globals, and output from
+    /// the run are kept until the VM is reset, which also happens at the start of each run.
+    ///
+    /// # Errors
+    /// Returns the error that stopped the program, along with its stack trace, or the errors that stopped it from
+    /// running at all. What it printed before the error can still be read with `output`.
+    pub fn run(&mut self) -> Result<Value, Vec<Log>> {
+        self.reset();
+        // A run without a fuel limit or breakpoints always finishes.
+        self.execute(None, false).map(|status| match status {
+            RunStatus::Finished(value) => value,
+            RunStatus::OutOfFuel | RunStatus::Breakpoint(_) => Value::Void,
+        })
+    }
+
+    /// Runs the program from the start like `run`, but stops once it has run the given number of instructions, so
+    /// that a server can run untrusted programs without them running forever. A program that runs out of fuel can be
+    /// continued with `resume_with_fuel`. The run also pauses at breakpoints.
+    ///
+    /// # Errors
+    /// Returns the error that stopped the program, along with its stack trace, or the errors that stopped it from
+    /// running at all.
+    pub fn run_with_fuel(&mut self, fuel: u64) -> Result<RunStatus, Vec<Log>> {
+        self.reset();
+        self.execute(Some(fuel), true)
+    }
+
+    /// Continues a paused run until it finishes or reaches a breakpoint. If no run is paused, a new one is started.
+    ///
+    /// # Errors
+    /// Returns the error that stopped the program, along with its stack trace, or the errors that stopped it from
+    /// running at all.
+    pub fn resume(&mut self) -> Result<RunStatus, Vec<Log>> {
+        if !self.is_paused() {
+            self.reset();
+        }
+        self.execute(None, true)
+    }
+
+    /// Continues a paused run, running at most the given number of instructions. If no run is paused, a new one is
+    /// started.
+    ///
+    /// # Errors
+    /// Returns the error that stopped the program, along with its stack trace, or the errors that stopped it from
+    /// running at all.
+    pub fn resume_with_fuel(&mut self, fuel: u64) -> Result<RunStatus, Vec<Log>> {
+        if !self.is_paused() {
+            self.reset();
+        }
+        self.execute(Some(fuel), true)
+    }
+
+    /// Returns whether or not the last run ran out of fuel or reached a breakpoint before it finished.
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.state.paused_at.is_some()
+    }
+
+    /// Runs the next instruction of a paused run, or starts a new run and runs its first instruction, so that a
+    /// debugger can follow the program one instruction at a time. Stepping pauses the run the same way running out
+    /// of fuel does, so `resume_with_fuel` can continue it.
+    ///
+    /// # Errors
+    /// Returns the error that the instruction stopped the program with, along with its stack trace, or the errors
+    /// that stopped it from running at all.
+    pub fn step(&mut self) -> Result<Step, Vec<Log>> {
+        if !self.is_paused() {
+            self.reset();
+        }
+        self.precompile()?;
+        let offset: usize = self
+            .state
+            .paused_at
+            .unwrap_or_else(|| code_start(&self.bytecode));
+        let op: Option<OpCode> = self
+            .bytecode
+            .get(offset)
+            .and_then(|byte| FromPrimitive::from_u8(*byte));
+        self.execute(Some(1), false)?;
+        Ok(Step {
+            op,
+            offset,
+            ip: self.state.paused_at,
+            stack: self.state.stack.clone(),
+        })
+    }
+
+    /// Sets a breakpoint on a line of the source code, so that runs other than `run` pause before each instruction on
+    /// the line that they reach. Only instructions in the line table can be stopped at, so this returns whether or not
+    /// the line has any. The first instruction of each statement is in the line table, so only lines with no
+    /// statement starting on them, and any program compiled without detailed errors, have none.
+    pub fn set_breakpoint(&mut self, line: usize) -> bool {
+        self.breakpoints.insert(line);
+        let _ = self.precompile();
+        matches!(&self.program, Some(Ok(program)) if program.locations.values().any(|(other, _)| *other == line))
+    }
+
+    /// Removes the breakpoint on a line of the source code, if it has one.
+    pub fn remove_breakpoint(&mut self, line: usize) {
+        self.breakpoints.remove(&line);
+    }
+
+    // Runs the program from where the state left off, precompiling it first if needed. The run only pauses at
+    // breakpoints if use_breakpoints is true.
+    fn execute(&mut self, fuel: Option<u64>, use_breakpoints: bool) -> Result<RunStatus, Vec<Log>> {
+        let breakpoints: Option<&HashSet<usize>> =
+            (use_breakpoints && !self.breakpoints.is_empty()).then_some(&self.breakpoints);
+        let program: &Program = match self
+            .program
+            .get_or_insert_with(|| precompile_checked(&self.bytecode, self.verify))
+        {
+            Ok(program) => program,
+            Err(logs) => return Err(logs.clone()),
+        };
+        let logs: Vec<Log> = execute(
+            &self.bytecode,
+            program,
+            &mut self.natives,
+            &mut self.state,
+            fuel,
+            breakpoints,
+        );
+        let _ = self.natives.output.flush();
+        if !logs.is_empty() {
+            return Err(logs);
+        }
+        Ok(match self.state.paused_at {
+            Some(index) => breakpoint_line(program, breakpoints, index)
+                .map_or(RunStatus::OutOfFuel, RunStatus::Breakpoint),
+            None => RunStatus::Finished(self.state.output.last().cloned().unwrap_or(Value::Void)),
+        })
+    }
+
+    /// Gets the lines printed by the last run, which are kept until the VM is reset.
+    #[must_use]
+    pub fn output(&self) -> Vec<String> {
+        self.state.output.iter().map(ToString::to_string).collect()
+    }
+
+    /// Clears the stack, heap, and globals left by the last run. The precompiled program and the program arguments
+    /// are kept, so a server can free the values from one evaluation as soon as it's done without loading the
+    /// program again.
+    pub fn reset(&mut self) {
+        self.state.clear();
+    }
+
+    /// Gets the profile of the last run, which is empty unless profiling was enabled. Functions are named if the
+    /// bytecode has their names, which it only does for programs that can panic.
+    #[must_use]
+    pub fn profile(&self) -> Profile {
+        let names: HashMap<usize, String> = function_names(&self.bytecode);
+        let runs = || {
+            self.state
+                .profile
+                .iter()
+                .enumerate()
+                .filter(|(_, (count, _))| *count > 0)
+        };
+        // Every function that ran was called by an instruction that ran, which gives the address of the function.
+        let mut addresses: Vec<usize> = runs()
+            .filter(|(offset, _)| {
+                matches!(
+                    OpCode::from_u8(self.bytecode[*offset]),
+                    Some(OpCode::Call | OpCode::TailCall)
+                )
+            })
+            .filter_map(|(offset, _)| read_ptr_size(&self.bytecode, &mut (offset + 1)))
+            .collect();
+        addresses.sort_unstable();
+        addresses.dedup();
+        let mut profile: Profile = Profile::default();
+        for (offset, &(count, time)) in runs() {
+            let Some(op) = OpCode::from_u8(self.bytecode[offset]) else {
+                continue;
+            };
+            add_op_time(&mut profile.ops, op, count, time);
+            // The functions come after the main program, so an instruction is in the last function that starts
+            // before it.
+            let function: Option<usize> = addresses
+                .partition_point(|address| *address <= offset)
+                .checked_sub(1)
+                .map(|position| addresses[position]);
+            let name: Option<String> = function.map(|address| {
+                names
+                    .get(&address)
+                    .cloned()
+                    .unwrap_or_else(|| format!("<function at offset {address}>"))
+            });
+            add_function_time(
+                &mut profile.functions,
+                name,
+                function == Some(offset),
+                count,
+                time,
+            );
+        }
+        profile
+            .ops
+            .sort_by(|a, b| b.time.cmp(&a.time).then(b.count.cmp(&a.count)));
+        profile.functions.sort_by(|a, b| {
+            b.time
+                .cmp(&a.time)
+                .then(b.instructions.cmp(&a.instructions))
+        });
+        profile
+    }
+
+    /// Gets the number of objects on the heap that haven't been freed, which is zero once the VM has been reset.
+    #[must_use]
+    pub fn heap_size(&self) -> usize {
+        self.state.heap.live()
+    }
+}
+
+// An operand decoded ahead of the run. Only the instructions that run the most have their operands decoded, and the
+// others read them from the bytecode as they run.
+#[derive(Clone, Copy)]
+enum Operand {
+    Encoded,
+    Int(i32),
+    Byte(u8),
+    Var(usize),
+    Call { address: usize, args_size: usize },
+}
+
+// An instruction decoded ahead of the run, so that the main loop doesn't have to read it from the bytecode again.
+#[derive(Clone, Copy)]
+struct Instruction {
+    op: OpCode,
+    operand: Operand,
+    next: usize, // The offset of the instruction after it.
+}
+
+// The parts of a program that only depend on its bytecode.
+struct Program {
+    instructions: Vec<Instruction>, // Each instruction up to the first that can't be read, in order.
+    positions: Vec<Option<usize>>, // The position in instructions of the instruction at each offset.
+    strings: HashMap<usize, (String, usize)>, // The value and operand length of each string constant, by offset.
+    constants: Vec<(OpCode, usize)>, // The push instruction of each constant in the pool and the offset of its operand.
+    locations: HashMap<usize, (usize, usize)>, // The line and column of each instruction in the line table, by offset.
+}
+
+impl Program {
+    // Gets the position of the instruction at the offset, if it could be decoded.
+    fn position(&self, offset: usize) -> Option<usize> {
+        self.positions.get(offset).copied().flatten()
+    }
+
+    // Gets the position of the instruction at the offset that the program continued at after the instruction at the
+    // given position. That is the next instruction unless the program jumped.
+    fn next_position(&self, position: Option<usize>, offset: usize) -> Option<usize> {
+        match position {
+            Some(position) if self.instructions[position].next == offset => {
+                Some(position + 1).filter(|next| *next < self.instructions.len())
+            }
+            _ => self.position(offset),
+        }
+    }
+}
+
+// Precompiles the program, running the verifier on it first if asked to. Returns the errors from the verifier if there
+// are any.
+fn precompile_checked(bytecode: &[u8], verify_first: bool) -> Result<Program, Vec<Log>> {
+    if verify_first {
+        let output: VerifierOutput = verify(bytecode);
+        if is_error(&output.logs) {
+            return Err(output.logs);
+        }
+    }
+    precompile(bytecode)
+}
+
+// Gets the parts of a program that only depend on its bytecode, decoding each instruction once. Instructions after one
+// that can't be read are decoded as they are run instead. Returns the errors in the header, if any.
+fn precompile(bytecode: &[u8]) -> Result<Program, Vec<Log>> {
+    let mut logs: Vec<Log> = Vec::new();
+    if handle_errors(bytecode, &mut Vec::new(), &mut logs).is_some() {
+        return Err(logs);
+    }
+    let ptr_size: usize = get_ptr_size(bytecode);
+    let mut program: Program = Program {
+        instructions: Vec::new(),
+        positions: vec![None; bytecode.len()],
+        strings: HashMap::new(),
+        constants: Vec::new(),
+        locations: HashMap::new(),
+    };
+    let mut index: usize = code_start(bytecode);
+    while index < bytecode.len() {
+        let op: Option<OpCode> = FromPrimitive::from_u8(bytecode[index]);
+        let operand_start: usize = index + 1;
+        let Some((op, length)) = op.and_then(|op| {
+            op.operand_length(ptr_size, &bytecode[operand_start..])
+                .filter(|length| operand_start + length <= bytecode.len())
+                .map(|length| (op, length))
+        }) else {
+            break;
+        };
+        program.positions[index] = Some(program.instructions.len());
+        program.instructions.push(Instruction {
+            op,
+            operand: decode_operand(op, bytecode, operand_start).unwrap_or(Operand::Encoded),
+            next: operand_start + length,
+        });
+        if op == OpCode::LineTable {
+            read_line_table(
+                bytecode,
+                operand_start + 4,
+                operand_start + length,
+                &mut program.locations,
+            );
+        }
+        // A constant is the push instruction that loading it runs.
+        let (op, operand_start, length): (OpCode, usize, usize) = if op == OpCode::Constant {
+            let push: OpCode = FromPrimitive::from_u8(bytecode[operand_start])
+                .expect("the length of a constant can only be read if it is a push");
+            program.constants.push((push, operand_start + 1));
+            (push, operand_start + 1, length - 1)
+        } else {
+            (op, operand_start, length)
+        };
+        if op == OpCode::PushString {
+            let value: Option<&str> =
+                std::str::from_utf8(&bytecode[operand_start + 4..operand_start + length]).ok();
+            if let Some(value) = value {
+                program
+                    .strings
+                    .insert(operand_start, (value.to_string(), length));
+            }
+        }
+        index = operand_start + length;
+    }
+    Ok(program)
+}
+
+// Decodes the operand of an instruction that runs often, or gives None if the operand is read as the instruction runs.
+fn decode_operand(op: OpCode, bytecode: &[u8], mut index: usize) -> Option<Operand> {
+    Some(match op {
+        OpCode::PushInt => Operand::Int(i32::from_le_bytes(
+            bytecode.get(index..index + 4)?.try_into().ok()?,
+        )),
+        OpCode::PushByte | OpCode::Dup => Operand::Byte(*bytecode.get(index)?),
+        OpCode::GetInt
+        | OpCode::GetBool
+        | OpCode::GetNullableInt
+        | OpCode::GetNullableBool
+        | OpCode::SetInt
+        | OpCode::SetBool
+        | OpCode::SetNullableInt
+        | OpCode::SetNullableBool => Operand::Var(get_var_index(bytecode, &mut index)?),
+        OpCode::BindParam => Operand::Var(read_ptr_size(bytecode, &mut index)?),
+        OpCode::TailCall => Operand::Call {
+            address: read_ptr_size(bytecode, &mut index)?,
+            args_size: read_ptr_size(bytecode, &mut index)?,
+        },
+        _ => return None,
+    })
+}
+
+// Reads the entries of a line table between two offsets, each of which is the offset of an instruction followed by its
+// line and column.
+fn read_line_table(
+    bytecode: &[u8],
+    mut index: usize,
+    end: usize,
+    locations: &mut HashMap<usize, (usize, usize)>,
+) {
+    while index < end {
+        let (Some(offset), Some(line), Some(col)) = (
+            read_ptr_size(bytecode, &mut index),
+            read_ptr_size(bytecode, &mut index),
+            read_ptr_size(bytecode, &mut index),
+        ) else {
+            return;
+        };
+        locations.insert(offset, (line, col));
+    }
+}
+
+/// Reads a compiled program file written by `compiler::serialize_bytecode`, giving the bytecode that the VM runs.
+///
+/// # Errors
+/// Returns a `CorruptBytecode` error if the file isn't in the format, uses another version of it, or has sections that
+/// are cut off, repeated, or don't fit together.
+#[allow(clippy::result_large_err)] // The error is reported like every other log.
+pub fn load_bytecode(file: &[u8]) -> Result<Vec<u8>, Log> {
+    let corrupt = |reason: &str| Log {
+        log_type: LogType::Error(ErrorType::CorruptBytecode(reason.to_string())),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    };
+    if file.len() < FILE_HEADER_SIZE || file[..4] != MAGIC {
+        return Err(corrupt(
+            "it doesn't start with the magic bytes of a compiled program",
+        ));
+    }
+    let version: u16 = u16::from_le_bytes([file[4], file[5]]);
+    if version != FORMAT_VERSION {
+        return Err(corrupt(&format!(
+            "it uses version {version} of the format, but only version {FORMAT_VERSION} is supported"
+        )));
+    }
+    let ptr_size: u8 = file[6];
+    let mut sections: [Option<&[u8]>; Section::ALL.len()] = [None; Section::ALL.len()];
+    let mut index: usize = FILE_HEADER_SIZE;
+    for _ in 0..file[7] {
+        let Some(header) = file.get(index..index + 5) else {
+            return Err(corrupt("a section header is cut off"));
+        };
+        let Some(section) = Section::from_u8(header[0]) else {
+            return Err(corrupt(&format!(
+                "it has a section of unknown kind {}",
+                header[0]
+            )));
+        };
+        let length: usize =
+            u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        let Some(contents) = file.get(index + 5..index + 5 + length) else {
+            return Err(corrupt(&format!(
+                "the {} section is cut off",
+                section.name()
+            )));
+        };
+        let slot: &mut Option<&[u8]> = &mut sections[section as usize - 1];
+        if slot.is_some() {
+            return Err(corrupt(&format!(
+                "it has more than one {} section",
+                section.name()
+            )));
+        }
+        *slot = Some(contents);
+        index += 5 + length;
+    }
+    if index != file.len() {
+        return Err(corrupt("there are bytes after the last section"));
+    }
+    let [constant_pool, code, debug_info] = sections;
+    let Some(code) = code else {
+        return Err(corrupt("it has no code section"));
+    };
+    if code.len() < HEADER_SIZE || code_start(code) > code.len() {
+        return Err(corrupt(
+            "the bytecode header in the code section is cut off",
+        ));
+    }
+    if code[0] != ptr_size {
+        return Err(corrupt(
+            "the pointer size of the file doesn't match the code section",
+        ));
+    }
+    // The VM reads little-endian bytecode, so the instructions of a big-endian program are converted back.
+    let in_memory_order = |instructions: &[u8]| {
+        if code[1] & BIG_ENDIAN_FLAG == 0 {
+            instructions.to_vec()
+        } else {
+            swap_byte_order(instructions, ptr_size.into(), true)
+        }
+    };
+    let code: Vec<u8> = [
+        &code[..code_start(code)],
+        &in_memory_order(&code[code_start(code)..])[..],
+    ]
+    .concat();
+    let constant_pool: Vec<u8> = in_memory_order(constant_pool.unwrap_or_default());
+    if !only_contains(&constant_pool, &[OpCode::Constant], ptr_size) {
+        return Err(corrupt(
+            "the constant pool section has something other than constants",
+        ));
+    }
+    let debug_info: Vec<u8> = in_memory_order(debug_info.unwrap_or_default());
+    if !only_contains(
+        &debug_info,
+        &[OpCode::FunctionName, OpCode::LineTable],
+        ptr_size,
+    ) {
+        return Err(corrupt(
+            "the debug info section has something other than function names and line tables",
+        ));
+    }
+    Ok([code, constant_pool, debug_info].concat())
+}
+
+// Returns whether or not the section is made up of complete instructions with one of the opcodes.
+fn only_contains(section: &[u8], ops: &[OpCode], ptr_size: u8) -> bool {
+    let mut offset: usize = 0;
+    while offset < section.len() {
+        let length: Option<usize> = ops
+            .iter()
+            .find(|op| section[offset] == **op as u8)
+            .and_then(|op| op.operand_length(ptr_size.into(), &section[offset + 1..]))
+            .filter(|length| offset + 1 + length <= section.len());
+        let Some(length) = length else {
+            return false;
+        };
+        offset += 1 + length;
+    }
+    true
+}
+
+/// Runs the bytecode without any program arguments.
+#[allow(clippy::must_use_candidate)] // Compiling using krustc requires that this not be annotated as must use.
+pub fn run(bytecode: &[u8]) -> (Vec<String>, Vec<Log>) {
+    match precompile(bytecode) {
+        Ok(program) => {
+            let mut state: State = State::default();
+            let logs: Vec<Log> = execute(
+                bytecode,
+                &program,
+                &mut Natives::default(),
+                &mut state,
+                None,
+                None,
+            );
+            (state.output.iter().map(ToString::to_string).collect(), logs)
+        }
+        Err(logs) => (Vec::new(), logs),
+    }
+}
+
+// Runs a precompiled program from where it was paused, or from the start if the state is empty. If the fuel runs out
+// or an instruction on a line with a breakpoint is reached, the run is paused before the next instruction.
+#[allow(clippy::too_many_lines)] // The limits of the run are checked after each instruction.
+fn execute(
+    bytecode: &[u8],
+    program: &Program,
+    natives: &mut Natives,
+    state: &mut State,
+    mut fuel: Option<u64>,
+    breakpoints: Option<&HashSet<usize>>,
+) -> Vec<Log> {
+    let mut logs: Vec<Log> = Vec::new();
+    // A run that is paused at a breakpoint continues past it.
+    let mut resumed: bool = state.paused_at.is_some();
+    let mut index: usize = state
+        .paused_at
+        .take()
+        .unwrap_or_else(|| code_start(bytecode));
+    // The position of the instruction at the index in the decoded instructions.
+    let mut position: Option<usize> = program.position(index);
+    while index < bytecode.len() {
+        if !resumed && breakpoint_line(program, breakpoints, index).is_some() {
+            state.paused_at = Some(index);
+            return logs;
+        }
+        resumed = false;
+        if let Some(fuel) = &mut fuel {
+            if *fuel == 0 {
+                state.paused_at = Some(index);
+                return logs;
+            }
+            *fuel -= 1;
+        }
+        let offset: usize = index;
+        let instruction: Option<Instruction> =
+            position.map(|position| program.instructions[position]);
+        let curr_op: Option<OpCode> = instruction
+            .map(|instruction| instruction.op)
+            .or_else(|| FromPrimitive::from_u8(bytecode[index]));
+        index += 1;
+
+        if let Some(op) = curr_op {
+            let printed: usize = state.output.len();
+            let started: Option<Instant> = natives.profiling.then(Instant::now);
+            let mut error: bool = match instruction {
+                Some(instruction) if !matches!(instruction.operand, Operand::Encoded) => {
+                    run_decoded(
+                        instruction,
+                        &mut state.stack,
+                        &mut index,
+                        &mut logs,
+                        &mut state.var_list,
+                        &state.frames,
+                    )
+                }
+                _ => match_op(
+                    op,
+                    bytecode,
+                    &mut state.stack,
+                    &mut index,
+                    &mut state.output,
+                    &mut logs,
+                    &mut state.var_list,
+                    &mut state.heap,
+                    &mut state.frames,
+                    program,
+                    natives,
+                ),
+            };
+            if let Some(started) = started {
+                if state.profile.len() < bytecode.len() {
+                    state.profile.resize(bytecode.len(), (0, Duration::ZERO));
+                }
+                state.profile[offset].0 += 1;
+                state.profile[offset].1 += started.elapsed();
+            }
+            // A failed write, such as to a closed pipe, doesn't stop the program, as its output is still kept.
+            for value in &state.output[printed..] {
+                let _ = writeln!(natives.output, "{value}");
+            }
+            if !error && state.stack.len() > natives.config.max_stack_bytes {
+                logs.push(Log {
+                    log_type: LogType::Error(ErrorType::VmStackOverflow(
+                        offset,
+                        natives.config.max_stack_bytes,
+                    )),
+                    line_and_col: None,
+                    span: None,
+                    notes: Vec::new(),
+                });
+                error = true;
+            }
+            if !error && state.heap.should_collect(natives.config.max_heap_objects) {
+                state.heap.collect(&state.stack);
+                if state.heap.live() > natives.config.max_heap_objects {
+                    logs.push(Log {
+                        log_type: LogType::Error(ErrorType::OutOfMemory(
+                            natives.config.max_heap_objects,
+                        )),
+                        line_and_col: None,
+                        span: None,
+                        notes: Vec::new(),
+                    });
+                    error = true;
+                }
+            }
+            if error {
+                locate_errors(&mut logs, program.locations.get(&offset).copied());
+                // Errors in the main program only get a stack trace if they are panics.
+                if !state.frames.is_empty() || matches!(op, OpCode::Panic | OpCode::Assert) {
+                    let line_and_col: Option<(usize, usize)> =
+                        logs.first().and_then(|log| log.line_and_col);
+                    logs.append(&mut frame_trace(bytecode, &state.frames, line_and_col));
+                }
+                if let Some(sink) = &mut natives.sink {
+                    sink.report_all(&logs);
+                    return logs;
+                }
+                for log in &logs {
+                    let _ = match &natives.source {
+                        Some(source) => writeln!(natives.errors, "{}", source.describe(log, natives.render)),
+                        None => writeln!(natives.errors, "{}", log.render(None, natives.render)),
+                    };
+                }
+                return logs;
+            }
+        } else {
+            logs.push(Log {
+                log_type: LogType::Error(ErrorType::FatalError),
+                line_and_col: None,
+                span: None,
+                notes: Vec::new(),
+            });
+            return logs;
+        }
+        position = program.next_position(position, index);
+    }
+    logs
+}
+
+// Adds the runs of an instruction to the profile of its opcode.
+fn add_op_time(ops: &mut Vec<OpProfile>, op: OpCode, count: u64, time: Duration) {
+    if let Some(other) = ops.iter_mut().find(|other| other.op == op) {
+        other.count += count;
+        other.time += time;
+    } else {
+        ops.push(OpProfile { op, count, time });
+    }
+}
+
+// Adds the runs of an instruction to the profile of the function it is in, which is None for the main program. The
+// runs of the first instruction of a function are its calls, and the main program is run once.
+fn add_function_time(
+    functions: &mut Vec<FunctionProfile>,
+    name: Option<String>,
+    is_entry: bool,
+    count: u64,
+    time: Duration,
+) {
+    let position: usize =
+        if let Some(position) = functions.iter().position(|other| other.name == name) {
+            position
+        } else {
+            functions.push(FunctionProfile {
+                calls: u64::from(name.is_none()),
+                name,
+                instructions: 0,
+                time: Duration::ZERO,
+            });
+            functions.len() - 1
+        };
+    let function: &mut FunctionProfile = &mut functions[position];
+    function.instructions += count;
+    function.time += time;
+    if is_entry {
+        function.calls += count;
+    }
+}
+
+// Gets the line of the instruction at the offset if the line has a breakpoint.
+fn breakpoint_line(
+    program: &Program,
+    breakpoints: Option<&HashSet<usize>>,
+    offset: usize,
+) -> Option<usize> {
+    let breakpoints: &HashSet<usize> = breakpoints?;
+    let (line, _): (usize, usize) = *program.locations.get(&offset)?;
+    breakpoints.contains(&line).then_some(line)
+}
+
+// Gives the errors from an instruction the line and column of the instruction, if the line table has them. Errors that
+// already have a location keep it.
+fn locate_errors(logs: &mut [Log], line_and_col: Option<(usize, usize)>) {
+    for log in logs {
+        if matches!(log.log_type, LogType::Error(_)) && log.line_and_col.is_none() {
+            log.line_and_col = line_and_col;
+        }
+    }
+}
+
+// Handle any errors immediatly present in the bytecode.
+fn handle_errors<'o, 'e>(
+    bytecode: &[u8],
+    output: &'o mut Vec<String>,
+    logs: &'e mut Vec<Log>,
+) -> Option<(&'o Vec<String>, &'e Vec<Log>)> {
+    if bytecode.len() < HEADER_SIZE || bytecode.len() < code_start(bytecode) {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return Some((output, logs));
+    }
+    let isa_level: Option<IsaLevel> = FromPrimitive::from_u8(bytecode[2]);
+    if isa_level.is_none() {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::UnsupportedIsaLevel(bytecode[2])),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return Some((output, logs));
+    }
+    let ptr_size: usize = bytecode[0] as usize;
+    if ptr_size * 8
+        > usize::BITS
+            .try_into()
+            .expect("max value of usize must be less than the number of bits")
+    {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::CompiledForDifferentTarget(ptr_size * 8)),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return Some((output, logs));
+    }
+    None
+}
+
+// Runs an instruction whose operand was decoded ahead of the run. Returns whether or not there was an error.
+fn run_decoded(
+    instruction: Instruction,
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    var_list: &mut Vec<usize>,
+    frames: &[Frame],
+) -> bool {
+    *index = instruction.next;
+    match (instruction.op, instruction.operand) {
+        (OpCode::PushInt, Operand::Int(value)) => value.push_to_stack(stack),
+        (OpCode::PushByte, Operand::Byte(value)) => stack.push(value),
+        (OpCode::Dup, Operand::Byte(size)) => dup_value(usize::from(size), stack, logs),
+        (OpCode::GetInt, Operand::Var(var)) => read_var::<i32>(var, stack, logs, var_list),
+        (OpCode::GetBool, Operand::Var(var)) => read_var::<bool>(var, stack, logs, var_list),
+        (OpCode::GetNullableInt, Operand::Var(var)) => {
+            read_var::<Nullable<i32>>(var, stack, logs, var_list);
+        }
+        (OpCode::GetNullableBool, Operand::Var(var)) => {
+            read_var::<Nullable<bool>>(var, stack, logs, var_list);
+        }
+        (OpCode::SetInt, Operand::Var(var)) => write_var::<i32>(var, stack, logs, var_list),
+        (OpCode::SetBool, Operand::Var(var)) => write_var::<bool>(var, stack, logs, var_list),
+        (OpCode::SetNullableInt, Operand::Var(var)) => {
+            write_var::<Nullable<i32>>(var, stack, logs, var_list);
+        }
+        (OpCode::SetNullableBool, Operand::Var(var)) => {
+            write_var::<Nullable<bool>>(var, stack, logs, var_list);
+        }
+        (OpCode::BindParam, Operand::Var(offset)) => bind_param_at(offset, logs, var_list, frames),
+        (OpCode::TailCall, Operand::Call { address, args_size }) => {
+            tail_call_to(address, args_size, stack, index, logs, var_list, frames);
+        }
+        _ => logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        }),
+    }
+    is_error(logs)
+}
+
+// Runs a function given a specific op code.
+#[allow(clippy::too_many_arguments)] // Each opcode needs different parts of the VM state.
+#[allow(clippy::too_many_lines)] // Necessary for all the different opcodes.
+fn match_op(
+    op: OpCode,
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    output: &mut Vec<Value>,
+    logs: &mut Vec<Log>,
+    var_list: &mut Vec<usize>,
+    heap: &mut Heap,
+    frames: &mut Vec<Frame>,
+    program: &Program,
+    natives: &mut Natives,
+) -> bool {
+    match op {
+        OpCode::PushInt => push::<i32>(bytecode, stack, index, logs),
+        OpCode::PushByte => push::<u8>(bytecode, stack, index, logs),
+        OpCode::PushString => push_string(bytecode, stack, index, logs, heap, &program.strings),
+        OpCode::LoadConst => load_const(bytecode, stack, index, logs, heap, program),
+        OpCode::PopInt => pop::<i32>(stack, logs),
+        OpCode::PopByte => pop::<u8>(stack, logs),
+        OpCode::Dup => dup(bytecode, stack, index, logs),
+        OpCode::PrintInt => print::<i32>(stack, output, logs),
+        OpCode::PrintBool => print::<bool>(stack, output, logs),
+        OpCode::PrintString => print_string(stack, output, logs, heap),
+        OpCode::PrintNull => print_null(output),
+        OpCode::PrintNullableInt => print::<Nullable<i32>>(stack, output, logs),
+        OpCode::PrintNullableBool => print::<Nullable<bool>>(stack, output, logs),
+
+        OpCode::AllocInt => alloc::<i32>(stack, var_list),
+        OpCode::AllocBool => alloc::<bool>(stack, var_list),
+        OpCode::AllocNullableInt => alloc::<Nullable<i32>>(stack, var_list),
+        OpCode::AllocNullableBool => alloc::<Nullable<bool>>(stack, var_list),
+        OpCode::GetInt => get::<i32>(bytecode, stack, index, logs, var_list),
+        OpCode::GetBool => get::<bool>(bytecode, stack, index, logs, var_list),
+        OpCode::GetNullableInt => get::<Nullable<i32>>(bytecode, stack, index, logs, var_list),
+        OpCode::GetNullableBool => get::<Nullable<bool>>(bytecode, stack, index, logs, var_list),
+        OpCode::SetInt => set::<i32>(bytecode, stack, index, logs, var_list),
+        OpCode::SetBool => set::<bool>(bytecode, stack, index, logs, var_list),
+        OpCode::SetNullableInt => set::<Nullable<i32>>(bytecode, stack, index, logs, var_list),
+        OpCode::SetNullableBool => set::<Nullable<bool>>(bytecode, stack, index, logs, var_list),
+
+        OpCode::Unwrap => unwrap(stack, logs),
+
+        OpCode::IntToBool => cast_int_to_bool(stack, logs),
+        OpCode::BoolToInt => cast_bool_to_int(stack),
+
+        OpCode::MinusInt => minus::<i32>(stack, logs),
+        OpCode::AddInt => add::<i32>(stack, logs),
+        OpCode::SubtractInt => subtract::<i32>(stack, logs),
+        OpCode::MultiplyInt => multiply::<i32>(stack, logs),
+        OpCode::DivideInt => divide::<i32>(stack, logs),
+        OpCode::ModuloInt => modulo::<i32>(stack, logs),
+        OpCode::RegisterOp => register_op(bytecode, stack, index, logs, var_list),
+
+        OpCode::AbsInt => abs::<i32>(stack, logs),
+        OpCode::MinInt => min::<i32>(stack, logs),
+        OpCode::MaxInt => max::<i32>(stack, logs),
+        OpCode::PowInt => pow::<i32>(stack, logs),
+        OpCode::SqrtInt => sqrt::<i32>(stack, logs),
+        OpCode::ClampInt => clamp::<i32>(stack, logs),
+
+        OpCode::LenString => len(stack, logs, heap),
+        OpCode::SubstringString => substring(stack, logs, heap),
+        OpCode::ContainsString => contains(stack, logs, heap),
+        OpCode::IntToString => int_to_string(stack, logs, heap),
+        OpCode::ParseInt => parse_int(stack, logs, heap),
+
+        OpCode::NewMap => alloc_object(stack, logs, heap, HeapObject::Map(HashMap::new())),
+        OpCode::PrintMap => print_map(bytecode, stack, index, output, logs, heap),
+        OpCode::LenMap => len_map(stack, logs, heap),
+        OpCode::MapInsert => map_insert(bytecode, stack, index, logs, heap),
+        OpCode::MapGet => map_get(bytecode, stack, index, logs, heap, false),
+        OpCode::MapRemove => map_get(bytecode, stack, index, logs, heap, true),
+
+        OpCode::NewList => alloc_object(stack, logs, heap, HeapObject::List(Vec::new())),
+        OpCode::PrintList => print_list(bytecode, stack, index, output, logs, heap),
+        OpCode::PrintStringList => print_string_list(stack, output, logs, heap),
+        OpCode::LenList => len_list(bytecode, stack, index, logs, heap),
+        OpCode::ListPush => list_push(bytecode, stack, index, logs, heap),
+        OpCode::ListPop => list_pop(bytecode, stack, index, logs, heap),
+        OpCode::ListIndex => list_index(bytecode, stack, index, logs, heap),
+        OpCode::Args => push_args(stack, logs, heap, &natives.args),
+
+        OpCode::OkResult => ok_result(bytecode, stack, index, logs, heap),
+        OpCode::ErrResult => err_result(stack, logs, heap),
+        OpCode::IsOkResult => is_ok_result(stack, logs, heap),
+        OpCode::UnwrapResult => unwrap_result(bytecode, stack, index, logs, heap),
+        OpCode::PrintResult => print_result(bytecode, stack, index, output, logs, heap),
+        OpCode::JumpIfOk => jump_if_ok(bytecode, stack, index, logs, heap),
+
+        OpCode::Call => call(
+            bytecode,
+            stack,
+            index,
+            logs,
+            var_list,
+            frames,
+            &program.locations,
+            natives.config.max_call_depth,
+        ),
+        OpCode::TailCall => tail_call(bytecode, stack, index, logs, var_list, frames),
+        OpCode::BindParam => bind_param(bytecode, index, logs, var_list, frames),
+        OpCode::Return => return_from_call(bytecode, stack, index, logs, var_list, frames),
+        OpCode::Halt => *index = bytecode.len(),
+        OpCode::Panic => panic(stack, logs, heap),
+        OpCode::Assert => assert(stack, logs, heap),
+        OpCode::FunctionName | OpCode::Constant | OpCode::LineTable | OpCode::Location => {
+            skip_operand(op, bytecode, index, logs);
+        }
+        OpCode::CallHost => call_host(bytecode, stack, index, logs, natives),
+        OpCode::ReadFile => read_file(stack, logs, heap, natives),
+        OpCode::WriteFile => write_file(stack, logs, heap, natives),
+
+        OpCode::LessInt => less::<i32>(stack, logs),
+        OpCode::LessEqualInt => less_equal::<i32>(stack, logs),
+        OpCode::GreaterInt => greater::<i32>(stack, logs),
+        OpCode::GreaterEqualInt => greater_equal::<i32>(stack, logs),
+
+        OpCode::Not => not(stack, logs),
+
+        OpCode::ComplementInt => complement::<i32>(stack, logs),
+        OpCode::AndInt => and::<i32>(stack, logs),
+        OpCode::AndByte => and::<u8>(stack, logs),
+        OpCode::XorInt => xor::<i32>(stack, logs),
+        OpCode::XorByte => xor::<u8>(stack, logs),
+        OpCode::OrInt => or::<i32>(stack, logs),
+        OpCode::OrByte => or::<u8>(stack, logs),
+
+        OpCode::LeftShiftInt => left_shift::<i32>(stack, logs),
+        OpCode::RightShiftInt => right_shift::<i32>(stack, logs),
+
+        OpCode::EqualityInt => equality::<i32>(stack, logs),
+        OpCode::EqualityByte => equality::<u8>(stack, logs),
+        OpCode::EqualityNullableInt => equality::<Nullable<i32>>(stack, logs),
+        OpCode::EqualityNullableBool => equality::<Nullable<bool>>(stack, logs),
+        OpCode::InequalityInt => inequality::<i32>(stack, logs),
+        OpCode::InequalityByte => inequality::<u8>(stack, logs),
+        OpCode::InequalityNullableInt => inequality::<Nullable<i32>>(stack, logs),
+        OpCode::InequalityNullableBool => inequality::<Nullable<bool>>(stack, logs),
+    }
+    is_error(logs)
+}
+
+// Pushes a value from the bytecode to the stack.
+fn push<T>(bytecode: &[u8], stack: &mut Vec<u8>, index: &mut usize, logs: &mut Vec<Log>)
+where
+    T: StackType,
+{
+    if *index + T::size() > bytecode.len() {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+    for _i in 0..T::size() {
+        stack.push(bytecode[*index]);
+        *index += 1;
+    }
+}
+
+fn pop<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: StackType,
+{
+    let value: Option<T> = T::pop_from_stack(stack);
+    if value.is_none() {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Pushes a copy of the value on top of the stack, whose size is given by the operand.
+fn dup(bytecode: &[u8], stack: &mut Vec<u8>, index: &mut usize, logs: &mut Vec<Log>) {
+    let size: Option<usize> = bytecode.get(*index).map(|size| usize::from(*size));
+    *index += 1;
+    match size {
+        Some(size) => dup_value(size, stack, logs),
+        None => logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        }),
+    }
+}
+
+// Pushes a copy of the value of the given size on top of the stack.
+fn dup_value(size: usize, stack: &mut Vec<u8>, logs: &mut Vec<Log>) {
+    if size <= stack.len() {
+        stack.extend_from_within(stack.len() - size..);
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Pops a value from the stack and adds it to the output.
+fn print<T>(stack: &mut Vec<u8>, output: &mut Vec<Value>, logs: &mut Vec<Log>)
+where
+    T: StackType + Into<Value>,
+{
+    let value: Option<T> = T::pop_from_stack(stack);
+    if let Some(value) = value {
+        print_value(value.into(), output);
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Pushes a string from the bytecode to the heap and pushes its handle to the stack. Strings that were decoded when
+// the program was precompiled are copied instead of being decoded again.
+fn push_string(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    heap: &mut Heap,
+    strings: &HashMap<usize, (String, usize)>,
+) {
+    if let Some((value, length)) = strings.get(index) {
+        *index += length;
+        alloc_object(stack, logs, heap, HeapObject::String(value.clone()));
+        return;
+    }
+    let length: Option<usize> =
+        OpCode::PushString.operand_length(get_ptr_size(bytecode), &bytecode[*index..]);
+    if let Some(length) = length.filter(|length| *index + length <= bytecode.len()) {
+        let value: Option<&str> = std::str::from_utf8(&bytecode[*index + 4..*index + length]).ok();
+        *index += length;
+        if let Some(value) = value {
+            alloc_object(stack, logs, heap, HeapObject::String(value.to_string()));
+            return;
+        }
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::FatalError),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+}
+
+// Pushes a constant from the constant pool by running the push instruction stored in the pool.
+fn load_const(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    heap: &mut Heap,
+    program: &Program,
+) {
+    let constant: Option<&(OpCode, usize)> = bytecode.get(*index..*index + 2).and_then(|bytes| {
+        program
+            .constants
+            .get(usize::from(u16::from_le_bytes([bytes[0], bytes[1]])))
+    });
+    *index += 2;
+    match constant {
+        Some((OpCode::PushInt, start)) => push::<i32>(bytecode, stack, &mut start.clone(), logs),
+        Some((OpCode::PushString, start)) => {
+            push_string(
+                bytecode,
+                stack,
+                &mut start.clone(),
+                logs,
+                heap,
+                &program.strings,
+            );
+        }
+        _ => logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        }),
+    }
+}
+
+// Pops a string handle from the stack and adds the string to the output.
+fn print_string(
+    stack: &mut Vec<u8>,
+    output: &mut Vec<Value>,
+    logs: &mut Vec<Log>,
+    heap: &[HeapObject],
+) {
+    if let Some(value) = pop_string(stack, heap) {
+        print_value(Value::String(value.clone()), output);
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Adds null to the output.
+fn print_null(output: &mut Vec<Value>) {
+    print_value(Value::Null, output);
+}
+
+// Adds a value to the output. It is written to the output stream once the instruction is done.
+fn print_value(value: Value, output: &mut Vec<Value>) {
+    output.push(value);
+}
+
+// Allocates a variable onto the stack.
+fn alloc<T>(stack: &mut Vec<u8>, var_list: &mut Vec<usize>)
+where
+    T: StackType,
+{
+    var_list.push(stack.len());
+    <T>::default().push_to_stack(stack);
+    <T>::default().push_to_stack(stack);
+}
+
+// Gets the value of a variable.
+fn get<T>(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    var_list: &[usize],
+) where
+    T: StackType,
+{
+    match get_var_index(bytecode, index) {
+        Some(var_index) => read_var::<T>(var_index, stack, logs, var_list),
+        None => logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        }),
+    }
+}
+
+// Pushes the value of the variable with the given index.
+fn read_var<T>(var_index: usize, stack: &mut Vec<u8>, logs: &mut Vec<Log>, var_list: &[usize])
+where
+    T: StackType,
+{
+    if var_index < var_list.len() {
+        let var: Option<T> = T::read_from_stack(stack, var_list[var_index]);
+        if let Some(var) = var {
+            var.push_to_stack(stack);
+            return;
+        }
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::FatalError),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+}
+
+// Sets the value of a variable.
+fn set<T>(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    var_list: &[usize],
+) where
+    T: StackType,
+{
+    match get_var_index(bytecode, index) {
+        Some(var_index) => write_var::<T>(var_index, stack, logs, var_list),
+        None => logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        }),
+    }
+}
+
+// Sets the value of the variable with the given index to the value on top of the stack, which is left there.
+fn write_var<T>(var_index: usize, stack: &mut Vec<u8>, logs: &mut Vec<Log>, var_list: &[usize])
+where
+    T: StackType,
+{
+    if var_index < var_list.len() {
+        let popped_value: Option<T> = T::pop_from_stack(stack);
+        if let Some(value) = popped_value {
+            if value.write_to_stack(stack, var_list[var_index]) {
+                value.push_to_stack(stack);
+                return;
+            }
+        }
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::FatalError),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+}
+
+// Removes the null flag from a nullable value. Reports an error if the value is null.
+fn unwrap(stack: &mut Vec<u8>, logs: &mut Vec<Log>) {
+    let error: RuntimeError<u8> = RuntimeError {
+        condition: &(|flag| flag == 0),
+        error: ErrorType::NullUnwrap,
+    };
+    if let Some(flag) = stack.pop() {
+        handle_error(&error, flag, logs);
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Converts an int to a boolean.
+fn cast_int_to_bool(stack: &mut Vec<u8>, logs: &mut Vec<Log>) {
+    let value: Option<i32> = <i32>::pop_from_stack(stack);
+    if let Some(value) = value {
+        (value != 0).push_to_stack(stack);
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Converts a boolean to an int.
+fn cast_bool_to_int(stack: &mut Vec<u8>) {
+    for _i in 0..3 {
+        stack.push(0);
+    }
+}
+
+// Gets the negative of an value.
+fn minus<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    unary(stack, logs, T::neg, None);
+}
+
+// Adds two values.
+fn add<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    binary(stack, logs, T::add, None);
+}
+
+// Subtracts two values.
+fn subtract<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    binary(stack, logs, T::sub, None);
+}
+
+// Multiplies two values.
+fn multiply<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    binary(stack, logs, T::mul, None);
+}
+
+// Divides two values. Reports an error if the second value is zero.
+fn divide<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    binary(
+        stack,
+        logs,
+        T::div,
+        Some(RuntimeError::<(T, T)> {
+            condition: &(|(_a, b)| <T>::is_zero(b)),
+            error: ErrorType::DivideByZero,
+        }),
+    );
+}
+
+// Gets the modulo of two values. Reports an error if the second value is zero.
+fn modulo<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    binary(
+        stack,
+        logs,
+        T::rem,
+        Some(RuntimeError::<(T, T)> {
+            condition: &(|(_a, b)| <T>::is_zero(b)),
+            error: ErrorType::DivideByZero,
+        }),
+    );
+}
+
+// Runs an operation on two ints whose operands are read from the stack, variables, or the bytecode, as given by the
+// operand. Reports an error if the operation is a division or modulo by zero.
+fn register_op(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    var_list: &[usize],
+) {
+    let operand: Option<(OpCode, [(OperandSource, u32); 2])> =
+        bytecode.get(*index..).and_then(read_register_operand);
+    *index += REGISTER_OPERAND_LENGTH;
+    let Some((op, [left, right])) = operand else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return;
+    };
+    // The right operand is on top of the stack.
+    let b: Option<i32> = read_source(right, stack, var_list);
+    let a: Option<i32> = read_source(left, stack, var_list);
+    let (Some(a), Some(b)) = (a, b) else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return;
+    };
+    if matches!(op, OpCode::DivideInt | OpCode::ModuloInt) {
+        let error: RuntimeError<i32> = RuntimeError {
+            condition: &(|b| <i32>::is_zero(b)),
+            error: ErrorType::DivideByZero,
+        };
+        handle_error(&error, b, logs);
+    }
+    match op {
+        OpCode::AddInt => i32::add(a, b).push_to_stack(stack),
+        OpCode::SubtractInt => i32::sub(a, b).push_to_stack(stack),
+        OpCode::MultiplyInt => i32::mul(a, b).push_to_stack(stack),
+        OpCode::DivideInt => i32::div(a, b).push_to_stack(stack),
+        OpCode::ModuloInt => i32::rem(a, b).push_to_stack(stack),
+        OpCode::MinInt => a.min(b).push_to_stack(stack),
+        OpCode::MaxInt => a.max(b).push_to_stack(stack),
+        OpCode::PowInt => <i32 as NumType>::pow(a, b).push_to_stack(stack),
+        OpCode::AndInt => (a & b).push_to_stack(stack),
+        OpCode::XorInt => (a ^ b).push_to_stack(stack),
+        OpCode::OrInt => (a | b).push_to_stack(stack),
+        OpCode::LeftShiftInt => i32::shift(a, b).push_to_stack(stack),
+        OpCode::RightShiftInt => i32::shift(a, i32::neg(b)).push_to_stack(stack),
+        OpCode::LessInt => i32::les(a, b).push_to_stack(stack),
+        OpCode::LessEqualInt => i32::leq(a, b).push_to_stack(stack),
+        OpCode::GreaterInt => i32::grt(a, b).push_to_stack(stack),
+        OpCode::GreaterEqualInt => i32::geq(a, b).push_to_stack(stack),
+        OpCode::EqualityInt => (a == b).push_to_stack(stack),
+        OpCode::InequalityInt => (a != b).push_to_stack(stack),
+        _ => panic!("the operand was read as an operation on two ints"),
+    }
+}
+
+// Gets an operand of a register instruction, popping it if it's on the stack.
+fn read_source(
+    (source, value): (OperandSource, u32),
+    stack: &mut Vec<u8>,
+    var_list: &[usize],
+) -> Option<i32> {
+    match source {
+        OperandSource::Stack => i32::pop_from_stack(stack),
+        OperandSource::Register => {
+            let var: usize = *var_list.get(usize::try_from(value).ok()?)?;
+            i32::read_from_stack(stack, var)
+        }
+        OperandSource::Immediate => Some(value.cast_signed()),
+    }
+}
+
+// Gets the absolute value of a value.
+fn abs<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    unary(stack, logs, T::abs, None);
+}
+
+// Gets the smaller of two values.
+fn min<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    binary(
+        stack,
+        logs,
+        |a: T, b: T| if T::les(b, a) { b } else { a },
+        None,
+    );
+}
+
+// Gets the larger of two values.
+fn max<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    binary(
+        stack,
+        logs,
+        |a: T, b: T| if T::grt(b, a) { b } else { a },
+        None,
+    );
+}
+
+// Raises a value to the power of another value.
+fn pow<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    binary(stack, logs, T::pow, None);
+}
+
+// Gets the square root of a value. Reports an error if the value is negative.
+fn sqrt<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    unary(
+        stack,
+        logs,
+        T::sqrt,
+        Some(RuntimeError::<T> {
+            condition: &(|a| <T>::is_negative(a)),
+            error: ErrorType::NegativeSquareRoot,
+        }),
+    );
+}
+
+// Restricts a value to be between a lower and an upper bound. The lower bound is prioritized if the bounds overlap.
+fn clamp<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    let upper: Option<T> = T::pop_from_stack(stack);
+    let lower: Option<T> = T::pop_from_stack(stack);
+    let value: Option<T> = T::pop_from_stack(stack);
+    if let (Some(value), Some(lower), Some(upper)) = (value, lower, upper) {
+        let value: T = if T::grt(value, upper) { upper } else { value };
+        let value: T = if T::les(value, lower) { lower } else { value };
+        value.push_to_stack(stack);
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Gets the number of characters in a string.
+fn len(stack: &mut Vec<u8>, logs: &mut Vec<Log>, heap: &[HeapObject]) {
+    let length: Option<i32> =
+        pop_string(stack, heap).and_then(|value| i32::try_from(value.chars().count()).ok());
+    if let Some(length) = length {
+        length.push_to_stack(stack);
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Gets the characters of a string from a start index up to but not including an end index. Reports an error if
+// the indices are not in order or are outside the string.
+fn substring(stack: &mut Vec<u8>, logs: &mut Vec<Log>, heap: &mut Heap) {
+    let error: RuntimeError<(usize, i32, i32)> = RuntimeError {
+        condition: &(|(length, start, end)| {
+            start < 0 || end < start || usize::try_from(end).map_or(true, |end| end > length)
+        }),
+        error: ErrorType::SubstringOutOfRange,
+    };
+    let end: Option<i32> = i32::pop_from_stack(stack);
+    let start: Option<i32> = i32::pop_from_stack(stack);
+    let value: Option<String> = pop_string(stack, heap).cloned();
+    if let (Some(value), Some(start), Some(end)) = (value, start, end) {
+        handle_error(&error, (value.chars().count(), start, end), logs);
+        if is_error(logs) {
+            return;
+        }
+        let start: usize = usize::try_from(start).expect("checked by runtime error");
+        let end: usize = usize::try_from(end).expect("checked by runtime error");
+        let value: String = value.chars().skip(start).take(end - start).collect();
+        alloc_object(stack, logs, heap, HeapObject::String(value));
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Checks whether or not a string contains another string.
+fn contains(stack: &mut Vec<u8>, logs: &mut Vec<Log>, heap: &[HeapObject]) {
+    let pattern: Option<&String> = pop_string(stack, heap);
+    let value: Option<&String> = pop_string(stack, heap);
+    if let (Some(value), Some(pattern)) = (value, pattern) {
+        value.contains(pattern.as_str()).push_to_stack(stack);
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Pops an int from the stack and pushes the handle of a string with its decimal representation.
+fn int_to_string(stack: &mut Vec<u8>, logs: &mut Vec<Log>, heap: &mut Heap) {
+    if let Some(value) = i32::pop_from_stack(stack) {
+        alloc_object(stack, logs, heap, HeapObject::String(value.to_string()));
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Pops a string handle from the stack and pushes the handle of a result with the int the string represents. The
+// result is an error if the string is not an int in decimal, with an optional sign, that fits in 32 bits.
+fn parse_int(stack: &mut Vec<u8>, logs: &mut Vec<Log>, heap: &mut Heap) {
+    let Some(text) = pop_string(stack, heap) else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return;
+    };
+    let result: Result<Vec<u8>, String> = match text.parse::<i32>() {
+        Ok(value) => {
+            let mut bytes: Vec<u8> = Vec::new();
+            value.push_to_stack(&mut bytes);
+            Ok(bytes)
+        }
+        Err(_) => Err(format!("\"{text}\" is not a valid int.")),
+    };
+    alloc_object(stack, logs, heap, HeapObject::Result(result));
+}
+
+// Compares two values with '<'.
+fn less<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    binary(stack, logs, T::les, None);
+}
+
+// Compares two values with '<='.
+fn less_equal<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    binary(stack, logs, T::leq, None);
+}
+
+// Compares two values with '>'.
+fn greater<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    binary(stack, logs, T::grt, None);
+}
+
+// Compares two values with '>='.
+fn greater_equal<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType,
+{
+    binary(stack, logs, T::geq, None);
+}
+
+// Negates a bool.
+fn not(stack: &mut Vec<u8>, logs: &mut Vec<Log>) {
+    unary(stack, logs, |a: u8| a ^ 1, None);
+}
+
+// Finds the complement of an value.
+fn complement<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType + Not,
+    <T as Not>::Output: StackType,
+{
+    unary(stack, logs, T::not, None);
+}
+
+// Bitwise ands two values.
+fn and<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType + BitAnd,
+    <T as BitAnd>::Output: StackType,
+{
+    binary(stack, logs, T::bitand, None);
+}
+
+// Bitwise xors two values.
+fn xor<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType + BitXor,
+    <T as BitXor>::Output: StackType,
+{
+    binary(stack, logs, T::bitxor, None);
+}
+
+// Bitwise ors two values.
+fn or<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: NumType + BitOr,
+    <T as BitOr>::Output: StackType,
+{
+    binary(stack, logs, T::bitor, None);
+}
+// Left shifts a value by another value.
+fn left_shift<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: IntegralType,
+{
+    binary(stack, logs, T::shift, None);
+}
+
+// Right shifts a value by another value.
+fn right_shift<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: IntegralType,
+{
+    binary(stack, logs, |a: T, b: T| T::shift(a, <T>::neg(b)), None);
+}
+
+// Checks if two values are equal.
+fn equality<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: StackType,
+{
+    binary(stack, logs, <T>::eq, None);
+}
+
+// Checks if two values are not equal.
+fn inequality<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
+where
+    T: StackType,
+{
+    binary(stack, logs, <T>::ineq, None);
+}
+
+// Performs a unary operation.
+fn unary<F, T, TOut>(
+    stack: &mut Vec<u8>,
+    logs: &mut Vec<Log>,
+    func: F,
+    error: Option<RuntimeError<T>>,
+) where
+    F: Fn(T) -> TOut,
+    T: StackType,
+    TOut: StackType,
+{
+    let value: Option<T> = T::pop_from_stack(stack);
+    if let Some(value) = value {
+        if let Some(error) = error {
+            handle_error(&error, value, logs);
+        }
+        let value: TOut = func(value);
+        value.push_to_stack(stack);
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Performs a binary operation.
+fn binary<F, T1, T2, TOut>(
+    stack: &mut Vec<u8>,
+    logs: &mut Vec<Log>,
+    func: F,
+    error: Option<RuntimeError<(T1, T2)>>,
+) where
+    F: Fn(T1, T2) -> TOut,
+    T1: StackType,
+    T2: StackType,
+    TOut: StackType,
+{
+    let b: Option<T2> = T2::pop_from_stack(stack);
+    let a: Option<T1> = T1::pop_from_stack(stack);
+    let mut fail: bool = true;
+    if let Some(a) = a {
+        if let Some(b) = b {
+            fail = false;
+            if let Some(error) = error {
+                handle_error(&error, (a, b), logs);
+            }
+            let c: TOut = func(a, b);
+            c.push_to_stack(stack);
+        }
+    }
+    if fail {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
     }
 }
 
-fn pop<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: StackType,
-{
-    let value: Option<T> = T::pop_from_stack(stack);
-    if value.is_none() {
+// Handles runtime errors. The error is given its line and column from the line table once the instruction is done.
+fn handle_error<T>(error: &RuntimeError<T>, value: T, logs: &mut Vec<Log>) {
+    if (error.condition)(value) {
+        logs.push(Log {
+            log_type: LogType::Error(error.error.clone()),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Adds an object to the heap and pushes its handle to the stack.
+fn alloc_object(stack: &mut Vec<u8>, logs: &mut Vec<Log>, heap: &mut Heap, object: HeapObject) {
+    if let Ok(handle) = i32::try_from(heap.alloc(object)) {
+        handle.push_to_stack(stack);
+    } else {
         logs.push(Log {
             log_type: LogType::Error(ErrorType::FatalError),
             line_and_col: None,
+            span: None,
+            notes: Vec::new(),
         });
     }
 }
 
-// Pops a value from the stack and adds it to the output.
-fn print<T>(stack: &mut Vec<u8>, output: &mut Vec<String>, logs: &mut Vec<Log>)
-where
-    T: StackType,
-{
-    let value: Option<T> = T::pop_from_stack(stack);
-    if let Some(value) = value {
-        println!("{value}");
-        output.push(format!("{value}"));
+// Pops a handle from the stack and gets the index of the object it refers to.
+fn pop_handle(stack: &mut Vec<u8>, heap: &[HeapObject]) -> Option<usize> {
+    let handle: usize = usize::try_from(i32::pop_from_stack(stack)?).ok()?;
+    if handle < heap.len() {
+        Some(handle)
+    } else {
+        None
+    }
+}
+
+// Pops a string handle from the stack and gets the string it refers to.
+fn pop_string<'h>(stack: &mut Vec<u8>, heap: &'h [HeapObject]) -> Option<&'h String> {
+    match &heap[pop_handle(stack, heap)?] {
+        HeapObject::String(value) => Some(value),
+        HeapObject::Map(_) | HeapObject::List(_) | HeapObject::Result(_) => None,
+    }
+}
+
+// Pops a map handle from the stack and gets the map it refers to.
+fn pop_map<'h>(stack: &mut Vec<u8>, heap: &'h mut [HeapObject]) -> Option<(i32, &'h mut Map)> {
+    let handle: usize = pop_handle(stack, heap)?;
+    match &mut heap[handle] {
+        HeapObject::Map(map) => Some((i32::try_from(handle).ok()?, map)),
+        HeapObject::String(_) | HeapObject::List(_) | HeapObject::Result(_) => None,
+    }
+}
+
+// Pops a list handle from the stack and gets the list it refers to.
+fn pop_list<'h>(stack: &mut Vec<u8>, heap: &'h mut [HeapObject]) -> Option<(i32, &'h mut Vec<u8>)> {
+    let handle: usize = pop_handle(stack, heap)?;
+    match &mut heap[handle] {
+        HeapObject::List(list) => Some((i32::try_from(handle).ok()?, list)),
+        HeapObject::String(_) | HeapObject::Map(_) | HeapObject::Result(_) => None,
+    }
+}
+
+// Pops a result handle from the stack and gets the result it refers to.
+fn pop_result<'h>(
+    stack: &mut Vec<u8>,
+    heap: &'h [HeapObject],
+) -> Option<&'h Result<Vec<u8>, String>> {
+    match &heap[pop_handle(stack, heap)?] {
+        HeapObject::Result(result) => Some(result),
+        HeapObject::String(_) | HeapObject::Map(_) | HeapObject::List(_) => None,
+    }
+}
+
+// Pops the given number of bytes from the stack.
+fn pop_bytes(stack: &mut Vec<u8>, size: usize) -> Option<Vec<u8>> {
+    if stack.len() < size {
+        None
+    } else {
+        Some(stack.split_off(stack.len() - size))
+    }
+}
+
+// Reads the sizes of the keys and values of a map from the bytecode.
+fn get_map_sizes(bytecode: &[u8], index: &mut usize) -> Option<(usize, usize)> {
+    let sizes: &[u8] = bytecode.get(*index..*index + 2)?;
+    *index += 2;
+    Some((sizes[0] as usize, sizes[1] as usize))
+}
+
+// Converts the bytes of a map key or value or a list element to a number used to sort and print it. Ints use 4 bytes and bools use 1.
+fn element_value(bytes: &[u8]) -> i64 {
+    if let Ok(bytes) = bytes.try_into() {
+        i64::from(i32::from_le_bytes(bytes))
+    } else {
+        i64::from(bytes[0] != 0)
+    }
+}
+
+// Gets the value of the bytes of a map key or value, a list element, or the value of a result.
+fn element_to_value(bytes: &[u8]) -> Value {
+    if bytes.len() == 1 {
+        Value::Bool(bytes[0] != 0)
+    } else {
+        Value::Int(
+            i32::try_from(element_value(bytes)).expect("elements are at most the size of an int"),
+        )
+    }
+}
+
+// Pops a map handle from the stack and adds the map to the output, with the entries sorted by key.
+fn print_map(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    output: &mut Vec<Value>,
+    logs: &mut Vec<Log>,
+    heap: &mut [HeapObject],
+) {
+    if get_map_sizes(bytecode, index).is_some() {
+        if let Some((_, map)) = pop_map(stack, heap) {
+            let mut entries: Vec<(&Vec<u8>, &Vec<u8>)> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| element_value(key));
+            let entries: Vec<(Value, Value)> = entries
+                .iter()
+                .map(|(key, value)| (element_to_value(key), element_to_value(value)))
+                .collect();
+            print_value(Value::Map(entries), output);
+            return;
+        }
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::FatalError),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
+}
+
+// Gets the number of entries in a map.
+fn len_map(stack: &mut Vec<u8>, logs: &mut Vec<Log>, heap: &mut [HeapObject]) {
+    let length: Option<i32> =
+        pop_map(stack, heap).and_then(|(_, map)| i32::try_from(map.len()).ok());
+    if let Some(length) = length {
+        length.push_to_stack(stack);
     } else {
         logs.push(Log {
             log_type: LogType::Error(ErrorType::FatalError),
             line_and_col: None,
+            span: None,
+            notes: Vec::new(),
         });
     }
 }
 
-// Allocates a variable onto the stack.
-fn alloc<T>(stack: &mut Vec<u8>, var_list: &mut Vec<usize>)
-where
-    T: StackType,
-{
-    var_list.push(stack.len());
-    <T>::default().push_to_stack(stack);
-    <T>::default().push_to_stack(stack);
+// Inserts a key and value into a map, replacing any value already stored with the key. Pushes the map back to the
+// stack so that insertions can be chained.
+fn map_insert(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    heap: &mut [HeapObject],
+) {
+    if let Some((key_size, value_size)) = get_map_sizes(bytecode, index) {
+        let value: Option<Vec<u8>> = pop_bytes(stack, value_size);
+        let key: Option<Vec<u8>> = pop_bytes(stack, key_size);
+        if let (Some(key), Some(value), Some((handle, map))) = (key, value, pop_map(stack, heap)) {
+            map.insert(key, value);
+            handle.push_to_stack(stack);
+            return;
+        }
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::FatalError),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
 }
 
-// Gets the value of a variable.
-fn get<T>(
-    bytecode: &Vec<u8>,
+// Gets the value stored in a map with a key as a nullable value, which is null if the key is not in the map. If
+// remove is true, the key is also removed from the map.
+fn map_get(
+    bytecode: &[u8],
     stack: &mut Vec<u8>,
     index: &mut usize,
     logs: &mut Vec<Log>,
-    var_list: &mut Vec<usize>,
-) where
-    T: StackType,
-{
-    let var_index: Option<usize> = get_var_index(bytecode, index);
-    if let Some(var_index) = var_index {
-        if var_index < var_list.len() {
-            let var: Option<T> = T::read_from_stack(stack, var_list[var_index]);
-            if let Some(var) = var {
-                var.push_to_stack(stack);
-                return;
+    heap: &mut [HeapObject],
+    remove: bool,
+) {
+    if let Some((key_size, value_size)) = get_map_sizes(bytecode, index) {
+        let key: Option<Vec<u8>> = pop_bytes(stack, key_size);
+        if let (Some(key), Some((_, map))) = (key, pop_map(stack, heap)) {
+            let value: Option<Vec<u8>> = if remove {
+                map.remove(&key)
+            } else {
+                map.get(&key).cloned()
+            };
+            if let Some(value) = value {
+                stack.extend_from_slice(&value);
+                stack.push(1);
+            } else {
+                stack.resize(stack.len() + value_size, 0); // The default value of the type.
+                stack.push(0);
             }
+            return;
         }
     }
     logs.push(Log {
         log_type: LogType::Error(ErrorType::FatalError),
         line_and_col: None,
+        span: None,
+        notes: Vec::new(),
     });
 }
 
-// Sets the value of a variable.
-fn set<T>(
-    bytecode: &Vec<u8>,
+// Reads the size of the elements of a list from the bytecode. Returns None if the size is zero, as every element
+// type has a size.
+fn get_list_size(bytecode: &[u8], index: &mut usize) -> Option<usize> {
+    let size: u8 = *bytecode.get(*index)?;
+    *index += 1;
+    if size == 0 {
+        None
+    } else {
+        Some(size as usize)
+    }
+}
+
+// Pops a list handle from the stack and adds the list to the output.
+fn print_list(
+    bytecode: &[u8],
     stack: &mut Vec<u8>,
     index: &mut usize,
+    output: &mut Vec<Value>,
     logs: &mut Vec<Log>,
-    var_list: &mut Vec<usize>,
-) where
-    T: StackType,
-{
-    let var_index: Option<usize> = get_var_index(bytecode, index);
-    if let Some(var_index) = var_index {
-        if var_index < var_list.len() {
-            let popped_value: Option<T> = T::pop_from_stack(stack);
-            if let Some(value) = popped_value {
-                if value.write_to_stack(stack, var_list[var_index]) {
-                    value.push_to_stack(stack);
-                    return;
-                }
-            }
+    heap: &mut [HeapObject],
+) {
+    if let Some(size) = get_list_size(bytecode, index) {
+        if let Some((_, list)) = pop_list(stack, heap) {
+            let elements: Vec<Value> = list.chunks(size).map(element_to_value).collect();
+            print_value(Value::List(elements), output);
+            return;
         }
     }
     logs.push(Log {
         log_type: LogType::Error(ErrorType::FatalError),
         line_and_col: None,
+        span: None,
+        notes: Vec::new(),
     });
 }
 
-// Converts an int to a boolean.
-fn cast_int_to_bool(stack: &mut Vec<u8>, logs: &mut Vec<Log>) {
-    let value: Option<i32> = <i32>::pop_from_stack(stack);
-    if let Some(value) = value {
-        (value != 0).push_to_stack(stack);
+// Pops the handle of a list of strings and adds the list to the output, with each string quoted.
+fn print_string_list(
+    stack: &mut Vec<u8>,
+    output: &mut Vec<Value>,
+    logs: &mut Vec<Log>,
+    heap: &mut [HeapObject],
+) {
+    let list: Option<Vec<u8>> = pop_list(stack, heap).map(|(_, list)| list.clone());
+    let elements: Option<Vec<Value>> = list.and_then(|list| {
+        list.chunks(4)
+            .map(|handle| {
+                let mut handle: Vec<u8> = handle.to_vec();
+                pop_string(&mut handle, heap).map(|value| Value::String(value.clone()))
+            })
+            .collect()
+    });
+    if let Some(elements) = elements {
+        print_value(Value::List(elements), output);
     } else {
         logs.push(Log {
             log_type: LogType::Error(ErrorType::FatalError),
             line_and_col: None,
+            span: None,
+            notes: Vec::new(),
         });
     }
 }
 
-// Converts a boolean to an int.
-fn cast_bool_to_int(stack: &mut Vec<u8>) {
-    for _i in 0..3 {
-        stack.push(0);
+// Pushes the handle of a new list with the arguments given to the program.
+fn push_args(stack: &mut Vec<u8>, logs: &mut Vec<Log>, heap: &mut Heap, args: &[String]) {
+    let mut list: Vec<u8> = Vec::new();
+    for arg in args {
+        alloc_object(&mut list, logs, heap, HeapObject::String(arg.clone()));
     }
+    alloc_object(stack, logs, heap, HeapObject::List(list));
 }
 
-// Gets the negative of an value.
-fn minus<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: NumType,
-{
-    unary(stack, logs, T::neg);
+// Gets the number of elements in a list.
+fn len_list(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    heap: &mut [HeapObject],
+) {
+    if let Some(size) = get_list_size(bytecode, index) {
+        let length: Option<i32> =
+            pop_list(stack, heap).and_then(|(_, list)| i32::try_from(list.len() / size).ok());
+        if let Some(length) = length {
+            length.push_to_stack(stack);
+            return;
+        }
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::FatalError),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
 }
 
-// Adds two values.
-fn add<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: NumType,
-{
-    binary(stack, logs, T::add, None);
+// Adds an element to the end of a list. Pushes the list back to the stack so that pushes can be chained.
+fn list_push(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    heap: &mut [HeapObject],
+) {
+    if let Some(size) = get_list_size(bytecode, index) {
+        let element: Option<Vec<u8>> = pop_bytes(stack, size);
+        if let (Some(element), Some((handle, list))) = (element, pop_list(stack, heap)) {
+            list.extend_from_slice(&element);
+            handle.push_to_stack(stack);
+            return;
+        }
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::FatalError),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
 }
 
-// Subtracts two values.
-fn subtract<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: NumType,
-{
-    binary(stack, logs, T::sub, None);
+// Removes the last element of a list and pushes it as a nullable value, which is null if the list is empty.
+fn list_pop(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    heap: &mut [HeapObject],
+) {
+    if let Some(size) = get_list_size(bytecode, index) {
+        if let Some((_, list)) = pop_list(stack, heap) {
+            if list.len() >= size {
+                let element: Vec<u8> = list.split_off(list.len() - size);
+                stack.extend_from_slice(&element);
+                stack.push(1);
+            } else {
+                stack.resize(stack.len() + size, 0); // The default value of the type.
+                stack.push(0);
+            }
+            return;
+        }
+    }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::FatalError),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
 }
 
-// Multiplies two values.
-fn multiply<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: NumType,
-{
-    binary(stack, logs, T::mul, None);
+// Gets the element of a list at an index. Reports an error if the index is outside the list.
+fn list_index(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    heap: &mut [HeapObject],
+) {
+    let Some(size) = get_list_size(bytecode, index) else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return;
+    };
+    let error: RuntimeError<(usize, i32)> = RuntimeError {
+        condition: &(|(length, list_index)| {
+            usize::try_from(list_index).map_or(true, |list_index| list_index >= length)
+        }),
+        error: ErrorType::IndexOutOfRange,
+    };
+    let list_index: Option<i32> = i32::pop_from_stack(stack);
+    if let (Some(list_index), Some((_, list))) = (list_index, pop_list(stack, heap)) {
+        handle_error(&error, (list.len() / size, list_index), logs);
+        if is_error(logs) {
+            return;
+        }
+        let start: usize = usize::try_from(list_index).expect("checked by runtime error") * size;
+        stack.extend_from_slice(&list[start..start + size]);
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
 }
 
-// Divides two values. Reports an error if the second value is zero.
-fn divide<T>(bytecode: &Vec<u8>, stack: &mut Vec<u8>, index: &mut usize, logs: &mut Vec<Log>)
-where
-    T: NumType,
-{
-    binary(
-        stack,
-        logs,
-        T::div,
-        Some(RuntimeError::<(T, T)> {
-            condition: &(|(_a, b)| <T>::is_zero(b)),
-            error: ErrorType::DivideByZero,
-            index,
-            bytecode,
-        }),
-    );
+// Reads the size of the value of a result from the bytecode. Errors that were never given a value type have a size of
+// 0.
+fn get_result_size(bytecode: &[u8], index: &mut usize) -> Option<usize> {
+    let size: u8 = *bytecode.get(*index)?;
+    *index += 1;
+    Some(size as usize)
 }
 
-// Gets the modulo of two values. Reports an error if the second value is zero.
-fn modulo<T>(bytecode: &Vec<u8>, stack: &mut Vec<u8>, index: &mut usize, logs: &mut Vec<Log>)
-where
-    T: NumType,
-{
-    binary(
-        stack,
-        logs,
-        T::rem,
-        Some(RuntimeError::<(T, T)> {
-            condition: &(|(_a, b)| <T>::is_zero(b)),
-            error: ErrorType::DivideByZero,
-            index,
-            bytecode,
-        }),
-    );
+// Pops a value from the stack and pushes the handle of an ok result holding it.
+fn ok_result(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    heap: &mut Heap,
+) {
+    let value: Option<Vec<u8>> =
+        get_result_size(bytecode, index).and_then(|size| pop_bytes(stack, size));
+    if let Some(value) = value {
+        alloc_object(stack, logs, heap, HeapObject::Result(Ok(value)));
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
 }
 
-// Compares two values with '<'.
-fn less<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: NumType,
-{
-    binary(stack, logs, T::les, None);
+// Pops a string handle from the stack and pushes the handle of an error result with the string as its message.
+fn err_result(stack: &mut Vec<u8>, logs: &mut Vec<Log>, heap: &mut Heap) {
+    if let Some(message) = pop_string(stack, heap).cloned() {
+        alloc_object(stack, logs, heap, HeapObject::Result(Err(message)));
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Pops a result handle from the stack and pushes whether or not the result is ok.
+fn is_ok_result(stack: &mut Vec<u8>, logs: &mut Vec<Log>, heap: &[HeapObject]) {
+    if let Some(result) = pop_result(stack, heap) {
+        result.is_ok().push_to_stack(stack);
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
+}
+
+// Pops a result handle from the stack and pushes the value of the result. Reports an error with the message of the
+// result if it is an error.
+fn unwrap_result(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    heap: &[HeapObject],
+) {
+    let (Some(size), Some(result)) = (get_result_size(bytecode, index), pop_result(stack, heap))
+    else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return;
+    };
+    let error: RuntimeError<bool> = RuntimeError {
+        condition: &(|is_err| is_err),
+        error: ErrorType::ErrUnwrap(result.clone().err().unwrap_or_default()),
+    };
+    handle_error(&error, result.is_err(), logs);
+    match result {
+        Ok(value) if value.len() == size => stack.extend_from_slice(value),
+        Ok(_) => logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        }),
+        Err(_) => {} // Already reported.
+    }
 }
 
-// Compares two values with '<='.
-fn less_equal<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: NumType,
-{
-    binary(stack, logs, T::leq, None);
+// Pops a result handle from the stack and adds the result to the output.
+fn print_result(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    output: &mut Vec<Value>,
+    logs: &mut Vec<Log>,
+    heap: &[HeapObject],
+) {
+    let size: Option<usize> = get_result_size(bytecode, index);
+    let value: Option<Value> = match (size, pop_result(stack, heap)) {
+        (Some(size), Some(Ok(value))) if size > 0 && value.len() == size => {
+            Some(Value::Result(Ok(Box::new(element_to_value(value)))))
+        }
+        (Some(_), Some(Err(message))) => Some(Value::Result(Err(message.clone()))),
+        _ => None,
+    };
+    if let Some(value) = value {
+        print_value(value, output);
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
 }
 
-// Compares two values with '>'.
-fn greater<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: NumType,
-{
-    binary(stack, logs, T::grt, None);
+// Jumps forward if the result on top of the stack is ok. The result is left on the stack either way.
+fn jump_if_ok(
+    bytecode: &[u8],
+    stack: &[u8],
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    heap: &[HeapObject],
+) {
+    let distance: Option<usize> = read_ptr_size(bytecode, index);
+    let handle: Option<usize> = stack
+        .len()
+        .checked_sub(4)
+        .and_then(|start| i32::read_from_stack(stack, start))
+        .and_then(|handle| usize::try_from(handle).ok());
+    match (distance, handle.and_then(|handle| heap.get(handle))) {
+        (Some(distance), Some(HeapObject::Result(result))) => {
+            if result.is_ok() {
+                *index += distance;
+            }
+        }
+        _ => logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        }),
+    }
 }
 
-// Compares two values with '>='.
-fn greater_equal<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: NumType,
-{
-    binary(stack, logs, T::geq, None);
+// Reads a value with a length of ptr_size from the bytecode.
+fn read_ptr_size(bytecode: &[u8], index: &mut usize) -> Option<usize> {
+    let ptr_size: usize = get_ptr_size(bytecode);
+    let value: &[u8] = bytecode.get(*index..*index + ptr_size)?;
+    *index += ptr_size;
+    let mut bytes: [u8; (usize::BITS / 8) as usize] = [0; (usize::BITS / 8) as usize];
+    for (byte, value) in bytes.iter_mut().zip(value) {
+        *byte = *value;
+    }
+    Some(usize::from_le_bytes(bytes))
 }
 
-// Negates a bool.
-fn not(stack: &mut Vec<u8>, logs: &mut Vec<Log>) {
-    unary(stack, logs, |a: u8| a ^ 1);
+// Calls a function. The arguments are already on the stack, and the function gets its own list of variables. Reports
+// an error if the maximum number of calls are already active. The location of the call is kept for stack traces.
+#[allow(clippy::too_many_arguments)] // The call needs the call stack as well as the operands.
+fn call(
+    bytecode: &[u8],
+    stack: &[u8],
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    var_list: &mut Vec<usize>,
+    frames: &mut Vec<Frame>,
+    locations: &HashMap<usize, (usize, usize)>,
+    max_call_depth: usize,
+) {
+    let call_site: Option<(usize, usize)> = locations.get(&(*index - 1)).copied();
+    let address: Option<usize> = read_ptr_size(bytecode, index);
+    let args_size: Option<usize> = read_ptr_size(bytecode, index);
+    *index += 1; // The size of the return value is only needed by the verifier.
+    let (Some(address), Some(args_size)) = (address, args_size) else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return;
+    };
+    if stack.len() < args_size {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return;
+    }
+    let error: RuntimeError<usize> = RuntimeError {
+        condition: &(|depth| depth >= max_call_depth),
+        error: ErrorType::CallDepthExceeded(max_call_depth),
+    };
+    handle_error(&error, frames.len(), logs);
+    if is_error(logs) {
+        return;
+    }
+    frames.push(Frame {
+        return_index: *index,
+        stack_base: stack.len() - args_size,
+        var_list: std::mem::take(var_list),
+        function: address,
+        call_site,
+    });
+    *index = address;
 }
 
-// Finds the complement of an value.
-fn complement<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: NumType + Not,
-    <T as Not>::Output: StackType,
-{
-    unary(stack, logs, T::not);
+// Calls a function in place of the current one. The arguments replace those of the current frame, which is reused
+// so that the call depth doesn't increase.
+fn tail_call(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    var_list: &mut Vec<usize>,
+    frames: &[Frame],
+) {
+    let address: Option<usize> = read_ptr_size(bytecode, index);
+    let args_size: Option<usize> = read_ptr_size(bytecode, index);
+    *index += 1; // The size of the return value is only needed by the verifier.
+    let (Some(address), Some(args_size)) = (address, args_size) else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return;
+    };
+    tail_call_to(address, args_size, stack, index, logs, var_list, frames);
 }
 
-// Bitwise ands two values.
-fn and<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: NumType + BitAnd,
-    <T as BitAnd>::Output: StackType,
-{
-    binary(stack, logs, T::bitand, None);
+// Calls the function at the address in place of the current one, with the arguments of the given size.
+fn tail_call_to(
+    address: usize,
+    args_size: usize,
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    var_list: &mut Vec<usize>,
+    frames: &[Frame],
+) {
+    let Some(frame) = frames.last() else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return;
+    };
+    if stack.len() < frame.stack_base + args_size {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return;
+    }
+    let args_start: usize = stack.len() - args_size;
+    stack.copy_within(args_start.., frame.stack_base);
+    stack.truncate(frame.stack_base + args_size);
+    var_list.clear();
+    *index = address;
 }
 
-// Bitwise xors two values.
-fn xor<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: NumType + BitXor,
-    <T as BitXor>::Output: StackType,
-{
-    binary(stack, logs, T::bitxor, None);
+// Pops a string handle from the stack and stops the program with the string as the message. Like other runtime
+// errors, the error is followed by a stack trace.
+fn panic(stack: &mut Vec<u8>, logs: &mut Vec<Log>, heap: &[HeapObject]) {
+    let error: ErrorType = match pop_string(stack, heap) {
+        Some(message) => ErrorType::Panic(message.clone()),
+        None => ErrorType::FatalError,
+    };
+    logs.push(Log {
+        log_type: LogType::Error(error),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
 }
 
-// Bitwise ors two values.
-fn or<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: NumType + BitOr,
-    <T as BitOr>::Output: StackType,
-{
-    binary(stack, logs, T::bitor, None);
-}
-// Left shifts a value by another value.
-fn left_shift<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: IntegralType,
-{
-    binary(stack, logs, T::shift, None);
+// Pops the message and the condition of an assertion, reporting the message as an error if the condition is false.
+fn assert(stack: &mut Vec<u8>, logs: &mut Vec<Log>, heap: &[HeapObject]) {
+    let message: Option<&String> = pop_string(stack, heap);
+    let error: ErrorType = match (message, bool::pop_from_stack(stack)) {
+        (Some(_), Some(true)) => return,
+        (Some(message), Some(false)) => ErrorType::AssertionFailed(message.clone()),
+        _ => ErrorType::FatalError,
+    };
+    logs.push(Log {
+        log_type: LogType::Error(error),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
 }
 
-// Right shifts a value by another value.
-fn right_shift<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: IntegralType,
-{
-    binary(stack, logs, |a: T, b: T| T::shift(a, <T>::neg(b)), None);
+// Gets the stack trace of the active function calls of the VM, with the functions named by the names in the
+// bytecode.
+fn frame_trace(
+    bytecode: &[u8],
+    frames: &[Frame],
+    line_and_col: Option<(usize, usize)>,
+) -> Vec<Log> {
+    let names: HashMap<usize, String> = function_names(bytecode);
+    let calls: Vec<ActiveCall> = frames
+        .iter()
+        .map(|frame| {
+            let name: String = names
+                .get(&frame.function)
+                .cloned()
+                .unwrap_or_else(|| format!("<function at offset {}>", frame.function));
+            (name, frame.call_site)
+        })
+        .collect();
+    stack_trace(&calls, line_and_col)
 }
 
-// Checks if two values are equal.
-fn equality<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: StackType,
-{
-    binary(stack, logs, <T>::eq, None);
+/// Gets a stack trace, which is a note for each active function call starting from the innermost, followed by one for
+/// the main program. The calls are given from the outermost. The first note has the location of the error, and the
+/// others the location of the call made from them. Only the innermost calls are listed if there are too many, such as
+/// after unbounded recursion.
+#[must_use]
+pub fn stack_trace(calls: &[ActiveCall], mut line_and_col: Option<(usize, usize)>) -> Vec<Log> {
+    let mut trace: Vec<Log> = Vec::new();
+    for (name, call_site) in calls.iter().rev().take(MAX_STACK_TRACE_FRAMES) {
+        trace.push(Log {
+            log_type: LogType::Note(NoteType::StackFrame(Some(name.clone()))),
+            line_and_col,
+            span: None,
+            notes: Vec::new(),
+        });
+        line_and_col = *call_site;
+    }
+    if let Some(omitted) = calls
+        .len()
+        .checked_sub(MAX_STACK_TRACE_FRAMES)
+        .filter(|omitted| *omitted > 0)
+    {
+        trace.push(Log {
+            log_type: LogType::Note(NoteType::OmittedStackFrames(omitted)),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        line_and_col = calls[0].1;
+    }
+    trace.push(Log {
+        log_type: LogType::Note(NoteType::StackFrame(None)),
+        line_and_col,
+        span: None,
+        notes: Vec::new(),
+    });
+    trace
 }
 
-// Checks if two values are not equal.
-fn inequality<T>(stack: &mut Vec<u8>, logs: &mut Vec<Log>)
-where
-    T: StackType,
-{
-    binary(stack, logs, <T>::ineq, None);
+// Gets the names of the functions in the bytecode by their addresses. The names are stored after the functions.
+fn function_names(bytecode: &[u8]) -> HashMap<usize, String> {
+    let ptr_size: usize = get_ptr_size(bytecode);
+    let mut names: HashMap<usize, String> = HashMap::new();
+    let mut index: usize = code_start(bytecode);
+    while let Some(byte) = bytecode.get(index) {
+        let op: Option<OpCode> = FromPrimitive::from_u8(*byte);
+        let operand: &[u8] = &bytecode[index + 1..];
+        let Some((op, length)) = op.and_then(|op| {
+            op.operand_length(ptr_size, operand)
+                .filter(|length| *length <= operand.len())
+                .map(|length| (op, length))
+        }) else {
+            break;
+        };
+        if op == OpCode::FunctionName {
+            let mut address_index: usize = index + 1;
+            if let Some(address) = read_ptr_size(bytecode, &mut address_index) {
+                let name: &[u8] = &operand[ptr_size + 4..length];
+                names.insert(address, String::from_utf8_lossy(name).into_owned());
+            }
+        }
+        index += 1 + length;
+    }
+    names
 }
 
-// Performs a unary operation.
-fn unary<F, T, TOut>(stack: &mut Vec<u8>, logs: &mut Vec<Log>, func: F)
-where
-    F: Fn(T) -> TOut,
-    T: StackType,
-    TOut: StackType,
-{
-    let value: Option<T> = T::pop_from_stack(stack);
-    if let Some(value) = value {
-        let value: TOut = func(value);
-        value.push_to_stack(stack);
+// Skips over an instruction that is only read by other instructions, such as the name of a function, which is only
+// read when panicking, or a constant.
+fn skip_operand(op: OpCode, bytecode: &[u8], index: &mut usize, logs: &mut Vec<Log>) {
+    let length: Option<usize> = op.operand_length(get_ptr_size(bytecode), &bytecode[*index..]);
+    if let Some(length) = length {
+        *index += length;
     } else {
         logs.push(Log {
             log_type: LogType::Error(ErrorType::FatalError),
             line_and_col: None,
+            span: None,
+            notes: Vec::new(),
         });
     }
 }
 
-// Performs a binary operation.
-fn binary<F, T1, T2, TOut>(
+// Calls a host function with ints from the stack as its arguments and pushes the int it returns. Reports an error if no
+// host function has been registered with the name or if the host function fails.
+fn call_host(
+    bytecode: &[u8],
     stack: &mut Vec<u8>,
+    index: &mut usize,
     logs: &mut Vec<Log>,
-    func: F,
-    error: Option<RuntimeError<(T1, T2)>>,
-) where
-    F: Fn(T1, T2) -> TOut,
-    T1: StackType,
-    T2: StackType,
-    TOut: StackType,
-{
-    let detailed_err: bool = if let Some(error) = &error {
-        get_detailed_err(error.bytecode)
-    } else {
-        false
-    };
-    if detailed_err && errors_stored_incorrectly(error.as_ref().expect("detailed_err is true")) {
+    natives: &mut Natives,
+) {
+    let call: Option<(usize, &str)> = read_host_call(bytecode, index);
+    let args: Option<Vec<i32>> = call.and_then(|(arg_count, _)| {
+        let mut args: Vec<i32> = (0..arg_count)
+            .map(|_| i32::pop_from_stack(stack))
+            .collect::<Option<Vec<i32>>>()?;
+        args.reverse();
+        Some(args)
+    });
+    let (Some((_, name)), Some(args)) = (call, args) else {
         logs.push(Log {
             log_type: LogType::Error(ErrorType::FatalError),
             line_and_col: None,
+            span: None,
+            notes: Vec::new(),
         });
         return;
+    };
+    let result: Result<i32, ErrorType> = match natives.host_functions.get_mut(name) {
+        Some(function) => function(&args)
+            .map_err(|message| ErrorType::HostFunctionFailed(name.to_string(), message)),
+        None => Err(ErrorType::UnknownHostFunction(name.to_string())),
+    };
+    if let Ok(value) = report_error(logs, result) {
+        value.push_to_stack(stack);
     }
+}
 
-    let b: Option<T2> = T2::pop_from_stack(stack);
-    let a: Option<T1> = T1::pop_from_stack(stack);
-    let mut fail: bool = true;
-    if let Some(a) = a {
-        if let Some(b) = b {
-            fail = false;
-            if let Some(mut error) = error {
-                handle_error(&mut error, (a, b), detailed_err, logs);
-            }
-            let c: TOut = func(a, b);
-            c.push_to_stack(stack);
-        }
+// Reports the error of a result, if there is one.
+fn report_error<T>(logs: &mut Vec<Log>, result: Result<T, ErrorType>) -> Result<T, ()> {
+    let error: RuntimeError<()> = RuntimeError {
+        condition: &(|()| result.is_err()),
+        error: result
+            .as_ref()
+            .err()
+            .cloned()
+            .unwrap_or(ErrorType::FatalError),
+    };
+    handle_error(&error, (), logs);
+    result.map_err(|_| ())
+}
+
+// Pops the handle of a path and pushes the handle of a string with the contents of the file at the path. Reports an
+// error if file access is disabled or the file can't be read.
+fn read_file(stack: &mut Vec<u8>, logs: &mut Vec<Log>, heap: &mut Heap, natives: &Natives) {
+    let Some(path) = pop_string(stack, heap).cloned() else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+        return;
+    };
+    let result: Result<String, ErrorType> = if natives.file_access {
+        fs::read_to_string(&path)
+            .map_err(|error| ErrorType::FileReadFailed(path, error.to_string()))
+    } else {
+        Err(ErrorType::FileAccessDisabled)
+    };
+    if let Ok(contents) = report_error(logs, result) {
+        alloc_object(stack, logs, heap, HeapObject::String(contents));
     }
-    if fail {
+}
+
+// Pops the handles of a path and the contents to write, and replaces the file at the path with the contents. Reports
+// an error if file access is disabled or the file can't be written.
+fn write_file(stack: &mut Vec<u8>, logs: &mut Vec<Log>, heap: &[HeapObject], natives: &Natives) {
+    let contents: Option<&String> = pop_string(stack, heap);
+    let path: Option<&String> = pop_string(stack, heap);
+    let (Some(path), Some(contents)) = (path, contents) else {
         logs.push(Log {
             log_type: LogType::Error(ErrorType::FatalError),
             line_and_col: None,
+            span: None,
+            notes: Vec::new(),
         });
+        return;
+    };
+    let result: Result<(), ErrorType> = if natives.file_access {
+        fs::write(path, contents)
+            .map_err(|error| ErrorType::FileWriteFailed(path.clone(), error.to_string()))
+    } else {
+        Err(ErrorType::FileAccessDisabled)
+    };
+    let _ = report_error(logs, result); // The error has already been logged.
+}
+
+// Reads the number of arguments and the name of the host function from the operand of a host call, and moves past
+// them.
+fn read_host_call<'b>(bytecode: &'b [u8], index: &mut usize) -> Option<(usize, &'b str)> {
+    let arg_count: usize = *bytecode.get(*index)? as usize;
+    let length: [u8; 4] = bytecode.get(*index + 1..*index + 5)?.try_into().ok()?;
+    let name_end: usize = *index + 5 + usize::try_from(u32::from_le_bytes(length)).ok()?;
+    let name: &str = std::str::from_utf8(bytecode.get(*index + 5..name_end)?).ok()?;
+    *index = name_end;
+    Some((arg_count, name))
+}
+
+// Makes an argument of the current function available as a variable.
+fn bind_param(
+    bytecode: &[u8],
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    var_list: &mut Vec<usize>,
+    frames: &[Frame],
+) {
+    let offset: Option<usize> = read_ptr_size(bytecode, index);
+    *index += 1; // The size of the argument is only needed by the verifier.
+    match offset {
+        Some(offset) => bind_param_at(offset, logs, var_list, frames),
+        None => logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        }),
     }
 }
 
-// Checks to make sure that line info is properly stored in operations that may have an error.
-fn errors_stored_incorrectly<T>(error: &RuntimeError<T>) -> bool {
-    let index: usize = *error.index;
-    let bytecode: &Vec<u8> = error.bytecode;
-    index + 2 * get_ptr_size(bytecode) > bytecode.len()
+// Adds the argument at the offset from the base of the current call to the variables.
+fn bind_param_at(offset: usize, logs: &mut Vec<Log>, var_list: &mut Vec<usize>, frames: &[Frame]) {
+    if let Some(frame) = frames.last() {
+        var_list.push(frame.stack_base + offset);
+    } else {
+        logs.push(Log {
+            log_type: LogType::Error(ErrorType::FatalError),
+            line_and_col: None,
+            span: None,
+            notes: Vec::new(),
+        });
+    }
 }
 
-// Handles runtime errors.
-fn handle_error<T>(error: &mut RuntimeError<T>, value: T, detailed_err: bool, logs: &mut Vec<Log>) {
-    let condition: &dyn Fn(T) -> bool = error.condition;
-    let index: &mut usize = error.index;
-    let bytecode: &Vec<u8> = error.bytecode;
-    let ptr_size: usize = get_ptr_size(bytecode);
-    if condition(value) {
-        if detailed_err {
-            let mut bytes: [u8; (usize::BITS / 8) as usize] = [0; (usize::BITS / 8) as usize];
-            for byte in bytes.iter_mut().take(ptr_size) {
-                *byte = bytecode[*index];
-                *index += 1;
-            }
-            let line: usize = usize::from_le_bytes(bytes);
-            bytes = [0; (usize::BITS / 8) as usize];
-            for byte in bytes.iter_mut().take(ptr_size) {
-                *byte = bytecode[*index];
-                *index += 1;
-            }
-            let col: usize = usize::from_le_bytes(bytes);
-            logs.push(Log {
-                log_type: LogType::Error(error.error.clone()),
-                line_and_col: Some((line, col)),
-            });
-        } else {
-            logs.push(Log {
-                log_type: LogType::Error(error.error.clone()),
-                line_and_col: None,
-            });
+// Returns from the current function. Everything the function pushed to the stack, including its arguments, is
+// replaced by the return value.
+fn return_from_call(
+    bytecode: &[u8],
+    stack: &mut Vec<u8>,
+    index: &mut usize,
+    logs: &mut Vec<Log>,
+    var_list: &mut Vec<usize>,
+    frames: &mut Vec<Frame>,
+) {
+    let size: Option<usize> = bytecode.get(*index).map(|size| *size as usize);
+    let value: Option<Vec<u8>> = size.and_then(|size| pop_bytes(stack, size));
+    if let (Some(value), Some(frame)) = (value, frames.pop()) {
+        if frame.stack_base <= stack.len() {
+            stack.truncate(frame.stack_base);
+            stack.extend_from_slice(&value);
+            *var_list = frame.var_list;
+            *index = frame.return_index;
+            return;
         }
-    } else if detailed_err {
-        *index += 2 * get_ptr_size(bytecode);
     }
+    logs.push(Log {
+        log_type: LogType::Error(ErrorType::FatalError),
+        line_and_col: None,
+        span: None,
+        notes: Vec::new(),
+    });
 }
 
 // Gets the variable index from the bytecode if availible.
-fn get_var_index(bytecode: &Vec<u8>, index: &mut usize) -> Option<usize> {
+fn get_var_index(bytecode: &[u8], index: &mut usize) -> Option<usize> {
     // TODO: Make sure BYTES_PER_VAR is at most ptr_size.
     if *index + compiler::BYTES_PER_VAR > bytecode.len() {
         None
@@ -853,11 +3696,6 @@ fn get_ptr_size(bytecode: &[u8]) -> usize {
     bytecode[0] as usize
 }
 
-// Gets whether or not runtime errors have extra info.
-fn get_detailed_err(bytecode: &[u8]) -> bool {
-    bytecode[1] != 0
-}
-
 /// The module for test functions
 pub mod test_func {
     use super::IntegralType;
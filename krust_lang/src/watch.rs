@@ -0,0 +1,61 @@
+//! The module for watch mode, which reruns a program whenever its source files change. The files are polled for their
+//! modification times and sizes, so no file watching API of the operating system is needed.
+
+use std::fs::{metadata, Metadata};
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+/// How often the files are checked for changes.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long the files have to stay the same after a change before it is reported, so that an editor saving a file in
+/// several writes only causes one rerun.
+pub const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// The state of a file that is compared to find changes, which is None if the file can't be read.
+type Stamp = Option<(SystemTime, u64)>;
+
+/// Watches some files for changes.
+pub struct Watcher {
+    paths: Vec<PathBuf>,
+    stamps: Vec<Stamp>,
+    debounce: Duration,
+}
+
+impl Watcher {
+    /// Creates a watcher for the files, which reports the changes made to them from now on once they have stayed the
+    /// same for the debounce time.
+    #[must_use]
+    pub fn new(paths: Vec<PathBuf>, debounce: Duration) -> Self {
+        let stamps: Vec<Stamp> = paths.iter().map(|path| stamp(path)).collect();
+        Self { paths, stamps, debounce }
+    }
+
+    /// Returns whether or not any of the files changed since they were last checked. A file that is deleted or
+    /// created counts as changed.
+    pub fn changed(&mut self) -> bool {
+        let stamps: Vec<Stamp> = self.paths.iter().map(|path| stamp(path)).collect();
+        let changed: bool = stamps != self.stamps;
+        self.stamps = stamps;
+        changed
+    }
+
+    /// Blocks until the files change and then stay the same for the debounce time.
+    pub fn wait_for_change(&mut self) {
+        while !self.changed() {
+            sleep(POLL_INTERVAL);
+        }
+        loop {
+            sleep(self.debounce);
+            if !self.changed() {
+                return;
+            }
+        }
+    }
+}
+
+// Gets the state of the file at the path.
+fn stamp(path: &Path) -> Stamp {
+    let metadata: Metadata = metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}